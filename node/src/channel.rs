@@ -0,0 +1,132 @@
+//! `--channel` / `--subscribe`: sub-channels within a room.
+//!
+//! The relay only forwards a message carrying a channel to connections whose Join subscribed
+//! to it; messages without one still reach everybody in the room. A node sends into its
+//! `--channel` (and receives it), and additionally receives every `--subscribe`d channel.
+
+use std::sync::OnceLock;
+
+use utils::Message;
+
+/// Env var used to pass `--channel` to helper processes.
+pub const CHANNEL_ENV: &str = "MCR_CHANNEL";
+/// Env var used to pass `--subscribe` to helper processes (comma-separated).
+pub const SUBSCRIBE_ENV: &str = "MCR_SUBSCRIBE";
+
+#[derive(Debug, Default)]
+struct Channels {
+    send: Option<String>,
+    subscribe: Vec<String>,
+}
+
+static CHANNELS: OnceLock<Channels> = OnceLock::new();
+
+/// Parse `--subscribe` values; each may itself be a comma-separated list.
+pub fn parse_channels(items: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut out: Vec<String> = Vec::new();
+    for c in items.iter().flat_map(|s| s.split(',')).map(str::trim) {
+        if c.is_empty() {
+            continue;
+        }
+        anyhow::ensure!(
+            !c.contains(char::is_whitespace),
+            "invalid channel '{c}' (no whitespace allowed)"
+        );
+        if !out.iter().any(|o| o == c) {
+            out.push(c.to_string());
+        }
+    }
+    Ok(out)
+}
+
+/// Parse a `--channel` value: a single channel name, empty meaning none.
+pub fn parse_channel(v: &str) -> anyhow::Result<Option<String>> {
+    let mut parsed = parse_channels(&[v.to_string()])?;
+    anyhow::ensure!(
+        parsed.len() <= 1,
+        "--channel takes a single channel, got '{v}'"
+    );
+    Ok(parsed.pop())
+}
+
+fn channels_from_env() -> Channels {
+    let send = std::env::var(CHANNEL_ENV).ok().and_then(|v| {
+        parse_channel(&v).unwrap_or_else(|e| {
+            log::warn!("ignoring {}: {:#}", CHANNEL_ENV, e);
+            None
+        })
+    });
+    let subscribe = std::env::var(SUBSCRIBE_ENV)
+        .ok()
+        .map(|v| {
+            parse_channels(&[v]).unwrap_or_else(|e| {
+                log::warn!("ignoring {}: {:#}", SUBSCRIBE_ENV, e);
+                Vec::new()
+            })
+        })
+        .unwrap_or_default();
+    Channels { send, subscribe }
+}
+
+/// Configure the channels (process-wide, set once at startup). Each falls back to its env var
+/// when not given.
+pub fn set_channels(send: Option<String>, subscribe: Vec<String>) {
+    let env = channels_from_env();
+    let _ = CHANNELS.set(Channels {
+        send: send.or(env.send),
+        subscribe: if subscribe.is_empty() {
+            env.subscribe
+        } else {
+            subscribe
+        },
+    });
+}
+
+fn channels() -> &'static Channels {
+    CHANNELS.get_or_init(Channels::default)
+}
+
+/// The channel outgoing messages are sent into; `None` reaches the whole room.
+pub fn send_channel() -> Option<&'static str> {
+    channels().send.as_deref()
+}
+
+/// `--subscribe` as given (without the `--channel`), e.g. to pass on to helper processes.
+pub fn subscribed() -> &'static [String] {
+    &channels().subscribe
+}
+
+/// Everything this node receives besides unchanneled messages: `--channel` and `--subscribe`,
+/// comma-separated as a Join carries them. `None` when there are none.
+pub fn join_channels() -> Option<String> {
+    let c = channels();
+    let mut all: Vec<&str> = c.send.iter().map(String::as_str).collect();
+    all.extend(
+        c.subscribe
+            .iter()
+            .map(String::as_str)
+            .filter(|s| Some(*s) != c.send.as_deref()),
+    );
+    (!all.is_empty()).then(|| all.join(","))
+}
+
+/// Tag an outgoing message with [`send_channel`].
+pub fn stamp_channel(msg: &mut Message) {
+    msg.channel = send_channel().map(str::to_string);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_lists_parse_and_reject_whitespace() {
+        let parsed = parse_channels(&["photos, notes".to_string(), "notes,,".to_string()]).unwrap();
+        assert_eq!(parsed, ["photos", "notes"]);
+        assert!(parse_channels(&["two words".to_string()]).is_err());
+
+        assert_eq!(parse_channel(" work ").unwrap().as_deref(), Some("work"));
+        assert_eq!(parse_channel("").unwrap(), None);
+        assert!(parse_channel("a,b").is_err());
+    }
+}
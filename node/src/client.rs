@@ -0,0 +1,313 @@
+//! A library front end for a room: push text, images and files to it (and read what others
+//! send) from Rust, without shelling out to `multicliprelay-node send-*`.
+//!
+//! Sends go through the same steps as the CLI: `--send-filter` (when configured), the
+//! sender name and history. The local copy kept for `resend` is opt-in
+//! ([`Client::keep_for_resend`]).
+
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use utils::{Kind, Message};
+
+use crate::channel::stamp_channel;
+use crate::consts::TAR_MIME;
+use crate::content_filter::filter_outgoing;
+use crate::device::{local_device_id, resolve_device_name, sender_name};
+use crate::hash::fingerprint;
+use crate::history::{record_event, record_send, send_outcome_event, Outcome};
+use crate::net::{
+    connect, join_for_ack, read_frame_body, send_join, wait_for_ack, write_frame, RelayStream,
+};
+use crate::paths::history_path;
+use crate::resend::{persist_image_to, persist_text_best_effort};
+use crate::transfer_file::{
+    bundle_name_for, filter_outgoing_spooled, orig_paths_for, spool_tar_bundle_capped,
+    SpooledPayload,
+};
+use crate::transfer_image::image_mimes;
+
+/// Who a [`Client`] sends as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    pub id: String,
+    /// Shown to peers as `sender_name` (blank = none).
+    pub name: String,
+}
+
+impl Device {
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+        }
+    }
+
+    /// This machine's node identity: the device id under `state_dir` and `name`, else the
+    /// usual fallbacks (env MCR_NAME, then the hostname).
+    pub async fn local(state_dir: &Path, name: Option<&str>) -> anyhow::Result<Self> {
+        tokio::fs::create_dir_all(state_dir)
+            .await
+            .with_context(|| format!("create {}", state_dir.display()))?;
+        Ok(Self::new(
+            local_device_id(state_dir).await?,
+            resolve_device_name(name),
+        ))
+    }
+}
+
+/// A connection to one room of a relay.
+pub struct Client {
+    device: Device,
+    relay: String,
+    room: String,
+    stream: RelayStream,
+    /// A Join went out, so the relay delivers the room's frames here.
+    joined: bool,
+    /// Joined announcing our `Hello`: sends ask for acks and peers' acks reach us.
+    acks: bool,
+    /// Where sent text and images are kept for `resend` (off unless asked for).
+    keep_dir: Option<PathBuf>,
+    /// The history log sends are recorded in.
+    history: PathBuf,
+}
+
+impl Client {
+    /// Connect to `relay` for `room`. Nothing goes out before the first send, so sending
+    /// works with any relay; [`Client::join`] (or the first [`Client::recv`]) joins the room.
+    pub async fn connect(relay: &str, room: &str, device: Device) -> anyhow::Result<Self> {
+        let stream = connect(relay).await?;
+        Ok(Self {
+            device,
+            relay: relay.to_string(),
+            room: room.to_string(),
+            stream,
+            joined: false,
+            acks: false,
+            keep_dir: None,
+            history: history_path(),
+        })
+    }
+
+    /// Like [`Client::connect`], but join right away announcing what this node understands,
+    /// so sends ask peers to ack them (see [`Client::wait_for_ack`]). Needs a relay that
+    /// knows the handshake; older ones never pass acks on.
+    pub async fn connect_for_ack(relay: &str, room: &str, device: Device) -> anyhow::Result<Self> {
+        let mut client = Self::connect(relay, room, device).await?;
+        let d = &client.device;
+        join_for_ack(&mut client.stream, &d.id, &d.name, room).await?;
+        client.joined = true;
+        client.acks = true;
+        Ok(client)
+    }
+
+    /// Also keep what is sent under `dir` (e.g. `paths::received_dir()`), the way the CLI
+    /// does for `resend`. Off by default: the copies hold clipboard contents in plaintext.
+    pub fn keep_for_resend(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.keep_dir = Some(dir.into());
+        self
+    }
+
+    /// Record sends in the history log at `path` instead of the user's
+    /// ([`history_path`]).
+    pub fn history_at(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history = path.into();
+        self
+    }
+
+    /// Join the room, so [`Client::recv`] gets what others send from now on.
+    pub async fn join(&mut self) -> anyhow::Result<()> {
+        if !self.joined {
+            let d = &self.device;
+            send_join(&mut self.stream, &d.id, &d.name, &self.room).await?;
+            self.joined = true;
+        }
+        Ok(())
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    /// Send `text`. Like every send, returns the message as it went out; its `event_id` is
+    /// what [`Client::wait_for_ack`] waits for.
+    pub async fn send_text(&mut self, text: &str) -> anyhow::Result<Message> {
+        let bytes = filter_outgoing(&Kind::Text, text.as_bytes().to_vec())
+            .await
+            .context("text dropped by --send-filter")?;
+        let mut msg = Message::new_text(&self.device.id, &self.room, "");
+        let sha = fingerprint(&bytes);
+        if let Some(dir) = &self.keep_dir {
+            let mime = msg.mime.clone().unwrap_or_default();
+            persist_text_best_effort(dir, &sha, &mime, &bytes).await;
+        }
+        msg.size = bytes.len();
+        msg.payload = Some(bytes);
+        self.send(msg, sha, None).await
+    }
+
+    /// Send an image as it is; `mime` must be one the nodes take (PNG, JPEG, WebP, GIF, SVG).
+    /// Conversions such as force-png are up to the caller (see `publish::prepare_payload`).
+    pub async fn send_image(&mut self, mime: &str, bytes: Vec<u8>) -> anyhow::Result<Message> {
+        anyhow::ensure!(
+            image_mimes().contains(&mime),
+            "unsupported image mime {}",
+            mime
+        );
+        let bytes = filter_outgoing(&Kind::Image, bytes)
+            .await
+            .context("image dropped by --send-filter")?;
+        let sha = fingerprint(&bytes);
+        if let Some(dir) = &self.keep_dir {
+            persist_image_to(dir, &sha, mime, &bytes).await;
+        }
+        let msg = Message::new_image(&self.device.id, &self.room, mime, bytes);
+        self.send(msg, sha, None).await
+    }
+
+    /// Send `bytes` as a file called `name`; peers keep it under their received dir.
+    pub async fn send_file(
+        &mut self,
+        name: &str,
+        mime: &str,
+        bytes: Vec<u8>,
+    ) -> anyhow::Result<Message> {
+        self.send_file_from(name, mime, bytes, None).await
+    }
+
+    /// Bundle files and folders into one tar, the way a copied file selection goes out.
+    /// The tar is streamed from a temp file, so the returned message has no payload.
+    pub async fn send_paths(
+        &mut self,
+        paths: &[PathBuf],
+        max_file_bytes: usize,
+    ) -> anyhow::Result<Message> {
+        let paths2 = paths.to_vec();
+        let tar =
+            tokio::task::spawn_blocking(move || spool_tar_bundle_capped(&paths2, max_file_bytes))
+                .await
+                .context("tar build join")??;
+        let Some(tar) = tar else {
+            anyhow::bail!("file too large: bundle exceeds {} bytes", max_file_bytes);
+        };
+        let tar = filter_outgoing_spooled(tar)
+            .await?
+            .context("file dropped by --send-filter")?;
+        let name = bundle_name_for(paths);
+        let mut msg = tar.file_message(&self.device.id, &self.room, &name, TAR_MIME);
+        msg.orig_paths = orig_paths_for(paths);
+        self.send(msg, tar.sha.clone(), Some(&tar)).await
+    }
+
+    async fn send_file_from(
+        &mut self,
+        name: &str,
+        mime: &str,
+        bytes: Vec<u8>,
+        orig_paths: Option<Vec<String>>,
+    ) -> anyhow::Result<Message> {
+        let bytes = filter_outgoing(&Kind::File, bytes)
+            .await
+            .context("file dropped by --send-filter")?;
+        let sha = fingerprint(&bytes);
+        let mut msg = Message::new_file(&self.device.id, &self.room, name, mime, bytes);
+        msg.orig_paths = orig_paths;
+        self.send(msg, sha, None).await
+    }
+
+    /// Send `msg`, with its payload streamed from `spooled` when it isn't held in the message.
+    async fn send(
+        &mut self,
+        mut msg: Message,
+        sha: String,
+        spooled: Option<&SpooledPayload>,
+    ) -> anyhow::Result<Message> {
+        msg.sender_name = sender_name(&self.device.name);
+        stamp_channel(&mut msg);
+        msg.want_ack = self.acks;
+        msg.sha256 = Some(sha.clone());
+        let sent = match spooled {
+            Some(payload) => payload.write_frame(&mut self.stream, &msg).await,
+            None => write_frame(&mut self.stream, &msg.to_bytes()).await,
+        };
+        if let Err(e) = sent {
+            let reason = format!("send failed: {e:#}");
+            record_event(
+                &self.history,
+                send_outcome_event(
+                    &self.device.id,
+                    msg.sender_name.clone(),
+                    &self.room,
+                    &self.relay,
+                    Outcome::Failed,
+                    &reason,
+                    msg.kind.clone(),
+                    msg.mime.clone(),
+                    msg.size,
+                    Some(sha),
+                ),
+            )
+            .await;
+            return Err(e);
+        }
+        log::debug!(
+            "client: sent kind={:?} room={} relay={} bytes={} sha={}",
+            msg.kind,
+            self.room,
+            self.relay,
+            msg.size,
+            sha
+        );
+        record_send(
+            &self.history,
+            &self.device.id,
+            msg.sender_name.clone(),
+            &self.room,
+            &self.relay,
+            msg.kind.clone(),
+            msg.mime.clone(),
+            msg.name.clone(),
+            msg.size,
+            Some(sha),
+        )
+        .await;
+        Ok(msg)
+    }
+
+    /// The next text, image or file another device sends to the room (joining it first if
+    /// that hasn't happened yet).
+    pub async fn recv(&mut self) -> anyhow::Result<Message> {
+        self.join().await?;
+        loop {
+            let len = self.stream.read_u32().await.context("read len")? as usize;
+            let buf = read_frame_body(&mut self.stream, len).await?;
+            let msg = match Message::try_from_bytes(&buf) {
+                Ok(m) => m,
+                Err(e) => {
+                    log::debug!("client: skipping undecodable frame: {e}");
+                    continue;
+                }
+            };
+            if let Some(reason) = msg.rejection() {
+                anyhow::bail!("relay refused connection: {reason}");
+            }
+            let payload = matches!(msg.kind, Kind::Text | Kind::Image | Kind::File);
+            if payload && msg.device_id != self.device.id && msg.room == self.room {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Wait until a peer's wl-apply has applied the send `event_id`; returns who did.
+    /// Frames arriving meanwhile are skipped. Needs [`Client::connect_for_ack`].
+    pub async fn wait_for_ack(&mut self, event_id: &str, wait: Duration) -> anyhow::Result<String> {
+        anyhow::ensure!(self.acks, "not connected for acks (see connect_for_ack)");
+        wait_for_ack(&mut self.stream, event_id, wait).await
+    }
+}
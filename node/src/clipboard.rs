@@ -1,15 +1,225 @@
 use anyhow::Context;
+use std::future::Future;
+use std::io::Read;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 
+use crate::consts::{
+    APPLIED_MARKER_MIME, GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME,
+};
+use crate::extra_mime::extra_mimes;
+use crate::rich_text::RTF_MIMES;
+use crate::transfer_image::image_mimes;
+use utils::kinds::WATCH_NONE;
+
+/// Env var used to pass `--native-clipboard` to helper processes (e.g. the wl-watch hook).
+pub const NATIVE_CLIPBOARD_ENV: &str = "MCR_NATIVE_CLIPBOARD";
+
+static NATIVE_CLIPBOARD: OnceLock<bool> = OnceLock::new();
+/// Set once the native backend turned out unusable here (e.g. no data-control protocol).
+static NATIVE_BROKEN: AtomicBool = AtomicBool::new(false);
+
+fn native_clipboard_from_env() -> bool {
+    matches!(
+        std::env::var(NATIVE_CLIPBOARD_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Read the clipboard over the Wayland protocol instead of spawning `wl-paste`
+/// (process-wide, set once at startup; falls back to `wl-paste` when unsupported).
+pub fn set_native_clipboard(on: bool) {
+    let _ = NATIVE_CLIPBOARD.set(on || native_clipboard_from_env());
+}
+
+pub fn native_clipboard() -> bool {
+    *NATIVE_CLIPBOARD.get_or_init(native_clipboard_from_env)
+}
+
+fn native_usable() -> bool {
+    native_clipboard() && !NATIVE_BROKEN.load(Ordering::Relaxed)
+}
+
+/// Env fallback for `--clipboard-timeout-ms` (also passed to helper processes).
+pub const CLIPBOARD_TIMEOUT_ENV: &str = "MCR_CLIPBOARD_TIMEOUT_MS";
+/// Default `--clipboard-timeout-ms`: generous for large images, short enough not to wedge a loop.
+pub const DEFAULT_CLIPBOARD_TIMEOUT_MS: u64 = 5000;
+
+static CLIPBOARD_TIMEOUT_MS: OnceLock<u64> = OnceLock::new();
+
+fn clipboard_timeout_from_env() -> u64 {
+    std::env::var(CLIPBOARD_TIMEOUT_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_CLIPBOARD_TIMEOUT_MS)
+}
+
+/// Give up on a clipboard read/write after this long (process-wide, set once at startup;
+/// `0` waits forever).
+pub fn set_clipboard_timeout_ms(ms: Option<u64>) {
+    let _ = CLIPBOARD_TIMEOUT_MS.set(ms.unwrap_or_else(clipboard_timeout_from_env));
+}
+
+pub fn clipboard_timeout_ms() -> u64 {
+    *CLIPBOARD_TIMEOUT_MS.get_or_init(clipboard_timeout_from_env)
+}
+
+/// Run `fut` for at most `timeout_ms` (`0`: no limit). A stalled compositor or clipboard owner
+/// then costs one skipped read instead of a frozen poll loop or hook.
+async fn within<T>(
+    what: &str,
+    timeout_ms: u64,
+    fut: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    if timeout_ms == 0 {
+        return fut.await;
+    }
+    match tokio::time::timeout(Duration::from_millis(timeout_ms), fut).await {
+        Ok(r) => r,
+        Err(_) => {
+            log::warn!("clipboard: {what} timed out after {timeout_ms}ms; skipping");
+            anyhow::bail!("{what} timed out after {timeout_ms}ms")
+        }
+    }
+}
+
+/// `cmd.output()` under the clipboard timeout; the child is killed when it runs over.
+async fn output_within(
+    mut cmd: Command,
+    what: &str,
+    timeout_ms: u64,
+) -> anyhow::Result<std::process::Output> {
+    cmd.stdin(Stdio::null()).kill_on_drop(true);
+    within(what, timeout_ms, async {
+        cmd.output().await.with_context(|| format!("spawn {what}"))
+    })
+    .await
+}
+
+/// Run a blocking wl-clipboard-rs paste call. `Ok(None)`: nothing (of that type) is offered;
+/// `Err`: the backend can't be used here, so callers fall back to `wl-paste`.
+async fn native_paste_call<T, F>(f: F) -> anyhow::Result<Option<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, wl_clipboard_rs::paste::Error> + Send + 'static,
+{
+    use wl_clipboard_rs::paste::Error as PasteError;
+
+    // The blocking call itself can't be cancelled; on timeout its thread finishes on its own.
+    let joined = within("native paste", clipboard_timeout_ms(), async {
+        tokio::task::spawn_blocking(f)
+            .await
+            .context("native paste join")
+    });
+    match joined.await? {
+        Ok(v) => Ok(Some(v)),
+        Err(PasteError::ClipboardEmpty | PasteError::NoMimeType | PasteError::NoSeats) => Ok(None),
+        Err(e) => {
+            if !NATIVE_BROKEN.swap(true, Ordering::Relaxed) {
+                log::warn!("native clipboard unavailable, falling back to wl-paste: {e}");
+            }
+            Err(e.into())
+        }
+    }
+}
+
+async fn native_paste(mime: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    use wl_clipboard_rs::paste::{get_contents, ClipboardType, MimeType, Seat};
+
+    let mime = mime.to_string();
+    let read = native_paste_call(move || {
+        let (mut pipe, _) = get_contents(
+            ClipboardType::Regular,
+            Seat::Unspecified,
+            MimeType::Specific(&mime),
+        )?;
+        let mut out = Vec::new();
+        // A failed transfer (e.g. the source app quit) is not a backend problem.
+        Ok(pipe.read_to_end(&mut out).map(|_| out))
+    })
+    .await?;
+    read.transpose().context("read native clipboard pipe")
+}
+
+/// MIME types currently offered on the regular clipboard (in offer order).
+///
+/// `None` when the clipboard can't be queried at all.
+pub async fn wl_list_types() -> Option<Vec<String>> {
+    if native_usable() {
+        use wl_clipboard_rs::paste::{get_mime_types_ordered, ClipboardType, Seat};
+
+        let native =
+            native_paste_call(|| get_mime_types_ordered(ClipboardType::Regular, Seat::Unspecified));
+        if let Ok(types) = native.await {
+            return Some(types.unwrap_or_default());
+        }
+    }
+    let mut cmd = Command::new("wl-paste");
+    cmd.arg("--list-types");
+    let out = output_within(cmd, "wl-paste --list-types", clipboard_timeout_ms())
+        .await
+        .ok()?;
+    let types = String::from_utf8_lossy(&out.stdout);
+    Some(types.lines().map(|l| l.trim().to_string()).collect())
+}
+
+/// Read side of a clipboard, so publish logic can run against a fake in tests.
+pub trait ClipboardSource {
+    /// Offered MIME types; `None` when the clipboard can't be queried.
+    fn list_types(&self) -> impl Future<Output = Option<Vec<String>>>;
+    fn read(&self, mime: &str) -> impl Future<Output = anyhow::Result<Vec<u8>>>;
+}
+
+/// Write side of a clipboard: offer all `items` as one selection.
+pub trait ClipboardSink {
+    fn write(&self, items: Vec<(String, Vec<u8>)>) -> impl Future<Output = anyhow::Result<()>>;
+    /// Same for the primary (middle-click) selection.
+    fn write_primary(
+        &self,
+        items: Vec<(String, Vec<u8>)>,
+    ) -> impl Future<Output = anyhow::Result<()>>;
+}
+
+/// The Wayland clipboard (native or via `wl-paste`, see [`set_native_clipboard`]).
+pub struct WlPaste;
+
+impl ClipboardSource for WlPaste {
+    async fn list_types(&self) -> Option<Vec<String>> {
+        wl_list_types().await
+    }
+
+    async fn read(&self, mime: &str) -> anyhow::Result<Vec<u8>> {
+        wl_paste(mime).await
+    }
+}
+
+impl ClipboardSink for WlPaste {
+    async fn write(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+        wl_write_selection(items, false).await
+    }
+
+    async fn write_primary(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+        wl_write_selection(items, true).await
+    }
+}
+
 pub async fn wl_paste(mime: &str) -> anyhow::Result<Vec<u8>> {
+    if native_usable() {
+        match native_paste(mime).await {
+            Ok(Some(bytes)) => return Ok(bytes),
+            Ok(None) => anyhow::bail!("wl-paste unavailable: {}", mime),
+            Err(_) => {} // backend unusable (logged once) or transfer failed: try wl-paste
+        }
+    }
+
     // wl-paste exits non-zero if the requested type is unavailable.
-    let out = Command::new("wl-paste")
-        .arg("--no-newline")
-        .arg("--type")
-        .arg(mime)
-        .output()
-        .await
-        .context("spawn wl-paste")?;
+    let mut cmd = Command::new("wl-paste");
+    cmd.arg("--no-newline").arg("--type").arg(mime);
+    let out = output_within(cmd, "wl-paste", clipboard_timeout_ms()).await?;
     if !out.status.success() {
         anyhow::bail!("wl-paste unavailable: {}", mime);
     }
@@ -17,11 +227,179 @@ pub async fn wl_paste(mime: &str) -> anyhow::Result<Vec<u8>> {
 }
 
 pub async fn wl_copy(mime: &str, bytes: &[u8]) -> anyhow::Result<()> {
-    wl_copy_multi(vec![(mime.to_string(), bytes.to_vec())]).await
+    wl_write_selection(vec![(mime.to_string(), bytes.to_vec())], false).await
+}
+
+/// Env fallback for `wl-apply --apply-to-primary`.
+pub const APPLY_TO_PRIMARY_ENV: &str = "MCR_APPLY_TO_PRIMARY";
+
+static APPLY_TO_PRIMARY: OnceLock<bool> = OnceLock::new();
+
+fn apply_to_primary_from_env() -> bool {
+    !matches!(
+        std::env::var(APPLY_TO_PRIMARY_ENV).ok().as_deref(),
+        Some("0") | Some("false")
+    )
+}
+
+/// Also put received text on the primary selection, as wl-copy does for text (on unless
+/// turned off; process-wide, set once at startup; `None` = env).
+pub fn set_apply_to_primary(on: Option<bool>) {
+    let _ = APPLY_TO_PRIMARY.set(on.unwrap_or_else(apply_to_primary_from_env));
+}
+
+pub fn apply_to_primary() -> bool {
+    *APPLY_TO_PRIMARY.get_or_init(apply_to_primary_from_env)
+}
+
+/// The names received text is offered under; apps differ in which one they ask for (X11
+/// clients through XWayland often want `UTF8_STRING` or `STRING`).
+pub const TEXT_ALIASES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "UTF8_STRING",
+    "STRING",
+];
+
+/// `text` under every name in [`TEXT_ALIASES`].
+pub fn text_clipboard_items(text: &[u8]) -> Vec<(String, Vec<u8>)> {
+    TEXT_ALIASES
+        .iter()
+        .map(|m| (m.to_string(), text.to_vec()))
+        .collect()
+}
+
+/// Types received text is offered under: [`TEXT_ALIASES`] for UTF-8 text (or a peer that didn't
+/// say), otherwise only the MIME it was sent with, since the aliases all promise UTF-8.
+pub fn received_text_mimes(mime: Option<&str>) -> Vec<&str> {
+    match mime {
+        Some(m) if !m.eq_ignore_ascii_case(TEXT_ALIASES[0]) => vec![m],
+        _ => TEXT_ALIASES.to_vec(),
+    }
+}
+
+/// `text` under every name in [`received_text_mimes`].
+pub fn received_text_items(mime: Option<&str>, text: &[u8]) -> Vec<(String, Vec<u8>)> {
+    received_text_mimes(mime)
+        .into_iter()
+        .map(|m| (m.to_string(), text.to_vec()))
+        .collect()
+}
+
+/// Put received plain text (sent as `mime`) on the clipboard and, with `to_primary`, on the
+/// primary selection.
+///
+/// The caller records the text as applied first; that covers both selections, so a watcher
+/// reading either one back doesn't send it again.
+pub async fn copy_text<C: ClipboardSink>(
+    clip: &C,
+    mime: Option<&str>,
+    text: &[u8],
+    to_primary: bool,
+) -> anyhow::Result<()> {
+    let items = received_text_items(mime, text);
+    clip.write(items.clone()).await?;
+    if to_primary {
+        write_primary(clip, items).await;
+    }
+    Ok(())
+}
+
+/// Best effort: not every compositor offers a primary selection.
+pub async fn copy_primary_text<C: ClipboardSink>(clip: &C, text: &[u8]) {
+    write_primary(clip, text_clipboard_items(text)).await;
+}
+
+async fn write_primary<C: ClipboardSink>(clip: &C, items: Vec<(String, Vec<u8>)>) {
+    if let Err(e) = clip.write_primary(items).await {
+        log::debug!("clipboard: primary selection write failed: {e:#}");
+    }
+}
+
+/// What a multi-format clipboard write left on the clipboard.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CopyReport {
+    /// Requested formats the clipboard offers afterwards.
+    pub landed: Vec<String>,
+    /// Requested formats it doesn't offer (empty when the types can't be listed).
+    pub missing: Vec<String>,
+    /// The write was repeated because the applied marker was missing.
+    pub retried: bool,
+}
+
+/// Checks for the applied marker after a write, before writing again.
+const MARKER_CHECKS: usize = 3;
+const MARKER_CHECK_DELAY: Duration = Duration::from_millis(50);
+
+async fn copy_report<C: ClipboardSource>(clip: &C, requested: &[String]) -> CopyReport {
+    let Some(types) = clip.list_types().await else {
+        return CopyReport::default();
+    };
+    let (landed, missing) = requested
+        .iter()
+        .cloned()
+        .partition(|m| types.iter().any(|t| t == m));
+    CopyReport {
+        landed,
+        missing,
+        retried: false,
+    }
+}
+
+/// Write `items` as one selection and report which formats landed.
+///
+/// When the applied marker is among them but doesn't show up, the write is repeated once:
+/// without the marker our own watcher would send the content straight back. Not when the
+/// clipboard holds something else by then: that is a newer copy, which stays.
+pub async fn copy_checked<C>(clip: &C, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<CopyReport>
+where
+    C: ClipboardSource + ClipboardSink,
+{
+    let requested: Vec<String> = items.iter().map(|(m, _)| m.clone()).collect();
+    let needs_marker = requested.iter().any(|m| m == APPLIED_MARKER_MIME);
+    let retry = needs_marker.then(|| items.clone());
+    clip.write(items).await?;
+
+    let mut report = copy_report(clip, &requested).await;
+    let marker_missing = |r: &CopyReport| r.missing.iter().any(|m| m == APPLIED_MARKER_MIME);
+    // The selection may take a moment to show up for other clients.
+    for _ in 1..MARKER_CHECKS {
+        if !needs_marker || !marker_missing(&report) {
+            break;
+        }
+        tokio::time::sleep(MARKER_CHECK_DELAY).await;
+        report = copy_report(clip, &requested).await;
+    }
+    if let Some(items) = retry.filter(|_| marker_missing(&report)) {
+        if !still_offers(clip, &items).await {
+            log::debug!("clipboard: applied marker missing, but the selection changed since");
+            return Ok(report);
+        }
+        log::debug!("clipboard: applied marker missing after write; writing again");
+        clip.write(items).await?;
+        report = copy_report(clip, &requested).await;
+        report.retried = true;
+    }
+    Ok(report)
+}
+
+/// Whether the clipboard still holds what `items` wrote, going by their first format besides
+/// the marker.
+async fn still_offers<C: ClipboardSource>(clip: &C, items: &[(String, Vec<u8>)]) -> bool {
+    match items.iter().find(|(m, _)| m != APPLIED_MARKER_MIME) {
+        Some((mime, bytes)) => clip.read(mime).await.is_ok_and(|b| &b == bytes),
+        None => true,
+    }
 }
 
-pub async fn wl_copy_multi(items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
-    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+/// Write several formats at once, verifying the applied marker (see [`copy_checked`]).
+pub async fn wl_copy_multi(items: Vec<(String, Vec<u8>)>) -> anyhow::Result<CopyReport> {
+    copy_checked(&WlPaste, items).await
+}
+
+/// Offer `items` as the regular clipboard, or the primary selection with `primary`.
+async fn wl_write_selection(items: Vec<(String, Vec<u8>)>, primary: bool) -> anyhow::Result<()> {
+    let write = tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
         use wl_clipboard_rs::copy::{
             ClipboardType, Error as WlCopyError, MimeSource, MimeType, Options, Seat, Source,
         };
@@ -38,35 +416,408 @@ pub async fn wl_copy_multi(items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()>
 
         let sources = mk_sources(&items);
 
-        // Practical note:
-        // - Setting file/URI payloads to PRIMARY can confuse some toolchains / file managers.
-        // - Some environments may accidentally interpret PRIMARY text as a "folder name" and
-        //   still paste the URI list from the regular clipboard, producing empty weird folders.
-        // To reduce these artifacts, only set BOTH for *pure* text copies.
-        let only_text = items
-            .iter()
-            .all(|(mime, _)| mime.starts_with("text/plain"));
-        let clipboard = if only_text {
-            ClipboardType::Both
+        // PRIMARY only ever gets text (see `copy_text`, `--no-apply-to-primary`): file/URI
+        // payloads there confuse some file managers, and some environments take PRIMARY text
+        // for a "folder name" while pasting the URI list from the clipboard.
+        let clipboard = if primary {
+            ClipboardType::Primary
         } else {
             ClipboardType::Regular
         };
 
         let mut opts = Options::new();
         opts.clipboard(clipboard).seat(Seat::All);
-
-        match opts.copy_multi(sources.clone()) {
+        match opts.copy_multi(sources) {
             Ok(()) => Ok(()),
-            Err(WlCopyError::PrimarySelectionUnsupported) if only_text => {
-                // Fallback: regular clipboard only.
-                let mut opts = Options::new();
-                opts.clipboard(ClipboardType::Regular).seat(Seat::All);
-                opts.copy_multi(sources).map_err(|e| anyhow::anyhow!(e))
+            Err(WlCopyError::PrimarySelectionUnsupported) => {
+                anyhow::bail!("primary selection not supported by the compositor")
             }
             Err(e) => Err(anyhow::anyhow!(e)),
         }
+    });
+    within("clipboard write", clipboard_timeout_ms(), async {
+        write.await.context("wl_copy_multi join")?
     })
     .await
-    .context("wl_copy_multi join")??;
-    Ok(())
+}
+
+/// Every MIME `wl-watch --mode watch` supervises a `wl-paste --watch` for, in spawn order.
+pub fn default_watch_mimes() -> Vec<String> {
+    let mut mimes: Vec<String> = [
+        URI_LIST_MIME,
+        GNOME_COPIED_FILES_MIME,
+        KDE_URI_LIST_MIME,
+        "text/plain;charset=utf-8",
+        "text/plain",
+    ]
+    .iter()
+    .chain(RTF_MIMES)
+    .chain(image_mimes())
+    .map(|m| m.to_string())
+    .chain(extra_mimes().iter().cloned())
+    .collect();
+    mimes.dedup();
+    mimes
+}
+
+/// Env fallback for `wl-watch --watch-mimes` (comma-separated), e.g. from a systemd env file.
+pub const WATCH_MIMES_ENV: &str = "MCR_WATCH_MIMES";
+
+/// Whether `--watch-mimes` `allow` lets `mime` through; empty = everything, and `none`
+/// ([`WATCH_NONE`]) matches nothing.
+///
+/// Entries are exact types or `type/*` wildcards, e.g. `text/*` for text-only.
+pub fn watch_allows(allow: &[String], mime: &str) -> bool {
+    allow.is_empty()
+        || allow.iter().any(|a| match a.strip_suffix("/*") {
+            Some(major) => mime.split('/').next() == Some(major),
+            None => a.eq_ignore_ascii_case(mime),
+        })
+}
+
+/// `--watch-mimes none`: every kind is switched off (the UIs pass it instead of an empty list).
+pub fn watch_nothing(allow: &[String]) -> bool {
+    !allow.is_empty() && allow.iter().all(|a| a.eq_ignore_ascii_case(WATCH_NONE))
+}
+
+/// The watched MIME set trimmed to `allow` (`--watch-mimes`, see [`watch_allows`]).
+pub fn watch_mimes(allow: &[String]) -> Vec<String> {
+    default_watch_mimes()
+        .into_iter()
+        .filter(|m| watch_allows(allow, m))
+        .collect()
+}
+
+/// Whether `wl-paste --watch` delivers selection events on this compositor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchProbe {
+    /// An event arrived within the probe window.
+    Fired,
+    /// The watcher ran but stayed silent.
+    Silent,
+    /// `wl-paste --watch` could not run at all (missing binary, no data-control, ...).
+    Unavailable,
+}
+
+/// Map a `wl-watch --mode` value to the concrete mode, running `probe` only for `auto`.
+pub async fn resolve_watch_mode(
+    mode: &str,
+    probe: impl Future<Output = WatchProbe>,
+) -> anyhow::Result<&'static str> {
+    match mode {
+        "watch" => Ok("watch"),
+        "poll" => Ok("poll"),
+        "auto" => {
+            let result = probe.await;
+            let chosen = if result == WatchProbe::Fired {
+                "watch"
+            } else {
+                "poll"
+            };
+            log::info!("wl-watch: auto mode probe={:?} -> {}", result, chosen);
+            crate::say!("wl-watch: auto mode probe={:?} -> {}", result, chosen);
+            Ok(chosen)
+        }
+        other => anyhow::bail!("invalid --mode {}, expected watch|poll|auto", other),
+    }
+}
+
+/// Check that `wl-paste --watch` reports a selection within `window`.
+///
+/// wl-paste announces the current selection on startup. When the clipboard is empty we copy a
+/// sentinel and clear it again afterwards; a non-empty clipboard is never overwritten.
+pub async fn probe_wl_paste_watch(window: Duration) -> WatchProbe {
+    let Ok(mut child) = Command::new("wl-paste")
+        .arg("--watch")
+        .arg("echo")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+    else {
+        return WatchProbe::Unavailable;
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return WatchProbe::Unavailable;
+    };
+    let mut events = BufReader::new(stdout).lines();
+
+    match tokio::time::timeout(window, events.next_line()).await {
+        Ok(Ok(Some(_))) => return WatchProbe::Fired,
+        // The watcher exited (e.g. "compositor doesn't support data-control").
+        Ok(_) => return WatchProbe::Unavailable,
+        Err(_) => {}
+    }
+    if wl_list_types().await.is_some_and(|t| !t.is_empty()) {
+        return WatchProbe::Silent;
+    }
+
+    if wl_copy("text/plain;charset=utf-8", b"multicliprelay watch probe")
+        .await
+        .is_err()
+    {
+        return WatchProbe::Unavailable;
+    }
+    let fired = matches!(
+        tokio::time::timeout(window, events.next_line()).await,
+        Ok(Ok(Some(_)))
+    );
+    let _ = tokio::task::spawn_blocking(|| {
+        use wl_clipboard_rs::copy::{clear, ClipboardType, Seat};
+        clear(ClipboardType::Both, Seat::All)
+    })
+    .await;
+    if fired {
+        WatchProbe::Fired
+    } else {
+        WatchProbe::Silent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn native_text_round_trip() {
+        if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            eprintln!("skipped: no Wayland compositor");
+            return;
+        }
+        let mime = "text/plain;charset=utf-8";
+        let text = format!("multicliprelay native test {}", uuid::Uuid::new_v4());
+        if let Err(e) = wl_copy(mime, text.as_bytes()).await {
+            eprintln!("skipped: compositor has no data-control support ({e:#})");
+            return;
+        }
+        match native_paste(mime).await {
+            Ok(read) => assert_eq!(read.as_deref(), Some(text.as_bytes())),
+            Err(e) => {
+                eprintln!("skipped: native paste unsupported ({e:#})");
+                return;
+            }
+        }
+        assert!(native_paste("application/x-mcr-not-offered")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn stalled_clipboard_command_times_out_promptly() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("5");
+        let start = std::time::Instant::now();
+        let err = output_within(cmd, "stub paste", 100).await.unwrap_err();
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            start.elapsed()
+        );
+        assert!(
+            format!("{err:#}").contains("timed out after 100ms"),
+            "{err:#}"
+        );
+
+        let mut cmd = Command::new("echo");
+        cmd.arg("fine");
+        let out = output_within(cmd, "stub paste", 2000).await.unwrap();
+        assert_eq!(out.stdout, b"fine\n");
+    }
+
+    #[test]
+    fn watcher_set_honors_allowlist() {
+        assert_eq!(watch_mimes(&[]), default_watch_mimes());
+
+        let text_only = watch_mimes(&["text/*".to_string()]);
+        assert!(text_only.contains(&"text/plain;charset=utf-8".to_string()));
+        assert!(text_only.contains(&URI_LIST_MIME.to_string()));
+        assert!(text_only.iter().all(|m| m.starts_with("text/")));
+
+        let picked = watch_mimes(&["image/png".to_string(), "Text/Plain".to_string()]);
+        assert_eq!(picked, ["text/plain", "image/png"]);
+
+        // Types we never watch can't be smuggled in through the allowlist.
+        assert!(watch_mimes(&["application/x-unknown".to_string()]).is_empty());
+
+        let none = ["none".to_string()];
+        assert!(watch_nothing(&none) && !watch_nothing(&[]));
+        assert!(watch_mimes(&none).is_empty());
+
+        // The UIs' per-kind lists only name types the watcher knows.
+        use utils::kinds::{FILE_WATCH_MIMES, IMAGE_WATCH_MIMES, TEXT_WATCH_MIMES};
+        let owned = |list: &[&str]| list.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+        for list in [TEXT_WATCH_MIMES, FILE_WATCH_MIMES] {
+            assert_eq!(watch_mimes(&owned(list)).len(), list.len(), "{list:?}");
+        }
+        assert_eq!(watch_mimes(&owned(IMAGE_WATCH_MIMES)), image_mimes());
+    }
+
+    #[tokio::test]
+    async fn auto_mode_follows_probe_result() {
+        let probe = |r| std::future::ready(r);
+        assert_eq!(
+            resolve_watch_mode("auto", probe(WatchProbe::Fired))
+                .await
+                .unwrap(),
+            "watch"
+        );
+        assert_eq!(
+            resolve_watch_mode("auto", probe(WatchProbe::Silent))
+                .await
+                .unwrap(),
+            "poll"
+        );
+        assert_eq!(
+            resolve_watch_mode("auto", probe(WatchProbe::Unavailable))
+                .await
+                .unwrap(),
+            "poll"
+        );
+
+        // Explicit modes never run the probe.
+        let never = async { unreachable!("probe must not run") };
+        assert_eq!(resolve_watch_mode("poll", never).await.unwrap(), "poll");
+        let never = async { unreachable!("probe must not run") };
+        assert_eq!(resolve_watch_mode("watch", never).await.unwrap(), "watch");
+        assert!(resolve_watch_mode("bogus", probe(WatchProbe::Fired))
+            .await
+            .is_err());
+    }
+
+    /// Loses `flaky` on its first `drops` writes; fails outright when `broken`. With
+    /// `copied_over`, someone else's copy replaces the first write.
+    #[derive(Default)]
+    struct StubClipboard {
+        flaky: &'static str,
+        drops: std::sync::atomic::AtomicUsize,
+        broken: bool,
+        copied_over: Option<(&'static str, &'static [u8])>,
+        writes: std::sync::atomic::AtomicUsize,
+        offered: std::sync::Mutex<Vec<(String, Vec<u8>)>>,
+        primary: std::sync::Mutex<Vec<(String, Vec<u8>)>>,
+    }
+
+    impl ClipboardSource for StubClipboard {
+        async fn list_types(&self) -> Option<Vec<String>> {
+            let offered = self.offered.lock().unwrap();
+            Some(offered.iter().map(|(m, _)| m.clone()).collect())
+        }
+
+        async fn read(&self, mime: &str) -> anyhow::Result<Vec<u8>> {
+            let offered = self.offered.lock().unwrap();
+            let found = offered.iter().find(|(m, _)| m == mime);
+            found.map(|(_, b)| b.clone()).context("not offered")
+        }
+    }
+
+    impl ClipboardSink for StubClipboard {
+        async fn write(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+            let first = self.writes.fetch_add(1, Ordering::SeqCst) == 0;
+            if self.broken {
+                anyhow::bail!("compositor went away");
+            }
+            let lose = self
+                .drops
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            *self.offered.lock().unwrap() = match self.copied_over.filter(|_| first) {
+                Some((m, b)) => vec![(m.to_string(), b.to_vec())],
+                None => items
+                    .into_iter()
+                    .filter(|(m, _)| !(lose && m == self.flaky))
+                    .collect(),
+            };
+            Ok(())
+        }
+
+        async fn write_primary(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+            *self.primary.lock().unwrap() = items;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn partial_copy_is_reported_and_marker_retried() {
+        let items = || {
+            vec![
+                ("text/plain".to_string(), b"a".to_vec()),
+                (GNOME_COPIED_FILES_MIME.to_string(), b"copy".to_vec()),
+                (APPLIED_MARKER_MIME.to_string(), b"applied".to_vec()),
+            ]
+        };
+        let stub = |flaky, drops: usize| StubClipboard {
+            flaky,
+            drops: drops.into(),
+            ..StubClipboard::default()
+        };
+
+        // Marker lost once: written again, everything landed.
+        let clip = stub(APPLIED_MARKER_MIME, 1);
+        let report = copy_checked(&clip, items()).await.unwrap();
+        assert!(report.retried);
+        assert!(report.missing.is_empty());
+        assert_eq!(report.landed.len(), 3);
+        assert_eq!(clip.writes.load(Ordering::SeqCst), 2);
+
+        // Marker never lands: one retry only, and it's reported.
+        let clip = stub(APPLIED_MARKER_MIME, usize::MAX);
+        let report = copy_checked(&clip, items()).await.unwrap();
+        assert!(report.retried);
+        assert_eq!(report.missing, [APPLIED_MARKER_MIME]);
+        assert_eq!(clip.writes.load(Ordering::SeqCst), 2);
+
+        // Another format lost: reported, but not worth a rewrite.
+        let clip = stub(GNOME_COPIED_FILES_MIME, 1);
+        let report = copy_checked(&clip, items()).await.unwrap();
+        assert!(!report.retried);
+        assert_eq!(report.missing, [GNOME_COPIED_FILES_MIME]);
+        assert_eq!(clip.writes.load(Ordering::SeqCst), 1);
+
+        // Copied over before the marker showed up: the newer copy isn't overwritten.
+        let clip = StubClipboard {
+            copied_over: Some(("text/plain", b"newer")),
+            ..StubClipboard::default()
+        };
+        let report = copy_checked(&clip, items()).await.unwrap();
+        assert!(!report.retried);
+        assert_eq!(clip.writes.load(Ordering::SeqCst), 1);
+        assert_eq!(clip.read("text/plain").await.unwrap(), b"newer");
+
+        let clip = StubClipboard {
+            broken: true,
+            ..StubClipboard::default()
+        };
+        assert!(copy_checked(&clip, items()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn received_text_reaches_primary_only_when_asked() {
+        let clip = StubClipboard::default();
+        copy_text(&clip, None, b"hi", false).await.unwrap();
+        assert_eq!(clip.list_types().await.unwrap(), TEXT_ALIASES);
+        assert!(clip.primary.lock().unwrap().is_empty());
+
+        let clip = StubClipboard::default();
+        copy_text(&clip, None, b"hi", true).await.unwrap();
+        assert_eq!(clip.list_types().await.unwrap(), TEXT_ALIASES);
+        assert_eq!(*clip.primary.lock().unwrap(), text_clipboard_items(b"hi"));
+    }
+
+    #[test]
+    fn received_text_is_offered_under_the_common_aliases() {
+        let items = text_clipboard_items("grüße".as_bytes());
+        let mimes: Vec<&str> = items.iter().map(|(m, _)| m.as_str()).collect();
+        assert_eq!(
+            mimes,
+            [
+                "text/plain;charset=utf-8",
+                "text/plain",
+                "UTF8_STRING",
+                "STRING"
+            ]
+        );
+        assert!(items.iter().all(|(_, b)| b == "grüße".as_bytes()));
+    }
 }
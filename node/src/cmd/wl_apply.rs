@@ -1,52 +1,120 @@
+use anyhow::Context;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncWrite};
 
-use utils::{Kind, Message};
+use utils::{Hello, Kind, Message, MAX_FRAME_BYTES};
 
-use node::clipboard::{wl_copy, wl_copy_multi};
-use node::consts::{APPLIED_MARKER_MIME, FILE_SUPPRESS_KEY, GNOME_COPIED_FILES_MIME, URI_LIST_MIME};
-use node::hash::sha256_hex;
+use node::clipboard::{
+    apply_to_primary, copy_checked, copy_primary_text, copy_text, received_text_mimes,
+    ClipboardSink, ClipboardSource, CopyReport,
+};
+use node::content_filter::filter_incoming;
+use node::consts::{APPLIED_MARKER_MIME, FILE_SUPPRESS_KEY, URI_LIST_MIME};
+use node::events::emit_event;
+use node::extra_mime::extra_mime_clipboard_items;
+use node::hash::{fingerprint, hash_algo, reconcile_fingerprint};
 use node::history::record_recv;
 use node::image_mode::ImageMode;
-use node::net::{connect, send_join};
-use node::paths::{first_8, is_tar_payload, received_dir, safe_for_filename};
-use node::suppress::{set_file_suppress, set_suppress};
-use node::transfer_file::{build_uri_list, unpack_tar_bytes};
-use node::transfer_image::to_png;
-
-fn image_ext_from_mime(mime: &str) -> Option<&'static str> {
-    match mime {
-        "image/png" => Some("png"),
-        "image/jpeg" => Some("jpg"),
-        "image/jpg" => Some("jpg"),
-        "image/webp" => Some("webp"),
-        "image/gif" => Some("gif"),
-        _ => None,
-    }
+use node::instances::ensure_no_other_rooms;
+use node::net::{
+    ack_wanted, connect_in, read_frame_body, send_ack, send_join, send_join_hello, Heartbeat,
+};
+use node::resend::{keep_text_enabled, persist_image_to, persist_text_best_effort};
+use node::publish::{applies_kind, text_only};
+use node::reload::{reload_on_sighup, LiveConfig};
+use node::rich_text::{is_rtf_mime, rtf_clipboard_items};
+use node::room::watch_room;
+use node::say;
+use node::paths::{
+    first_8, is_tar_payload, received_file_path, safe_for_filename, store_received_file,
+    systemd_env_path,
+};
+use node::receive::store_received;
+use node::suppress::{
+    is_paused, record_applied, set_file_suppress, set_suppress, suppress_items, Staleness,
+    FILE_APPLY_SUPPRESS,
+};
+use node::transfer_file::{
+    build_uri_list, bundle_clipboard_items, extract_bundle, orig_path_marker_lines, BundleExpose,
+};
+use node::transfer_image::{force_png_blocking, received_image_mime, to_png_blocking};
+
+/// How wl-apply (and `receive --dir`) handle what arrives.
+pub(super) struct ApplyOpts {
+    pub image_mode: ImageMode,
+    pub bundle_expose: BundleExpose,
+    pub max_listed_items: usize,
+    pub staleness: Staleness,
+    /// `receive --dir`: keep received files, bundles and images here only; the clipboard is
+    /// never read or written.
+    pub store_only: Option<PathBuf>,
 }
 
-pub(super) async fn run_wl_apply(
+pub(super) async fn run_wl_apply<C: ClipboardSource + ClipboardSink>(
     ctx: &super::Ctx,
+    clip: &C,
     room: &str,
     relay: &str,
-    image_mode: ImageMode,
+    opts: ApplyOpts,
 ) -> anyhow::Result<()> {
-    // Guard against accidentally starting multiple appliers (which can cause confusing race-y clipboard behavior).
-    let _lock = super::acquire_instance_lock(&ctx.state_dir, "wl-apply", room, relay)?;
+    let ApplyOpts {
+        image_mode,
+        bundle_expose,
+        max_listed_items,
+        staleness,
+        store_only,
+    } = opts;
+    let _lock = if store_only.is_none() {
+        // Guard against accidentally starting multiple appliers (which can cause confusing race-y clipboard behavior).
+        ensure_no_other_rooms(&ctx.state_dir, "wl-apply", room, relay)?;
+        let lock = super::acquire_instance_lock(&ctx.state_dir, "wl-apply", room, relay)?;
+
+        // Texts kept by an earlier run: expired ones go, and all of them once --keep-text is off.
+        let ttl = if keep_text_enabled() {
+            node::resend::KEEP_TEXT_TTL
+        } else {
+            Duration::ZERO
+        };
+        let pruned = node::resend::prune_kept_texts(&ctx.data.received, ttl).await;
+        if pruned > 0 {
+            log::info!("wl-apply: removed {} kept text(s)", pruned);
+        }
+        Some(lock)
+    } else {
+        None
+    };
 
     // Heartbeat + reconnect:
     // - If the TCP connection drops, don't exit cleanly (systemd won't restart on exit 0).
     // - Periodically send Join as a lightweight heartbeat to keep NAT/stateful firewalls happy.
     let reconnect_backoff = Duration::from_millis(800);
-    let heartbeat_interval = Duration::from_secs(20);
 
     // Simple loop-prevention: skip if we applied same sha recently.
     let mut last_applied_sha: std::collections::HashMap<String, String> =
         std::collections::HashMap::new();
 
+    // Follow room switch requests (see `node::room`) without restarting.
+    let mut room_rx = watch_room(&ctx.state_dir, room);
+    // SIGHUP re-reads the env file: a new image mode applies live, a new relay reconnects.
+    let live = LiveConfig {
+        room: room.to_string(),
+        relay: relay.to_string(),
+        max_text_bytes: None,
+        max_image_bytes: None,
+        max_file_bytes: None,
+        image_mode,
+    };
+    let mut live = reload_on_sighup(live, systemd_env_path(), ctx.state_dir.clone())
+        .context("install SIGHUP handler")?;
+    let mut image_mode = image_mode;
+
     loop {
-        let stream = match connect(relay).await {
+        let mut room = room_rx.borrow_and_update().clone();
+        let relay = live.borrow().relay.clone();
+        let relay = relay.as_str();
+        let stream = match connect_in(&ctx.state_dir, relay).await {
             Ok(s) => s,
             Err(e) => {
                 log::warn!("wl-apply: connect failed: {e:?}");
@@ -56,26 +124,57 @@ pub(super) async fn run_wl_apply(
         };
 
         let (mut reader, mut writer) = stream.into_split();
-        if let Err(e) = send_join(&mut writer, &ctx.device_id, &ctx.device_name, room).await {
+        let joined = send_join_hello(&mut writer, &ctx.device_id, &ctx.device_name, &room).await;
+        if let Err(e) = joined {
             log::warn!("wl-apply: send join failed: {e:?}");
             tokio::time::sleep(reconnect_backoff).await;
             continue;
         }
         log::info!("wl-apply: connected room='{}' relay='{}'", room, relay);
-        println!("wl-apply: room='{}' relay='{}'", room, relay);
+        match store_only.as_deref() {
+            Some(dir) => say!(
+                "receive: room='{}' relay='{}' dir={}",
+                room,
+                relay,
+                dir.display()
+            ),
+            None => say!("wl-apply: room='{}' relay='{}'", room, relay),
+        }
 
-        let mut hb = tokio::time::interval(heartbeat_interval);
-        hb.tick().await;
+        let mut hb = Heartbeat::new();
+        // The relay's answer to our `Hello`; relays that never send one can't take acks.
+        let mut relay_hello = None;
 
         loop {
             let len: usize = tokio::select! {
                 _ = hb.tick() => {
-                    if let Err(e) = send_join(&mut writer, &ctx.device_id, &ctx.device_name, room).await {
+                    if let Err(e) = send_join(&mut writer, &ctx.device_id, &ctx.device_name, &room).await {
                         log::warn!("wl-apply: heartbeat failed (will reconnect): {e:?}");
                         break;
                     }
                     continue;
                 }
+                Ok(()) = room_rx.changed() => {
+                    // Re-join on the same connection; the relay moves our registration.
+                    let next = room_rx.borrow_and_update().clone();
+                    if let Err(e) = send_join(&mut writer, &ctx.device_id, &ctx.device_name, &next).await {
+                        log::warn!("wl-apply: room switch join failed (will reconnect): {e:?}");
+                        break;
+                    }
+                    say!("wl-apply: switched room '{}' -> '{}'", room, next);
+                    room = next;
+                    last_applied_sha.clear();
+                    continue;
+                }
+                Ok(()) = live.changed() => {
+                    let next = live.borrow_and_update().clone();
+                    image_mode = next.image_mode;
+                    if next.relay != relay {
+                        say!("wl-apply: relay '{}' -> '{}', reconnecting", relay, next.relay);
+                        break;
+                    }
+                    continue;
+                }
                 res = reader.read_u32() => {
                     match res {
                         Ok(l) => l as usize,
@@ -87,17 +186,28 @@ pub(super) async fn run_wl_apply(
                 }
             };
 
-            let mut buf = vec![0u8; len];
-            if let Err(e) = reader.read_exact(&mut buf).await {
-                log::warn!("wl-apply: read payload failed (will reconnect): {e:?}");
+            if len > MAX_FRAME_BYTES {
+                log::error!(
+                    "wl-apply: frame too large (will reconnect): len={} max={}",
+                    len,
+                    MAX_FRAME_BYTES
+                );
                 break;
             }
-            let msg = match Message::try_from_bytes(&buf) {
+            let buf = match read_frame_body(&mut reader, len).await {
+                Ok(b) => b,
+                Err(e) => {
+                    log::warn!("wl-apply: read payload failed (will reconnect): {e:#}");
+                    break;
+                }
+            };
+            let room = room.as_str();
+            let mut msg = match Message::try_from_bytes(&buf) {
                 Ok(m) => m,
                 Err(e) => {
                     let prefix_len = buf.len().min(16);
                     log::warn!(
-                        "wl-apply: decode failed (will reconnect): len={} prefix={:02x?} err={:?}",
+                        "wl-apply: decode failed (will reconnect): len={} prefix={:02x?} err={}",
                         len,
                         &buf[..prefix_len],
                         e
@@ -118,14 +228,164 @@ pub(super) async fn run_wl_apply(
             if msg.device_id == ctx.device_id {
                 continue;
             }
+            // Frames already in flight from the room we just left.
+            if msg.room != room {
+                continue;
+            }
+            // Before the staleness check: the relay hands us peers' hellos from when they joined.
+            if let Some(hello) = msg.hello() {
+                log::info!(
+                    "wl-apply: {} speaks v{} caps={:?}",
+                    msg.device_id,
+                    hello.version,
+                    hello.caps
+                );
+                if let Some(banner) = msg.relay_banner() {
+                    relay_hello = Some(hello.clone());
+                    log::info!(
+                        "wl-apply: relay {} runs v{} (up {}s)",
+                        relay,
+                        banner.version,
+                        banner.uptime_secs
+                    );
+                }
+                continue;
+            }
+            // Other peers' acks; only a sender waiting with `--wait-ack` needs them.
+            if matches!(msg.kind, Kind::Ack) {
+                continue;
+            }
+            // E.g. a backlog after reconnecting: don't let it overwrite a newer local clipboard.
+            if staleness.is_stale(&msg, utils::now_ms()) {
+                log::info!(
+                    "wl-apply: dropping stale kind={:?} from={} age={}ms",
+                    msg.kind,
+                    msg.device_id,
+                    utils::now_ms().saturating_sub(msg.ts)
+                );
+                continue;
+            }
+            // Paused (or disabled): keep the connection (and heartbeat) but leave the clipboard alone.
+            if is_paused(&ctx.state_dir, room).await {
+                log::debug!("wl-apply: room '{}' paused or sync disabled; drop kind={:?}", room, msg.kind);
+                continue;
+            }
+            if !applies_kind(&msg.kind, text_only()) {
+                log::debug!("wl-apply: --text-only; drop kind={:?}", msg.kind);
+                continue;
+            }
+            // Before dedupe/suppression: those must see the bytes that reach the clipboard.
+            if !filter_incoming(&mut msg).await {
+                log::debug!("wl-apply: dropped by --apply-filter kind={:?}", msg.kind);
+                continue;
+            }
+            if let Some(dir) = store_only.as_deref() {
+                if !matches!(msg.kind, Kind::Text | Kind::Image | Kind::File) {
+                    continue;
+                }
+                record_recv(
+                    &ctx.data.history,
+                    &ctx.device_id,
+                    Some(ctx.device_name.clone()),
+                    room,
+                    relay,
+                    &msg,
+                )
+                .await;
+                emit_event("recv", &msg);
+
+                let from = msg.sender_name.as_deref().unwrap_or(&msg.device_id);
+                match store_received(dir, &msg, bundle_expose, max_listed_items).await {
+                    Ok(Some(path)) => {
+                        say!(
+                            "received {:?} from {} -> {} ({} bytes)",
+                            msg.kind,
+                            from,
+                            path.display(),
+                            msg.size
+                        );
+                        ack(&mut writer, ctx, &msg, relay_hello.as_ref()).await;
+                    }
+                    Ok(None) => say!(
+                        "received {:?} from {} ({} bytes, not stored)",
+                        msg.kind,
+                        from,
+                        msg.size
+                    ),
+                    Err(e) => log::warn!("receive: storing {:?} failed: {e:#}", msg.kind),
+                }
+                continue;
+            }
+            // A peer on another --hash-algo: suppression keys must match what our watcher hashes.
+            if let (Some(sha), Some(payload)) = (&msg.sha256, &msg.payload) {
+                let local = reconcile_fingerprint(sha, payload, hash_algo());
+                msg.sha256 = Some(local);
+            }
             if let Some(sha) = msg.sha256.as_deref() {
                 let key = msg.mime.clone().unwrap_or_else(|| "(no-mime)".to_string());
                 if last_applied_sha.get(&key).map(|s| s.as_str()) == Some(sha) {
+                    // Already on our clipboard, which still counts as landed.
+                    ack(&mut writer, ctx, &msg, relay_hello.as_ref()).await;
                     continue;
                 }
             }
 
+            if !matches!(
+                msg.kind,
+                Kind::Join | Kind::Hello | Kind::Replay | Kind::Ping | Kind::Pong
+            ) {
+                emit_event("apply", &msg);
+            }
+
             match msg.kind {
+                Kind::Text if msg.mime.as_deref().is_some_and(is_rtf_mime) => {
+                    let Some(payload) = msg.payload.as_deref() else {
+                        continue;
+                    };
+                    record_recv(
+                        &ctx.data.history,
+                        &ctx.device_id,
+                        Some(ctx.device_name.clone()),
+                        room,
+                        relay,
+                        &msg,
+                    )
+                    .await;
+                    let mime = msg.mime.clone().unwrap_or_default();
+                    let sha = msg.sha256.clone().unwrap_or_else(|| fingerprint(payload));
+                    if keep_text_enabled() {
+                        persist_text_best_effort(&ctx.data.received, &sha, &mime, payload).await;
+                    }
+                    let items = rtf_clipboard_items(&mime, payload);
+                    // Suppress every offered type so the local watcher doesn't echo it back.
+                    let suppress_items: Vec<(String, String)> = items
+                        .iter()
+                        .map(|(m, b)| {
+                            let h = if is_rtf_mime(m) { sha.clone() } else { fingerprint(b) };
+                            (m.clone(), h)
+                        })
+                        .collect();
+                    record_applied(
+                        &ctx.state_dir,
+                        room,
+                        &msg.event_id,
+                        items.iter().map(|(_, b)| fingerprint(b)),
+                    )
+                    .await;
+                    let plain = items
+                        .iter()
+                        .find(|(m, _)| m.starts_with("text/plain"))
+                        .map(|(_, b)| b.clone());
+                    log_copy(copy_checked(clip, items).await);
+                    if let Some(plain) = plain.filter(|_| apply_to_primary()) {
+                        copy_primary_text(clip, &plain).await;
+                    }
+                    for (m, h) in suppress_items {
+                        set_suppress(&ctx.state_dir, room, &m, &h, Duration::from_secs(2)).await;
+                        last_applied_sha.insert(m, h);
+                    }
+                    say!("applied {} ({} bytes, +plain fallback)", mime, payload.len());
+                }
                 Kind::Text => {
                     if let Some(payload) = msg.payload.as_deref() {
                         let preview = String::from_utf8_lossy(payload)
@@ -133,57 +393,87 @@ pub(super) async fn run_wl_apply(
                             .take(120)
                             .collect::<String>();
                         log::debug!("wl-apply: text preview={}", preview);
-                        wl_copy("text/plain;charset=utf-8", payload).await.ok();
-                        record_recv(&ctx.device_id, Some(ctx.device_name.clone()), room, relay, &msg).await;
+                        record_applied(
+                            &ctx.state_dir,
+                            room,
+                            &msg.event_id,
+                            [fingerprint(payload)],
+                        )
+                        .await;
+                        // Non-UTF-8 text keeps the MIME (and charset) it was sent with.
+                        let mime = msg.mime.as_deref();
+                        copy_text(clip, mime, payload, apply_to_primary())
+                            .await
+                            .ok();
+                        record_recv(
+                            &ctx.data.history,
+                            &ctx.device_id,
+                            Some(ctx.device_name.clone()),
+                            room,
+                            relay,
+                            &msg,
+                        )
+                        .await;
+                        let sha = msg.sha256.clone().unwrap_or_else(|| fingerprint(payload));
+                        let persist_mime = mime.unwrap_or("text/plain;charset=utf-8");
+                        if keep_text_enabled() {
+                            let dir = &ctx.data.received;
+                            persist_text_best_effort(dir, &sha, persist_mime, payload).await;
+                        }
                         if let Some(sha) = msg.sha256.as_deref() {
-                            set_suppress(
-                                &ctx.state_dir,
-                                room,
-                                "text/plain;charset=utf-8",
-                                sha,
-                                Duration::from_secs(2),
-                            )
-                            .await;
-                            last_applied_sha
-                                .insert("text/plain;charset=utf-8".to_string(), sha.to_string());
+                            // Every type the text went out under (see `copy_text`).
+                            for mime in received_text_mimes(mime) {
+                                let ttl = Duration::from_secs(2);
+                                set_suppress(&ctx.state_dir, room, mime, sha, ttl).await;
+                                last_applied_sha.insert(mime.to_string(), sha.to_string());
+                            }
                         }
-                        println!("applied text ({} bytes)", payload.len());
+                        say!("applied text ({} bytes)", payload.len());
                     }
                 }
                 Kind::Image => {
                     if let Some(payload) = msg.payload.as_deref() {
-                        record_recv(&ctx.device_id, Some(ctx.device_name.clone()), room, relay, &msg).await;
-                        let mime = msg.mime.clone().unwrap_or_else(|| "image/png".to_string());
+                        record_recv(
+                            &ctx.data.history,
+                            &ctx.device_id,
+                            Some(ctx.device_name.clone()),
+                            room,
+                            relay,
+                            &msg,
+                        )
+                        .await;
+                        let mime = received_image_mime(msg.mime.as_deref(), payload);
 
                         // Best-effort: persist the received image so the UI can preview it.
                         // Path convention: $XDG_DATA_HOME/multicliprelay/received/<sha8>/image.<ext>
-                        let sha = msg.sha256.clone().unwrap_or_else(|| sha256_hex(payload));
-                        let sha8 = first_8(&sha).to_string();
-                        let dir = received_dir().join(&sha8);
-                        tokio::fs::create_dir_all(&dir).await.ok();
-                        if let Some(ext) = image_ext_from_mime(&mime) {
-                            let p = dir.join(format!("image.{ext}"));
-                            // ignore errors (preview is best-effort)
-                            let _ = tokio::fs::write(&p, payload).await;
-                        } else {
-                            let p = dir.join("image.bin");
-                            let _ = tokio::fs::write(&p, payload).await;
-                        }
+                        // Converted variants land next to the original, so the stored files
+                        // always include what was actually put on the clipboard.
+                        let sha = msg.sha256.clone().unwrap_or_else(|| fingerprint(payload));
+                        let dir = &ctx.data.received;
+                        persist_image_to(dir, &sha, &mime, payload).await;
 
                         match image_mode {
                             ImageMode::ForcePng => {
-                                let (apply_mime, apply_bytes) = match to_png(payload) {
-                                    Ok(png) => ("image/png".to_string(), png),
+                                let (apply_mime, apply_bytes) = match force_png_blocking(&mime, payload.to_vec()).await {
+                                    Ok((m, b)) => (m.to_string(), b),
                                     Err(_) => (mime.clone(), payload.to_vec()),
                                 };
 
                                 // If we generated a png fallback, persist it too for easier preview.
-                                if apply_mime == "image/png" {
-                                    let p = dir.join("image.png");
-                                    let _ = tokio::fs::write(&p, &apply_bytes).await;
+                                if apply_mime != mime {
+                                    persist_image_to(dir, &sha, &apply_mime, &apply_bytes).await;
                                 }
 
-                                let _ = wl_copy(&apply_mime, &apply_bytes).await;
+                                record_applied(
+                                    &ctx.state_dir,
+                                    room,
+                                    &msg.event_id,
+                                    [fingerprint(&apply_bytes)],
+                                )
+                                .await;
+                                let _ = clip
+                                    .write(vec![(apply_mime.clone(), apply_bytes.clone())])
+                                    .await;
                                 if let Some(sha) = msg.sha256.as_deref() {
                                     set_suppress(
                                         &ctx.state_dir,
@@ -196,12 +486,22 @@ pub(super) async fn run_wl_apply(
                                     last_applied_sha
                                         .insert(apply_mime.clone(), sha.to_string());
                                 }
-                                println!("applied {} ({} bytes)", apply_mime, apply_bytes.len());
+                                say!("applied {} ({} bytes)", apply_mime, apply_bytes.len());
                             }
-                            ImageMode::Passthrough => {
+                            // force-webp converts on send; what arrives is applied as it is.
+                            ImageMode::Passthrough | ImageMode::ForceWebp => {
                                 let apply_mime = mime.clone();
                                 let apply_bytes = payload.to_vec();
-                                let _ = wl_copy(&apply_mime, &apply_bytes).await;
+                                record_applied(
+                                    &ctx.state_dir,
+                                    room,
+                                    &msg.event_id,
+                                    [fingerprint(&apply_bytes)],
+                                )
+                                .await;
+                                let _ = clip
+                                    .write(vec![(apply_mime.clone(), apply_bytes.clone())])
+                                    .await;
                                 if let Some(sha) = msg.sha256.as_deref() {
                                     set_suppress(
                                         &ctx.state_dir,
@@ -214,14 +514,23 @@ pub(super) async fn run_wl_apply(
                                     last_applied_sha
                                         .insert(apply_mime.clone(), sha.to_string());
                                 }
-                                println!("applied {} ({} bytes)", apply_mime, apply_bytes.len());
+                                say!("applied {} ({} bytes)", apply_mime, apply_bytes.len());
                             }
                             ImageMode::MultiMime => {
                                 // Offer both the original format and a PNG fallback (when possible).
                                 if mime == "image/png" {
                                     let apply_mime = mime.clone();
                                     let apply_bytes = payload.to_vec();
-                                    let _ = wl_copy(&apply_mime, &apply_bytes).await;
+                                    record_applied(
+                                        &ctx.state_dir,
+                                        room,
+                                        &msg.event_id,
+                                        [fingerprint(&apply_bytes)],
+                                    )
+                                    .await;
+                                    let _ = clip
+                                        .write(vec![(apply_mime.clone(), apply_bytes.clone())])
+                                        .await;
                                     if let Some(sha) = msg.sha256.as_deref() {
                                         set_suppress(
                                             &ctx.state_dir,
@@ -234,7 +543,7 @@ pub(super) async fn run_wl_apply(
                                         last_applied_sha
                                             .insert(apply_mime.clone(), sha.to_string());
                                     }
-                                    println!("applied {} ({} bytes)", apply_mime, apply_bytes.len());
+                                    say!("applied {} ({} bytes)", apply_mime, apply_bytes.len());
                                 } else {
                                     let orig_bytes = payload.to_vec();
                                     let mut items = vec![(mime.clone(), orig_bytes.clone())];
@@ -245,13 +554,21 @@ pub(super) async fn run_wl_apply(
                                         suppress_items.push((mime.clone(), sha.to_string()));
                                     }
 
-                                    if let Ok(png) = to_png(&orig_bytes) {
-                                        let png_sha = sha256_hex(&png);
+                                    if let Ok(png) = to_png_blocking(orig_bytes.clone()).await {
+                                        let png_sha = fingerprint(&png);
+                                        persist_image_to(dir, &sha, "image/png", &png).await;
                                         items.push(("image/png".to_string(), png));
                                         suppress_items.push(("image/png".to_string(), png_sha));
                                     }
 
-                                    let _ = wl_copy_multi(items).await;
+                                    record_applied(
+                                        &ctx.state_dir,
+                                        room,
+                                        &msg.event_id,
+                                        items.iter().map(|(_, b)| fingerprint(b)),
+                                    )
+                                    .await;
+                                    log_copy(copy_checked(clip, items).await);
                                     for (m, sha) in suppress_items {
                                         set_suppress(
                                             &ctx.state_dir,
@@ -263,7 +580,7 @@ pub(super) async fn run_wl_apply(
                                         .await;
                                         last_applied_sha.insert(m, sha);
                                     }
-                                    println!("applied multi-mime {} (+png fallback)", mime);
+                                    say!("applied multi-mime {} (+png fallback)", mime);
                                 }
                             }
                             ImageMode::SpoofPng => {
@@ -276,7 +593,16 @@ pub(super) async fn run_wl_apply(
 
                                 let apply_mime = "image/png".to_string();
                                 let apply_bytes = payload.to_vec();
-                                let _ = wl_copy(&apply_mime, &apply_bytes).await;
+                                record_applied(
+                                    &ctx.state_dir,
+                                    room,
+                                    &msg.event_id,
+                                    [fingerprint(&apply_bytes)],
+                                )
+                                .await;
+                                let _ = clip
+                                    .write(vec![(apply_mime.clone(), apply_bytes.clone())])
+                                    .await;
 
                                 if let Some(sha) = msg.sha256.as_deref() {
                                     set_suppress(
@@ -290,7 +616,7 @@ pub(super) async fn run_wl_apply(
                                     last_applied_sha
                                         .insert(apply_mime.clone(), sha.to_string());
                                 }
-                                println!(
+                                say!(
                                     "applied spoof-png (orig {} bytes as image/png)",
                                     apply_bytes.len()
                                 );
@@ -302,15 +628,26 @@ pub(super) async fn run_wl_apply(
                     let Some(payload) = msg.payload.as_deref() else {
                         continue;
                     };
-                    record_recv(&ctx.device_id, Some(ctx.device_name.clone()), room, relay, &msg).await;
-                    let sha = msg.sha256.clone().unwrap_or_else(|| sha256_hex(payload));
+                    record_recv(
+                        &ctx.data.history,
+                        &ctx.device_id,
+                        Some(ctx.device_name.clone()),
+                        room,
+                        relay,
+                        &msg,
+                    )
+                    .await;
+                    let sha = msg.sha256.clone().unwrap_or_else(|| fingerprint(payload));
                     if last_applied_sha
                         .get(FILE_SUPPRESS_KEY)
                         .map(|s| s.as_str())
                         == Some(sha.as_str())
                     {
+                        ack(&mut writer, ctx, &msg, relay_hello.as_ref()).await;
                         continue;
                     }
+                    record_applied(&ctx.state_dir, room, &msg.event_id, [sha.as_str()]).await;
+                    set_file_suppress(&ctx.state_dir, room, &sha, Duration::from_secs(2)).await;
 
                     let name = msg
                         .name
@@ -318,249 +655,72 @@ pub(super) async fn run_wl_apply(
                         .unwrap_or_else(|| format!("multicliprelay-{}", &sha[..8]));
                     let safe = safe_for_filename(&name);
 
-                    let dir = received_dir();
-                    tokio::fs::create_dir_all(&dir).await.ok();
+                    let dir = &ctx.data.received;
+                    tokio::fs::create_dir_all(dir).await.ok();
                     let sha8 = first_8(&sha).to_string();
 
-                    // If this is a tar bundle, extract into a directory and put that directory into the clipboard.
-                    if is_tar_payload(&name, msg.mime.as_deref()) {
-                        // Prevent immediate feedback-loop: wl-apply writes file clipboard formats,
-                        // which can trigger wl-watch almost instantly on the same machine.
-                        // Use a short wildcard suppress window to ignore any file/text changes.
-                        set_file_suppress(&ctx.state_dir, room, "*", Duration::from_millis(1500)).await;
-                        set_suppress(
-                            &ctx.state_dir,
-                            room,
-                            "text/plain;charset=utf-8",
-                            "*",
-                            Duration::from_millis(1500),
-                        )
-                        .await;
-                        set_suppress(
-                            &ctx.state_dir,
-                            room,
-                            "text/plain",
-                            "*",
-                            Duration::from_millis(1500),
+                    // `--extra-mime`: hand the app its own format back instead of a file.
+                    if let Some(items) = extra_mime_clipboard_items(&msg) {
+                        suppress_items(&ctx.state_dir, room, &items, FILE_APPLY_SUPPRESS).await;
+                        log_copy(copy_checked(clip, items).await);
+                        say!(
+                            "applied {} ({} bytes)",
+                            msg.mime.as_deref().unwrap_or_default(),
+                            payload.len()
+                        );
+                    } else if is_tar_payload(&name, msg.mime.as_deref()) {
+                        // A tar bundle: extract into a directory and put that directory into the clipboard.
+                        // Ensures "copy folder" semantics across file managers (see `--bundle-expose`);
+                        // a repeat of an earlier bundle reuses what is already on disk.
+                        let out_dir =
+                            received_file_path(dir, &sha, Some(&name), msg.mime.as_deref());
+                        let bundle = match extract_bundle(
+                            payload,
+                            &sha,
+                            &out_dir,
+                            &name,
+                            bundle_expose,
+                            max_listed_items,
                         )
-                        .await;
-
-                        let stem = safe
-                            .trim_end_matches(".tar")
-                            .trim_end_matches(".TAR")
-                            .to_string();
-                        let out_dir = dir.join(format!("{}_{}", sha8, stem));
-                        tokio::fs::create_dir_all(&out_dir).await.ok();
-
-                        // unpack in a blocking task
-                        let out_dir2 = out_dir.clone();
-                        let payload2 = payload.to_vec();
-                        let _ = tokio::task::spawn_blocking(move || unpack_tar_bytes(&payload2, &out_dir2)).await;
-
-                        // Ensure "copy folder" semantics across file managers:
-                        // expose a *single root directory* in the clipboard.
-                        // Some environments may copy a folder as its children (multiple top-level entries).
-                        // In that case, we synthesize a wrapper folder and move entries into it.
-                        let mut entries = node::transfer_file::list_top_level_items(&out_dir, 5000);
-
-                        let sanitize_component = |s: &str| -> String {
-                            let mut out: String = s
-                                .chars()
-                                .map(|c| match c {
-                                    '/' | '\\' | '\0' => '_',
-                                    _ => c,
-                                })
-                                .collect();
-                            if out.is_empty() {
-                                out = "multicliprelay".to_string();
+                        .await
+                        {
+                            Ok(b) => b,
+                            Err(e) => {
+                                log::warn!("wl-apply: extract bundle into {} failed: {e:#}", out_dir.display());
+                                continue;
                             }
-                            if out == "." || out == ".." {
-                                out = format!("_{}", out);
-                            }
-                            out
                         };
-
-                        // Prefer the raw tar stem (preserves unicode) rather than `safe_for_filename`.
-                        let stem_raw = name
-                            .trim_end_matches(".tar")
-                            .trim_end_matches(".TAR")
-                            .to_string();
-                        let wrapper_name = sanitize_component(&stem_raw);
-
-                        // Generic names come from multi-selection without a clear folder intent.
-                        // In that case we should preserve multi-item paste semantics (no wrapper).
-                        let is_generic_bundle_name = stem_raw.starts_with("multicliprelay-bundle-");
-
-                        async fn move_entry_best_effort(
-                            src: &std::path::PathBuf,
-                            dst: &std::path::PathBuf,
-                        ) {
-                            if tokio::fs::rename(src, dst).await.is_ok() {
-                                return;
-                            }
-
-                            let md = tokio::fs::metadata(src).await;
-                            let Ok(md) = md else {
-                                return;
-                            };
-
-                            if md.is_file() {
-                                if tokio::fs::copy(src, dst).await.is_ok() {
-                                    let _ = tokio::fs::remove_file(src).await;
-                                }
-                                return;
-                            }
-
-                            if md.is_dir() {
-                                let src2 = src.clone();
-                                let dst2 = dst.clone();
-                                let _ = tokio::task::spawn_blocking(move || {
-                                    use walkdir::WalkDir;
-                                    std::fs::create_dir_all(&dst2).ok();
-                                    for e in WalkDir::new(&src2)
-                                        .follow_links(false)
-                                        .into_iter()
-                                        .filter_map(|e| e.ok())
-                                    {
-                                        let p = e.path();
-                                        let Ok(rel) = p.strip_prefix(&src2) else {
-                                            continue;
-                                        };
-                                        let target = dst2.join(rel);
-                                        if e.file_type().is_dir() {
-                                            std::fs::create_dir_all(&target).ok();
-                                        } else if e.file_type().is_file() {
-                                            if let Some(parent) = target.parent() {
-                                                std::fs::create_dir_all(parent).ok();
-                                            }
-                                            std::fs::copy(p, &target).ok();
-                                        }
-                                    }
-                                    std::fs::remove_dir_all(&src2).ok();
-                                })
-                                .await;
-                            }
+                        if bundle.reused {
+                            log::info!("wl-apply: bundle {} already extracted at {}", sha8, out_dir.display());
                         }
+                        let items = bundle_clipboard_items(&bundle, &sha, &name, &msg);
 
-                        // Decide how to expose extracted content:
-                        // - If it looks like a generic multi-item bundle, keep multi-item semantics.
-                        // - Otherwise, prefer a single root directory (wrapper) for folder-copy semantics.
-                        let (root_paths, root_name_for_plain) = if entries.is_empty() {
-                            (vec![out_dir.clone()], wrapper_name.clone())
-                        } else if entries.len() == 1 {
-                            let p = entries[0].clone();
-                            let n = p
-                                .file_name()
-                                .and_then(|s| s.to_str())
-                                .map(|s| s.to_string())
-                                .unwrap_or_else(|| wrapper_name.clone());
-                            (vec![p], n)
-                        } else if is_generic_bundle_name {
-                            // Preserve multi-item paste (e.g. user copied multiple files).
-                            let first_name = entries[0]
-                                .file_name()
-                                .and_then(|s| s.to_str())
-                                .unwrap_or("items")
-                                .to_string();
-                            (entries.clone(), first_name)
-                        } else {
-                            // Synthesize a wrapper folder and move top-level entries into it.
-                            let wrapper = out_dir.join(&wrapper_name);
-                            tokio::fs::create_dir_all(&wrapper).await.ok();
-
-                            for src in entries.drain(..) {
-                                if src == wrapper {
-                                    continue;
-                                }
-                                let Some(base) = src.file_name().map(|s| s.to_os_string()) else {
-                                    continue;
-                                };
-                                let mut dst = wrapper.join(&base);
-                                if tokio::fs::metadata(&dst).await.is_ok() {
-                                    // Best-effort collision avoidance.
-                                    let b = base.to_string_lossy();
-                                    let ts = std::time::SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .unwrap_or_default()
-                                        .as_millis();
-                                    dst = wrapper.join(format!("{}_{}", b, ts));
-                                }
-                                move_entry_best_effort(&src, &dst).await;
-                            }
-
-                            (vec![wrapper], wrapper_name.clone())
-                        };
-
-                        // Clipboard payloads.
-                        // - Always expose paths via uri-list.
-                        // - Avoid providing large/ambiguous text/plain for file bundles to reduce
-                        //   file manager paste oddities (esp. KDE/Dolphin). Keep marker mime.
-                        let uri_list = build_uri_list(&root_paths);
-                        let gnome_list = format!("copy\n{}", uri_list);
-
-                        let items = vec![
-                            (
-                                "text/plain;charset=utf-8".to_string(),
-                                root_name_for_plain.as_bytes().to_vec(),
-                            ),
-                            (
-                                "text/plain".to_string(),
-                                root_name_for_plain.as_bytes().to_vec(),
-                            ),
-                            (URI_LIST_MIME.to_string(), uri_list.as_bytes().to_vec()),
-                            (
-                                GNOME_COPIED_FILES_MIME.to_string(),
-                                gnome_list.as_bytes().to_vec(),
-                            ),
-                            (
-                                APPLIED_MARKER_MIME.to_string(),
-                                format!(
-                                    "applied\nkind=tar\nsha={}\nname={}\nroot_hint={}\n",
-                                    sha, name, root_name_for_plain
-                                )
-                                .as_bytes()
-                                .to_vec(),
-                            ),
-                        ];
-
-                        let _ = wl_copy_multi(items).await;
-                        println!(
+                        // Prevent immediate feedback-loop: wl-watch fires almost instantly on the
+                        // same machine. Suppress exactly what we write, not any new copy.
+                        suppress_items(&ctx.state_dir, room, &items, FILE_APPLY_SUPPRESS).await;
+                        log_copy(copy_checked(clip, items).await);
+                        say!(
                             "received bundle -> {} item(s) ({} bytes)",
-                            root_paths.len(),
+                            bundle.roots.len(),
                             payload.len(),
                         );
                     } else {
-                        // Same feedback-loop guard for single-file payloads.
-                        set_file_suppress(&ctx.state_dir, room, "*", Duration::from_millis(1500)).await;
-                        set_suppress(
-                            &ctx.state_dir,
-                            room,
-                            "text/plain;charset=utf-8",
-                            "*",
-                            Duration::from_millis(1500),
-                        )
-                        .await;
-                        set_suppress(
-                            &ctx.state_dir,
-                            room,
-                            "text/plain",
-                            "*",
-                            Duration::from_millis(1500),
-                        )
-                        .await;
-
                         // Store under a stable hash directory, but keep the original filename.
                         // This avoids "sha prefix" polluting the visible filename on the receiving side.
-                        let out_dir = dir.join(&sha8);
-                        tokio::fs::create_dir_all(&out_dir).await.ok();
-                        let out_path = out_dir.join(&safe);
-                        tokio::fs::write(&out_path, payload).await.ok();
+                        let out_path = match store_received_file(dir, &sha8, &safe, payload).await {
+                            Ok(p) => p,
+                            Err(e) => {
+                                log::warn!("wl-apply: store received file under {} failed: {e:?}", dir.display());
+                                continue;
+                            }
+                        };
 
                         // Write clipboard as file URI + plain path.
-                        // NOTE: We can't preserve original remote paths; we point to the local received file.
-                        let uri = build_uri_list(&vec![out_path.clone()]);
+                        // The sender's original path is only informational (marker `orig_path=`);
+                        // we point to the local received file.
+                        let uri = build_uri_list(std::slice::from_ref(&out_path));
                         let plain = out_path.to_string_lossy().to_string();
-                        let _ = wl_copy_multi(vec![
+                        let items = vec![
                             (
                                 "text/plain;charset=utf-8".to_string(),
                                 plain.as_bytes().to_vec(),
@@ -568,22 +728,240 @@ pub(super) async fn run_wl_apply(
                             (URI_LIST_MIME.to_string(), uri.as_bytes().to_vec()),
                             (
                                 APPLIED_MARKER_MIME.to_string(),
-                                format!("applied\nkind=file\nsha={}\nname={}\n", sha, name)
-                                    .as_bytes()
-                                    .to_vec(),
+                                format!(
+                                    "applied\nkind=file\nsha={}\nname={}\n{}",
+                                    sha,
+                                    name,
+                                    orig_path_marker_lines(&msg)
+                                )
+                                .as_bytes()
+                                .to_vec(),
                             ),
-                        ])
-                        .await;
-                        println!("received file -> {} ({} bytes)", out_path.display(), payload.len());
+                        ];
+                        // Same feedback-loop guard; `text/plain` reads return the same path.
+                        let mut suppressed = items.clone();
+                        suppressed.push(("text/plain".to_string(), plain.as_bytes().to_vec()));
+                        suppress_items(&ctx.state_dir, room, &suppressed, FILE_APPLY_SUPPRESS).await;
+                        log_copy(copy_checked(clip, items).await);
+                        say!("received file -> {} ({} bytes)", out_path.display(), payload.len());
                     }
 
-                    set_file_suppress(&ctx.state_dir, room, &sha, Duration::from_secs(2)).await;
                     last_applied_sha.insert(FILE_SUPPRESS_KEY.to_string(), sha.clone());
                 }
-                Kind::Join => {}
+                Kind::Join => {
+                    if let Some(reason) = msg.rejection() {
+                        log::warn!("wl-apply: relay refused connection: {}", reason);
+                    }
+                }
+                // Hellos are handled before the staleness check; replays and pings are never
+                // asked for here.
+                Kind::Hello | Kind::Replay | Kind::Ping | Kind::Pong | Kind::Ack => {}
+            }
+            // Failed applies `continue` above, so what gets here is on the clipboard.
+            if matches!(msg.kind, Kind::Text | Kind::Image | Kind::File) && msg.payload.is_some() {
+                ack(&mut writer, ctx, &msg, relay_hello.as_ref()).await;
             }
         }
 
         tokio::time::sleep(reconnect_backoff).await;
     }
 }
+
+/// Let a `--wait-ack` sender know `msg` was applied here (best effort; see [`ack_wanted`]).
+async fn ack<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    ctx: &super::Ctx,
+    msg: &Message,
+    relay_hello: Option<&Hello>,
+) {
+    if !ack_wanted(relay_hello, msg) {
+        return;
+    }
+    if let Err(e) = send_ack(writer, &ctx.device_id, &ctx.device_name, msg).await {
+        log::warn!("wl-apply: ack failed: {e:#}");
+    }
+}
+
+/// Warn about formats a clipboard write didn't manage to offer (a missing marker means echoes).
+fn log_copy(res: anyhow::Result<CopyReport>) {
+    match res {
+        Ok(r) if r.missing.is_empty() => {}
+        Ok(r) => log::warn!(
+            "wl-apply: clipboard lacks {:?} after write (retried={})",
+            r.missing,
+            r.retried
+        ),
+        Err(e) => log::warn!("wl-apply: clipboard write failed: {e:#}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use node::client::{Client, Device};
+    use node::consts::TAR_MIME;
+    use node::paths::DataDirs;
+    use relay::{run_relay, RelayConfig};
+
+    /// Records every call; `receive --dir` must not make any.
+    #[derive(Default)]
+    struct FakeClipboard(Mutex<Vec<String>>);
+
+    impl FakeClipboard {
+        fn called(&self, what: &str) {
+            self.0.lock().unwrap().push(what.to_string());
+        }
+    }
+
+    impl ClipboardSource for FakeClipboard {
+        async fn list_types(&self) -> Option<Vec<String>> {
+            self.called("list_types");
+            None
+        }
+
+        async fn read(&self, mime: &str) -> anyhow::Result<Vec<u8>> {
+            self.called(&format!("read {mime}"));
+            anyhow::bail!("fake")
+        }
+    }
+
+    impl ClipboardSink for FakeClipboard {
+        async fn write(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+            self.called(&format!(
+                "write {:?}",
+                items.iter().map(|(m, _)| m).collect::<Vec<_>>()
+            ));
+            Ok(())
+        }
+
+        async fn write_primary(&self, _items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+            self.called("write_primary");
+            Ok(())
+        }
+    }
+
+    fn free_addr() -> String {
+        let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        l.local_addr().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn store_only_keeps_payloads_in_dir_and_never_touches_the_clipboard() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("inbox");
+        let ctx = crate::Ctx {
+            state_dir: tmp.path().join("state"),
+            data: DataDirs::under(&tmp.path().join("data")),
+            device_id: "dev-box".to_string(),
+            device_name: "box".to_string(),
+        };
+        let opts = ApplyOpts {
+            image_mode: ImageMode::ForcePng,
+            bundle_expose: BundleExpose::Auto,
+            max_listed_items: 8,
+            staleness: Staleness::default(),
+            store_only: Some(out.clone()),
+        };
+        let clip = FakeClipboard::default();
+
+        let addr = free_addr();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let config = RelayConfig {
+            bind: vec![addr.clone()],
+            ..RelayConfig::default()
+        };
+        let server = tokio::spawn(run_relay(config, async {
+            let _ = stopped.await;
+        }));
+
+        let sender = async {
+            let mut laptop = loop {
+                let device = Device::new("dev-laptop", "laptop");
+                match Client::connect_for_ack(&addr, "room", device).await {
+                    Ok(c) => break c.history_at(tmp.path().join("sent.jsonl")),
+                    Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+                }
+            };
+            // Resent until the receiver is in the room and acks it (kept on disk).
+            let mut joined = false;
+            for _ in 0..50 {
+                let sent = laptop
+                    .send_file("notes.md", "text/markdown", b"# hi".to_vec())
+                    .await
+                    .unwrap();
+                let ack = laptop.wait_for_ack(&sent.event_id, Duration::from_millis(200));
+                if ack.await.is_ok() {
+                    joined = true;
+                    break;
+                }
+            }
+            assert!(joined, "receive never acked");
+
+            let mut tar = tar::Builder::new(Vec::new());
+            let mut h = tar::Header::new_gnu();
+            h.set_size(3);
+            h.set_mode(0o644);
+            h.set_cksum();
+            tar.append_data(&mut h, "pics/a.txt", &b"abc"[..]).unwrap();
+            let bundle = tar.into_inner().unwrap();
+            let png = b"\x89PNG\r\n\x1a\nrest".to_vec();
+
+            laptop.send_text("hello").await.unwrap();
+            for sent in [
+                laptop
+                    .send_file("pics.tar", TAR_MIME, bundle)
+                    .await
+                    .unwrap(),
+                laptop.send_image("image/png", png).await.unwrap(),
+            ] {
+                let ack = laptop.wait_for_ack(&sent.event_id, Duration::from_secs(5));
+                ack.await.unwrap();
+            }
+        };
+        tokio::select! {
+            res = run_wl_apply(&ctx, &clip, "room", &addr, opts) => panic!("receive exited: {res:?}"),
+            () = sender => {}
+        }
+
+        assert_eq!(clip.0.lock().unwrap().as_slice(), &[] as &[String]);
+        let stored = walk(&out);
+        assert!(
+            stored.iter().any(|p| p.ends_with("/notes.md")),
+            "{stored:?}"
+        );
+        assert!(
+            stored.iter().any(|p| p.ends_with("/pics/a.txt")),
+            "{stored:?}"
+        );
+        assert!(
+            stored.iter().any(|p| p.ends_with("/image.png")),
+            "{stored:?}"
+        );
+        // The text is only recorded.
+        let history = std::fs::read_to_string(&ctx.data.history).unwrap();
+        assert!(history.contains(r#""kind":"text""#), "{history}");
+
+        let _ = stop.send(());
+        server.await.unwrap().unwrap();
+    }
+
+    /// Every file under `dir`, as paths relative to it.
+    fn walk(dir: &std::path::Path) -> Vec<String> {
+        let mut out = Vec::new();
+        for e in std::fs::read_dir(dir).unwrap().flatten() {
+            let p = e.path();
+            if p.is_dir() {
+                out.extend(
+                    walk(&p)
+                        .into_iter()
+                        .map(|s| format!("{}/{s}", e.file_name().to_string_lossy())),
+                );
+            } else {
+                out.push(e.file_name().to_string_lossy().into_owned());
+            }
+        }
+        out
+    }
+}
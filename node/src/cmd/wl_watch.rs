@@ -1,46 +1,53 @@
 use anyhow::Context;
-use std::path::PathBuf;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::process::Command;
 use tokio::sync::watch;
 
-use utils::{Kind, Message};
-
-use node::clipboard::wl_paste;
-use node::consts::{
-    APPLIED_MARKER_MIME, GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME,
+use node::channel::{send_channel, set_channels, subscribed, CHANNEL_ENV, SUBSCRIBE_ENV};
+use node::clipboard::{
+    clipboard_timeout_ms, default_watch_mimes, native_clipboard, probe_wl_paste_watch,
+    resolve_watch_mode, watch_allows, watch_mimes, watch_nothing, wl_list_types, wl_paste, WlPaste, CLIPBOARD_TIMEOUT_ENV,
+    NATIVE_CLIPBOARD_ENV,
 };
-use node::hash::sha256_hex;
-use node::history::record_send;
+use node::content_filter::content_filters;
+use node::consts::APPLIED_MARKER_MIME;
+use node::events::{json_output, OUTPUT_ENV};
+use node::extra_mime::{extra_mimes, set_extra_mimes, EXTRA_MIMES_ENV};
+use node::hash::{fingerprint, hash_algo, hash_algo_as_cli_arg, HASH_ALGO_ENV};
+use node::device::{resolve_device_name, DEVICE_NAME_ENV};
 use node::image_mode::{parse_image_mode, ImageMode};
-use node::net::{connect, send_frame, send_join};
-use node::paths::{first_8, received_dir};
-use node::suppress::{is_file_suppressed, is_suppressed, set_suppress};
-use node::transfer_file::{collect_clipboard_paths, send_paths_as_file};
-use node::transfer_image::{image_mimes, to_png};
-
-fn image_ext_from_mime(mime: &str) -> Option<&'static str> {
-    match mime {
-        "image/png" => Some("png"),
-        "image/jpeg" => Some("jpg"),
-        "image/jpg" => Some("jpg"),
-        "image/webp" => Some("webp"),
-        "image/gif" => Some("gif"),
-        _ => None,
-    }
-}
-
-async fn persist_image_best_effort(sha: &str, mime: &str, bytes: &[u8]) {
-    let sha8 = first_8(sha).to_string();
-    let dir = received_dir().join(&sha8);
-    tokio::fs::create_dir_all(&dir).await.ok();
-    let ext = image_ext_from_mime(mime).unwrap_or("bin");
-    let p = dir.join(format!("image.{ext}"));
-    let _ = tokio::fs::write(&p, bytes).await;
-}
+use node::instances::ensure_no_other_rooms;
+use node::net::{
+    compress_enabled, connect_timeout_ms, frame_crc_enabled, COMPRESS_ENV, CONNECT_TIMEOUT_ENV,
+    FRAME_CRC_ENV,
+};
+use node::paths::{received_dir, systemd_env_path, DataDirs, RECEIVED_DIR_ENV, STATE_DIR_ENV};
+use node::poll::{poll_loop, PollCtx, PollIntervals};
+use node::publish::{
+    candidate_cap, choose_publish_mime, dispatch, is_own_selection, is_text_mime,
+    publish_current, publish_files, publish_payload, read_candidate, record_candidate_too_large,
+    text_only, CandidateInput, Dispatch, PayloadOutcome, PublishCtx, PublishLimits, SendPlan,
+    DRY_RUN_ENV, TEXT_ONLY_ENV,
+};
+use node::reload::{reload_on_sighup, LiveConfig};
+use node::resend::{keep_text_enabled, KEEP_TEXT_ENV};
+use node::room::watch_room;
+use node::say;
+use node::suppress::is_paused;
+use node::throttle::{
+    max_events_per_sec, max_upload_kbps, MAX_EVENTS_PER_SEC_ENV, MAX_UPLOAD_KBPS_ENV,
+};
+use node::transfer_file::{
+    bundle_mtime, bundle_mtime_as_cli_arg, path_metadata, BUNDLE_MTIME_ENV, NO_PATH_METADATA_ENV,
+};
+use node::transfer_image::{
+    image_priority, preserve_animation, IMAGE_PRIORITY_ENV, PRESERVE_ANIMATION_ENV,
+};
 
 pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
+    // Runs before CLI parsing: `--extra-mime`, `--channel` and `--subscribe` arrive via env.
+    set_extra_mimes(Vec::new());
+    set_channels(None, Vec::new());
     let debug_path = std::env::var("MCR_HOOK_DEBUG_PATH").ok();
     let debug = |line: &str| {
         if let Some(p) = debug_path.as_deref() {
@@ -55,9 +62,8 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
         }
     };
 
-    let state_dir = std::env::var_os("MCR_STATE_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(super::default_state_dir);
+    // Honors MCR_STATE_DIR, which wl-watch sets for its hooks.
+    let state_dir = super::default_state_dir();
     tokio::fs::create_dir_all(&state_dir).await.ok();
 
     let device_id = std::env::var("MCR_DEVICE_ID").unwrap_or_else(|_| uuid::Uuid::new_v4().to_string());
@@ -67,7 +73,7 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
     let max_text_bytes = std::env::var("MCR_MAX_TEXT_BYTES")
         .ok()
         .and_then(|v| v.parse::<usize>().ok())
-        .unwrap_or(1 * 1024 * 1024);
+        .unwrap_or(1024 * 1024);
     let max_image_bytes = std::env::var("MCR_MAX_IMAGE_BYTES")
         .ok()
         .and_then(|v| v.parse::<usize>().ok())
@@ -79,10 +85,12 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
 
     let image_mode = std::env::var("MCR_IMAGE_MODE").unwrap_or_else(|_| "force-png".to_string());
     let im = parse_image_mode(&image_mode)?;
+    let dry_run = std::env::var(DRY_RUN_ENV).is_ok_and(|v| v == "1");
 
-    let device_name = super::default_device_name();
+    let device_name = resolve_device_name(None);
     let ctx = super::Ctx {
         state_dir,
+        data: DataDirs::resolve(),
         device_id,
         device_name,
     };
@@ -92,46 +100,32 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
     let candidate = std::env::var("MCR_WATCH_CANDIDATE_MIME").ok();
     if let Some(candidate) = candidate {
         debug(&format!("hook: candidate={}", candidate));
-        let cap = if candidate.starts_with("image/") {
-            max_image_bytes
-        } else {
-            // text + uri-list/gnome are tiny in practice, but keep a generous cap.
-            std::cmp::max(max_text_bytes, max_file_bytes)
+        let limits = PublishLimits {
+            max_text_bytes,
+            max_image_bytes,
+            max_file_bytes,
+            text_only: text_only(),
         };
-
-        let mut stdin = tokio::io::stdin();
-        let mut stored: Vec<u8> = Vec::new();
-        let mut buf = [0u8; 8192];
-        let mut too_big = false;
-        loop {
-            let n = match stdin.read(&mut buf).await {
-                Ok(0) => break,
-                Ok(n) => n,
-                Err(_) => break,
-            };
-            if !too_big {
-                if stored.len() + n > cap {
-                    too_big = true;
-                } else {
-                    stored.extend_from_slice(&buf[..n]);
-                }
+        let (_, (_, cap)) = candidate_cap(&candidate, &limits);
+        // Past the cap, keep draining without storing to avoid blocking wl-paste.
+        let input = read_candidate(&mut tokio::io::stdin(), cap).await;
+        match &input {
+            CandidateInput::Stored(b) => debug(&format!("hook: stdin_bytes={}", b.len())),
+            CandidateInput::TooBig { len, .. } => {
+                debug(&format!("hook: stdin too big (bytes={} cap={})", len, cap))
             }
-            // If too_big, continue draining without storing to avoid blocking wl-paste.
         }
-        if too_big {
-            debug(&format!("hook: stdin too big (cap={})", cap));
+
+        if is_paused(&ctx.state_dir, &room).await {
+            debug("hook: room paused; ignore");
             return Ok(());
         }
 
-        debug(&format!("hook: stdin_bytes={}", stored.len()));
-
         // Determine best MIME for this selection.
-        let out = match Command::new("wl-paste").arg("--list-types").output().await {
-            Ok(o) => o,
-            Err(_) => return Ok(()),
+        let Some(types) = wl_list_types().await else {
+            return Ok(());
         };
-        let types = String::from_utf8_lossy(&out.stdout);
-        let has = |m: &str| types.lines().any(|l| l.trim() == m);
+        let has = |m: &str| types.iter().any(|t| t == m);
 
         // If the clipboard contains our "applied" marker, it was written by wl-apply.
         // Ignore to prevent feedback loops (apply -> watch -> re-send).
@@ -140,42 +134,9 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
             return Ok(());
         }
 
-        let chosen = if has(URI_LIST_MIME) {
-            URI_LIST_MIME
-        } else if has(KDE_URI_LIST_MIME) {
-            KDE_URI_LIST_MIME
-        } else if has(GNOME_COPIED_FILES_MIME) {
-            GNOME_COPIED_FILES_MIME
-        } else {
-            let choose_image = || {
-                if im == ImageMode::MultiMime {
-                    for m in ["image/jpeg", "image/webp", "image/gif", "image/png"] {
-                        if has(m) {
-                            return Some(m);
-                        }
-                    }
-                } else {
-                    if has("image/png") {
-                        return Some("image/png");
-                    }
-                    for m in ["image/jpeg", "image/webp", "image/gif"] {
-                        if has(m) {
-                            return Some(m);
-                        }
-                    }
-                }
-                None
-            };
-
-            if let Some(m) = choose_image() {
-                m
-            } else if has("text/plain;charset=utf-8") {
-                "text/plain;charset=utf-8"
-            } else if has("text/plain") {
-                "text/plain"
-            } else {
-                return Ok(());
-            }
+        let publishable = |m: &str| has(m) && (!text_only() || is_text_mime(m));
+        let Some(chosen) = choose_publish_mime(publishable, im) else {
+            return Ok(());
         };
 
         if candidate != chosen {
@@ -185,186 +146,75 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
 
         debug(&format!("hook: chosen={}", chosen));
 
-        // Publish using the stdin bytes for the chosen type.
-        if chosen == URI_LIST_MIME || chosen == KDE_URI_LIST_MIME || chosen == GNOME_COPIED_FILES_MIME {
-            // Multiple supervised wl-paste watchers can trigger nearly at the same time.
-            // Use a short-lived non-blocking lock to ensure we only process one file event
-            // per clipboard change, preventing duplicate sends and feedback-loop amplification.
-            #[cfg(unix)]
-            let _hook_lock = match super::acquire_instance_lock(&ctx.state_dir, "wl-watch-hook-file", &room, &relay) {
-                Ok(f) => Some(f),
-                Err(e) => {
-                    debug(&format!("hook: file lock busy or error: {:#}", e));
-                    return Ok(());
-                }
-            };
+        let cx = PublishCtx {
+            state_dir: &ctx.state_dir,
+            data: &ctx.data,
+            device_id: &ctx.device_id,
+            device_name: &ctx.device_name,
+            room: &room,
+            relay: &relay,
+            dry_run,
+        };
 
-            let paths = collect_clipboard_paths(&stored);
-            let mut uniq: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
-            for p in paths {
-                uniq.insert(p);
+        let stored = match input {
+            CandidateInput::Stored(b) => b,
+            CandidateInput::TooBig { len, sha } => {
+                record_candidate_too_large(&cx, chosen, &limits, len, &sha).await;
+                return Ok(());
             }
-            let paths: Vec<PathBuf> = uniq.into_iter().collect();
-            if paths.is_empty() {
+        };
+
+        // Publish using the stdin bytes for the chosen type.
+        match dispatch(chosen, &stored) {
+            Dispatch::Skip => {
                 debug("hook: no paths in uri-list");
                 return Ok(());
             }
-            let _ = send_paths_as_file(
-                &ctx.state_dir,
-                &ctx.device_id,
-                &ctx.device_name,
-                &room,
-                &relay,
-                paths,
-                max_file_bytes,
-            )
-            .await?;
-
-            // File clipboards may also provide a text/plain `file:///...` representation.
-            // Suppress text sends briefly to avoid overriding receiver clipboard with host paths.
-            set_suppress(
-                &ctx.state_dir,
-                &room,
-                "text/plain;charset=utf-8",
-                "*",
-                Duration::from_millis(1500),
-            )
-            .await;
-            set_suppress(
-                &ctx.state_dir,
-                &room,
-                "text/plain",
-                "*",
-                Duration::from_millis(1500),
-            )
-            .await;
-            debug("hook: sent file bundle");
-            return Ok(());
-        }
-
-        // Some clipboard producers may expose file copies as plain text containing `file:///...`
-        // without offering `text/uri-list` consistently. Treat such payloads as file clipboard.
-        if chosen.starts_with("text/plain") {
-            let mut paths = collect_clipboard_paths(&stored);
-            if !paths.is_empty() {
-                let mut existing: Vec<PathBuf> = Vec::new();
-                for p in paths.drain(..) {
-                    if tokio::fs::metadata(&p).await.is_ok() {
-                        existing.push(p);
-                    }
-                }
-
-                let mut uniq: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
-                for p in existing {
-                    uniq.insert(p);
-                }
-                let existing: Vec<PathBuf> = uniq.into_iter().collect();
-
-                if !existing.is_empty() {
-                    let _ = send_paths_as_file(
-                        &ctx.state_dir,
-                        &ctx.device_id,
-                        &ctx.device_name,
-                        &room,
-                        &relay,
-                        existing,
-                        max_file_bytes,
-                    )
-                    .await?;
-
-                    // Suppress follow-up text/plain `file:///...` updates.
-                    set_suppress(
-                        &ctx.state_dir,
-                        &room,
-                        "text/plain;charset=utf-8",
-                        "*",
-                        Duration::from_millis(1500),
-                    )
-                    .await;
-                    set_suppress(
-                        &ctx.state_dir,
-                        &room,
-                        "text/plain",
-                        "*",
-                        Duration::from_millis(1500),
-                    )
-                    .await;
-                    debug("hook: text looked like file URIs; sent as file bundle");
-                    return Ok(());
-                }
+            // Text-only: `file:///...` text goes out as the text it is.
+            Dispatch::Files(_) if text_only() => {}
+            Dispatch::Files(_) if is_own_selection(&ctx.state_dir, &room, chosen, &stored).await => {
+                debug("hook: file selection written by wl-apply; ignore");
+                return Ok(());
             }
-        }
-
-        let (send_mime, send_bytes) = if chosen.starts_with("image/") {
-            match im {
-                ImageMode::ForcePng => match to_png(&stored) {
-                    Ok(png) => ("image/png", png),
+            Dispatch::Files(paths) => {
+                // Multiple supervised wl-paste watchers can trigger nearly at the same time.
+                // Use a short-lived non-blocking lock to ensure we only process one file event
+                // per clipboard change, preventing duplicate sends and feedback-loop amplification.
+                #[cfg(unix)]
+                let _hook_lock = match super::acquire_instance_lock(&ctx.state_dir, hook_file_lock_name(dry_run), &room, &relay) {
+                    Ok(f) => Some(f),
                     Err(e) => {
-                        debug(&format!("hook: to_png failed: {:#}", e));
+                        debug(&format!("hook: file lock busy or error: {:#}", e));
                         return Ok(());
                     }
-                },
-                ImageMode::Passthrough | ImageMode::MultiMime | ImageMode::SpoofPng => (chosen, stored),
+                };
+                let plan = publish_files(&cx, &WlPaste, paths, max_file_bytes).await?;
+                match plan {
+                    Some(plan) if dry_run => report_plan(&plan),
+                    Some(_) => debug("hook: sent file bundle"),
+                    None => debug("hook: nothing to send in file selection"),
+                }
+                return Ok(());
             }
-        } else {
-            (chosen, stored)
-        };
-
-        let sha = sha256_hex(&send_bytes);
-        if is_suppressed(&ctx.state_dir, &room, send_mime, &sha).await {
-            debug(&format!("hook: suppressed mime={} sha={}", send_mime, sha));
-            return Ok(());
-        }
-
-        if send_mime.starts_with("image/") {
-            persist_image_best_effort(&sha, send_mime, &send_bytes).await;
+            Dispatch::Payload => {}
         }
 
-        debug(&format!("hook: sending mime={} bytes={}", send_mime, send_bytes.len()));
-
-        let stream = match connect(&relay).await {
-            Ok(s) => s,
-            Err(e) => {
-                debug(&format!("hook: connect failed: {:#}", e));
-                return Ok(());
+        debug(&format!("hook: sending mime={} bytes={}", chosen, stored.len()));
+        match publish_payload(&cx, chosen, stored, im).await {
+            Ok(outcome @ PayloadOutcome::Sent { .. }) if dry_run => {
+                if let Some(plan) = outcome.into_plan() {
+                    report_plan(&plan);
+                }
             }
-        };
-        let mut msg = if send_mime.starts_with("text/") {
-            let mut m = Message::new_text(&ctx.device_id, &room, "");
-            m.payload = Some(send_bytes);
-            m.size = m.payload.as_ref().map(|p| p.len()).unwrap_or(0);
-            m
-        } else {
-            Message::new_image(&ctx.device_id, &room, send_mime, send_bytes)
-        };
-        if !ctx.device_name.trim().is_empty() {
-            msg.sender_name = Some(ctx.device_name.clone());
-        }
-        msg.sha256 = Some(sha);
-        if let Err(e) = send_frame(stream, msg.to_bytes()).await {
-            debug(&format!("hook: send_frame failed: {:#}", e));
-            return Ok(());
+            Ok(PayloadOutcome::Sent { kind, mime, size, .. }) => {
+                debug(&format!("hook: send done kind={:?} mime={} bytes={}", kind, mime, size))
+            }
+            Ok(PayloadOutcome::Suppressed { mime, sha }) => {
+                debug(&format!("hook: suppressed mime={} sha={}", mime, sha))
+            }
+            Ok(PayloadOutcome::Dropped) => debug("hook: dropped (conversion failed, --send-filter or superseded)"),
+            Err(e) => debug(&format!("hook: send failed: {:#}", e)),
         }
-        log::debug!(
-            "wl-watch: sent kind={:?} mime={} bytes={} sha={:?}",
-            msg.kind,
-            send_mime,
-            msg.size,
-            msg.sha256
-        );
-        record_send(
-            &ctx.device_id,
-            Some(ctx.device_name.clone()),
-            &room,
-            &relay,
-            msg.kind,
-            Some(send_mime.to_string()),
-            msg.name.clone(),
-            msg.size,
-            msg.sha256.clone(),
-        )
-        .await;
-        debug("hook: send done");
         return Ok(());
     }
 
@@ -378,333 +228,159 @@ pub(super) async fn wl_watch_hook() -> anyhow::Result<()> {
         max_image_bytes,
         max_file_bytes,
         im,
+        dry_run,
     )
     .await
 }
 
+/// `wl-watch --dry-run` runs beside a real watcher, so it takes its own locks.
+fn hook_file_lock_name(dry_run: bool) -> &'static str {
+    if dry_run {
+        "wl-watch-hook-file-dry-run"
+    } else {
+        "wl-watch-hook-file"
+    }
+}
+
+/// Print what `--dry-run` would have sent (a JSON line under `--output json`).
+fn report_plan(plan: &SendPlan) {
+    if json_output() {
+        let mut v = serde_json::to_value(plan).unwrap_or_default();
+        v["event"] = "dry-run".into();
+        println!("{}", v);
+    } else {
+        println!("wl-watch(dry-run): would send {}", plan);
+    }
+}
+
+/// Restart delay for a `wl-paste --watch` child that exited (type not offered).
+const WATCHER_BACKOFF_MIN: Duration = Duration::from_millis(300);
+const WATCHER_BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+/// How long `--mode auto` waits for a `wl-paste --watch` event (per probe step).
+const WATCH_PROBE_WINDOW_MS: u64 = 1500;
+
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn run_wl_watch(
     ctx: &super::Ctx,
     room: &str,
     relay: &str,
     mode: &str,
-    interval_ms: u64,
+    intervals: PollIntervals,
+    file_resend_cooldown: Duration,
     max_text_bytes: usize,
     max_image_bytes: usize,
     max_file_bytes: usize,
     image_mode: ImageMode,
+    watch_mime_allow: &[String],
+    dry_run: bool,
 ) -> anyhow::Result<()> {
     // Guard against accidentally starting multiple watchers (which spawns multiple `wl-paste --watch` processes).
     // Holding this lock for the duration of the command keeps the system tidy.
-    let _lock = super::acquire_instance_lock(&ctx.state_dir, "wl-watch", room, relay)?;
+    let lock_name = if dry_run { "wl-watch-dry-run" } else { "wl-watch" };
+    if !dry_run {
+        // Two watchers in different rooms would publish every copy twice.
+        ensure_no_other_rooms(&ctx.state_dir, lock_name, room, relay)?;
+    }
+    let _lock = super::acquire_instance_lock(&ctx.state_dir, lock_name, room, relay)?;
+    if watch_nothing(watch_mime_allow) {
+        // The UIs' way of saying every kind is off; an empty list would mean every type.
+        say!("wl-watch: --watch-mimes none: every kind is off, nothing to publish");
+        return Ok(());
+    }
 
-    match mode {
-        "watch" => {
-            wl_watch_evented(
-                ctx,
-                room,
-                relay,
+    // SIGHUP re-reads the env file; size caps and image mode apply live, a new relay reconnects.
+    let live = LiveConfig {
+        room: room.to_string(),
+        relay: relay.to_string(),
+        max_text_bytes: Some(max_text_bytes),
+        max_image_bytes: Some(max_image_bytes),
+        max_file_bytes: Some(max_file_bytes),
+        image_mode,
+    };
+    let live = reload_on_sighup(live, systemd_env_path(), ctx.state_dir.clone())
+        .context("install SIGHUP handler")?;
+
+    let probe = probe_wl_paste_watch(Duration::from_millis(WATCH_PROBE_WINDOW_MS));
+    match resolve_watch_mode(mode, probe).await? {
+        "watch" => wl_watch_evented(ctx, room, live, watch_mime_allow, dry_run).await,
+        "poll" if dry_run => {
+            let limits = PublishLimits {
                 max_text_bytes,
                 max_image_bytes,
                 max_file_bytes,
-                image_mode,
-            )
-            .await
+                text_only: text_only(),
+            };
+            let (interval, allow) = (intervals.text, watch_mime_allow);
+            wl_watch_poll_dry_run(ctx, room, relay, interval, limits, image_mode, allow).await
         }
         "poll" => {
-            wl_watch_poll(
-                ctx,
-                room,
-                relay,
-                interval_ms,
-                max_text_bytes,
-                max_image_bytes,
-                max_file_bytes,
-                image_mode,
-            )
-            .await
+            let pctx = PollCtx {
+                state_dir: ctx.state_dir.clone(),
+                data: ctx.data.clone(),
+                device_id: ctx.device_id.clone(),
+                device_name: ctx.device_name.clone(),
+            };
+            let (cooldown, allow) = (file_resend_cooldown, watch_mime_allow);
+            poll_loop(&WlPaste, &pctx, room, intervals, cooldown, live, allow).await
         }
-        other => anyhow::bail!("invalid --mode {}, expected watch|poll", other),
+        _ => unreachable!("resolve_watch_mode returns watch|poll"),
     }
 }
 
-async fn wl_watch_poll(
+/// `--mode poll --dry-run`: report what each clipboard change would publish, without connecting.
+async fn wl_watch_poll_dry_run(
     ctx: &super::Ctx,
     room: &str,
     relay: &str,
-    interval_ms: u64,
-    max_text_bytes: usize,
-    max_image_bytes: usize,
-    max_file_bytes: usize,
+    interval: Duration,
+    limits: PublishLimits,
     image_mode: ImageMode,
+    allow: &[String],
 ) -> anyhow::Result<()> {
-    let stream = connect(relay).await?;
-    let (_reader, mut writer) = stream.into_split();
-    send_join(&mut writer, &ctx.device_id, &ctx.device_name, room).await?;
-    log::info!("wl-watch(poll): connected room='{}' relay='{}'", room, relay);
-    println!("wl-watch(poll): room='{}' relay='{}'", room, relay);
-
-    let mut last_text_hash: Option<String> = None;
-    let mut last_img_hash: std::collections::HashMap<String, String> =
-        std::collections::HashMap::new();
-    let mut last_file_hash: Option<String> = None;
+    say!("wl-watch(poll): room='{}' relay='{}' (dry run: nothing is sent)", room, relay);
+    let cx = PublishCtx {
+        state_dir: &ctx.state_dir,
+        data: &ctx.data,
+        device_id: &ctx.device_id,
+        device_name: &ctx.device_name,
+        room,
+        relay,
+        dry_run: true,
+    };
 
+    // Re-plan only when the chosen selection changes (bundling files every tick is expensive).
+    let mut last: Option<(String, String)> = None;
     loop {
-        // If wl-apply recently wrote the clipboard, it will include our marker MIME.
-        // Avoid polling and re-sending during that window.
-        if let Ok(out) = Command::new("wl-paste").arg("--list-types").output().await {
-            let types = String::from_utf8_lossy(&out.stdout);
-            if types.lines().any(|l| l.trim() == APPLIED_MARKER_MIME) {
-                tokio::time::sleep(std::time::Duration::from_millis(interval_ms)).await;
-                continue;
-            }
-        }
-
-        // files (uri-list / KDE / gnome)
-        let mut list_bytes: Option<Vec<u8>> = None;
-        if let Ok(b) = wl_paste(URI_LIST_MIME).await {
-            if !b.is_empty() {
-                list_bytes = Some(b);
-            }
-        }
-        if list_bytes.is_none() {
-            if let Ok(b) = wl_paste(KDE_URI_LIST_MIME).await {
-                if !b.is_empty() {
-                    list_bytes = Some(b);
-                }
-            }
-        }
-        if list_bytes.is_none() {
-            if let Ok(b) = wl_paste(GNOME_COPIED_FILES_MIME).await {
-                if !b.is_empty() {
-                    list_bytes = Some(b);
-                }
-            }
+        tokio::time::sleep(interval).await;
+        let Some(types) = wl_list_types().await else {
+            continue;
+        };
+        let has = |m: &str| types.iter().any(|t| t == m);
+        if has(APPLIED_MARKER_MIME) {
+            continue;
         }
-        if let Some(list_bytes) = list_bytes {
-            let paths = collect_clipboard_paths(&list_bytes);
-            // quick de-dupe
-            let mut uniq: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
-            for p in paths {
-                uniq.insert(p);
-            }
-            let paths: Vec<PathBuf> = uniq.into_iter().collect();
-
-            let maybe_sha = if paths.is_empty() {
-                None
-            } else {
-                // Cheap pre-check: for single file we can compute sha after reading; for bundles, sha is from tar.
-                // Loop prevention uses the sha of the transmitted payload.
-                if last_file_hash.as_deref().is_some() {
-                    // keep as-is
-                }
-                if let Some(sha) = send_paths_as_file(
-                    &ctx.state_dir,
-                    &ctx.device_id,
-                    &ctx.device_name,
-                    room,
-                    relay,
-                    paths,
-                    max_file_bytes,
-                )
-                .await?
-                {
-                    Some(sha)
-                } else {
-                    None
-                }
-            };
-
-            if let Some(sha) = maybe_sha {
-                if last_file_hash.as_deref() != Some(&sha)
-                    && !is_file_suppressed(&ctx.state_dir, room, &sha).await
-                {
-                    last_file_hash = Some(sha);
-                }
-            }
-
-            // File clipboards may also (briefly) provide text/plain with `file:///...`.
-            // Suppress text sends briefly to avoid overriding receiver clipboard with host paths.
-            set_suppress(
-                &ctx.state_dir,
-                room,
-                "text/plain;charset=utf-8",
-                "*",
-                Duration::from_millis(1500),
-            )
-            .await;
-            set_suppress(
-                &ctx.state_dir,
-                room,
-                "text/plain",
-                "*",
-                Duration::from_millis(1500),
-            )
-            .await;
-
-            // Treat file clipboard as dominant for this tick.
-            tokio::time::sleep(std::time::Duration::from_millis(interval_ms)).await;
+        let publishable = |m: &str| has(m) && (!limits.text_only || is_text_mime(m));
+        let Some(chosen) = choose_publish_mime(publishable, image_mode) else {
+            continue;
+        };
+        // A copy whose best type is switched off is skipped as a whole.
+        if !watch_allows(allow, chosen) {
             continue;
         }
-
-        // text/plain
-        if let Ok(text_bytes) = wl_paste("text/plain;charset=utf-8").await {
-            if !text_bytes.is_empty() && text_bytes.len() <= max_text_bytes {
-                let mut paths = collect_clipboard_paths(&text_bytes);
-                if !paths.is_empty() {
-                    let mut existing: Vec<PathBuf> = Vec::new();
-                    for p in paths.drain(..) {
-                        if tokio::fs::metadata(&p).await.is_ok() {
-                            existing.push(p);
-                        }
-                    }
-                    let mut uniq: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
-                    for p in existing {
-                        uniq.insert(p);
-                    }
-                    let existing: Vec<PathBuf> = uniq.into_iter().collect();
-
-                    if !existing.is_empty() {
-                        if let Some(sha) = send_paths_as_file(
-                            &ctx.state_dir,
-                            &ctx.device_id,
-                            &ctx.device_name,
-                            room,
-                            relay,
-                            existing,
-                            max_file_bytes,
-                        )
-                        .await?
-                        {
-                            last_file_hash = Some(sha);
-                        }
-
-                        set_suppress(
-                            &ctx.state_dir,
-                            room,
-                            "text/plain;charset=utf-8",
-                            "*",
-                            Duration::from_millis(1500),
-                        )
-                        .await;
-                        set_suppress(
-                            &ctx.state_dir,
-                            room,
-                            "text/plain",
-                            "*",
-                            Duration::from_millis(1500),
-                        )
-                        .await;
-
-                        tokio::time::sleep(std::time::Duration::from_millis(interval_ms)).await;
-                        continue;
-                    }
-                }
-
-                let h = sha256_hex(&text_bytes);
-                if last_text_hash.as_deref() != Some(&h)
-                    && !is_suppressed(&ctx.state_dir, room, "text/plain;charset=utf-8", &h).await
-                {
-                    let mut msg = Message::new_text(&ctx.device_id, room, "");
-                    msg.payload = Some(text_bytes);
-                    msg.size = msg.payload.as_ref().map(|p| p.len()).unwrap_or(0);
-                    let preview = msg
-                        .payload
-                        .as_ref()
-                        .map(|p| String::from_utf8_lossy(p).chars().take(120).collect::<String>())
-                        .unwrap_or_default();
-                    log::debug!("wl-watch: text preview={}", preview);
-                    if !ctx.device_name.trim().is_empty() {
-                        msg.sender_name = Some(ctx.device_name.clone());
-                    }
-                    msg.sha256 = Some(h.clone());
-                    let buf = msg.to_bytes();
-                    writer.write_u32(buf.len() as u32).await?;
-                    writer.write_all(&buf).await?;
-                    record_send(
-                        &ctx.device_id,
-                        Some(ctx.device_name.clone()),
-                        room,
-                        relay,
-                        Kind::Text,
-                        Some("text/plain;charset=utf-8".to_string()),
-                        None,
-                        msg.size,
-                        Some(h.clone()),
-                    )
-                    .await;
-                    log::debug!(
-                        "wl-watch: sent kind=text mime=text/plain;charset=utf-8 bytes={} sha={}",
-                        msg.size,
-                        h
-                    );
-                    last_text_hash = Some(h);
-                }
-            }
+        let Ok(bytes) = wl_paste(chosen).await else {
+            continue;
+        };
+        let seen = Some((chosen.to_string(), fingerprint(&bytes)));
+        if seen == last {
+            continue;
         }
-
-        // images
-        let mut sent_non_png = false;
-        for &mime in image_mimes().iter() {
-            if let Ok(img_bytes) = wl_paste(mime).await {
-                if img_bytes.is_empty() || img_bytes.len() > max_image_bytes {
-                    continue;
-                }
-                if image_mode == ImageMode::MultiMime && mime == "image/png" && sent_non_png {
-                    // In multi-mime mode, prefer publishing the non-png representation to the relay
-                    // to preserve the original format across devices.
-                    continue;
-                }
-
-                let mut send_mime = mime;
-                let mut send_bytes: Vec<u8> = img_bytes;
-                if image_mode == ImageMode::ForcePng {
-                    if let Ok(png) = to_png(&send_bytes) {
-                        send_mime = "image/png";
-                        send_bytes = png;
-                    } else {
-                        continue;
-                    }
-                }
-                let h = sha256_hex(&send_bytes);
-                if last_img_hash.get(send_mime).map(|s| s.as_str()) != Some(&h)
-                    && !is_suppressed(&ctx.state_dir, room, send_mime, &h).await
-                {
-                    persist_image_best_effort(&h, send_mime, &send_bytes).await;
-                    let mut msg = Message::new_image(&ctx.device_id, room, send_mime, send_bytes);
-                    if !ctx.device_name.trim().is_empty() {
-                        msg.sender_name = Some(ctx.device_name.clone());
-                    }
-                    msg.sha256 = Some(h.clone());
-                    let buf = msg.to_bytes();
-                    writer.write_u32(buf.len() as u32).await?;
-                    writer.write_all(&buf).await?;
-                    record_send(
-                        &ctx.device_id,
-                        Some(ctx.device_name.clone()),
-                        room,
-                        relay,
-                        Kind::Image,
-                        Some(send_mime.to_string()),
-                        None,
-                        msg.size,
-                        Some(h.clone()),
-                    )
-                    .await;
-                    log::debug!(
-                        "wl-watch: sent kind=image mime={} bytes={} sha={}",
-                        send_mime,
-                        msg.size,
-                        h
-                    );
-                    last_img_hash.insert(send_mime.to_string(), h);
-                    if send_mime != "image/png" {
-                        sent_non_png = true;
-                    }
-                }
-            }
+        last = seen;
+        match publish_current(&cx, &WlPaste, chosen, None, limits, image_mode).await {
+            Ok(Some(plan)) => report_plan(&plan),
+            Ok(None) => log::debug!("wl-watch(dry-run): nothing to send for mime={}", chosen),
+            Err(e) => log::warn!("wl-watch(dry-run): {:#}", e),
         }
-
-        tokio::time::sleep(std::time::Duration::from_millis(interval_ms)).await;
     }
 }
 
@@ -712,15 +388,19 @@ async fn wl_watch_poll(
 async fn wl_watch_evented(
     ctx: &super::Ctx,
     room: &str,
-    relay: &str,
-    max_text_bytes: usize,
-    max_image_bytes: usize,
-    max_file_bytes: usize,
-    image_mode: ImageMode,
+    live: watch::Receiver<LiveConfig>,
+    watch_mime_allow: &[String],
+    dry_run: bool,
 ) -> anyhow::Result<()> {
     let exe = std::env::current_exe().context("current_exe")?;
+    let relay = live.borrow().relay.clone();
     log::info!("wl-watch(watch): room='{}' relay='{}'", room, relay);
-    println!("wl-watch(watch): room='{}' relay='{}'", room, relay);
+    say!(
+        "wl-watch(watch): room='{}' relay='{}'{}",
+        room,
+        relay,
+        if dry_run { " (dry run: nothing is sent)" } else { "" }
+    );
 
     let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
         .context("install SIGTERM handler")?;
@@ -742,33 +422,44 @@ async fn wl_watch_evented(
         None
     };
 
-    let mut watch_mimes: Vec<String> = Vec::new();
-    watch_mimes.push(URI_LIST_MIME.to_string());
-    watch_mimes.push(GNOME_COPIED_FILES_MIME.to_string());
-    watch_mimes.push(KDE_URI_LIST_MIME.to_string());
-    watch_mimes.push("text/plain;charset=utf-8".to_string());
-    watch_mimes.push("text/plain".to_string());
-    for &m in image_mimes().iter() {
-        watch_mimes.push(m.to_string());
+    let watch_mimes: Vec<String> = watch_mimes(watch_mime_allow)
+        .into_iter()
+        .filter(|m| !text_only() || is_text_mime(m))
+        .collect();
+    if watch_mimes.is_empty() {
+        anyhow::bail!(
+            "--watch-mimes{} matches none of: {}",
+            if text_only() { " (with --text-only)" } else { "" },
+            default_watch_mimes().join(", ")
+        );
     }
+    log::info!("wl-watch(watch): supervising {} watchers: {:?}", watch_mimes.len(), watch_mimes);
+
+    // Hooks get the room via env, so a room switch just restarts the wl-paste watchers.
+    let room_rx = watch_room(&ctx.state_dir, room);
 
     for mime in watch_mimes {
         let mut stop_rx = stop_rx.clone();
+        let mut room_rx = room_rx.clone();
+        let mut live = live.clone();
         let exe = exe.clone();
         let state_dir = ctx.state_dir.clone();
         let device_id = ctx.device_id.clone();
-        let room = room.to_string();
-        let relay = relay.to_string();
-        let im = image_mode;
+        let device_name = ctx.device_name.clone();
         let debug_hook_path = debug_hook_path.clone();
 
         let handle = tokio::spawn(async move {
-            // Backoff to avoid hot loops when the mime isn't currently offered.
-            let backoff = Duration::from_millis(300);
+            // Backoff to avoid hot loops when the mime isn't currently offered; it grows while
+            // wl-paste keeps exiting right away, so idle types don't respawn every 300ms.
+            let mut backoff = WATCHER_BACKOFF_MIN;
             loop {
                 if *stop_rx.borrow() {
                     break;
                 }
+                let room = room_rx.borrow_and_update().clone();
+                // Hooks get their settings via env too: a SIGHUP reload restarts the watchers.
+                let cfg = live.borrow_and_update().clone();
+                let (max_text, max_image, max_file) = cfg.caps();
 
                 let mut cmd = Command::new("wl-paste");
                 cmd.arg("--no-newline")
@@ -790,13 +481,35 @@ async fn wl_watch_evented(
 
                 cmd.env("MCR_WL_WATCH_HOOK", "1")
                     .env("MCR_WATCH_CANDIDATE_MIME", &mime)
-                    .env("MCR_STATE_DIR", state_dir.to_string_lossy().to_string())
+                    .env(STATE_DIR_ENV, state_dir.to_string_lossy().to_string())
                     .env("MCR_DEVICE_ID", device_id.clone())
-                    .env("MCR_ROOM", room.clone())
-                    .env("MCR_RELAY", relay.clone())
-                    .env("MCR_MAX_TEXT_BYTES", max_text_bytes.to_string())
-                    .env("MCR_MAX_IMAGE_BYTES", max_image_bytes.to_string())
-                    .env("MCR_MAX_FILE_BYTES", max_file_bytes.to_string())
+                    .env(DEVICE_NAME_ENV, &device_name)
+                    .env("MCR_ROOM", &room)
+                    .env("MCR_RELAY", &cfg.relay)
+                    .env("MCR_MAX_TEXT_BYTES", max_text.to_string())
+                    .env("MCR_MAX_IMAGE_BYTES", max_image.to_string())
+                    .env("MCR_MAX_FILE_BYTES", max_file.to_string())
+                    .env(BUNDLE_MTIME_ENV, bundle_mtime_as_cli_arg(bundle_mtime()))
+                    .env(HASH_ALGO_ENV, hash_algo_as_cli_arg(hash_algo()))
+                    .env(NO_PATH_METADATA_ENV, if path_metadata() { "0" } else { "1" })
+                    .env(PRESERVE_ANIMATION_ENV, if preserve_animation() { "1" } else { "0" })
+                    .env(NATIVE_CLIPBOARD_ENV, if native_clipboard() { "1" } else { "0" })
+                    .env(CLIPBOARD_TIMEOUT_ENV, clipboard_timeout_ms().to_string())
+                    .env(IMAGE_PRIORITY_ENV, image_priority().join(","))
+                    .env(RECEIVED_DIR_ENV, received_dir())
+                    .envs(max_upload_kbps().map(|k| (MAX_UPLOAD_KBPS_ENV, k.to_string())))
+                    .envs(max_events_per_sec().map(|n| (MAX_EVENTS_PER_SEC_ENV, n.to_string())))
+                    .envs(content_filters().env_pairs())
+                    .env(TEXT_ONLY_ENV, if text_only() { "1" } else { "0" })
+                    .env(OUTPUT_ENV, if json_output() { "json" } else { "text" })
+                    .env(DRY_RUN_ENV, if dry_run { "1" } else { "0" })
+                    .env(COMPRESS_ENV, if compress_enabled() { "1" } else { "0" })
+                    .env(FRAME_CRC_ENV, if frame_crc_enabled() { "1" } else { "0" })
+                    .env(KEEP_TEXT_ENV, if keep_text_enabled() { "1" } else { "0" })
+                    .env(CONNECT_TIMEOUT_ENV, connect_timeout_ms().to_string())
+                    .env(EXTRA_MIMES_ENV, extra_mimes().join(","))
+                    .env(CHANNEL_ENV, send_channel().unwrap_or_default())
+                    .env(SUBSCRIBE_ENV, subscribed().join(","))
                     .envs(
                         debug_hook_path
                             .as_ref()
@@ -806,11 +519,12 @@ async fn wl_watch_evented(
                     )
                     .env(
                         "MCR_IMAGE_MODE",
-                        match im {
+                        match cfg.image_mode {
                             ImageMode::Passthrough => "passthrough",
                             ImageMode::ForcePng => "force-png",
                             ImageMode::MultiMime => "multi",
                             ImageMode::SpoofPng => "spoof-png",
+                            ImageMode::ForceWebp => "force-webp",
                         },
                     )
                     .kill_on_drop(true);
@@ -824,16 +538,35 @@ async fn wl_watch_evented(
                         continue;
                     }
                 };
+                let started = std::time::Instant::now();
 
                 tokio::select! {
                     _ = stop_rx.changed() => {
                         let _ = child.kill().await;
                         break;
                     }
+                    Ok(()) = room_rx.changed() => {
+                        let _ = child.kill().await;
+                        continue;
+                    }
+                    Ok(()) = live.changed() => {
+                        let _ = child.kill().await;
+                        continue;
+                    }
                     _ = child.wait() => {
                         // wl-paste exits if the requested type is not currently offered.
                         // We'll restart after a short backoff.
-                        tokio::time::sleep(backoff).await;
+                        backoff = if started.elapsed() < WATCHER_BACKOFF_MAX {
+                            (backoff * 2).min(WATCHER_BACKOFF_MAX)
+                        } else {
+                            WATCHER_BACKOFF_MIN
+                        };
+                        tokio::select! {
+                            _ = stop_rx.changed() => break,
+                            Ok(()) = room_rx.changed() => {}
+                            Ok(()) = live.changed() => {}
+                            _ = tokio::time::sleep(backoff) => {}
+                        }
                         continue;
                     }
                 }
@@ -865,12 +598,15 @@ async fn wl_watch_evented(
 async fn wl_watch_evented(
     ctx: &super::Ctx,
     room: &str,
-    relay: &str,
-    max_text_bytes: usize,
-    max_image_bytes: usize,
+    live: watch::Receiver<LiveConfig>,
+    _watch_mime_allow: &[String],
+    _dry_run: bool,
 ) -> anyhow::Result<()> {
     let exe = std::env::current_exe().context("current_exe")?;
-    println!("wl-watch(watch): room='{}' relay='{}'", room, relay);
+    let cfg = live.borrow().clone();
+    let (max_text_bytes, max_image_bytes, _) = cfg.caps();
+    let relay = cfg.relay.as_str();
+    say!("wl-watch(watch): room='{}' relay='{}'", room, relay);
 
     let mut text_child = Command::new("wl-paste")
         .arg("--type")
@@ -937,6 +673,7 @@ async fn wl_watch_evented(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn wl_publish_current(
     ctx: &super::Ctx,
     room: &str,
@@ -946,248 +683,28 @@ pub(super) async fn wl_publish_current(
     max_image_bytes: usize,
     max_file_bytes: usize,
     image_mode: ImageMode,
+    dry_run: bool,
 ) -> anyhow::Result<()> {
-    // Auto mode: determine the best MIME to publish based on current offers.
-    // This is used by wl-watch(watch) to stay robust even when the clipboard
-    // does not currently offer a particular MIME type at startup.
-    let mime = if mime == "auto" {
-        let out = match Command::new("wl-paste").arg("--list-types").output().await {
-            Ok(o) => o,
-            Err(_) => return Ok(()),
-        };
-        let types = String::from_utf8_lossy(&out.stdout);
-        let has = |m: &str| types.lines().any(|l| l.trim() == m);
-
-        let chosen = if has(URI_LIST_MIME) {
-            URI_LIST_MIME
-        } else if has(KDE_URI_LIST_MIME) {
-            KDE_URI_LIST_MIME
-        } else if has(GNOME_COPIED_FILES_MIME) {
-            GNOME_COPIED_FILES_MIME
-        } else {
-            // Prefer images when available.
-            let choose_image = || {
-                if image_mode == ImageMode::MultiMime {
-                    // Prefer original formats over PNG when we can offer a PNG fallback.
-                    for m in ["image/jpeg", "image/webp", "image/gif", "image/png"] {
-                        if has(m) {
-                            return Some(m);
-                        }
-                    }
-                } else {
-                    // Prefer PNG if present.
-                    if has("image/png") {
-                        return Some("image/png");
-                    }
-                    for m in ["image/jpeg", "image/webp", "image/gif"] {
-                        if has(m) {
-                            return Some(m);
-                        }
-                    }
-                }
-                None
-            };
-
-            if let Some(m) = choose_image() {
-                m
-            } else if has("text/plain;charset=utf-8") {
-                "text/plain;charset=utf-8"
-            } else if has("text/plain") {
-                "text/plain"
-            } else {
-                return Ok(());
-            }
-        };
-
-        // When invoked by wl-watch(watch), multiple MIME-specific watchers may fire.
-        // Only allow the watcher whose candidate MIME matches our chosen best MIME.
-        if let Ok(candidate) = std::env::var("MCR_WATCH_CANDIDATE_MIME") {
-            if candidate != chosen {
-                return Ok(());
-            }
-        }
-
-        chosen
-    } else {
-        mime
-    };
-
-    // File selection: read uri-list and send file bytes.
-    if mime == URI_LIST_MIME || mime == KDE_URI_LIST_MIME || mime == GNOME_COPIED_FILES_MIME {
-        let list_bytes = match wl_paste(mime).await {
-            Ok(b) => b,
-            Err(_) => return Ok(()),
-        };
-        if list_bytes.is_empty() {
-            return Ok(());
-        }
-        let paths = collect_clipboard_paths(&list_bytes);
-        let mut uniq: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
-        for p in paths {
-            uniq.insert(p);
-        }
-        let paths: Vec<PathBuf> = uniq.into_iter().collect();
-        if paths.is_empty() {
-            return Ok(());
-        }
-
-        // send (single file raw / multi or dir tar)
-        let _ = send_paths_as_file(
-            &ctx.state_dir,
-            &ctx.device_id,
-            &ctx.device_name,
-            room,
-            relay,
-            paths,
-            max_file_bytes,
-        )
-        .await?;
-
-        // Same as hook/poll: avoid a follow-up text/plain `file:///...` overriding the receiver.
-        set_suppress(
-            &ctx.state_dir,
-            room,
-            "text/plain;charset=utf-8",
-            "*",
-            Duration::from_millis(1500),
-        )
-        .await;
-        set_suppress(
-            &ctx.state_dir,
-            room,
-            "text/plain",
-            "*",
-            Duration::from_millis(1500),
-        )
-        .await;
-        return Ok(());
-    }
-
-    if mime == "image/png" && image_mode == ImageMode::MultiMime {
-        // If the clipboard currently offers a non-png image type too, prefer publishing that one.
-        // This avoids publishing both png and the original format when we locally set multi-mime.
-        if let Ok(out) = Command::new("wl-paste").arg("--list-types").output().await {
-            let types = String::from_utf8_lossy(&out.stdout);
-            let has_other = image_mimes()
-                .iter()
-                .any(|m| *m != "image/png" && types.lines().any(|l| l.trim() == *m));
-            if has_other {
-                return Ok(());
-            }
-        }
-    }
-
-    let bytes = wl_paste(mime).await?;
-    if bytes.is_empty() {
-        return Ok(());
-    }
-    if mime.starts_with("text/") && bytes.len() > max_text_bytes {
-        return Ok(());
-    }
-    if mime.starts_with("image/") && bytes.len() > max_image_bytes {
-        return Ok(());
-    }
-
-    if mime.starts_with("text/plain") {
-        let mut paths = collect_clipboard_paths(&bytes);
-        if !paths.is_empty() {
-            let mut existing: Vec<PathBuf> = Vec::new();
-            for p in paths.drain(..) {
-                if tokio::fs::metadata(&p).await.is_ok() {
-                    existing.push(p);
-                }
-            }
-            let mut uniq: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
-            for p in existing {
-                uniq.insert(p);
-            }
-            let existing: Vec<PathBuf> = uniq.into_iter().collect();
-            if !existing.is_empty() {
-                let _ = send_paths_as_file(
-                    &ctx.state_dir,
-                    &ctx.device_id,
-                    &ctx.device_name,
-                    room,
-                    relay,
-                    existing,
-                    max_file_bytes,
-                )
-                .await?;
-
-                set_suppress(
-                    &ctx.state_dir,
-                    room,
-                    "text/plain;charset=utf-8",
-                    "*",
-                    Duration::from_millis(1500),
-                )
-                .await;
-                set_suppress(
-                    &ctx.state_dir,
-                    room,
-                    "text/plain",
-                    "*",
-                    Duration::from_millis(1500),
-                )
-                .await;
-                return Ok(());
-            }
-        }
-    }
-
-    let (send_mime, send_bytes) = if mime.starts_with("image/") {
-        match image_mode {
-            ImageMode::ForcePng => match to_png(&bytes) {
-                Ok(png) => ("image/png", png),
-                Err(_) => return Ok(()),
-            },
-            ImageMode::Passthrough | ImageMode::MultiMime | ImageMode::SpoofPng => (mime, bytes),
-        }
-    } else {
-        (mime, bytes)
+    let cx = PublishCtx {
+        state_dir: &ctx.state_dir,
+        data: &ctx.data,
+        device_id: &ctx.device_id,
+        device_name: &ctx.device_name,
+        room,
+        relay,
+        dry_run,
     };
-
-    let sha = sha256_hex(&send_bytes);
-    if is_suppressed(&ctx.state_dir, room, send_mime, &sha).await {
-        return Ok(());
-    }
-
-    if send_mime.starts_with("image/") {
-        persist_image_best_effort(&sha, send_mime, &send_bytes).await;
-    }
-
-    let stream = connect(relay).await?;
-    let mut msg = if send_mime.starts_with("text/") {
-        let mut m = Message::new_text(&ctx.device_id, room, "");
-        m.payload = Some(send_bytes);
-        m.size = m.payload.as_ref().map(|p| p.len()).unwrap_or(0);
-        m
-    } else {
-        Message::new_image(&ctx.device_id, room, send_mime, send_bytes)
+    let limits = PublishLimits {
+        max_text_bytes,
+        max_image_bytes,
+        max_file_bytes,
+        text_only: text_only(),
     };
-    if !ctx.device_name.trim().is_empty() {
-        msg.sender_name = Some(ctx.device_name.clone());
+    // Set by wl-watch(watch) on its MIME-specific watchers.
+    let candidate = std::env::var("MCR_WATCH_CANDIDATE_MIME").ok();
+    let plan = publish_current(&cx, &WlPaste, mime, candidate.as_deref(), limits, image_mode).await?;
+    if let Some(plan) = plan.filter(|_| dry_run) {
+        report_plan(&plan);
     }
-    msg.sha256 = Some(sha.clone());
-    send_frame(stream, msg.to_bytes()).await?;
-    log::debug!(
-        "wl-watch: sent kind={:?} mime={} bytes={} sha={}",
-        msg.kind,
-        send_mime,
-        msg.size,
-        sha
-    );
-    record_send(
-        &ctx.device_id,
-        Some(ctx.device_name.clone()),
-        room,
-        relay,
-        msg.kind,
-        Some(send_mime.to_string()),
-        msg.name.clone(),
-        msg.size,
-        msg.sha256.clone(),
-    )
-    .await;
     Ok(())
 }
@@ -1,4 +1,4 @@
-pub const APP_DIR_NAME: &str = "multicliprelay";
+pub use utils::paths::{APP_DIR_NAME, TAR_MIME};
 
 // Suppress marker keys (used only locally for loop prevention).
 pub const FILE_SUPPRESS_KEY: &str = "application/x-multicliprelay-file";
@@ -20,5 +20,3 @@ pub const APPLIED_MARKER_MIME: &str = "application/x-multicliprelay-applied";
 //
 // This marker should generally NOT be forwarded over the network.
 pub const X11_SYNC_MARKER_MIME: &str = "application/x-multicliprelay-x11-sync";
-
-pub const TAR_MIME: &str = "application/x-tar";
@@ -0,0 +1,192 @@
+use anyhow::Context;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use utils::{Kind, Message};
+
+use crate::hash::fingerprint;
+
+/// Env vars used to pass `--send-filter` / `--apply-filter` / `--filter-all-kinds`
+/// to helper processes (e.g. the wl-watch hook).
+pub const SEND_FILTER_ENV: &str = "MCR_SEND_FILTER";
+pub const APPLY_FILTER_ENV: &str = "MCR_APPLY_FILTER";
+pub const FILTER_ALL_KINDS_ENV: &str = "MCR_FILTER_ALL_KINDS";
+
+/// User commands that payloads are piped through (stdin -> stdout, run via `sh -c`).
+#[derive(Debug, Clone, Default)]
+pub struct ContentFilters {
+    pub send: Option<String>,
+    pub apply: Option<String>,
+    /// Also filter images and files (text only by default).
+    pub all_kinds: bool,
+}
+
+impl ContentFilters {
+    fn from_env() -> Self {
+        let cmd = |k: &str| std::env::var(k).ok().filter(|v| !v.trim().is_empty());
+        Self {
+            send: cmd(SEND_FILTER_ENV),
+            apply: cmd(APPLY_FILTER_ENV),
+            all_kinds: matches!(
+                std::env::var(FILTER_ALL_KINDS_ENV).ok().as_deref(),
+                Some("1") | Some("true")
+            ),
+        }
+    }
+
+    fn applies_to(&self, kind: &Kind) -> bool {
+        match kind {
+            Kind::Text => true,
+            Kind::Image | Kind::File => self.all_kinds,
+            Kind::Join | Kind::Hello | Kind::Replay | Kind::Ping | Kind::Pong | Kind::Ack => false,
+        }
+    }
+
+    /// Env pairs for helper processes.
+    pub fn env_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut out = Vec::new();
+        if let Some(c) = &self.send {
+            out.push((SEND_FILTER_ENV, c.clone()));
+        }
+        if let Some(c) = &self.apply {
+            out.push((APPLY_FILTER_ENV, c.clone()));
+        }
+        if self.all_kinds {
+            out.push((FILTER_ALL_KINDS_ENV, "1".to_string()));
+        }
+        out
+    }
+}
+
+static FILTERS: OnceLock<ContentFilters> = OnceLock::new();
+
+/// Configure the filters (process-wide, set once at startup). Unset options fall back to env.
+pub fn set_content_filters(send: Option<String>, apply: Option<String>, all_kinds: bool) {
+    let env = ContentFilters::from_env();
+    let _ = FILTERS.set(ContentFilters {
+        send: send.filter(|c| !c.trim().is_empty()).or(env.send),
+        apply: apply.filter(|c| !c.trim().is_empty()).or(env.apply),
+        all_kinds: all_kinds || env.all_kinds,
+    });
+}
+
+pub fn content_filters() -> &'static ContentFilters {
+    FILTERS.get_or_init(ContentFilters::from_env)
+}
+
+/// Pipe `input` through `sh -c <cmd>`; `None` when the command exits nonzero.
+pub async fn run_filter(cmd: &str, input: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("spawn filter '{cmd}'"))?;
+
+    // Feed stdin concurrently so a filter that streams output can't deadlock on a full pipe.
+    let mut stdin = child.stdin.take().context("filter stdin")?;
+    let input = input.to_vec();
+    let feed = tokio::spawn(async move {
+        // A filter may legitimately stop reading early (e.g. `head`); ignore EPIPE.
+        let _ = stdin.write_all(&input).await;
+    });
+    let out = child
+        .wait_with_output()
+        .await
+        .with_context(|| format!("wait filter '{cmd}'"))?;
+    let _ = feed.await;
+
+    if !out.status.success() {
+        log::info!(
+            "filter '{}' exited with {}; dropping payload",
+            cmd,
+            out.status
+        );
+        return Ok(None);
+    }
+    Ok(Some(out.stdout))
+}
+
+async fn filter_with(cmd: Option<&str>, kind: &Kind, bytes: Vec<u8>) -> Option<Vec<u8>> {
+    let Some(cmd) = cmd.filter(|_| content_filters().applies_to(kind)) else {
+        return Some(bytes);
+    };
+    match run_filter(cmd, &bytes).await {
+        Ok(out) => out,
+        Err(e) => {
+            // Fail closed: the filter may exist to redact secrets.
+            log::warn!("filter failed; dropping payload: {e:#}");
+            None
+        }
+    }
+}
+
+/// Run an outgoing payload through `--send-filter` (`None` = drop it).
+///
+/// Call before hashing, so the sent sha matches what peers end up applying.
+pub async fn filter_outgoing(kind: &Kind, bytes: Vec<u8>) -> Option<Vec<u8>> {
+    filter_with(content_filters().send.as_deref(), kind, bytes).await
+}
+
+/// Whether `--send-filter` is set and covers `kind`, i.e. whether [`filter_outgoing`] needs
+/// the payload at all.
+pub fn filters_outgoing(kind: &Kind) -> bool {
+    let filters = content_filters();
+    filters.send.is_some() && filters.applies_to(kind)
+}
+
+/// Run an incoming message through `--apply-filter`, fixing up `size`/`sha256`.
+///
+/// Returns false when the message should be dropped.
+pub async fn filter_incoming(msg: &mut Message) -> bool {
+    let filters = content_filters();
+    if filters.apply.is_none() || !filters.applies_to(&msg.kind) {
+        return true;
+    }
+    let Some(payload) = msg.payload.take() else {
+        return true;
+    };
+    let Some(out) = filter_with(filters.apply.as_deref(), &msg.kind, payload).await else {
+        return false;
+    };
+    // Suppression markers use this sha; it must match what lands in the local clipboard.
+    msg.sha256 = Some(fingerprint(&out));
+    msg.size = out.len();
+    msg.payload = Some(out);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn filter_command_transforms_or_drops_payload() {
+        let out = run_filter("tr a-z A-Z", b"hello\r\nworld").await.unwrap();
+        assert_eq!(out.as_deref(), Some(b"HELLO\r\nWORLD".as_slice()));
+        let out = run_filter("tr -d '\\r'", b"a\r\nb\r\n").await.unwrap();
+        assert_eq!(out.as_deref(), Some(b"a\nb\n".as_slice()));
+        assert_eq!(
+            run_filter("cat >/dev/null; exit 3", b"x").await.unwrap(),
+            None
+        );
+
+        let text_only = ContentFilters {
+            send: Some("tr a-z A-Z".to_string()),
+            apply: None,
+            all_kinds: false,
+        };
+        assert!(text_only.applies_to(&Kind::Text));
+        assert!(!text_only.applies_to(&Kind::Image));
+        assert!(!text_only.applies_to(&Kind::File));
+        assert_eq!(
+            text_only.env_pairs(),
+            [(SEND_FILTER_ENV, "tr a-z A-Z".to_string())]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Context;
+use std::path::Path;
+
+use utils::Message;
+
+/// Env var carrying `--device-name` (also read from the systemd env file and passed to hooks).
+pub const DEVICE_NAME_ENV: &str = "MCR_NAME";
+
+fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+/// Best-effort hostname without extra dependencies.
+pub fn hostname() -> Option<String> {
+    if let Some(h) = std::env::var("HOSTNAME")
+        .ok()
+        .as_deref()
+        .and_then(non_empty)
+    {
+        return Some(h);
+    }
+    ["/etc/hostname", "/proc/sys/kernel/hostname"]
+        .iter()
+        .find_map(|p| {
+            std::fs::read_to_string(p)
+                .ok()
+                .as_deref()
+                .and_then(non_empty)
+        })
+}
+
+/// Display name sent as `sender_name`: `--device-name`, then env MCR_NAME, then the hostname.
+pub fn resolve_device_name(cli: Option<&str>) -> String {
+    let env = std::env::var(DEVICE_NAME_ENV).ok();
+    resolve_device_name_from(cli, env.as_deref(), hostname())
+}
+
+fn resolve_device_name_from(cli: Option<&str>, env: Option<&str>, host: Option<String>) -> String {
+    cli.and_then(non_empty)
+        .or_else(|| env.and_then(non_empty))
+        .or(host)
+        .or_else(|| std::env::var("USER").ok().as_deref().and_then(non_empty))
+        .unwrap_or_else(|| "multicliprelay".to_string())
+}
+
+/// The id node commands on this machine send as (`<state_dir>/device_id`), created on first use.
+pub async fn local_device_id(state_dir: &Path) -> anyhow::Result<String> {
+    let p = state_dir.join("device_id");
+    if let Ok(s) = tokio::fs::read_to_string(&p).await {
+        let id = s.trim().to_string();
+        if !id.is_empty() {
+            return Ok(id);
+        }
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    tokio::fs::write(&p, &id).await.context("write device_id")?;
+    Ok(id)
+}
+
+/// `sender_name` for outgoing messages (`None` when the name is blank).
+pub fn sender_name(device_name: &str) -> Option<String> {
+    non_empty(device_name)
+}
+
+pub fn set_sender_name(msg: &mut Message, device_name: &str) {
+    msg.sender_name = sender_name(device_name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn configured_name_propagates_to_sent_messages() {
+        let name =
+            resolve_device_name_from(Some("  Kitchen PC "), Some("env-name"), Some("host".into()));
+        assert_eq!(name, "Kitchen PC");
+        assert_eq!(
+            resolve_device_name_from(Some(" "), Some("env-name"), None),
+            "env-name"
+        );
+        assert_eq!(
+            resolve_device_name_from(None, None, Some("host".into())),
+            "host"
+        );
+
+        let mut msgs = [
+            Message::new_text("dev", "room", "hi"),
+            Message::new_image("dev", "room", "image/png", vec![1]),
+            Message::new_file("dev", "room", "a.txt", "text/plain", vec![2]),
+        ];
+        for m in msgs.iter_mut() {
+            set_sender_name(m, &name);
+            let decoded = Message::try_from_bytes(&m.to_bytes()).unwrap();
+            assert_eq!(decoded.sender_name.as_deref(), Some("Kitchen PC"));
+        }
+        set_sender_name(&mut msgs[0], "   ");
+        assert_eq!(msgs[0].sender_name, None);
+
+        // Join frames carry it too, so peers can show the name before any clipboard traffic.
+        let l = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut server, _) = l.accept().await.unwrap();
+        let (_r, mut w) = client.into_split();
+        crate::net::send_join(&mut w, "dev", &name, "room")
+            .await
+            .unwrap();
+        let join = crate::net::read_msg(&mut server).await;
+        assert_eq!(join.sender_name.as_deref(), Some("Kitchen PC"));
+    }
+}
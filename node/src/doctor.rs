@@ -0,0 +1,261 @@
+//! `doctor`: check that this machine can run the node (session, helper programs, relay,
+//! clipboard access) and print a pass/fail summary.
+//!
+//! Each check is split into a probe (which touches the system) and a pure function turning
+//! the probe's outcome into a [`Check`], so the verdicts can be tested without a compositor.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::process::Command;
+use utils::probe::{probe_ping, ProbeResult};
+
+use crate::clipboard::{wl_copy, wl_list_types, wl_paste};
+
+/// How long the relay probe may take.
+pub const RELAY_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Not needed for the common setup, but some feature won't work.
+    Warn,
+    Fail,
+}
+
+/// One line of the report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn line(&self) -> String {
+        let tag = match self.status {
+            Status::Pass => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        format!("[{:<4}] {}: {}", tag, self.name, self.detail)
+    }
+}
+
+/// Run `name --help` to see whether the program is on PATH.
+pub async fn probe_bin(name: &str) -> std::io::Result<()> {
+    Command::new(name)
+        .arg("--help")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .await
+        .map(|_| ())
+}
+
+/// Fail unless `name` can be started (commands that shell out call this up front).
+pub async fn ensure_bin(name: &str) -> anyhow::Result<()> {
+    match probe_bin(name).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("required program not found in PATH: {name}")
+        }
+        Err(e) => Err(anyhow::anyhow!(e)).context(format!("check program: {name}")),
+    }
+}
+
+/// Verdict for a program probe.
+pub fn bin_check(name: &str, used_for: &str, probe: std::io::Result<()>) -> Check {
+    let label = format!("program {name}");
+    match probe {
+        Ok(()) => Check::new(&label, Status::Pass, "found"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Check::new(
+            &label,
+            Status::Fail,
+            format!("not found in PATH (needed for {used_for})"),
+        ),
+        Err(e) => Check::new(&label, Status::Fail, format!("cannot run: {e}")),
+    }
+}
+
+/// Verdict for the session: wl-watch/wl-apply need Wayland, x11-sync also needs X11.
+pub fn session_checks(wayland_display: Option<&str>, display: Option<&str>) -> Vec<Check> {
+    let set = |v: Option<&str>| {
+        v.map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    vec![
+        match set(wayland_display) {
+            Some(v) => Check::new(
+                "wayland session",
+                Status::Pass,
+                format!("WAYLAND_DISPLAY={v}"),
+            ),
+            None => Check::new(
+                "wayland session",
+                Status::Fail,
+                "WAYLAND_DISPLAY is not set",
+            ),
+        },
+        match set(display) {
+            Some(v) => Check::new("x11 display", Status::Pass, format!("DISPLAY={v}")),
+            None => Check::new(
+                "x11 display",
+                Status::Warn,
+                "DISPLAY is not set (only x11-sync needs it)",
+            ),
+        },
+    ]
+}
+
+/// Verdict for the relay ping.
+pub fn relay_check(relay: &str, probe: &ProbeResult) -> Check {
+    let name = format!("relay {relay}");
+    if probe.ok {
+        Check::new(&name, Status::Pass, probe.detail.clone())
+    } else {
+        Check::new(
+            &name,
+            Status::Fail,
+            format!("unreachable: {}", probe.detail),
+        )
+    }
+}
+
+/// Verdict for listing the clipboard's types (`None`: the clipboard could not be read).
+pub fn clipboard_read_check(types: Option<&[String]>) -> Check {
+    match types {
+        Some([]) => Check::new("clipboard read", Status::Pass, "readable (currently empty)"),
+        Some(types) => Check::new(
+            "clipboard read",
+            Status::Pass,
+            format!("{} type(s) offered, e.g. {}", types.len(), types[0]),
+        ),
+        None => Check::new(
+            "clipboard read",
+            Status::Fail,
+            "cannot list clipboard types (no data-control support or wl-paste failing?)",
+        ),
+    }
+}
+
+/// Verdict for writing `sent` and reading back `got`.
+pub fn roundtrip_check(sent: &str, got: anyhow::Result<Vec<u8>>) -> Check {
+    let name = "clipboard write";
+    match got {
+        Ok(bytes) if bytes.strip_suffix(b"\n").unwrap_or(&bytes) == sent.as_bytes() => {
+            Check::new(name, Status::Pass, "round-trip ok")
+        }
+        Ok(bytes) => Check::new(
+            name,
+            Status::Fail,
+            format!(
+                "read back {} bytes that differ from what was written",
+                bytes.len()
+            ),
+        ),
+        Err(e) => Check::new(name, Status::Fail, format!("{e:#}")),
+    }
+}
+
+/// Run every check. `write` also replaces the clipboard with a test string and reads it back.
+pub async fn run_checks(relay: &str, write: bool) -> Vec<Check> {
+    let mut checks = session_checks(
+        std::env::var("WAYLAND_DISPLAY").ok().as_deref(),
+        std::env::var("DISPLAY").ok().as_deref(),
+    );
+    checks.push(bin_check(
+        "wl-paste",
+        "wl-watch and x11-sync",
+        probe_bin("wl-paste").await,
+    ));
+
+    let addr = relay.to_string();
+    let probe = tokio::task::spawn_blocking(move || probe_ping(&addr, RELAY_PROBE_TIMEOUT))
+        .await
+        .unwrap_or_else(|e| ProbeResult {
+            ok: false,
+            detail: format!("probe task failed: {e}"),
+        });
+    checks.push(relay_check(relay, &probe));
+
+    checks.push(clipboard_read_check(wl_list_types().await.as_deref()));
+    if write {
+        let token = format!("multicliprelay doctor {}", utils::now_ms());
+        let got = match wl_copy("text/plain;charset=utf-8", token.as_bytes()).await {
+            Ok(()) => wl_paste("text/plain;charset=utf-8").await,
+            Err(e) => Err(e),
+        };
+        checks.push(roundtrip_check(&token, got));
+    } else {
+        checks.push(Check::new(
+            "clipboard write",
+            Status::Warn,
+            "skipped (pass --write; it replaces the current clipboard)",
+        ));
+    }
+    checks
+}
+
+/// Count of failed checks.
+pub fn failures(checks: &[Check]) -> usize {
+    checks.iter().filter(|c| c.status == Status::Fail).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_verdicts_from_mocked_probes() {
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(bin_check("wl-paste", "x", Ok(())).status, Status::Pass);
+        let c = bin_check("wl-paste", "wl-watch", Err(missing));
+        assert_eq!(c.status, Status::Fail);
+        assert!(c.detail.contains("needed for wl-watch"), "{}", c.detail);
+
+        let session = session_checks(Some("wayland-0"), Some(" "));
+        assert_eq!(session[0].status, Status::Pass);
+        assert_eq!(session[1].status, Status::Warn);
+        assert_eq!(session_checks(None, None)[0].status, Status::Fail);
+
+        let down = ProbeResult {
+            ok: false,
+            detail: "connection refused".to_string(),
+        };
+        let c = relay_check("127.0.0.1:8080", &down);
+        assert_eq!(c.status, Status::Fail);
+        assert_eq!(
+            c.line(),
+            "[FAIL] relay 127.0.0.1:8080: unreachable: connection refused"
+        );
+
+        assert_eq!(clipboard_read_check(Some(&[])).status, Status::Pass);
+        assert_eq!(clipboard_read_check(None).status, Status::Fail);
+
+        assert_eq!(
+            roundtrip_check("hi", Ok(b"hi\n".to_vec())).status,
+            Status::Pass
+        );
+        assert_eq!(
+            roundtrip_check("hi", Ok(b"other".to_vec())).status,
+            Status::Fail
+        );
+        assert_eq!(
+            roundtrip_check("hi", Err(anyhow::anyhow!("no seat"))).detail,
+            "no seat"
+        );
+
+        let all = [c, bin_check("wl-paste", "x", Ok(()))];
+        assert_eq!(failures(&all), 1);
+    }
+}
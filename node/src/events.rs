@@ -0,0 +1,140 @@
+//! `--output json`: one JSON object per event on stdout, for piping into other tools.
+//!
+//! In json mode the human-readable status lines (see [`say!`](crate::say)) are silenced so
+//! stdout stays line-parseable; errors and logs still go to stderr.
+
+use serde::Serialize;
+use std::sync::OnceLock;
+
+use utils::{Kind, Message};
+
+/// Env var used to pass `--output` to helper processes (e.g. the wl-watch hook).
+pub const OUTPUT_ENV: &str = "MCR_OUTPUT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub fn parse_output_format(s: &str) -> anyhow::Result<OutputFormat> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "text" | "" => Ok(OutputFormat::Text),
+        "json" => Ok(OutputFormat::Json),
+        other => anyhow::bail!("invalid output format '{other}' (expected text or json)"),
+    }
+}
+
+static OUTPUT: OnceLock<OutputFormat> = OnceLock::new();
+
+fn output_from_env() -> OutputFormat {
+    std::env::var(OUTPUT_ENV)
+        .ok()
+        .and_then(|v| parse_output_format(&v).ok())
+        .unwrap_or_default()
+}
+
+/// Configure the output format (process-wide, set once at startup). `None` falls back to env.
+pub fn set_output_format(format: Option<OutputFormat>) {
+    let _ = OUTPUT.set(format.unwrap_or_else(output_from_env));
+}
+
+pub fn output_format() -> OutputFormat {
+    *OUTPUT.get_or_init(output_from_env)
+}
+
+pub fn json_output() -> bool {
+    output_format() == OutputFormat::Json
+}
+
+/// `println!` for human-readable status lines; silent under `--output json`.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::events::json_output() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// One line of `--output json`.
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    /// `recv` (listen), `apply` (wl-apply) or `send` (wl-watch / publish).
+    pub event: &'a str,
+    pub ts_ms: u64,
+    pub room: &'a str,
+    pub kind: &'a Kind,
+    pub device_id: &'a str,
+    pub sender_name: Option<&'a str>,
+    pub mime: Option<&'a str>,
+    pub name: Option<&'a str>,
+    pub size: usize,
+    pub sha256: Option<&'a str>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(event: &'a str, msg: &'a Message) -> Self {
+        Self {
+            event,
+            ts_ms: utils::now_ms(),
+            room: &msg.room,
+            kind: &msg.kind,
+            device_id: &msg.device_id,
+            sender_name: msg.sender_name.as_deref(),
+            mime: msg.mime.as_deref(),
+            name: msg.name.as_deref(),
+            size: msg.size,
+            sha256: msg.sha256.as_deref(),
+        }
+    }
+
+    pub fn to_json_line(&self) -> String {
+        // Only strings and numbers: serialization can't fail.
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Print `msg` as an event line when `--output json` is active (no-op otherwise).
+pub fn emit_event(event: &str, msg: &Message) {
+    if json_output() {
+        println!("{}", Event::new(event, msg).to_json_line());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_lines_parse_for_every_kind() {
+        let mut text = Message::new_text("dev-a", "r", "hi");
+        text.sender_name = Some("laptop".to_string());
+        let msgs = [
+            text,
+            Message::new_image("dev-a", "r", "image/png", vec![1, 2, 3]),
+            Message::new_file("dev-a", "r", "a.tar", "application/x-tar", vec![0; 10]),
+            Message::new_join("dev-a", "r"),
+        ];
+        for (msg, kind) in msgs.iter().zip(["Text", "Image", "File", "Join"]) {
+            let line = Event::new("recv", msg).to_json_line();
+            assert!(!line.contains('\n'), "{line}");
+            let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(v["event"], "recv");
+            assert_eq!(v["kind"], kind);
+            assert_eq!(v["device_id"], "dev-a");
+            assert_eq!(v["room"], "r");
+            assert_eq!(v["size"], msg.size);
+            assert_eq!(v["mime"].as_str(), msg.mime.as_deref());
+            assert_eq!(v["sha256"].as_str(), msg.sha256.as_deref());
+        }
+        let v: serde_json::Value =
+            serde_json::from_str(&Event::new("recv", &msgs[0]).to_json_line()).unwrap();
+        assert_eq!(v["sender_name"], "laptop");
+
+        assert_eq!(parse_output_format("JSON").unwrap(), OutputFormat::Json);
+        assert_eq!(parse_output_format("text").unwrap(), OutputFormat::Text);
+        assert!(parse_output_format("yaml").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! `--extra-mime`: app-specific clipboard types synced as opaque bytes.
+//!
+//! Some apps copy in private formats (`application/x-inkscape-svg`, a DAW's pattern type,
+//! ...) that only another instance of the same app understands. Listed types are published
+//! as `Kind::File` payloads tagged with their MIME, and wl-apply re-offers them under that
+//! MIME on devices that list it too (elsewhere they arrive as a plain received file).
+
+use std::sync::OnceLock;
+
+use utils::{Kind, Message};
+
+use crate::consts::APPLIED_MARKER_MIME;
+use crate::paths::safe_for_filename;
+use crate::publish::is_file_list_mime;
+use crate::rich_text::is_rtf_mime;
+use crate::transfer_image::image_mimes;
+
+/// Env var used to pass `--extra-mime` to helper processes (comma-separated).
+pub const EXTRA_MIMES_ENV: &str = "MCR_EXTRA_MIMES";
+
+static EXTRA_MIMES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Parse `--extra-mime` values; each may itself be a comma-separated list.
+///
+/// Types the node already syncs natively (text, RTF, images, file lists) are rejected: passing
+/// them through opaquely would lose the conversions and fallbacks.
+pub fn parse_extra_mimes(items: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut out: Vec<String> = Vec::new();
+    for m in items.iter().flat_map(|s| s.split(',')).map(str::trim) {
+        if m.is_empty() {
+            continue;
+        }
+        let valid = m.split_once('/').is_some_and(|(t, s)| {
+            !t.is_empty() && !s.is_empty() && !m.contains(|c: char| c.is_whitespace() || c == '*')
+        });
+        anyhow::ensure!(valid, "invalid extra mime '{m}' (expected type/subtype)");
+        anyhow::ensure!(
+            !is_builtin_mime(m),
+            "extra mime '{m}' is already synced (text, rtf, images and file lists are built in)"
+        );
+        if !out.iter().any(|o| o == m) {
+            out.push(m.to_string());
+        }
+    }
+    Ok(out)
+}
+
+fn is_builtin_mime(mime: &str) -> bool {
+    mime == APPLIED_MARKER_MIME
+        || mime.starts_with("text/plain")
+        || is_rtf_mime(mime)
+        || is_file_list_mime(mime)
+        || image_mimes().contains(&mime)
+}
+
+fn extra_mimes_from_env() -> Vec<String> {
+    let Ok(v) = std::env::var(EXTRA_MIMES_ENV) else {
+        return Vec::new();
+    };
+    parse_extra_mimes(&[v]).unwrap_or_else(|e| {
+        log::warn!("ignoring {}: {:#}", EXTRA_MIMES_ENV, e);
+        Vec::new()
+    })
+}
+
+/// Configure the passthrough types (process-wide, set once at startup). Empty falls back to env.
+pub fn set_extra_mimes(mimes: Vec<String>) {
+    let mimes = if mimes.is_empty() {
+        extra_mimes_from_env()
+    } else {
+        mimes
+    };
+    let _ = EXTRA_MIMES.set(mimes);
+}
+
+/// The passthrough types; none until [`set_extra_mimes`] ran.
+pub fn extra_mimes() -> &'static [String] {
+    EXTRA_MIMES.get().map(Vec::as_slice).unwrap_or(&[])
+}
+
+pub fn is_extra_mime(mime: &str) -> bool {
+    extra_mimes().iter().any(|m| m == mime)
+}
+
+/// First passthrough type the clipboard offers, if any.
+pub fn pick_extra_mime<F: Fn(&str) -> bool>(has: F) -> Option<&'static str> {
+    extra_mimes().iter().map(String::as_str).find(|m| has(m))
+}
+
+/// Name for an outgoing passthrough payload (receivers without the type store it as a file).
+pub fn extra_mime_file_name(mime: &str) -> String {
+    format!("clipboard-{}.bin", safe_for_filename(mime))
+}
+
+/// Clipboard offers for an incoming passthrough payload: the bytes under their own MIME, plus
+/// the applied marker. `None` unless `msg` carries a type this device passes through.
+pub fn extra_mime_clipboard_items(msg: &Message) -> Option<Vec<(String, Vec<u8>)>> {
+    let mime = msg.mime.as_deref().filter(|m| is_extra_mime(m))?;
+    let payload = msg.payload.as_deref()?;
+    if !matches!(msg.kind, Kind::File) {
+        return None;
+    }
+    Some(vec![
+        (mime.to_string(), payload.to_vec()),
+        (
+            APPLIED_MARKER_MIME.to_string(),
+            format!(
+                "applied\nkind=extra\nsha={}\nmime={}\n",
+                msg.sha256.as_deref().unwrap_or_default(),
+                mime
+            )
+            .into_bytes(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extra_mimes_parse_and_reject_builtin_types() {
+        let parsed = parse_extra_mimes(&[
+            "application/x-inkscape-svg, application/x-foo".to_string(),
+            "application/x-foo".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(parsed, ["application/x-inkscape-svg", "application/x-foo"]);
+        assert!(parse_extra_mimes(&["".to_string()]).unwrap().is_empty());
+
+        for bad in ["foo", "application/*", "a b/c", "/x"] {
+            assert!(parse_extra_mimes(&[bad.to_string()]).is_err(), "{bad}");
+        }
+        for builtin in [
+            "text/plain",
+            "text/rtf",
+            "image/png",
+            "text/uri-list",
+            APPLIED_MARKER_MIME,
+        ] {
+            assert!(
+                parse_extra_mimes(&[builtin.to_string()]).is_err(),
+                "{builtin}"
+            );
+        }
+        assert_eq!(
+            extra_mime_file_name("application/x-inkscape-svg"),
+            "clipboard-application_x-inkscape-svg.bin"
+        );
+    }
+}
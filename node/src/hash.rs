@@ -1,7 +1,187 @@
+//! Content fingerprints for suppression, dedupe and the `sha256` message field.
+//!
+//! These only ever answer "is this the same payload as before", never anything
+//! security-related, so the algorithm is a speed trade-off (`--hash-algo`). Values other
+//! than sha256 carry a tag prefix (`b3:<hex>`), so fingerprints from different
+//! algorithms never compare equal, and peers don't need to agree on one.
+
+use std::sync::OnceLock;
+
 use sha2::{Digest, Sha256};
 
+/// Env fallback for `--hash-algo` (also passed to helper processes).
+pub const HASH_ALGO_ENV: &str = "MCR_HASH_ALGO";
+
+const BLAKE3_TAG: &str = "b3:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgo {
+    /// Untagged hex, as sent by every version (default).
+    Sha256,
+    /// Much faster on large payloads.
+    Blake3,
+}
+
+pub fn parse_hash_algo(s: &str) -> anyhow::Result<HashAlgo> {
+    match s {
+        "sha256" => Ok(HashAlgo::Sha256),
+        "blake3" => Ok(HashAlgo::Blake3),
+        other => anyhow::bail!("invalid --hash-algo {}, expected sha256|blake3", other),
+    }
+}
+
+pub fn hash_algo_as_cli_arg(a: HashAlgo) -> &'static str {
+    match a {
+        HashAlgo::Sha256 => "sha256",
+        HashAlgo::Blake3 => "blake3",
+    }
+}
+
+static HASH_ALGO: OnceLock<HashAlgo> = OnceLock::new();
+
+/// Set the process-wide fingerprint algorithm (call once at startup).
+pub fn set_hash_algo(a: HashAlgo) {
+    let _ = HASH_ALGO.set(a);
+}
+
+/// Process-wide fingerprint algorithm (CLI, then env, then sha256).
+pub fn hash_algo() -> HashAlgo {
+    *HASH_ALGO.get_or_init(|| {
+        std::env::var(HASH_ALGO_ENV)
+            .ok()
+            .and_then(|v| parse_hash_algo(v.trim()).ok())
+            .unwrap_or(HashAlgo::Sha256)
+    })
+}
+
 pub fn sha256_hex(bytes: &[u8]) -> String {
     let mut h = Sha256::new();
     h.update(bytes);
     hex::encode(h.finalize())
 }
+
+/// Fingerprint of `bytes` with `algo`.
+pub fn fingerprint_with(algo: HashAlgo, bytes: &[u8]) -> String {
+    match algo {
+        HashAlgo::Sha256 => sha256_hex(bytes),
+        HashAlgo::Blake3 => format!("{}{}", BLAKE3_TAG, blake3::hash(bytes).to_hex()),
+    }
+}
+
+/// Fingerprint of `bytes` with the configured algorithm.
+pub fn fingerprint(bytes: &[u8]) -> String {
+    fingerprint_with(hash_algo(), bytes)
+}
+
+/// [`fingerprint`] of a payload that is written or read in pieces.
+pub enum Fingerprinter {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Default for Fingerprinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fingerprinter {
+    /// With the configured algorithm.
+    pub fn new() -> Self {
+        Self::with(hash_algo())
+    }
+
+    pub fn with(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgo::Blake3 => Self::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(bytes),
+            Self::Blake3(h) => {
+                h.update(bytes);
+            }
+        }
+    }
+
+    pub fn finish(self) -> String {
+        match self {
+            Self::Sha256(h) => hex::encode(h.finalize()),
+            Self::Blake3(h) => format!("{}{}", BLAKE3_TAG, h.finalize().to_hex()),
+        }
+    }
+}
+
+/// The algorithm a fingerprint was made with (untagged values are sha256).
+pub fn fingerprint_algo(fp: &str) -> HashAlgo {
+    if fp.starts_with(BLAKE3_TAG) {
+        HashAlgo::Blake3
+    } else {
+        HashAlgo::Sha256
+    }
+}
+
+/// A peer's fingerprint for `payload`, redone with `local` when the peer used another
+/// algorithm; the local watcher compares against its own fingerprints, so a foreign one
+/// would never suppress the echo.
+pub fn reconcile_fingerprint(remote: &str, payload: &[u8], local: HashAlgo) -> String {
+    if fingerprint_algo(remote) == local {
+        remote.to_string()
+    } else {
+        fingerprint_with(local, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn fingerprints_are_namespaced_by_algorithm() {
+        let sha = fingerprint_with(HashAlgo::Sha256, b"hello");
+        let b3 = fingerprint_with(HashAlgo::Blake3, b"hello");
+        assert_eq!(sha, sha256_hex(b"hello"));
+        assert!(b3.starts_with("b3:") && b3.len() == 3 + 64, "{b3}");
+        assert_eq!(fingerprint_algo(&sha), HashAlgo::Sha256);
+        assert_eq!(fingerprint_algo(&b3), HashAlgo::Blake3);
+
+        assert_eq!(reconcile_fingerprint(&b3, b"hello", HashAlgo::Blake3), b3);
+        assert_eq!(reconcile_fingerprint(&b3, b"hello", HashAlgo::Sha256), sha);
+        assert_eq!(reconcile_fingerprint(&sha, b"hello", HashAlgo::Blake3), b3);
+        assert!(parse_hash_algo("md5").is_err());
+    }
+
+    #[test]
+    fn incremental_fingerprint_matches_one_shot() {
+        for algo in [HashAlgo::Sha256, HashAlgo::Blake3] {
+            let mut fp = Fingerprinter::with(algo);
+            fp.update(b"hel");
+            fp.update(b"");
+            fp.update(b"lo");
+            assert_eq!(fp.finish(), fingerprint_with(algo, b"hello"), "{algo:?}");
+        }
+    }
+
+    /// Not a pass/fail benchmark (debug builds and shared CI machines are too noisy);
+    /// run with `--ignored --nocapture` to compare throughput.
+    #[test]
+    #[ignore]
+    fn hashing_throughput() {
+        let data: Vec<u8> = (0..16u32 << 20).map(|i| (i * 31 % 251) as u8).collect();
+        for algo in [HashAlgo::Sha256, HashAlgo::Blake3] {
+            let start = Instant::now();
+            let fp = fingerprint_with(algo, &data);
+            let secs = start.elapsed().as_secs_f64().max(1e-9);
+            eprintln!(
+                "{:?}: {:.0} MiB/s",
+                algo,
+                data.len() as f64 / (1 << 20) as f64 / secs
+            );
+            assert_eq!(fp, fingerprint_with(algo, &data));
+        }
+    }
+}
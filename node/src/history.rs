@@ -1,9 +1,23 @@
-use serde::Serialize;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 use tokio::io::AsyncWriteExt;
 
 use utils::{Kind, Message};
 
-#[derive(Debug, Clone, Serialize)]
+/// How a clipboard event ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Sent,
+    Recv,
+    /// Not sent on purpose (size cap, superseded by a newer copy); `reason` says which.
+    Skipped,
+    /// Meant to go out but didn't (connect or send error).
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEvent {
     pub ts_ms: u64,
     pub dir: String,
@@ -20,15 +34,20 @@ pub struct HistoryEvent {
     pub name: Option<String>,
     pub bytes: usize,
     pub sha256: Option<String>,
+    /// Where a received file bundle came from on the sender, when it shared that.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orig_paths: Option<Vec<String>>,
+    /// Missing on lines from older versions, which only logged what went through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+    /// Why a send was skipped or failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
-fn history_path() -> std::path::PathBuf {
-    crate::paths::history_path()
-}
-
-async fn append_history(event: HistoryEvent) {
+/// Append `event` to the log at `p` (see [`DataDirs::history`](crate::paths::DataDirs)).
+async fn append_history(p: &Path, event: HistoryEvent) {
     // Best-effort; never fail the main flow.
-    let p = history_path();
     if let Some(parent) = p.parent() {
         let _ = tokio::fs::create_dir_all(parent).await;
     }
@@ -41,7 +60,7 @@ async fn append_history(event: HistoryEvent) {
     let mut f = match tokio::fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&p)
+        .open(p)
         .await
     {
         Ok(v) => v,
@@ -58,11 +77,18 @@ fn kind_to_string(k: &Kind) -> String {
         Kind::Image => "image",
         Kind::File => "file",
         Kind::Join => "join",
+        Kind::Hello => "hello",
+        Kind::Replay => "replay",
+        Kind::Ping => "ping",
+        Kind::Pong => "pong",
+        Kind::Ack => "ack",
     }
     .to_string()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn record_send(
+    history: &Path,
     local_device_id: &str,
     local_device_name: Option<String>,
     room: &str,
@@ -83,7 +109,45 @@ pub async fn record_send(
         bytes,
         sha256
     );
-    append_history(HistoryEvent {
+    append_history(
+        history,
+        HistoryEvent {
+            ts_ms: utils::now_ms(),
+            dir: "send".to_string(),
+            room: room.to_string(),
+            relay: relay.to_string(),
+            local_device_id: local_device_id.to_string(),
+            local_device_name,
+            remote_device_id: None,
+            remote_device_name: None,
+            kind: kind_to_string(&kind),
+            mime,
+            name,
+            bytes,
+            sha256,
+            orig_paths: None,
+            outcome: Some(Outcome::Sent),
+            reason: None,
+        },
+    )
+    .await;
+}
+
+/// The history entry for a send that was skipped or failed (`outcome`), and why.
+#[allow(clippy::too_many_arguments)]
+pub fn send_outcome_event(
+    local_device_id: &str,
+    local_device_name: Option<String>,
+    room: &str,
+    relay: &str,
+    outcome: Outcome,
+    reason: &str,
+    kind: Kind,
+    mime: Option<String>,
+    bytes: usize,
+    sha256: Option<String>,
+) -> HistoryEvent {
+    HistoryEvent {
         ts_ms: utils::now_ms(),
         dir: "send".to_string(),
         room: room.to_string(),
@@ -94,32 +158,39 @@ pub async fn record_send(
         remote_device_name: None,
         kind: kind_to_string(&kind),
         mime,
-        name,
+        name: None,
         bytes,
         sha256,
-    })
-    .await;
+        orig_paths: None,
+        outcome: Some(outcome),
+        reason: Some(reason.to_string()),
+    }
 }
 
-pub async fn record_recv(
+/// Log an event that isn't a plain send or receive, such as a skipped or failed send.
+pub async fn record_event(history: &Path, event: HistoryEvent) {
+    log::debug!(
+        "{}: {:?} room={} kind={} mime={:?} bytes={} reason={:?}",
+        event.dir,
+        event.outcome,
+        event.room,
+        event.kind,
+        event.mime,
+        event.bytes,
+        event.reason
+    );
+    append_history(history, event).await;
+}
+
+/// The history entry for `msg`, received in `room` on `relay`.
+fn recv_event(
     local_device_id: &str,
     local_device_name: Option<String>,
     room: &str,
     relay: &str,
     msg: &Message,
-) {
-    log::debug!(
-        "recv: room={} relay={} from={} kind={:?} mime={:?} name={:?} bytes={} sha={:?}",
-        room,
-        relay,
-        msg.device_id,
-        msg.kind,
-        msg.mime,
-        msg.name,
-        msg.payload.as_ref().map(|p| p.len()).unwrap_or(0),
-        msg.sha256
-    );
-    append_history(HistoryEvent {
+) -> HistoryEvent {
+    HistoryEvent {
         ts_ms: utils::now_ms(),
         dir: "recv".to_string(),
         room: room.to_string(),
@@ -133,6 +204,241 @@ pub async fn record_recv(
         name: msg.name.clone(),
         bytes: msg.payload.as_ref().map(|p| p.len()).unwrap_or(0),
         sha256: msg.sha256.clone(),
-    })
-    .await;
+        orig_paths: msg.orig_paths.clone(),
+        outcome: Some(Outcome::Recv),
+        reason: None,
+    }
+}
+
+pub async fn record_recv(
+    history: &Path,
+    local_device_id: &str,
+    local_device_name: Option<String>,
+    room: &str,
+    relay: &str,
+    msg: &Message,
+) {
+    log::debug!(
+        "recv: room={} relay={} from={} kind={:?} mime={:?} name={:?} bytes={} sha={:?}",
+        room,
+        relay,
+        msg.device_id,
+        msg.kind,
+        msg.mime,
+        msg.name,
+        msg.payload.as_ref().map(|p| p.len()).unwrap_or(0),
+        msg.sha256
+    );
+    let event = recv_event(local_device_id, local_device_name, room, relay, msg);
+    append_history(history, event).await;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+pub fn parse_export_format(s: &str) -> anyhow::Result<ExportFormat> {
+    match s {
+        "json" => Ok(ExportFormat::Json),
+        "csv" => Ok(ExportFormat::Csv),
+        other => anyhow::bail!("invalid --format {}, expected json|csv", other),
+    }
+}
+
+/// Read the persisted history log; lines that don't parse (truncated writes, older formats)
+/// are skipped.
+pub fn read_history(path: &Path) -> anyhow::Result<Vec<HistoryEvent>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+    };
+    Ok(text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect())
+}
+
+const CSV_HEADER: &[&str] = &[
+    "ts_ms",
+    "dir",
+    "room",
+    "relay",
+    "local_device_id",
+    "local_device_name",
+    "remote_device_id",
+    "remote_device_name",
+    "kind",
+    "mime",
+    "name",
+    "bytes",
+    "sha256",
+    "outcome",
+    "reason",
+];
+
+/// RFC 4180 quoting: only when needed, with embedded quotes doubled.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn outcome_str(o: Outcome) -> &'static str {
+    match o {
+        Outcome::Sent => "sent",
+        Outcome::Recv => "recv",
+        Outcome::Skipped => "skipped",
+        Outcome::Failed => "failed",
+    }
+}
+
+fn csv_row(e: &HistoryEvent) -> String {
+    let opt = |v: &Option<String>| v.clone().unwrap_or_default();
+    [
+        e.ts_ms.to_string(),
+        e.dir.clone(),
+        e.room.clone(),
+        e.relay.clone(),
+        e.local_device_id.clone(),
+        opt(&e.local_device_name),
+        opt(&e.remote_device_id),
+        opt(&e.remote_device_name),
+        e.kind.clone(),
+        opt(&e.mime),
+        opt(&e.name),
+        e.bytes.to_string(),
+        opt(&e.sha256),
+        e.outcome
+            .map(|o| outcome_str(o).to_string())
+            .unwrap_or_default(),
+        opt(&e.reason),
+    ]
+    .iter()
+    .map(|f| csv_field(f))
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+/// Render events for export. Only metadata is recorded in history, never payloads.
+pub fn export_events(events: &[HistoryEvent], format: ExportFormat) -> anyhow::Result<String> {
+    match format {
+        ExportFormat::Json => {
+            let mut s = serde_json::to_string_pretty(events).context("serialize history")?;
+            s.push('\n');
+            Ok(s)
+        }
+        ExportFormat::Csv => {
+            let mut s = CSV_HEADER.join(",");
+            s.push_str("\r\n");
+            for e in events {
+                s.push_str(&csv_row(e));
+                s.push_str("\r\n");
+            }
+            Ok(s)
+        }
+    }
+}
+
+/// `history export`: write the whole log at `history` to `out`; returns the event count.
+pub fn export_history(history: &Path, format: ExportFormat, out: &Path) -> anyhow::Result<usize> {
+    let events = read_history(history)?;
+    let body = export_events(&events, format)?;
+    if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).with_context(|| format!("mkdir {}", parent.display()))?;
+    }
+    std::fs::write(out, body).with_context(|| format!("write {}", out.display()))?;
+    Ok(events.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ev(dir: &str, name: Option<&str>, remote: Option<&str>) -> HistoryEvent {
+        HistoryEvent {
+            ts_ms: 1_700_000_000_000,
+            dir: dir.to_string(),
+            room: "default".to_string(),
+            relay: "127.0.0.1:8080".to_string(),
+            local_device_id: "local".to_string(),
+            local_device_name: Some("me@host".to_string()),
+            remote_device_id: remote.map(str::to_string),
+            remote_device_name: None,
+            kind: "file".to_string(),
+            mime: Some("text/plain".to_string()),
+            name: name.map(str::to_string),
+            bytes: 42,
+            sha256: Some("ab".repeat(32)),
+            orig_paths: None,
+            outcome: None,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn csv_export_escapes_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+
+        let out = export_events(&[ev("send", Some("x, \"y\".txt"), None)], ExportFormat::Csv).unwrap();
+        let mut lines = out.split("\r\n");
+        assert_eq!(lines.next().unwrap(), CSV_HEADER.join(","));
+        let row = lines.next().unwrap();
+        assert!(row.starts_with("1700000000000,send,default,127.0.0.1:8080,local,me@host,,,file,"));
+        assert!(row.contains(",\"x, \"\"y\"\".txt\",42,"), "{row}");
+        assert_eq!(lines.next(), Some(""));
+    }
+
+    #[test]
+    fn json_export_round_trips_events_from_log() {
+        let tmp = tempfile::tempdir().unwrap();
+        let log = tmp.path().join("history.jsonl");
+        let events = [ev("send", Some("a.txt"), None), ev("recv", None, Some("peer"))];
+        let mut text = String::new();
+        for e in &events {
+            text.push_str(&serde_json::to_string(e).unwrap());
+            text.push('\n');
+        }
+        text.push_str("{truncated\n");
+        std::fs::write(&log, text).unwrap();
+
+        let out = tmp.path().join("export/history.json");
+        assert_eq!(export_history(&log, ExportFormat::Json, &out).unwrap(), 2);
+        let v: serde_json::Value = serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+        let arr = v.as_array().unwrap();
+        assert_eq!(arr.len(), 2);
+        assert_eq!(arr[0]["name"], "a.txt");
+        assert_eq!(arr[1]["dir"], "recv");
+        assert_eq!(arr[1]["remote_device_id"], "peer");
+        // Absent optionals are omitted, not null.
+        assert!(arr[0].get("remote_device_name").is_none());
+        assert_eq!(arr[0]["bytes"], 42);
+
+        assert!(parse_export_format("xml").is_err());
+    }
+
+    #[test]
+    fn received_orig_paths_are_recorded() {
+        let mut msg = Message::new_file("peer", "default", "a.tar", "application/x-tar", vec![1]);
+        msg.orig_paths = Some(vec!["/home/alice/a.txt".to_string(), "/home/alice/b".to_string()]);
+        let received = Message::try_from_bytes(&msg.to_bytes()).unwrap();
+
+        let e = recv_event("local", None, "default", "127.0.0.1:8080", &received);
+        assert_eq!(e.orig_paths, msg.orig_paths);
+        let v: serde_json::Value = serde_json::to_value(&e).unwrap();
+        assert_eq!(v["orig_paths"][1], "/home/alice/b");
+
+        // Senders that opted out (or predate the field) leave no key behind.
+        msg.orig_paths = None;
+        let v = serde_json::to_value(recv_event("local", None, "default", "r", &msg)).unwrap();
+        assert!(v.get("orig_paths").is_none());
+    }
 }
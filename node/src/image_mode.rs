@@ -4,6 +4,8 @@ pub enum ImageMode {
     ForcePng,
     MultiMime,
     SpoofPng,
+    /// Like force-png, but WebP: much smaller for photos (lossless, so nothing is lost).
+    ForceWebp,
 }
 
 pub fn parse_image_mode(s: &str) -> anyhow::Result<ImageMode> {
@@ -12,8 +14,9 @@ pub fn parse_image_mode(s: &str) -> anyhow::Result<ImageMode> {
         "force-png" => Ok(ImageMode::ForcePng),
         "multi" | "multi-mime" => Ok(ImageMode::MultiMime),
         "spoof-png" | "fake-png" => Ok(ImageMode::SpoofPng),
+        "force-webp" => Ok(ImageMode::ForceWebp),
         other => anyhow::bail!(
-            "invalid --image-mode {}, expected force-png|multi|passthrough|spoof-png",
+            "invalid --image-mode {}, expected force-png|force-webp|multi|passthrough|spoof-png",
             other
         ),
     }
@@ -25,5 +28,6 @@ pub fn image_mode_as_cli_arg(m: ImageMode) -> &'static str {
         ImageMode::ForcePng => "force-png",
         ImageMode::MultiMime => "multi",
         ImageMode::SpoofPng => "spoof-png",
+        ImageMode::ForceWebp => "force-webp",
     }
 }
@@ -0,0 +1,244 @@
+//! Per-command instance locks under the state dir, and spotting the same command already
+//! running for another room.
+//!
+//! Each `wl-watch`/`wl-apply` holds `<name>_room=<room>_relay=<relay>.lock` while it runs, so a
+//! second one for the same room fails fast. A different room gets a different lock, which used
+//! to let a second watcher start silently next to the first; [`ensure_no_other_rooms`] refuses
+//! that unless `--allow-other-rooms` is set.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+
+use crate::paths::safe_for_filename;
+
+/// Env fallback for `--allow-other-rooms`.
+pub const ALLOW_OTHER_ROOMS_ENV: &str = "MCR_ALLOW_OTHER_ROOMS";
+
+static ALLOW_OTHER_ROOMS: OnceLock<bool> = OnceLock::new();
+
+fn allow_other_rooms_from_env() -> bool {
+    matches!(
+        std::env::var(ALLOW_OTHER_ROOMS_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Let a command run next to the same command in another room
+/// (process-wide, set once at startup).
+pub fn set_allow_other_rooms(on: bool) {
+    let _ = ALLOW_OTHER_ROOMS.set(on || allow_other_rooms_from_env());
+}
+
+pub fn allow_other_rooms() -> bool {
+    *ALLOW_OTHER_ROOMS.get_or_init(allow_other_rooms_from_env)
+}
+
+fn lock_prefix(name: &str) -> String {
+    format!("{}_room=", name)
+}
+
+fn lock_file_name(name: &str, room: &str, relay: &str) -> String {
+    format!(
+        "{}{}_relay={}.lock",
+        lock_prefix(name),
+        safe_for_filename(room),
+        safe_for_filename(relay)
+    )
+}
+
+/// Take the instance lock for `name` in `room` on `relay`; fails if another process holds it.
+///
+/// The holder writes its room, relay and pid into the file for [`active_instances`].
+#[cfg(unix)]
+pub fn acquire_instance_lock(
+    state_dir: &Path,
+    name: &str,
+    room: &str,
+    relay: &str,
+) -> anyhow::Result<File> {
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let lock_path = state_dir.join(lock_file_name(name, room, relay));
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("open lock {}", lock_path.display()))?;
+
+    let rc = unsafe { libc::flock(f.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if rc != 0 {
+        let e = std::io::Error::last_os_error();
+        // EWOULDBLOCK means another instance holds the lock.
+        if e.raw_os_error() == Some(libc::EWOULDBLOCK) {
+            anyhow::bail!(
+                "{} already running (lock busy): {}",
+                name,
+                lock_path.display()
+            );
+        }
+        return Err(anyhow::Error::new(e)).context("flock");
+    }
+
+    // Best effort: the lock itself is what matters.
+    let owner = format!(
+        "room={}\nrelay={}\npid={}\n",
+        room,
+        relay,
+        std::process::id()
+    );
+    let _ = f.set_len(0).and_then(|_| f.write_all(owner.as_bytes()));
+    Ok(f)
+}
+
+#[cfg(not(unix))]
+pub fn acquire_instance_lock(
+    _state_dir: &Path,
+    _name: &str,
+    _room: &str,
+    _relay: &str,
+) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// A running instance found through its lock file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveInstance {
+    pub room: String,
+    pub relay: String,
+    pub pid: Option<u32>,
+    pub lock_path: PathBuf,
+}
+
+fn parse_owner(text: &str) -> (Option<String>, Option<String>, Option<u32>) {
+    let (mut room, mut relay, mut pid) = (None, None, None);
+    for line in text.lines() {
+        match line.split_once('=') {
+            Some(("room", v)) => room = Some(v.to_string()),
+            Some(("relay", v)) => relay = Some(v.to_string()),
+            Some(("pid", v)) => pid = v.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    (room, relay, pid)
+}
+
+/// Whether some process holds the lock at `path` (stale files from exited runs don't count).
+#[cfg(unix)]
+fn is_held(path: &Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(f) = File::open(path) else {
+        return false;
+    };
+    let rc = unsafe { libc::flock(f.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) };
+    // Our probe lock is released when `f` is dropped.
+    rc != 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EWOULDBLOCK)
+}
+
+#[cfg(not(unix))]
+fn is_held(_path: &Path) -> bool {
+    false
+}
+
+/// Running instances of `name` in this state dir (i.e. on this device), sorted by room.
+pub fn active_instances(state_dir: &Path, name: &str) -> Vec<ActiveInstance> {
+    let prefix = lock_prefix(name);
+    let Ok(entries) = std::fs::read_dir(state_dir) else {
+        return Vec::new();
+    };
+    let mut out: Vec<ActiveInstance> = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(rest) = file_name
+            .to_str()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|n| n.strip_suffix(".lock"))
+        else {
+            continue;
+        };
+        let path = entry.path();
+        if !is_held(&path) {
+            continue;
+        }
+        // Locks taken by older versions are empty; their (sanitized) name still tells the room.
+        let (room, relay, pid) = parse_owner(&std::fs::read_to_string(&path).unwrap_or_default());
+        let (name_room, name_relay) = rest.split_once("_relay=").unwrap_or((rest, ""));
+        out.push(ActiveInstance {
+            room: room.unwrap_or_else(|| name_room.to_string()),
+            relay: relay.unwrap_or_else(|| name_relay.to_string()),
+            pid,
+            lock_path: path,
+        });
+    }
+    out.sort_by(|a, b| (&a.room, &a.relay).cmp(&(&b.room, &b.relay)));
+    out
+}
+
+/// Refuse to start `name` for `room` on `relay` while it already runs for another room or relay
+/// (only warn with `--allow-other-rooms`).
+pub fn ensure_no_other_rooms(
+    state_dir: &Path,
+    name: &str,
+    room: &str,
+    relay: &str,
+) -> anyhow::Result<()> {
+    let others: Vec<String> = active_instances(state_dir, name)
+        .into_iter()
+        .filter(|i| i.room != room || i.relay != relay)
+        .map(|i| match i.pid {
+            Some(pid) => format!("room={} relay={} (pid {})", i.room, i.relay, pid),
+            None => format!("room={} relay={}", i.room, i.relay),
+        })
+        .collect();
+    if others.is_empty() {
+        return Ok(());
+    }
+    if allow_other_rooms() {
+        log::warn!("{} is also running for {}", name, others.join(", "));
+        return Ok(());
+    }
+    anyhow::bail!(
+        "{} is already running for {}; stop it first or pass --allow-other-rooms",
+        name,
+        others.join(", ")
+    )
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_locks_are_listed_with_their_room_and_relay() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+
+        let _work = acquire_instance_lock(dir, "wl-watch", "work/team", "10.0.0.2:8080").unwrap();
+        let _home = acquire_instance_lock(dir, "wl-watch", "home", "127.0.0.1:8080").unwrap();
+        let _apply = acquire_instance_lock(dir, "wl-apply", "other", "127.0.0.1:8080").unwrap();
+        drop(acquire_instance_lock(dir, "wl-watch", "stale", "127.0.0.1:8080").unwrap());
+        std::fs::write(dir.join("wl-watch-dry-run_room=x_relay=y.lock"), "").unwrap();
+
+        let found = active_instances(dir, "wl-watch");
+        let pairs: Vec<(&str, &str)> = found
+            .iter()
+            .map(|i| (i.room.as_str(), i.relay.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [("home", "127.0.0.1:8080"), ("work/team", "10.0.0.2:8080")]
+        );
+        assert!(found.iter().all(|i| i.pid == Some(std::process::id())));
+
+        // Same room and relay is the instance lock's job; another room is refused here.
+        assert!(ensure_no_other_rooms(dir, "wl-apply", "other", "127.0.0.1:8080").is_ok());
+        let err = ensure_no_other_rooms(dir, "wl-apply", "home", "127.0.0.1:8080").unwrap_err();
+        assert!(format!("{err:#}").contains("room=other"), "{err:#}");
+    }
+}
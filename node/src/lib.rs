@@ -3,14 +3,31 @@
 // Keeping these in a library module allows us to split the former monolithic
 // `main.rs` into smaller, testable units.
 
+pub mod channel;
+pub mod client;
 pub mod clipboard;
+pub mod content_filter;
 pub mod consts;
+pub mod device;
+pub mod doctor;
+pub mod events;
+pub mod extra_mime;
 pub mod hash;
 pub mod history;
 pub mod image_mode;
+pub mod instances;
+pub mod lock;
 pub mod net;
 pub mod paths;
+pub mod poll;
+pub mod publish;
+pub mod receive;
+pub mod reload;
+pub mod resend;
+pub mod rich_text;
+pub mod room;
 pub mod suppress;
+pub mod throttle;
 #[path = "transfer/file.rs"]
 pub mod transfer_file;
 
@@ -0,0 +1,205 @@
+//! `--pause-on-lock`: no clipboard sync while the session is locked (shared machines).
+//!
+//! A monitor polls the session's lock state and keeps a marker in the state dir while it is
+//! locked; [`crate::suppress::is_paused`] honours it, so wl-watch, its hooks and wl-apply all
+//! go idle like under `pause`, and pick up again on unlock. A manual `pause` is left alone.
+//!
+//! The marker is per state dir, not per process: one service started with `--pause-on-lock`
+//! pauses every node command sharing its state dir, flag or not.
+
+use anyhow::Context;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+/// Env fallback for `--pause-on-lock` (e.g. from the systemd EnvironmentFile).
+pub const PAUSE_ON_LOCK_ENV: &str = "MCR_PAUSE_ON_LOCK";
+
+/// How often the lock state is checked.
+pub const LOCK_POLL: Duration = Duration::from_secs(2);
+
+/// A marker not refreshed for this long is ignored, so sync doesn't stay paused when the
+/// monitor dies while the session is locked.
+const LOCK_MARKER_TTL: Duration = Duration::from_secs(10);
+
+pub fn pause_on_lock_enabled(flag: bool) -> bool {
+    flag || matches!(
+        std::env::var(PAUSE_ON_LOCK_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+pub fn locked_path(state_dir: &Path) -> PathBuf {
+    state_dir.join("LOCKED")
+}
+
+/// Whether a lock monitor currently reports the session as locked.
+pub async fn is_locked(state_dir: &Path) -> bool {
+    let Ok(s) = tokio::fs::read_to_string(locked_path(state_dir)).await else {
+        return false;
+    };
+    let since: u64 = s.trim().parse().unwrap_or(0);
+    utils::now_ms().saturating_sub(since) <= LOCK_MARKER_TTL.as_millis() as u64
+}
+
+/// Where the lock state comes from, so the monitor can run against a fake in tests.
+pub trait LockProbe {
+    fn locked(&self) -> impl Future<Output = anyhow::Result<bool>>;
+}
+
+/// logind's `LockedHint` for a session, read with `loginctl`.
+pub struct Loginctl {
+    session: String,
+}
+
+impl Loginctl {
+    /// The session this process runs in (`$XDG_SESSION_ID`, else logind's own pick).
+    pub fn current() -> Self {
+        let session = std::env::var("XDG_SESSION_ID")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| "auto".to_string());
+        Self { session }
+    }
+}
+
+impl LockProbe for Loginctl {
+    async fn locked(&self) -> anyhow::Result<bool> {
+        let mut cmd = Command::new("loginctl");
+        cmd.args(["show-session", &self.session, "-p", "LockedHint", "--value"])
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        let out = tokio::time::timeout(LOCK_POLL, cmd.output())
+            .await
+            .context("loginctl timed out")?
+            .context("spawn loginctl")?;
+        anyhow::ensure!(
+            out.status.success(),
+            "loginctl show-session {}: {}",
+            self.session,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+        match String::from_utf8_lossy(&out.stdout).trim() {
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            other => anyhow::bail!("unexpected LockedHint {other:?}"),
+        }
+    }
+}
+
+/// Lock state as last seen by the monitor.
+#[derive(Debug, Default)]
+pub struct LockMonitor {
+    locked: bool,
+    /// The probe is failing (warned once, not on every check).
+    failing: bool,
+}
+
+impl LockMonitor {
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// One check: ask `probe` and update the marker under `state_dir`. While the probe fails
+    /// the marker is left alone, so a locked session stays paused until it expires.
+    pub async fn poll<P: LockProbe>(&mut self, probe: &P, state_dir: &Path) {
+        let locked = match probe.locked().await {
+            Ok(l) => l,
+            Err(e) => {
+                if !std::mem::replace(&mut self.failing, true) {
+                    log::warn!("pause-on-lock: can't read the lock state: {e:#}");
+                }
+                return;
+            }
+        };
+        self.failing = false;
+        let p = locked_path(state_dir);
+        let res = if locked {
+            // Refreshed on every check (see LOCK_MARKER_TTL).
+            tokio::fs::write(&p, format!("{}\n", utils::now_ms())).await
+        } else {
+            match tokio::fs::remove_file(&p).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        };
+        if let Err(e) = res {
+            log::warn!("pause-on-lock: update {}: {e}", p.display());
+        }
+        if locked != std::mem::replace(&mut self.locked, locked) {
+            if locked {
+                log::info!("pause-on-lock: session locked; sync paused");
+            } else {
+                log::info!("pause-on-lock: session unlocked; sync resumed");
+            }
+        }
+    }
+}
+
+/// Keep the marker in step with the lock state, forever (spawn it next to a service).
+pub async fn pause_on_lock<P: LockProbe>(probe: P, state_dir: PathBuf) {
+    let mut tick = tokio::time::interval(LOCK_POLL);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut monitor = LockMonitor::default();
+    loop {
+        tick.tick().await;
+        monitor.poll(&probe, &state_dir).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::suppress::is_paused;
+
+    /// Answers from a script, one per check.
+    struct FakeLock(Mutex<Vec<anyhow::Result<bool>>>);
+
+    impl LockProbe for FakeLock {
+        async fn locked(&self) -> anyhow::Result<bool> {
+            self.0.lock().unwrap().remove(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn lock_state_pauses_and_resumes_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let st = dir.path();
+        let probe = FakeLock(Mutex::new(vec![
+            Ok(false),
+            Ok(true),
+            Err(anyhow::anyhow!("logind gone")),
+            Ok(true),
+            Ok(false),
+        ]));
+
+        let mut monitor = LockMonitor::default();
+        monitor.poll(&probe, st).await;
+        assert!(!monitor.locked());
+        assert!(!is_paused(st, "r").await);
+
+        monitor.poll(&probe, st).await;
+        assert!(monitor.locked());
+        assert!(is_paused(st, "r").await);
+        assert!(is_paused(st, "other").await, "locking pauses every room");
+
+        // A failed check leaves the marker as it was.
+        monitor.poll(&probe, st).await;
+        assert!(monitor.locked());
+        assert!(is_locked(st).await);
+
+        monitor.poll(&probe, st).await;
+        monitor.poll(&probe, st).await;
+        assert!(!monitor.locked());
+        assert!(!is_paused(st, "r").await);
+
+        // A marker nobody refreshes any more doesn't keep sync paused.
+        std::fs::write(locked_path(st), "1\n").unwrap();
+        assert!(!is_locked(st).await);
+    }
+}
@@ -1,23 +1,48 @@
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::io::AsyncReadExt;
 use tokio::process::Command;
-use std::io;
 
-use utils::{Kind, Message};
+use utils::{Kind, Message, MAX_FRAME_BYTES};
+use utils::probe::ping_relay;
+use utils::uri::parse_connect_uri;
+use node::clipboard::{WlPaste, WATCH_MIMES_ENV};
 use node::consts::{
     GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME,
 };
-use node::hash::sha256_hex;
-use node::history::{record_recv, record_send};
-use node::image_mode::parse_image_mode;
-use node::net::{connect, send_frame, send_join};
-use node::paths::{default_state_dir, safe_for_filename};
-use node::transfer_file::send_file;
-use node::transfer_image::send_image;
+use node::client::{Client, Device};
+use node::content_filter::set_content_filters;
+use node::device::{local_device_id, resolve_device_name, sender_name, set_sender_name};
+use node::doctor::{ensure_bin, failures, run_checks};
+use node::events::{emit_event, json_output, parse_output_format, set_output_format};
+use node::hash::{parse_hash_algo, set_hash_algo};
+use node::history::{
+    export_history, parse_export_format, record_event, record_recv, record_send,
+    send_outcome_event, Outcome,
+};
+use node::image_mode::{parse_image_mode, ImageMode};
+use node::instances::{acquire_instance_lock, set_allow_other_rooms};
+use node::lock::{pause_on_lock, pause_on_lock_enabled, Loginctl};
+use node::net::{
+    connect_in, pull_scrollback, read_frame_body, send_frame, send_join, send_join_hello,
+    Heartbeat, ACK_WAIT, REPLAY_WAIT,
+};
+use node::poll::{PollIntervals, FILE_RESEND_COOLDOWN_MS};
+use node::paths::{
+    default_state_dir, received_dir, safe_for_filename, systemd_env_path, DataDirs,
+};
+use node::publish::{publish_current, PublishCtx, PublishLimits};
+use node::resend::{keep_text_enabled, prepare_resend, Resend, ResendEntry};
+use node::room::{request_room_switch, room_control_path};
+use node::suppress::{disabled_path, ensure_enabled, set_disabled, set_paused, Staleness};
+use node::transfer_file::{
+    parse_bundle_expose, parse_bundle_mtime, send_file, set_bundle_mtime, set_path_metadata,
+    BundleExpose, MAX_LISTED_ITEMS,
+};
+use node::transfer_image::{parse_image_priority, send_image};
 use node::x11_sync::{x11_hook_apply_wayland_to_x11, x11_sync_service, X11SyncOpts};
 
 #[path = "cmd/wl_apply.rs"]
@@ -31,6 +56,7 @@ mod cmd_wl_watch;
 #[derive(Clone, Debug)]
 struct Ctx {
     state_dir: PathBuf,
+    data: DataDirs,
     device_id: String,
     device_name: String,
 }
@@ -39,6 +65,7 @@ struct Ctx {
 #[command(name = "multicliprelay-node")]
 struct Cli {
     /// Directory for local state (device id, suppress markers).
+    /// Falls back to env MCR_STATE_DIR.
     #[arg(long, global = true)]
     state_dir: Option<PathBuf>,
 
@@ -46,91 +73,249 @@ struct Cli {
     #[arg(long, global = true)]
     device_id: Option<String>,
 
-    /// Human-friendly device name (defaults to env MCR_NAME or USER@HOSTNAME).
+    /// Human-friendly device name shown to peers (sent as `sender_name`).
+    /// Falls back to env MCR_NAME, then the hostname.
+    #[arg(long = "device-name", visible_alias = "name", global = true)]
+    device_name: Option<String>,
+
+    /// Limit upload bandwidth for all sends of this process (kilobits/s; 0 = unlimited).
+    /// Falls back to env MCR_MAX_UPLOAD_KBPS.
+    #[arg(long, global = true)]
+    max_upload_kbps: Option<u64>,
+
+    /// Publish at most this many clipboard changes per second (0 = unlimited); a burst
+    /// coalesces to the latest copy. Falls back to env MCR_MAX_EVENTS_PER_SEC.
+    #[arg(long, global = true)]
+    max_events_per_sec: Option<u32>,
+
+    /// File bundle mtimes: preserve (default) or zero (stable hashes for unchanged content).
+    /// Falls back to env MCR_BUNDLE_MTIME.
+    #[arg(long, global = true)]
+    bundle_mtime: Option<String>,
+
+    /// Content fingerprint for dedupe and echo suppression: sha256 (default) or blake3
+    /// (faster on large payloads; peers may differ). Falls back to env MCR_HASH_ALGO.
+    #[arg(long, global = true)]
+    hash_algo: Option<String>,
+
+    /// In force-png mode, keep animated GIFs as image/gif instead of flattening to one frame.
+    /// Falls back to env MCR_PRESERVE_ANIMATION=1.
+    #[arg(long, global = true)]
+    preserve_animation: bool,
+
+    /// Multi-mime image preference, most preferred first (e.g. image/png,image/jpeg).
+    /// Falls back to env MCR_IMAGE_PRIORITY, then svg,jpeg,webp,gif,png.
+    #[arg(long, global = true)]
+    image_priority: Option<String>,
+
+    /// Where received files and image previews are stored (e.g. ~/Downloads/multicliprelay).
+    /// Falls back to env MCR_RECEIVED_DIR, then the data dir.
+    #[arg(long, global = true)]
+    received_dir: Option<String>,
+
+    /// Pipe outgoing payloads through this shell command (stdin -> stdout) before sending;
+    /// a nonzero exit drops the payload. Text only unless --filter-all-kinds.
+    /// Falls back to env MCR_SEND_FILTER.
+    #[arg(long, global = true)]
+    send_filter: Option<String>,
+
+    /// Like --send-filter, for incoming payloads before they are applied.
+    /// Falls back to env MCR_APPLY_FILTER.
+    #[arg(long, global = true)]
+    apply_filter: Option<String>,
+
+    /// Also run the filters on images and files (env MCR_FILTER_ALL_KINDS=1).
+    #[arg(long, global = true)]
+    filter_all_kinds: bool,
+
+    /// Read the Wayland clipboard over the protocol instead of spawning wl-paste
+    /// (falls back to wl-paste when the compositor lacks data-control). Env MCR_NATIVE_CLIPBOARD=1.
+    #[arg(long, global = true)]
+    native_clipboard: bool,
+
+    /// Give up on a clipboard read or write that takes longer than this (ms; 0 = wait forever),
+    /// so a stalled clipboard owner can't freeze a watcher. Falls back to env
+    /// MCR_CLIPBOARD_TIMEOUT_MS, then 5000.
     #[arg(long, global = true)]
-    name: Option<String>,
+    clipboard_timeout_ms: Option<u64>,
+
+    /// Keepalive interval for long-lived relay connections (seconds; 0 = off).
+    /// Falls back to env MCR_HEARTBEAT_SECS, then 20.
+    #[arg(long, global = true)]
+    heartbeat_secs: Option<u64>,
+
+    /// Give up connecting to the relay after this long (ms; 0 = leave it to the OS), so an
+    /// unreachable relay fails fast instead of hanging a send or hook. Falls back to env
+    /// MCR_CONNECT_TIMEOUT_MS, then 5000.
+    #[arg(long, global = true)]
+    connect_timeout_ms: Option<u64>,
+
+    /// Sync text only: skip the file/image watchers, sends and applies (lighter on CPU and
+    /// subprocesses). Falls back to env MCR_TEXT_ONLY=1.
+    #[arg(long, global = true)]
+    text_only: bool,
+
+    /// Keep sent and received texts (up to 256 KiB, for a day) so history can re-send them.
+    /// Off by default: they are stored in plaintext. Falls back to env MCR_KEEP_TEXT=1.
+    #[arg(long, global = true)]
+    keep_text: bool,
+
+    /// Send frames with a crc32 trailer (MCR3) so receivers drop corrupted payloads. Only
+    /// for rooms where every node and the relay understand MCR3; older ones can't decode them.
+    /// Falls back to env MCR_FRAME_CRC=1.
+    #[arg(long, global = true)]
+    frame_crc: bool,
+
+    /// Ask the relay to deflate the connection (both ways); plain frames when it can't.
+    /// Falls back to env MCR_COMPRESS=1.
+    #[arg(long, global = true)]
+    compress: bool,
+
+    /// Also sync this app-specific clipboard type as opaque bytes (repeatable, or comma-separated),
+    /// e.g. application/x-inkscape-svg. Re-offered only by peers listing it too.
+    /// Falls back to env MCR_EXTRA_MIMES.
+    #[arg(long = "extra-mime", global = true)]
+    extra_mime: Vec<String>,
+
+    /// Send into this channel of the room (and receive it): only peers subscribed to it get
+    /// what this node sends. Falls back to env MCR_CHANNEL.
+    #[arg(long, global = true)]
+    channel: Option<String>,
+
+    /// Also receive this channel of the room (repeatable, or comma-separated). Messages sent
+    /// without a channel are always received. Falls back to env MCR_SUBSCRIBE.
+    #[arg(long, global = true)]
+    subscribe: Vec<String>,
+
+    /// Stdout format for listen/wl-apply/wl-watch: text (default) or json, one event object
+    /// per line (kind, device_id, sender_name, mime, size, sha256). Falls back to env MCR_OUTPUT.
+    #[arg(long, global = true)]
+    output: Option<String>,
+
+    /// Start wl-watch/wl-apply even while this device runs them for another room or relay
+    /// (normally refused), e.g. to bridge two rooms. Each service then stays in its own room:
+    /// switch-room requests are ignored. Falls back to env MCR_ALLOW_OTHER_ROOMS=1.
+    #[arg(long, visible_alias = "allow-multi", global = true)]
+    allow_other_rooms: bool,
+
+    /// Don't tell peers where sent files live on this device (their absolute paths are
+    /// otherwise shown in the receiver's history). Falls back to env MCR_NO_PATH_METADATA=1.
+    #[arg(long, global = true)]
+    no_path_metadata: bool,
 
     #[command(subcommand)]
     cmd: Commands,
 }
 
-fn default_device_name() -> String {
-    if let Ok(v) = std::env::var("MCR_NAME") {
-        let v = v.trim().to_string();
-        if !v.is_empty() {
-            return v;
-        }
-    }
-
-    let user = std::env::var("USER")
-        .ok()
-        .or_else(|| std::env::var("LOGNAME").ok())
-        .unwrap_or_else(|| "user".to_string());
-    let host = std::env::var("HOSTNAME").ok().unwrap_or_default();
-    if host.trim().is_empty() {
-        user
-    } else {
-        format!("{}@{}", user, host)
-    }
+#[derive(Subcommand)]
+enum HistoryCommands {
+    /// Export history metadata (never payloads) as JSON or CSV.
+    Export {
+        /// json|csv
+        #[arg(long, default_value = "json")]
+        format: String,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Fetch and print the relay's recent messages for a room (needs relay `--scrollback`).
+    Pull {
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
+        room: String,
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
+        relay: String,
+    },
 }
 
+// Every `--room`/`--relay` falls back to env MCR_ROOM/MCR_RELAY (the same variables the
+// wl-watch hook and the systemd env file use), so they can be configured once.
 #[derive(Subcommand)]
 enum Commands {
     Listen {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
     },
     SendText {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
         #[arg(long)]
         text: String,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
+        /// Don't exit until a peer's wl-apply acknowledges it applied the text (fails on timeout).
+        #[arg(long)]
+        wait_ack: bool,
+        /// Seconds --wait-ack waits for the acknowledgement.
+        #[arg(long, default_value_t = ACK_WAIT.as_secs())]
+        ack_timeout: u64,
     },
     SendImage {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
         /// Path to an image file (png/jpeg/webp/gif recommended)
         #[arg(long)]
         file: PathBuf,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
         /// Max bytes allowed to send
         #[arg(long, default_value_t = 20 * 1024 * 1024)]
         max_bytes: usize,
-        /// Image mode: passthrough keeps original mime; force-png converts and sends image/png.
+        /// Image mode: passthrough keeps original mime; force-png/force-webp convert and send image/png or lossless image/webp.
         #[arg(long, default_value = "force-png")]
         image_mode: String,
+        /// Send as this image type instead of the detected one (e.g. image/webp).
+        #[arg(long)]
+        mime: Option<String>,
     },
 
     SendFile {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
         /// Path to any file
         #[arg(long)]
         file: PathBuf,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
         /// Max bytes allowed to send
         #[arg(long, default_value_t = 20 * 1024 * 1024)]
         max_file_bytes: usize,
+        /// Send the file as-is with this MIME type instead of as a tar bundle
+        /// (e.g. text/plain for a config file).
+        #[arg(long)]
+        mime: Option<String>,
+        /// Don't exit until a peer's wl-apply acknowledges it applied the file (fails on timeout).
+        #[arg(long)]
+        wait_ack: bool,
+        /// Seconds --wait-ack waits for the acknowledgement.
+        #[arg(long, default_value_t = ACK_WAIT.as_secs())]
+        ack_timeout: u64,
     },
 
     /// Watch local Wayland clipboard (text + image/png) and publish to relay.
     WlWatch {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
-        /// Watch mode: "watch" uses wl-paste --watch (event-driven), "poll" uses polling.
+        /// Watch mode: "watch" uses wl-paste --watch (event-driven), "poll" uses polling,
+        /// "auto" probes whether wl-paste --watch fires here and falls back to polling.
         #[arg(long, default_value = "watch")]
         mode: String,
         /// Poll interval (ms), only used when mode=poll.
         #[arg(long, default_value_t = 200)]
         interval_ms: u64,
+        /// Text poll interval (ms) for mode=poll; defaults to --interval-ms.
+        #[arg(long)]
+        text_poll_ms: Option<u64>,
+        /// File list, extra-mime and image poll interval (ms) for mode=poll; these reads are
+        /// larger, so a slower cadence saves CPU. Defaults to --interval-ms.
+        #[arg(long)]
+        media_poll_ms: Option<u64>,
+        /// Don't send the same file selection again within this many ms while it stays on the
+        /// clipboard (mode=poll; 0 = send it again on every media poll).
+        #[arg(long, default_value_t = FILE_RESEND_COOLDOWN_MS)]
+        file_resend_cooldown_ms: u64,
         #[arg(long, default_value_t = 1 * 1024 * 1024)]
         max_text_bytes: usize,
         #[arg(long, default_value_t = 20 * 1024 * 1024)]
@@ -138,28 +323,99 @@ enum Commands {
         /// Max bytes allowed to send for file clipboard (text/uri-list)
         #[arg(long, default_value_t = 20 * 1024 * 1024)]
         max_file_bytes: usize,
-        /// Image mode: passthrough keeps original mime; force-png converts and sends image/png.
+        /// Image mode: passthrough keeps original mime; force-png/force-webp convert and send image/png or lossless image/webp.
         #[arg(long, default_value = "force-png")]
         image_mode: String,
+        /// Only run wl-paste watchers for these types (comma-separated, `type/*` allowed,
+        /// e.g. "text/*" for text-only; "none" for nothing); default watches every supported
+        /// type. Poll mode reads only these types. Falls back to env MCR_WATCH_MIMES.
+        #[arg(long, value_delimiter = ',')]
+        watch_mimes: Vec<String>,
+        /// Don't connect or send: print what each clipboard change would publish
+        /// (kind, mime, size, sha, bundle entries).
+        #[arg(long)]
+        dry_run: bool,
+        /// Pause syncing while the session is locked (logind LockedHint via loginctl),
+        /// resuming on unlock. The lock marker lives in the state dir, so every node sharing
+        /// it (wl-apply, hooks) pauses too. Falls back to env MCR_PAUSE_ON_LOCK=1.
+        #[arg(long)]
+        pause_on_lock: bool,
     },
 
     /// Apply incoming events to local Wayland clipboard (text + image/png).
     WlApply {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
         /// Image mode: passthrough writes original mime; force-png converts and writes image/png.
         #[arg(long, default_value = "force-png")]
         image_mode: String,
+        /// Received file bundles: wrapper (always one root folder), flat (top-level entries),
+        /// auto (one folder unless it was a multi-file selection).
+        #[arg(long, default_value = "auto")]
+        bundle_expose: String,
+        /// Most top-level entries of a received bundle put on the clipboard as separate items;
+        /// the rest stay in the received dir (a warning says so).
+        #[arg(long, default_value_t = MAX_LISTED_ITEMS)]
+        max_listed_items: usize,
+        /// Drop incoming messages older than this (ms, by sender timestamp); 0 = apply all.
+        #[arg(long, default_value_t = 0)]
+        max_age_ms: u64,
+        /// Clock difference tolerated between devices on top of --max-age-ms.
+        #[arg(long, default_value_t = 5000)]
+        clock_skew_ms: u64,
+        /// Also put received text on the primary selection (middle-click paste); the default.
+        #[arg(long, overrides_with = "no_apply_to_primary")]
+        apply_to_primary: bool,
+        /// Write received text to the regular clipboard only.
+        /// Falls back to env MCR_APPLY_TO_PRIMARY=0.
+        #[arg(long)]
+        no_apply_to_primary: bool,
+        /// Pause syncing while the session is locked (logind LockedHint via loginctl),
+        /// resuming on unlock. The lock marker lives in the state dir, so every node sharing
+        /// it (wl-watch, hooks) pauses too. Falls back to env MCR_PAUSE_ON_LOCK=1.
+        #[arg(long)]
+        pause_on_lock: bool,
+    },
+
+    /// Write received files, bundles and images to a directory without touching the clipboard
+    /// (headless boxes, archiving). Text is logged only.
+    Receive {
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
+        room: String,
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
+        relay: String,
+        /// Where to keep what arrives (same layout as wl-apply's received dir).
+        #[arg(long)]
+        dir: PathBuf,
+    },
+
+    /// Publish the current Wayland clipboard once and exit (for keybinds and scripts).
+    ///
+    /// Picks the best offered type like wl-watch does; respects pause and loop suppression.
+    PublishCurrent {
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
+        room: String,
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
+        relay: String,
+        #[arg(long, default_value_t = 1 * 1024 * 1024)]
+        max_text_bytes: usize,
+        #[arg(long, default_value_t = 20 * 1024 * 1024)]
+        max_image_bytes: usize,
+        #[arg(long, default_value_t = 20 * 1024 * 1024)]
+        max_file_bytes: usize,
+        /// Image mode: passthrough keeps original mime; force-png/force-webp convert and send image/png or lossless image/webp.
+        #[arg(long, default_value = "force-png")]
+        image_mode: String,
     },
 
     /// Internal: invoked by wl-paste --watch to publish current clipboard content.
     #[command(hide = true)]
     WlPublishCurrent {
-        #[arg(long, default_value = "default")]
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
         room: String,
-        #[arg(long, default_value = "127.0.0.1:8080")]
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
         relay: String,
         /// "text" or "image/png"
         #[arg(long)]
@@ -170,17 +426,101 @@ enum Commands {
         max_image_bytes: usize,
         #[arg(long, default_value_t = 20 * 1024 * 1024)]
         max_file_bytes: usize,
-        /// Image mode: passthrough keeps original mime; force-png converts and sends image/png.
+        /// Image mode: passthrough keeps original mime; force-png/force-webp convert and send image/png or lossless image/webp.
         #[arg(long, default_value = "force-png")]
         image_mode: String,
     },
 
+    /// Parse an onboarding URI (mcr://relay=...&room=..., e.g. scanned from the UI QR code).
+    ///
+    /// Prints the relay/room; with --write-env also overrides them in the systemd env file.
+    ConnectUri {
+        uri: String,
+        /// Write MULTICLIPRELAY_RELAY / MULTICLIPRELAY_ROOM into multicliprelay.env.
+        #[arg(long)]
+        write_env: bool,
+    },
+
+    /// Switch running wl-apply/wl-watch (same --state-dir) to another room without restarting.
+    SwitchRoom {
+        room: String,
+    },
+
+    /// Pause sync for a room (running wl-watch/wl-apply stay connected but idle).
+    Pause {
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
+        room: String,
+    },
+
+    /// Resume sync for a room paused with `pause`.
+    Resume {
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
+        room: String,
+    },
+
+    /// Kill switch: stop all sending and applying on this device, in every room, until `enable`
+    /// (running services stay connected). Stronger than `pause`.
+    Disable,
+
+    /// Undo `disable`.
+    Enable,
+
+    /// Push a history item to the room again, using its payload kept in the received dir.
+    Resend {
+        #[arg(long, env = "MCR_ROOM", default_value = "default")]
+        room: String,
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
+        relay: String,
+        /// text|image|file (the history `kind`)
+        #[arg(long)]
+        kind: String,
+        /// Full sha256 recorded in history.
+        #[arg(long)]
+        sha: String,
+        #[arg(long)]
+        mime: Option<String>,
+        /// File name recorded in history (file items).
+        #[arg(long)]
+        file_name: Option<String>,
+        /// Max bytes allowed to send (file items)
+        #[arg(long, default_value_t = 20 * 1024 * 1024)]
+        max_file_bytes: usize,
+    },
+
+    /// Check this machine's setup (session, helper programs, relay, clipboard) and print a
+    /// pass/fail summary; exits nonzero if anything failed.
+    Doctor {
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
+        relay: String,
+        /// Also write a test string to the clipboard and read it back (replaces its contents).
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Ask a relay for a pong (an application-level check, not just a TCP connect) and print
+    /// the round-trip time.
+    Ping {
+        #[arg(long, env = "MCR_RELAY", default_value = "127.0.0.1:8080")]
+        relay: String,
+        /// Pings to send, one per second.
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+        #[arg(long, default_value_t = 3000)]
+        timeout_ms: u64,
+    },
+
+    /// Inspect the persisted sync history.
+    History {
+        #[command(subcommand)]
+        cmd: HistoryCommands,
+    },
+
     /// Sync clipboard between X11 and Wayland (replaces legacy xclip_sync.sh).
     ///
-    /// - X11 -> Wayland: polling (small interval)
+    /// - X11 -> Wayland: event-driven via XFixes (owner polling when XFixes is missing)
     /// - Wayland -> X11: event-driven via wl-paste --watch
     X11Sync {
-        /// X11 poll interval (ms)
+        /// X11 poll interval (ms): owner polling without XFixes, and the rate-limit retry cadence
         #[arg(long, default_value_t = 200)]
         x11_poll_interval_ms: u64,
         #[arg(long, default_value_t = 1 * 1024 * 1024)]
@@ -215,6 +555,40 @@ async fn main() -> anyhow::Result<()> {
     }
 
     let cli = Cli::parse();
+    node::throttle::set_max_upload_kbps(cli.max_upload_kbps);
+    node::throttle::set_max_events_per_sec(cli.max_events_per_sec);
+    node::net::set_heartbeat_secs(cli.heartbeat_secs);
+    node::net::set_connect_timeout_ms(cli.connect_timeout_ms);
+    node::clipboard::set_native_clipboard(cli.native_clipboard);
+    node::clipboard::set_clipboard_timeout_ms(cli.clipboard_timeout_ms);
+    node::publish::set_text_only(cli.text_only);
+    node::net::set_compress(cli.compress);
+    node::net::set_frame_crc(cli.frame_crc);
+    node::resend::set_keep_text(cli.keep_text);
+    node::extra_mime::set_extra_mimes(node::extra_mime::parse_extra_mimes(&cli.extra_mime)?);
+    node::channel::set_channels(
+        cli.channel.as_deref().map(node::channel::parse_channel).transpose()?.flatten(),
+        node::channel::parse_channels(&cli.subscribe)?,
+    );
+    set_output_format(cli.output.as_deref().map(parse_output_format).transpose()?);
+    if cli.preserve_animation {
+        node::transfer_image::set_preserve_animation(true);
+    }
+    if let Some(p) = cli.image_priority.as_deref() {
+        node::transfer_image::set_image_priority(parse_image_priority(p)?);
+    }
+    if let Some(m) = cli.bundle_mtime.as_deref() {
+        set_bundle_mtime(parse_bundle_mtime(m)?);
+    }
+    if let Some(a) = cli.hash_algo.as_deref() {
+        set_hash_algo(parse_hash_algo(a)?);
+    }
+    if let Some(d) = cli.received_dir.as_deref() {
+        node::paths::set_received_dir(d).context("--received-dir")?;
+    }
+    set_content_filters(cli.send_filter, cli.apply_filter, cli.filter_all_kinds);
+    set_allow_other_rooms(cli.allow_other_rooms);
+    set_path_metadata(!cli.no_path_metadata);
 
     let state_dir = cli.state_dir.unwrap_or_else(default_state_dir);
     tokio::fs::create_dir_all(&state_dir)
@@ -222,27 +596,50 @@ async fn main() -> anyhow::Result<()> {
         .context("create state_dir")?;
     let device_id = match cli.device_id {
         Some(id) => id,
-        None => get_or_create_device_id(&state_dir).await?,
+        None => local_device_id(&state_dir).await?,
     };
-    let device_name = cli.name.unwrap_or_else(default_device_name);
+    let device_name = resolve_device_name(cli.device_name.as_deref());
     let ctx = Ctx {
         state_dir,
+        data: DataDirs::resolve(),
         device_id,
         device_name,
     };
 
+    if matches!(
+        cli.cmd,
+        Commands::SendText { .. }
+            | Commands::SendImage { .. }
+            | Commands::SendFile { .. }
+            | Commands::Resend { .. }
+    ) {
+        ensure_enabled(&ctx.state_dir).await?;
+    }
+
     match cli.cmd {
         Commands::Listen { room, relay } => listen_mode(&ctx, &room, &relay).await?,
-        Commands::SendText { room, text, relay } => send_text(&ctx, &room, &text, &relay).await?,
+        Commands::SendText {
+            room,
+            text,
+            relay,
+            wait_ack,
+            ack_timeout,
+        } => {
+            let wait_ack = wait_ack.then(|| Duration::from_secs(ack_timeout));
+            send_text(&ctx, &room, &text, &relay, wait_ack).await?
+        }
         Commands::SendImage {
             room,
             file,
             relay,
             max_bytes,
             image_mode,
+            mime,
         } => {
             let im = parse_image_mode(&image_mode)?;
-            send_image(&ctx.device_id, &ctx.device_name, &room, &file, &relay, max_bytes, im).await?;
+            let (id, name) = (&ctx.device_id, &ctx.device_name);
+            let mime = mime.as_deref();
+            send_image(&ctx.data, id, name, &room, &file, &relay, max_bytes, im, mime).await?;
             println!("sent image to room {}", room);
         }
         Commands::SendFile {
@@ -250,38 +647,107 @@ async fn main() -> anyhow::Result<()> {
             file,
             relay,
             max_file_bytes,
-        } => send_file(&ctx.device_id, &ctx.device_name, &room, &file, &relay, max_file_bytes).await?,
+            mime,
+            wait_ack,
+            ack_timeout,
+        } => {
+            let (id, name) = (&ctx.device_id, &ctx.device_name);
+            let wait_ack = wait_ack.then(|| Duration::from_secs(ack_timeout));
+            let mime = mime.as_deref();
+            let history = &ctx.data.history;
+            send_file(history, id, name, &room, &file, &relay, max_file_bytes, mime, wait_ack)
+                .await?
+        }
         Commands::WlWatch {
             room,
             relay,
             mode,
             interval_ms,
+            text_poll_ms,
+            media_poll_ms,
+            file_resend_cooldown_ms,
             max_text_bytes,
             max_image_bytes,
             max_file_bytes,
             image_mode,
+            watch_mimes,
+            dry_run,
+            pause_on_lock,
         } => {
+            spawn_lock_monitor(&ctx, pause_on_lock);
             let im = parse_image_mode(&image_mode)?;
+            let watch_mimes: Vec<String> = if watch_mimes.is_empty() {
+                std::env::var(WATCH_MIMES_ENV)
+                    .map(|v| v.split(',').map(str::to_string).collect())
+                    .unwrap_or_default()
+            } else {
+                watch_mimes
+            };
+            let watch_mimes: Vec<String> = watch_mimes
+                .iter()
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .collect();
             cmd_wl_watch::run_wl_watch(
                 &ctx,
                 &room,
                 &relay,
                 &mode,
-                interval_ms,
+                PollIntervals::from_ms(interval_ms, text_poll_ms, media_poll_ms),
+                Duration::from_millis(file_resend_cooldown_ms),
                 max_text_bytes,
                 max_image_bytes,
                 max_file_bytes,
                 im,
+                &watch_mimes,
+                dry_run,
             )
             .await?
         }
+        Commands::Receive { room, relay, dir } => {
+            let opts = cmd_wl_apply::ApplyOpts {
+                image_mode: ImageMode::Passthrough,
+                bundle_expose: BundleExpose::Auto,
+                max_listed_items: MAX_LISTED_ITEMS,
+                staleness: Staleness::default(),
+                store_only: Some(dir),
+            };
+            cmd_wl_apply::run_wl_apply(&ctx, &WlPaste, &room, &relay, opts).await?
+        }
         Commands::WlApply {
             room,
             relay,
             image_mode,
+            bundle_expose,
+            max_listed_items,
+            max_age_ms,
+            clock_skew_ms,
+            apply_to_primary,
+            no_apply_to_primary,
+            pause_on_lock,
         } => {
+            spawn_lock_monitor(&ctx, pause_on_lock);
+            let primary = match (apply_to_primary, no_apply_to_primary) {
+                (_, true) => Some(false),
+                (true, _) => Some(true),
+                _ => None,
+            };
+            node::clipboard::set_apply_to_primary(primary);
             let im = parse_image_mode(&image_mode)?;
-            cmd_wl_apply::run_wl_apply(&ctx, &room, &relay, im).await?
+            let be = parse_bundle_expose(&bundle_expose)?;
+            anyhow::ensure!(max_listed_items > 0, "--max-listed-items must be > 0");
+            let staleness = Staleness {
+                max_age: (max_age_ms > 0).then(|| Duration::from_millis(max_age_ms)),
+                clock_skew: Duration::from_millis(clock_skew_ms),
+            };
+            let opts = cmd_wl_apply::ApplyOpts {
+                image_mode: im,
+                bundle_expose: be,
+                max_listed_items,
+                staleness,
+                store_only: None,
+            };
+            cmd_wl_apply::run_wl_apply(&ctx, &WlPaste, &room, &relay, opts).await?
         }
         Commands::WlPublishCurrent {
             room,
@@ -302,31 +768,183 @@ async fn main() -> anyhow::Result<()> {
                 max_image_bytes,
                 max_file_bytes,
                 im,
+                false,
             )
             .await?
         }
 
-        Commands::X11Sync {
-            x11_poll_interval_ms,
+        Commands::PublishCurrent {
+            room,
+            relay,
             max_text_bytes,
             max_image_bytes,
+            max_file_bytes,
+            image_mode,
         } => {
-            async fn ensure_bin(name: &str) -> anyhow::Result<()> {
-                match Command::new(name)
-                    .arg("--help")
-                    .stdout(std::process::Stdio::null())
-                    .stderr(std::process::Stdio::null())
-                    .output()
-                    .await
-                {
-                    Ok(_) => Ok(()),
-                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                        anyhow::bail!("required program not found in PATH: {name}")
-                    }
-                    Err(e) => Err(anyhow::anyhow!(e)).context(format!("check program: {name}")),
+            let im = parse_image_mode(&image_mode)?;
+            let cx = PublishCtx {
+                state_dir: &ctx.state_dir,
+                data: &ctx.data,
+                device_id: &ctx.device_id,
+                device_name: &ctx.device_name,
+                room: &room,
+                relay: &relay,
+                dry_run: false,
+            };
+            let limits = PublishLimits {
+                max_text_bytes,
+                max_image_bytes,
+                max_file_bytes,
+                text_only: node::publish::text_only(),
+            };
+            if publish_current(&cx, &WlPaste, "auto", None, limits, im).await?.is_some() {
+                println!("published current clipboard to room '{}'", room);
+            } else {
+                println!("nothing to publish (empty, unsupported, paused or suppressed)");
+            }
+        }
+
+        Commands::ConnectUri { uri, write_env } => {
+            let parsed =
+                parse_connect_uri(&uri).with_context(|| format!("parse {}", uri.trim()))?;
+            println!("MULTICLIPRELAY_RELAY={}", parsed.relay);
+            println!("MULTICLIPRELAY_ROOM={}", parsed.room);
+            if write_env {
+                let path = systemd_env_path();
+                upsert_env_file(
+                    &path,
+                    &[
+                        ("MULTICLIPRELAY_RELAY", parsed.relay.as_str()),
+                        ("MULTICLIPRELAY_ROOM", parsed.room.as_str()),
+                    ],
+                )?;
+                println!("updated {}", path.display());
+            }
+        }
+
+        Commands::Pause { room } => {
+            set_paused(&ctx.state_dir, &room, true)
+                .await
+                .context("write pause flag")?;
+            println!("paused room '{}'", room);
+        }
+
+        Commands::Resume { room } => {
+            set_paused(&ctx.state_dir, &room, false)
+                .await
+                .context("remove pause flag")?;
+            println!("resumed room '{}'", room);
+        }
+
+        Commands::Disable => {
+            set_disabled(&ctx.state_dir, true)
+                .await
+                .context("write kill switch")?;
+            println!("syncing disabled ({})", disabled_path(&ctx.state_dir).display());
+        }
+
+        Commands::Enable => {
+            set_disabled(&ctx.state_dir, false)
+                .await
+                .context("remove kill switch")?;
+            println!("syncing enabled");
+        }
+
+        Commands::Resend {
+            room,
+            relay,
+            kind,
+            sha,
+            mime,
+            file_name,
+            max_file_bytes,
+        } => {
+            let entry = ResendEntry {
+                kind,
+                sha256: sha,
+                mime,
+                name: file_name,
+            };
+            resend(&ctx, &room, &relay, &entry, max_file_bytes).await?
+        }
+
+        Commands::History {
+            cmd: HistoryCommands::Export { format, out },
+        } => {
+            let format = parse_export_format(&format)?;
+            let n = export_history(&ctx.data.history, format, &out)?;
+            println!("exported {} events to {}", n, out.display());
+        }
+        Commands::History {
+            cmd: HistoryCommands::Pull { room, relay },
+        } => {
+            let msgs =
+                pull_scrollback(&relay, &ctx.device_id, &ctx.device_name, &room, REPLAY_WAIT).await?;
+            for msg in &msgs {
+                if json_output() {
+                    emit_event("replay", msg);
+                    continue;
                 }
+                let text = match msg.kind {
+                    Kind::Text => msg.payload.as_deref().map(String::from_utf8_lossy).unwrap_or_default(),
+                    _ => Default::default(),
+                };
+                println!(
+                    "REPLAY from {} kind={:?} name={} mime={} bytes={} text={}",
+                    msg.device_id,
+                    msg.kind,
+                    msg.name.as_deref().unwrap_or_default(),
+                    msg.mime.as_deref().unwrap_or_default(),
+                    msg.size,
+                    text
+                );
             }
+            node::say!("{} messages in room {}", msgs.len(), room);
+        }
 
+        Commands::Doctor { relay, write } => {
+            let checks = run_checks(&relay, write).await;
+            for c in &checks {
+                println!("{}", c.line());
+            }
+            let failed = failures(&checks);
+            anyhow::ensure!(failed == 0, "{} of {} checks failed", failed, checks.len());
+            println!("all {} checks passed", checks.len());
+        }
+
+        Commands::Ping {
+            relay,
+            count,
+            timeout_ms,
+        } => {
+            let timeout = Duration::from_millis(timeout_ms);
+            for i in 0..count.max(1) {
+                if i > 0 {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                let addr = relay.clone();
+                let (sock, rtt) = tokio::task::spawn_blocking(move || ping_relay(&addr, timeout))
+                    .await
+                    .context("ping task")?
+                    .map_err(anyhow::Error::msg)?;
+                println!("pong from {} in {:.1}ms", sock, rtt.as_secs_f64() * 1000.0);
+            }
+        }
+
+        Commands::SwitchRoom { room } => {
+            request_room_switch(&ctx.state_dir, &room).context("request room switch")?;
+            println!(
+                "requested room '{}' ({})",
+                room.trim(),
+                room_control_path(&ctx.state_dir).display()
+            );
+        }
+
+        Commands::X11Sync {
+            x11_poll_interval_ms,
+            max_text_bytes,
+            max_image_bytes,
+        } => {
             ensure_bin("wl-paste").await?;
 
             // Global lock (independent of --state-dir):
@@ -388,6 +1006,8 @@ async fn main() -> anyhow::Result<()> {
                 .context("spawn wl-paste image/webp watch")?;
             let _wl_gif = spawn_watch("image/gif", "full")
                 .context("spawn wl-paste image/gif watch")?;
+            let _wl_svg = spawn_watch("image/svg+xml", "full")
+                .context("spawn wl-paste image/svg+xml watch")?;
 
             // Main loop: X11 -> Wayland.
             x11_sync_service(X11SyncOpts {
@@ -439,6 +1059,7 @@ fn acquire_global_instance_lock(name: &str) -> anyhow::Result<File> {
 
     let f = std::fs::OpenOptions::new()
         .create(true)
+        .truncate(false)
         .read(true)
         .write(true)
         .open(&lock_path)
@@ -461,75 +1082,33 @@ fn acquire_global_instance_lock(_name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-#[cfg(unix)]
-fn acquire_instance_lock(
-    state_dir: &PathBuf,
-    name: &str,
-    room: &str,
-    relay: &str,
-) -> anyhow::Result<File> {
-    use std::os::unix::io::AsRawFd;
-
-    let lock_name = format!(
-        "{}_room={}_relay={}.lock",
-        name,
-        safe_for_filename(room),
-        safe_for_filename(relay)
-    );
-    let lock_path = state_dir.join(lock_name);
-    let f = std::fs::OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .open(&lock_path)
-        .with_context(|| format!("open lock {}", lock_path.display()))?;
-
-    let rc = unsafe { libc::flock(f.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
-    if rc != 0 {
-        let e = std::io::Error::last_os_error();
-        // EWOULDBLOCK means another instance holds the lock.
-        if e.raw_os_error() == Some(libc::EWOULDBLOCK) {
-            anyhow::bail!(
-                "{} already running (lock busy): {}",
-                name,
-                lock_path.display()
-            );
+/// Override (or append) `KEY=value` lines in an EnvironmentFile, keeping other lines intact.
+fn upsert_env_file(path: &Path, pairs: &[(&str, &str)]) -> anyhow::Result<()> {
+    let existing = std::fs::read_to_string(path).unwrap_or_default();
+    let mut lines: Vec<String> = Vec::new();
+    for line in existing.lines() {
+        let key = line.split_once('=').map(|(k, _)| k.trim());
+        if key.is_some_and(|k| pairs.iter().any(|(pk, _)| *pk == k)) {
+            continue;
         }
-        return Err(anyhow::Error::new(e)).context("flock");
+        lines.push(line.to_string());
     }
-    Ok(f)
-}
-
-#[cfg(not(unix))]
-fn acquire_instance_lock(
-    _state_dir: &PathBuf,
-    _name: &str,
-    _room: &str,
-    _relay: &str,
-) -> anyhow::Result<()> {
-    Ok(())
-}
-
-async fn get_or_create_device_id(state_dir: &PathBuf) -> anyhow::Result<String> {
-    let p = state_dir.join("device_id");
-    if let Ok(s) = tokio::fs::read_to_string(&p).await {
-        let id = s.trim().to_string();
-        if !id.is_empty() {
-            return Ok(id);
-        }
+    for (k, v) in pairs {
+        lines.push(format!("{k}={v}"));
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
     }
-    let id = uuid::Uuid::new_v4().to_string();
-    tokio::fs::write(&p, &id).await.context("write device_id")?;
-    Ok(id)
+    std::fs::write(path, lines.join("\n") + "\n")
+        .with_context(|| format!("write {}", path.display()))
 }
 
 async fn listen_mode(ctx: &Ctx, room: &str, relay: &str) -> anyhow::Result<()> {
     // Heartbeat + reconnect (mirror wl-apply behavior to avoid idle disconnects).
     let reconnect_backoff = Duration::from_millis(800);
-    let heartbeat_interval = Duration::from_secs(20);
 
     loop {
-        let stream = match connect(relay).await {
+        let stream = match connect_in(&ctx.state_dir, relay).await {
             Ok(s) => s,
             Err(e) => {
                 log::warn!("listen: connect failed: {e:?}");
@@ -540,17 +1119,16 @@ async fn listen_mode(ctx: &Ctx, room: &str, relay: &str) -> anyhow::Result<()> {
         let (mut reader, mut writer) = stream.into_split();
 
         // Send a Join message so the relay can register us into the room.
-        if let Err(e) = send_join(&mut writer, &ctx.device_id, &ctx.device_name, room).await {
+        if let Err(e) = send_join_hello(&mut writer, &ctx.device_id, &ctx.device_name, room).await {
             log::warn!("listen: send join failed: {e:?}");
             tokio::time::sleep(reconnect_backoff).await;
             continue;
         }
 
         log::info!("listen: connected room='{}' relay='{}'", room, relay);
-        println!("Listening in room '{}' on {}", room, relay);
+        node::say!("Listening in room '{}' on {}", room, relay);
 
-        let mut hb = tokio::time::interval(heartbeat_interval);
-        hb.tick().await;
+        let mut hb = Heartbeat::new();
 
         loop {
             let len: usize = tokio::select! {
@@ -572,18 +1150,28 @@ async fn listen_mode(ctx: &Ctx, room: &str, relay: &str) -> anyhow::Result<()> {
                 }
             };
 
-            let mut buf = vec![0u8; len];
-            if let Err(e) = reader.read_exact(&mut buf).await {
-                log::warn!("listen: read payload failed (will reconnect): {e:?}");
+            if len > MAX_FRAME_BYTES {
+                log::error!(
+                    "listen: frame too large (will reconnect): len={} max={}",
+                    len,
+                    MAX_FRAME_BYTES
+                );
                 break;
             }
+            let buf = match read_frame_body(&mut reader, len).await {
+                Ok(b) => b,
+                Err(e) => {
+                    log::warn!("listen: read payload failed (will reconnect): {e:#}");
+                    break;
+                }
+            };
 
             let msg = match Message::try_from_bytes(&buf) {
                 Ok(m) => m,
                 Err(e) => {
                     let prefix_len = buf.len().min(16);
                     log::warn!(
-                        "listen: decode failed (will reconnect): len={} prefix={:02x?} err={:?}",
+                        "listen: decode failed (will reconnect): len={} prefix={:02x?} err={}",
                         len,
                         &buf[..prefix_len],
                         e
@@ -597,9 +1185,17 @@ async fn listen_mode(ctx: &Ctx, room: &str, relay: &str) -> anyhow::Result<()> {
                 continue;
             }
 
-            if !matches!(msg.kind, Kind::Join) {
-                record_recv(&ctx.device_id, Some(ctx.device_name.clone()), room, relay, &msg)
-                    .await;
+            if !matches!(
+                msg.kind,
+                Kind::Join | Kind::Hello | Kind::Replay | Kind::Ping | Kind::Pong | Kind::Ack
+            ) {
+                let name = Some(ctx.device_name.clone());
+                record_recv(&ctx.data.history, &ctx.device_id, name, room, relay, &msg).await;
+            }
+
+            if json_output() && msg.rejection().is_none() {
+                emit_event("recv", &msg);
+                continue;
             }
 
             match msg.kind {
@@ -635,9 +1231,30 @@ async fn listen_mode(ctx: &Ctx, room: &str, relay: &str) -> anyhow::Result<()> {
                         msg.sha256.clone().unwrap_or_default()
                     );
                 }
-                Kind::Join => {
-                    println!("RECV from {} kind=Join", msg.device_id);
+                Kind::Join => match msg.rejection() {
+                    Some(reason) => eprintln!("relay refused connection: {reason}"),
+                    None => println!("RECV from {} kind=Join", msg.device_id),
+                },
+                Kind::Hello => {
+                    let hello = msg.hello();
+                    println!(
+                        "RECV from {} kind=Hello version={} caps={:?}",
+                        msg.device_id,
+                        hello.as_ref().map_or(0, |h| h.version),
+                        hello.map(|h| h.caps).unwrap_or_default()
+                    );
+                    if let Some(banner) = msg.relay_banner() {
+                        println!(
+                            "relay {} runs v{} (up {}s)",
+                            relay, banner.version, banner.uptime_secs
+                        );
+                    }
                 }
+                Kind::Ack => println!(
+                    "RECV from {} kind=Ack event_id={}",
+                    msg.device_id, msg.event_id
+                ),
+                Kind::Replay | Kind::Ping | Kind::Pong => {}
             }
         }
 
@@ -645,35 +1262,169 @@ async fn listen_mode(ctx: &Ctx, room: &str, relay: &str) -> anyhow::Result<()> {
     }
 }
 
-async fn send_text(ctx: &Ctx, room: &str, text: &str, relay: &str) -> anyhow::Result<()> {
-    let stream = connect(relay).await?;
-    let mut msg = Message::new_text(&ctx.device_id, room, text);
-    msg.sender_name = Some(ctx.device_name.clone());
-    let sha = sha256_hex(msg.payload.as_deref().unwrap_or_default());
-    msg.sha256 = Some(sha.clone());
-    let buf = msg.to_bytes();
-    send_frame(stream, buf).await?;
-    log::debug!(
-        "send-text: room={} relay={} bytes={} sha={} text_preview={}",
-        room,
-        relay,
-        msg.size,
-        sha,
-        text.chars().take(120).collect::<String>()
-    );
+async fn resend(
+    ctx: &Ctx,
+    room: &str,
+    relay: &str,
+    entry: &ResendEntry,
+    max_file_bytes: usize,
+) -> anyhow::Result<()> {
+    let mut msg = match prepare_resend(&ctx.data.received, &ctx.device_id, room, entry)? {
+        Resend::File(path) => {
+            let (id, name) = (&ctx.device_id, &ctx.device_name);
+            let history = &ctx.data.history;
+            return send_file(history, id, name, room, &path, relay, max_file_bytes, None, None)
+                .await;
+        }
+        Resend::Frame(msg) => *msg,
+    };
+    set_sender_name(&mut msg, &ctx.device_name);
+    node::channel::stamp_channel(&mut msg);
+    let sent = async { send_frame(connect_in(&ctx.state_dir, relay).await?, msg.to_bytes()).await }.await;
+    if let Err(e) = sent {
+        let reason = format!("send failed: {e:#}");
+        record_event(
+            &ctx.data.history,
+            send_outcome_event(
+                &ctx.device_id,
+                msg.sender_name.clone(),
+                room,
+                relay,
+                Outcome::Failed,
+                &reason,
+                msg.kind.clone(),
+                msg.mime.clone(),
+                msg.size,
+                msg.sha256.clone(),
+            ),
+        )
+        .await;
+        return Err(e);
+    }
     record_send(
+        &ctx.data.history,
         &ctx.device_id,
         Some(ctx.device_name.clone()),
         room,
         relay,
-        Kind::Text,
-        Some("text/plain;charset=utf-8".to_string()),
+        msg.kind.clone(),
+        msg.mime.clone(),
         None,
         msg.size,
-        Some(sha),
+        msg.sha256.clone(),
     )
     .await;
+    println!("resent {} ({} bytes) to room {}", entry.kind, msg.size, room);
+    Ok(())
+}
+
+/// `--pause-on-lock`: track the session lock state for as long as the service runs.
+fn spawn_lock_monitor(ctx: &Ctx, flag: bool) {
+    if pause_on_lock_enabled(flag) {
+        let monitor = pause_on_lock(Loginctl::current(), ctx.state_dir.clone());
+        tokio::spawn(monitor);
+    }
+}
+
+async fn send_text(
+    ctx: &Ctx,
+    room: &str,
+    text: &str,
+    relay: &str,
+    wait_ack: Option<Duration>,
+) -> anyhow::Result<()> {
+    let device = Device::new(&ctx.device_id, &ctx.device_name);
+    // Without --wait-ack only the text frame goes out, which any relay takes.
+    let connected = match wait_ack {
+        Some(_) => Client::connect_for_ack(relay, room, device).await,
+        None => Client::connect(relay, room, device).await,
+    };
+    let client = match connected {
+        Ok(client) => client,
+        Err(e) => {
+            let reason = format!("connect failed: {e:#}");
+            record_event(
+                &ctx.data.history,
+                send_outcome_event(
+                    &ctx.device_id,
+                    sender_name(&ctx.device_name),
+                    room,
+                    relay,
+                    Outcome::Failed,
+                    &reason,
+                    Kind::Text,
+                    Some("text/plain;charset=utf-8".to_string()),
+                    text.len(),
+                    None,
+                ),
+            )
+            .await;
+            return Err(e);
+        }
+    };
+    let mut client = if keep_text_enabled() {
+        client.keep_for_resend(received_dir())
+    } else {
+        client
+    };
+    let msg = client.send_text(text).await?;
     println!("sent text to room {}", room);
+    if let Some(wait) = wait_ack {
+        let by = client.wait_for_ack(&msg.event_id, wait).await?;
+        println!("applied by {}", by);
+    }
     Ok(())
 }
-// Tests live in the dedicated modules (e.g. transfer_file).
+// Most tests live in the dedicated modules (e.g. transfer_file); only argument parsing is
+// tested here.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_target(args: &[&str]) -> (String, String) {
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.cmd {
+            Commands::WlApply { room, relay, .. } => (room, relay),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn relay_room_and_state_dir_come_from_flags_then_env_then_defaults() {
+        let flags = ["node", "wl-apply", "--room", "home", "--relay", "r:1"];
+        assert_eq!(
+            apply_target(&flags),
+            ("home".to_string(), "r:1".to_string())
+        );
+        let cli = Cli::try_parse_from(["node", "--state-dir", "/elsewhere", "disable"]).unwrap();
+        assert_eq!(cli.state_dir, Some(PathBuf::from("/elsewhere")));
+        let cli = Cli::try_parse_from(["node", "disable"]).unwrap();
+        assert_eq!(cli.state_dir, None, "resolved later (utils::paths)");
+
+        // Without flags clap reads the env, then the defaults; checked on the declarations so
+        // the test doesn't depend on (or change) this process's env.
+        let cmd = <Cli as clap::CommandFactory>::command();
+        let apply = cmd.find_subcommand("wl-apply").unwrap();
+        let arg = |id: &str| apply.get_arguments().find(|a| a.get_id() == id).unwrap();
+        for (id, env, default) in [
+            ("room", "MCR_ROOM", "default"),
+            ("relay", "MCR_RELAY", "127.0.0.1:8080"),
+        ] {
+            assert_eq!(arg(id).get_env(), Some(std::ffi::OsStr::new(env)));
+            assert_eq!(arg(id).get_default_values(), [default]);
+        }
+    }
+
+    #[test]
+    fn resend_file_name_and_device_name_dont_clash() {
+        <Cli as clap::CommandFactory>::command().debug_assert();
+        let args = ["node", "resend", "--kind", "file", "--sha", "ab"];
+        let names = ["--file-name", "a.txt", "--name", "desk"];
+        let cli = Cli::try_parse_from(args.iter().chain(&names)).unwrap();
+        assert_eq!(cli.device_name.as_deref(), Some("desk"));
+        let Commands::Resend { file_name, .. } = cli.cmd else {
+            unreachable!()
+        };
+        assert_eq!(file_name.as_deref(), Some("a.txt"));
+    }
+}
@@ -1,46 +1,1048 @@
 use anyhow::Context;
-use tokio::io::AsyncWriteExt;
+use async_compression::tokio::bufread::DeflateDecoder;
+use async_compression::tokio::write::DeflateEncoder;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
 use tokio::net::TcpStream;
+use tokio::time::Instant;
 
-pub async fn connect(relay: &str) -> anyhow::Result<TcpStream> {
-    log::debug!("connect: target={}", relay);
-    let s = TcpStream::connect(relay).await.context("connect")?;
-    log::info!("connect: ok target={}", relay);
+use utils::probe::normalize_relay_addr_for_connect;
+use utils::{
+    conn_hello, parse_conn_hello, Hello, CAP_ACK, CAP_DEFLATE, CAP_EXTRA_MIME, CONN_CODEC_DEFLATE,
+};
+
+use crate::throttle::write_throttled;
+
+/// Env var used to pass `--compress` to helper processes (e.g. the wl-watch hook).
+pub const COMPRESS_ENV: &str = "MCR_COMPRESS";
+
+/// How long `--compress` waits for the relay to answer its hello.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(2);
+
+static COMPRESS: OnceLock<bool> = OnceLock::new();
+
+fn compress_from_env() -> bool {
+    matches!(
+        std::env::var(COMPRESS_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Ask relays to deflate the connection (process-wide, set once at startup; `false` = env).
+pub fn set_compress(on: bool) {
+    let _ = COMPRESS.set(on || compress_from_env());
+}
+
+pub fn compress_enabled() -> bool {
+    *COMPRESS.get_or_init(compress_from_env)
+}
+
+/// Env var used to pass `--frame-crc` to helper processes.
+pub const FRAME_CRC_ENV: &str = "MCR_FRAME_CRC";
+
+static FRAME_CRC: OnceLock<bool> = OnceLock::new();
+
+fn frame_crc_from_env() -> bool {
+    matches!(
+        std::env::var(FRAME_CRC_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Send MCR3 frames (crc32 trailer). Off by default: nodes and relays older than MCR3 can't
+/// decode them, so only turn it on once every peer is new enough (process-wide; `false` = env).
+pub fn set_frame_crc(on: bool) {
+    let _ = FRAME_CRC.set(on || frame_crc_from_env());
+}
+
+pub fn frame_crc_enabled() -> bool {
+    *FRAME_CRC.get_or_init(frame_crc_from_env)
+}
+
+/// Env fallback for `--connect-timeout-ms` (also passed to helper processes).
+pub const CONNECT_TIMEOUT_ENV: &str = "MCR_CONNECT_TIMEOUT_MS";
+/// Default `--connect-timeout-ms`: a reachable relay answers far sooner, and a hook or
+/// `send-text` shouldn't hang on one whose SYNs are dropped.
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5000;
+
+static CONNECT_TIMEOUT_MS: OnceLock<u64> = OnceLock::new();
+
+fn connect_timeout_from_env() -> u64 {
+    std::env::var(CONNECT_TIMEOUT_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS)
+}
+
+/// Give up on a relay connect after this long (process-wide, set once at startup; `0` leaves
+/// it to the OS).
+pub fn set_connect_timeout_ms(ms: Option<u64>) {
+    let _ = CONNECT_TIMEOUT_MS.set(ms.unwrap_or_else(connect_timeout_from_env));
+}
+
+pub fn connect_timeout_ms() -> u64 {
+    *CONNECT_TIMEOUT_MS.get_or_init(connect_timeout_from_env)
+}
+
+pub type RelayReader = Box<dyn AsyncRead + Send + Unpin>;
+pub type RelayWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// A relay connection: plain TCP, or deflated both ways once `--compress` was agreed on.
+pub struct RelayStream {
+    reader: RelayReader,
+    writer: RelayWriter,
+}
+
+impl RelayStream {
+    fn plain(s: TcpStream) -> Self {
+        let (r, w) = s.into_split();
+        Self {
+            reader: Box::new(r),
+            writer: Box::new(w),
+        }
+    }
+
+    fn deflate(s: TcpStream) -> Self {
+        let (r, w) = s.into_split();
+        Self {
+            reader: Box::new(DeflateDecoder::new(BufReader::new(r))),
+            writer: Box::new(DeflateEncoder::new(w)),
+        }
+    }
+
+    pub fn into_split(self) -> (RelayReader, RelayWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+impl AsyncRead for RelayStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RelayStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// Connect to `relay`; `host`, `tcp://host` and the like are accepted (see
+/// [`normalize_relay_addr_for_connect`]). Fails after `--connect-timeout-ms`.
+pub async fn connect(relay: &str) -> anyhow::Result<RelayStream> {
+    connect_within(None, relay, default_connect_timeout()).await
+}
+
+/// [`connect`], remembering relays that can't compress in `state_dir` (see
+/// [`plain_relay_path`]) so later connects from this node skip the hello.
+pub async fn connect_in(state_dir: &Path, relay: &str) -> anyhow::Result<RelayStream> {
+    connect_within(Some(state_dir), relay, default_connect_timeout()).await
+}
+
+fn default_connect_timeout() -> Option<Duration> {
+    Some(connect_timeout_ms())
+        .filter(|&ms| ms > 0)
+        .map(Duration::from_millis)
+}
+
+/// [`connect`] with an explicit TCP connect timeout (`None`: wait as long as the OS does).
+/// Without a `state_dir`, nothing is remembered about the relay.
+pub async fn connect_within(
+    state_dir: Option<&Path>,
+    relay: &str,
+    timeout: Option<Duration>,
+) -> anyhow::Result<RelayStream> {
+    let target = normalize_relay_addr_for_connect(relay);
+    let s = connect_tcp(&target, timeout).await?;
+    if !compress_enabled() {
+        return Ok(RelayStream::plain(s));
+    }
+    if let Some(dir) = state_dir {
+        if known_plain(dir, relay).await {
+            log::debug!("connect: {relay} can't compress (remembered); sending plain frames");
+            return Ok(RelayStream::plain(s));
+        }
+    }
+    match negotiate_deflate(s, HELLO_TIMEOUT).await? {
+        Negotiated::Answered(stream) => Ok(stream),
+        Negotiated::Silent(stream) => {
+            if let Some(dir) = state_dir {
+                remember_plain(dir, relay).await;
+            }
+            Ok(stream)
+        }
+        Negotiated::Closed => {
+            if let Some(dir) = state_dir {
+                remember_plain(dir, relay).await;
+            }
+            let s = connect_tcp(&target, timeout).await?;
+            Ok(RelayStream::plain(s))
+        }
+    }
+}
+
+async fn connect_tcp(target: &str, timeout: Option<Duration>) -> anyhow::Result<TcpStream> {
+    log::debug!("connect: target={}", target);
+    let tcp = TcpStream::connect(target);
+    let s = match timeout {
+        Some(t) => tokio::time::timeout(t, tcp).await.map_err(|_| {
+            anyhow::anyhow!("connect {}: timed out after {}ms", target, t.as_millis())
+        })?,
+        None => tcp.await,
+    }
+    .with_context(|| format!("connect {}", target))?;
+    log::info!("connect: ok target={}", target);
     Ok(s)
 }
 
-pub async fn send_frame(mut stream: TcpStream, buf: Vec<u8>) -> anyhow::Result<()> {
+/// First wait after a failed publish connect; doubles per failure up to [`RELAY_BACKOFF_MAX`].
+const RELAY_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RELAY_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Backoff record for `relay` (`<failures> <retry_at_ms>`), shared by the per-event hooks.
+pub fn relay_backoff_path(state_dir: &Path, relay: &str) -> PathBuf {
+    let safe_relay = relay.replace(['/', ':'], "_");
+    state_dir.join(format!("relay_backoff_{}", safe_relay))
+}
+
+fn relay_backoff_delay(failures: u32) -> Duration {
+    let exp = failures.saturating_sub(1).min(16);
+    (RELAY_BACKOFF_MIN * 2u32.pow(exp)).min(RELAY_BACKOFF_MAX)
+}
+
+/// [`connect`] for one-shot publishes, with a circuit breaker kept in `state_dir`.
+///
+/// Every clipboard change in the evented watcher runs a fresh hook process; with the relay
+/// down, each would try (and wait for) its own connect. After a failure, attempts within the
+/// backoff window fail immediately instead, so those copies are dropped, not queued.
+pub async fn connect_with_backoff(state_dir: &Path, relay: &str) -> anyhow::Result<RelayStream> {
+    let p = relay_backoff_path(state_dir, relay);
+    let record = tokio::fs::read_to_string(&p).await.unwrap_or_default();
+    let mut it = record.split_whitespace().map(|v| v.parse::<u64>().ok());
+    let failures = it.next().flatten().unwrap_or(0) as u32;
+    let retry_at = it.next().flatten().unwrap_or(0);
+    let now = utils::now_ms();
+    if now < retry_at {
+        anyhow::bail!(
+            "relay {} unreachable ({} failed attempts); next try in {}ms",
+            relay,
+            failures,
+            retry_at - now
+        );
+    }
+    match connect_in(state_dir, relay).await {
+        Ok(s) => {
+            if failures > 0 {
+                let _ = tokio::fs::remove_file(&p).await;
+            }
+            Ok(s)
+        }
+        Err(e) => {
+            let failures = failures.saturating_add(1);
+            let delay = relay_backoff_delay(failures);
+            log::warn!(
+                "relay {} unreachable; skipping sends for {}ms: {:#}",
+                relay,
+                delay.as_millis(),
+                e
+            );
+            let retry_at = now.saturating_add(delay.as_millis() as u64);
+            let _ = tokio::fs::write(&p, format!("{} {}\n", failures, retry_at)).await;
+            Err(e)
+        }
+    }
+}
+
+/// How a relay took the compression hello.
+enum Negotiated {
+    /// It answered: deflated if it agreed, plain if it declined.
+    Answered(RelayStream),
+    /// No answer within the wait (e.g. a proxy that holds the frame); plain frames from here.
+    Silent(RelayStream),
+    /// It hung up: relays from before compression drop the connection on a frame they can't
+    /// decode. The connection is gone; reconnect and send plain frames.
+    Closed,
+}
+
+/// Send the compression hello and switch to deflate if the relay agrees.
+async fn negotiate_deflate(mut s: TcpStream, wait: Duration) -> anyhow::Result<Negotiated> {
+    let hello = conn_hello(CONN_CODEC_DEFLATE);
+    s.write_u32(hello.len() as u32)
+        .await
+        .context("write hello len")?;
+    s.write_all(&hello).await.context("write hello")?;
+
+    let read_answer = async {
+        let len = s.read_u32().await? as usize;
+        if len > utils::MAX_FRAME_BYTES {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("hello answer too large: {len}"),
+            ));
+        }
+        let mut buf = vec![0u8; len];
+        s.read_exact(&mut buf).await?;
+        Ok(buf)
+    };
+    let answer = match tokio::time::timeout(wait, read_answer).await {
+        Ok(Ok(answer)) => answer,
+        Ok(Err(e)) if is_hang_up(&e) => {
+            log::info!("connect: relay closed the connection on the compression hello ({e})");
+            return Ok(Negotiated::Closed);
+        }
+        Ok(Err(e)) => return Err(e).context("read hello answer"),
+        Err(_) => {
+            log::info!("connect: relay did not answer the compression hello; sending plain frames");
+            return Ok(Negotiated::Silent(RelayStream::plain(s)));
+        }
+    };
+    let stream = match parse_conn_hello(&answer) {
+        Some(CONN_CODEC_DEFLATE) => {
+            log::debug!("connect: deflate on");
+            RelayStream::deflate(s)
+        }
+        Some(codec) => {
+            log::info!("connect: relay declined compression (codec={codec}); sending plain frames");
+            RelayStream::plain(s)
+        }
+        None => {
+            // Not an answer (e.g. a rejection notice): leave it for the caller to read.
+            let mut first = (answer.len() as u32).to_be_bytes().to_vec();
+            first.extend_from_slice(&answer);
+            let (r, w) = s.into_split();
+            RelayStream {
+                reader: Box::new(Cursor::new(first).chain(r)),
+                writer: Box::new(w),
+            }
+        }
+    };
+    Ok(Negotiated::Answered(stream))
+}
+
+fn is_hang_up(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        e.kind(),
+        UnexpectedEof | ConnectionReset | ConnectionAborted | BrokenPipe
+    )
+}
+
+/// How long a relay that can't do compression is remembered, so hooks skip the hello (and
+/// its wait); after that it is asked again, in case it was upgraded.
+const PLAIN_RELAY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Marker for a relay found not to speak the compression hello (`<ms when found>`), shared
+/// by the per-event hook processes.
+pub fn plain_relay_path(state_dir: &Path, relay: &str) -> PathBuf {
+    let safe_relay = relay.replace(['/', ':'], "_");
+    state_dir.join(format!("plain_relay_{}", safe_relay))
+}
+
+async fn known_plain(state_dir: &Path, relay: &str) -> bool {
+    let Ok(s) = tokio::fs::read_to_string(plain_relay_path(state_dir, relay)).await else {
+        return false;
+    };
+    let since: u64 = s.trim().parse().unwrap_or(0);
+    utils::now_ms().saturating_sub(since) <= PLAIN_RELAY_TTL.as_millis() as u64
+}
+
+async fn remember_plain(state_dir: &Path, relay: &str) {
+    let p = plain_relay_path(state_dir, relay);
+    if let Err(e) = tokio::fs::write(&p, format!("{}\n", utils::now_ms())).await {
+        log::debug!("connect: can't remember {}: {e}", p.display());
+    }
+}
+
+pub async fn send_frame(mut stream: RelayStream, buf: Vec<u8>) -> anyhow::Result<()> {
     log::debug!("send_frame: bytes={}", buf.len());
-    stream
-        .write_u32(buf.len() as u32)
+    write_frame(&mut stream, &buf).await
+}
+
+/// Write one length-prefixed frame (payload paced by `--max-upload-kbps`, if set).
+///
+/// With `--frame-crc`, message frames go out as MCR3 (crc32 trailer) so receivers can drop
+/// corrupt payloads; the frame is streamed with the new magic and trailer, not copied.
+pub async fn write_frame<W: AsyncWrite + Unpin>(w: &mut W, buf: &[u8]) -> anyhow::Result<()> {
+    write_frame_as(w, buf, frame_crc_enabled()).await
+}
+
+async fn write_frame_as<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    buf: &[u8],
+    crc: bool,
+) -> anyhow::Result<()> {
+    let seal = if crc { utils::seal_parts(buf) } else { None };
+    match seal {
+        Some((magic, trailer)) => {
+            let len = buf.len() + trailer.len();
+            w.write_u32(len as u32).await.context("write len")?;
+            w.write_all(magic).await.context("write magic")?;
+            write_throttled(w, &buf[magic.len()..])
+                .await
+                .context("write payload")?;
+            w.write_all(&trailer).await.context("write crc")?;
+        }
+        None => {
+            w.write_u32(buf.len() as u32).await.context("write len")?;
+            write_throttled(w, buf).await.context("write payload")?;
+        }
+    }
+    // Pushes a deflated frame out now; a no-op on plain TCP.
+    w.flush().await.context("flush")?;
+    Ok(())
+}
+
+/// Piece of a streamed payload read and written at a time.
+const FRAME_WRITE_CHUNK: usize = 64 * 1024;
+
+/// Write `msg` as one frame like [`write_frame`], with its payload streamed from `payload`
+/// (`payload_len` bytes) instead of held in the message.
+pub async fn write_frame_streamed<W, R>(
+    w: &mut W,
+    msg: &utils::Message,
+    payload: &mut R,
+    payload_len: u64,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    write_frame_streamed_as(w, msg, payload, payload_len, frame_crc_enabled()).await
+}
+
+async fn write_frame_streamed_as<W, R>(
+    w: &mut W,
+    msg: &utils::Message,
+    payload: &mut R,
+    payload_len: u64,
+    crc: bool,
+) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let parts = msg.frame_parts(payload_len as usize, crc);
+    let trailer_len = if crc { 4 } else { 0 };
+    let len = (parts.magic.len() + parts.head.len() + parts.tail.len() + trailer_len) as u64
+        + payload_len;
+    let len = u32::try_from(len).map_err(|_| anyhow::anyhow!("frame too large: {len} bytes"))?;
+    let mut sum = utils::Crc32::new();
+    w.write_u32(len).await.context("write len")?;
+    w.write_all(parts.magic).await.context("write magic")?;
+    sum.update(&parts.head);
+    write_throttled(w, &parts.head)
         .await
-        .context("write len")?;
-    stream.write_all(&buf).await.context("write payload")?;
+        .context("write header")?;
+
+    let mut buf = vec![0u8; FRAME_WRITE_CHUNK.min(payload_len as usize)];
+    let mut left = payload_len;
+    while left > 0 {
+        let want = buf.len().min(left as usize);
+        let n = payload
+            .read(&mut buf[..want])
+            .await
+            .context("read payload")?;
+        anyhow::ensure!(n > 0, "payload ended {} bytes short", left);
+        sum.update(&buf[..n]);
+        write_throttled(w, &buf[..n])
+            .await
+            .context("write payload")?;
+        left -= n as u64;
+    }
+
+    sum.update(&parts.tail);
+    write_throttled(w, &parts.tail)
+        .await
+        .context("write trailer")?;
+    if crc {
+        w.write_all(&sum.finish().to_be_bytes())
+            .await
+            .context("write crc")?;
+    }
+    w.flush().await.context("flush")?;
     Ok(())
 }
 
-pub async fn send_join(
-    writer: &mut tokio::net::tcp::OwnedWriteHalf,
+/// Initial buffer for an incoming frame body; it grows only as bytes actually arrive.
+const FRAME_READ_CHUNK: usize = 64 * 1024;
+
+/// Read the body of a frame whose length prefix announced `len` bytes.
+///
+/// Lengths above `MAX_FRAME_BYTES` are refused before anything is read or allocated. Below
+/// that, the buffer follows the bytes received instead of trusting the prefix, so a peer that
+/// announces a huge frame and then stalls or disconnects costs only what it really sent.
+pub async fn read_frame_body<R: AsyncRead + Unpin>(
+    r: &mut R,
+    len: usize,
+) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(
+        len <= utils::MAX_FRAME_BYTES,
+        "frame too large: len={} max={}",
+        len,
+        utils::MAX_FRAME_BYTES
+    );
+    let mut buf = Vec::with_capacity(len.min(FRAME_READ_CHUNK));
+    let n = (&mut *r)
+        .take(len as u64)
+        .read_to_end(&mut buf)
+        .await
+        .context("read payload")?;
+    anyhow::ensure!(n == len, "truncated frame: got {} of {} bytes", n, len);
+    Ok(buf)
+}
+
+/// Tests: the next frame's body, as it came over the wire.
+#[cfg(test)]
+pub(crate) async fn read_frame<R: AsyncRead + Unpin>(r: &mut R) -> Vec<u8> {
+    let len = r.read_u32().await.unwrap() as usize;
+    read_frame_body(r, len).await.unwrap()
+}
+
+/// Tests: the next frame, decoded.
+#[cfg(test)]
+pub(crate) async fn read_msg<R: AsyncRead + Unpin>(r: &mut R) -> utils::Message {
+    utils::Message::try_from_bytes(&read_frame(r).await).unwrap()
+}
+
+pub async fn send_join<W: AsyncWrite + Unpin>(
+    writer: &mut W,
     device_id: &str,
     device_name: &str,
     room: &str,
 ) -> anyhow::Result<()> {
-    let mut join = utils::Message::new_join(device_id, room);
-    if !device_name.trim().is_empty() {
-        join.sender_name = Some(device_name.to_string());
-    }
-    let join = join.to_bytes();
+    let join = utils::Message::new_join(device_id, room);
+    write_join(writer, join, device_name).await
+}
+
+/// [`send_join`] announcing [`local_hello`]: the relay answers with its own `Hello` and
+/// passes on the room's peers'. Relays from before the handshake take it as a plain Join.
+pub async fn send_join_hello<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    device_id: &str,
+    device_name: &str,
+    room: &str,
+) -> anyhow::Result<()> {
+    let join = utils::Message::new_join_hello(device_id, room, &local_hello());
+    write_join(writer, join, device_name).await
+}
+
+async fn write_join<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    mut join: utils::Message,
+    device_name: &str,
+) -> anyhow::Result<()> {
+    crate::device::set_sender_name(&mut join, device_name);
+    join.channel = crate::channel::join_channels();
+    let bytes = join.to_bytes();
     log::debug!(
-        "send_join: room={} device_id={} name_present={} bytes={}",
-        room,
-        device_id,
+        "send_join: room={} device_id={} name_present={} hello={} bytes={}",
+        join.room,
+        join.device_id,
         !device_name.trim().is_empty(),
-        join.len()
+        join.hello().is_some(),
+        bytes.len()
     );
     writer
-        .write_u32(join.len() as u32)
+        .write_u32(bytes.len() as u32)
         .await
         .context("write join len")?;
-    writer.write_all(&join).await.context("write join")?;
+    writer.write_all(&bytes).await.context("write join")?;
+    writer.flush().await.context("flush join")?;
     Ok(())
 }
+
+/// What this node can decode, announced in the first Join (see [`send_join_hello`]).
+pub fn local_hello() -> Hello {
+    let mut caps = vec![CAP_EXTRA_MIME, CAP_ACK];
+    if compress_enabled() {
+        caps.push(CAP_DEFLATE);
+    }
+    Hello::new(&caps)
+}
+
+/// Default `--ack-timeout`: how long `--wait-ack` waits for a peer to apply a send.
+pub const ACK_WAIT: Duration = Duration::from_secs(10);
+
+/// Whether to ack `msg` once applied: its sender asked (`--wait-ack`) and the relay's `Hello`
+/// (`relay`) says it passes acks on. Older relays drop the connection on a `Kind::Ack`.
+pub fn ack_wanted(relay: Option<&Hello>, msg: &utils::Message) -> bool {
+    msg.want_ack && relay.is_some_and(|h| h.has(CAP_ACK))
+}
+
+/// Tell the room `applied` landed on this clipboard (see `Kind::Ack`).
+pub async fn send_ack<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    device_id: &str,
+    device_name: &str,
+    applied: &utils::Message,
+) -> anyhow::Result<()> {
+    let mut ack = utils::Message::new_ack(device_id, applied);
+    crate::device::set_sender_name(&mut ack, device_name);
+    write_frame(writer, &ack.to_bytes()).await
+}
+
+/// `--wait-ack`: join `room` and announce [`local_hello`] before sending, so the relay
+/// passes peers' acks back on this connection.
+pub async fn join_for_ack<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    device_id: &str,
+    device_name: &str,
+    room: &str,
+) -> anyhow::Result<()> {
+    send_join_hello(writer, device_id, device_name, room).await
+}
+
+/// Wait until a peer acks `event_id`; returns who did (display name, else device id).
+pub async fn wait_for_ack<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    event_id: &str,
+    wait: Duration,
+) -> anyhow::Result<String> {
+    use utils::{Kind, Message};
+
+    let deadline = Instant::now() + wait;
+    loop {
+        let read = async {
+            let len = reader.read_u32().await.context("read len")? as usize;
+            read_frame_body(reader, len).await
+        };
+        let buf = tokio::time::timeout_at(deadline, read)
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!("no peer acknowledged the send within {}s", wait.as_secs())
+            })??;
+        let msg = match Message::try_from_bytes(&buf) {
+            Ok(m) => m,
+            Err(e) => {
+                log::debug!("wait-ack: skipping undecodable frame: {e}");
+                continue;
+            }
+        };
+        if let Some(reason) = msg.rejection() {
+            anyhow::bail!("relay refused connection: {reason}");
+        }
+        if matches!(msg.kind, Kind::Ack) && msg.event_id == event_id {
+            return Ok(msg.sender_name.unwrap_or(msg.device_id));
+        }
+    }
+}
+
+/// How long `history pull` waits for each scrollback frame.
+pub const REPLAY_WAIT: Duration = Duration::from_secs(5);
+
+/// Ask the relay for the room's `--scrollback` and collect it, oldest first.
+pub async fn pull_scrollback(
+    relay: &str,
+    device_id: &str,
+    device_name: &str,
+    room: &str,
+    wait: Duration,
+) -> anyhow::Result<Vec<utils::Message>> {
+    use utils::{Kind, Message};
+
+    let (mut reader, mut writer) = connect(relay).await?.into_split();
+    send_join(&mut writer, device_id, device_name, room).await?;
+    write_frame(
+        &mut writer,
+        &Message::new_replay(device_id, room).to_bytes(),
+    )
+    .await?;
+    let mut out = Vec::new();
+    loop {
+        let read = async {
+            let len = reader.read_u32().await.context("read len")? as usize;
+            read_frame_body(&mut reader, len).await
+        };
+        let buf = tokio::time::timeout(wait, read).await.map_err(|_| {
+            anyhow::anyhow!(
+                "no scrollback answer from {} within {}s (relay too old?)",
+                relay,
+                wait.as_secs()
+            )
+        })??;
+        let msg = match Message::try_from_bytes(&buf) {
+            Ok(m) => m,
+            Err(e) => {
+                log::warn!("history pull: skipping undecodable frame: {e}");
+                continue;
+            }
+        };
+        if let Some(reason) = msg.rejection() {
+            anyhow::bail!("relay refused connection: {reason}");
+        }
+        match msg.kind {
+            Kind::Replay => return Ok(out),
+            Kind::Join | Kind::Hello | Kind::Ping | Kind::Pong | Kind::Ack => {}
+            Kind::Text | Kind::Image | Kind::File => out.push(msg),
+        }
+    }
+}
+
+pub const HEARTBEAT_SECS_ENV: &str = "MCR_HEARTBEAT_SECS";
+const DEFAULT_HEARTBEAT_SECS: u64 = 20;
+
+static HEARTBEAT: OnceLock<Option<Duration>> = OnceLock::new();
+
+fn heartbeat_from_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn heartbeat_from_env() -> Option<Duration> {
+    let secs = std::env::var(HEARTBEAT_SECS_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_HEARTBEAT_SECS);
+    heartbeat_from_secs(secs)
+}
+
+/// Configure the keepalive interval for long-lived relay connections (0 = disabled).
+///
+/// Must be called before the first connection; otherwise the env var (if any) wins.
+pub fn set_heartbeat_secs(secs: Option<u64>) {
+    let _ = HEARTBEAT.set(secs.map_or_else(heartbeat_from_env, heartbeat_from_secs));
+}
+
+pub fn heartbeat_interval() -> Option<Duration> {
+    *HEARTBEAT.get_or_init(heartbeat_from_env)
+}
+
+/// Keepalive schedule for an idle-ish relay connection.
+///
+/// On each beat the caller re-sends its Join (see [`send_join`]): the relay never broadcasts
+/// Joins, and the traffic keeps NAT/stateful firewalls from silently dropping the connection.
+/// A failed heartbeat write is also how a dead connection gets noticed early.
+pub struct Heartbeat {
+    period: Option<Duration>,
+    next: Instant,
+}
+
+impl Heartbeat {
+    /// Uses the configured `--heartbeat-secs`.
+    pub fn new() -> Self {
+        Self::with_period(heartbeat_interval())
+    }
+
+    /// First beat is one `period` from now (`None` = never).
+    pub fn with_period(period: Option<Duration>) -> Self {
+        Self {
+            period,
+            next: Instant::now() + period.unwrap_or_default(),
+        }
+    }
+
+    /// Wait for the next beat (for `select!` loops; cancel-safe).
+    pub async fn tick(&mut self) {
+        let Some(period) = self.period else {
+            return std::future::pending().await;
+        };
+        tokio::time::sleep_until(self.next).await;
+        self.next = Instant::now() + period;
+    }
+
+    /// Non-blocking variant for polling loops: true (and reschedule) once a beat is due.
+    pub fn due(&mut self) -> bool {
+        let Some(period) = self.period else {
+            return false;
+        };
+        let now = Instant::now();
+        if now < self.next {
+            return false;
+        }
+        self.next = now + period;
+        true
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn heartbeat_sends_join_at_configured_cadence() {
+        let period = Duration::from_millis(60);
+        let (mut writer, mut reader) = tokio::io::duplex(4096);
+        let started = Instant::now();
+        let beats = tokio::spawn(async move {
+            let mut hb = Heartbeat::with_period(Some(period));
+            assert!(!hb.due(), "no beat before the first period");
+            for _ in 0..3 {
+                hb.tick().await;
+                send_join(&mut writer, "dev", "name", "room").await.unwrap();
+            }
+        });
+
+        for i in 1..=3u32 {
+            let msg = read_msg(&mut reader).await;
+            assert!(matches!(msg.kind, utils::Kind::Join));
+            assert_eq!(msg.room, "room");
+            assert!(started.elapsed() >= period * i, "beat {i} came early");
+        }
+        beats.await.unwrap();
+
+        let mut off = Heartbeat::with_period(None);
+        assert!(!off.due());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(30), off.tick())
+                .await
+                .is_err()
+        );
+    }
+
+    /// Accept one connection and read its first frame raw.
+    async fn accept_hello(listener: &tokio::net::TcpListener) -> (TcpStream, Vec<u8>) {
+        let (mut conn, _) = listener.accept().await.unwrap();
+        let hello = read_frame(&mut conn).await;
+        (conn, hello)
+    }
+
+    #[tokio::test]
+    async fn deflate_is_used_only_when_the_relay_agrees() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // A relay that agrees: both directions are deflated after its answer.
+        let relay = async {
+            let (mut conn, hello) = accept_hello(&listener).await;
+            assert_eq!(parse_conn_hello(&hello), Some(CONN_CODEC_DEFLATE));
+            let answer = conn_hello(CONN_CODEC_DEFLATE);
+            conn.write_u32(answer.len() as u32).await.unwrap();
+            conn.write_all(&answer).await.unwrap();
+            let (r, w) = conn.into_split();
+            let mut r = DeflateDecoder::new(BufReader::new(r));
+            let buf = read_frame(&mut r).await;
+            let mut w = DeflateEncoder::new(w);
+            write_frame(&mut w, &buf).await.unwrap();
+            buf
+        };
+        let client = async {
+            let s = TcpStream::connect(addr).await.unwrap();
+            let negotiated = negotiate_deflate(s, Duration::from_secs(2)).await;
+            let Ok(Negotiated::Answered(stream)) = negotiated else {
+                panic!("relay answered");
+            };
+            let (mut r, mut w) = stream.into_split();
+            send_join(&mut w, "dev", "", "room").await.unwrap();
+            read_frame(&mut r).await
+        };
+        let (got, echo) = tokio::join!(relay, client);
+        let msg = utils::Message::try_from_bytes(&got).unwrap();
+        assert!(matches!(msg.kind, utils::Kind::Join));
+        assert!(utils::Message::try_from_bytes(&echo).is_ok());
+
+        // A relay that never answers: fall back to plain frames after the wait.
+        let relay = async {
+            let (mut conn, _hello) = accept_hello(&listener).await;
+            read_frame(&mut conn).await
+        };
+        let client = async {
+            let s = TcpStream::connect(addr).await.unwrap();
+            let negotiated = negotiate_deflate(s, Duration::from_millis(100)).await;
+            let Ok(Negotiated::Silent(mut stream)) = negotiated else {
+                panic!("no answer expected");
+            };
+            send_join(&mut stream, "dev", "", "room").await.unwrap();
+        };
+        let (got, ()) = tokio::join!(relay, client);
+        assert!(utils::Message::try_from_bytes(&got).is_ok());
+
+        // A relay from before compression drops the connection on the hello it can't decode.
+        let relay = async {
+            let (conn, _hello) = accept_hello(&listener).await;
+            drop(conn);
+        };
+        let client = async {
+            let s = TcpStream::connect(addr).await.unwrap();
+            negotiate_deflate(s, Duration::from_secs(2)).await
+        };
+        let ((), negotiated) = tokio::join!(relay, client);
+        assert!(matches!(negotiated, Ok(Negotiated::Closed)));
+    }
+
+    #[tokio::test]
+    async fn relays_without_compression_are_remembered_for_a_while() {
+        let dir = tempfile::tempdir().unwrap();
+        let st = dir.path();
+        assert!(!known_plain(st, "relay:8080").await);
+        remember_plain(st, "relay:8080").await;
+        assert!(known_plain(st, "relay:8080").await);
+        assert!(!known_plain(st, "other:8080").await);
+
+        // Asked again once the memo is old (the relay may have been upgraded).
+        std::fs::write(plain_relay_path(st, "relay:8080"), "1\n").unwrap();
+        assert!(!known_plain(st, "relay:8080").await);
+    }
+
+    #[tokio::test]
+    async fn wait_for_ack_resolves_on_the_applier_ack() {
+        use utils::Message;
+
+        let sent = Message::new_text("sender", "room", "hi");
+        let (mut relay, mut sender) = tokio::io::duplex(4096);
+        // The relay's hello answer and an ack for some other send come first.
+        let noise = Message::new_hello("relay", "room", &local_hello());
+        write_frame(&mut relay, &noise.to_bytes()).await.unwrap();
+        let other = Message::new_text("sender", "room", "earlier");
+        send_ack(&mut relay, "laptop", "", &other).await.unwrap();
+        send_ack(&mut relay, "laptop", "Laptop", &sent)
+            .await
+            .unwrap();
+
+        let by = wait_for_ack(&mut sender, &sent.event_id, Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(by, "Laptop");
+
+        // Nobody applied it: the wait gives up.
+        let err = wait_for_ack(&mut sender, &sent.event_id, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        let err = format!("{err:#}");
+        assert!(err.contains("no peer acknowledged"), "{err}");
+    }
+
+    #[test]
+    fn acks_only_when_asked_and_the_relay_passes_them_on() {
+        let mut msg = utils::Message::new_text("sender", "room", "hi");
+        let relay = Hello::new(&[CAP_ACK]);
+        assert!(!ack_wanted(Some(&relay), &msg), "sender didn't ask");
+        msg.want_ack = true;
+        assert!(ack_wanted(Some(&relay), &msg));
+        // A relay that never answered our hello, or one that doesn't pass acks on.
+        assert!(!ack_wanted(None, &msg));
+        assert!(!ack_wanted(Some(&Hello::new(&[])), &msg));
+    }
+
+    #[tokio::test]
+    async fn crc_trailer_is_opt_in_and_streamed_in_place() {
+        let frame = utils::Message::new_text("dev", "room", "hi").to_bytes();
+        for crc in [false, true] {
+            let mut out = Vec::new();
+            write_frame_as(&mut out, &frame, crc).await.unwrap();
+            let want = if crc {
+                utils::seal_frame(frame.clone())
+            } else {
+                frame.clone()
+            };
+            assert_eq!(&out[..4], (want.len() as u32).to_be_bytes());
+            assert_eq!(&out[4..], want);
+            let got = utils::Message::try_from_bytes(&out[4..]).unwrap();
+            assert_eq!(got.payload.as_deref(), Some(&b"hi"[..]));
+        }
+        // Anything but a message frame goes out untouched.
+        let mut out = Vec::new();
+        write_frame_as(&mut out, b"MCRZ\x01", true).await.unwrap();
+        assert_eq!(&out[4..], b"MCRZ\x01");
+    }
+
+    #[tokio::test]
+    async fn streamed_payload_frames_match_in_memory_ones() {
+        let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let msg = utils::Message::new_file("dev", "room", "a.tar", "x/y", payload.clone());
+        let mut head_only = msg.clone();
+        head_only.payload = None;
+        for crc in [false, true] {
+            let mut want = Vec::new();
+            write_frame_as(&mut want, &msg.to_bytes(), crc)
+                .await
+                .unwrap();
+            let mut out = Vec::new();
+            let mut src = payload.as_slice();
+            write_frame_streamed_as(&mut out, &head_only, &mut src, payload.len() as u64, crc)
+                .await
+                .unwrap();
+            assert_eq!(out, want, "crc={crc}");
+        }
+
+        // A payload source that runs dry fails the write instead of sending a short frame.
+        let mut short = &payload[..10];
+        let err = write_frame_streamed_as(&mut Vec::new(), &head_only, &mut short, 20, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("10 bytes short"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn oversized_frame_is_refused_before_reading() {
+        let (mut w, mut r) = tokio::io::duplex(4096);
+        let frame = utils::Message::new_text("dev", "room", "hi").to_bytes();
+        w.write_all(&frame).await.unwrap();
+        drop(w);
+
+        let err = read_frame_body(&mut r, utils::MAX_FRAME_BYTES + 1)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("too large"), "{err:#}");
+        // Nothing was consumed, so the stream is still usable.
+        let buf = read_frame_body(&mut r, frame.len()).await.unwrap();
+        assert_eq!(buf, frame);
+        assert!(utils::Message::try_from_bytes(&buf).is_ok());
+
+        // A peer announcing the maximum and then hanging up only costs what it sent.
+        let (mut w, mut r) = tokio::io::duplex(4096);
+        w.write_all(b"MCR2").await.unwrap();
+        drop(w);
+        let err = read_frame_body(&mut r, utils::MAX_FRAME_BYTES)
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("got 4 of"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn connect_gives_up_on_a_black_holed_relay() {
+        // A listener that never accepts, with its backlog full, drops further SYNs: the same
+        // silence as a firewall that black-holes the relay port.
+        let sock = tokio::net::TcpSocket::new_v4().unwrap();
+        sock.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = sock.listen(1).unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut queued = Vec::new();
+        for _ in 0..8 {
+            let tcp = TcpStream::connect(&addr);
+            match tokio::time::timeout(Duration::from_millis(100), tcp).await {
+                Ok(s) => queued.push(s.unwrap()),
+                Err(_) => break,
+            }
+        }
+
+        let timeout = Duration::from_millis(300);
+        let started = Instant::now();
+        let err = connect_within(None, &addr, Some(timeout))
+            .await
+            .err()
+            .expect("connected past a full backlog");
+        let took = started.elapsed();
+        assert!(took >= timeout && took < timeout * 3, "took {took:?}");
+        let err = format!("{err:#}");
+        assert!(err.contains("timed out after 300ms"), "{err}");
+    }
+}
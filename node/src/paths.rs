@@ -1,55 +1,226 @@
-use std::path::PathBuf;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use crate::consts::{APP_DIR_NAME, TAR_MIME};
+use crate::consts::APP_DIR_NAME;
 
-pub fn default_state_dir() -> PathBuf {
-    if let Ok(d) = std::env::var("XDG_RUNTIME_DIR") {
-        let base = PathBuf::from(d);
-        return base.join(APP_DIR_NAME);
-    }
-    let uid = unsafe { libc::geteuid() };
-    PathBuf::from(format!("/tmp/{}-{}", APP_DIR_NAME, uid))
+// Shared with the UIs, so they look exactly where node writes.
+pub use utils::paths::{
+    default_config_dir, default_data_dir, default_state_dir, first_8, is_tar_payload,
+    kept_text_path, received_file_path, safe_for_filename, DATA_DIR_ENV, RECEIVED_DIR_ENV,
+    STATE_DIR_ENV,
+};
+
+/// EnvironmentFile shared with the systemd user units (written by the UIs too).
+pub fn systemd_env_path() -> PathBuf {
+    default_config_dir().join("multicliprelay.env")
+}
+
+static RECEIVED_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn default_received_dir() -> PathBuf {
+    default_data_dir().join("received")
+}
+
+/// Resolve a user-supplied destination: expand `~`, make it absolute, create it and make sure
+/// we can actually write there (so a typo fails at startup, not on the first transfer).
+pub fn prepare_received_dir(input: &str) -> anyhow::Result<PathBuf> {
+    let s = input.trim();
+    anyhow::ensure!(!s.is_empty(), "received dir is empty");
+    let p = match s.strip_prefix("~") {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = std::env::var("HOME").context("expand ~: HOME is not set")?;
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(s),
+    };
+    let p = if p.is_absolute() {
+        p
+    } else {
+        std::env::current_dir().context("cwd")?.join(p)
+    };
+
+    std::fs::create_dir_all(&p).with_context(|| format!("create {}", p.display()))?;
+    let probe = p.join(format!(".{}-write-test-{}", APP_DIR_NAME, std::process::id()));
+    std::fs::write(&probe, b"")
+        .with_context(|| format!("received dir is not writable: {}", p.display()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(p)
+}
+
+/// Override where received files/previews are stored (process-wide, set once at startup).
+pub fn set_received_dir(input: &str) -> anyhow::Result<PathBuf> {
+    let p = prepare_received_dir(input)?;
+    let _ = RECEIVED_DIR.set(p.clone());
+    Ok(p)
 }
 
-pub fn default_data_dir() -> PathBuf {
-    if let Ok(d) = std::env::var("XDG_DATA_HOME") {
-        let base = PathBuf::from(d);
-        return base.join(APP_DIR_NAME);
+fn received_dir_from_env() -> Option<PathBuf> {
+    let v = std::env::var(RECEIVED_DIR_ENV).ok()?;
+    if v.trim().is_empty() {
+        return None;
     }
-    if let Ok(home) = std::env::var("HOME") {
-        let base = PathBuf::from(home).join(".local/share");
-        return base.join(APP_DIR_NAME);
+    match prepare_received_dir(&v) {
+        Ok(p) => Some(p),
+        Err(e) => {
+            log::warn!("ignoring {}: {:#}", RECEIVED_DIR_ENV, e);
+            None
+        }
     }
-    // Last resort: state_dir should exist; fall back to /tmp.
-    let base = PathBuf::from("/tmp");
-    base.join(APP_DIR_NAME)
 }
 
 pub fn received_dir() -> PathBuf {
-    default_data_dir().join("received")
+    RECEIVED_DIR
+        .get_or_init(|| received_dir_from_env().unwrap_or_else(default_received_dir))
+        .clone()
+}
+
+/// Write a received single-file payload as `<base>/<sha8>/<name>` and return the path.
+///
+/// The bytes go to a hidden temp file first and are renamed into place, so a file manager
+/// pasting the clipboard URI right away never reads a partially written file.
+pub async fn store_received_file(
+    base: &Path,
+    sha8: &str,
+    safe_name: &str,
+    payload: &[u8],
+) -> std::io::Result<PathBuf> {
+    let out_dir = base.join(sha8);
+    tokio::fs::create_dir_all(&out_dir).await?;
+    let out_path = out_dir.join(safe_name);
+    let tmp = out_dir.join(format!(".mcr-{}.partial", std::process::id()));
+    if let Err(e) = tokio::fs::write(&tmp, payload).await {
+        let _ = tokio::fs::remove_file(&tmp).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&tmp, &out_path).await?;
+    Ok(out_path)
 }
 
 pub fn history_path() -> PathBuf {
     default_data_dir().join("history.jsonl")
 }
 
-pub fn safe_for_filename(s: &str) -> String {
-    s.chars()
-        .map(|c| match c {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
-            _ => '_',
-        })
-        .collect()
+/// Where sends and receives are logged and their payloads kept.
+///
+/// Commands resolve it once ([`DataDirs::resolve`]) and hand it down to what records history,
+/// so tests can point it at a temp dir instead ([`DataDirs::under`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDirs {
+    /// The history log ([`history_path`]).
+    pub history: PathBuf,
+    /// Received payloads and kept sends ([`received_dir`]).
+    pub received: PathBuf,
 }
 
-pub fn is_tar_payload(name: &str, mime: Option<&str>) -> bool {
-    mime == Some(TAR_MIME) || name.to_ascii_lowercase().ends_with(".tar")
+impl DataDirs {
+    /// The user's dirs: [`history_path`] and [`received_dir`].
+    pub fn resolve() -> Self {
+        Self {
+            history: history_path(),
+            received: received_dir(),
+        }
+    }
+
+    /// Laid out like the default data dir, under `dir`.
+    pub fn under(dir: &Path) -> Self {
+        Self {
+            history: dir.join("history.jsonl"),
+            received: dir.join("received"),
+        }
+    }
 }
 
-pub fn first_8(s: &str) -> &str {
-    if s.len() >= 8 {
-        &s[..8]
-    } else {
-        s
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn received_files_land_in_overridden_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let wanted = tmp.path().join("Downloads/mcr");
+        let dir = prepare_received_dir(wanted.to_str().unwrap()).unwrap();
+        assert_eq!(dir, wanted);
+        assert!(dir.is_dir());
+
+        let out = store_received_file(&dir, "deadbeef", "note.txt", b"hi")
+            .await
+            .unwrap();
+        assert_eq!(out, wanted.join("deadbeef").join("note.txt"));
+        assert_eq!(std::fs::read(&out).unwrap(), b"hi");
+        // The write probe must not be left behind.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert!(prepare_received_dir("  ").is_err());
+    }
+
+    #[tokio::test]
+    async fn received_file_appears_only_when_complete() {
+        let tmp = tempfile::tempdir().unwrap();
+        let payload = vec![7u8; 16 * 1024 * 1024];
+        let out = tmp.path().join("cafebabe").join("big.bin");
+
+        // Watch the final path while the write is in flight, like an eager file manager.
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let watcher = {
+            let (out, done, len) = (out.clone(), done.clone(), payload.len() as u64);
+            std::thread::spawn(move || {
+                while !done.load(std::sync::atomic::Ordering::SeqCst) {
+                    if let Ok(md) = std::fs::metadata(&out) {
+                        assert_eq!(md.len(), len, "final path visible before fully written");
+                    }
+                }
+            })
+        };
+        let stored = store_received_file(tmp.path(), "cafebabe", "big.bin", &payload)
+            .await
+            .unwrap();
+        done.store(true, std::sync::atomic::Ordering::SeqCst);
+        watcher.join().unwrap();
+
+        assert_eq!(stored, out);
+        assert_eq!(std::fs::metadata(&out).unwrap().len(), payload.len() as u64);
+        // No temp file left next to it.
+        assert_eq!(std::fs::read_dir(out.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn ui_and_node_agree_on_received_file_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sha = crate::hash::fingerprint(b"report");
+        let name = "Q3 report (final).pdf";
+
+        // What wl-apply writes ...
+        let stored = store_received_file(
+            tmp.path(),
+            first_8(&sha),
+            &safe_for_filename(name),
+            b"report",
+        )
+        .await
+        .unwrap();
+        // ... is where the UI history looks for the preview.
+        assert_eq!(
+            stored,
+            utils::paths::received_file_path(tmp.path(), &sha, Some(name), Some("application/pdf"))
+        );
+
+        // Both sides resolve the same dirs from the env overrides.
+        let data = tmp.path().as_os_str().to_owned();
+        let env = |k: &str| match k {
+            DATA_DIR_ENV => Some(data.clone()),
+            STATE_DIR_ENV => Some("~/state".into()),
+            "HOME" => Some("/home/me".into()),
+            _ => None,
+        };
+        assert_eq!(utils::paths::resolve_data_dir(&env), tmp.path());
+        assert_eq!(
+            utils::paths::resolve_received_dir(&env),
+            tmp.path().join("received")
+        );
+        assert_eq!(
+            utils::paths::resolve_state_dir(&env),
+            Path::new("/home/me/state")
+        );
     }
 }
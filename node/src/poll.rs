@@ -0,0 +1,1130 @@
+//! Poll mode (`wl-watch --mode poll`): the loop and its cadence.
+//!
+//! Reading text is cheap, so it can be checked often. File lists, `--extra-mime` types and
+//! images mean large reads and hashing, so they run on their own, usually slower, clock.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use tokio::io::AsyncWrite;
+use tokio::sync::watch;
+use utils::Kind;
+use walkdir::WalkDir;
+
+use crate::clipboard::{watch_allows, ClipboardSource};
+use crate::consts::{
+    APPLIED_MARKER_MIME, GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME,
+};
+use crate::content_filter::filter_outgoing;
+use crate::events::emit_event;
+use crate::extra_mime::extra_mimes;
+use crate::hash::{fingerprint, Fingerprinter};
+use crate::history::record_send;
+use crate::image_mode::ImageMode;
+use crate::net::{connect_in, send_join, write_frame, Heartbeat, RelayWriter};
+use crate::paths::DataDirs;
+use crate::publish::{
+    build_message, dispatch, is_own_selection, large_text_message, pick_text_mime, prepare_payload,
+    promotes_large_text, record_too_large, suppress_text_after_files, text_only, Dispatch,
+    PublishCtx, PublishLimits,
+};
+use crate::reload::LiveConfig;
+use crate::resend::{keep_text_enabled, persist_image_to, persist_text_best_effort};
+use crate::room::watch_room;
+use crate::say;
+use crate::suppress::{is_paused, is_recently_applied, is_suppressed};
+use crate::throttle::{max_events_per_sec, EventGate};
+use crate::transfer_file::send_paths_as_file;
+use crate::transfer_image::image_mimes;
+
+/// How often each category is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollIntervals {
+    pub text: Duration,
+    pub media: Duration,
+}
+
+impl PollIntervals {
+    /// `--text-poll-ms` / `--media-poll-ms`, each defaulting to `--interval-ms`.
+    pub fn from_ms(interval_ms: u64, text_ms: Option<u64>, media_ms: Option<u64>) -> Self {
+        Self {
+            text: Duration::from_millis(text_ms.unwrap_or(interval_ms)),
+            media: Duration::from_millis(media_ms.unwrap_or(interval_ms)),
+        }
+    }
+}
+
+/// What a poll tick should check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Due {
+    pub text: bool,
+    pub media: bool,
+}
+
+/// Independent next-check instants for text and media.
+#[derive(Debug)]
+pub struct PollSchedule {
+    intervals: PollIntervals,
+    next_text: Instant,
+    next_media: Instant,
+}
+
+impl PollSchedule {
+    /// Both categories are due right away.
+    pub fn new(intervals: PollIntervals, now: Instant) -> Self {
+        Self {
+            intervals,
+            next_text: now,
+            next_media: now,
+        }
+    }
+
+    /// Which categories are due at `now`; those are rescheduled one interval later.
+    pub fn due(&mut self, now: Instant) -> Due {
+        let due = Due {
+            text: now >= self.next_text,
+            media: now >= self.next_media,
+        };
+        if due.text {
+            self.next_text = now + self.intervals.text;
+        }
+        if due.media {
+            self.next_media = now + self.intervals.media;
+        }
+        due
+    }
+
+    /// When the next category comes due.
+    pub fn next_wakeup(&self) -> Instant {
+        self.next_text.min(self.next_media)
+    }
+}
+
+/// Default `--file-resend-cooldown-ms`.
+pub const FILE_RESEND_COOLDOWN_MS: u64 = 30_000;
+
+/// The file selection last sent, and when.
+///
+/// A file selection stays on the clipboard and is read again on every media tick; the same
+/// selection only goes out again once `cooldown` has passed. It is keyed on the paths and
+/// their metadata ([`FileCooldown::key`]), so a repeat is turned away before anything is
+/// bundled. This is independent of the short feedback-loop suppression around wl-apply
+/// (`node::suppress`).
+#[derive(Debug)]
+pub struct FileCooldown {
+    cooldown: Duration,
+    last: Option<(String, Instant)>,
+}
+
+impl FileCooldown {
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            last: None,
+        }
+    }
+
+    /// The cooldown key of a selection: its paths with the size and mtime of each file in it,
+    /// folders walked, so a file rewritten in place (at any depth) counts as new content.
+    pub async fn key(paths: &[PathBuf]) -> String {
+        let paths = paths.to_vec();
+        let key = tokio::task::spawn_blocking(move || {
+            let mut fp = Fingerprinter::new();
+            for p in &paths {
+                fp.update(p.as_os_str().as_encoded_bytes());
+                // A plain file yields just itself.
+                let entries = WalkDir::new(p).follow_links(false).sort_by_file_name();
+                for e in entries.into_iter().filter_map(|e| e.ok()) {
+                    let Ok(md) = e.metadata() else {
+                        continue;
+                    };
+                    let mtime = md
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+                    let mtime = mtime.map_or(0, |d| d.as_nanos());
+                    let rel = e.path().strip_prefix(p).unwrap_or(e.path());
+                    fp.update(rel.as_os_str().as_encoded_bytes());
+                    fp.update(format!("\0{}\0{}\0", md.len(), mtime).as_bytes());
+                }
+            }
+            fp.finish()
+        });
+        key.await.unwrap_or_default()
+    }
+
+    /// Whether the selection `key` may be sent at `now`.
+    pub fn allows(&self, key: &str, now: Instant) -> bool {
+        match &self.last {
+            Some((last, at)) if last == key => now.duration_since(*at) >= self.cooldown,
+            _ => true,
+        }
+    }
+
+    pub fn record(&mut self, key: String, now: Instant) {
+        self.last = Some((key, now));
+    }
+
+    /// Forget the last bundle (e.g. after a room switch).
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Who polls: the service's state and data dirs and device.
+#[derive(Debug, Clone)]
+pub struct PollCtx {
+    pub state_dir: PathBuf,
+    pub data: DataDirs,
+    pub device_id: String,
+    pub device_name: String,
+}
+
+/// How long poll mode waits before reconnecting to the relay.
+const POLL_RECONNECT_BACKOFF: Duration = Duration::from_millis(800);
+
+/// Connect to `relay` and join `room`; poll mode's (re)connect.
+async fn join_relay(ctx: &PollCtx, relay: &str, room: &str) -> anyhow::Result<RelayWriter> {
+    let (_reader, mut writer) = connect_in(&ctx.state_dir, relay).await?.into_split();
+    send_join(&mut writer, &ctx.device_id, &ctx.device_name, room).await?;
+    Ok(writer)
+}
+
+/// What the poll loop remembers between ticks, across reconnects.
+struct PollState {
+    last_text_hash: Option<String>,
+    last_img_hash: std::collections::HashMap<String, String>,
+    // A file selection is read again every media tick; `--file-resend-cooldown-ms` keeps the
+    // same selection from being bundled and sent again and again.
+    last_file_hash: FileCooldown,
+    last_extra_hash: Option<String>,
+    // Over `--max-events-per-sec`, a change waits for a later poll (hashes stay unrecorded).
+    events: EventGate,
+}
+
+impl PollState {
+    /// Forget what was sent (a new room gets the current clipboard too).
+    fn clear(&mut self) {
+        self.last_text_hash = None;
+        self.last_img_hash.clear();
+        self.last_file_hash.clear();
+        self.last_extra_hash = None;
+    }
+}
+
+/// wl-watch's poll mode: read `clip` on the [`PollSchedule`] and publish what changed over one
+/// joined relay connection, following room switches and SIGHUP reloads (`live`). Reconnects on
+/// its own and runs until cancelled.
+pub async fn poll_loop<C: ClipboardSource>(
+    clip: &C,
+    ctx: &PollCtx,
+    room: &str,
+    intervals: PollIntervals,
+    file_resend_cooldown: Duration,
+    mut live: watch::Receiver<LiveConfig>,
+    watch_mime_allow: &[String],
+) -> anyhow::Result<()> {
+    let mut cfg = live.borrow_and_update().clone();
+    let mut st = PollState {
+        last_text_hash: None,
+        last_img_hash: std::collections::HashMap::new(),
+        last_file_hash: FileCooldown::new(file_resend_cooldown),
+        last_extra_hash: None,
+        events: EventGate::new(max_events_per_sec()),
+    };
+
+    // Follow room switch requests (see `node::room`) on the existing connection.
+    let mut room_rx = watch_room(&ctx.state_dir, room);
+    let mut current_room = room.to_string();
+    // `None` until (re)connected; a failed write drops the connection and the next tick
+    // reconnects, like wl-apply does.
+    let mut writer: Option<RelayWriter> = None;
+    // Nothing is written while the clipboard is idle; keep the connection alive meanwhile.
+    let mut hb = Heartbeat::new();
+    // Text every `--text-poll-ms`; file lists, extra types and images every `--media-poll-ms`.
+    let mut schedule = PollSchedule::new(intervals, std::time::Instant::now());
+
+    loop {
+        if room_rx.has_changed().unwrap_or(false) {
+            let next = room_rx.borrow_and_update().clone();
+            if let Some(w) = writer.as_mut() {
+                if let Err(e) = send_join(w, &ctx.device_id, &ctx.device_name, &next).await {
+                    log::warn!("wl-watch(poll): join failed (will reconnect): {e:#}");
+                    writer = None;
+                }
+            }
+            say!(
+                "wl-watch(poll): switched room '{}' -> '{}'",
+                current_room,
+                next
+            );
+            current_room = next;
+            st.clear();
+        }
+        if live.has_changed().unwrap_or(false) {
+            let next = live.borrow_and_update().clone();
+            if next.relay != cfg.relay {
+                say!("wl-watch(poll): relay '{}' -> '{}'", cfg.relay, next.relay);
+                writer = None;
+            }
+            cfg = next;
+        }
+        let w = match writer.as_mut() {
+            Some(w) => w,
+            None => match join_relay(ctx, &cfg.relay, &current_room).await {
+                Ok(w) => {
+                    log::info!(
+                        "wl-watch(poll): connected room='{}' relay='{}'",
+                        current_room,
+                        cfg.relay
+                    );
+                    say!(
+                        "wl-watch(poll): room='{}' relay='{}'",
+                        current_room,
+                        cfg.relay
+                    );
+                    hb = Heartbeat::new();
+                    writer.insert(w)
+                }
+                Err(e) => {
+                    log::warn!("wl-watch(poll): connect failed (will retry): {e:#}");
+                    tokio::time::sleep(POLL_RECONNECT_BACKOFF).await;
+                    continue;
+                }
+            },
+        };
+        if hb.due() {
+            if let Err(e) = send_join(w, &ctx.device_id, &ctx.device_name, &current_room).await {
+                log::warn!("wl-watch(poll): heartbeat failed (will reconnect): {e:#}");
+                writer = None;
+                continue;
+            }
+        }
+        let due = schedule.due(std::time::Instant::now());
+        let tick = poll_tick(
+            clip,
+            ctx,
+            &current_room,
+            &cfg,
+            watch_mime_allow,
+            due,
+            w,
+            &mut st,
+        );
+        if let Err(e) = tick.await {
+            log::warn!("wl-watch(poll): send failed (will reconnect): {e:#}");
+            writer = None;
+        }
+        tokio::time::sleep_until(schedule.next_wakeup().into()).await;
+    }
+}
+
+/// One poll of the clipboard: publish whatever changed on `w`, of the types `allow`
+/// (`--watch-mimes`) lets through. An error means the connection is no longer usable.
+#[allow(clippy::too_many_arguments)]
+async fn poll_tick<C: ClipboardSource, W: AsyncWrite + Unpin>(
+    clip: &C,
+    ctx: &PollCtx,
+    room: &str,
+    cfg: &LiveConfig,
+    allow: &[String],
+    due: Due,
+    writer: &mut W,
+    st: &mut PollState,
+) -> anyhow::Result<()> {
+    let (relay, image_mode) = (cfg.relay.as_str(), cfg.image_mode);
+    let (max_text_bytes, max_image_bytes, max_file_bytes) = cfg.caps();
+    if is_paused(&ctx.state_dir, room).await {
+        // Nothing is sent, but what is copied meanwhile counts as seen: it must not go out
+        // on resume either.
+        remember_current(clip, cfg, allow, due, st).await;
+        return Ok(());
+    }
+    let PollState {
+        last_text_hash,
+        last_img_hash,
+        last_file_hash,
+        last_extra_hash,
+        events,
+    } = st;
+    let cx = PublishCtx {
+        state_dir: &ctx.state_dir,
+        data: &ctx.data,
+        device_id: &ctx.device_id,
+        device_name: &ctx.device_name,
+        room,
+        relay,
+        dry_run: false,
+    };
+
+    // If wl-apply recently wrote the clipboard, it will include our marker MIME.
+    // Avoid polling and re-sending during that window.
+    let types = clip.list_types().await;
+    if types
+        .as_ref()
+        .is_some_and(|t| t.iter().any(|t| t == APPLIED_MARKER_MIME))
+    {
+        return Ok(());
+    }
+
+    // files (uri-list / KDE / gnome); text-only skips file selections entirely
+    let mut list_bytes: Option<(&str, Vec<u8>)> = None;
+    let list_mimes: &[&str] = if text_only() || !due.media {
+        &[]
+    } else {
+        &[URI_LIST_MIME, KDE_URI_LIST_MIME, GNOME_COPIED_FILES_MIME]
+    };
+    for &mime in list_mimes {
+        if let Ok(b) = clip.read(mime).await {
+            if !b.is_empty() {
+                list_bytes = Some((mime, b));
+                break;
+            }
+        }
+    }
+    if let Some((list_mime, list_bytes)) = list_bytes {
+        if !watch_allows(allow, list_mime) {
+            // Files are off: skip the copy as a whole, as the watchers do, rather than sending
+            // its `file://` text.
+            return Ok(());
+        }
+        // Our own apply, read back: the exact list wl-apply wrote is suppressed.
+        let own = is_own_selection(&ctx.state_dir, room, list_mime, &list_bytes).await;
+        match dispatch(URI_LIST_MIME, &list_bytes) {
+            // A repeat within the cooldown costs neither a tar nor an event token.
+            Dispatch::Files(paths)
+                if !own
+                    && last_file_hash.allows(&FileCooldown::key(&paths).await, Instant::now())
+                    && events.allow() =>
+            {
+                send_paths_as_file(
+                    writer,
+                    &ctx.state_dir,
+                    &ctx.data.history,
+                    &ctx.device_id,
+                    &ctx.device_name,
+                    room,
+                    relay,
+                    paths,
+                    max_file_bytes,
+                    last_file_hash,
+                )
+                .await?;
+            }
+            Dispatch::Files(_) | Dispatch::Payload | Dispatch::Skip => {}
+        }
+
+        suppress_text_after_files(clip, &ctx.state_dir, room).await;
+
+        // Treat file clipboard as dominant for this tick.
+        return Ok(());
+    }
+
+    // `--extra-mime` types: an app's own format also wins over its text/image fallbacks.
+    let mut extra: Option<(&str, Vec<u8>)> = None;
+    let extras: &[String] = if text_only() || !due.media {
+        &[]
+    } else {
+        extra_mimes()
+    };
+    for mime in extras.iter().filter(|m| watch_allows(allow, m)) {
+        if let Ok(b) = clip.read(mime).await {
+            if b.len() > max_file_bytes {
+                // Logged once per copy, not on every poll.
+                let h = fingerprint(&b);
+                if last_extra_hash.as_deref() != Some(&h) {
+                    let cap = ("max_file_bytes", max_file_bytes);
+                    record_too_large(&cx, Kind::File, mime, b.len(), cap).await;
+                    *last_extra_hash = Some(h);
+                }
+            } else if !b.is_empty() {
+                extra = Some((mime, b));
+                break;
+            }
+        }
+    }
+    if let Some((mime, bytes)) = extra {
+        let h = fingerprint(&bytes);
+        if last_extra_hash.as_deref() != Some(&h)
+            && !is_suppressed(&ctx.state_dir, room, mime, &h).await
+            && !is_recently_applied(&ctx.state_dir, room, &h).await
+            && events.allow()
+        {
+            *last_extra_hash = Some(h);
+            if let Some(bytes) = filter_outgoing(&Kind::File, bytes).await {
+                let h = fingerprint(&bytes);
+                let msg = build_message(
+                    &ctx.device_id,
+                    &ctx.device_name,
+                    room,
+                    mime,
+                    bytes,
+                    h.clone(),
+                );
+                write_frame(writer, &msg.to_bytes()).await?;
+                emit_event("send", &msg);
+                record_send(
+                    &ctx.data.history,
+                    &ctx.device_id,
+                    Some(ctx.device_name.clone()),
+                    room,
+                    relay,
+                    Kind::File,
+                    Some(mime.to_string()),
+                    msg.name.clone(),
+                    msg.size,
+                    Some(h.clone()),
+                )
+                .await;
+                log::debug!(
+                    "wl-watch: sent kind=file mime={} bytes={} sha={}",
+                    mime,
+                    msg.size,
+                    h
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    // text/plain; another charset only when that's all the clipboard offers
+    let text_mime = types
+        .as_deref()
+        .and_then(|t| pick_text_mime(|m| t.iter().any(|x| x == m)))
+        .unwrap_or("text/plain;charset=utf-8");
+    let text = if due.text && watch_allows(allow, text_mime) {
+        clip.read(text_mime).await.ok()
+    } else {
+        None
+    };
+    if let Some(text_bytes) = text {
+        if text_bytes.len() > max_text_bytes {
+            // Too big for a text message: send it as a file rather than dropping it.
+            let h = fingerprint(&text_bytes);
+            if last_text_hash.as_deref() != Some(&h)
+                && !is_recently_applied(&ctx.state_dir, room, &h).await
+                && events.allow()
+            {
+                *last_text_hash = Some(h);
+                let limits = PublishLimits {
+                    max_text_bytes,
+                    max_image_bytes,
+                    max_file_bytes,
+                    text_only: text_only(),
+                };
+                if !promotes_large_text(text_bytes.len(), &limits) {
+                    let cap = ("max_text_bytes", max_text_bytes);
+                    record_too_large(&cx, Kind::Text, text_mime, text_bytes.len(), cap).await;
+                } else {
+                    if let Some(bytes) = filter_outgoing(&Kind::File, text_bytes).await {
+                        let h = fingerprint(&bytes);
+                        let msg = large_text_message(
+                            &ctx.device_id,
+                            &ctx.device_name,
+                            room,
+                            text_mime,
+                            bytes,
+                            h.clone(),
+                        );
+                        write_frame(writer, &msg.to_bytes()).await?;
+                        emit_event("send", &msg);
+                        record_send(
+                            &ctx.data.history,
+                            &ctx.device_id,
+                            Some(ctx.device_name.clone()),
+                            room,
+                            relay,
+                            Kind::File,
+                            Some(text_mime.to_string()),
+                            msg.name.clone(),
+                            msg.size,
+                            Some(h),
+                        )
+                        .await;
+                    }
+                }
+            }
+        } else if !text_bytes.is_empty() {
+            let files = if text_only() {
+                Dispatch::Payload
+            } else {
+                dispatch(text_mime, &text_bytes)
+            };
+            if let Dispatch::Files(existing) = files {
+                // Copied files are bundled on the media clock only.
+                if !due.media
+                    || is_own_selection(&ctx.state_dir, room, text_mime, &text_bytes).await
+                    || !events.allow()
+                {
+                    return Ok(());
+                }
+                send_paths_as_file(
+                    writer,
+                    &ctx.state_dir,
+                    &ctx.data.history,
+                    &ctx.device_id,
+                    &ctx.device_name,
+                    room,
+                    relay,
+                    existing,
+                    max_file_bytes,
+                    last_file_hash,
+                )
+                .await?;
+                suppress_text_after_files(clip, &ctx.state_dir, room).await;
+
+                return Ok(());
+            }
+
+            let h = fingerprint(&text_bytes);
+            if last_text_hash.as_deref() != Some(&h)
+                && !is_suppressed(&ctx.state_dir, room, text_mime, &h).await
+                && !is_recently_applied(&ctx.state_dir, room, &h).await
+                && events.allow()
+            {
+                // Remember the raw hash either way, so a dropped text isn't retried every poll.
+                *last_text_hash = Some(h.clone());
+                if let Some(text_bytes) = filter_outgoing(&Kind::Text, text_bytes).await {
+                    let h = fingerprint(&text_bytes);
+                    if keep_text_enabled() {
+                        persist_text_best_effort(&ctx.data.received, &h, text_mime, &text_bytes)
+                            .await;
+                    }
+                    let msg = build_message(
+                        &ctx.device_id,
+                        &ctx.device_name,
+                        room,
+                        text_mime,
+                        text_bytes,
+                        h.clone(),
+                    );
+                    let preview = msg
+                        .payload
+                        .as_ref()
+                        .map(|p| {
+                            String::from_utf8_lossy(p)
+                                .chars()
+                                .take(120)
+                                .collect::<String>()
+                        })
+                        .unwrap_or_default();
+                    log::debug!("wl-watch: text preview={}", preview);
+                    let buf = msg.to_bytes();
+                    write_frame(writer, &buf).await?;
+                    emit_event("send", &msg);
+                    record_send(
+                        &ctx.data.history,
+                        &ctx.device_id,
+                        Some(ctx.device_name.clone()),
+                        room,
+                        relay,
+                        Kind::Text,
+                        msg.mime.clone(),
+                        None,
+                        msg.size,
+                        Some(h.clone()),
+                    )
+                    .await;
+                    log::debug!(
+                        "wl-watch: sent kind=text mime={} bytes={} sha={}",
+                        msg.mime.as_deref().unwrap_or_default(),
+                        msg.size,
+                        h
+                    );
+                }
+            }
+        }
+    }
+
+    // images
+    let mut sent_non_png = false;
+    let images: &[&str] = if text_only() || !due.media {
+        &[]
+    } else {
+        image_mimes()
+    };
+    for &mime in images.iter().filter(|m| watch_allows(allow, m)) {
+        if let Ok(img_bytes) = clip.read(mime).await {
+            if img_bytes.is_empty() {
+                continue;
+            }
+            if img_bytes.len() > max_image_bytes {
+                // Logged once per copy, not on every poll (nor for our own applies).
+                let h = fingerprint(&img_bytes);
+                if last_img_hash.get(mime) != Some(&h)
+                    && !is_recently_applied(&ctx.state_dir, room, &h).await
+                {
+                    let cap = ("max_image_bytes", max_image_bytes);
+                    record_too_large(&cx, Kind::Image, mime, img_bytes.len(), cap).await;
+                    last_img_hash.insert(mime.to_string(), h);
+                }
+                continue;
+            }
+            if image_mode == ImageMode::MultiMime && mime == "image/png" && sent_non_png {
+                // In multi-mime mode, prefer publishing the non-png representation to the relay
+                // to preserve the original format across devices.
+                continue;
+            }
+
+            if is_recently_applied(&ctx.state_dir, room, &fingerprint(&img_bytes)).await {
+                continue;
+            }
+            let Some((send_mime, send_bytes)) = prepare_payload(mime, img_bytes, image_mode).await
+            else {
+                continue;
+            };
+            let h = fingerprint(&send_bytes);
+            if last_img_hash.get(send_mime).map(|s| s.as_str()) != Some(&h)
+                && !is_suppressed(&ctx.state_dir, room, send_mime, &h).await
+                && events.allow()
+            {
+                last_img_hash.insert(send_mime.to_string(), h);
+                let Some(send_bytes) = filter_outgoing(&Kind::Image, send_bytes).await else {
+                    continue;
+                };
+                let h = fingerprint(&send_bytes);
+                persist_image_to(&ctx.data.received, &h, send_mime, &send_bytes).await;
+                let msg = build_message(
+                    &ctx.device_id,
+                    &ctx.device_name,
+                    room,
+                    send_mime,
+                    send_bytes,
+                    h.clone(),
+                );
+                let buf = msg.to_bytes();
+                write_frame(writer, &buf).await?;
+                emit_event("send", &msg);
+                record_send(
+                    &ctx.data.history,
+                    &ctx.device_id,
+                    Some(ctx.device_name.clone()),
+                    room,
+                    relay,
+                    Kind::Image,
+                    Some(send_mime.to_string()),
+                    None,
+                    msg.size,
+                    Some(h.clone()),
+                )
+                .await;
+                log::debug!(
+                    "wl-watch: sent kind=image mime={} bytes={} sha={}",
+                    send_mime,
+                    msg.size,
+                    h
+                );
+                if send_mime != "image/png" {
+                    sent_non_png = true;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the clipboard as [`poll_tick`] would and record its hashes in `st` without sending
+/// anything (paused, disabled or locked).
+async fn remember_current<C: ClipboardSource>(
+    clip: &C,
+    cfg: &LiveConfig,
+    allow: &[String],
+    due: Due,
+    st: &mut PollState,
+) {
+    let media = due.media && !text_only();
+    if media {
+        for mime in [URI_LIST_MIME, KDE_URI_LIST_MIME, GNOME_COPIED_FILES_MIME] {
+            let Ok(b) = clip.read(mime).await else {
+                continue;
+            };
+            if b.is_empty() {
+                continue;
+            }
+            if let Dispatch::Files(paths) = dispatch(URI_LIST_MIME, &b) {
+                let key = FileCooldown::key(&paths).await;
+                st.last_file_hash.record(key, Instant::now());
+            }
+            return;
+        }
+        for mime in extra_mimes().iter().filter(|m| watch_allows(allow, m)) {
+            if let Ok(b) = clip.read(mime).await {
+                if !b.is_empty() {
+                    st.last_extra_hash = Some(fingerprint(&b));
+                    return;
+                }
+            }
+        }
+    }
+
+    if due.text {
+        let types = clip.list_types().await;
+        let text_mime = types
+            .as_deref()
+            .and_then(|t| pick_text_mime(|m| t.iter().any(|x| x == m)))
+            .unwrap_or("text/plain;charset=utf-8");
+        if watch_allows(allow, text_mime) {
+            if let Some(b) = clip.read(text_mime).await.ok().filter(|b| !b.is_empty()) {
+                match dispatch(text_mime, &b) {
+                    Dispatch::Files(paths) if media => {
+                        let key = FileCooldown::key(&paths).await;
+                        st.last_file_hash.record(key, Instant::now());
+                    }
+                    _ => st.last_text_hash = Some(fingerprint(&b)),
+                }
+            }
+        }
+    }
+
+    if media {
+        let (_, max_image_bytes, _) = cfg.caps();
+        for &mime in image_mimes().iter().filter(|m| watch_allows(allow, m)) {
+            let Ok(b) = clip.read(mime).await else {
+                continue;
+            };
+            if b.is_empty() {
+                continue;
+            }
+            if b.len() > max_image_bytes {
+                st.last_img_hash.insert(mime.to_string(), fingerprint(&b));
+                continue;
+            }
+            // Keyed like a send: by the type and bytes that would go out.
+            if let Some((send_mime, send_bytes)) = prepare_payload(mime, b, cfg.image_mode).await {
+                st.last_img_hash
+                    .insert(send_mime.to_string(), fingerprint(&send_bytes));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use crate::consts::TAR_MIME;
+    use crate::net::read_msg;
+
+    /// A clipboard the test swaps the contents of.
+    struct FakeClipboard(Mutex<Vec<(String, Vec<u8>)>>);
+
+    impl FakeClipboard {
+        fn set(&self, items: &[(&str, &[u8])]) {
+            *self.0.lock().unwrap() = items
+                .iter()
+                .map(|(m, b)| (m.to_string(), b.to_vec()))
+                .collect();
+        }
+    }
+
+    impl ClipboardSource for FakeClipboard {
+        async fn list_types(&self) -> Option<Vec<String>> {
+            Some(
+                self.0
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(m, _)| m.clone())
+                    .collect(),
+            )
+        }
+
+        async fn read(&self, mime: &str) -> anyhow::Result<Vec<u8>> {
+            let items = self.0.lock().unwrap();
+            let (_, b) = items
+                .iter()
+                .find(|(m, _)| m == mime)
+                .context("not offered")?;
+            Ok(b.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_loop_sends_everything_over_one_connection() {
+        let state = tempfile::tempdir().unwrap();
+        let src = tempfile::tempdir().unwrap();
+        let (a, b) = (src.path().join("a.txt"), src.path().join("b.txt"));
+        std::fs::write(&a, b"one").unwrap();
+        std::fs::write(&b, b"two").unwrap();
+        let uris = |p: &Path| format!("{}\n", url::Url::from_file_path(p).unwrap());
+        let relay = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = relay.local_addr().unwrap().to_string();
+
+        let ctx = PollCtx {
+            state_dir: state.path().to_path_buf(),
+            data: DataDirs::under(state.path()),
+            device_id: "dev".into(),
+            device_name: String::new(),
+        };
+        let (_live_tx, live) = watch::channel(LiveConfig {
+            room: "r".into(),
+            relay: addr.clone(),
+            max_text_bytes: Some(1 << 20),
+            max_image_bytes: Some(1 << 20),
+            max_file_bytes: Some(1 << 20),
+            image_mode: ImageMode::Passthrough,
+        });
+        let clip = FakeClipboard(Mutex::new(Vec::new()));
+        clip.set(&[(URI_LIST_MIME, uris(&a).as_bytes())]);
+        let intervals = PollIntervals::from_ms(10, None, None);
+        let cooldown = Duration::from_secs(60);
+        let poll = poll_loop(&clip, &ctx, "r", intervals, cooldown, live, &[]);
+
+        // Two file copies and an image, each picked up by a later tick.
+        let relay_side = async {
+            let (mut conn, _) = relay.accept().await.unwrap();
+            let mut seen = vec![read_msg(&mut conn).await];
+            seen.push(read_msg(&mut conn).await);
+            clip.set(&[(URI_LIST_MIME, uris(&b).as_bytes())]);
+            seen.push(read_msg(&mut conn).await);
+            clip.set(&[("image/png", b"\x89PNG fake")]);
+            seen.push(read_msg(&mut conn).await);
+            let again = tokio::time::timeout(Duration::from_millis(200), relay.accept()).await;
+            (seen, again.is_ok())
+        };
+        let (seen, reconnected) = tokio::select! {
+            _ = poll => unreachable!("the poll loop runs until cancelled"),
+            r = tokio::time::timeout(Duration::from_secs(10), relay_side) => r.unwrap(),
+        };
+
+        let kinds: Vec<_> = seen
+            .iter()
+            .map(|m| (format!("{:?}", m.kind), m.mime.clone().unwrap_or_default()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("Join".to_string(), String::new()),
+                ("File".to_string(), TAR_MIME.to_string()),
+                ("File".to_string(), TAR_MIME.to_string()),
+                ("Image".to_string(), "image/png".to_string()),
+            ]
+        );
+        assert_eq!(seen[1].name.as_deref(), Some("a.txt.tar"));
+        assert_eq!(seen[2].name.as_deref(), Some("b.txt.tar"));
+        assert!(!reconnected, "the loop opened a second connection");
+    }
+
+    #[test]
+    fn media_is_checked_at_the_slower_cadence() {
+        let intervals = PollIntervals::from_ms(200, Some(100), Some(1000));
+        assert_eq!(intervals.text, Duration::from_millis(100));
+        assert_eq!(
+            PollIntervals::from_ms(200, None, None).media,
+            Duration::from_millis(200)
+        );
+
+        let start = Instant::now();
+        let end = start + Duration::from_secs(3);
+        let mut schedule = PollSchedule::new(intervals, start);
+        let (mut text, mut media) = (Vec::new(), Vec::new());
+        let mut now = start;
+        while now < end {
+            let due = schedule.due(now);
+            // Never woken up for nothing.
+            assert!(due.text || due.media);
+            let at = now.duration_since(start).as_millis();
+            if due.text {
+                text.push(at);
+            }
+            if due.media {
+                media.push(at);
+            }
+            now = schedule.next_wakeup();
+        }
+        assert_eq!(text.len(), 30);
+        assert_eq!(media, [0, 1000, 2000]);
+    }
+
+    #[tokio::test]
+    async fn reloaded_text_cap_applies_to_the_next_copy() {
+        let state = tempfile::tempdir().unwrap();
+        let relay = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let cfg = LiveConfig {
+            room: "r".into(),
+            relay: relay.local_addr().unwrap().to_string(),
+            max_text_bytes: Some(1 << 20),
+            max_image_bytes: Some(1 << 20),
+            max_file_bytes: Some(1 << 20),
+            image_mode: ImageMode::Passthrough,
+        };
+        let (live_tx, live) = watch::channel(cfg.clone());
+        let ctx = PollCtx {
+            state_dir: state.path().to_path_buf(),
+            data: DataDirs::under(state.path()),
+            device_id: "dev".into(),
+            device_name: String::new(),
+        };
+        let clip = FakeClipboard(Mutex::new(Vec::new()));
+        clip.set(&[("text/plain;charset=utf-8", b"hello there")]);
+        let intervals = PollIntervals::from_ms(10, None, None);
+        let poll = poll_loop(&clip, &ctx, "r", intervals, Duration::ZERO, live, &[]);
+
+        let relay_side = async {
+            let (mut conn, _) = relay.accept().await.unwrap();
+            let join = read_msg(&mut conn).await;
+            let before = read_msg(&mut conn).await;
+            // As a SIGHUP reload hands it over: the same copy size is now over the cap.
+            live_tx
+                .send(LiveConfig {
+                    max_text_bytes: Some(8),
+                    ..cfg.clone()
+                })
+                .unwrap();
+            clip.set(&[("text/plain;charset=utf-8", b"hello again")]);
+            let after = read_msg(&mut conn).await;
+            (join, before, after)
+        };
+        let (join, before, after) = tokio::select! {
+            _ = poll => unreachable!("the poll loop runs until cancelled"),
+            r = tokio::time::timeout(Duration::from_secs(10), relay_side) => r.unwrap(),
+        };
+
+        assert!(matches!(join.kind, Kind::Join));
+        assert!(matches!(before.kind, Kind::Text));
+        assert_eq!(before.payload.as_deref(), Some(b"hello there".as_slice()));
+        // Over the new cap: promoted to a file instead of sent as text.
+        assert!(matches!(after.kind, Kind::File));
+        assert_eq!(after.payload.as_deref(), Some(b"hello again".as_slice()));
+    }
+
+    #[tokio::test]
+    async fn copies_made_while_paused_disabled_or_locked_are_not_sent_after() {
+        use crate::suppress::{set_disabled, set_paused};
+
+        let state = tempfile::tempdir().unwrap();
+        let ctx = PollCtx {
+            state_dir: state.path().to_path_buf(),
+            data: DataDirs::under(state.path()),
+            device_id: "dev".into(),
+            device_name: String::new(),
+        };
+        let cfg = LiveConfig {
+            room: "r".into(),
+            relay: "relay".into(),
+            max_text_bytes: Some(1 << 20),
+            max_image_bytes: Some(1 << 20),
+            max_file_bytes: Some(1 << 20),
+            image_mode: ImageMode::Passthrough,
+        };
+        let mut st = PollState {
+            last_text_hash: None,
+            last_img_hash: std::collections::HashMap::new(),
+            last_file_hash: FileCooldown::new(Duration::from_secs(60)),
+            last_extra_hash: None,
+            events: EventGate::new(None),
+        };
+        let clip = FakeClipboard(Mutex::new(Vec::new()));
+        let due = Due {
+            text: true,
+            media: true,
+        };
+        let mut out = Vec::new();
+        let mut sent = async |clip: &FakeClipboard, st: &mut PollState| {
+            let before = out.len();
+            poll_tick(clip, &ctx, "r", &cfg, &[], due, &mut out, st)
+                .await
+                .unwrap();
+            out.len() > before
+        };
+
+        set_paused(state.path(), "r", true).await.unwrap();
+        clip.set(&[("text/plain;charset=utf-8", b"secret")]);
+        assert!(!sent(&clip, &mut st).await);
+        clip.set(&[("image/png", b"\x89PNG secret")]);
+        assert!(!sent(&clip, &mut st).await);
+        set_paused(state.path(), "r", false).await.unwrap();
+        assert!(!sent(&clip, &mut st).await, "the paused copy leaked on resume");
+
+        clip.set(&[("text/plain;charset=utf-8", b"after resume")]);
+        assert!(sent(&clip, &mut st).await);
+
+        // Same for the `disable` kill switch.
+        set_disabled(state.path(), true).await.unwrap();
+        clip.set(&[("text/plain;charset=utf-8", b"while disabled")]);
+        assert!(!sent(&clip, &mut st).await);
+        set_disabled(state.path(), false).await.unwrap();
+        assert!(!sent(&clip, &mut st).await, "the disabled copy leaked on enable");
+
+        // And for `--pause-on-lock`'s marker.
+        let locked = crate::lock::locked_path(state.path());
+        std::fs::write(&locked, format!("{}\n", utils::now_ms())).unwrap();
+        clip.set(&[("text/plain;charset=utf-8", b"while locked")]);
+        assert!(!sent(&clip, &mut st).await);
+        std::fs::remove_file(&locked).unwrap();
+        assert!(!sent(&clip, &mut st).await, "the locked copy leaked on unlock");
+    }
+
+    #[tokio::test]
+    async fn repeated_file_selection_spends_no_event_token() {
+        let state = tempfile::tempdir().unwrap();
+        let src = tempfile::tempdir().unwrap();
+        let a = src.path().join("a.txt");
+        std::fs::write(&a, b"one").unwrap();
+        let uri = format!("{}\n", url::Url::from_file_path(&a).unwrap());
+        let ctx = PollCtx {
+            state_dir: state.path().to_path_buf(),
+            data: DataDirs::under(state.path()),
+            device_id: "dev".into(),
+            device_name: String::new(),
+        };
+        let cfg = LiveConfig {
+            room: "r".into(),
+            relay: "relay".into(),
+            max_text_bytes: Some(1 << 20),
+            max_image_bytes: Some(1 << 20),
+            max_file_bytes: Some(1 << 20),
+            image_mode: ImageMode::Passthrough,
+        };
+        let mut st = PollState {
+            last_text_hash: None,
+            last_img_hash: std::collections::HashMap::new(),
+            last_file_hash: FileCooldown::new(Duration::from_secs(60)),
+            last_extra_hash: None,
+            // One token, for the text change.
+            events: EventGate::new(Some(1)),
+        };
+        // The selection went out on an earlier tick.
+        let key = FileCooldown::key(std::slice::from_ref(&a)).await;
+        st.last_file_hash.record(key, Instant::now());
+        let clip = FakeClipboard(Mutex::new(Vec::new()));
+        let due = || Due {
+            text: true,
+            media: true,
+        };
+        let mut out = Vec::new();
+        let mut sent = async |clip: &FakeClipboard, st: &mut PollState| {
+            let before = out.len();
+            poll_tick(clip, &ctx, "r", &cfg, &[], due(), &mut out, st)
+                .await
+                .unwrap();
+            out.len() > before
+        };
+
+        clip.set(&[(URI_LIST_MIME, uri.as_bytes())]);
+        for _ in 0..3 {
+            // Held back by the cooldown before it reaches the event limit.
+            assert!(!sent(&clip, &mut st).await);
+        }
+        clip.set(&[("text/plain;charset=utf-8", b"hello")]);
+        assert!(sent(&clip, &mut st).await, "the text found no token left");
+    }
+
+    #[tokio::test]
+    async fn file_cooldown_key_changes_when_a_nested_file_does() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("project");
+        std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+        std::fs::write(dir.join("src/deep/a.txt"), b"one").unwrap();
+        let sel = [dir.clone()];
+
+        let before = FileCooldown::key(&sel).await;
+        assert_eq!(FileCooldown::key(&sel).await, before);
+        // Neither the folder itself nor `src` changes.
+        std::fs::write(dir.join("src/deep/a.txt"), b"one more").unwrap();
+        assert_ne!(FileCooldown::key(&sel).await, before);
+    }
+}
@@ -0,0 +1,1506 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use utils::{Kind, Message};
+
+use crate::clipboard::ClipboardSource;
+use crate::consts::{GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, TAR_MIME, URI_LIST_MIME};
+use crate::content_filter::filter_outgoing;
+use crate::channel::stamp_channel;
+use crate::device::set_sender_name;
+use crate::events::emit_event;
+use crate::extra_mime::{extra_mime_file_name, is_extra_mime, pick_extra_mime};
+use crate::hash::{fingerprint, Fingerprinter};
+use crate::history::{record_event, record_send, send_outcome_event, Outcome};
+use crate::image_mode::ImageMode;
+use crate::net::{connect_with_backoff, send_frame};
+use crate::paths::DataDirs;
+use crate::resend::{keep_text_enabled, persist_image_to, persist_text_best_effort};
+use crate::rich_text::{is_rtf_mime, pick_rtf_mime};
+use crate::suppress::{
+    claim_send, is_paused, is_recently_applied, is_suppressed, set_suppress, SEND_CLAIM_TTL,
+};
+use crate::throttle::wait_event_slot;
+use crate::transfer_file::{
+    collect_clipboard_paths, prepare_paths_bundle, send_paths_bundle, PreparedPaths,
+};
+use crate::transfer_image::{
+    choose_image_mime, force_png_blocking, force_webp_blocking, image_mimes,
+};
+
+/// How long text sends are held back after publishing a file selection.
+const FILE_TEXT_SUPPRESS: Duration = Duration::from_millis(1500);
+
+/// History reason for a copy that lost its send slot to a newer one.
+const SUPERSEDED_REASON: &str = "superseded by a newer copy (--max-events-per-sec)";
+
+/// Env var used to pass `--text-only` to helper processes (e.g. the wl-watch hook).
+pub const TEXT_ONLY_ENV: &str = "MCR_TEXT_ONLY";
+
+static TEXT_ONLY: OnceLock<bool> = OnceLock::new();
+
+fn text_only_from_env() -> bool {
+    matches!(
+        std::env::var(TEXT_ONLY_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Sync text only: no file/image watchers, sends or applies
+/// (process-wide, set once at startup).
+pub fn set_text_only(on: bool) {
+    let _ = TEXT_ONLY.set(on || text_only_from_env());
+}
+
+pub fn text_only() -> bool {
+    *TEXT_ONLY.get_or_init(text_only_from_env)
+}
+
+/// Where a publish goes (shared by the wl-watch hook and `publish-current`).
+#[derive(Debug, Clone, Copy)]
+pub struct PublishCtx<'a> {
+    pub state_dir: &'a Path,
+    /// Where history is logged and sent payloads are kept.
+    pub data: &'a DataDirs,
+    pub device_id: &'a str,
+    pub device_name: &'a str,
+    pub room: &'a str,
+    pub relay: &'a str,
+    /// Plan only (`wl-watch --dry-run`): run every check, but send and persist nothing.
+    pub dry_run: bool,
+}
+
+/// Env var used to pass `wl-watch --dry-run` to its hooks.
+pub const DRY_RUN_ENV: &str = "MCR_DRY_RUN";
+
+/// What a publish sent (or, in dry-run mode, would have sent).
+#[derive(Debug, Clone, Serialize)]
+pub struct SendPlan {
+    pub kind: Kind,
+    pub mime: String,
+    pub size: usize,
+    pub sha: String,
+    /// Bundle name (files only).
+    pub name: Option<String>,
+    /// Paths inside the bundle (files only).
+    pub entries: Vec<String>,
+}
+
+impl std::fmt::Display for SendPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "kind={:?} mime={} bytes={} sha={}",
+            self.kind, self.mime, self.size, self.sha
+        )?;
+        if let Some(name) = &self.name {
+            write!(f, " name={} entries={:?}", name, self.entries)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn is_file_list_mime(mime: &str) -> bool {
+    mime == URI_LIST_MIME || mime == KDE_URI_LIST_MIME || mime == GNOME_COPIED_FILES_MIME
+}
+
+/// Plain or rich text, i.e. what `--text-only` still publishes (file lists don't count).
+pub fn is_text_mime(mime: &str) -> bool {
+    !is_file_list_mime(mime) && matches!(kind_for_mime(mime), Kind::Text)
+}
+
+/// Whether an incoming message of `kind` gets applied (`--text-only` drops images and files).
+pub fn applies_kind(kind: &Kind, text_only: bool) -> bool {
+    !text_only || matches!(kind, Kind::Text | Kind::Join | Kind::Hello)
+}
+
+/// Message kind a published clipboard type is sent as.
+pub fn kind_for_mime(mime: &str) -> Kind {
+    if is_extra_mime(mime) {
+        Kind::File
+    } else if mime.starts_with("text/") || is_rtf_mime(mime) {
+        Kind::Text
+    } else {
+        Kind::Image
+    }
+}
+
+/// Best type to publish from the current offers: file lists, then `--extra-mime` types, then
+/// images, then RTF, then text.
+pub fn choose_publish_mime<F>(has: F, image_mode: ImageMode) -> Option<&'static str>
+where
+    F: Fn(&str) -> bool + Copy,
+{
+    [URI_LIST_MIME, KDE_URI_LIST_MIME, GNOME_COPIED_FILES_MIME]
+        .into_iter()
+        .find(|m| has(m))
+        // An app's own format round-trips better than its image/text fallbacks.
+        .or_else(|| pick_extra_mime(has))
+        .or_else(|| choose_image_mime(has, image_mode))
+        // Office apps: keep formatting (wl-apply adds a plain-text fallback).
+        .or_else(|| pick_rtf_mime(has))
+        .or_else(|| pick_text_mime(has))
+}
+
+/// Text offered only in another charset; sent as-is under that MIME rather than as UTF-8.
+pub const LEGACY_TEXT_MIMES: &[&str] = &[
+    "text/plain;charset=iso-8859-1",
+    "text/plain;charset=ISO-8859-1",
+    "text/plain;charset=latin1",
+    "text/plain;charset=iso-8859-15",
+    "text/plain;charset=windows-1252",
+    "text/plain;charset=shift_jis",
+    "text/plain;charset=euc-jp",
+    "text/plain;charset=gbk",
+    "text/plain;charset=gb2312",
+    "text/plain;charset=big5",
+    "text/plain;charset=euc-kr",
+];
+
+/// Plain text to read: UTF-8 if offered, then bare `text/plain`, then a legacy charset.
+pub fn pick_text_mime<F>(has: F) -> Option<&'static str>
+where
+    F: Fn(&str) -> bool,
+{
+    ["text/plain;charset=utf-8", "text/plain"]
+        .into_iter()
+        .chain(LEGACY_TEXT_MIMES.iter().copied())
+        .find(|m| has(m))
+}
+
+/// Whether `mime` names a charset other than UTF-8.
+fn declares_other_charset(mime: &str) -> bool {
+    mime.split(';')
+        .skip(1)
+        .filter_map(|p| p.split_once('='))
+        .any(|(k, v)| {
+            let v = v.trim().trim_matches('"');
+            k.trim().eq_ignore_ascii_case("charset")
+                && !v.eq_ignore_ascii_case("utf-8")
+                && !v.eq_ignore_ascii_case("utf8")
+        })
+}
+
+/// MIME plain text goes out under: the UTF-8 default, unless the offer named another charset
+/// or the bytes aren't UTF-8; then exactly what was offered, so peers get the bytes untouched.
+pub fn outgoing_text_mime<'a>(mime: &'a str, bytes: &[u8]) -> &'a str {
+    if declares_other_charset(mime) || std::str::from_utf8(bytes).is_err() {
+        mime
+    } else {
+        "text/plain;charset=utf-8"
+    }
+}
+
+/// What to do with the bytes of the chosen type.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// A file selection: send the (deduplicated, sorted) paths as a bundle.
+    Files(Vec<PathBuf>),
+    /// Send the bytes as a text/image payload.
+    Payload,
+    /// Nothing usable (e.g. an empty uri-list).
+    Skip,
+}
+
+pub fn dispatch(mime: &str, bytes: &[u8]) -> Dispatch {
+    if is_file_list_mime(mime) {
+        let paths: BTreeSet<PathBuf> = collect_clipboard_paths(bytes).into_iter().collect();
+        if paths.is_empty() {
+            return Dispatch::Skip;
+        }
+        return Dispatch::Files(paths.into_iter().collect());
+    }
+    // Some clipboard producers expose file copies as plain text containing `file:///...`
+    // without offering `text/uri-list` consistently. Treat those as a file selection, but
+    // only for paths that exist (otherwise it's just text that looks like a URI).
+    if mime.starts_with("text/plain") {
+        let existing: BTreeSet<PathBuf> = collect_clipboard_paths(bytes)
+            .into_iter()
+            .filter(|p| p.exists())
+            .collect();
+        if !existing.is_empty() {
+            return Dispatch::Files(existing.into_iter().collect());
+        }
+    }
+    Dispatch::Payload
+}
+
+/// Apply the image mode before sending; `None` when a force-png/force-webp conversion fails.
+pub async fn prepare_payload(
+    mime: &str,
+    bytes: Vec<u8>,
+    image_mode: ImageMode,
+) -> Option<(&str, Vec<u8>)> {
+    if !mime.starts_with("image/") {
+        return Some((mime, bytes));
+    }
+    match image_mode {
+        ImageMode::ForcePng => force_png_blocking(mime, bytes)
+            .await
+            .map_err(|e| log::debug!("publish: to_png failed: {e:#}"))
+            .ok(),
+        ImageMode::ForceWebp => force_webp_blocking(mime, bytes)
+            .await
+            .map_err(|e| log::debug!("publish: to_webp failed: {e:#}"))
+            .ok(),
+        ImageMode::Passthrough | ImageMode::MultiMime | ImageMode::SpoofPng => Some((mime, bytes)),
+    }
+}
+
+/// Build the outgoing text/image message (RTF and non-UTF-8 text keep their MIME; other plain
+/// text uses the text default).
+///
+/// `--extra-mime` types go out as opaque files tagged with their MIME.
+pub fn build_message(
+    device_id: &str,
+    device_name: &str,
+    room: &str,
+    mime: &str,
+    bytes: Vec<u8>,
+    sha: String,
+) -> Message {
+    let mut msg = match kind_for_mime(mime) {
+        Kind::Text => {
+            let mut m = Message::new_text(device_id, room, "");
+            let mime = if is_rtf_mime(mime) {
+                mime
+            } else {
+                outgoing_text_mime(mime, &bytes)
+            };
+            m.mime = Some(mime.to_string());
+            m.size = bytes.len();
+            m.payload = Some(bytes);
+            m
+        }
+        Kind::File => Message::new_file(device_id, room, &extra_mime_file_name(mime), mime, bytes),
+        _ => Message::new_image(device_id, room, mime, bytes),
+    };
+    set_sender_name(&mut msg, device_name);
+    stamp_channel(&mut msg);
+    msg.sha256 = Some(sha);
+    msg
+}
+
+/// Name of a text payload sent as a file because it was over `max_text_bytes`.
+pub fn large_text_file_name(mime: &str) -> &'static str {
+    if is_rtf_mime(mime) {
+        "clipboard.rtf"
+    } else {
+        "clipboard.txt"
+    }
+}
+
+/// Whether text over `max_text_bytes` may go out as a file instead; logs why not.
+///
+/// `--text-only` peers don't apply files, and `max_file_bytes` still caps the payload.
+pub fn promotes_large_text(len: usize, limits: &PublishLimits) -> bool {
+    if limits.text_only || len > limits.max_file_bytes {
+        log::warn!(
+            "publish: text too large ({} bytes > max_text_bytes={}), skipped",
+            len,
+            limits.max_text_bytes
+        );
+        return false;
+    }
+    log::info!(
+        "publish: text of {} bytes is over max_text_bytes={}; sending it as a file",
+        len,
+        limits.max_text_bytes
+    );
+    true
+}
+
+/// Build the outgoing file message for text over `max_text_bytes` (it keeps its text MIME).
+pub fn large_text_message(
+    device_id: &str,
+    device_name: &str,
+    room: &str,
+    mime: &str,
+    bytes: Vec<u8>,
+    sha: String,
+) -> Message {
+    let mut msg = Message::new_file(device_id, room, large_text_file_name(mime), mime, bytes);
+    set_sender_name(&mut msg, device_name);
+    stamp_channel(&mut msg);
+    msg.sha256 = Some(sha);
+    msg
+}
+
+/// Persist a sent payload next to received ones under `base` (images for preview, texts for
+/// resend with `--keep-text`).
+pub async fn persist_sent_best_effort(base: &Path, sha: &str, mime: &str, bytes: &[u8]) {
+    if mime.starts_with("image/") {
+        persist_image_to(base, sha, mime, bytes).await;
+    } else if keep_text_enabled() {
+        persist_text_best_effort(base, sha, mime, bytes).await;
+    }
+}
+
+/// File clipboards may also offer a text/plain `file:///...` representation; hold exactly that
+/// text back briefly so it doesn't override the receiver's clipboard with host paths.
+pub async fn suppress_text_after_files<C: ClipboardSource>(clip: &C, state_dir: &Path, room: &str) {
+    for mime in ["text/plain;charset=utf-8", "text/plain"] {
+        let Ok(text) = clip.read(mime).await else {
+            continue;
+        };
+        if !text.is_empty() {
+            set_suppress(
+                state_dir,
+                room,
+                mime,
+                &fingerprint(&text),
+                FILE_TEXT_SUPPRESS,
+            )
+            .await;
+        }
+    }
+}
+
+/// A file selection (or its text form) wl-apply just wrote, read back by a watcher.
+pub async fn is_own_selection(state_dir: &Path, room: &str, mime: &str, bytes: &[u8]) -> bool {
+    is_suppressed(state_dir, room, mime, &fingerprint(bytes)).await
+}
+
+/// Best-effort history entry for a send that didn't go out (none in dry-run mode).
+async fn record_unsent(
+    cx: &PublishCtx<'_>,
+    outcome: Outcome,
+    reason: &str,
+    kind: Kind,
+    mime: &str,
+    bytes: usize,
+    sha: Option<String>,
+) {
+    if cx.dry_run {
+        return;
+    }
+    record_event(
+        &cx.data.history,
+        send_outcome_event(
+            cx.device_id,
+            Some(cx.device_name.to_string()),
+            cx.room,
+            cx.relay,
+            outcome,
+            reason,
+            kind,
+            Some(mime.to_string()),
+            bytes,
+            sha,
+        ),
+    )
+    .await;
+}
+
+/// History entry for a copy left unsent because it is over the size cap `cap` (its name and
+/// value). Echoes of our own applies aren't logged: they were never meant to go out.
+pub async fn record_too_large(
+    cx: &PublishCtx<'_>,
+    kind: Kind,
+    mime: &str,
+    bytes: usize,
+    (cap, max): (&str, usize),
+) {
+    let reason = format!("too large: {} bytes > {}={}", bytes, cap, max);
+    record_unsent(cx, Outcome::Skipped, &reason, kind, mime, bytes, None).await;
+}
+
+/// What a wl-watch hook got on stdin for its candidate type.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CandidateInput {
+    Stored(Vec<u8>),
+    /// Over the cap: drained (so wl-paste isn't blocked), but only sized and fingerprinted.
+    TooBig {
+        len: usize,
+        sha: String,
+    },
+}
+
+/// Read a hook's candidate bytes from `r`, keeping them only while they fit in `cap`.
+pub async fn read_candidate<R: AsyncRead + Unpin>(r: &mut R, cap: usize) -> CandidateInput {
+    let mut stored: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 8192];
+    let mut too_big: Option<Fingerprinter> = None;
+    let mut len = 0usize;
+    loop {
+        let n = match r.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(_) => break,
+        };
+        len += n;
+        if let Some(fp) = too_big.as_mut() {
+            fp.update(&buf[..n]);
+        } else if len > cap {
+            let mut fp = Fingerprinter::new();
+            fp.update(&std::mem::take(&mut stored));
+            fp.update(&buf[..n]);
+            too_big = Some(fp);
+        } else {
+            stored.extend_from_slice(&buf[..n]);
+        }
+    }
+    match too_big {
+        Some(fp) => CandidateInput::TooBig {
+            len,
+            sha: fp.finish(),
+        },
+        None => CandidateInput::Stored(stored),
+    }
+}
+
+/// The cap a hook applies to a `candidate` type (its name and value), and the kind it is
+/// logged under when over it. Text over `max_text_bytes` still goes out as a file, so text
+/// and file lists get the larger of the two.
+pub fn candidate_cap(candidate: &str, limits: &PublishLimits) -> (Kind, (&'static str, usize)) {
+    if candidate.starts_with("image/") {
+        return (Kind::Image, ("max_image_bytes", limits.max_image_bytes));
+    }
+    let kind = if is_file_list_mime(candidate) {
+        Kind::File
+    } else {
+        kind_for_mime(candidate)
+    };
+    let cap = if limits.max_file_bytes >= limits.max_text_bytes {
+        ("max_file_bytes", limits.max_file_bytes)
+    } else {
+        ("max_text_bytes", limits.max_text_bytes)
+    };
+    (kind, cap)
+}
+
+/// [`record_too_large`] for a hook's oversized candidate, unless it is an echo of our own
+/// apply (as in poll mode).
+pub async fn record_candidate_too_large(
+    cx: &PublishCtx<'_>,
+    candidate: &str,
+    limits: &PublishLimits,
+    len: usize,
+    sha: &str,
+) {
+    if is_recently_applied(cx.state_dir, cx.room, sha).await {
+        return;
+    }
+    let (kind, cap) = candidate_cap(candidate, limits);
+    record_too_large(cx, kind, candidate, len, cap).await;
+}
+
+/// Send a file selection, then suppress the trailing text offers.
+pub async fn publish_files<C: ClipboardSource>(
+    cx: &PublishCtx<'_>,
+    clip: &C,
+    paths: Vec<PathBuf>,
+    max_file_bytes: usize,
+) -> anyhow::Result<Option<SendPlan>> {
+    let prepared = prepare_paths_bundle(cx.state_dir, cx.room, paths, max_file_bytes).await?;
+    let plan = match prepared {
+        PreparedPaths::Ready(bundle) => {
+            let plan = SendPlan {
+                kind: Kind::File,
+                mime: TAR_MIME.to_string(),
+                size: bundle.payload.len as usize,
+                sha: bundle.payload.sha.clone(),
+                name: Some(bundle.name.clone()),
+                entries: bundle.payload.entry_names(),
+            };
+            if !cx.dry_run {
+                if !wait_event_slot(cx.state_dir, cx.room).await {
+                    let (size, sha) = (plan.size, Some(plan.sha.clone()));
+                    let (reason, kind) = (SUPERSEDED_REASON, Kind::File);
+                    record_unsent(cx, Outcome::Skipped, reason, kind, TAR_MIME, size, sha).await;
+                    return Ok(None);
+                }
+                let res = send_paths_bundle(
+                    cx.state_dir,
+                    &cx.data.history,
+                    cx.device_id,
+                    cx.device_name,
+                    cx.room,
+                    cx.relay,
+                    bundle,
+                )
+                .await;
+                if let Err(e) = res {
+                    let reason = format!("send failed: {e:#}");
+                    let (size, sha) = (plan.size, Some(plan.sha.clone()));
+                    record_unsent(
+                        cx,
+                        Outcome::Failed,
+                        &reason,
+                        Kind::File,
+                        TAR_MIME,
+                        size,
+                        sha,
+                    )
+                    .await;
+                    return Err(e);
+                }
+            }
+            Some(plan)
+        }
+        PreparedPaths::Nothing | PreparedPaths::Filtered(_) => None,
+    };
+    if !cx.dry_run {
+        suppress_text_after_files(clip, cx.state_dir, cx.room).await;
+    }
+    Ok(plan)
+}
+
+#[derive(Debug)]
+pub enum PayloadOutcome {
+    /// Sent, or in dry-run mode ready to be.
+    Sent {
+        kind: Kind,
+        mime: String,
+        size: usize,
+        sha: String,
+    },
+    /// Recently applied by wl-apply (loop prevention).
+    Suppressed { mime: String, sha: String },
+    /// Image conversion failed, `--send-filter` dropped it, or a newer copy took its
+    /// `--max-events-per-sec` slot.
+    Dropped,
+}
+
+impl PayloadOutcome {
+    pub fn into_plan(self) -> Option<SendPlan> {
+        match self {
+            PayloadOutcome::Sent {
+                kind,
+                mime,
+                size,
+                sha,
+            } => Some(SendPlan {
+                kind,
+                mime,
+                size,
+                sha,
+                name: None,
+                entries: Vec::new(),
+            }),
+            PayloadOutcome::Suppressed { .. } | PayloadOutcome::Dropped => None,
+        }
+    }
+}
+
+/// Convert, check suppression, filter, persist and send a text/image payload.
+pub async fn publish_payload(
+    cx: &PublishCtx<'_>,
+    mime: &str,
+    bytes: Vec<u8>,
+    image_mode: ImageMode,
+) -> anyhow::Result<PayloadOutcome> {
+    publish_payload_as(cx, mime, bytes, image_mode, false).await
+}
+
+/// [`publish_payload`], optionally sending text as a file (see [`promotes_large_text`]).
+async fn publish_payload_as(
+    cx: &PublishCtx<'_>,
+    mime: &str,
+    bytes: Vec<u8>,
+    image_mode: ImageMode,
+    as_file: bool,
+) -> anyhow::Result<PayloadOutcome> {
+    // Our own apply, read back before its marker MIME was visible (or without one).
+    let raw_sha = fingerprint(&bytes);
+    if is_recently_applied(cx.state_dir, cx.room, &raw_sha).await {
+        return Ok(PayloadOutcome::Suppressed {
+            mime: mime.to_string(),
+            sha: raw_sha,
+        });
+    }
+    let Some((send_mime, send_bytes)) = prepare_payload(mime, bytes, image_mode).await else {
+        return Ok(PayloadOutcome::Dropped);
+    };
+
+    let sha = fingerprint(&send_bytes);
+    if is_suppressed(cx.state_dir, cx.room, send_mime, &sha).await {
+        return Ok(PayloadOutcome::Suppressed {
+            mime: send_mime.to_string(),
+            sha,
+        });
+    }
+    // Losing the claim means another hook is sending this very copy; that one records it.
+    if !claim_send(cx.state_dir, cx.room, &sha, SEND_CLAIM_TTL, cx.dry_run).await {
+        return Ok(PayloadOutcome::Suppressed {
+            mime: send_mime.to_string(),
+            sha,
+        });
+    }
+    let kind = if as_file {
+        Kind::File
+    } else {
+        kind_for_mime(send_mime)
+    };
+    // Suppression is checked on the raw clipboard bytes; the sent sha follows the filter.
+    let Some(send_bytes) = filter_outgoing(&kind, send_bytes).await else {
+        return Ok(PayloadOutcome::Dropped);
+    };
+    let sha = fingerprint(&send_bytes);
+    if cx.dry_run {
+        return Ok(PayloadOutcome::Sent {
+            kind,
+            mime: send_mime.to_string(),
+            size: send_bytes.len(),
+            sha,
+        });
+    }
+    if !wait_event_slot(cx.state_dir, cx.room).await {
+        let (reason, len, fp) = (SUPERSEDED_REASON, send_bytes.len(), Some(sha.clone()));
+        record_unsent(cx, Outcome::Skipped, reason, kind, send_mime, len, fp).await;
+        return Ok(PayloadOutcome::Dropped);
+    }
+    // Opaque app data has no preview and isn't offered for resend (nor is oversized text: a
+    // resend would go out as plain text again).
+    if !as_file && !is_extra_mime(send_mime) {
+        persist_sent_best_effort(&cx.data.received, &sha, send_mime, &send_bytes).await;
+    }
+
+    let len = send_bytes.len();
+    let stream = match connect_with_backoff(cx.state_dir, cx.relay).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            let reason = format!("connect failed: {e:#}");
+            let sha = Some(sha.clone());
+            record_unsent(cx, Outcome::Failed, &reason, kind, send_mime, len, sha).await;
+            return Err(e);
+        }
+    };
+    let build = if as_file {
+        large_text_message
+    } else {
+        build_message
+    };
+    let msg = build(
+        cx.device_id,
+        cx.device_name,
+        cx.room,
+        send_mime,
+        send_bytes,
+        sha.clone(),
+    );
+    if let Err(e) = send_frame(stream, msg.to_bytes()).await {
+        let reason = format!("send failed: {e:#}");
+        let sha = Some(sha.clone());
+        record_unsent(cx, Outcome::Failed, &reason, msg.kind, send_mime, len, sha).await;
+        return Err(e);
+    }
+    emit_event("send", &msg);
+    log::debug!(
+        "wl-watch: sent kind={:?} mime={} bytes={} sha={}",
+        msg.kind,
+        send_mime,
+        msg.size,
+        sha
+    );
+    record_send(
+        &cx.data.history,
+        cx.device_id,
+        Some(cx.device_name.to_string()),
+        cx.room,
+        cx.relay,
+        msg.kind.clone(),
+        Some(send_mime.to_string()),
+        msg.name.clone(),
+        msg.size,
+        msg.sha256.clone(),
+    )
+    .await;
+    Ok(PayloadOutcome::Sent {
+        kind: msg.kind,
+        mime: send_mime.to_string(),
+        size: msg.size,
+        sha,
+    })
+}
+
+/// Size caps for publishing the current clipboard.
+#[derive(Debug, Clone, Copy)]
+pub struct PublishLimits {
+    pub max_text_bytes: usize,
+    pub max_image_bytes: usize,
+    pub max_file_bytes: usize,
+    /// Publish only text: never read file lists or images (`--text-only`).
+    pub text_only: bool,
+}
+
+/// Publish what the clipboard currently holds (one message at most).
+///
+/// `mime` is a concrete type or `"auto"` (pick via [`choose_publish_mime`]). With `candidate`
+/// set (a MIME-specific watcher fired), auto mode only proceeds when it picked that type.
+/// Returns what was sent (in dry-run mode: what would have been).
+pub async fn publish_current<C: ClipboardSource>(
+    cx: &PublishCtx<'_>,
+    clip: &C,
+    mime: &str,
+    candidate: Option<&str>,
+    limits: PublishLimits,
+    image_mode: ImageMode,
+) -> anyhow::Result<Option<SendPlan>> {
+    if is_paused(cx.state_dir, cx.room).await {
+        return Ok(None);
+    }
+    if limits.text_only && mime != "auto" && !is_text_mime(mime) {
+        return Ok(None);
+    }
+
+    let mime = if mime == "auto" {
+        let Some(types) = clip.list_types().await else {
+            return Ok(None);
+        };
+        let has = |m: &str| types.iter().any(|t| t == m) && (!limits.text_only || is_text_mime(m));
+        let Some(chosen) = choose_publish_mime(has, image_mode) else {
+            return Ok(None);
+        };
+        // Several MIME-specific watchers may fire for one copy; only the matching one sends.
+        if candidate.is_some_and(|c| c != chosen) {
+            return Ok(None);
+        }
+        chosen
+    } else {
+        mime
+    };
+
+    // File selection: read uri-list and send file bytes.
+    if is_file_list_mime(mime) {
+        let Ok(list_bytes) = clip.read(mime).await else {
+            return Ok(None);
+        };
+        if is_own_selection(cx.state_dir, cx.room, mime, &list_bytes).await {
+            return Ok(None);
+        }
+        if let Dispatch::Files(paths) = dispatch(mime, &list_bytes) {
+            return publish_files(cx, clip, paths, limits.max_file_bytes).await;
+        }
+        return Ok(None);
+    }
+
+    if mime == "image/png" && image_mode == ImageMode::MultiMime {
+        // If the clipboard currently offers a non-png image type too, prefer publishing that one.
+        // This avoids publishing both png and the original format when we locally set multi-mime.
+        if let Some(types) = clip.list_types().await {
+            let has_other = image_mimes()
+                .iter()
+                .any(|m| *m != "image/png" && types.iter().any(|t| t == m));
+            if has_other {
+                return Ok(None);
+            }
+        }
+    }
+
+    let bytes = clip.read(mime).await?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    let large_text =
+        (mime.starts_with("text/") || is_rtf_mime(mime)) && bytes.len() > limits.max_text_bytes;
+    let over_cap = if large_text && !promotes_large_text(bytes.len(), &limits) {
+        Some(("max_text_bytes", limits.max_text_bytes))
+    } else if mime.starts_with("image/") && bytes.len() > limits.max_image_bytes {
+        Some(("max_image_bytes", limits.max_image_bytes))
+    } else if is_extra_mime(mime) && bytes.len() > limits.max_file_bytes {
+        Some(("max_file_bytes", limits.max_file_bytes))
+    } else {
+        None
+    };
+    if let Some((cap, max)) = over_cap {
+        record_too_large(cx, kind_for_mime(mime), mime, bytes.len(), (cap, max)).await;
+        return Ok(None);
+    }
+
+    if let Dispatch::Files(paths) = dispatch(mime, &bytes) {
+        // Text-only: `file:///...` text goes out as the text it is.
+        if !limits.text_only {
+            if is_own_selection(cx.state_dir, cx.room, mime, &bytes).await {
+                return Ok(None);
+            }
+            return publish_files(cx, clip, paths, limits.max_file_bytes).await;
+        }
+    }
+
+    let plan = publish_payload_as(cx, mime, bytes, image_mode, large_text)
+        .await?
+        .into_plan();
+    Ok(plan.map(|p| SendPlan {
+        name: large_text.then(|| large_text_file_name(mime).to_string()),
+        ..p
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::read_msg;
+    use crate::transfer_image::{sniff_image_mime, SVG_MIME};
+    use anyhow::Context;
+    use tokio::io::AsyncReadExt;
+
+    fn offers<'a>(types: &'a [&'a str]) -> impl Fn(&str) -> bool + Copy + 'a {
+        move |m: &str| types.contains(&m)
+    }
+
+    #[test]
+    fn publish_mime_choice_prefers_files_then_images_then_text() {
+        let force = ImageMode::ForcePng;
+        let multi = ImageMode::MultiMime;
+
+        // File managers also offer text: the file list wins.
+        let t = [
+            "text/plain",
+            URI_LIST_MIME,
+            GNOME_COPIED_FILES_MIME,
+            "image/png",
+        ];
+        assert_eq!(choose_publish_mime(offers(&t), force), Some(URI_LIST_MIME));
+        let t = ["text/plain", GNOME_COPIED_FILES_MIME];
+        assert_eq!(
+            choose_publish_mime(offers(&t), force),
+            Some(GNOME_COPIED_FILES_MIME)
+        );
+
+        // Browsers offer an image plus its alt text: the image wins; PNG unless multi-mime.
+        let t = ["text/plain;charset=utf-8", "image/png", "image/jpeg"];
+        assert_eq!(choose_publish_mime(offers(&t), force), Some("image/png"));
+        assert_eq!(choose_publish_mime(offers(&t), multi), Some("image/jpeg"));
+        let t = ["image/png", SVG_MIME];
+        assert_eq!(choose_publish_mime(offers(&t), multi), Some(SVG_MIME));
+
+        // Office: RTF over plain text; then the charset-qualified text type.
+        let t = ["text/plain", "text/plain;charset=utf-8", "text/rtf"];
+        assert_eq!(choose_publish_mime(offers(&t), force), Some("text/rtf"));
+        let t = ["text/plain", "text/plain;charset=utf-8"];
+        assert_eq!(
+            choose_publish_mime(offers(&t), force),
+            Some("text/plain;charset=utf-8")
+        );
+        assert_eq!(choose_publish_mime(offers(&["text/html"]), force), None);
+    }
+
+    #[test]
+    fn dispatch_routes_files_text_and_images() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.txt");
+        std::fs::write(&a, b"a").unwrap();
+        let uri = format!("file://{}", a.display());
+
+        let list = format!("# comment\r\n{uri}\r\n{uri}\r\n");
+        assert_eq!(
+            dispatch(URI_LIST_MIME, list.as_bytes()),
+            Dispatch::Files(vec![a.clone()])
+        );
+        assert_eq!(
+            dispatch(URI_LIST_MIME, b"# only a comment\n"),
+            Dispatch::Skip
+        );
+
+        // Plain text naming an existing file is a file copy; a missing one is just text.
+        assert_eq!(
+            dispatch("text/plain", uri.as_bytes()),
+            Dispatch::Files(vec![a.clone()])
+        );
+        let missing = format!("file://{}", tmp.path().join("gone.txt").display());
+        assert_eq!(
+            dispatch("text/plain", missing.as_bytes()),
+            Dispatch::Payload
+        );
+        assert_eq!(dispatch("text/plain", b"hello"), Dispatch::Payload);
+        assert_eq!(dispatch("image/png", uri.as_bytes()), Dispatch::Payload);
+
+        let msg = build_message(
+            "dev",
+            "pc",
+            "room",
+            "text/rtf",
+            b"{\\rtf1}".to_vec(),
+            "s".into(),
+        );
+        assert!(matches!(msg.kind, Kind::Text));
+        assert_eq!(msg.mime.as_deref(), Some("text/rtf"));
+        assert_eq!(msg.sender_name.as_deref(), Some("pc"));
+        assert_eq!(msg.size, 7);
+        let msg = build_message("dev", "", "room", "text/plain", b"hi".to_vec(), "s".into());
+        assert_eq!(msg.mime.as_deref(), Some("text/plain;charset=utf-8"));
+        assert_eq!(msg.sender_name, None);
+        let msg = build_message("dev", "pc", "room", "image/webp", vec![1, 2], "s".into());
+        assert!(matches!(msg.kind, Kind::Image));
+        assert_eq!(msg.mime.as_deref(), Some("image/webp"));
+        assert_eq!(msg.sha256.as_deref(), Some("s"));
+    }
+
+    #[test]
+    fn latin1_text_round_trips_under_its_own_mime() {
+        use crate::clipboard::received_text_items;
+
+        let latin1 = "text/plain;charset=iso-8859-1";
+        // "Grüße, café" in ISO-8859-1: not valid UTF-8.
+        let bytes = b"Gr\xfc\xdfe, caf\xe9".to_vec();
+        let t = ["text/plain;charset=ISO-8859-1", latin1];
+        assert_eq!(
+            choose_publish_mime(offers(&t), ImageMode::ForcePng),
+            Some(latin1)
+        );
+
+        let msg = build_message(
+            "dev",
+            "",
+            "room",
+            latin1,
+            bytes.clone(),
+            fingerprint(&bytes),
+        );
+        let msg = Message::try_from_bytes(&msg.to_bytes()).unwrap();
+        assert_eq!(msg.mime.as_deref(), Some(latin1));
+        assert_eq!(msg.payload.as_deref(), Some(&bytes[..]));
+        // Applied under exactly that type, not the UTF-8 aliases.
+        let items = received_text_items(msg.mime.as_deref(), msg.payload.as_deref().unwrap());
+        assert_eq!(items, [(latin1.to_string(), bytes.clone())]);
+
+        // Bare text/plain that isn't UTF-8 isn't relabelled as UTF-8 either.
+        let msg = build_message("dev", "", "room", "text/plain", bytes, "s".into());
+        assert_eq!(msg.mime.as_deref(), Some("text/plain"));
+    }
+
+    struct FakeClipboard(Vec<(&'static str, &'static [u8])>);
+
+    impl ClipboardSource for FakeClipboard {
+        async fn list_types(&self) -> Option<Vec<String>> {
+            Some(self.0.iter().map(|(m, _)| m.to_string()).collect())
+        }
+
+        async fn read(&self, mime: &str) -> anyhow::Result<Vec<u8>> {
+            let (_, b) = self
+                .0
+                .iter()
+                .find(|(m, _)| *m == mime)
+                .context("not offered")?;
+            Ok(b.to_vec())
+        }
+    }
+
+    /// History and kept payloads go under `state_dir` too (see [`history_of`]).
+    fn ctx<'a>(state_dir: &'a Path, relay: &'a str) -> PublishCtx<'a> {
+        PublishCtx {
+            state_dir,
+            data: Box::leak(Box::new(DataDirs::under(state_dir))),
+            device_id: "dev",
+            device_name: "",
+            room: "room",
+            relay,
+            dry_run: false,
+        }
+    }
+
+    /// What `cx` logged so far.
+    fn history_of(cx: &PublishCtx<'_>) -> Vec<crate::history::HistoryEvent> {
+        crate::history::read_history(&cx.data.history).unwrap()
+    }
+
+    /// A state dir and a relay listening on a free local port (and its address).
+    async fn local_relay() -> (tempfile::TempDir, tokio::net::TcpListener, String) {
+        let state = tempfile::tempdir().unwrap();
+        let relay = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = relay.local_addr().unwrap().to_string();
+        (state, relay, addr)
+    }
+
+    const LIMITS: PublishLimits = PublishLimits {
+        max_text_bytes: 1024,
+        max_image_bytes: 1024,
+        max_file_bytes: 1024,
+        text_only: false,
+    };
+
+    #[tokio::test]
+    async fn publish_current_sends_one_frame_or_none() {
+        let (state, relay, addr) = local_relay().await;
+        let cx = ctx(state.path(), &addr);
+        let publish = |clip: FakeClipboard| async move {
+            publish_current(&cx, &clip, "auto", None, LIMITS, ImageMode::ForcePng)
+                .await
+                .unwrap()
+                .is_some()
+        };
+        let accept = || tokio::time::timeout(Duration::from_millis(200), relay.accept());
+
+        let text = FakeClipboard(vec![("text/plain;charset=utf-8", b"hello")]);
+        assert!(publish(text).await);
+        let (mut conn, _) = accept().await.unwrap().unwrap();
+        let msg = read_msg(&mut conn).await;
+        assert!(matches!(msg.kind, Kind::Text));
+        assert_eq!(msg.payload.as_deref(), Some(b"hello".as_slice()));
+        assert!(conn.read_u32().await.is_err(), "exactly one frame");
+
+        // Empty clipboard, empty text, or only unsupported types: nothing is sent.
+        assert!(!publish(FakeClipboard(vec![])).await);
+        assert!(!publish(FakeClipboard(vec![("text/plain", b"")])).await);
+        assert!(!publish(FakeClipboard(vec![("text/html", b"<b>x</b>")])).await);
+        assert!(
+            accept().await.is_err(),
+            "no connection for an empty clipboard"
+        );
+    }
+
+    #[tokio::test]
+    async fn size_capped_send_leaves_a_skipped_history_event() {
+        let state = tempfile::tempdir().unwrap();
+        // The relay is never reached: nothing goes out.
+        let mut cx = PublishCtx {
+            device_name: "pc",
+            room: "capped",
+            ..ctx(state.path(), "127.0.0.1:9")
+        };
+        let limits = PublishLimits {
+            max_image_bytes: 16,
+            ..LIMITS
+        };
+        let clip = FakeClipboard(vec![("image/png", &[7u8; 64])]);
+        let mode = ImageMode::ForcePng;
+        let sent = publish_current(&cx, &clip, "image/png", None, limits, mode).await;
+        cx.dry_run = true;
+        let planned = publish_current(&cx, &clip, "image/png", None, limits, mode).await;
+        let events = history_of(&cx);
+
+        assert!(sent.unwrap().is_none() && planned.unwrap().is_none());
+        assert_eq!(events.len(), 1, "dry runs leave no history: {events:?}");
+        let e = &events[0];
+        assert_eq!(e.room, "capped");
+        assert_eq!(e.outcome, Some(Outcome::Skipped));
+        assert_eq!((e.dir.as_str(), e.kind.as_str()), ("send", "image"));
+        assert_eq!(e.bytes, 64);
+        assert_eq!(
+            e.reason.as_deref(),
+            Some("too large: 64 bytes > max_image_bytes=16")
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_hook_candidate_is_logged_unless_it_is_our_echo() {
+        let state = tempfile::tempdir().unwrap();
+        let cx = PublishCtx {
+            room: "hook-capped",
+            ..ctx(state.path(), "127.0.0.1:9")
+        };
+        let limits = PublishLimits {
+            max_image_bytes: 16,
+            ..LIMITS
+        };
+        let (_, (_, cap)) = candidate_cap("image/png", &limits);
+        let small = [1u8; 16];
+        assert_eq!(
+            read_candidate(&mut &small[..], cap).await,
+            CandidateInput::Stored(small.to_vec())
+        );
+        // Several reads' worth, so the cap is crossed mid-stream.
+        let big = vec![7u8; 20_000];
+        let CandidateInput::TooBig { len, sha } = read_candidate(&mut &big[..], cap).await else {
+            panic!("stored past the cap");
+        };
+        assert_eq!((len, sha.as_str()), (big.len(), fingerprint(&big).as_str()));
+
+        record_candidate_too_large(&cx, "image/png", &limits, len, &sha).await;
+        // The same image written by wl-apply: its echo isn't logged.
+        crate::suppress::record_applied(state.path(), "hook-capped", "evt", [sha.clone()]).await;
+        record_candidate_too_large(&cx, "image/png", &limits, len, &sha).await;
+        let events = history_of(&cx);
+
+        assert_eq!(events.len(), 1, "{events:?}");
+        assert_eq!(events[0].room, "hook-capped");
+        assert_eq!(events[0].outcome, Some(Outcome::Skipped));
+        assert_eq!(events[0].kind, "image");
+        assert_eq!(
+            events[0].reason.as_deref(),
+            Some("too large: 20000 bytes > max_image_bytes=16")
+        );
+        // Text and file lists are held to the larger of the text and file caps.
+        let text = PublishLimits {
+            max_text_bytes: 16,
+            ..LIMITS
+        };
+        assert!(matches!(
+            candidate_cap("text/plain", &text),
+            (Kind::Text, ("max_file_bytes", 1024))
+        ));
+        assert!(matches!(
+            candidate_cap(URI_LIST_MIME, &limits),
+            (Kind::File, ("max_file_bytes", 1024))
+        ));
+    }
+
+    #[tokio::test]
+    async fn oversized_text_is_promoted_to_a_file() {
+        let (state, relay, addr) = local_relay().await;
+        let cx = ctx(state.path(), &addr);
+        let limits = PublishLimits {
+            max_text_bytes: 16,
+            max_file_bytes: 64,
+            ..LIMITS
+        };
+        let big: &'static [u8] = &[b'x'; 40];
+        let clip = FakeClipboard(vec![("text/plain;charset=utf-8", big)]);
+        let plan = publish_current(&cx, &clip, "auto", None, limits, ImageMode::ForcePng)
+            .await
+            .unwrap()
+            .expect("oversized text was dropped");
+        assert!(matches!(plan.kind, Kind::File));
+        assert_eq!(plan.name.as_deref(), Some("clipboard.txt"));
+
+        let (mut conn, _) = relay.accept().await.unwrap();
+        let msg = read_msg(&mut conn).await;
+        assert!(matches!(msg.kind, Kind::File));
+        assert_eq!(msg.name.as_deref(), Some("clipboard.txt"));
+        assert_eq!(msg.mime.as_deref(), Some("text/plain;charset=utf-8"));
+        assert_eq!(msg.payload.as_deref(), Some(big));
+
+        // Over the file cap too, or text-only: skipped (with a warning), nothing sent.
+        let huge: &'static [u8] = &[b'y'; 100];
+        let clip = FakeClipboard(vec![("text/plain;charset=utf-8", huge)]);
+        let publish =
+            |limits| publish_current(&cx, &clip, "auto", None, limits, ImageMode::ForcePng);
+        assert!(publish(limits).await.unwrap().is_none());
+        let text_only = PublishLimits {
+            max_file_bytes: 1024,
+            text_only: true,
+            ..limits
+        };
+        assert!(publish(text_only).await.unwrap().is_none());
+        let accept = tokio::time::timeout(Duration::from_millis(200), relay.accept());
+        assert!(accept.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fast_apply_then_watch_does_not_echo() {
+        let (state, relay, addr) = local_relay().await;
+        let cx = ctx(state.path(), &addr);
+
+        // wl-apply records what it writes before touching the clipboard; the watcher fires
+        // right away and sees the text without the applied marker MIME.
+        let text = b"from a peer".as_slice();
+        crate::suppress::record_applied(state.path(), "room", "evt-1", [fingerprint(text)]).await;
+        let outcome = publish_payload(
+            &cx,
+            "text/plain;charset=utf-8",
+            text.to_vec(),
+            ImageMode::ForcePng,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, PayloadOutcome::Suppressed { .. }));
+        // Any MIME: the same bytes offered as plain text are ours too.
+        let outcome = publish_payload(&cx, "text/plain", text.to_vec(), ImageMode::ForcePng)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PayloadOutcome::Suppressed { .. }));
+        // The clipboard is shared, so a watcher in another room treats it as ours too.
+        assert!(
+            crate::suppress::is_recently_applied(state.path(), "other", &fingerprint(text)).await
+        );
+        let accept = tokio::time::timeout(Duration::from_millis(200), relay.accept());
+        assert!(accept.await.is_err(), "nothing echoed back to the relay");
+
+        let outcome = publish_payload(
+            &cx,
+            "text/plain",
+            b"typed locally".to_vec(),
+            ImageMode::ForcePng,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(outcome, PayloadOutcome::Sent { .. }));
+    }
+
+    #[test]
+    fn concurrent_hooks_for_one_copy_send_once() {
+        let state = tempfile::tempdir().unwrap();
+        let relay = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = relay.local_addr().unwrap().to_string();
+        let start = std::sync::Barrier::new(2);
+
+        // Like two wl-paste --watch hooks for the same copy: separate runtimes that share only
+        // the state dir, both past every suppress check before either has sent.
+        let hook = || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let cx = ctx(state.path(), &addr);
+            start.wait();
+            rt.block_on(publish_payload(
+                &cx,
+                "text/plain;charset=utf-8",
+                b"copied once".to_vec(),
+                ImageMode::ForcePng,
+            ))
+            .unwrap()
+        };
+        let outcomes: Vec<PayloadOutcome> = std::thread::scope(|s| {
+            let hooks = [s.spawn(hook), s.spawn(hook)];
+            hooks.map(|h| h.join().unwrap()).into()
+        });
+
+        let sent = outcomes
+            .iter()
+            .filter(|o| matches!(o, PayloadOutcome::Sent { .. }))
+            .count();
+        assert_eq!(sent, 1);
+        relay.set_nonblocking(true).unwrap();
+        assert!(relay.accept().is_ok());
+        assert!(relay.accept().is_err(), "the second hook never connected");
+    }
+
+    #[tokio::test]
+    async fn text_only_neither_sends_nor_applies_images() {
+        let (state, relay, addr) = local_relay().await;
+        let cx = ctx(state.path(), &addr);
+        let limits = PublishLimits {
+            text_only: true,
+            ..LIMITS
+        };
+        let publish = |clip: FakeClipboard, mime: &'static str| async move {
+            publish_current(&cx, &clip, mime, None, limits, ImageMode::ForcePng)
+                .await
+                .unwrap()
+                .is_some()
+        };
+        let accept = || tokio::time::timeout(Duration::from_millis(200), relay.accept());
+
+        let image = || FakeClipboard(vec![("image/png", b"\x89PNG")]);
+        assert!(!publish(image(), "auto").await);
+        assert!(!publish(image(), "image/png").await);
+        let files = FakeClipboard(vec![(URI_LIST_MIME, b"file:///etc/hostname\n")]);
+        assert!(!publish(files, "auto").await);
+        assert!(accept().await.is_err(), "nothing sent for images or files");
+
+        // A browser image with alt text: the text still goes out.
+        let both = FakeClipboard(vec![
+            ("image/png", b"\x89PNG"),
+            ("text/plain;charset=utf-8", b"alt"),
+        ]);
+        assert!(publish(both, "auto").await);
+        let (mut conn, _) = accept().await.unwrap().unwrap();
+        let msg = read_msg(&mut conn).await;
+        assert!(matches!(msg.kind, Kind::Text));
+
+        assert!(!applies_kind(&Kind::Image, true));
+        assert!(!applies_kind(&Kind::File, true));
+        assert!(applies_kind(&Kind::Text, true));
+        assert!(applies_kind(&Kind::Image, false));
+        assert!(is_text_mime("text/rtf"));
+        assert!(!is_text_mime(URI_LIST_MIME));
+    }
+
+    #[tokio::test]
+    async fn applied_file_selection_guard_spares_new_copies() {
+        use crate::suppress::{suppress_items, FILE_APPLY_SUPPRESS};
+
+        let (state, relay, addr) = local_relay().await;
+        let cx = ctx(state.path(), &addr);
+        let limits = PublishLimits {
+            max_file_bytes: 1 << 20,
+            ..LIMITS
+        };
+        let uri = "file:///tmp/received/a.txt\n";
+        let plain = "/tmp/received/a.txt";
+
+        // What wl-apply wrote for an incoming file.
+        let written = vec![
+            (
+                "text/plain;charset=utf-8".to_string(),
+                plain.as_bytes().to_vec(),
+            ),
+            (URI_LIST_MIME.to_string(), uri.as_bytes().to_vec()),
+        ];
+        suppress_items(state.path(), "room", &written, FILE_APPLY_SUPPRESS).await;
+        let publish = |clip: FakeClipboard| async move {
+            publish_current(&cx, &clip, "auto", None, limits, ImageMode::ForcePng)
+                .await
+                .unwrap()
+                .is_some()
+        };
+        let accept = || tokio::time::timeout(Duration::from_millis(200), relay.accept());
+
+        // The echo: same uri-list, or its plain path text on its own.
+        let echo = FakeClipboard(vec![
+            (URI_LIST_MIME, uri.as_bytes()),
+            ("text/plain;charset=utf-8", plain.as_bytes()),
+        ]);
+        assert!(!publish(echo).await);
+        let echo_text = FakeClipboard(vec![("text/plain;charset=utf-8", plain.as_bytes())]);
+        assert!(!publish(echo_text).await);
+        assert!(accept().await.is_err(), "the echo is not sent");
+
+        // A different copy within the same window goes out.
+        let fresh = FakeClipboard(vec![("text/plain;charset=utf-8", b"typed right after")]);
+        assert!(publish(fresh).await);
+        let (mut conn, _) = accept().await.unwrap().unwrap();
+        let msg = read_msg(&mut conn).await;
+        assert_eq!(
+            msg.payload.as_deref(),
+            Some(b"typed right after".as_slice())
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_plans_a_file_bundle_without_sending() {
+        let (state, relay, addr) = local_relay().await;
+        let files = tempfile::tempdir().unwrap();
+        std::fs::write(files.path().join("notes.txt"), b"hello").unwrap();
+        std::fs::create_dir(files.path().join("pics")).unwrap();
+        std::fs::write(files.path().join("pics/a.png"), b"png").unwrap();
+        let cx = PublishCtx {
+            dry_run: true,
+            ..ctx(state.path(), &addr)
+        };
+        let limits = PublishLimits {
+            max_file_bytes: 1 << 20,
+            ..LIMITS
+        };
+        let uri = format!(
+            "file://{}\r\nfile://{}\r\n",
+            files.path().join("notes.txt").display(),
+            files.path().join("pics").display()
+        );
+        let uri: &'static [u8] = uri.into_bytes().leak();
+        let clip = FakeClipboard(vec![
+            (URI_LIST_MIME, uri),
+            ("text/plain;charset=utf-8", b"notes.txt pics"),
+        ]);
+
+        let plan = publish_current(&cx, &clip, "auto", None, limits, ImageMode::ForcePng)
+            .await
+            .unwrap()
+            .expect("a plan for the file selection");
+        assert!(matches!(plan.kind, Kind::File));
+        assert_eq!(plan.mime, TAR_MIME);
+        assert!(plan.name.as_deref().is_some_and(|n| n.ends_with(".tar")));
+        assert!(plan.size > 0);
+        assert_eq!(plan.sha.len(), 64);
+        for entry in ["notes.txt", "pics/a.png"] {
+            assert!(
+                plan.entries.iter().any(|e| e.ends_with(entry)),
+                "{entry} missing from {:?}",
+                plan.entries
+            );
+        }
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), relay.accept())
+                .await
+                .is_err(),
+            "dry run connects to nothing"
+        );
+        // Nothing was suppressed either: a real send of the same selection still goes out.
+        assert!(
+            !is_suppressed(
+                state.path(),
+                "room",
+                "text/plain;charset=utf-8",
+                &fingerprint(b"notes.txt pics")
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn extra_mime_round_trips_as_an_opaque_payload() {
+        use crate::consts::APPLIED_MARKER_MIME;
+        use crate::extra_mime::{extra_mime_clipboard_items, set_extra_mimes};
+
+        // Unique to this test: other tests never see it offered.
+        const CLIP: &str = "application/x-mcr-test-clip";
+        set_extra_mimes(vec![CLIP.to_string()]);
+
+        let (state, relay, addr) = local_relay().await;
+        let cx = ctx(state.path(), &addr);
+        // The app's own format wins over its image and text fallbacks.
+        let data: &[u8] = b"\x00\x01opaque\xff";
+        let clip = FakeClipboard(vec![
+            ("text/plain;charset=utf-8", b"fallback"),
+            ("image/png", b"\x89PNG"),
+            (CLIP, data),
+        ]);
+        let plan = publish_current(&cx, &clip, "auto", None, LIMITS, ImageMode::ForcePng)
+            .await
+            .unwrap()
+            .expect("the custom type is sent");
+        assert_eq!(plan.mime, CLIP);
+
+        let (mut conn, _) = relay.accept().await.unwrap();
+        let msg = read_msg(&mut conn).await;
+        assert!(matches!(msg.kind, Kind::File));
+        assert_eq!(msg.mime.as_deref(), Some(CLIP));
+        assert_eq!(msg.payload.as_deref(), Some(data));
+
+        // wl-apply offers the same bytes under the same type, marked as applied.
+        let items = extra_mime_clipboard_items(&msg).expect("re-offered");
+        assert_eq!(items[0], (CLIP.to_string(), data.to_vec()));
+        assert!(items.iter().any(|(m, _)| m == APPLIED_MARKER_MIME));
+        // Ordinary files still go through the bundle/file path.
+        let file = Message::new_file("dev", "room", "a.bin", "application/octet-stream", vec![1]);
+        assert!(extra_mime_clipboard_items(&file).is_none());
+    }
+
+    #[tokio::test]
+    async fn failing_publishes_back_off_instead_of_reconnecting() {
+        use crate::net::relay_backoff_path;
+
+        let state = tempfile::tempdir().unwrap();
+        // A port nothing listens on (yet).
+        let addr = {
+            let l = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            l.local_addr().unwrap().to_string()
+        };
+        let cx = ctx(state.path(), &addr);
+        let publish = |text: &'static [u8]| {
+            publish_payload(&cx, "text/plain", text.to_vec(), ImageMode::ForcePng)
+        };
+
+        let err = publish(b"first").await.unwrap_err();
+        assert!(!format!("{err:#}").contains("unreachable"), "{err:#}");
+        assert!(relay_backoff_path(state.path(), &addr).exists());
+
+        // The relay comes back, but the next copies within the window don't even try.
+        let relay = tokio::net::TcpListener::bind(&addr).await.unwrap();
+        for text in [b"second".as_slice(), b"third"] {
+            let err = publish(text).await.unwrap_err();
+            assert!(format!("{err:#}").contains("unreachable"), "{err:#}");
+        }
+        let accept = tokio::time::timeout(Duration::from_millis(200), relay.accept());
+        assert!(
+            accept.await.is_err(),
+            "no connect attempt while backing off"
+        );
+
+        // Once the window has passed, a successful send clears the record.
+        let p = relay_backoff_path(state.path(), &addr);
+        std::fs::write(&p, format!("1 {}\n", utils::now_ms() - 1)).unwrap();
+        let outcome = publish(b"fourth").await.unwrap();
+        assert!(matches!(outcome, PayloadOutcome::Sent { .. }));
+        assert!(!p.exists());
+    }
+
+    #[tokio::test]
+    async fn force_webp_sends_lossless_webp() {
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(3, 2, image::Rgba([1, 2, 3, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let (mime, webp) = prepare_payload("image/png", png, ImageMode::ForceWebp)
+            .await
+            .unwrap();
+        assert_eq!(mime, "image/webp");
+        assert_eq!(sniff_image_mime(&webp), Some("image/webp"));
+        let img = image::load_from_memory_with_format(&webp, image::ImageFormat::WebP)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(img.get_pixel(2, 1).0, [1, 2, 3, 255]);
+
+        // Already WebP: sent as it is, not re-encoded.
+        let again = prepare_payload("image/webp", webp.clone(), ImageMode::ForceWebp).await;
+        assert_eq!(again, Some(("image/webp", webp)));
+    }
+}
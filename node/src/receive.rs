@@ -0,0 +1,115 @@
+//! `receive --dir`: keep what a room sends on disk without a clipboard (headless boxes,
+//! archiving). Files land in the same layout wl-apply uses under its received dir.
+
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+use utils::{Kind, Message};
+
+use crate::hash::fingerprint;
+use crate::paths::{
+    first_8, is_tar_payload, received_file_path, safe_for_filename, store_received_file,
+};
+use crate::resend::persist_image_to;
+use crate::transfer_file::{extract_bundle, BundleExpose};
+use crate::transfer_image::received_image_mime;
+
+/// Write a received file, bundle or image under `dir`; `None` for anything else (text,
+/// control frames). Bundles are extracted to `<sha8>_<stem>/` as wl-apply does (`expose`,
+/// `max_items`), files kept as `<sha8>/<name>`, images as `<sha8>/image.<ext>`.
+pub async fn store_received(
+    dir: &Path,
+    msg: &Message,
+    expose: BundleExpose,
+    max_items: usize,
+) -> anyhow::Result<Option<PathBuf>> {
+    let Some(payload) = msg.payload.as_deref() else {
+        return Ok(None);
+    };
+    let sha = msg.sha256.clone().unwrap_or_else(|| fingerprint(payload));
+    match msg.kind {
+        Kind::File => {
+            let name = msg
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("multicliprelay-{}", first_8(&sha)));
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("create {}", dir.display()))?;
+            if is_tar_payload(&name, msg.mime.as_deref()) {
+                let out_dir = received_file_path(dir, &sha, Some(&name), msg.mime.as_deref());
+                extract_bundle(payload, &sha, &out_dir, &name, expose, max_items)
+                    .await
+                    .with_context(|| format!("extract bundle into {}", out_dir.display()))?;
+                Ok(Some(out_dir))
+            } else {
+                let safe = safe_for_filename(&name);
+                let out_path = store_received_file(dir, first_8(&sha), &safe, payload)
+                    .await
+                    .with_context(|| format!("store {} under {}", safe, dir.display()))?;
+                Ok(Some(out_path))
+            }
+        }
+        Kind::Image => {
+            let mime = received_image_mime(msg.mime.as_deref(), payload);
+            let out_path = persist_image_to(dir, &sha, &mime, payload)
+                .await
+                .with_context(|| format!("write image under {}", dir.display()))?;
+            Ok(Some(out_path))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::TAR_MIME;
+
+    #[tokio::test]
+    async fn received_payloads_land_on_disk_and_text_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("inbox");
+
+        let file = Message::new_file("peer", "r", "notes.md", "text/markdown", b"# hi".to_vec());
+        let path = store_received(&out, &file, BundleExpose::Auto, 8)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"# hi");
+        assert!(path.ends_with("notes.md"));
+
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut h = tar::Header::new_gnu();
+        h.set_size(3);
+        h.set_mode(0o644);
+        h.set_cksum();
+        tar.append_data(&mut h, "pics/a.txt", &b"abc"[..]).unwrap();
+        let bundle =
+            Message::new_file("peer", "r", "pics.tar", TAR_MIME, tar.into_inner().unwrap());
+        let root = store_received(&out, &bundle, BundleExpose::Auto, 8)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read(root.join("pics/a.txt")).unwrap(), b"abc");
+
+        let png = b"\x89PNG\r\n\x1a\nrest".to_vec();
+        let image = Message::new_image("peer", "r", "image/png", png.clone());
+        let path = store_received(&out, &image, BundleExpose::Auto, 8)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), png);
+        assert!(path.ends_with("image.png"));
+
+        // Text has no file to keep, and nothing is written outside `dir`.
+        let text = Message::new_text("peer", "r", "hello");
+        assert!(store_received(&out, &text, BundleExpose::Auto, 8)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        // The file, the image, and the bundle with its (hidden) extraction record.
+        assert_eq!(std::fs::read_dir(&out).unwrap().count(), 4);
+    }
+}
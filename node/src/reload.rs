@@ -0,0 +1,258 @@
+//! SIGHUP: re-read the EnvironmentFile the systemd units start from (`multicliprelay.env`)
+//! and hand running services the settings they can change without a restart.
+
+use std::path::{Path, PathBuf};
+
+use tokio::sync::watch;
+
+use crate::image_mode::{parse_image_mode, ImageMode};
+use crate::room::request_room_switch;
+
+/// Settings a running wl-watch/wl-apply picks up on SIGHUP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveConfig {
+    /// Applied through a room switch request (see `node::room`).
+    pub room: String,
+    /// A new relay means a reconnect.
+    pub relay: String,
+    /// Size caps; `None` for a service that has none (wl-apply), and a reload never adds one.
+    pub max_text_bytes: Option<usize>,
+    pub max_image_bytes: Option<usize>,
+    pub max_file_bytes: Option<usize>,
+    pub image_mode: ImageMode,
+}
+
+/// `KEY=value` pairs of an EnvironmentFile (comments, blank lines and quotes dropped).
+pub fn parse_env_file(text: &str) -> Vec<(&str, &str)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim().trim_matches('"').trim_matches('\'')))
+        .collect()
+}
+
+impl LiveConfig {
+    /// `self` with the `MULTICLIPRELAY_*` settings of an env file applied; keys that are
+    /// missing or don't parse keep their current value.
+    pub fn with_env_file(&self, text: &str) -> LiveConfig {
+        let mut next = self.clone();
+        for (key, value) in parse_env_file(text) {
+            let bytes = || value.parse::<usize>().ok();
+            match key {
+                "MULTICLIPRELAY_ROOM" if !value.is_empty() => next.room = value.to_string(),
+                "MULTICLIPRELAY_RELAY" if !value.is_empty() => next.relay = value.to_string(),
+                "MULTICLIPRELAY_MAX_TEXT_BYTES" => {
+                    next.max_text_bytes = next.max_text_bytes.map(|cur| bytes().unwrap_or(cur))
+                }
+                "MULTICLIPRELAY_MAX_IMAGE_BYTES" => {
+                    next.max_image_bytes = next.max_image_bytes.map(|cur| bytes().unwrap_or(cur))
+                }
+                "MULTICLIPRELAY_MAX_FILE_BYTES" => {
+                    next.max_file_bytes = next.max_file_bytes.map(|cur| bytes().unwrap_or(cur))
+                }
+                "MULTICLIPRELAY_IMAGE_MODE" => match parse_image_mode(value) {
+                    Ok(mode) => next.image_mode = mode,
+                    Err(e) => log::warn!("reload: {e:#}"),
+                },
+                _ => {}
+            }
+        }
+        next
+    }
+
+    /// The size caps as (text, image, file), `usize::MAX` where there is none.
+    pub fn caps(&self) -> (usize, usize, usize) {
+        let cap = |c: Option<usize>| c.unwrap_or(usize::MAX);
+        (
+            cap(self.max_text_bytes),
+            cap(self.max_image_bytes),
+            cap(self.max_file_bytes),
+        )
+    }
+
+    /// What differs from `next`, one `name old -> new` entry per setting.
+    pub fn changes(&self, next: &LiveConfig) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut diff = |name: &str, a: String, b: String| {
+            if a != b {
+                out.push(format!("{name} {a} -> {b}"));
+            }
+        };
+        diff("room", self.room.clone(), next.room.clone());
+        diff("relay", self.relay.clone(), next.relay.clone());
+        diff(
+            "max_text_bytes",
+            cap(self.max_text_bytes),
+            cap(next.max_text_bytes),
+        );
+        diff(
+            "max_image_bytes",
+            cap(self.max_image_bytes),
+            cap(next.max_image_bytes),
+        );
+        diff(
+            "max_file_bytes",
+            cap(self.max_file_bytes),
+            cap(next.max_file_bytes),
+        );
+        diff(
+            "image_mode",
+            format!("{:?}", self.image_mode),
+            format!("{:?}", next.image_mode),
+        );
+        out
+    }
+}
+
+fn cap(c: Option<usize>) -> String {
+    c.map_or_else(|| "none".to_string(), |n| n.to_string())
+}
+
+/// Follow `initial`, re-reading `env_file` on every SIGHUP.
+///
+/// A changed room is requested through the room control file under `state_dir`, like
+/// `switch-room` does; everything else reaches the service through the receiver.
+#[cfg(unix)]
+pub fn reload_on_sighup(
+    initial: LiveConfig,
+    env_file: PathBuf,
+    state_dir: PathBuf,
+) -> std::io::Result<watch::Receiver<LiveConfig>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hup = signal(SignalKind::hangup())?;
+    let (tx, rx) = watch::channel(initial);
+    tokio::spawn(async move {
+        while hup.recv().await.is_some() {
+            if tx.is_closed() {
+                break;
+            }
+            reload(&tx, &env_file, &state_dir);
+        }
+    });
+    Ok(rx)
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(
+    initial: LiveConfig,
+    _env_file: PathBuf,
+    _state_dir: PathBuf,
+) -> std::io::Result<watch::Receiver<LiveConfig>> {
+    let (tx, rx) = watch::channel(initial);
+    // No SIGHUP here: the config never changes, but the receiver must stay open.
+    std::mem::forget(tx);
+    Ok(rx)
+}
+
+#[cfg(unix)]
+fn reload(tx: &watch::Sender<LiveConfig>, env_file: &Path, state_dir: &Path) {
+    let text = match std::fs::read_to_string(env_file) {
+        Ok(t) => t,
+        Err(e) => {
+            log::warn!(
+                "SIGHUP: can't read {} ({e}); keeping the current settings",
+                env_file.display()
+            );
+            return;
+        }
+    };
+    let cur = tx.borrow().clone();
+    let next = cur.with_env_file(&text);
+    let changes = cur.changes(&next);
+    if changes.is_empty() {
+        log::info!("SIGHUP: {} unchanged", env_file.display());
+        return;
+    }
+    log::info!(
+        "SIGHUP: reloaded {}: {}",
+        env_file.display(),
+        changes.join(", ")
+    );
+    if next.room != cur.room {
+        if let Err(e) = request_room_switch(state_dir, &next.room) {
+            log::warn!("SIGHUP: room switch to '{}' failed: {e:#}", next.room);
+        }
+    }
+    let _ = tx.send(next);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn live() -> LiveConfig {
+        LiveConfig {
+            room: "default".into(),
+            relay: "127.0.0.1:8080".into(),
+            max_text_bytes: Some(1 << 20),
+            max_image_bytes: Some(20 << 20),
+            max_file_bytes: Some(20 << 20),
+            image_mode: ImageMode::ForcePng,
+        }
+    }
+
+    #[test]
+    fn env_file_overrides_only_what_it_sets() {
+        let text = "# ui\n\
+                    MULTICLIPRELAY_MAX_TEXT_BYTES=4096\n\
+                    MULTICLIPRELAY_IMAGE_MODE=\"multi\"\n\
+                    MULTICLIPRELAY_MAX_FILE_BYTES=lots\n\
+                    OTHER=1\n";
+        let next = live().with_env_file(text);
+        assert_eq!(next.max_text_bytes, Some(4096));
+        assert_eq!(next.image_mode, ImageMode::MultiMime);
+        assert_eq!(next.max_file_bytes, Some(20 << 20));
+        assert_eq!(next.relay, "127.0.0.1:8080");
+        assert_eq!(
+            live().changes(&next),
+            [
+                "max_text_bytes 1048576 -> 4096",
+                "image_mode ForcePng -> MultiMime"
+            ]
+        );
+
+        // No caps (wl-apply): a reload doesn't make any up.
+        let uncapped = LiveConfig {
+            max_text_bytes: None,
+            max_image_bytes: None,
+            max_file_bytes: None,
+            ..live()
+        };
+        let next = uncapped.with_env_file(text);
+        assert_eq!(next.max_text_bytes, None);
+        assert_eq!(
+            uncapped.changes(&next),
+            ["image_mode ForcePng -> MultiMime"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sighup_updates_the_size_cap_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join("multicliprelay.env");
+        std::fs::write(&env_file, "MULTICLIPRELAY_MAX_TEXT_BYTES=1048576\n").unwrap();
+        let mut rx = reload_on_sighup(live(), env_file.clone(), dir.path().into()).unwrap();
+
+        std::fs::write(&env_file, "MULTICLIPRELAY_MAX_TEXT_BYTES=4096\n").unwrap();
+        unsafe { libc::raise(libc::SIGHUP) };
+        tokio::time::timeout(Duration::from_secs(5), rx.changed())
+            .await
+            .expect("no reload after SIGHUP")
+            .unwrap();
+        let now = rx.borrow().clone();
+        assert_eq!(now.max_text_bytes, Some(4096));
+        assert_eq!(
+            now,
+            LiveConfig {
+                max_text_bytes: Some(4096),
+                ..live()
+            }
+        );
+        // The room stayed, so no switch was requested.
+        assert!(!crate::room::room_control_path(dir.path()).exists());
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use utils::paths::KEPT_TEXT_FILES;
+use utils::Message;
+
+use crate::hash::fingerprint;
+use crate::paths::{first_8, kept_text_path, received_file_path};
+use crate::rich_text::is_rtf_mime;
+
+/// Texts up to this size are kept under `received_dir()/<sha8>/` so history can re-send them.
+pub const PERSIST_TEXT_MAX_BYTES: usize = 256 * 1024;
+
+/// Env var used to pass `--keep-text` to helper processes.
+pub const KEEP_TEXT_ENV: &str = "MCR_KEEP_TEXT";
+
+/// Kept texts older than this are deleted (see [`prune_kept_texts`]).
+pub const KEEP_TEXT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+static KEEP_TEXT: OnceLock<bool> = OnceLock::new();
+
+fn keep_text_from_env() -> bool {
+    matches!(
+        std::env::var(KEEP_TEXT_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Keep sent and received texts for `resend` (process-wide; `false` = env). Off by default:
+/// the copies are clipboard contents in plaintext, passwords included.
+pub fn set_keep_text(on: bool) {
+    let _ = KEEP_TEXT.set(on || keep_text_from_env());
+}
+
+pub fn keep_text_enabled() -> bool {
+    *KEEP_TEXT.get_or_init(keep_text_from_env)
+}
+
+/// Stored image extensions and the MIME they are re-sent as.
+const IMAGE_EXTS: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("webp", "image/webp"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+];
+
+/// Extension of a stored image (`image.<ext>`), if it's a supported type.
+pub fn image_ext_from_mime(mime: &str) -> Option<&'static str> {
+    if mime == "image/jpg" {
+        return Some("jpg");
+    }
+    IMAGE_EXTS.iter().find(|(_, m)| *m == mime).map(|(ext, _)| *ext)
+}
+
+/// Best-effort: keep an image under `base/<sha8>/image.<ext>` (history previews and resend).
+///
+/// Unknown types are kept as `image.bin`. Returns the file written, `None` when it wasn't.
+pub async fn persist_image_to(base: &Path, sha: &str, mime: &str, bytes: &[u8]) -> Option<PathBuf> {
+    let dir = base.join(first_8(sha));
+    tokio::fs::create_dir_all(&dir).await.ok();
+    let ext = image_ext_from_mime(mime).unwrap_or("bin");
+    let path = dir.join(format!("image.{ext}"));
+    tokio::fs::write(&path, bytes).await.ok()?;
+    Some(path)
+}
+
+/// Best-effort: keep a sent/received text next to the image/file payloads. Callers check
+/// [`keep_text_enabled`] first; texts older than [`KEEP_TEXT_TTL`] are dropped meanwhile.
+pub async fn persist_text_best_effort(base: &Path, sha: &str, mime: &str, bytes: &[u8]) {
+    if bytes.len() > PERSIST_TEXT_MAX_BYTES {
+        return;
+    }
+    prune_kept_texts(base, KEEP_TEXT_TTL).await;
+    tokio::fs::create_dir_all(base.join(first_8(sha)))
+        .await
+        .ok();
+    let _ = tokio::fs::write(kept_text_path(base, sha, is_rtf_mime(mime)), bytes).await;
+}
+
+/// Delete kept texts under `base` last written more than `max_age` ago (every one of them
+/// for `Duration::ZERO`); returns how many went. Images and files are left alone.
+pub async fn prune_kept_texts(base: &Path, max_age: Duration) -> usize {
+    let Ok(mut dirs) = tokio::fs::read_dir(base).await else {
+        return 0;
+    };
+    let mut removed = 0;
+    while let Ok(Some(entry)) = dirs.next_entry().await {
+        for name in KEPT_TEXT_FILES {
+            let p = entry.path().join(name);
+            let Ok(modified) = tokio::fs::metadata(&p).await.and_then(|m| m.modified()) else {
+                continue;
+            };
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+            if age >= max_age && tokio::fs::remove_file(&p).await.is_ok() {
+                removed += 1;
+            }
+        }
+        // Only goes when nothing else is stored there.
+        let _ = tokio::fs::remove_dir(entry.path()).await;
+    }
+    removed
+}
+
+/// A history entry to push to the room again.
+#[derive(Debug, Clone)]
+pub struct ResendEntry {
+    /// `text` / `image` / `file`, as recorded in history.
+    pub kind: String,
+    pub sha256: String,
+    pub mime: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Resend {
+    /// A ready-to-send frame (text/image).
+    Frame(Box<Message>),
+    /// Stored file or extracted bundle directory; goes through the normal file send path.
+    File(PathBuf),
+}
+
+fn find_stored_image(dir: &Path, mime: Option<&str>) -> Option<(PathBuf, &'static str)> {
+    // The original format first: the png fallback is only a preview convenience.
+    let preferred = mime.and_then(|m| IMAGE_EXTS.iter().find(|(_, im)| *im == m));
+    preferred
+        .into_iter()
+        .chain(IMAGE_EXTS.iter())
+        .map(|(ext, m)| (dir.join(format!("image.{ext}")), *m))
+        .find(|(p, _)| p.is_file())
+}
+
+/// Locate the stored payload for `entry` under `base` (the received dir) and build what to send.
+pub fn prepare_resend(
+    base: &Path,
+    device_id: &str,
+    room: &str,
+    entry: &ResendEntry,
+) -> anyhow::Result<Resend> {
+    let sha8 = first_8(&entry.sha256);
+    let dir = base.join(sha8);
+    let mime = entry.mime.as_deref();
+
+    let mut msg = match entry.kind.as_str() {
+        "text" => {
+            let mime = mime.unwrap_or("text/plain;charset=utf-8");
+            let p = kept_text_path(base, &entry.sha256, is_rtf_mime(mime));
+            let bytes = std::fs::read(&p).with_context(|| {
+                format!(
+                    "text payload not stored ({}; needs --keep-text)",
+                    p.display()
+                )
+            })?;
+            let mut m = Message::new_text(device_id, room, "");
+            m.size = bytes.len();
+            m.payload = Some(bytes);
+            // RTF and non-UTF-8 text go out under the type they were recorded with.
+            m.mime = Some(mime.to_string());
+            m
+        }
+        "image" => {
+            let (p, mime) = find_stored_image(&dir, mime)
+                .with_context(|| format!("image payload not stored ({})", dir.display()))?;
+            let bytes = std::fs::read(&p).with_context(|| format!("read {}", p.display()))?;
+            Message::new_image(device_id, room, mime, bytes)
+        }
+        "file" => {
+            let p = received_file_path(base, &entry.sha256, entry.name.as_deref(), mime);
+            anyhow::ensure!(p.exists(), "file payload not stored ({})", p.display());
+            return Ok(Resend::File(p));
+        }
+        other => anyhow::bail!("cannot resend kind {}", other),
+    };
+    msg.sha256 = Some(fingerprint(msg.payload.as_deref().unwrap_or_default()));
+    Ok(Resend::Frame(Box::new(msg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::Kind;
+
+    #[tokio::test]
+    async fn resend_rebuilds_image_and_text_messages() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sha = "0123456789abcdef".repeat(4);
+        let dir = tmp.path().join(first_8(&sha));
+        std::fs::create_dir_all(&dir).unwrap();
+        // wl-apply in force-png mode stores the original plus a png preview.
+        std::fs::write(dir.join("image.webp"), b"RIFFwebp").unwrap();
+        std::fs::write(dir.join("image.png"), b"\x89PNGpng").unwrap();
+
+        let entry = |kind: &str, mime: Option<&str>| ResendEntry {
+            kind: kind.to_string(),
+            sha256: sha.clone(),
+            mime: mime.map(str::to_string),
+            name: None,
+        };
+
+        let Resend::Frame(m) = prepare_resend(
+            tmp.path(),
+            "dev",
+            "room",
+            &entry("image", Some("image/webp")),
+        )
+        .unwrap() else {
+            panic!("image resends as a frame");
+        };
+        assert!(matches!(m.kind, Kind::Image));
+        assert_eq!(m.mime.as_deref(), Some("image/webp"));
+        assert_eq!(m.payload.as_deref(), Some(b"RIFFwebp".as_slice()));
+        assert_eq!(m.room, "room");
+        assert_eq!(m.sha256.as_deref(), Some(fingerprint(b"RIFFwebp").as_str()));
+
+        // Unknown original format: fall back to whatever is stored.
+        let Resend::Frame(m) =
+            prepare_resend(tmp.path(), "dev", "room", &entry("image", None)).unwrap()
+        else {
+            panic!("image resends as a frame");
+        };
+        assert_eq!(m.mime.as_deref(), Some("image/png"));
+
+        // Text is only resendable once persisted.
+        assert!(prepare_resend(tmp.path(), "dev", "room", &entry("text", None)).is_err());
+        persist_text_best_effort(tmp.path(), &sha, "text/plain;charset=utf-8", b"hello").await;
+        let Resend::Frame(m) =
+            prepare_resend(tmp.path(), "dev", "room", &entry("text", None)).unwrap()
+        else {
+            panic!("text resends as a frame");
+        };
+        assert!(matches!(m.kind, Kind::Text));
+        assert_eq!(m.payload.as_deref(), Some(b"hello".as_slice()));
+        assert_eq!(m.size, 5);
+    }
+
+    #[tokio::test]
+    async fn kept_texts_expire_and_images_stay() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (old, new) = ("aa".repeat(32), "bb".repeat(32));
+        persist_text_best_effort(tmp.path(), &old, "text/plain", b"password").await;
+        persist_image_to(tmp.path(), &old, "image/png", b"\x89PNG").await;
+        let old_txt = kept_text_path(tmp.path(), &old, false);
+        let day_ago = SystemTime::now() - KEEP_TEXT_TTL - Duration::from_secs(60);
+        let f = std::fs::File::options().write(true).open(&old_txt).unwrap();
+        f.set_modified(day_ago).unwrap();
+
+        // Writing a new text drops the expired one.
+        persist_text_best_effort(tmp.path(), &new, "text/rtf", b"{\\rtf1 hi}").await;
+        let new_rtf = kept_text_path(tmp.path(), &new, true);
+        assert!(!old_txt.exists());
+        assert!(new_rtf.exists());
+        assert!(tmp.path().join(first_8(&old)).join("image.png").exists());
+
+        // With keeping off, every kept text goes (and the directories left empty).
+        assert_eq!(prune_kept_texts(tmp.path(), Duration::ZERO).await, 1);
+        assert!(!tmp.path().join(first_8(&new)).exists());
+        assert!(tmp.path().join(first_8(&old)).join("image.png").exists());
+    }
+
+    #[tokio::test]
+    async fn received_image_is_persisted_for_previews() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sha = fingerprint(b"RIFFwebp");
+        // What wl-apply stores for a webp applied with a png fallback.
+        persist_image_to(tmp.path(), &sha, "image/webp", b"RIFFwebp").await;
+        persist_image_to(tmp.path(), &sha, "image/png", b"\x89PNGpng").await;
+        persist_image_to(tmp.path(), &sha, "image/x-unknown", b"???").await;
+
+        let dir = tmp.path().join(first_8(&sha));
+        assert_eq!(std::fs::read(dir.join("image.webp")).unwrap(), b"RIFFwebp");
+        assert_eq!(std::fs::read(dir.join("image.png")).unwrap(), b"\x89PNGpng");
+        assert_eq!(std::fs::read(dir.join("image.bin")).unwrap(), b"???");
+        assert_eq!(image_ext_from_mime("image/jpg"), Some("jpg"));
+    }
+}
@@ -0,0 +1,190 @@
+/// RTF clipboard types offered by office apps (LibreOffice uses `text/rtf`, others `application/rtf`).
+pub const RTF_MIMES: &[&str] = &["text/rtf", "application/rtf"];
+
+pub fn is_rtf_mime(mime: &str) -> bool {
+    RTF_MIMES.contains(&mime)
+}
+
+/// First RTF type the clipboard offers, if any.
+pub fn pick_rtf_mime<F: Fn(&str) -> bool>(has: F) -> Option<&'static str> {
+    RTF_MIMES.iter().copied().find(|m| has(m))
+}
+
+/// Clipboard offers for an incoming RTF payload: the RTF itself (under the common aliases)
+/// plus a plain-text fallback for apps that don't understand RTF.
+pub fn rtf_clipboard_items(mime: &str, rtf: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut items = vec![(mime.to_string(), rtf.to_vec())];
+    for m in ["text/rtf", "application/rtf"] {
+        if m != mime {
+            items.push((m.to_string(), rtf.to_vec()));
+        }
+    }
+    let plain = rtf_to_plain(rtf).into_bytes();
+    items.push(("text/plain;charset=utf-8".to_string(), plain.clone()));
+    items.push(("text/plain".to_string(), plain));
+    items
+}
+
+/// Groups whose content is metadata, not document text.
+const SKIP_DESTINATIONS: &[&str] = &[
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "info",
+    "pict",
+    "object",
+    "header",
+    "footer",
+    "listtable",
+    "listoverridetable",
+    "rsidtbl",
+    "generator",
+    "xmlnstbl",
+    "themedata",
+    "datastore",
+    "latentstyles",
+];
+
+/// Best-effort RTF -> plain text (enough for a paste fallback, not a full RTF reader).
+pub fn rtf_to_plain(rtf: &[u8]) -> String {
+    let mut out = String::new();
+    // Per-group state: (skipping, \ucN).
+    let mut stack: Vec<(bool, usize)> = Vec::new();
+    let mut skip = false;
+    let mut uc = 1usize;
+    // Fallback bytes still to drop after a \uN escape.
+    let mut pending_skip = 0usize;
+    let mut i = 0usize;
+
+    while i < rtf.len() {
+        let c = rtf[i];
+        match c {
+            b'{' => {
+                stack.push((skip, uc));
+                pending_skip = 0;
+                i += 1;
+            }
+            b'}' => {
+                (skip, uc) = stack.pop().unwrap_or((false, 1));
+                pending_skip = 0;
+                i += 1;
+            }
+            b'\\' => {
+                i += 1;
+                let Some(&n) = rtf.get(i) else { break };
+                if n.is_ascii_alphabetic() {
+                    let start = i;
+                    while i < rtf.len() && rtf[i].is_ascii_alphabetic() {
+                        i += 1;
+                    }
+                    let word = std::str::from_utf8(&rtf[start..i]).unwrap_or("");
+                    let num_start = i;
+                    if i < rtf.len() && rtf[i] == b'-' {
+                        i += 1;
+                    }
+                    while i < rtf.len() && rtf[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                    let param = std::str::from_utf8(&rtf[num_start..i])
+                        .ok()
+                        .and_then(|s| s.parse::<i32>().ok());
+                    // A single space delimits the control word and is not text.
+                    if i < rtf.len() && rtf[i] == b' ' {
+                        i += 1;
+                    }
+
+                    if SKIP_DESTINATIONS.contains(&word) {
+                        skip = true;
+                        continue;
+                    }
+                    if skip {
+                        continue;
+                    }
+                    match word {
+                        "par" | "line" | "row" => out.push('\n'),
+                        "tab" | "cell" => out.push('\t'),
+                        "uc" => uc = param.unwrap_or(1).max(0) as usize,
+                        "u" => {
+                            if let Some(p) = param {
+                                // Negative values encode code points above 0x7fff.
+                                let cp = if p < 0 { p + 0x10000 } else { p } as u32;
+                                out.push(char::from_u32(cp).unwrap_or('\u{fffd}'));
+                                pending_skip = uc;
+                            }
+                        }
+                        _ => {}
+                    }
+                } else {
+                    i += 1;
+                    match n {
+                        b'*' => skip = true,
+                        b'\'' => {
+                            let hex = rtf.get(i..i + 2).and_then(|h| std::str::from_utf8(h).ok());
+                            i += 2;
+                            if pending_skip > 0 {
+                                pending_skip -= 1;
+                            } else if let (false, Some(b)) =
+                                (skip, hex.and_then(|h| u8::from_str_radix(h, 16).ok()))
+                            {
+                                // Code page is usually cp1252; latin-1 is close enough here.
+                                out.push(b as char);
+                            }
+                        }
+                        b'\\' | b'{' | b'}' if !skip => {
+                            if pending_skip > 0 {
+                                pending_skip -= 1;
+                            } else {
+                                out.push(n as char);
+                            }
+                        }
+                        b'~' if !skip => out.push('\u{a0}'),
+                        b'\n' | b'\r' if !skip => out.push('\n'),
+                        _ => {}
+                    }
+                }
+            }
+            b'\r' | b'\n' => i += 1,
+            _ => {
+                let start = i;
+                while i < rtf.len() && !matches!(rtf[i], b'{' | b'}' | b'\\' | b'\r' | b'\n') {
+                    i += 1;
+                }
+                if skip {
+                    continue;
+                }
+                let mut text = &rtf[start..i];
+                let drop = pending_skip.min(text.len());
+                pending_skip -= drop;
+                text = &text[drop..];
+                out.push_str(&String::from_utf8_lossy(text));
+            }
+        }
+    }
+
+    out.trim_end_matches(['\n', '\r']).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &[u8] = br"{\rtf1\ansi\deff0{\fonttbl{\f0 Times New Roman;}}{\colortbl;\red255\green0\blue0;}
+{\*\generator LibreOffice}\pard\plain \f0 Hello \b bold\b0  w\'f6rld\par
+Caf\u233?\tab x \{y\}\par}";
+
+    #[test]
+    fn rtf_is_selected_and_applied_with_plain_fallback() {
+        // LibreOffice-style offer list: RTF wins over plain text.
+        let offered = ["text/plain;charset=utf-8", "text/plain", "text/rtf"];
+        let picked = pick_rtf_mime(|m| offered.contains(&m));
+        assert_eq!(picked, Some("text/rtf"));
+        assert_eq!(pick_rtf_mime(|m| m.starts_with("text/plain")), None);
+
+        let items = rtf_clipboard_items("application/rtf", SAMPLE);
+        let get = |m: &str| items.iter().find(|(k, _)| k == m).map(|(_, v)| v.clone());
+        assert_eq!(get("application/rtf").as_deref(), Some(SAMPLE));
+        assert_eq!(get("text/rtf").as_deref(), Some(SAMPLE));
+        let plain = String::from_utf8(get("text/plain;charset=utf-8").unwrap()).unwrap();
+        assert_eq!(plain, "Hello bold wörld\nCafé\tx {y}");
+    }
+}
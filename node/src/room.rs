@@ -0,0 +1,82 @@
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::instances::allow_other_rooms;
+
+pub use utils::paths::{request_room_switch, room_control_path, ROOM_CONTROL_FILE};
+
+const ROOM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn read_room_control(path: &Path) -> Option<String> {
+    let s = std::fs::read_to_string(path).ok()?;
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+/// Follow the room control file, starting from `initial`.
+///
+/// Only changes made after this call count: a file left over from an earlier session does not
+/// override the `--room` the service was started with. With `--allow-other-rooms` the file is
+/// shared by services in different rooms, so following it would merge them; requests are
+/// ignored then.
+pub fn watch_room(state_dir: &Path, initial: &str) -> watch::Receiver<String> {
+    let (tx, rx) = watch::channel(initial.to_string());
+    let path = room_control_path(state_dir);
+    let mut seen = read_room_control(&path);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(ROOM_POLL_INTERVAL).await;
+            if tx.is_closed() {
+                break;
+            }
+            let cur = read_room_control(&path);
+            if cur == seen {
+                continue;
+            }
+            seen = cur.clone();
+            if allow_other_rooms() {
+                log::warn!("room switch to {:?} ignored (--allow-other-rooms)", cur);
+                continue;
+            }
+            if let Some(room) = cur {
+                tx.send_if_modified(|r| {
+                    if *r == room {
+                        return false;
+                    }
+                    log::info!("room switch: '{}' -> '{}'", r, room);
+                    *r = room;
+                    true
+                });
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn room_watch_follows_control_file_changes_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        // Stale request from an earlier session: must not override the startup room.
+        request_room_switch(tmp.path(), "stale").unwrap();
+
+        let mut rx = watch_room(tmp.path(), "a");
+        tokio::time::sleep(ROOM_POLL_INTERVAL * 2).await;
+        assert_eq!(*rx.borrow_and_update(), "a");
+
+        request_room_switch(tmp.path(), "b").unwrap();
+        tokio::time::timeout(Duration::from_secs(3), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*rx.borrow(), "b");
+
+        assert!(request_room_switch(tmp.path(), " ").is_err());
+    }
+}
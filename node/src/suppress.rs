@@ -1,22 +1,25 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use crate::consts::FILE_SUPPRESS_KEY;
+use crate::hash::fingerprint;
+use crate::instances::allow_other_rooms;
+use crate::lock::is_locked;
 
-pub fn suppress_path(state_dir: &PathBuf, room: &str, mime: &str) -> PathBuf {
+pub fn suppress_path(state_dir: &Path, room: &str, mime: &str) -> PathBuf {
     // include room to allow multiple rooms on same machine
     let safe_room = room.replace('/', "_");
-    let safe_mime = mime.replace('/', "_").replace(';', "_").replace('=', "_");
+    let safe_mime = mime.replace(['/', ';', '='], "_");
     state_dir.join(format!("suppress_{}_{}", safe_room, safe_mime))
 }
 
-pub async fn set_suppress(state_dir: &PathBuf, room: &str, mime: &str, sha: &str, ttl: Duration) {
+pub async fn set_suppress(state_dir: &Path, room: &str, mime: &str, sha: &str, ttl: Duration) {
     let expires = utils::now_ms().saturating_add(ttl.as_millis() as u64);
     let p = suppress_path(state_dir, room, mime);
     let _ = tokio::fs::write(p, format!("{}\n{}\n", sha, expires)).await;
 }
 
-pub async fn is_suppressed(state_dir: &PathBuf, room: &str, mime: &str, sha: &str) -> bool {
+pub async fn is_suppressed(state_dir: &Path, room: &str, mime: &str, sha: &str) -> bool {
     let p = suppress_path(state_dir, room, mime);
     let s = match tokio::fs::read_to_string(p).await {
         Ok(v) => v,
@@ -39,10 +42,450 @@ pub async fn is_suppressed(state_dir: &PathBuf, room: &str, mime: &str, sha: &st
     utils::now_ms() <= exp
 }
 
-pub async fn is_file_suppressed(state_dir: &PathBuf, room: &str, sha: &str) -> bool {
+pub async fn is_file_suppressed(state_dir: &Path, room: &str, sha: &str) -> bool {
     is_suppressed(state_dir, room, FILE_SUPPRESS_KEY, sha).await
 }
 
-pub async fn set_file_suppress(state_dir: &PathBuf, room: &str, sha: &str, ttl: Duration) {
+pub async fn set_file_suppress(state_dir: &Path, room: &str, sha: &str, ttl: Duration) {
     set_suppress(state_dir, room, FILE_SUPPRESS_KEY, sha, ttl).await;
 }
+
+/// How long the watchers ignore a file selection wl-apply just wrote.
+pub const FILE_APPLY_SUPPRESS: Duration = Duration::from_millis(1500);
+
+/// Suppress exactly what was just written to the clipboard: per MIME, by content sha.
+///
+/// Unlike a `*` marker, a different copy the user makes within `ttl` still goes out.
+pub async fn suppress_items(
+    state_dir: &Path,
+    room: &str,
+    items: &[(String, Vec<u8>)],
+    ttl: Duration,
+) {
+    for (mime, bytes) in items {
+        set_suppress(state_dir, room, mime, &fingerprint(bytes), ttl).await;
+    }
+}
+
+/// How long content written by wl-apply is recognised as our own (by sha, any MIME).
+pub const APPLIED_TTL: Duration = Duration::from_secs(10);
+/// Entries kept in the applied log (one incoming event may put several variants on the clipboard).
+const APPLIED_LOG_MAX: usize = 32;
+
+/// One log for the whole device: there is one clipboard, whichever room wrote to it.
+pub fn applied_path(state_dir: &Path) -> PathBuf {
+    state_dir.join("applied")
+}
+
+/// Record the shas of what wl-apply is about to write to the clipboard
+/// (`<expires> <sha> <event_id> <room>`).
+///
+/// Unlike the per-MIME suppress markers and the applied marker MIME, this is checked against the
+/// bytes the watcher actually reads back, so it still works when the marker is dropped or the
+/// watcher fires before the marker is visible. Call it *before* writing the clipboard.
+pub async fn record_applied<I>(state_dir: &Path, room: &str, event_id: &str, shas: I)
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let p = applied_path(state_dir);
+    let expires = utils::now_ms().saturating_add(APPLIED_TTL.as_millis() as u64);
+    let added: Vec<String> = shas
+        .into_iter()
+        .map(|sha| format!("{expires} {} {event_id} {room}", sha.as_ref()))
+        .collect();
+    let _ = tokio::task::spawn_blocking(move || record_applied_blocking(&p, added)).await;
+}
+
+/// The log's read-filter-append runs under an exclusive lock, so appliers running at once
+/// (several rooms, a bridge) keep each other's entries and don't share the tmp file.
+fn record_applied_blocking(p: &Path, added: Vec<String>) {
+    let _lock = lock_file(&p.with_extension("lock"));
+    let now = utils::now_ms();
+    let mut lines: Vec<String> = std::fs::read_to_string(p)
+        .unwrap_or_default()
+        .lines()
+        .filter(|l| applied_entry(l).is_some_and(|(exp, _, _)| exp >= now))
+        .map(str::to_string)
+        .chain(added)
+        .collect();
+    let skip = lines.len().saturating_sub(APPLIED_LOG_MAX);
+    lines.drain(..skip);
+
+    // Replace atomically: watchers read without the lock.
+    let tmp = p.with_extension("tmp");
+    if std::fs::write(&tmp, lines.join("\n") + "\n").is_ok() {
+        let _ = std::fs::rename(&tmp, p);
+    }
+}
+
+/// `(expires, sha, room)` of a log line; the room is the rest of the line.
+fn applied_entry(line: &str) -> Option<(u64, &str, &str)> {
+    let mut it = line.splitn(4, ' ');
+    let exp = it.next()?.parse().ok()?;
+    let sha = it.next().filter(|s| !s.is_empty())?;
+    let _event_id = it.next();
+    Some((exp, sha, it.next().unwrap_or("")))
+}
+
+/// Whether `sha` is content wl-apply wrote recently (i.e. publishing it would echo it back).
+///
+/// Any room's apply counts, so a watcher that runs apart from the applier (or just switched
+/// rooms) never sends it out again. Only a device bridging rooms on purpose
+/// (`--allow-other-rooms`) republishes what it applied from another room.
+pub async fn is_recently_applied(state_dir: &Path, room: &str, sha: &str) -> bool {
+    applied_by(state_dir, sha, allow_other_rooms().then_some(room)).await
+}
+
+/// `only_room`: ignore what was applied for other rooms.
+async fn applied_by(state_dir: &Path, sha: &str, only_room: Option<&str>) -> bool {
+    let Ok(s) = tokio::fs::read_to_string(applied_path(state_dir)).await else {
+        return false;
+    };
+    let now = utils::now_ms();
+    s.lines()
+        .filter_map(applied_entry)
+        .any(|(exp, h, r)| h == sha && exp >= now && only_room.is_none_or(|room| r == room))
+}
+
+/// How long identical content is sent only once, however many hooks fire for it.
+pub const SEND_CLAIM_TTL: Duration = Duration::from_millis(1000);
+
+/// Last content claimed for sending in `room` (dry runs keep their own, like their locks).
+pub fn send_claim_path(state_dir: &Path, room: &str, dry_run: bool) -> PathBuf {
+    let safe_room = room.replace('/', "_");
+    let prefix = if dry_run {
+        "sending-dry-run"
+    } else {
+        "sending"
+    };
+    state_dir.join(format!("{}_{}", prefix, safe_room))
+}
+
+/// Claim the send of `sha` in `room`; false while another process claimed the same content
+/// within `ttl`.
+///
+/// One copy can fire several `wl-paste --watch` hooks at once, each racing to publish before
+/// the others' suppress markers exist. The claim is read and written under an exclusive lock,
+/// so exactly one of them wins. Call it as soon as a send is decided.
+pub async fn claim_send(
+    state_dir: &Path,
+    room: &str,
+    sha: &str,
+    ttl: Duration,
+    dry_run: bool,
+) -> bool {
+    let p = send_claim_path(state_dir, room, dry_run);
+    let sha = sha.to_string();
+    tokio::task::spawn_blocking(move || claim_send_blocking(&p, &sha, ttl))
+        .await
+        // A state dir we can't use must not stop syncing.
+        .unwrap_or(true)
+}
+
+/// `p`, created if missing and opened read-write under an exclusive `flock` that is held until
+/// the file is dropped; `None` when the state dir can't be used.
+#[cfg(unix)]
+pub(crate) fn lock_file(p: &Path) -> Option<std::fs::File> {
+    use std::os::unix::io::AsRawFd;
+
+    let f = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(p)
+        .ok()?;
+    (unsafe { libc::flock(f.as_raw_fd(), libc::LOCK_EX) } == 0).then_some(f)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock_file(_p: &Path) -> Option<std::fs::File> {
+    None
+}
+
+#[cfg(unix)]
+fn claim_send_blocking(p: &Path, sha: &str, ttl: Duration) -> bool {
+    use std::io::{Read, Seek, Write};
+
+    // The critical section is one small read and write.
+    let Some(mut f) = lock_file(p) else {
+        return true;
+    };
+    let mut s = String::new();
+    let _ = f.read_to_string(&mut s);
+    let mut it = s.lines();
+    let claimed = it.next().unwrap_or("").trim();
+    let exp: u64 = it.next().unwrap_or("0").trim().parse().unwrap_or(0);
+    let now = utils::now_ms();
+    if claimed == sha && now <= exp {
+        return false;
+    }
+    let expires = now.saturating_add(ttl.as_millis() as u64);
+    let _ = f
+        .set_len(0)
+        .and_then(|_| f.rewind())
+        .and_then(|_| write!(f, "{}\n{}\n", sha, expires));
+    true
+}
+
+#[cfg(not(unix))]
+fn claim_send_blocking(_p: &Path, _sha: &str, _ttl: Duration) -> bool {
+    true
+}
+
+pub use utils::paths::paused_path;
+
+/// Pause/resume sync for `room`: while the flag file exists, wl-watch publishes nothing and
+/// wl-apply ignores incoming events (connections and heartbeats stay up).
+pub async fn set_paused(state_dir: &Path, room: &str, paused: bool) -> std::io::Result<()> {
+    let p = paused_path(state_dir, room);
+    if paused {
+        tokio::fs::create_dir_all(state_dir).await?;
+        tokio::fs::write(p, format!("{}\n", utils::now_ms())).await
+    } else {
+        match tokio::fs::remove_file(p).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether `room` is paused, the whole node is disabled (see [`set_disabled`]) or the session
+/// is locked under `--pause-on-lock`.
+pub async fn is_paused(state_dir: &Path, room: &str) -> bool {
+    is_disabled(state_dir).await
+        || is_locked(state_dir).await
+        || tokio::fs::metadata(paused_path(state_dir, room)).await.is_ok()
+}
+
+pub use utils::paths::{disabled_path, DISABLED_FILE};
+
+/// Stop/allow all syncing on this device: while the file exists, nothing is sent or applied
+/// in any room, including one-shot sends (running services stay connected).
+pub async fn set_disabled(state_dir: &Path, disabled: bool) -> std::io::Result<()> {
+    let p = disabled_path(state_dir);
+    if disabled {
+        tokio::fs::create_dir_all(state_dir).await?;
+        tokio::fs::write(p, format!("{}\n", utils::now_ms())).await
+    } else {
+        match tokio::fs::remove_file(p).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub async fn is_disabled(state_dir: &Path) -> bool {
+    tokio::fs::metadata(disabled_path(state_dir)).await.is_ok()
+}
+
+/// For one-shot commands: refuse to send while the kill switch is on.
+pub async fn ensure_enabled(state_dir: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !is_disabled(state_dir).await,
+        "syncing is disabled on this device ({}); run `enable` first",
+        disabled_path(state_dir).display()
+    );
+    Ok(())
+}
+
+/// `wl-apply --max-age-ms`: skip messages too old to apply, e.g. a backlog delivered after a
+/// reconnect that would overwrite a newer local clipboard.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Staleness {
+    /// `None` applies messages regardless of age.
+    pub max_age: Option<Duration>,
+    /// Extra allowance for clocks that differ between devices.
+    pub clock_skew: Duration,
+}
+
+impl Staleness {
+    pub fn is_stale(&self, msg: &utils::Message, now_ms: u64) -> bool {
+        let Some(max_age) = self.max_age else {
+            return false;
+        };
+        // ts=0: sender had no usable clock. A ts ahead of ours is skew, not staleness.
+        if msg.ts == 0 {
+            return false;
+        }
+        now_ms.saturating_sub(msg.ts) > (max_age + self.clock_skew).as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn kill_switch_stops_sends_and_applies_in_every_room() {
+        use crate::transfer_file::{prepare_paths_bundle, PreparedPaths};
+
+        let state = tempfile::tempdir().unwrap();
+        let dir = state.path();
+        let src = tempfile::tempdir().unwrap();
+        let f = src.path().join("a.txt");
+        std::fs::write(&f, b"secret").unwrap();
+        let bundle = || prepare_paths_bundle(dir, "r", vec![f.clone()], 1 << 20);
+
+        assert!(matches!(bundle().await.unwrap(), PreparedPaths::Ready(_)));
+        set_disabled(dir, true).await.unwrap();
+        // wl-watch, the hook and wl-apply all gate on `is_paused` before acting.
+        for room in ["r", "other"] {
+            assert!(is_paused(dir, room).await, "{room}");
+        }
+        assert!(matches!(bundle().await.unwrap(), PreparedPaths::Nothing));
+        // One-shot sends refuse outright.
+        assert!(ensure_enabled(dir).await.is_err());
+
+        set_disabled(dir, false).await.unwrap();
+        set_disabled(dir, false).await.unwrap();
+        assert!(!is_paused(dir, "r").await);
+        assert!(ensure_enabled(dir).await.is_ok());
+        assert!(matches!(bundle().await.unwrap(), PreparedPaths::Ready(_)));
+    }
+
+    #[tokio::test]
+    async fn watchers_in_two_rooms_keep_their_state_apart() {
+        use crate::instances::{acquire_instance_lock, active_instances};
+
+        let state = tempfile::tempdir().unwrap();
+        let dir = state.path();
+        let relay = "127.0.0.1:8080";
+        let _a = acquire_instance_lock(dir, "wl-watch", "a", relay).unwrap();
+        let _b = acquire_instance_lock(dir, "wl-watch", "b", relay).unwrap();
+        assert_eq!(active_instances(dir, "wl-watch").len(), 2);
+
+        let sha = fingerprint(b"hello");
+        set_suppress(dir, "a", "text/plain", &sha, Duration::from_secs(60)).await;
+        record_applied(dir, "a", "ev", [sha.as_str()]).await;
+        set_paused(dir, "a", true).await.unwrap();
+
+        assert!(is_suppressed(dir, "a", "text/plain", &sha).await);
+        assert!(is_recently_applied(dir, "a", &sha).await);
+        assert!(is_paused(dir, "a").await);
+        // Room a's suppress markers and pause are its own...
+        assert!(!is_suppressed(dir, "b", "text/plain", &sha).await);
+        assert!(!is_paused(dir, "b").await);
+        // ...but what it applied is on the one clipboard, so b must not publish it either.
+        assert!(is_recently_applied(dir, "b", &sha).await);
+    }
+
+    #[test]
+    fn applier_and_watcher_processes_share_the_applied_log() {
+        let state = tempfile::tempdir().unwrap();
+        let dir = state.path().to_path_buf();
+        let (clipboard, watched) = std::sync::mpsc::channel::<Vec<u8>>();
+
+        // Separate threads with their own runtimes, talking only through the state dir and
+        // the "clipboard" - like wl-apply and wl-watch. The watcher has moved to room b.
+        let applier_dir = dir.clone();
+        let applier = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            for i in 0..20 {
+                let bytes = format!("from a peer {i}").into_bytes();
+                let sha = fingerprint(&bytes);
+                rt.block_on(record_applied(&applier_dir, "a", "ev", [sha]));
+                clipboard.send(bytes).unwrap();
+            }
+            clipboard.send(b"typed locally".to_vec()).unwrap();
+        });
+        let watcher = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            watched
+                .iter()
+                .filter(|bytes| !rt.block_on(is_recently_applied(&dir, "b", &fingerprint(bytes))))
+                .collect::<Vec<_>>()
+        });
+        applier.join().unwrap();
+        assert_eq!(watcher.join().unwrap(), [b"typed locally".to_vec()]);
+    }
+
+    #[test]
+    fn concurrent_appliers_keep_each_others_entries() {
+        let state = tempfile::tempdir().unwrap();
+        let shas: Vec<String> = (0..8)
+            .map(|i| fingerprint(format!("{i}").as_bytes()))
+            .collect();
+        let appliers: Vec<_> = shas
+            .iter()
+            .cloned()
+            .map(|sha| {
+                let dir = state.path().to_path_buf();
+                std::thread::spawn(move || {
+                    let rt = tokio::runtime::Runtime::new().unwrap();
+                    rt.block_on(record_applied(&dir, "r", "ev", [sha]));
+                })
+            })
+            .collect();
+        for a in appliers {
+            a.join().unwrap();
+        }
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for sha in &shas {
+            assert!(rt.block_on(is_recently_applied(state.path(), "r", sha)));
+        }
+    }
+
+    #[tokio::test]
+    async fn bridging_republishes_only_other_rooms_applies() {
+        let state = tempfile::tempdir().unwrap();
+        let dir = state.path();
+        let sha = fingerprint(b"x");
+        record_applied(dir, "a b", "ev", [sha.as_str()]).await;
+        assert!(applied_by(dir, &sha, None).await);
+        assert!(applied_by(dir, &sha, Some("a b")).await);
+        assert!(!applied_by(dir, &sha, Some("b")).await);
+    }
+
+    #[tokio::test]
+    async fn a_peer_on_another_hash_algo_is_still_suppressed() {
+        use crate::hash::{fingerprint_with, reconcile_fingerprint, HashAlgo};
+
+        let state = tempfile::tempdir().unwrap();
+        let dir = state.path();
+        let text = b"hello";
+        let mime = "text/plain;charset=utf-8";
+        // The sender fingerprints with blake3; this device (and its watcher) with sha256.
+        let remote = fingerprint_with(HashAlgo::Blake3, text);
+        let watcher_sees = fingerprint_with(HashAlgo::Sha256, text);
+
+        set_suppress(dir, "r", mime, &remote, Duration::from_secs(60)).await;
+        assert!(!is_suppressed(dir, "r", mime, &watcher_sees).await);
+
+        let local = reconcile_fingerprint(&remote, text, HashAlgo::Sha256);
+        set_suppress(dir, "r", mime, &local, Duration::from_secs(60)).await;
+        record_applied(dir, "r", "ev", [local.as_str()]).await;
+        assert!(is_suppressed(dir, "r", mime, &watcher_sees).await);
+        assert!(is_recently_applied(dir, "r", &watcher_sees).await);
+        // Another payload isn't mistaken for it under either algorithm.
+        for algo in [HashAlgo::Sha256, HashAlgo::Blake3] {
+            let other = fingerprint_with(algo, b"other");
+            assert!(!is_suppressed(dir, "r", mime, &other).await);
+        }
+    }
+
+    #[test]
+    fn old_message_is_dropped_fresh_one_applied() {
+        let now = utils::now_ms();
+        let at = |ts: u64| {
+            let mut m = utils::Message::new_text("peer", "room", "x");
+            m.ts = ts;
+            m
+        };
+        let s = Staleness {
+            max_age: Some(Duration::from_secs(30)),
+            clock_skew: Duration::from_secs(2),
+        };
+        assert!(s.is_stale(&at(now - 60_000), now));
+        assert!(!s.is_stale(&at(now - 1_000), now));
+        // Within max age + skew tolerance.
+        assert!(!s.is_stale(&at(now - 31_000), now));
+        // Sender clock ahead of ours.
+        assert!(!s.is_stale(&at(now + 5_000), now));
+        assert!(!s.is_stale(&at(0), now));
+
+        // Off by default.
+        assert!(!Staleness::default().is_stale(&at(1), now));
+    }
+}
@@ -0,0 +1,331 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Env var used to pass the upload limit to helper processes (e.g. the wl-watch hook).
+pub const MAX_UPLOAD_KBPS_ENV: &str = "MCR_MAX_UPLOAD_KBPS";
+
+/// Env var used to pass the clipboard event limit to helper processes.
+pub const MAX_EVENTS_PER_SEC_ENV: &str = "MCR_MAX_EVENTS_PER_SEC";
+
+/// Largest single write while throttled; keeps pacing smooth for big bundles.
+const MAX_CHUNK: usize = 16 * 1024;
+
+/// Simple token bucket (bytes). Callers may go into debt; the debt is paid by sleeping.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn from_kbps(kbps: u64) -> Self {
+        // kbps = kilobits per second.
+        let rate = (kbps.max(1) as f64) * 1000.0 / 8.0;
+        // Allow ~100ms worth of burst (but at least 1 KiB so tiny rates still make progress).
+        let burst = (rate / 10.0).max(1024.0);
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    fn chunk_size(&self) -> usize {
+        (self.burst as usize).clamp(1024, MAX_CHUNK)
+    }
+
+    /// Reserve `n` bytes and return how long the caller must wait before sending them.
+    fn reserve(&mut self, n: usize) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.tokens -= n as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+static UPLOAD_LIMIT: OnceLock<Option<Mutex<TokenBucket>>> = OnceLock::new();
+
+fn limit_from_env() -> Option<u64> {
+    std::env::var(MAX_UPLOAD_KBPS_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|v| *v > 0)
+}
+
+/// Configure the per-process upload limit (0/None = unlimited).
+///
+/// Must be called before the first send; otherwise the env var (if any) wins.
+pub fn set_max_upload_kbps(kbps: Option<u64>) {
+    let kbps = kbps.filter(|v| *v > 0).or_else(limit_from_env);
+    let _ = UPLOAD_LIMIT.set(kbps.map(|k| Mutex::new(TokenBucket::from_kbps(k))));
+}
+
+fn upload_limit() -> Option<&'static Mutex<TokenBucket>> {
+    UPLOAD_LIMIT
+        .get_or_init(|| limit_from_env().map(|k| Mutex::new(TokenBucket::from_kbps(k))))
+        .as_ref()
+}
+
+/// Effective limit (kbps) for propagating to helper processes.
+pub fn max_upload_kbps() -> Option<u64> {
+    upload_limit().map(|b| (b.lock().unwrap().rate * 8.0 / 1000.0).round() as u64)
+}
+
+/// Write `buf`, pacing with the given bucket.
+pub async fn write_paced<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    buf: &[u8],
+    bucket: &Mutex<TokenBucket>,
+) -> std::io::Result<()> {
+    let chunk = bucket.lock().unwrap().chunk_size();
+    for part in buf.chunks(chunk) {
+        let wait = bucket.lock().unwrap().reserve(part.len());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        w.write_all(part).await?;
+    }
+    Ok(())
+}
+
+/// Write `buf`, honoring the process-wide upload limit (if configured).
+pub async fn write_throttled<W: AsyncWrite + Unpin>(w: &mut W, buf: &[u8]) -> std::io::Result<()> {
+    match upload_limit() {
+        Some(bucket) => write_paced(w, buf, bucket).await,
+        None => w.write_all(buf).await,
+    }
+}
+
+static EVENT_LIMIT: OnceLock<Option<u32>> = OnceLock::new();
+
+fn events_from_env() -> Option<u32> {
+    std::env::var(MAX_EVENTS_PER_SEC_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .filter(|v| *v > 0)
+}
+
+/// Configure how many clipboard events per second this device publishes (0/None = unlimited).
+///
+/// Must be called before the first send; otherwise the env var (if any) wins.
+pub fn set_max_events_per_sec(n: Option<u32>) {
+    let _ = EVENT_LIMIT.set(n.filter(|v| *v > 0).or_else(events_from_env));
+}
+
+pub fn max_events_per_sec() -> Option<u32> {
+    *EVENT_LIMIT.get_or_init(events_from_env)
+}
+
+/// Token bucket counting clipboard events. It holds a single token, so events go out at
+/// least `1 / rate` apart and no one-second window sees more than `rate` of them.
+///
+/// Plain numbers (no `Instant`) so the watch hooks can share one through a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventBucket {
+    tokens: f64,
+    at_ms: u64,
+}
+
+impl EventBucket {
+    pub fn full(now_ms: u64) -> Self {
+        Self {
+            tokens: 1.0,
+            at_ms: now_ms,
+        }
+    }
+
+    /// Take one event at `now_ms`, or return how long until the next one is free.
+    pub fn take(&mut self, rate: u32, now_ms: u64) -> Result<(), Duration> {
+        let rate = rate.max(1) as f64;
+        let elapsed = now_ms.saturating_sub(self.at_ms) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * rate).min(1.0);
+        self.at_ms = self.at_ms.max(now_ms);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// The poll loop's event limit: a change over the rate isn't sent (nor remembered as sent),
+/// so a later poll publishes whatever the clipboard holds by then.
+#[derive(Debug)]
+pub struct EventGate {
+    limit: Option<(u32, EventBucket)>,
+}
+
+impl EventGate {
+    pub fn new(rate: Option<u32>) -> Self {
+        Self {
+            limit: rate.map(|r| (r, EventBucket::full(utils::now_ms()))),
+        }
+    }
+
+    /// Whether one more event may go out now (always, without a limit).
+    pub fn allow(&mut self) -> bool {
+        self.allow_at(utils::now_ms())
+    }
+
+    fn allow_at(&mut self, now_ms: u64) -> bool {
+        match &mut self.limit {
+            Some((rate, bucket)) => bucket.take(*rate, now_ms).is_ok(),
+            None => true,
+        }
+    }
+}
+
+/// Event bucket shared by the watch hooks of `room`.
+pub fn event_rate_path(state_dir: &Path, room: &str) -> PathBuf {
+    let safe_room = room.replace('/', "_");
+    state_dir.join(format!("event_rate_{}", safe_room))
+}
+
+#[derive(Debug, PartialEq)]
+enum EventSlot {
+    Go,
+    Wait(Duration),
+    Superseded,
+}
+
+/// Wait until `--max-events-per-sec` lets this hook publish; `false` when a newer copy queued
+/// behind it meanwhile (that one goes out instead, so bursts coalesce to the latest content).
+///
+/// Each hook is a separate process, so the bucket and the newest ticket live in a file.
+pub async fn wait_event_slot(state_dir: &Path, room: &str) -> bool {
+    let Some(rate) = max_events_per_sec() else {
+        return true;
+    };
+    let p = event_rate_path(state_dir, room);
+    let mut ticket = None;
+    loop {
+        let p2 = p.clone();
+        let res = tokio::task::spawn_blocking(move || event_slot_blocking(&p2, rate, ticket))
+            .await
+            // A state dir we can't use must not stop syncing.
+            .unwrap_or((0, EventSlot::Go));
+        match res {
+            (_, EventSlot::Go) => return true,
+            (_, EventSlot::Superseded) => return false,
+            (mine, EventSlot::Wait(d)) => {
+                ticket = Some(mine);
+                tokio::time::sleep(d).await;
+            }
+        }
+    }
+}
+
+/// One locked step of [`wait_event_slot`]: queue as the newest event (no `ticket` yet), then
+/// try to take a token. The file holds `tokens at_ms newest_ticket`.
+#[cfg(unix)]
+fn event_slot_blocking(p: &Path, rate: u32, ticket: Option<u64>) -> (u64, EventSlot) {
+    use std::io::{Read, Seek, Write};
+
+    // The critical section is one small read and write.
+    let Some(mut f) = crate::suppress::lock_file(p) else {
+        return (0, EventSlot::Go);
+    };
+    let mut s = String::new();
+    let _ = f.read_to_string(&mut s);
+    let now = utils::now_ms();
+    let mut it = s.split_whitespace();
+    let tokens = it.next().and_then(|v| v.parse::<f64>().ok());
+    let at_ms = it.next().and_then(|v| v.parse::<u64>().ok());
+    let newest = it.next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+    let mut bucket = match (tokens, at_ms) {
+        (Some(tokens), Some(at_ms)) => EventBucket { tokens, at_ms },
+        _ => EventBucket::full(now),
+    };
+    let mine = ticket.unwrap_or(newest + 1);
+    let newest = newest.max(mine);
+    if mine != newest {
+        return (mine, EventSlot::Superseded);
+    }
+    let slot = match bucket.take(rate, now) {
+        Ok(()) => EventSlot::Go,
+        Err(d) => EventSlot::Wait(d),
+    };
+    let _ = f
+        .set_len(0)
+        .and_then(|_| f.rewind())
+        .and_then(|_| writeln!(f, "{} {} {}", bucket.tokens, bucket.at_ms, newest));
+    (mine, slot)
+}
+
+#[cfg(not(unix))]
+fn event_slot_blocking(_p: &Path, _rate: u32, _ticket: Option<u64>) -> (u64, EventSlot) {
+    (0, EventSlot::Go)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn paced_write_respects_rate() {
+        // 80 kbps = 10_000 bytes/s, burst = 1 KiB.
+        let bucket = Mutex::new(TokenBucket::from_kbps(80));
+        let payload = vec![0u8; 10_000];
+        let mut sink = tokio::io::sink();
+
+        let start = Instant::now();
+        write_paced(&mut sink, &payload, &bucket).await.unwrap();
+        let took = start.elapsed();
+
+        // (10_000 - 1024) / 10_000 s ≈ 0.9s minimum.
+        assert!(took >= Duration::from_millis(850), "took {took:?}");
+    }
+
+    #[test]
+    fn bucket_allows_initial_burst() {
+        let mut b = TokenBucket::from_kbps(80);
+        assert_eq!(b.reserve(1024), Duration::ZERO);
+        assert!(b.reserve(1000) > Duration::ZERO);
+    }
+
+    #[test]
+    fn event_burst_sends_at_most_the_rate_and_the_latest_wins() {
+        // Ten copies within one second at 3 events/s: the poll loop sends three, spaced out.
+        let mut gate = EventGate::new(Some(3));
+        let start = utils::now_ms();
+        let sent: Vec<u64> = (0..10u64)
+            .filter(|i| gate.allow_at(start + i * 90))
+            .collect();
+        assert_eq!(sent, vec![0, 4, 8]);
+        assert!(EventGate::new(None).allow());
+
+        // Hooks: every copy queues; an earlier one still waiting for a slot gives way.
+        let dir = tempfile::tempdir().unwrap();
+        let p = event_rate_path(dir.path(), "r");
+        let mut waiting = Vec::new();
+        let mut sends = 0;
+        for _ in 0..10 {
+            match event_slot_blocking(&p, 3, None) {
+                (_, EventSlot::Go) => sends += 1,
+                (mine, EventSlot::Wait(_)) => waiting.push(mine),
+                (_, EventSlot::Superseded) => unreachable!("a new copy is always the newest"),
+            }
+        }
+        assert_eq!(sends, 1);
+        let (&latest, older) = waiting.split_last().unwrap();
+        for &t in older {
+            assert_eq!(event_slot_blocking(&p, 3, Some(t)).1, EventSlot::Superseded);
+        }
+        // The latest copy goes out once a token is back.
+        std::thread::sleep(Duration::from_millis(350));
+        assert_eq!(event_slot_blocking(&p, 3, Some(latest)).1, EventSlot::Go);
+    }
+}
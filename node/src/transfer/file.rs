@@ -1,21 +1,30 @@
 use anyhow::Context;
-use std::io::Cursor;
-use std::time::UNIX_EPOCH;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::time::{Instant, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use url::Url;
 use walkdir::WalkDir;
 
-use crate::consts::TAR_MIME;
-use crate::hash::sha256_hex;
-use crate::history::record_send;
-use crate::net::{connect, send_frame};
-use crate::suppress::is_file_suppressed;
-
+use crate::channel::stamp_channel;
+use crate::content_filter::{filter_outgoing, filters_outgoing};
+use crate::device::sender_name;
+use crate::consts::{APPLIED_MARKER_MIME, GNOME_COPIED_FILES_MIME, TAR_MIME, URI_LIST_MIME};
+use crate::hash::{fingerprint, Fingerprinter};
+use crate::events::emit_event;
+use crate::history::{record_event, record_send, send_outcome_event, Outcome};
+use crate::net::{connect, connect_with_backoff, join_for_ack, wait_for_ack, write_frame_streamed};
+use crate::poll::FileCooldown;
+use crate::suppress::{is_file_suppressed, is_paused, is_recently_applied};
+use crate::transfer_image::sniff_mime;
+
+use tokio::io::AsyncWrite;
 use utils::{Kind, Message};
 
-pub fn detect_file_mime(bytes: &[u8], file: &PathBuf) -> String {
-    if let Some(kind) = infer::get(bytes) {
-        return kind.mime_type().to_string();
+pub fn detect_file_mime(bytes: &[u8], file: &Path) -> String {
+    if let Some(mime) = sniff_mime(bytes) {
+        return mime.to_string();
     }
     // Extension-based minimal hints for common cases.
     let ext = file
@@ -113,7 +122,7 @@ fn common_path_prefix(paths: &[PathBuf]) -> Option<PathBuf> {
     Some(prefix)
 }
 
-fn common_path_prefix2(a: &PathBuf, b: &PathBuf) -> PathBuf {
+fn common_path_prefix2(a: &Path, b: &Path) -> PathBuf {
     let mut out = PathBuf::new();
     let mut ita = a.components();
     let mut itb = b.components();
@@ -154,39 +163,141 @@ fn mtime_secs_or_zero(md: Option<&std::fs::Metadata>) -> u64 {
         .unwrap_or(0)
 }
 
-fn header_for_dir_with(md: Option<&std::fs::Metadata>) -> tar::Header {
+/// How tar header mtimes are recorded in file bundles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BundleMtime {
+    /// Keep real modification times (default).
+    Preserve,
+    /// Write 0 so repeated copies of unchanged content hash identically.
+    Zero,
+}
+
+/// Env var used to pass `--bundle-mtime` to helper processes (e.g. the wl-watch hook).
+pub const BUNDLE_MTIME_ENV: &str = "MCR_BUNDLE_MTIME";
+
+pub fn parse_bundle_mtime(s: &str) -> anyhow::Result<BundleMtime> {
+    match s {
+        "preserve" => Ok(BundleMtime::Preserve),
+        "zero" => Ok(BundleMtime::Zero),
+        other => anyhow::bail!("invalid --bundle-mtime {}, expected preserve|zero", other),
+    }
+}
+
+pub fn bundle_mtime_as_cli_arg(m: BundleMtime) -> &'static str {
+    match m {
+        BundleMtime::Preserve => "preserve",
+        BundleMtime::Zero => "zero",
+    }
+}
+
+static BUNDLE_MTIME: OnceLock<BundleMtime> = OnceLock::new();
+
+/// Set the process-wide bundle mtime mode (call once at startup).
+pub fn set_bundle_mtime(m: BundleMtime) {
+    let _ = BUNDLE_MTIME.set(m);
+}
+
+/// Process-wide bundle mtime mode (CLI, then env, then `preserve`).
+pub fn bundle_mtime() -> BundleMtime {
+    *BUNDLE_MTIME.get_or_init(|| {
+        std::env::var(BUNDLE_MTIME_ENV)
+            .ok()
+            .and_then(|v| parse_bundle_mtime(v.trim()).ok())
+            .unwrap_or(BundleMtime::Preserve)
+    })
+}
+
+/// Env var for `--no-path-metadata` (also passed to helper processes).
+pub const NO_PATH_METADATA_ENV: &str = "MCR_NO_PATH_METADATA";
+
+static PATH_METADATA: OnceLock<bool> = OnceLock::new();
+
+fn no_path_metadata_from_env() -> bool {
+    matches!(
+        std::env::var(NO_PATH_METADATA_ENV).ok().as_deref(),
+        Some("1") | Some("true")
+    )
+}
+
+/// Whether outgoing bundles carry their source paths (process-wide, set once at startup).
+pub fn set_path_metadata(on: bool) {
+    let _ = PATH_METADATA.set(on && !no_path_metadata_from_env());
+}
+
+pub fn path_metadata() -> bool {
+    *PATH_METADATA.get_or_init(|| !no_path_metadata_from_env())
+}
+
+/// `Message::orig_paths` for a bundle of `paths`: their absolute form, or none when disabled.
+pub fn orig_paths_for(paths: &[PathBuf]) -> Option<Vec<String>> {
+    if !path_metadata() {
+        return None;
+    }
+    Some(
+        paths
+            .iter()
+            .map(|p| std::path::absolute(p).unwrap_or_else(|_| p.clone()))
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect(),
+    )
+}
+
+/// `orig_path=` lines for the applied marker, one per sender path.
+pub fn orig_path_marker_lines(msg: &Message) -> String {
+    msg.orig_paths
+        .iter()
+        .flatten()
+        .map(|p| format!("orig_path={}\n", p.replace('\n', " ")))
+        .collect()
+}
+
+fn header_mtime(md: Option<&std::fs::Metadata>, mtime: BundleMtime) -> u64 {
+    match mtime {
+        BundleMtime::Preserve => mtime_secs_or_zero(md),
+        BundleMtime::Zero => 0,
+    }
+}
+
+fn header_for_dir_with(md: Option<&std::fs::Metadata>, mtime: BundleMtime) -> tar::Header {
     let mut h = tar::Header::new_ustar();
     h.set_entry_type(tar::EntryType::Directory);
     h.set_size(0);
     h.set_mode(unix_mode_or_default(md, 0o755));
-    h.set_mtime(mtime_secs_or_zero(md));
+    h.set_mtime(header_mtime(md, mtime));
     h.set_uid(0);
     h.set_gid(0);
     h.set_cksum();
     h
 }
 
-fn header_for_file_with(len: u64, md: Option<&std::fs::Metadata>) -> tar::Header {
+fn header_for_file_with(
+    len: u64,
+    md: Option<&std::fs::Metadata>,
+    mtime: BundleMtime,
+) -> tar::Header {
     let mut h = tar::Header::new_ustar();
     h.set_entry_type(tar::EntryType::Regular);
     h.set_size(len);
     h.set_mode(unix_mode_or_default(md, 0o644));
-    h.set_mtime(mtime_secs_or_zero(md));
+    h.set_mtime(header_mtime(md, mtime));
     h.set_uid(0);
     h.set_gid(0);
     h.set_cksum();
     h
 }
 
-fn append_dir_deterministic(
-    builder: &mut tar::Builder<Vec<u8>>,
+/// Append `fs_dir` and everything under it. Every subdirectory gets its own header, so empty
+/// ones are recreated on the receiver too.
+fn append_dir_deterministic<W: Write>(
+    builder: &mut tar::Builder<W>,
     fs_dir: &PathBuf,
     archive_dir: &PathBuf,
+    mtime: BundleMtime,
 ) -> anyhow::Result<()> {
     // Root dir entry.
     let md_root = std::fs::metadata(fs_dir)
         .with_context(|| format!("metadata {}", fs_dir.display()))?;
-    let mut h = header_for_dir_with(Some(&md_root));
+    let mut h = header_for_dir_with(Some(&md_root), mtime);
     h.set_path(archive_dir)
         .with_context(|| format!("set tar dir path {}", archive_dir.display()))?;
     h.set_cksum();
@@ -213,7 +324,7 @@ fn append_dir_deterministic(
         if e.file_type().is_dir() {
             let md = std::fs::metadata(&fs_path)
                 .with_context(|| format!("metadata {}", fs_path.display()))?;
-            let mut h = header_for_dir_with(Some(&md));
+            let mut h = header_for_dir_with(Some(&md), mtime);
             h.set_path(&archive_path).with_context(|| {
                 format!("set tar dir path {}", archive_path.display())
             })?;
@@ -226,7 +337,7 @@ fn append_dir_deterministic(
                 .with_context(|| format!("metadata {}", fs_path.display()))?;
             let mut f = std::fs::File::open(&fs_path)
                 .with_context(|| format!("open {}", fs_path.display()))?;
-            let mut h = header_for_file_with(md.len(), Some(&md));
+            let mut h = header_for_file_with(md.len(), Some(&md), mtime);
             h.set_path(&archive_path).with_context(|| {
                 format!("set tar file path {}", archive_path.display())
             })?;
@@ -243,17 +354,18 @@ fn append_dir_deterministic(
     Ok(())
 }
 
-fn append_file_deterministic(
-    builder: &mut tar::Builder<Vec<u8>>,
+fn append_file_deterministic<W: Write>(
+    builder: &mut tar::Builder<W>,
     fs_file: &PathBuf,
     archive_file: &PathBuf,
+    mtime: BundleMtime,
 ) -> anyhow::Result<()> {
     let md = std::fs::metadata(fs_file).with_context(|| format!("metadata {}", fs_file.display()))?;
     if !md.is_file() {
         return Ok(());
     }
     let mut f = std::fs::File::open(fs_file).with_context(|| format!("open {}", fs_file.display()))?;
-    let mut h = header_for_file_with(md.len(), Some(&md));
+    let mut h = header_for_file_with(md.len(), Some(&md), mtime);
     h.set_path(archive_file)
         .with_context(|| format!("set tar file path {}", archive_file.display()))?;
     h.set_cksum();
@@ -264,7 +376,196 @@ fn append_file_deterministic(
 }
 
 pub fn build_tar_bundle(paths: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
-    let mut builder = tar::Builder::new(Vec::new());
+    build_tar_bundle_with(paths, bundle_mtime())
+}
+
+pub fn build_tar_bundle_with(paths: &[PathBuf], mtime: BundleMtime) -> anyhow::Result<Vec<u8>> {
+    write_tar_bundle(paths, mtime, Vec::new())
+}
+
+/// Buffer between the tar builder and the spool file.
+const SPOOL_BUFFER_BYTES: usize = 256 * 1024;
+
+/// Stops the build once the archive would pass `max` bytes, fingerprinting what goes through.
+struct CappedWriter<W> {
+    inner: W,
+    written: u64,
+    max: u64,
+    over: bool,
+    hash: Fingerprinter,
+}
+
+impl<W: Write> Write for CappedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.written + buf.len() as u64 > self.max {
+            self.over = true;
+            return Err(std::io::Error::other(format!("bundle exceeds {} bytes", self.max)));
+        }
+        let n = self.inner.write(buf)?;
+        self.hash.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A payload kept in a file, usually a bundle spooled to an unlinked temp file (see
+/// [`spool_tar_bundle_capped`]), so it is hashed and sent without being held in memory.
+#[derive(Debug)]
+pub struct SpooledPayload {
+    file: std::fs::File,
+    pub len: u64,
+    /// Fingerprint of the content, taken as it was spooled.
+    pub sha: String,
+}
+
+impl SpooledPayload {
+    /// Spool `bytes`, e.g. what `--send-filter` made of a bundle.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut file = tempfile::tempfile().context("create spool file")?;
+        file.write_all(bytes).context("write spool file")?;
+        Ok(Self {
+            file,
+            len: bytes.len() as u64,
+            sha: fingerprint(bytes),
+        })
+    }
+
+    /// `path` as it is (`send-file --mime`), hashed in one pass and read again when sent.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file =
+            std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut hash = Fingerprinter::new();
+        let mut buf = vec![0u8; SPOOL_BUFFER_BYTES];
+        let mut len = 0u64;
+        loop {
+            let n = file
+                .read(&mut buf)
+                .with_context(|| format!("read {}", path.display()))?;
+            if n == 0 {
+                break;
+            }
+            hash.update(&buf[..n]);
+            len += n as u64;
+        }
+        Ok(Self {
+            file,
+            len,
+            sha: hash.finish(),
+        })
+    }
+
+    /// The content from the start.
+    fn rewound(&self) -> std::io::Result<&std::fs::File> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+
+    pub fn read_all(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.len as usize);
+        self.rewound()
+            .and_then(|mut f| f.read_to_end(&mut out))
+            .context("read spool file")?;
+        Ok(out)
+    }
+
+    /// [`tar_entry_names`] of a spooled bundle.
+    pub fn entry_names(&self) -> Vec<String> {
+        self.rewound().map(tar_entry_names).unwrap_or_default()
+    }
+
+    /// A `File` message for this payload; its bytes go out with [`Self::write_frame`].
+    pub fn file_message(&self, device_id: &str, room: &str, name: &str, mime: &str) -> Message {
+        let mut msg = Message::new_file(device_id, room, name, mime, Vec::new());
+        msg.payload = None;
+        msg.size = self.len as usize;
+        stamp_channel(&mut msg);
+        msg
+    }
+
+    /// Write `msg` to `w` with this payload streamed in from the spool file.
+    pub async fn write_frame<W: AsyncWrite + Unpin>(
+        &self,
+        w: &mut W,
+        msg: &Message,
+    ) -> anyhow::Result<()> {
+        let file = self
+            .rewound()
+            .and_then(|f| f.try_clone())
+            .context("rewind spool file")?;
+        let mut file = tokio::fs::File::from_std(file);
+        write_frame_streamed(w, msg, &mut file, self.len).await
+    }
+}
+
+/// Run a spooled payload through `--send-filter`, when it covers files (`None` = drop it).
+pub async fn filter_outgoing_spooled(
+    spooled: SpooledPayload,
+) -> anyhow::Result<Option<SpooledPayload>> {
+    if !filters_outgoing(&Kind::File) {
+        return Ok(Some(spooled));
+    }
+    // The filter reads the whole payload on stdin and answers with a new one, so this is the
+    // one path that holds a bundle in memory.
+    let bytes = spooled.read_all()?;
+    let Some(out) = filter_outgoing(&Kind::File, bytes).await else {
+        return Ok(None);
+    };
+    tokio::task::spawn_blocking(move || SpooledPayload::from_bytes(&out))
+        .await
+        .context("spool join")?
+        .map(Some)
+}
+
+/// Like [`build_tar_bundle`], but the archive goes to an unlinked temp file, hashed on the
+/// way; `None` once it passes `max_bytes`. Nothing but small copy buffers is held in memory.
+pub fn spool_tar_bundle_capped(
+    paths: &[PathBuf],
+    max_bytes: usize,
+) -> anyhow::Result<Option<SpooledPayload>> {
+    // Never linked into a directory, so nothing is left behind whatever happens below.
+    let file = tempfile::tempfile().context("create tar spool")?;
+    let mut capped = CappedWriter {
+        inner: std::io::BufWriter::with_capacity(SPOOL_BUFFER_BYTES, file),
+        written: 0,
+        max: max_bytes as u64,
+        over: false,
+        hash: Fingerprinter::new(),
+    };
+    if let Err(e) = write_tar_bundle(paths, bundle_mtime(), &mut capped) {
+        // Hitting the cap surfaces as a write error from inside the builder.
+        return if capped.over { Ok(None) } else { Err(e) };
+    }
+    let file = capped
+        .inner
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .context("flush tar spool")?;
+    Ok(Some(SpooledPayload {
+        file,
+        len: capped.written,
+        sha: capped.hash.finish(),
+    }))
+}
+
+/// Write the bundle for `paths` to `out` as it is built (the archive is never held whole).
+pub fn write_tar_bundle<W: Write>(
+    paths: &[PathBuf],
+    mtime: BundleMtime,
+    out: W,
+) -> anyhow::Result<W> {
+    // Canonicalize entry order: the same selection must always produce the same bytes
+    // (sha256 drives dedup/suppression), regardless of the order the clipboard listed it.
+    let mut sorted: Vec<PathBuf> = paths.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    let paths = sorted.as_slice();
+
+    let mut builder = tar::Builder::new(out);
 
     // Heuristic: some environments represent "copy folder" as a flat list of files
     // (no directory entry in the uri-list). If we detect that all selected items are
@@ -307,8 +608,9 @@ pub fn build_tar_bundle(paths: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
         }
     }
 
-    // If we're preserving a file-tree (only-files selection), collect and append
-    // directory headers first so empty dirs can still be reconstructed when possible.
+    // If we're preserving a file-tree (only-files selection), collect and append the
+    // directories leading to the selected files first. Empty dirs are not in such a list, so
+    // only an explicitly selected folder (`append_dir_deterministic`) carries those.
     if let (Some(root), Some(root_name)) = (&tree_root, &tree_root_name) {
         let mut dirs: std::collections::BTreeSet<PathBuf> = std::collections::BTreeSet::new();
         dirs.insert(PathBuf::from(root_name));
@@ -326,7 +628,7 @@ pub fn build_tar_bundle(paths: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
         }
 
         for d in dirs {
-            let fs_dir = if d == PathBuf::from(root_name) {
+            let fs_dir = if d == *root_name {
                 root.clone()
             } else {
                 let rel = d
@@ -336,7 +638,7 @@ pub fn build_tar_bundle(paths: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
             };
             let md = std::fs::metadata(&fs_dir)
                 .with_context(|| format!("metadata {}", fs_dir.display()))?;
-            let mut h = header_for_dir_with(Some(&md));
+            let mut h = header_for_dir_with(Some(&md), mtime);
             h.set_path(&d)
                 .with_context(|| format!("set tar dir path {}", d.display()))?;
             h.set_cksum();
@@ -357,19 +659,19 @@ pub fn build_tar_bundle(paths: &[PathBuf]) -> anyhow::Result<Vec<u8>> {
         if md.is_dir() {
             // Preserve the directory as a top-level folder in the archive.
             let archive_dir = PathBuf::from(&name);
-            append_dir_deterministic(&mut builder, p, &archive_dir)
+            append_dir_deterministic(&mut builder, p, &archive_dir, mtime)
                 .with_context(|| format!("append dir {}", p.display()))?;
         } else if md.is_file() {
             if let (Some(root), Some(root_name)) = (&tree_root, &tree_root_name) {
                 if let Ok(rel) = p.strip_prefix(root) {
                     let archive_name = PathBuf::from(root_name).join(rel);
-                    append_file_deterministic(&mut builder, p, &archive_name)
+                    append_file_deterministic(&mut builder, p, &archive_name, mtime)
                         .with_context(|| format!("append file {}", p.display()))?;
                     continue;
                 }
             }
 
-            append_file_deterministic(&mut builder, p, &PathBuf::from(&name))
+            append_file_deterministic(&mut builder, p, &PathBuf::from(&name), mtime)
                 .with_context(|| format!("append file {}", p.display()))?;
         } else {
             // Skip symlinks/special files for safety.
@@ -391,6 +693,35 @@ pub fn unpack_tar_bytes(bytes: &[u8], dest: &PathBuf) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Like [`unpack_tar_bytes`], but `dest` only appears once everything is extracted
+/// (staged in a hidden sibling dir, then renamed into place).
+pub fn unpack_tar_bytes_atomic(bytes: &[u8], dest: &Path) -> anyhow::Result<()> {
+    let staging = hidden_sibling(dest, "partial")?;
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging)
+        .with_context(|| format!("create {}", staging.display()))?;
+    if let Err(e) = unpack_tar_bytes(bytes, &staging) {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    // Same bundle received again: replace the previous extraction.
+    if dest.exists() {
+        std::fs::remove_dir_all(dest).with_context(|| format!("replace {}", dest.display()))?;
+    }
+    std::fs::rename(&staging, dest).with_context(|| format!("rename to {}", dest.display()))?;
+    Ok(())
+}
+
+/// `.<name>.<suffix>` next to `dest`, for bookkeeping that must not show up inside it.
+fn hidden_sibling(dest: &Path, suffix: &str) -> anyhow::Result<PathBuf> {
+    let parent = dest.parent().context("bundle dir has no parent")?;
+    let tag: String = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().chars().take(64).collect())
+        .unwrap_or_default();
+    Ok(parent.join(format!(".{}.{}", tag, suffix)))
+}
+
 pub fn build_uri_list(paths: &[PathBuf]) -> String {
     let mut out = String::new();
     for p in paths {
@@ -419,10 +750,346 @@ pub fn list_top_level_items(dir: &PathBuf, max_items: usize) -> Vec<PathBuf> {
         Err(_) => Vec::new(),
     };
     items.sort();
-    if items.len() > max_items {
+    truncate_listing(dir, &mut items, max_items);
+    items
+}
+
+/// Default `wl-apply --max-listed-items`.
+pub const MAX_LISTED_ITEMS: usize = 5000;
+
+/// Cut a directory listing to `max_items`, warning that the rest is left out.
+fn truncate_listing(dir: &Path, items: &mut Vec<PathBuf>, max_items: usize) -> usize {
+    let omitted = items.len().saturating_sub(max_items);
+    if omitted > 0 {
+        log::warn!(
+            "{}: listing only {} of {} items ({} omitted; raise --max-listed-items)",
+            dir.display(),
+            max_items,
+            items.len(),
+            omitted
+        );
         items.truncate(max_items);
     }
-    items
+    omitted
+}
+
+/// How an extracted bundle is exposed on the receiving clipboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BundleExpose {
+    /// Always one root folder (top-level entries are moved into a wrapper when needed).
+    Wrapper,
+    /// Always the top-level entries as they are.
+    Flat,
+    /// One folder for named bundles, separate items for generic multi-selections (default).
+    Auto,
+}
+
+pub fn parse_bundle_expose(s: &str) -> anyhow::Result<BundleExpose> {
+    match s {
+        "wrapper" => Ok(BundleExpose::Wrapper),
+        "flat" => Ok(BundleExpose::Flat),
+        "auto" => Ok(BundleExpose::Auto),
+        other => anyhow::bail!("invalid --bundle-expose {}, expected wrapper|flat|auto", other),
+    }
+}
+
+fn sanitize_component(s: &str) -> String {
+    let mut out: String = s
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            _ => c,
+        })
+        .collect();
+    if out.is_empty() {
+        out = "multicliprelay".to_string();
+    }
+    if out == "." || out == ".." {
+        out = format!("_{}", out);
+    }
+    out
+}
+
+async fn move_entry_best_effort(src: &Path, dst: &Path) {
+    if tokio::fs::rename(src, dst).await.is_ok() {
+        return;
+    }
+
+    let md = tokio::fs::metadata(src).await;
+    let Ok(md) = md else {
+        return;
+    };
+
+    if md.is_file() {
+        if tokio::fs::copy(src, dst).await.is_ok() {
+            let _ = tokio::fs::remove_file(src).await;
+        }
+        return;
+    }
+
+    if md.is_dir() {
+        let src2 = src.to_path_buf();
+        let dst2 = dst.to_path_buf();
+        let _ = tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&dst2).ok();
+            for e in WalkDir::new(&src2)
+                .follow_links(false)
+                .into_iter()
+                .filter_map(|e| e.ok())
+            {
+                let p = e.path();
+                let Ok(rel) = p.strip_prefix(&src2) else {
+                    continue;
+                };
+                let target = dst2.join(rel);
+                if e.file_type().is_dir() {
+                    std::fs::create_dir_all(&target).ok();
+                } else if e.file_type().is_file() {
+                    if let Some(parent) = target.parent() {
+                        std::fs::create_dir_all(parent).ok();
+                    }
+                    std::fs::copy(p, &target).ok();
+                }
+            }
+            std::fs::remove_dir_all(&src2).ok();
+        })
+        .await;
+    }
+}
+
+/// Decide which extracted paths go on the clipboard for bundle `name` unpacked into `out_dir`.
+///
+/// Returns the root paths plus the name offered as text/plain. Wrapping moves the top-level
+/// entries into `out_dir/<bundle stem>`. Unwrapped, at most `max_items` entries are returned;
+/// the text/plain then says how many were left out and where they are.
+pub async fn expose_bundle_roots(
+    out_dir: &Path,
+    name: &str,
+    expose: BundleExpose,
+    max_items: usize,
+) -> (Vec<PathBuf>, String) {
+    // Uncapped: wrapping must move every entry, not just the listed ones.
+    let mut entries = list_top_level_items(&out_dir.to_path_buf(), usize::MAX);
+
+    // Prefer the raw tar stem (preserves unicode) rather than `safe_for_filename`.
+    let stem_raw = name
+        .trim_end_matches(".tar")
+        .trim_end_matches(".TAR")
+        .to_string();
+    let mut wrapper_name = sanitize_component(&stem_raw);
+
+    // Generic names come from multi-selection without a clear folder intent.
+    // In that case we should preserve multi-item paste semantics (no wrapper).
+    let is_generic_bundle_name = stem_raw.starts_with("multicliprelay-bundle-");
+
+    let file_name = |p: &Path, fallback: &str| {
+        p.file_name()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| fallback.to_string())
+    };
+
+    let wrap = match expose {
+        _ if entries.is_empty() => return (vec![out_dir.to_path_buf()], wrapper_name),
+        // A lone folder already is a single root.
+        BundleExpose::Wrapper => !(entries.len() == 1 && entries[0].is_dir()),
+        BundleExpose::Flat => false,
+        BundleExpose::Auto => entries.len() > 1 && !is_generic_bundle_name,
+    };
+    if !wrap {
+        let mut n = file_name(&entries[0], &wrapper_name);
+        let omitted = truncate_listing(out_dir, &mut entries, max_items.max(1));
+        if omitted > 0 {
+            n = format!("{} (+{} more not listed, see {})", n, omitted, out_dir.display());
+        }
+        return (entries, n);
+    }
+
+    // Synthesize a wrapper folder and move top-level entries into it.
+    if entries.iter().any(|e| e.file_name() == Some(wrapper_name.as_ref()) && !e.is_dir()) {
+        // A file already named like the bundle stem can't become the wrapper folder.
+        wrapper_name = format!("{}_files", wrapper_name);
+    }
+    let wrapper = out_dir.join(&wrapper_name);
+    tokio::fs::create_dir_all(&wrapper).await.ok();
+
+    for src in entries.drain(..) {
+        if src == wrapper {
+            continue;
+        }
+        let Some(base) = src.file_name().map(|s| s.to_os_string()) else {
+            continue;
+        };
+        let mut dst = wrapper.join(&base);
+        if tokio::fs::metadata(&dst).await.is_ok() {
+            // Best-effort collision avoidance.
+            let b = base.to_string_lossy();
+            let ts = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            dst = wrapper.join(format!("{}_{}", b, ts));
+        }
+        move_entry_best_effort(&src, &dst).await;
+    }
+
+    (vec![wrapper], wrapper_name)
+}
+
+/// A received bundle, extracted and ready for the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedBundle {
+    pub roots: Vec<PathBuf>,
+    /// Offered as text/plain.
+    pub plain: String,
+    /// An earlier receive of the same content was still on disk; nothing was extracted.
+    pub reused: bool,
+}
+
+/// What a finished extraction leaves next to its directory (`.<dir>.bundle`).
+#[derive(Serialize, Deserialize)]
+struct BundleRecord {
+    /// Content hash plus the options that shaped `roots`.
+    key: String,
+    roots: Vec<PathBuf>,
+    plain: String,
+    /// What the archive unpacked to (see [`extracted_entries`]).
+    entries: Vec<ExtractedEntry>,
+}
+
+/// One path under an extraction: relative path, size and mtime (ms) for files, `None` for
+/// directories and links.
+type ExtractedEntry = (String, Option<(u64, u64)>);
+
+/// Everything under `out_dir`, sorted, so a later look can tell whether anything in it was
+/// added, removed or changed since.
+fn extracted_entries(out_dir: &Path) -> Vec<ExtractedEntry> {
+    let mut entries: Vec<ExtractedEntry> = WalkDir::new(out_dir)
+        .min_depth(1)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let rel = e.path().strip_prefix(out_dir).unwrap_or(e.path());
+            let file = e
+                .metadata()
+                .ok()
+                .filter(|m| m.is_file())
+                .map(|m| (m.len(), m.modified().map(mtime_ms).unwrap_or(0)));
+            (rel.to_string_lossy().into_owned(), file)
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn mtime_ms(t: std::time::SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Extract bundle `name` (content hash `sha`) into `out_dir` and pick its clipboard roots
+/// (see [`expose_bundle_roots`]).
+///
+/// Identical content always maps to the same `out_dir`, so a repeat receive finds its
+/// earlier extraction there. It is reused as it is, instead of unpacking and wrapping
+/// everything again, only while the directory still holds exactly what the archive unpacked
+/// to: the same names, sizes and mtimes, nothing added or removed.
+pub async fn extract_bundle(
+    payload: &[u8],
+    sha: &str,
+    out_dir: &Path,
+    name: &str,
+    expose: BundleExpose,
+    max_items: usize,
+) -> anyhow::Result<ExtractedBundle> {
+    let record_path = hidden_sibling(out_dir, "bundle")?;
+    let key = format!("{} {:?} {}", sha, expose, max_items);
+    let record = read_bundle_record(&record_path).await;
+    if let Some(r) = record.filter(|r| r.key == key) {
+        let dir = out_dir.to_path_buf();
+        let on_disk = tokio::task::spawn_blocking(move || extracted_entries(&dir))
+            .await
+            .context("scan extraction")?;
+        if !r.roots.is_empty() && r.entries == on_disk {
+            return Ok(ExtractedBundle {
+                roots: r.roots,
+                plain: r.plain,
+                reused: true,
+            });
+        }
+        log::debug!("{} changed since it was extracted", out_dir.display());
+    }
+    // Whatever happens next invalidates the old record.
+    let _ = tokio::fs::remove_file(&record_path).await;
+
+    let (bytes, dest) = (payload.to_vec(), out_dir.to_path_buf());
+    tokio::task::spawn_blocking(move || unpack_tar_bytes_atomic(&bytes, &dest))
+        .await
+        .context("extract task")??;
+    let (roots, plain) = expose_bundle_roots(out_dir, name, expose, max_items).await;
+    let dir = out_dir.to_path_buf();
+    let entries = tokio::task::spawn_blocking(move || extracted_entries(&dir))
+        .await
+        .context("scan extraction")?;
+
+    let record = BundleRecord {
+        key,
+        roots: roots.clone(),
+        plain: plain.clone(),
+        entries,
+    };
+    // Best effort: without a record the next receive just extracts again.
+    if let Ok(json) = serde_json::to_vec(&record) {
+        if let Err(e) = tokio::fs::write(&record_path, json).await {
+            log::debug!("bundle record {} not written: {e}", record_path.display());
+        }
+    }
+    Ok(ExtractedBundle {
+        roots,
+        plain,
+        reused: false,
+    })
+}
+
+async fn read_bundle_record(path: &Path) -> Option<BundleRecord> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Clipboard items wl-apply offers for an extracted bundle.
+///
+/// Paths always go out as uri-list; text/plain only carries the root name, since large or
+/// ambiguous text/plain makes some file managers (esp. KDE/Dolphin) paste oddly.
+pub fn bundle_clipboard_items(
+    bundle: &ExtractedBundle,
+    sha: &str,
+    name: &str,
+    msg: &Message,
+) -> Vec<(String, Vec<u8>)> {
+    let uri_list = build_uri_list(&bundle.roots);
+    let gnome_list = format!("copy\n{}", uri_list);
+    vec![
+        (
+            "text/plain;charset=utf-8".to_string(),
+            bundle.plain.as_bytes().to_vec(),
+        ),
+        ("text/plain".to_string(), bundle.plain.as_bytes().to_vec()),
+        (URI_LIST_MIME.to_string(), uri_list.into_bytes()),
+        (GNOME_COPIED_FILES_MIME.to_string(), gnome_list.into_bytes()),
+        (
+            APPLIED_MARKER_MIME.to_string(),
+            format!(
+                "applied\nkind=tar\nsha={}\nname={}\nroot_hint={}\n{}",
+                sha,
+                name,
+                bundle.plain,
+                orig_path_marker_lines(msg)
+            )
+            .into_bytes(),
+        ),
+    ]
 }
 
 pub fn list_files_recursively(dir: &PathBuf, max_items: usize) -> Vec<PathBuf> {
@@ -434,60 +1101,167 @@ pub fn list_files_recursively(dir: &PathBuf, max_items: usize) -> Vec<PathBuf> {
         .map(|e| e.path().to_path_buf())
         .collect();
     files.sort();
-    if files.len() > max_items {
-        files.truncate(max_items);
-    }
+    truncate_listing(dir, &mut files, max_items);
     files
 }
 
+/// `send-file`: `file` as a tar bundle, or with `mime` set (`--mime`), as-is under that type.
+///
+/// With `wait_ack` (`--wait-ack`), returns only once a peer acknowledged applying it. The
+/// outcome is logged in `history`.
+#[allow(clippy::too_many_arguments)]
 pub async fn send_file(
+    history: &Path,
     local_device_id: &str,
     local_device_name: &str,
     room: &str,
-    file: &PathBuf,
+    file: &Path,
     relay: &str,
     max_file_bytes: usize,
+    mime: Option<&str>,
+    wait_ack: Option<std::time::Duration>,
 ) -> anyhow::Result<()> {
-    // Send as a tar bundle to preserve metadata (mtime/mode).
-    let file2 = file.clone();
-    let tar_bytes = tokio::task::spawn_blocking(move || build_tar_bundle(&vec![file2]))
-        .await
-        .context("tar build join")??;
-    if tar_bytes.len() > max_file_bytes {
-        anyhow::bail!("file too large: {} bytes > {}", tar_bytes.len(), max_file_bytes);
-    }
-
-    let name = bundle_name_for(&vec![file.clone()]);
-    let sha = sha256_hex(&tar_bytes);
-
-    let stream = connect(relay).await?;
-    let mut msg = Message::new_file(local_device_id, room, &name, TAR_MIME, tar_bytes);
-    let local_name_opt = if local_device_name.trim().is_empty() {
-        None
-    } else {
-        Some(local_device_name.to_string())
+    let (name, send_mime, payload) = match mime {
+        Some(mime) => {
+            anyhow::ensure!(
+                mime.contains('/'),
+                "invalid --mime {mime}, expected type/subtype"
+            );
+            let meta = tokio::fs::metadata(file)
+                .await
+                .with_context(|| format!("stat {}", file.display()))?;
+            anyhow::ensure!(meta.is_file(), "not a regular file: {}", file.display());
+            if meta.len() > max_file_bytes as u64 {
+                let reason = format!(
+                    "too large: {} bytes > max_file_bytes={}",
+                    meta.len(),
+                    max_file_bytes
+                );
+                record_event(
+                    history,
+                    send_outcome_event(
+                        local_device_id,
+                        sender_name(local_device_name),
+                        room,
+                        relay,
+                        Outcome::Skipped,
+                        &reason,
+                        Kind::File,
+                        Some(mime.to_string()),
+                        meta.len() as usize,
+                        None,
+                    ),
+                )
+                .await;
+                anyhow::bail!("file too large: {} bytes > {}", meta.len(), max_file_bytes);
+            }
+            let file2 = file.to_path_buf();
+            let payload = tokio::task::spawn_blocking(move || SpooledPayload::open(&file2))
+                .await
+                .context("file read join")??;
+            let name = file
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "file".to_string());
+            (name, mime.to_string(), payload)
+        }
+        None => {
+            // Send as a tar bundle to preserve metadata (mtime/mode).
+            let file2 = file.to_path_buf();
+            let spooled = tokio::task::spawn_blocking(move || {
+                spool_tar_bundle_capped(&[file2], max_file_bytes)
+            })
+            .await
+            .context("tar build join")??;
+            let Some(spooled) = spooled else {
+                let reason = format!(
+                    "too large: bundle exceeds max_file_bytes={}",
+                    max_file_bytes
+                );
+                record_event(
+                    history,
+                    send_outcome_event(
+                        local_device_id,
+                        sender_name(local_device_name),
+                        room,
+                        relay,
+                        Outcome::Skipped,
+                        &reason,
+                        Kind::File,
+                        Some(TAR_MIME.to_string()),
+                        0,
+                        None,
+                    ),
+                )
+                .await;
+                anyhow::bail!("file too large: bundle exceeds {} bytes", max_file_bytes);
+            };
+            let name = bundle_name_for(&[file.to_path_buf()]);
+            (name, TAR_MIME.to_string(), spooled)
+        }
+    };
+    let Some(payload) = filter_outgoing_spooled(payload).await? else {
+        anyhow::bail!("file dropped by --send-filter");
     };
+    let sha = payload.sha.clone();
+
+    let mut msg = payload.file_message(local_device_id, room, &name, &send_mime);
+    let local_name_opt = sender_name(local_device_name);
     msg.sender_name = local_name_opt.clone();
     msg.sha256 = Some(sha.clone());
-    send_frame(stream, msg.to_bytes()).await?;
+    msg.orig_paths = orig_paths_for(&[file.to_path_buf()]);
+    msg.want_ack = wait_ack.is_some();
+    let sent = async {
+        let mut stream = connect(relay).await?;
+        if wait_ack.is_some() {
+            join_for_ack(&mut stream, local_device_id, local_device_name, room).await?;
+        }
+        payload.write_frame(&mut stream, &msg).await?;
+        anyhow::Ok(stream)
+    }
+    .await;
+    let mut stream = match sent {
+        Ok(stream) => stream,
+        Err(e) => {
+            let reason = format!("send failed: {e:#}");
+            record_event(
+                history,
+                send_outcome_event(
+                    local_device_id,
+                    local_name_opt,
+                    room,
+                    relay,
+                    Outcome::Failed,
+                    &reason,
+                    Kind::File,
+                    Some(send_mime),
+                    msg.size,
+                    Some(sha),
+                ),
+            )
+            .await;
+            return Err(e);
+        }
+    };
 
     log::debug!(
         "send-file: room={} relay={} name={} mime={} bytes={} sha={}",
         room,
         relay,
         name,
-        TAR_MIME,
+        send_mime,
         msg.size,
         sha
     );
 
     record_send(
+        history,
         local_device_id,
         local_name_opt,
         room,
         relay,
         Kind::File,
-        Some(TAR_MIME.to_string()),
+        Some(send_mime),
         Some(name.clone()),
         msg.size,
         Some(sha),
@@ -495,57 +1269,139 @@ pub async fn send_file(
     .await;
 
     println!("sent file '{}' to room {}", name, room);
+    if let Some(wait) = wait_ack {
+        let by = wait_for_ack(&mut stream, &msg.event_id, wait).await?;
+        println!("applied by {}", by);
+    }
     Ok(())
 }
 
-pub async fn send_paths_as_file(
-    state_dir: &PathBuf,
-    local_device_id: &str,
-    local_device_name: &str,
+/// A file selection bundled for sending (see [`prepare_paths_bundle`]).
+#[derive(Debug)]
+pub struct PathsBundle {
+    /// Sha of the tar as built from disk; callers dedupe on it.
+    pub raw_sha: String,
+    pub name: String,
+    /// What goes on the wire (after `--send-filter`).
+    pub payload: SpooledPayload,
+    /// Where the selection lives on this device (see [`orig_paths_for`]).
+    pub orig_paths: Option<Vec<String>>,
+}
+
+#[derive(Debug)]
+pub enum PreparedPaths {
+    /// Paused, empty, too large, suppressed or recently applied.
+    Nothing,
+    /// `--send-filter` dropped the bundle (raw sha).
+    Filtered(String),
+    Ready(PathsBundle),
+}
+
+/// Bundle `paths` into a tar and run the send-side checks, without touching the network.
+pub async fn prepare_paths_bundle(
+    state_dir: &Path,
     room: &str,
-    relay: &str,
     paths: Vec<PathBuf>,
     max_file_bytes: usize,
-) -> anyhow::Result<Option<String>> {
+) -> anyhow::Result<PreparedPaths> {
     if paths.is_empty() {
-        return Ok(None);
+        return Ok(PreparedPaths::Nothing);
     }
 
-    // Fast path: if file clipboard is globally suppressed (wildcard '*'), avoid
-    // doing any expensive IO / tar building.
-    if is_file_suppressed(state_dir, room, "0").await {
-        return Ok(None);
+    // Fast path: if sync is paused, avoid doing any expensive IO / tar building.
+    if is_paused(state_dir, room).await {
+        return Ok(PreparedPaths::Nothing);
     }
 
     // Bundle into a tar (also for a single file) so we can preserve metadata.
     // Build tar in a blocking task (std::fs + tar builder).
     let paths2 = paths.clone();
-    let tar_bytes = tokio::task::spawn_blocking(move || build_tar_bundle(&paths2))
-        .await
-        .context("tar build join")??;
-    if tar_bytes.is_empty() || tar_bytes.len() > max_file_bytes {
-        return Ok(None);
-    }
+    let spooled =
+        tokio::task::spawn_blocking(move || spool_tar_bundle_capped(&paths2, max_file_bytes))
+            .await
+            .context("tar build join")??;
+    let Some(spooled) = spooled.filter(|s| s.len > 0) else {
+        return Ok(PreparedPaths::Nothing);
+    };
 
-    let sha = sha256_hex(&tar_bytes);
-    if is_file_suppressed(state_dir, room, &sha).await {
-        return Ok(None);
+    let raw_sha = spooled.sha.clone();
+    if is_file_suppressed(state_dir, room, &raw_sha).await
+        || is_recently_applied(state_dir, room, &raw_sha).await
+    {
+        return Ok(PreparedPaths::Nothing);
     }
+    let Some(payload) = filter_outgoing_spooled(spooled).await? else {
+        return Ok(PreparedPaths::Filtered(raw_sha));
+    };
+    Ok(PreparedPaths::Ready(PathsBundle {
+        raw_sha,
+        name: bundle_name_for(&paths),
+        payload,
+        orig_paths: orig_paths_for(&paths),
+    }))
+}
 
-    let name = bundle_name_for(&paths);
-
-    let stream = connect(relay).await?;
-    let mut msg = Message::new_file(local_device_id, room, &name, TAR_MIME, tar_bytes);
-    let local_name_opt = if local_device_name.trim().is_empty() {
-        None
-    } else {
-        Some(local_device_name.to_string())
+/// Paths inside a tar bundle, in archive order (unreadable entries are skipped).
+pub fn tar_entry_names<R: Read>(tar: R) -> Vec<String> {
+    let mut ar = tar::Archive::new(tar);
+    let Ok(entries) = ar.entries() else {
+        return Vec::new();
     };
+    entries
+        .filter_map(|e| e.ok()?.path().ok().map(|p| p.display().to_string()))
+        .collect()
+}
+
+pub async fn send_paths_bundle(
+    state_dir: &Path,
+    history: &Path,
+    local_device_id: &str,
+    local_device_name: &str,
+    room: &str,
+    relay: &str,
+    bundle: PathsBundle,
+) -> anyhow::Result<()> {
+    let mut stream = connect_with_backoff(state_dir, relay).await?;
+    write_paths_bundle(
+        &mut stream,
+        history,
+        local_device_id,
+        local_device_name,
+        room,
+        relay,
+        bundle,
+    )
+    .await
+}
+
+/// Write `bundle` as one file frame to an open relay connection, logging it in `history`
+/// (`relay` is for the log).
+pub async fn write_paths_bundle<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    history: &Path,
+    local_device_id: &str,
+    local_device_name: &str,
+    room: &str,
+    relay: &str,
+    bundle: PathsBundle,
+) -> anyhow::Result<()> {
+    let PathsBundle {
+        name,
+        payload,
+        orig_paths,
+        ..
+    } = bundle;
+    let sha = payload.sha.clone();
+    let mut msg = payload.file_message(local_device_id, room, &name, TAR_MIME);
+    let local_name_opt = sender_name(local_device_name);
     msg.sender_name = local_name_opt.clone();
     msg.sha256 = Some(sha.clone());
-    send_frame(stream, msg.to_bytes()).await?;
+    msg.orig_paths = orig_paths;
+    payload.write_frame(w, &msg).await?;
+    emit_event("send", &msg);
 
     record_send(
+        history,
         local_device_id,
         local_name_opt,
         room,
@@ -554,17 +1410,285 @@ pub async fn send_paths_as_file(
         Some(TAR_MIME.to_string()),
         Some(name),
         msg.size,
-        Some(sha.clone()),
+        Some(sha),
     )
     .await;
+    Ok(())
+}
 
-    Ok(Some(sha))
+/// Bundle `paths` and write them to `w`, the caller's already-joined relay connection.
+///
+/// A selection `cooldown` saw recently is not bundled again (`None`); what goes out, or is
+/// dropped by `--send-filter`, is recorded there (and what goes out, in `history` too).
+#[allow(clippy::too_many_arguments)]
+pub async fn send_paths_as_file<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    state_dir: &Path,
+    history: &Path,
+    local_device_id: &str,
+    local_device_name: &str,
+    room: &str,
+    relay: &str,
+    paths: Vec<PathBuf>,
+    max_file_bytes: usize,
+    cooldown: &mut FileCooldown,
+) -> anyhow::Result<Option<String>> {
+    let key = FileCooldown::key(&paths).await;
+    if !cooldown.allows(&key, Instant::now()) {
+        log::debug!("file selection {} sent recently; not re-sending", key);
+        return Ok(None);
+    }
+    // The returned sha stays the raw bundle's (callers dedupe on it); peers get the filtered one.
+    let bundle = match prepare_paths_bundle(state_dir, room, paths, max_file_bytes).await? {
+        PreparedPaths::Nothing => return Ok(None),
+        PreparedPaths::Filtered(raw_sha) => {
+            cooldown.record(key, Instant::now());
+            return Ok(Some(raw_sha));
+        }
+        PreparedPaths::Ready(bundle) => bundle,
+    };
+    let raw_sha = bundle.raw_sha.clone();
+    write_paths_bundle(
+        w,
+        history,
+        local_device_id,
+        local_device_name,
+        room,
+        relay,
+        bundle,
+    )
+    .await?;
+    cooldown.record(key, Instant::now());
+    Ok(Some(raw_sha))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn paused_room_blocks_sends_until_resumed() {
+        use crate::suppress::set_paused;
+
+        let state = tempfile::tempdir().unwrap();
+        let src = tempfile::tempdir().unwrap();
+        let f = src.path().join("a.txt");
+        std::fs::write(&f, b"secret").unwrap();
+        let (st, paths) = (state.path(), || vec![f.clone()]);
+        let history = &st.join("history.jsonl");
+        let mut out = Vec::new();
+        let cd = &mut FileCooldown::new(std::time::Duration::ZERO);
+
+        set_paused(st, "r", true).await.unwrap();
+        let sent = send_paths_as_file(
+            &mut out,
+            st,
+            history,
+            "dev",
+            "",
+            "r",
+            "relay",
+            paths(),
+            1 << 20,
+            cd,
+        );
+        assert!(matches!(sent.await, Ok(None)));
+        assert!(out.is_empty());
+        // Other rooms are unaffected.
+        assert!(!is_paused(st, "other").await);
+
+        set_paused(st, "r", false).await.unwrap();
+        let sent = send_paths_as_file(
+            &mut out,
+            st,
+            history,
+            "dev",
+            "",
+            "r",
+            "relay",
+            paths(),
+            1 << 20,
+            cd,
+        );
+        assert!(sent.await.unwrap().is_some());
+        assert!(!out.is_empty(), "resumed room should write the bundle");
+        // Resuming twice is fine.
+        set_paused(state.path(), "r", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn same_file_waits_out_the_resend_cooldown() {
+        /// One poll tick with `f` still on the clipboard; whether a frame went out.
+        async fn tick(out: &mut Vec<u8>, st: &Path, f: &Path, cd: &mut FileCooldown) -> bool {
+            let before = out.len();
+            let paths = vec![f.to_path_buf()];
+            let history = &st.join("history.jsonl");
+            let sent = send_paths_as_file(
+                out,
+                st,
+                history,
+                "dev",
+                "",
+                "r",
+                "relay",
+                paths,
+                1 << 20,
+                cd,
+            );
+            let sent = sent.await.unwrap();
+            assert_eq!(sent.is_some(), out.len() > before);
+            sent.is_some()
+        }
+
+        let state = tempfile::tempdir().unwrap();
+        let src = tempfile::tempdir().unwrap();
+        let (a, b) = (src.path().join("a.txt"), src.path().join("b.txt"));
+        std::fs::write(&a, b"one").unwrap();
+        std::fs::write(&b, b"two").unwrap();
+        let (st, mut out) = (state.path(), Vec::new());
+        let cooldown = std::time::Duration::from_millis(300);
+        let cd = &mut FileCooldown::new(cooldown);
+
+        assert!(tick(&mut out, st, &a, cd).await);
+        for _ in 0..3 {
+            // Still on the clipboard: not re-sent within the cooldown.
+            assert!(!tick(&mut out, st, &a, cd).await);
+        }
+        tokio::time::sleep(cooldown).await;
+        assert!(tick(&mut out, st, &a, cd).await);
+        // Other content is never held back, nor is the same file rewritten in place.
+        assert!(tick(&mut out, st, &b, cd).await);
+        assert!(tick(&mut out, st, &a, cd).await);
+        assert!(!tick(&mut out, st, &a, cd).await);
+        std::fs::write(&a, b"one, edited").unwrap();
+        assert!(tick(&mut out, st, &a, cd).await);
+    }
+
+    #[tokio::test]
+    async fn mime_override_sends_the_file_as_is() {
+        use crate::net::read_msg;
+
+        let src = tempfile::tempdir().unwrap();
+        let f = src.path().join("app.conf");
+        std::fs::write(&f, b"key = value\n").unwrap();
+        let relay = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = relay.local_addr().unwrap().to_string();
+        let next_msg = || async {
+            let (mut conn, _) = relay.accept().await.unwrap();
+            read_msg(&mut conn).await
+        };
+
+        let history = src.path().join("history.jsonl");
+        let send = |max, mime| send_file(&history, "dev", "", "r", &f, &addr, max, mime, None);
+
+        let mime = Some("text/plain;charset=utf-8");
+        let (sent, msg) = tokio::join!(send(1024, mime), next_msg());
+        sent.unwrap();
+        assert_eq!(msg.mime.as_deref(), mime);
+        assert_eq!(msg.name.as_deref(), Some("app.conf"));
+        assert_eq!(msg.payload.as_deref(), Some(&b"key = value\n"[..]));
+
+        // Without it the file still goes out as a bundle.
+        let (sent, msg) = tokio::join!(send(1 << 20, None), next_msg());
+        sent.unwrap();
+        assert_eq!(msg.mime.as_deref(), Some(TAR_MIME));
+
+        assert!(send(4, mime).await.is_err(), "over the cap");
+        assert!(send(1024, Some("conf")).await.is_err(), "not a MIME type");
+    }
+
+    #[tokio::test]
+    async fn bundle_expose_modes_on_multi_entry_bundle() {
+        let extracted = || {
+            let tmp = tempfile::tempdir().unwrap();
+            std::fs::write(tmp.path().join("a.txt"), b"a").unwrap();
+            std::fs::create_dir_all(tmp.path().join("docs")).unwrap();
+            std::fs::write(tmp.path().join("docs/b.txt"), b"b").unwrap();
+            tmp
+        };
+        let names = |paths: &[PathBuf]| -> Vec<String> {
+            paths
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        for (mode, bundle, want_wrapped) in [
+            (BundleExpose::Auto, "project.tar", true),
+            (BundleExpose::Auto, "multicliprelay-bundle-1.tar", false),
+            (BundleExpose::Wrapper, "multicliprelay-bundle-1.tar", true),
+            (BundleExpose::Flat, "project.tar", false),
+        ] {
+            let tmp = extracted();
+            let (roots, plain) = expose_bundle_roots(tmp.path(), bundle, mode, MAX_LISTED_ITEMS).await;
+            let stem = bundle.trim_end_matches(".tar");
+            if want_wrapped {
+                assert_eq!(roots, vec![tmp.path().join(stem)], "{mode:?} {bundle}");
+                assert_eq!(plain, stem);
+                assert!(roots[0].join("a.txt").is_file());
+                assert!(roots[0].join("docs/b.txt").is_file());
+            } else {
+                assert_eq!(names(&roots), ["a.txt", "docs"], "{mode:?} {bundle}");
+                assert_eq!(plain, "a.txt");
+            }
+        }
+
+        // A lone folder is already one root; wrapper mode still wraps a lone file.
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tmp.path().join("docs")).unwrap();
+        let (roots, _) = expose_bundle_roots(tmp.path(), "docs.tar", BundleExpose::Wrapper, MAX_LISTED_ITEMS).await;
+        assert_eq!(roots, vec![tmp.path().join("docs")]);
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("a.txt"), b"a").unwrap();
+        let (roots, _) = expose_bundle_roots(tmp.path(), "a.txt.tar", BundleExpose::Wrapper, MAX_LISTED_ITEMS).await;
+        assert_eq!(roots, vec![tmp.path().join("a.txt_files")]);
+        assert!(roots[0].join("a.txt").is_file());
+
+        assert!(parse_bundle_expose("nested").is_err());
+    }
+
+    #[tokio::test]
+    async fn listing_cap_truncates_and_reports_what_was_left_out() {
+        // The omitted count is what the warning reports; within the cap nothing is cut.
+        let mut items: Vec<PathBuf> = (0..5).map(|i| PathBuf::from(format!("f{i}"))).collect();
+        assert_eq!(truncate_listing(Path::new("d"), &mut items, 3), 2);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[2], PathBuf::from("f2"));
+        assert_eq!(truncate_listing(Path::new("d"), &mut items, 3), 0);
+        assert_eq!(items.len(), 3);
+
+        let extracted = || {
+            let tmp = tempfile::tempdir().unwrap();
+            for i in 0..5 {
+                std::fs::write(tmp.path().join(format!("f{i}.txt")), b"x").unwrap();
+            }
+            tmp
+        };
+
+        // Flat exposure lists only the first 3 and says so in the text offer.
+        let tmp = extracted();
+        let (roots, plain) =
+            expose_bundle_roots(tmp.path(), "multicliprelay-bundle-1.tar", BundleExpose::Flat, 3)
+                .await;
+        assert_eq!(roots.len(), 3);
+        assert_eq!(roots[0], tmp.path().join("f0.txt"));
+        assert!(plain.starts_with("f0.txt (+2 more not listed"), "{plain}");
+
+        // Within the cap nothing is cut or reported.
+        let tmp = extracted();
+        let (roots, plain) = expose_bundle_roots(tmp.path(), "x.tar", BundleExpose::Flat, 5).await;
+        assert_eq!(roots.len(), 5);
+        assert_eq!(plain, "f0.txt");
+
+        // The wrapper holds every entry regardless of the cap.
+        let tmp = extracted();
+        let (roots, _) = expose_bundle_roots(tmp.path(), "x.tar", BundleExpose::Wrapper, 3).await;
+        assert_eq!(roots, vec![tmp.path().join("x")]);
+        assert_eq!(std::fs::read_dir(&roots[0]).unwrap().count(), 5);
+
+        assert_eq!(list_files_recursively(&tmp.path().to_path_buf(), 2).len(), 2);
+    }
+
     #[test]
     fn parse_uri_list_ignores_comments_and_gnome_prefix() {
         let s = b"# comment\ncopy\nfile:///tmp/a.txt\n\nfile:///tmp/b.txt\n";
@@ -587,7 +1711,7 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         let p = dir.path().join("d");
         std::fs::create_dir_all(&p).unwrap();
-        let s = build_uri_list(&vec![p.clone()]);
+        let s = build_uri_list(std::slice::from_ref(&p));
         let u = Url::from_file_path(&p).unwrap();
         assert_eq!(s, format!("{}\n", u.as_str()));
     }
@@ -620,7 +1744,7 @@ mod tests {
             assert_eq!(rc, 0);
         }
 
-        let tar = build_tar_bundle(&vec![p.clone()]).unwrap();
+        let tar = build_tar_bundle(std::slice::from_ref(&p)).unwrap();
         let out = tempfile::tempdir().unwrap();
         unpack_tar_bytes(&tar, &out.path().to_path_buf()).unwrap();
 
@@ -632,7 +1756,7 @@ mod tests {
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs() as i64;
-        assert_eq!(mtime, target_secs as i64);
+        assert_eq!(mtime, target_secs);
     }
 
     #[test]
@@ -645,7 +1769,7 @@ mod tests {
         std::fs::write(&a, b"hello").unwrap();
         std::fs::write(&b, b"world").unwrap();
 
-        let tar = build_tar_bundle(&vec![a.clone(), sub.clone()]).unwrap();
+        let tar = build_tar_bundle(&[a.clone(), sub.clone()]).unwrap();
         assert!(!tar.is_empty());
 
         let out = tempfile::tempdir().unwrap();
@@ -654,6 +1778,100 @@ mod tests {
         // a.txt should exist; sub/b.txt should exist (directory preserved).
         assert!(out.path().join("a.txt").exists());
         assert!(out.path().join("sub").join("b.txt").exists());
+
+        // Atomic variant: the target dir only exists complete, also when received again.
+        let dest = out.path().join("bundle");
+        for _ in 0..2 {
+            unpack_tar_bytes_atomic(&tar, &dest).unwrap();
+            assert!(dest.join("a.txt").exists());
+            assert!(dest.join("sub").join("b.txt").exists());
+        }
+        let leftovers: Vec<_> = std::fs::read_dir(out.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".partial"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn repeated_bundle_reuses_its_extraction() {
+        let src = tempfile::tempdir().unwrap();
+        std::fs::write(src.path().join("a.txt"), b"a").unwrap();
+        std::fs::create_dir_all(src.path().join("docs")).unwrap();
+        std::fs::write(src.path().join("docs/b.txt"), b"b").unwrap();
+        let tar = build_tar_bundle(&[src.path().join("a.txt"), src.path().join("docs")]).unwrap();
+        let sha = fingerprint(&tar);
+        let msg = Message::new_file("peer", "r", "project.tar", TAR_MIME, tar.clone());
+
+        let received = tempfile::tempdir().unwrap();
+        let out_dir = received.path().join(format!("{}_project", &sha[..8]));
+        let apply = || extract_bundle(&tar, &sha, &out_dir, "project.tar", BundleExpose::Auto, 10);
+
+        let first = apply().await.unwrap();
+        assert!(!first.reused);
+        assert_eq!(first.roots, vec![out_dir.join("project")]);
+
+        let second = apply().await.unwrap();
+        assert!(second.reused);
+        assert_eq!((&second.roots, &second.plain), (&first.roots, &first.plain));
+        // The clipboard still gets the bundle, exactly as the first time.
+        let items = bundle_clipboard_items(&second, &sha, "project.tar", &msg);
+        let first_items = bundle_clipboard_items(&first, &sha, "project.tar", &msg);
+        assert_eq!(items, first_items);
+        let uris = &items.iter().find(|(m, _)| m == URI_LIST_MIME).unwrap().1;
+        assert_eq!(uris, build_uri_list(&first.roots).as_bytes());
+
+        // Anything added, removed or edited in the old tree: it is unpacked again, so the
+        // clipboard gets what was sent, not what the user left there.
+        let a = out_dir.join("project/a.txt");
+        let b = out_dir.join("project/docs/b.txt");
+        let x = out_dir.join("project/x.txt");
+        for what in ["added", "removed", "resized", "rewritten"] {
+            match what {
+                "added" => std::fs::write(&x, b"x").unwrap(),
+                "removed" => std::fs::remove_file(&b).unwrap(),
+                "resized" => std::fs::write(&a, b"edited").unwrap(),
+                _ => {
+                    // Same size; only the mtime gives it away.
+                    std::fs::write(&a, b"z").unwrap();
+                    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+                    let f = std::fs::File::options().write(true).open(&a).unwrap();
+                    f.set_modified(later).unwrap();
+                }
+            }
+            let again = apply().await.unwrap();
+            assert!(!again.reused, "{what}");
+            assert_eq!(std::fs::read(&a).unwrap(), b"a", "{what}");
+            assert!(b.is_file() && !x.exists(), "{what}");
+            assert!(apply().await.unwrap().reused, "{what}");
+        }
+
+        // Once the extracted content is gone, it is unpacked again.
+        std::fs::remove_dir_all(&out_dir).unwrap();
+        let third = apply().await.unwrap();
+        assert!(!third.reused);
+        assert!(out_dir.join("project/docs/b.txt").is_file());
+    }
+
+    #[test]
+    fn selected_folder_keeps_its_empty_subdirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("folder");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir_all(root.join("deep").join("er")).unwrap();
+        std::fs::write(root.join("a.txt"), b"hello").unwrap();
+
+        let tar = build_tar_bundle(std::slice::from_ref(&root)).unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let dest = out.path().join("bundle");
+        unpack_tar_bytes_atomic(&tar, &dest).unwrap();
+
+        assert!(dest.join("folder").join("a.txt").is_file());
+        for empty in [dest.join("folder").join("sub"), dest.join("folder/deep/er")] {
+            assert!(empty.is_dir(), "{} missing", empty.display());
+            assert_eq!(std::fs::read_dir(&empty).unwrap().count(), 0);
+        }
     }
 
     #[test]
@@ -670,7 +1888,7 @@ mod tests {
         std::fs::write(&b, b"world").unwrap();
 
         // Clipboard gives us only files, no directory entry.
-        let tar = build_tar_bundle(&vec![a.clone(), b.clone()]).unwrap();
+        let tar = build_tar_bundle(&[a.clone(), b.clone()]).unwrap();
         assert!(!tar.is_empty());
 
         let out = tempfile::tempdir().unwrap();
@@ -680,13 +1898,105 @@ mod tests {
         assert!(out.path().join("folder").join("sub").join("b.txt").exists());
     }
 
+    #[test]
+    fn tar_bundle_is_independent_of_selection_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("folder");
+        let sub = root.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let a = root.join("a.txt");
+        let b = sub.join("b.txt");
+        let c = root.join("c.txt");
+        std::fs::write(&a, b"hello").unwrap();
+        std::fs::write(&b, b"world").unwrap();
+        std::fs::write(&c, b"!").unwrap();
+
+        // Tree-detection path (files only).
+        let t1 = build_tar_bundle(&[a.clone(), b.clone(), c.clone()]).unwrap();
+        let t2 = build_tar_bundle(&[c.clone(), b.clone(), a.clone(), b.clone()]).unwrap();
+        assert_eq!(t1, t2);
+
+        // Mixed files + directories.
+        let t3 = build_tar_bundle(&[sub.clone(), a.clone(), c.clone()]).unwrap();
+        let t4 = build_tar_bundle(&[c.clone(), sub.clone(), a.clone()]).unwrap();
+        assert_eq!(t3, t4);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn tar_bundle_zero_mtime_is_stable_across_touches() {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        fn set_mtime(p: &Path, secs: i64) {
+            let cpath = CString::new(p.as_os_str().as_bytes()).unwrap();
+            let ts = [
+                libc::timespec { tv_sec: secs, tv_nsec: 0 },
+                libc::timespec { tv_sec: secs, tv_nsec: 0 },
+            ];
+            let rc = unsafe { libc::utimensat(libc::AT_FDCWD, cpath.as_ptr(), ts.as_ptr(), 0) };
+            assert_eq!(rc, 0);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("folder");
+        let sub = root.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let a = sub.join("a.txt");
+        std::fs::write(&a, b"hello").unwrap();
+
+        for p in [&root, &sub, &a] {
+            set_mtime(p, 1_700_000_000);
+        }
+        let z1 = build_tar_bundle_with(std::slice::from_ref(&root), BundleMtime::Zero).unwrap();
+        let p1 = build_tar_bundle_with(std::slice::from_ref(&root), BundleMtime::Preserve).unwrap();
+
+        for p in [&root, &sub, &a] {
+            set_mtime(p, 1_700_000_500);
+        }
+        let z2 = build_tar_bundle_with(std::slice::from_ref(&root), BundleMtime::Zero).unwrap();
+        let p2 = build_tar_bundle_with(std::slice::from_ref(&root), BundleMtime::Preserve).unwrap();
+
+        assert_eq!(z1, z2);
+        assert_ne!(p1, p2);
+    }
+
+    #[tokio::test]
+    async fn cli_sends_that_dont_go_out_are_recorded() {
+        let src = tempfile::tempdir().unwrap();
+        let history = &src.path().join("history.jsonl");
+        let f = src.path().join("a.bin");
+        std::fs::write(&f, [7u8; 64]).unwrap();
+        let mime = Some("application/octet-stream");
+
+        // Nothing listens on the discard port.
+        let relay = "127.0.0.1:9";
+        let send = |room, max| send_file(history, "dev", "pc", room, &f, relay, max, mime, None);
+        let capped = send("cli-capped", 16).await;
+        let failed = send("cli-failed", 1 << 20).await;
+        let events = crate::history::read_history(history).unwrap();
+
+        assert!(capped.is_err() && failed.is_err());
+        let outcomes: Vec<_> = events
+            .iter()
+            .map(|e| (e.room.as_str(), e.outcome, e.bytes))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("cli-capped", Some(Outcome::Skipped), 64),
+                ("cli-failed", Some(Outcome::Failed), 64)
+            ]
+        );
+    }
+
     #[test]
     fn build_uri_list_uses_file_scheme_and_lf() {
         let dir = tempfile::tempdir().unwrap();
         let p = dir.path().join("a b.txt");
         std::fs::write(&p, b"x").unwrap();
 
-        let s = build_uri_list(&vec![p]);
+        let s = build_uri_list(&[p]);
         assert!(s.starts_with("file:///"), "uri list should start with file:/// but got: {s:?}");
         assert!(s.ends_with("\n"), "uri list should end with LF but got: {s:?}");
         assert!(
@@ -1,26 +1,151 @@
 use anyhow::Context;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use crate::hash::sha256_hex;
-use crate::history::record_send;
+use crate::channel::stamp_channel;
+use crate::content_filter::filter_outgoing;
+use crate::device::sender_name;
+use crate::hash::fingerprint;
+use crate::history::{record_event, record_send, send_outcome_event, Outcome};
 use crate::image_mode::ImageMode;
 use crate::net::{connect, send_frame};
-use crate::paths::{first_8, received_dir};
+use crate::paths::DataDirs;
+use crate::resend::persist_image_to;
 
 use utils::{Kind, Message};
 
+pub const SVG_MIME: &str = "image/svg+xml";
+
+/// Largest side (px) of a rasterized SVG fallback; keeps hostile `width="1e9"` documents cheap.
+const SVG_MAX_SIDE: f32 = 4096.0;
+
 pub fn image_mimes() -> &'static [&'static str] {
-    &["image/png", "image/jpeg", "image/webp", "image/gif"]
+    &["image/png", "image/jpeg", "image/webp", "image/gif", SVG_MIME]
 }
 
-fn detect_image_mime(bytes: &[u8], file: &PathBuf) -> anyhow::Result<String> {
-    // Prefer content sniffing.
-    if let Some(kind) = infer::get(bytes) {
-        let mime = kind.mime_type();
-        if mime.starts_with("image/") {
-            return Ok(mime.to_string());
+/// Env var carrying `--image-priority` (comma-separated MIME list); also passed to hooks.
+pub const IMAGE_PRIORITY_ENV: &str = "MCR_IMAGE_PRIORITY";
+
+/// Multi-mime default: original formats before PNG, since wl-apply offers a PNG fallback anyway.
+pub const DEFAULT_IMAGE_PRIORITY: &[&str] =
+    &[SVG_MIME, "image/jpeg", "image/webp", "image/gif", "image/png"];
+
+/// Other modes convert or pass through a single type, so an offered PNG always wins.
+const PNG_FIRST: &[&str] = &["image/png", "image/jpeg", "image/webp", "image/gif", SVG_MIME];
+
+static IMAGE_PRIORITY: OnceLock<Vec<&'static str>> = OnceLock::new();
+
+/// Parse a comma-separated priority list.
+///
+/// Unknown types are rejected; supported types left out keep their default relative order
+/// after the listed ones, so a partial list like `image/png` still considers everything.
+pub fn parse_image_priority(s: &str) -> anyhow::Result<Vec<&'static str>> {
+    let mut out: Vec<&'static str> = Vec::new();
+    for item in s.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+        let Some(m) = image_mimes().iter().copied().find(|m| m.eq_ignore_ascii_case(item)) else {
+            anyhow::bail!(
+                "unsupported image type {} in image priority (expected one of {})",
+                item,
+                image_mimes().join(",")
+            );
+        };
+        if !out.contains(&m) {
+            out.push(m);
         }
     }
+    for m in DEFAULT_IMAGE_PRIORITY {
+        if !out.contains(m) {
+            out.push(m);
+        }
+    }
+    Ok(out)
+}
+
+/// Configure the multi-mime preference order (process-wide, set once at startup).
+pub fn set_image_priority(v: Vec<&'static str>) {
+    let _ = IMAGE_PRIORITY.set(v);
+}
+
+pub fn image_priority() -> &'static [&'static str] {
+    IMAGE_PRIORITY.get_or_init(|| {
+        std::env::var(IMAGE_PRIORITY_ENV)
+            .ok()
+            .and_then(|v| parse_image_priority(&v).ok())
+            .unwrap_or_else(|| DEFAULT_IMAGE_PRIORITY.to_vec())
+    })
+}
+
+/// Pick the image type to publish from what the clipboard offers.
+pub fn choose_image_mime<F: Fn(&str) -> bool>(has: F, mode: ImageMode) -> Option<&'static str> {
+    choose_image_mime_with(has, mode, image_priority())
+}
+
+pub fn choose_image_mime_with<F: Fn(&str) -> bool>(
+    has: F,
+    mode: ImageMode,
+    priority: &[&'static str],
+) -> Option<&'static str> {
+    let order = if mode == ImageMode::MultiMime {
+        priority
+    } else {
+        PNG_FIRST
+    };
+    order.iter().copied().find(|m| has(m))
+}
+
+/// Cheap content check for SVG documents (infer does not detect text-based formats).
+pub fn looks_like_svg(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<svg") || head.starts_with("<!DOCTYPE svg"))
+        && head.contains("<svg")
+}
+
+/// Rasterize an SVG document to PNG at its intrinsic size (scaled down if huge).
+pub fn svg_to_png(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    use resvg::{tiny_skia, usvg};
+
+    let tree = usvg::Tree::from_data(bytes, &usvg::Options::default()).context("parse svg")?;
+    let size = tree.size();
+    let scale = (SVG_MAX_SIDE / size.width().max(size.height())).min(1.0);
+    let w = (size.width() * scale).ceil().max(1.0) as u32;
+    let h = (size.height() * scale).ceil().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(w, h).context("allocate svg pixmap")?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.encode_png().context("encode png")
+}
+
+/// MIME type sniffed from the content, if it has a recognizable signature.
+pub fn sniff_mime(bytes: &[u8]) -> Option<&'static str> {
+    infer::get(bytes).map(|k| k.mime_type())
+}
+
+/// Image type sniffed from the content (SVG included, which has no magic bytes).
+pub fn sniff_image_mime(bytes: &[u8]) -> Option<&'static str> {
+    sniff_mime(bytes)
+        .filter(|m| m.starts_with("image/"))
+        .or_else(|| looks_like_svg(bytes).then_some(SVG_MIME))
+}
+
+/// MIME to apply a received image under: what the bytes really are, else the sender's label,
+/// else PNG. Senders that omit or mislabel the type would otherwise get a broken clipboard entry.
+pub fn received_image_mime(claimed: Option<&str>, bytes: &[u8]) -> String {
+    sniff_image_mime(bytes)
+        .or(claimed)
+        .unwrap_or("image/png")
+        .to_string()
+}
+
+fn detect_image_mime(bytes: &[u8], file: &Path) -> anyhow::Result<String> {
+    // Prefer content sniffing.
+    if let Some(mime) = sniff_image_mime(bytes) {
+        return Ok(mime.to_string());
+    }
     // Fallback: extension guess.
     let ext = file
         .extension()
@@ -32,6 +157,7 @@ fn detect_image_mime(bytes: &[u8], file: &PathBuf) -> anyhow::Result<String> {
         "jpg" | "jpeg" => "image/jpeg",
         "webp" => "image/webp",
         "gif" => "image/gif",
+        "svg" => SVG_MIME,
         _ => anyhow::bail!(
             "unsupported image type (cannot detect mime): {}",
             file.display()
@@ -41,6 +167,9 @@ fn detect_image_mime(bytes: &[u8], file: &PathBuf) -> anyhow::Result<String> {
 }
 
 pub fn to_png(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if looks_like_svg(bytes) {
+        return svg_to_png(bytes);
+    }
     let img = image::load_from_memory(bytes).context("decode image")?;
     let mut out = Vec::new();
     img.write_to(&mut std::io::Cursor::new(&mut out), image::ImageFormat::Png)
@@ -48,18 +177,132 @@ pub fn to_png(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
     Ok(out)
 }
 
-fn image_ext_from_mime(mime: &str) -> Option<&'static str> {
-    match mime {
-        "image/png" => Some("png"),
-        "image/jpeg" => Some("jpg"),
-        "image/jpg" => Some("jpg"),
-        "image/webp" => Some("webp"),
-        "image/gif" => Some("gif"),
-        _ => None,
+/// Lossless WebP of `bytes` (any decodable image, SVG included).
+pub fn to_webp(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let img = if looks_like_svg(bytes) {
+        image::load_from_memory(&svg_to_png(bytes)?)
+    } else {
+        image::load_from_memory(bytes)
+    }
+    .context("decode image")?;
+    // The WebP encoder only takes 8-bit pixels.
+    let img = match img {
+        image::DynamicImage::ImageLuma8(_)
+        | image::DynamicImage::ImageLumaA8(_)
+        | image::DynamicImage::ImageRgb8(_)
+        | image::DynamicImage::ImageRgba8(_) => img,
+        other => image::DynamicImage::ImageRgba8(other.to_rgba8()),
+    };
+    let mut out = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut out);
+    img.write_to(&mut cursor, image::ImageFormat::WebP)
+        .context("encode webp")?;
+    Ok(out)
+}
+
+/// Env var used to pass `--preserve-animation` to helper processes (e.g. the wl-watch hook).
+pub const PRESERVE_ANIMATION_ENV: &str = "MCR_PRESERVE_ANIMATION";
+
+static PRESERVE_ANIMATION: OnceLock<bool> = OnceLock::new();
+
+/// Keep animated GIFs as `image/gif` in force-png mode (process-wide, set once at startup).
+pub fn set_preserve_animation(v: bool) {
+    let _ = PRESERVE_ANIMATION.set(v);
+}
+
+pub fn preserve_animation() -> bool {
+    *PRESERVE_ANIMATION.get_or_init(|| {
+        matches!(
+            std::env::var(PRESERVE_ANIMATION_ENV).ok().as_deref(),
+            Some("1") | Some("true")
+        )
+    })
+}
+
+/// True if `bytes` is a GIF with more than one frame.
+pub fn is_animated_gif(bytes: &[u8]) -> bool {
+    use image::AnimationDecoder;
+
+    if sniff_mime(bytes) != Some("image/gif") {
+        return false;
     }
+    let Ok(dec) = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(bytes)) else {
+        return false;
+    };
+    dec.into_frames().take(2).filter(|f| f.is_ok()).count() > 1
 }
 
+/// Force-png transform: convert to PNG, except animated GIFs when `preserve_animation` is set
+/// (PNG would collapse them to a single still frame).
+pub fn force_png_with(
+    mime: &str,
+    bytes: Vec<u8>,
+    preserve_animation: bool,
+) -> anyhow::Result<(&'static str, Vec<u8>)> {
+    if preserve_animation && mime == "image/gif" && is_animated_gif(&bytes) {
+        return Ok(("image/gif", bytes));
+    }
+    Ok(("image/png", to_png(&bytes)?))
+}
+
+/// Force-webp transform: like [`force_png_with`], but to WebP; WebP input is sent as it is
+/// (re-encoding a lossy WebP losslessly would only grow it).
+pub fn force_webp_with(
+    mime: &str,
+    bytes: Vec<u8>,
+    preserve_animation: bool,
+) -> anyhow::Result<(&'static str, Vec<u8>)> {
+    if preserve_animation && mime == "image/gif" && is_animated_gif(&bytes) {
+        return Ok(("image/gif", bytes));
+    }
+    if sniff_image_mime(&bytes) == Some("image/webp") {
+        return Ok(("image/webp", bytes));
+    }
+    Ok(("image/webp", to_webp(&bytes)?))
+}
+
+/// `force_png_with` using the process-wide `--preserve-animation` setting.
+pub fn force_png(mime: &str, bytes: Vec<u8>) -> anyhow::Result<(&'static str, Vec<u8>)> {
+    force_png_with(mime, bytes, preserve_animation())
+}
+
+/// [`to_png`] on the blocking pool: decoding and re-encoding a large image takes long enough
+/// to stall every other task on the worker (heartbeats, other connections).
+pub async fn to_png_blocking(bytes: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || to_png(&bytes))
+        .await
+        .context("png conversion join")?
+}
+
+/// [`force_png`] on the blocking pool (see [`to_png_blocking`]).
+pub async fn force_png_blocking(
+    mime: &str,
+    bytes: Vec<u8>,
+) -> anyhow::Result<(&'static str, Vec<u8>)> {
+    let mime = mime.to_string();
+    let preserve = preserve_animation();
+    tokio::task::spawn_blocking(move || force_png_with(&mime, bytes, preserve))
+        .await
+        .context("png conversion join")?
+}
+
+/// [`force_webp_with`] on the blocking pool (see [`to_png_blocking`]).
+pub async fn force_webp_blocking(
+    mime: &str,
+    bytes: Vec<u8>,
+) -> anyhow::Result<(&'static str, Vec<u8>)> {
+    let mime = mime.to_string();
+    let preserve = preserve_animation();
+    tokio::task::spawn_blocking(move || force_webp_with(&mime, bytes, preserve))
+        .await
+        .context("webp conversion join")?
+}
+
+/// `send-image`; `mime` (`--mime`) replaces the detected type (force-png/force-webp still
+/// convert). The outcome is logged, and the image kept for previews, under `data`.
+#[allow(clippy::too_many_arguments)]
 pub async fn send_image(
+    data: &DataDirs,
     local_device_id: &str,
     local_device_name: &str,
     room: &str,
@@ -67,12 +310,37 @@ pub async fn send_image(
     relay: &str,
     max_bytes: usize,
     image_mode: ImageMode,
+    mime: Option<&str>,
 ) -> anyhow::Result<()> {
     let bytes = tokio::fs::read(file).await.context("read image")?;
+    let mime = match mime {
+        Some(mime) => mime.to_string(),
+        None => detect_image_mime(&bytes, file)?,
+    };
     if bytes.len() > max_bytes {
+        let reason = format!(
+            "too large: {} bytes > max_image_bytes={}",
+            bytes.len(),
+            max_bytes
+        );
+        record_event(
+            &data.history,
+            send_outcome_event(
+                local_device_id,
+                sender_name(local_device_name),
+                room,
+                relay,
+                Outcome::Skipped,
+                &reason,
+                Kind::Image,
+                Some(mime),
+                bytes.len(),
+                None,
+            ),
+        )
+        .await;
         anyhow::bail!("image too large: {} bytes > {}", bytes.len(), max_bytes);
     }
-    let mime = detect_image_mime(&bytes, file)?;
     if !image_mimes().iter().any(|m| *m == mime) {
         anyhow::bail!("unsupported image mime {}", mime);
     }
@@ -81,31 +349,46 @@ pub async fn send_image(
         ImageMode::Passthrough | ImageMode::MultiMime | ImageMode::SpoofPng => {
             (mime.as_str(), bytes)
         }
-        ImageMode::ForcePng => ("image/png", to_png(&bytes)?),
+        ImageMode::ForcePng => force_png_blocking(&mime, bytes).await?,
+        ImageMode::ForceWebp => force_webp_blocking(&mime, bytes).await?,
+    };
+    let Some(send_bytes) = filter_outgoing(&Kind::Image, send_bytes).await else {
+        anyhow::bail!("image dropped by --send-filter");
     };
 
-    let stream = connect(relay).await?;
     let mut msg = Message::new_image(local_device_id, room, send_mime, send_bytes);
-    let local_name_opt = if local_device_name.trim().is_empty() {
-        None
-    } else {
-        Some(local_device_name.to_string())
-    };
+    let local_name_opt = sender_name(local_device_name);
     msg.sender_name = local_name_opt.clone();
-    let sha = sha256_hex(msg.payload.as_deref().unwrap_or_default());
+    stamp_channel(&mut msg);
+    let sha = fingerprint(msg.payload.as_deref().unwrap_or_default());
     msg.sha256 = Some(sha.clone());
 
     // Best-effort: persist sent image so local UI can preview it too.
     if let Some(payload) = msg.payload.as_deref() {
-        let sha8 = first_8(&sha).to_string();
-        let dir = received_dir().join(&sha8);
-        tokio::fs::create_dir_all(&dir).await.ok();
-        let ext = image_ext_from_mime(send_mime).unwrap_or("bin");
-        let p = dir.join(format!("image.{ext}"));
-        let _ = tokio::fs::write(&p, payload).await;
+        persist_image_to(&data.received, &sha, send_mime, payload).await;
     }
 
-    send_frame(stream, msg.to_bytes()).await?;
+    let sent = async { send_frame(connect(relay).await?, msg.to_bytes()).await }.await;
+    if let Err(e) = sent {
+        let reason = format!("send failed: {e:#}");
+        record_event(
+            &data.history,
+            send_outcome_event(
+                local_device_id,
+                local_name_opt,
+                room,
+                relay,
+                Outcome::Failed,
+                &reason,
+                Kind::Image,
+                Some(send_mime.to_string()),
+                msg.size,
+                Some(sha),
+            ),
+        )
+        .await;
+        return Err(e);
+    }
 
     log::debug!(
         "send-image: room={} relay={} mime={} bytes={} sha={}",
@@ -117,6 +400,7 @@ pub async fn send_image(
     );
 
     record_send(
+        &data.history,
         local_device_id,
         local_name_opt,
         room,
@@ -133,3 +417,144 @@ pub async fn send_image(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::AnimationDecoder;
+
+    fn two_frame_gif() -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut enc = image::codecs::gif::GifEncoder::new(&mut out);
+            let red = image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+            let blue = image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 255, 255]));
+            enc.encode_frames(vec![image::Frame::new(red), image::Frame::new(blue)])
+                .unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn image_chooser_respects_priority() {
+        let offered = ["image/png", "image/webp", "text/plain"];
+        let has = |m: &str| offered.contains(&m);
+
+        // Defaults: original format in multi-mime mode, PNG otherwise.
+        let default = parse_image_priority("").unwrap();
+        assert_eq!(default, DEFAULT_IMAGE_PRIORITY);
+        assert_eq!(
+            choose_image_mime_with(has, ImageMode::MultiMime, &default),
+            Some("image/webp")
+        );
+        assert_eq!(
+            choose_image_mime_with(has, ImageMode::ForcePng, &default),
+            Some("image/png")
+        );
+
+        let png_first = parse_image_priority(" image/PNG ,image/jpeg").unwrap();
+        assert_eq!(png_first[..3], ["image/png", "image/jpeg", SVG_MIME]);
+        assert_eq!(png_first.len(), image_mimes().len());
+        assert_eq!(
+            choose_image_mime_with(has, ImageMode::MultiMime, &png_first),
+            Some("image/png")
+        );
+        assert_eq!(
+            choose_image_mime_with(|m| m == "image/gif", ImageMode::MultiMime, &png_first),
+            Some("image/gif")
+        );
+        assert_eq!(choose_image_mime_with(|_| false, ImageMode::MultiMime, &png_first), None);
+
+        assert!(parse_image_priority("image/bmp").is_err());
+    }
+
+    #[test]
+    fn force_png_keeps_animated_gif_frames() {
+        let gif = two_frame_gif();
+        assert!(is_animated_gif(&gif));
+
+        let (mime, out) = force_png_with("image/gif", gif.clone(), true).unwrap();
+        assert_eq!(mime, "image/gif");
+        let dec = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(&out)).unwrap();
+        assert!(dec.into_frames().count() > 1);
+
+        // Without preservation it still collapses to a still PNG.
+        let (mime, out) = force_png_with("image/gif", gif, false).unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(infer::get(&out).map(|k| k.mime_type()), Some("image/png"));
+    }
+
+    #[test]
+    fn svg_round_trips_with_png_fallback() {
+        let svg = br##"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="16" height="8">
+  <rect width="16" height="8" fill="#ff0000"/>
+</svg>"##;
+        assert!(looks_like_svg(svg));
+        assert!(image_mimes().contains(&SVG_MIME));
+
+        // The original SVG survives the wire untouched.
+        let msg = Message::new_image("dev", "room", SVG_MIME, svg.to_vec());
+        let decoded = Message::try_from_bytes(&msg.to_bytes()).unwrap();
+        assert_eq!(decoded.mime.as_deref(), Some(SVG_MIME));
+        assert_eq!(decoded.payload.as_deref(), Some(&svg[..]));
+
+        // ...and the fallback is a real PNG at the intrinsic size.
+        let png = to_png(decoded.payload.as_deref().unwrap()).unwrap();
+        let img = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(img.dimensions(), (16, 8));
+        assert_eq!(img.get_pixel(4, 4).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn mime_less_jpeg_is_applied_as_jpeg() {
+        let mut jpeg = Vec::new();
+        image::RgbImage::from_pixel(4, 4, image::Rgb([0, 128, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+
+        // Missing or wrong labels lose to the bytes.
+        assert_eq!(received_image_mime(None, &jpeg), "image/jpeg");
+        assert_eq!(received_image_mime(Some("image/png"), &jpeg), "image/jpeg");
+        // Unrecognizable bytes keep the sender's label, then fall back to PNG.
+        assert_eq!(received_image_mime(Some("image/webp"), b"????"), "image/webp");
+        assert_eq!(received_image_mime(None, b"????"), "image/png");
+        assert_eq!(
+            received_image_mime(None, br#"<svg xmlns="http://www.w3.org/2000/svg"/>"#),
+            SVG_MIME
+        );
+    }
+
+    #[tokio::test]
+    async fn other_tasks_run_while_a_large_image_converts() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let mut jpeg = Vec::new();
+        let img = image::RgbImage::from_fn(1024, 1024, |x, y| {
+            image::Rgb([x as u8, y as u8, (x ^ y) as u8])
+        });
+        let out = &mut std::io::Cursor::new(&mut jpeg);
+        img.write_to(out, image::ImageFormat::Jpeg).unwrap();
+
+        // Single-threaded runtime: the ticker only runs if the conversion leaves the worker.
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                }
+            }
+        });
+        let png = to_png_blocking(jpeg).await.unwrap();
+        let seen = ticks.load(Ordering::SeqCst);
+        ticker.abort();
+
+        assert_eq!(sniff_mime(&png), Some("image/png"));
+        assert!(seen > 0, "ticker never ran during the conversion");
+    }
+}
@@ -76,7 +76,7 @@ fn run_owner(items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
     }
 
     // We must always support TARGETS.
-    payloads.entry(targets_atom).or_insert_with(Vec::new);
+    payloads.entry(targets_atom).or_default();
 
     // Become the clipboard owner.
     conn.set_selection_owner(win, clipboard, CURRENT_TIME)
@@ -383,7 +383,7 @@ fn get_atom_pairs<C: Connection>(
     for chunk in reply.value.chunks_exact(4) {
         atoms.push(u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
     }
-    if atoms.len() % 2 != 0 {
+    if !atoms.len().is_multiple_of(2) {
         return Ok(None);
     }
 
@@ -0,0 +1,73 @@
+use crate::consts::{GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME};
+use crate::transfer_file::parse_uri_list;
+
+/// Make a file selection pasteable on both sides of the bridge.
+///
+/// Apps usually read only one of the file targets: GTK file managers want
+/// `x-special/gnome-copied-files`, most others `text/uri-list`. Whichever one the source
+/// offered, add the missing ones with the same URIs.
+pub(super) fn complete_file_targets(items: &mut Vec<(String, Vec<u8>)>) {
+    let source = [URI_LIST_MIME, KDE_URI_LIST_MIME, GNOME_COPIED_FILES_MIME]
+        .iter()
+        .find_map(|m| items.iter().find(|(mime, _)| mime == m));
+    let Some((_, bytes)) = source else {
+        return;
+    };
+    let uris = parse_uri_list(bytes);
+    if uris.is_empty() {
+        return;
+    }
+    let uri_list: String = uris.iter().map(|u| format!("{}\n", u)).collect();
+
+    let has = |items: &[(String, Vec<u8>)], m: &str| items.iter().any(|(mime, _)| mime == m);
+    if !has(items, URI_LIST_MIME) {
+        items.push((URI_LIST_MIME.to_string(), uri_list.as_bytes().to_vec()));
+    }
+    if !has(items, GNOME_COPIED_FILES_MIME) {
+        let gnome = format!("copy\n{}", uri_list.trim_end());
+        items.push((GNOME_COPIED_FILES_MIME.to_string(), gnome.into_bytes()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get<'a>(items: &'a [(String, Vec<u8>)], m: &str) -> Option<&'a str> {
+        items
+            .iter()
+            .find(|(mime, _)| mime == m)
+            .map(|(_, b)| std::str::from_utf8(b).unwrap())
+    }
+
+    #[test]
+    fn missing_file_targets_are_synthesized() {
+        let mut items = vec![(
+            URI_LIST_MIME.to_string(),
+            b"file:///tmp/a.txt\r\nfile:///tmp/b%20c\r\n".to_vec(),
+        )];
+        complete_file_targets(&mut items);
+        assert_eq!(
+            get(&items, GNOME_COPIED_FILES_MIME),
+            Some("copy\nfile:///tmp/a.txt\nfile:///tmp/b%20c")
+        );
+        // The original target is left untouched.
+        assert_eq!(
+            get(&items, URI_LIST_MIME),
+            Some("file:///tmp/a.txt\r\nfile:///tmp/b%20c\r\n")
+        );
+
+        let mut items = vec![(
+            GNOME_COPIED_FILES_MIME.to_string(),
+            b"cut\nfile:///tmp/a.txt".to_vec(),
+        )];
+        complete_file_targets(&mut items);
+        assert_eq!(get(&items, URI_LIST_MIME), Some("file:///tmp/a.txt\n"));
+        assert_eq!(items.len(), 2);
+
+        // Text-only selections stay text-only.
+        let mut items = vec![("text/plain".to_string(), b"file:///tmp/a.txt".to_vec())];
+        complete_file_targets(&mut items);
+        assert_eq!(items.len(), 1);
+    }
+}
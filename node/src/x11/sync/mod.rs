@@ -1,3 +1,4 @@
+mod files;
 mod service;
 mod state;
 mod wl_to_x11;
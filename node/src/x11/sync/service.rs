@@ -5,8 +5,11 @@ use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use tokio::net::UnixDatagram;
 
+use crate::clipboard::{
+    apply_to_primary, copy_checked, copy_primary_text, ClipboardSink, ClipboardSource, WlPaste,
+};
 use crate::consts::X11_SYNC_MARKER_MIME;
-use crate::hash::sha256_hex;
+use crate::hash::fingerprint;
 
 use super::state;
 use super::wl_to_x11::apply_wayland_to_x11_full;
@@ -58,6 +61,77 @@ fn env_u64(name: &str) -> Option<u64> {
     std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok())
 }
 
+/// Offer an X11 selection on the Wayland clipboard, tagged as coming from X11.
+///
+/// With `to_primary` (see [`apply_to_primary`]), text-only selections also go to the primary
+/// selection, like received text does; files and images only ever go to the clipboard.
+async fn x11_to_wl_once<C: ClipboardSource + ClipboardSink>(
+    clip: &C,
+    mut snap: X11Snapshot,
+    last_hash: &mut Option<String>,
+    to_primary: bool,
+) {
+    // Skip echo: if X11 clipboard was produced by us from Wayland, it will contain our marker with payload from=wl.
+    if snap.marked_from_wayland {
+        debug!("x11->wl skip: x11 clipboard marked from wl");
+        return;
+    }
+
+    // If X11 has no meaningful data (e.g. no owner / selection cleared), do not override Wayland.
+    if snap.items.is_empty() {
+        debug!("x11->wl skip: empty snapshot");
+        return;
+    }
+
+    // Construct Wayland multi-mime set, and tag it as originating from X11.
+    let mut items: Vec<(String, Vec<u8>)> = Vec::new();
+    items.push((X11_SYNC_MARKER_MIME.to_string(), b"from=x11".to_vec()));
+
+    let mut payload_count = 0usize;
+    for (mime, bytes) in snap.items.drain(..) {
+        if bytes.is_empty() {
+            continue;
+        }
+        if mime == X11_SYNC_MARKER_MIME {
+            continue;
+        }
+        payload_count += 1;
+        items.push((mime, bytes));
+    }
+    if payload_count == 0 {
+        debug!("x11->wl skip: marker-only payload");
+        return;
+    }
+
+    // Hash guard.
+    let hash_material = items
+        .iter()
+        .map(|(m, b)| format!("{}:{}", m, fingerprint(b)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let sha = fingerprint(hash_material.as_bytes());
+    if last_hash.as_deref() == Some(&sha) {
+        debug!("x11->wl skip: same hash {sha}");
+        return;
+    }
+
+    // Everything after the marker; plain text only, it goes to PRIMARY as well.
+    let payload = &items[1..];
+    let plain = payload
+        .iter()
+        .all(|(m, _)| m.starts_with("text/plain"))
+        .then(|| payload[0].1.clone());
+    match copy_checked(clip, items).await {
+        Ok(report) if report.missing.is_empty() => info!("x11->wl applied (hash={sha})"),
+        Ok(report) => warn!("x11->wl applied without {:?} (hash={sha})", report.missing),
+        Err(e) => warn!("x11->wl failed to write wl clipboard: {e:?}"),
+    }
+    if let Some(plain) = plain.filter(|_| to_primary) {
+        copy_primary_text(clip, &plain).await;
+    }
+    *last_hash = Some(sha);
+}
+
 pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
     state::ensure_state_dir(&opts.state_dir).await;
 
@@ -86,9 +160,10 @@ pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
     let (tx, mut rx) = tokio::sync::mpsc::channel::<X11Snapshot>(8);
     let max_text = opts.max_text_bytes;
     let max_img = opts.max_image_bytes;
+    let poll_interval = opts.poll_interval;
 
     tokio::task::spawn_blocking(move || {
-        if let Err(e) = x11_watch_clipboard_loop(tx, max_text, max_img) {
+        if let Err(e) = x11_watch_clipboard_loop(tx, max_text, max_img, poll_interval) {
             eprintln!("x11-sync: x11 watch loop failed: {:#}", e);
         }
     });
@@ -108,61 +183,6 @@ pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
     let mut tick = tokio::time::interval(tick_every);
     tick.tick().await;
 
-    async fn run_x11_to_wl_once(
-        mut snap: X11Snapshot,
-        last_hash: &mut Option<String>,
-    ) {
-        // Skip echo: if X11 clipboard was produced by us from Wayland, it will contain our marker with payload from=wl.
-        if snap.marked_from_wayland {
-            debug!("x11->wl skip: x11 clipboard marked from wl");
-            return;
-        }
-
-        // If X11 has no meaningful data (e.g. no owner / selection cleared), do not override Wayland.
-        if snap.items.is_empty() {
-            debug!("x11->wl skip: empty snapshot");
-            return;
-        }
-
-        // Construct Wayland multi-mime set, and tag it as originating from X11.
-        let mut items: Vec<(String, Vec<u8>)> = Vec::new();
-        items.push((X11_SYNC_MARKER_MIME.to_string(), b"from=x11".to_vec()));
-
-        let mut payload_count = 0usize;
-        for (mime, bytes) in snap.items.drain(..) {
-            if bytes.is_empty() {
-                continue;
-            }
-            if mime == X11_SYNC_MARKER_MIME {
-                continue;
-            }
-            payload_count += 1;
-            items.push((mime, bytes));
-        }
-        if payload_count == 0 {
-            debug!("x11->wl skip: marker-only payload");
-            return;
-        }
-
-        // Hash guard.
-        let hash_material = items
-            .iter()
-            .map(|(m, b)| format!("{}:{}", m, sha256_hex(b)))
-            .collect::<Vec<_>>()
-            .join("\n");
-        let sha = sha256_hex(hash_material.as_bytes());
-        if last_hash.as_deref() == Some(&sha) {
-            debug!("x11->wl skip: same hash {sha}");
-            return;
-        }
-
-        match crate::clipboard::wl_copy_multi(items).await {
-            Ok(()) => info!("x11->wl applied (hash={sha})"),
-            Err(e) => warn!("x11->wl failed to write wl clipboard: {e:?}"),
-        }
-        *last_hash = Some(sha);
-    }
-
     loop {
         tokio::select! {
             _ = tick.tick() => {
@@ -171,7 +191,7 @@ pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
 
                 if let Some(snap) = pending_x11_to_wl.take() {
                     if limiter.allow(now) {
-                        match tokio::time::timeout(task_timeout, run_x11_to_wl_once(snap, &mut last_hash)).await {
+                        match tokio::time::timeout(task_timeout, x11_to_wl_once(&WlPaste, snap, &mut last_hash, apply_to_primary())).await {
                             Ok(()) => {}
                             Err(_) => warn!("x11-sync guard: x11->wl task timed out after {:?}", task_timeout),
                         }
@@ -181,13 +201,11 @@ pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
                     continue;
                 }
 
-                if pending_wl_to_x11 {
-                    if limiter.allow(now) {
-                        pending_wl_to_x11 = false;
-                        match tokio::time::timeout(task_timeout, apply_wayland_to_x11_full(&opts.state_dir)).await {
-                            Ok(()) => {}
-                            Err(_) => warn!("x11-sync guard: wl->x11 task timed out after {:?}", task_timeout),
-                        }
+                if pending_wl_to_x11 && limiter.allow(now) {
+                    pending_wl_to_x11 = false;
+                    match tokio::time::timeout(task_timeout, apply_wayland_to_x11_full(&opts.state_dir)).await {
+                        Ok(()) => {}
+                        Err(_) => warn!("x11-sync guard: wl->x11 task timed out after {:?}", task_timeout),
                     }
                 }
             }
@@ -201,7 +219,7 @@ pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
                 let now = Instant::now();
                 if limiter.allow(now) {
                     if let Some(snap) = pending_x11_to_wl.take() {
-                        match tokio::time::timeout(task_timeout, run_x11_to_wl_once(snap, &mut last_hash)).await {
+                        match tokio::time::timeout(task_timeout, x11_to_wl_once(&WlPaste, snap, &mut last_hash, apply_to_primary())).await {
                             Ok(()) => {}
                             Err(_) => warn!("x11-sync guard: x11->wl task timed out after {:?}", task_timeout),
                         }
@@ -228,3 +246,79 @@ pub async fn x11_sync_service(opts: X11SyncOpts) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct StubClipboard {
+        regular: Mutex<Vec<String>>,
+        primary: Mutex<Vec<String>>,
+    }
+
+    impl ClipboardSource for StubClipboard {
+        async fn list_types(&self) -> Option<Vec<String>> {
+            Some(self.regular.lock().unwrap().clone())
+        }
+
+        async fn read(&self, _mime: &str) -> anyhow::Result<Vec<u8>> {
+            anyhow::bail!("not needed")
+        }
+    }
+
+    impl ClipboardSink for StubClipboard {
+        async fn write(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+            *self.regular.lock().unwrap() = items.into_iter().map(|(m, _)| m).collect();
+            Ok(())
+        }
+
+        async fn write_primary(&self, items: Vec<(String, Vec<u8>)>) -> anyhow::Result<()> {
+            *self.primary.lock().unwrap() = items.into_iter().map(|(m, _)| m).collect();
+            Ok(())
+        }
+    }
+
+    fn snap(items: &[(&str, &[u8])]) -> X11Snapshot {
+        X11Snapshot {
+            marked_from_wayland: false,
+            items: items
+                .iter()
+                .map(|(m, b)| (m.to_string(), b.to_vec()))
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn x11_text_reaches_primary_but_files_do_not() {
+        let text = || snap(&[("text/plain;charset=utf-8", b"hi"), ("text/plain", b"hi")]);
+        let clip = StubClipboard::default();
+        x11_to_wl_once(&clip, text(), &mut None, true).await;
+        assert!(clip
+            .regular
+            .lock()
+            .unwrap()
+            .contains(&X11_SYNC_MARKER_MIME.to_string()));
+        assert!(clip
+            .primary
+            .lock()
+            .unwrap()
+            .contains(&"text/plain;charset=utf-8".to_string()));
+
+        // `--no-apply-to-primary`.
+        let clip = StubClipboard::default();
+        x11_to_wl_once(&clip, text(), &mut None, false).await;
+        assert_eq!(clip.regular.lock().unwrap().len(), 3);
+        assert!(clip.primary.lock().unwrap().is_empty());
+
+        let clip = StubClipboard::default();
+        let files = snap(&[
+            ("text/uri-list", b"file:///tmp/a\n"),
+            ("text/plain;charset=utf-8", b"/tmp/a"),
+        ]);
+        x11_to_wl_once(&clip, files, &mut None, true).await;
+        assert_eq!(clip.regular.lock().unwrap().len(), 3);
+        assert!(clip.primary.lock().unwrap().is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use tokio::net::UnixDatagram;
 
@@ -8,19 +8,19 @@ const WL_NOTIFY_SOCK: &str = "wl_notify.sock";
 pub(crate) const MARK_FROM_X11: &[u8] = b"from=x11";
 pub(crate) const MARK_FROM_WL: &[u8] = b"from=wl";
 
-fn wl_notify_socket_path(state_dir: &PathBuf) -> PathBuf {
+fn wl_notify_socket_path(state_dir: &Path) -> PathBuf {
     state_dir.join(SUBDIR).join(WL_NOTIFY_SOCK)
 }
 
-fn state_path(state_dir: &PathBuf, key: &str) -> PathBuf {
+fn state_path(state_dir: &Path, key: &str) -> PathBuf {
     state_dir.join(SUBDIR).join(key)
 }
 
-pub(crate) async fn ensure_state_dir(state_dir: &PathBuf) {
+pub(crate) async fn ensure_state_dir(state_dir: &Path) {
     let _ = tokio::fs::create_dir_all(state_dir.join(SUBDIR)).await;
 }
 
-pub(crate) async fn send_wl_notify(state_dir: &PathBuf) {
+pub(crate) async fn send_wl_notify(state_dir: &Path) {
     ensure_state_dir(state_dir).await;
     let p = wl_notify_socket_path(state_dir);
     let sock = UnixDatagram::unbound();
@@ -30,18 +30,18 @@ pub(crate) async fn send_wl_notify(state_dir: &PathBuf) {
     let _ = sock.send_to(b"changed", &p).await;
 }
 
-pub(crate) fn wl_notify_socket_path_for_bind(state_dir: &PathBuf) -> PathBuf {
+pub(crate) fn wl_notify_socket_path_for_bind(state_dir: &Path) -> PathBuf {
     wl_notify_socket_path(state_dir)
 }
 
-pub(crate) async fn state_get(state_dir: &PathBuf, key: &str) -> Option<String> {
+pub(crate) async fn state_get(state_dir: &Path, key: &str) -> Option<String> {
     let p = state_path(state_dir, key);
     let s = tokio::fs::read_to_string(&p).await.ok()?;
     let s = s.trim().to_string();
     if s.is_empty() { None } else { Some(s) }
 }
 
-pub(crate) async fn state_set(state_dir: &PathBuf, key: &str, val: &str) {
+pub(crate) async fn state_set(state_dir: &Path, key: &str, val: &str) {
     let p = state_path(state_dir, key);
     let _ = tokio::fs::write(&p, val).await;
 }
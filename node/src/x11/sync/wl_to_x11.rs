@@ -1,18 +1,18 @@
 use log::{debug, info, warn};
-use std::path::PathBuf;
-use tokio::process::Command;
+use std::path::Path;
 
 use crate::clipboard::wl_paste;
 use crate::consts::{
     GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME, X11_SYNC_MARKER_MIME,
 };
-use crate::hash::sha256_hex;
+use crate::hash::fingerprint;
 use crate::x11_native;
 
+use super::files::complete_file_targets;
 use super::state::{self, MARK_FROM_X11};
 
 pub async fn x11_hook_apply_wayland_to_x11(
-    state_dir: &PathBuf,
+    state_dir: &Path,
     _kind: &str,
     _stdin_bytes: Vec<u8>,
 ) {
@@ -26,11 +26,10 @@ pub async fn x11_hook_apply_wayland_to_x11(
 }
 
 async fn wl_list_types() -> String {
-    let out = Command::new("wl-paste").arg("--list-types").output().await;
-    match out {
-        Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout).to_string(),
-        _ => String::new(),
-    }
+    crate::clipboard::wl_list_types()
+        .await
+        .unwrap_or_default()
+        .join("\n")
 }
 
 async fn wl_marker_origin_is_x11(wl_types: &str) -> bool {
@@ -59,7 +58,7 @@ async fn wl_marker_origin_is_x11(wl_types: &str) -> bool {
     }
 }
 
-pub(super) async fn apply_wayland_to_x11_full(state_dir: &PathBuf) {
+pub(super) async fn apply_wayland_to_x11_full(state_dir: &Path) {
     state::ensure_state_dir(state_dir).await;
 
     // Marker-based loop prevention:
@@ -88,6 +87,8 @@ pub(super) async fn apply_wayland_to_x11_full(state_dir: &PathBuf) {
         }
     }
 
+    complete_file_targets(&mut items);
+
     // Image targets.
     for m in ["image/png", "image/jpeg", "image/gif", "image/webp"] {
         if wl_types.lines().any(|l| l.trim() == m) {
@@ -134,10 +135,10 @@ pub(super) async fn apply_wayland_to_x11_full(state_dir: &PathBuf) {
     // Hash guard to avoid repeated owner churn when multiple wl-paste watchers fire.
     let hash_material = items
         .iter()
-        .map(|(m, b)| format!("{}:{}", m, sha256_hex(b)))
+        .map(|(m, b)| format!("{}:{}", m, fingerprint(b)))
         .collect::<Vec<_>>()
         .join("\n");
-    let sha = sha256_hex(hash_material.as_bytes());
+    let sha = fingerprint(hash_material.as_bytes());
     if let Some(last) = state::state_get(state_dir, "wl_full_hash").await {
         if last == sha {
             debug!("wl->x11 skip: same hash {sha}");
@@ -155,3 +156,41 @@ pub(super) async fn apply_wayland_to_x11_full(state_dir: &PathBuf) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn wayland_uri_list_appears_on_x11_selection() {
+        if std::env::var_os("WAYLAND_DISPLAY").is_none() || std::env::var_os("DISPLAY").is_none() {
+            eprintln!("skipped: needs both Wayland and X11 (XWayland) displays");
+            return;
+        }
+        let uri = format!("file:///tmp/mcr-x11-files-{}\n", uuid::Uuid::new_v4());
+        if let Err(e) = crate::clipboard::wl_copy(URI_LIST_MIME, uri.as_bytes()).await {
+            eprintln!("skipped: compositor has no data-control support ({e:#})");
+            return;
+        }
+
+        let state_dir = tempfile::tempdir().unwrap();
+        apply_wayland_to_x11_full(state_dir.path()).await;
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let snap = loop {
+            assert!(std::time::Instant::now() < deadline, "uri-list never reached X11");
+            let snap = tokio::task::spawn_blocking(super::super::x11_watch::read_clipboard_once)
+                .await
+                .unwrap();
+            if let Ok(snap) = snap {
+                if snap.items.iter().any(|(m, b)| m == URI_LIST_MIME && *b == uri.as_bytes()) {
+                    break snap;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        assert!(snap.marked_from_wayland);
+        assert!(snap.items.iter().any(|(m, _)| m == GNOME_COPIED_FILES_MIME));
+    }
+}
@@ -13,6 +13,7 @@ use crate::consts::{
     GNOME_COPIED_FILES_MIME, KDE_URI_LIST_MIME, URI_LIST_MIME, X11_SYNC_MARKER_MIME,
 };
 
+use super::files::complete_file_targets;
 use super::state::{MARK_FROM_WL};
 
 pub(super) struct X11Snapshot {
@@ -27,19 +28,20 @@ fn looks_like_file_uri_list_text(s: &str) -> bool {
     })
 }
 
+/// Send a snapshot whenever the X11 CLIPBOARD changes.
+///
+/// Event-driven via XFixes selection notifications; without XFixes, falls back to checking
+/// the selection owner every `poll_interval`.
 pub(super) fn x11_watch_clipboard_loop(
     tx: tokio::sync::mpsc::Sender<X11Snapshot>,
     max_text_bytes: usize,
     max_image_bytes: usize,
+    poll_interval: std::time::Duration,
 ) -> anyhow::Result<()> {
     let (conn, screen_num) = RustConnection::connect(None).context("connect X11")?;
     let screen = &conn.setup().roots[screen_num];
 
-    // Ensure XFixes is available.
-    let _ = xfixes::query_version(&conn, 5, 0)
-        .context("xfixes query_version")?
-        .reply()
-        .context("xfixes query_version reply")?;
+    let xfixes_ok = xfixes_available(&conn);
 
     let win: Window = conn.generate_id().context("gen window id")?;
     conn.create_window(
@@ -58,29 +60,106 @@ pub(super) fn x11_watch_clipboard_loop(
     .context("create window")?;
 
     let clipboard = intern_atom(&conn, "CLIPBOARD")?;
+    if !xfixes_ok {
+        warn!(
+            "x11-sync: XFixes unavailable; polling the clipboard owner every {:?}",
+            poll_interval
+        );
+        return poll_clipboard_owner_loop(
+            &conn,
+            win,
+            clipboard,
+            tx,
+            max_text_bytes,
+            max_image_bytes,
+            poll_interval,
+        );
+    }
     xfixes::select_selection_input(&conn, win, clipboard, SelectionEventMask::SET_SELECTION_OWNER)
         .context("xfixes select_selection_input")?;
     conn.flush().ok();
 
     loop {
         let ev = conn.wait_for_event().context("wait_for_event")?;
-        match ev {
-            Event::XfixesSelectionNotify(_n) => {
-                if let Ok(snap) = read_x11_clipboard_snapshot(
-                    &conn,
-                    win,
-                    clipboard,
-                    max_text_bytes,
-                    max_image_bytes,
-                ) {
+        if let Event::XfixesSelectionNotify(_n) = ev {
+            if let Ok(snap) = read_x11_clipboard_snapshot(
+                &conn,
+                win,
+                clipboard,
+                max_text_bytes,
+                max_image_bytes,
+            ) {
+                let _ = tx.blocking_send(snap);
+            }
+        }
+    }
+}
+
+pub(super) fn xfixes_available<C: Connection>(conn: &C) -> bool {
+    xfixes::query_version(conn, 5, 0)
+        .ok()
+        .and_then(|c| c.reply().ok())
+        .is_some()
+}
+
+/// Fallback for servers without XFixes: a new owner means a new copy. Apps that keep
+/// ownership across copies are only picked up on the next owner change.
+fn poll_clipboard_owner_loop(
+    conn: &RustConnection,
+    win: Window,
+    clipboard: Atom,
+    tx: tokio::sync::mpsc::Sender<X11Snapshot>,
+    max_text_bytes: usize,
+    max_image_bytes: usize,
+    poll_interval: std::time::Duration,
+) -> anyhow::Result<()> {
+    let none: Window = AtomEnum::NONE.into();
+    let mut last_owner = none;
+    loop {
+        let owner = conn
+            .get_selection_owner(clipboard)
+            .context("get_selection_owner")?
+            .reply()
+            .context("get_selection_owner reply")?
+            .owner;
+        if owner != last_owner {
+            last_owner = owner;
+            if owner != none {
+                if let Ok(snap) =
+                    read_x11_clipboard_snapshot(conn, win, clipboard, max_text_bytes, max_image_bytes)
+                {
                     let _ = tx.blocking_send(snap);
                 }
             }
-            _ => {}
         }
+        std::thread::sleep(poll_interval);
     }
 }
 
+/// One-off read of the X11 CLIPBOARD (tests).
+#[cfg(test)]
+pub(super) fn read_clipboard_once() -> anyhow::Result<X11Snapshot> {
+    let (conn, screen_num) = RustConnection::connect(None).context("connect X11")?;
+    let screen = &conn.setup().roots[screen_num];
+    let win: Window = conn.generate_id().context("gen window id")?;
+    conn.create_window(
+        0,
+        win,
+        screen.root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        xproto::WindowClass::INPUT_OUTPUT,
+        COPY_FROM_PARENT,
+        &xproto::CreateWindowAux::new(),
+    )
+    .context("create window")?;
+    let clipboard = intern_atom(&conn, "CLIPBOARD")?;
+    read_x11_clipboard_snapshot(&conn, win, clipboard, 1 << 20, 1 << 20)
+}
+
 fn intern_atom<C: Connection>(conn: &C, name: &str) -> anyhow::Result<Atom> {
     Ok(conn
         .intern_atom(false, name.as_bytes())
@@ -249,6 +328,8 @@ fn read_x11_clipboard_snapshot(
         }
     }
 
+    complete_file_targets(&mut items);
+
     // Dedup by mime.
     let mut seen = std::collections::BTreeSet::new();
     items.retain(|(m, _)| seen.insert(m.clone()));
@@ -264,3 +345,48 @@ fn read_x11_clipboard_snapshot(
         items,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn selection_change_triggers_snapshot_without_polling() {
+        if std::env::var_os("DISPLAY").is_none() {
+            eprintln!("skipped: no X11 display");
+            return;
+        }
+        let Ok((conn, _)) = RustConnection::connect(None) else {
+            eprintln!("skipped: X11 display not reachable");
+            return;
+        };
+        if !xfixes_available(&conn) {
+            eprintln!("skipped: XFixes unavailable");
+            return;
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        // Far longer than the test: only a selection event can deliver the snapshot.
+        std::thread::spawn(move || {
+            let _ = x11_watch_clipboard_loop(tx, 1 << 20, 1 << 20, Duration::from_secs(3600));
+        });
+        std::thread::sleep(Duration::from_millis(200));
+
+        let text = format!("mcr x11 watch {}", uuid::Uuid::new_v4());
+        crate::x11_native::spawn_clipboard_owner(vec![(
+            "text/plain;charset=utf-8".to_string(),
+            text.as_bytes().to_vec(),
+        )])
+        .unwrap();
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            assert!(std::time::Instant::now() < deadline, "no snapshot after selection change");
+            match rx.try_recv() {
+                Ok(snap) if snap.items.iter().any(|(_, b)| b == text.as_bytes()) => break,
+                _ => std::thread::sleep(Duration::from_millis(20)),
+            }
+        }
+    }
+}
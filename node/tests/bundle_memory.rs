@@ -0,0 +1,115 @@
+//! Peak-memory check for spooled bundles. Its own test binary: it installs a counting
+//! global allocator.
+
+use node::consts::TAR_MIME;
+use node::hash::fingerprint;
+use node::transfer_file::{build_tar_bundle, spool_tar_bundle_capped};
+use utils::Message;
+
+/// Counts what the current thread allocates while [`peak_alloc::track`] runs.
+mod peak_alloc {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::future::Future;
+
+    struct Tracking;
+
+    thread_local! {
+        static ACTIVE: Cell<bool> = const { Cell::new(false) };
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+        static PEAK: Cell<isize> = const { Cell::new(0) };
+    }
+
+    fn note(delta: isize) {
+        let _ = ACTIVE.try_with(|active| {
+            if active.get() {
+                let live = LIVE.get() + delta;
+                LIVE.set(live);
+                PEAK.set(PEAK.get().max(live));
+            }
+        });
+    }
+
+    unsafe impl GlobalAlloc for Tracking {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            note(layout.size() as isize);
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            note(layout.size() as isize);
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            note(new_size as isize - layout.size() as isize);
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            note(-(layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Tracking = Tracking;
+
+    /// Run `fut` on this thread; returns its output and the most it had allocated at once.
+    pub async fn track<F: Future>(fut: F) -> (F::Output, usize) {
+        LIVE.set(0);
+        PEAK.set(0);
+        ACTIVE.set(true);
+        let out = fut.await;
+        ACTIVE.set(false);
+        (out, PEAK.get().max(0) as usize)
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn large_tree_is_bundled_and_sent_in_bounded_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("tree");
+    let chunk = vec![7u8; 256 * 1024];
+    for i in 0..48 {
+        let sub = root.join(format!("d{}", i % 6));
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(sub.join(format!("f{i}.bin")), &chunk).unwrap();
+    }
+    let paths = [root.clone()];
+    let whole = build_tar_bundle(&paths).unwrap();
+    assert!(whole.len() > 12 * 1024 * 1024, "{}", whole.len());
+
+    // Spooling and sending a 12 MiB bundle needs no more than its copy buffers.
+    let ((spooled, msg), peak) = peak_alloc::track(async {
+        let spooled = spool_tar_bundle_capped(&paths, whole.len())
+            .unwrap()
+            .unwrap();
+        let mut msg = spooled.file_message("dev", "r", "tree", TAR_MIME);
+        msg.sha256 = Some(spooled.sha.clone());
+        spooled
+            .write_frame(&mut tokio::io::sink(), &msg)
+            .await
+            .unwrap();
+        (spooled, msg)
+    })
+    .await;
+    assert!(peak < 1024 * 1024, "peak {peak} bytes");
+    assert_eq!(spooled.len, whole.len() as u64);
+    assert_eq!(spooled.sha, fingerprint(&whole));
+
+    // What goes out is the same frame as sending the archive from memory.
+    let mut frame = Vec::new();
+    spooled.write_frame(&mut frame, &msg).await.unwrap();
+    let got = Message::try_from_bytes(&frame[4..]).unwrap();
+    assert_eq!(got.payload.as_deref(), Some(whole.as_slice()));
+    assert_eq!(got.sha256.as_deref(), Some(fingerprint(&whole).as_str()));
+
+    // Over the cap: given up on instead of spooled whole.
+    assert!(spool_tar_bundle_capped(&paths, 1024 * 1024)
+        .unwrap()
+        .is_none());
+    assert!(spool_tar_bundle_capped(&paths, whole.len() - 1)
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use relay::{run_relay, RelayConfig};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::Command;
+
+fn free_addr() -> String {
+    let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    l.local_addr().unwrap().to_string()
+}
+
+/// The node binary with its state and data kept under `dir`.
+fn node(dir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_node"));
+    cmd.env(utils::paths::STATE_DIR_ENV, dir.join("state"))
+        .env(utils::paths::DATA_DIR_ENV, dir.join("data"))
+        .env_remove("MCR_CHANNEL")
+        .env_remove("MCR_SUBSCRIBE")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    cmd
+}
+
+async fn send_text(dir: &Path, relay: &str, channel: &str, text: &str) {
+    let status = node(dir)
+        .args(["--channel", channel, "send-text", "--room", "room"])
+        .args(["--relay", relay, "--text", text])
+        .stdout(Stdio::null())
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success(), "send-text --channel {channel} failed");
+}
+
+async fn next_line<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> String {
+    tokio::time::timeout(Duration::from_secs(10), lines.next_line())
+        .await
+        .expect("listen printed nothing")
+        .unwrap()
+        .expect("listen exited")
+}
+
+/// The next text listen received, skipping the relay's hello and other status lines.
+async fn next_text<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> String {
+    loop {
+        let line = next_line(lines).await;
+        if line.contains("kind=Text") {
+            return line;
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_node_subscribed_to_one_channel_does_not_receive_another() {
+    let listener_dir = tempfile::tempdir().unwrap();
+    let sender_dir = tempfile::tempdir().unwrap();
+
+    let addr = free_addr();
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let config = RelayConfig {
+        bind: vec![addr.clone()],
+        ..RelayConfig::default()
+    };
+    let server = tokio::spawn(run_relay(config, async {
+        let _ = stopped.await;
+    }));
+
+    let mut listener = node(listener_dir.path())
+        .args([
+            "--subscribe",
+            "a",
+            "listen",
+            "--room",
+            "room",
+            "--relay",
+            &addr,
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(listener.stdout.take().unwrap()).lines();
+    // The relay answers the Join with its Hello once the subscription is registered.
+    while !next_line(&mut lines).await.contains("kind=Hello") {}
+
+    send_text(sender_dir.path(), &addr, "b", "for channel b").await;
+    send_text(sender_dir.path(), &addr, "a", "for channel a").await;
+
+    let got = next_text(&mut lines).await;
+    assert!(got.ends_with("text=for channel a"), "{got}");
+    // Nothing else arrives (in particular not the earlier message to b).
+    let more = tokio::time::timeout(Duration::from_millis(300), next_text(&mut lines)).await;
+    assert!(more.is_err(), "unexpected text: {more:?}");
+
+    listener.kill().await.unwrap();
+    let _ = stop.send(());
+    server.await.unwrap().unwrap();
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use node::client::{Client, Device};
+use relay::{run_relay, RelayConfig};
+use utils::Kind;
+
+fn free_addr() -> String {
+    let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    l.local_addr().unwrap().to_string()
+}
+
+async fn connect_when_up(addr: &str, device: Device) -> Client {
+    for _ in 0..100 {
+        if let Ok(c) = Client::connect(addr, "room", device.clone()).await {
+            return c;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("relay on {addr} never came up");
+}
+
+async fn next(client: &mut Client) -> utils::Message {
+    tokio::time::timeout(Duration::from_secs(5), client.recv())
+        .await
+        .expect("nothing received")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn clients_exchange_text_and_files_through_an_embedded_relay() {
+    // Keep history out of the user's data dir.
+    let data = tempfile::tempdir().unwrap();
+    let history = data.path().join("history.jsonl");
+
+    let addr = free_addr();
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let config = RelayConfig {
+        bind: vec![addr.clone()],
+        ..RelayConfig::default()
+    };
+    let server = tokio::spawn(run_relay(config, async {
+        let _ = stopped.await;
+    }));
+
+    let laptop = connect_when_up(&addr, Device::new("dev-laptop", "laptop")).await;
+    let mut laptop = laptop.history_at(&history);
+    laptop.join().await.unwrap();
+    let mut desk = Client::connect(&addr, "room", Device::new("dev-desk", ""))
+        .await
+        .unwrap()
+        .history_at(&history);
+    desk.join().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let sent = laptop.send_text("hello from laptop").await.unwrap();
+    let got = next(&mut desk).await;
+    assert!(matches!(got.kind, Kind::Text));
+    assert_eq!(got.event_id, sent.event_id);
+    assert_eq!(got.payload.as_deref(), Some(&b"hello from laptop"[..]));
+    assert_eq!(got.sender_name.as_deref(), Some("laptop"));
+    assert_eq!(got.sha256, sent.sha256);
+
+    desk.send_file("notes.md", "text/markdown", b"# hi".to_vec())
+        .await
+        .unwrap();
+    let got = next(&mut laptop).await;
+    assert!(matches!(got.kind, Kind::File));
+    assert_eq!(got.name.as_deref(), Some("notes.md"));
+    assert_eq!(got.payload.as_deref(), Some(&b"# hi"[..]));
+    assert_eq!(got.sender_name, None);
+
+    assert!(laptop.send_image("image/x-nope", vec![1]).await.is_err());
+    // A plain connect neither asks for acks nor keeps local copies.
+    assert!(!sent.want_ack);
+    assert!(!data.path().join("received").exists());
+    let wait = laptop.wait_for_ack(&sent.event_id, Duration::from_millis(10));
+    assert!(wait.await.is_err());
+
+    let _ = stop.send(());
+    server.await.unwrap().unwrap();
+}
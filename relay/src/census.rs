@@ -0,0 +1,85 @@
+//! `--census-secs`: a periodic one-line summary of the relay for the journal.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use tokio::sync::mpsc;
+
+use crate::Relay;
+
+/// Traffic counters since start (broadcast frames only; Joins are not forwarded).
+#[derive(Default)]
+pub struct Stats {
+    pub frames_in: AtomicU64,
+    pub bytes_in: AtomicU64,
+    pub frames_out: AtomicU64,
+    pub bytes_out: AtomicU64,
+    /// Frames not delivered because the client's queue was full.
+    pub dropped: AtomicU64,
+}
+
+impl Stats {
+    pub fn received(&self, bytes: usize) {
+        self.frames_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn forwarded(&self, bytes: usize) {
+        self.frames_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// One census line: rooms, connections and traffic as of `now`.
+pub async fn census_line(relay: &Relay, started: Instant, now: Instant) -> String {
+    let (rooms, members) = {
+        let map = relay.rooms.lock().await;
+        (map.len(), map.values().map(Vec::len).sum::<usize>())
+    };
+    let stats = &relay.stats;
+    let n = |c: &AtomicU64| c.load(Ordering::Relaxed);
+    format!(
+        "relay: census uptime={}s rooms={} members={} connections={} frames_in={} bytes_in={} \
+         frames_out={} bytes_out={} dropped={}",
+        now.saturating_duration_since(started).as_secs(),
+        rooms,
+        members,
+        relay.connections.load(Ordering::SeqCst),
+        n(&stats.frames_in),
+        n(&stats.bytes_in),
+        n(&stats.frames_out),
+        n(&stats.bytes_out),
+        n(&stats.dropped),
+    )
+}
+
+/// Emit a census line for every tick until the tick sender goes away.
+///
+/// Ticks carry the current time, so tests can drive the task with a fake clock.
+pub async fn census_task(
+    relay: Relay,
+    started: Instant,
+    mut ticks: mpsc::Receiver<Instant>,
+    mut emit: impl FnMut(String),
+) {
+    while let Some(now) = ticks.recv().await {
+        emit(census_line(&relay, started, now).await);
+    }
+}
+
+/// Ticks every `period` from the real clock.
+pub fn interval_ticks(period: std::time::Duration) -> mpsc::Receiver<Instant> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        // The first tick fires immediately; a census of an empty relay says nothing.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if tx.send(Instant::now()).await.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
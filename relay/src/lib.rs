@@ -0,0 +1,1614 @@
+//! The relay: clients join rooms and every frame is forwarded to the other members of its room.
+//!
+//! The `relay` binary is a thin CLI over [`run_relay`]; embedders can run it in their own
+//! process, or drive [`serve`] / [`handle_conn`] on listeners they own.
+
+use anyhow::Context;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinSet;
+
+use utils::Kind;
+use utils::Message;
+use utils::MAX_FRAME_BYTES;
+use utils::{conn_hello, parse_conn_hello, CONN_CODEC_DEFLATE, CONN_CODEC_NONE};
+use utils::{Hello, RelayBanner, CAP_ACK, CAP_CHANNELS, CAP_DEFLATE, CAP_RETAIN, CAP_SCROLLBACK};
+
+mod census;
+mod pace;
+mod peer_log;
+mod retain;
+mod scrollback;
+mod transport;
+
+use census::{census_task, interval_ticks, Stats};
+use pace::AcceptPacer;
+use retain::Retained;
+use scrollback::Scrollback;
+use transport::{accept_ws, Frame};
+
+pub use peer_log::LogPeer;
+pub use retain::RETAIN_MAX_BYTES;
+pub use scrollback::SCROLLBACK_MAX_BYTES;
+pub use transport::Transport;
+
+type Tx = mpsc::Sender<Vec<u8>>;
+type ConnId = u64;
+/// Room name -> its members; shared by every connection of one relay.
+pub type SharedRooms = Arc<Mutex<HashMap<String, Vec<Member>>>>;
+
+/// Default `--client-queue`: frames buffered per client.
+pub const CLIENT_QUEUE: usize = 32;
+
+/// Per-relay resource caps (`None` = unlimited).
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_frame_bytes: usize,
+    pub max_rooms: Option<usize>,
+    pub max_connections: Option<usize>,
+    /// `--accept-rate`: new connections taken on per second, across all listeners.
+    pub accept_rate: Option<u32>,
+    /// Frames buffered per client before broadcasts to it are dropped.
+    pub client_queue: usize,
+    /// Optional cap on the bytes buffered per client, so big frames count for what they weigh.
+    pub client_queue_bytes: Option<usize>,
+    /// `--retain-last`: replay the last frames to joiners, holding at most this many bytes.
+    pub retain_last: Option<usize>,
+    /// `--scrollback`: frames kept per room for `Replay` requests.
+    pub scrollback: Option<usize>,
+    pub scrollback_max_bytes: usize,
+    /// Agree to deflate when a client asks (off with `--no-compress`; it costs CPU per client).
+    pub compress: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_frame_bytes: MAX_FRAME_BYTES,
+            max_rooms: None,
+            max_connections: None,
+            accept_rate: None,
+            client_queue: CLIENT_QUEUE,
+            client_queue_bytes: None,
+            retain_last: None,
+            scrollback: None,
+            scrollback_max_bytes: SCROLLBACK_MAX_BYTES,
+            compress: true,
+        }
+    }
+}
+
+/// State shared by every connection of one relay.
+#[derive(Clone, Default)]
+pub struct Relay {
+    rooms: SharedRooms,
+    /// Live connections, counted from accept until the handler returns.
+    connections: Arc<AtomicUsize>,
+    accept_pacer: Arc<AcceptPacer>,
+    limits: Limits,
+    /// `--log-peer`: how client addresses appear in the logs.
+    log_peer: LogPeer,
+    retained: Arc<Mutex<Retained>>,
+    scrollback: Arc<Mutex<Scrollback>>,
+    stats: Arc<Stats>,
+    started: Started,
+}
+
+impl Relay {
+    pub fn new(limits: Limits, log_peer: LogPeer) -> Self {
+        Self {
+            limits,
+            log_peer,
+            ..Self::default()
+        }
+    }
+
+    /// The rooms and their members, e.g. to see who is connected.
+    pub fn rooms(&self) -> SharedRooms {
+        self.rooms.clone()
+    }
+}
+
+/// When the relay came up (for the uptime in its banner).
+#[derive(Clone, Copy)]
+struct Started(Instant);
+
+impl Default for Started {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+/// Holds one slot of `Relay::connections`; released on drop.
+struct ConnSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A connection registered in a room.
+pub struct Member {
+    id: ConnId,
+    tx: Tx,
+    /// Channels declared in the latest Join; channeled messages only go to subscribers.
+    channels: Vec<String>,
+    /// Bytes sitting in `tx` not yet written to the socket.
+    queued_bytes: Arc<AtomicUsize>,
+    /// The `Hello` frame this connection sent, passed on to peers that speak it too.
+    hello: Option<Vec<u8>>,
+    /// Its `Hello` listed `CAP_ACK`, so it can decode the acks it is forwarded.
+    acks: bool,
+}
+
+impl Member {
+    fn wants(&self, channel: Option<&str>) -> bool {
+        channel.is_none_or(|c| self.channels.iter().any(|s| s == c))
+    }
+
+    /// Queue a frame without waiting; false when it was dropped because the queue is full.
+    fn offer(&self, frame: Vec<u8>, max_bytes: Option<usize>) -> bool {
+        let len = frame.len();
+        let before = self.queued_bytes.fetch_add(len, Ordering::SeqCst);
+        // A frame always fits into an empty queue, so one big bundle can't be starved forever.
+        let over = max_bytes.is_some_and(|max| before > 0 && before + len > max);
+        if over || self.tx.try_send(frame).is_err() {
+            self.queued_bytes.fetch_sub(len, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+}
+
+/// What this relay announces in answer to a client's `Hello`.
+fn relay_hello(limits: &Limits) -> Hello {
+    // Acks are passed on to connections whose `Hello` lists `CAP_ACK`.
+    let mut caps = vec![CAP_CHANNELS, CAP_ACK];
+    if limits.compress {
+        caps.push(CAP_DEFLATE);
+    }
+    if limits.retain_last.is_some() {
+        caps.push(CAP_RETAIN);
+    }
+    if limits.scrollback.is_some() {
+        caps.push(CAP_SCROLLBACK);
+    }
+    Hello::new(&caps)
+}
+
+fn relay_banner(started: Started) -> RelayBanner {
+    RelayBanner {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: started.0.elapsed().as_secs(),
+    }
+}
+
+/// Introduce `me` and the room's other Hello-speaking members to each other.
+///
+/// Members that never sent a `Hello` are older builds that can't decode one; they're skipped.
+fn exchange_hellos(list: &[Member], me: &Member, max_bytes: Option<usize>) {
+    let Some(mine) = &me.hello else {
+        return;
+    };
+    for m in list.iter().filter(|m| m.id != me.id) {
+        if let Some(theirs) = &m.hello {
+            m.offer(mine.clone(), max_bytes);
+            me.offer(theirs.clone(), max_bytes);
+        }
+    }
+}
+
+/// What [`run_relay`] listens on and how it treats clients.
+#[derive(Clone, Debug, Default)]
+pub struct RelayConfig {
+    /// TCP listen addresses (`--bind`).
+    pub bind: Vec<String>,
+    /// WebSocket listen addresses (`--ws-bind`).
+    pub ws_bind: Vec<String>,
+    pub limits: Limits,
+    pub log_peer: LogPeer,
+    /// `--census-secs`: log a rooms/traffic summary this often (0 = off).
+    pub census_secs: u64,
+}
+
+/// Bind every address in `config` and relay until `shutdown` completes.
+///
+/// A bad address fails before anything is served. When `shutdown` fires, the accept loops
+/// stop and open connections are dropped; the call then returns `Ok`.
+pub async fn run_relay(
+    config: RelayConfig,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let RelayConfig {
+        bind,
+        ws_bind,
+        limits,
+        log_peer,
+        census_secs,
+    } = config;
+    anyhow::ensure!(
+        !bind.is_empty() || !ws_bind.is_empty(),
+        "no address to listen on"
+    );
+
+    // Bind everything up front so a bad address fails startup instead of running half-bound.
+    let mut listeners = Vec::with_capacity(bind.len());
+    for addr in &bind {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("bind {addr}"))?;
+        log::info!("listening on {}", addr);
+        listeners.push(listener);
+    }
+    let mut ws_listeners = Vec::with_capacity(ws_bind.len());
+    for addr in &ws_bind {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("bind {addr}"))?;
+        log::info!("listening for WebSocket clients on {}", addr);
+        ws_listeners.push(listener);
+    }
+    let relay = Relay::new(limits, log_peer);
+
+    // One accept loop per listener, all sharing the same rooms and limits. Dropping the set
+    // (on shutdown) aborts them, and with them their connections.
+    let mut tasks = JoinSet::new();
+    for listener in listeners {
+        tasks.spawn(serve(listener, relay.clone()));
+    }
+    for listener in ws_listeners {
+        tasks.spawn(serve_ws(listener, relay.clone()));
+    }
+    if census_secs > 0 {
+        let ticks = interval_ticks(Duration::from_secs(census_secs));
+        let census = census_task(relay.clone(), relay.started.0, ticks, |line| {
+            log::info!("{line}")
+        });
+        tasks.spawn(async move {
+            census.await;
+            Ok(())
+        });
+    }
+    drop(relay);
+
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => {
+                log::info!("relay: shutting down");
+                return Ok(());
+            }
+            res = tasks.join_next() => match res {
+                Some(res) => res.context("accept loop panicked")??,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Accept loop for TCP clients. Connections live as long as the loop: aborting it drops them.
+pub async fn serve(listener: TcpListener, relay: Relay) -> anyhow::Result<()> {
+    let mut conns = JoinSet::new();
+    loop {
+        let (socket, peer) = accept(&listener, &mut conns).await?;
+        let relay = relay.clone();
+        let shown = relay.log_peer.show(peer);
+        log::info!("relay: accept peer={}", shown);
+        relay.accept_pacer.wait(relay.limits.accept_rate).await;
+        conns.spawn(async move {
+            if let Err(e) = handle_conn(socket, relay, peer).await {
+                log::warn!("relay: connection error peer={} err={:?}", shown, e);
+            }
+        });
+    }
+}
+
+/// Accept loop for `--ws-bind`: same rooms as TCP clients, after a WebSocket handshake.
+pub async fn serve_ws(listener: TcpListener, relay: Relay) -> anyhow::Result<()> {
+    let handshake_timeout = Duration::from_secs(10);
+    let mut conns = JoinSet::new();
+    loop {
+        let (socket, peer) = accept(&listener, &mut conns).await?;
+        let relay = relay.clone();
+        let shown = relay.log_peer.show(peer);
+        log::info!("relay: accept websocket peer={}", shown);
+        relay.accept_pacer.wait(relay.limits.accept_rate).await;
+        conns.spawn(async move {
+            let max_frame_bytes = relay.limits.max_frame_bytes;
+            let res =
+                match tokio::time::timeout(handshake_timeout, accept_ws(socket, max_frame_bytes))
+                    .await
+                {
+                    Ok(Ok(ws)) => handle_conn(ws, relay, peer).await,
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Err(anyhow::anyhow!("websocket handshake timed out")),
+                };
+            if let Err(e) = res {
+                log::warn!("relay: connection error peer={} err={:?}", shown, e);
+            }
+        });
+    }
+}
+
+/// The next connection, reaping finished connection tasks while waiting.
+async fn accept(
+    listener: &TcpListener,
+    conns: &mut JoinSet<()>,
+) -> anyhow::Result<(tokio::net::TcpStream, std::net::SocketAddr)> {
+    loop {
+        tokio::select! {
+            res = listener.accept() => return res.context("accept"),
+            Some(_) = conns.join_next(), if !conns.is_empty() => {}
+        }
+    }
+}
+
+/// Send a rejection notice and hang up.
+async fn reject(conn: Transport, reason: &str) -> anyhow::Result<()> {
+    let (_reader, mut writer) = conn.split();
+    writer
+        .send(&Message::new_reject("", reason).to_bytes())
+        .await?;
+    writer.close().await?;
+    Ok(())
+}
+
+/// Serve one client until it disconnects: its joins, room broadcasts, pings and replays.
+pub async fn handle_conn(
+    conn: impl Into<Transport>,
+    relay: Relay,
+    peer: std::net::SocketAddr,
+) -> anyhow::Result<()> {
+    let conn = conn.into();
+    let conn_id: ConnId = rand_conn_id();
+    let Relay {
+        rooms,
+        connections,
+        accept_pacer: _,
+        limits,
+        log_peer,
+        retained,
+        scrollback,
+        stats,
+        started,
+    } = relay;
+    let peer = log_peer.show(peer);
+    let max_frame_bytes = limits.max_frame_bytes;
+    let live = connections.fetch_add(1, Ordering::SeqCst);
+    let _slot = ConnSlot(connections);
+    if let Some(max) = limits.max_connections.filter(|&max| live >= max) {
+        log::warn!(
+            "relay: connection limit reached peer={} conn_id={} max={}",
+            peer,
+            conn_id,
+            max
+        );
+        return reject(conn, "too many connections").await;
+    }
+    let (mut reader, mut writer_half) = conn.split();
+
+    // If a peer goes away without FIN/RST (e.g. network loss), we may not notice promptly.
+    // A conservative idle timeout plus client heartbeat keeps the room membership fresh.
+    let idle_timeout = Duration::from_secs(120);
+
+    // `--compress` clients open with a hello and wait for the answer before sending anything
+    // else, so the codec switch happens at the same point in both directions.
+    let mut pending = Some(tokio::time::timeout(idle_timeout, reader.recv(max_frame_bytes)).await);
+    if let Some(Ok(Ok(Frame::Data(buf)))) = &pending {
+        if let Some(asked) = parse_conn_hello(buf) {
+            let codec =
+                if asked == CONN_CODEC_DEFLATE && limits.compress && writer_half.can_deflate() {
+                    CONN_CODEC_DEFLATE
+                } else {
+                    CONN_CODEC_NONE
+                };
+            writer_half.send(&conn_hello(codec)).await?;
+            if codec == CONN_CODEC_DEFLATE {
+                reader = reader.deflate();
+                writer_half = writer_half.deflate();
+            }
+            log::info!(
+                "relay: hello peer={} conn_id={} asked={} codec={}",
+                peer,
+                conn_id,
+                asked,
+                codec
+            );
+            pending = None;
+        }
+    }
+
+    // create outbound channel
+    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(limits.client_queue);
+    let queued_bytes = Arc::new(AtomicUsize::new(0));
+    // writer task
+    let writer_queued = queued_bytes.clone();
+    let writer = tokio::spawn(async move {
+        while let Some(buf) = rx.recv().await {
+            // Saturating: our own rejection notice is sent without going through `offer`.
+            let _ = writer_queued.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |q| {
+                Some(q.saturating_sub(buf.len()))
+            });
+            if writer_half.send(&buf).await.is_err() {
+                break;
+            }
+        }
+        let _ = writer_half.close().await;
+    });
+
+    // read loop
+    let mut registered_room: Option<String> = None;
+    // Kept across room switches, so peers in the new room learn about us too.
+    let mut my_hello: Option<Vec<u8>> = None;
+    let mut my_acks = false;
+    loop {
+        let next = match pending.take() {
+            Some(first) => first,
+            None => tokio::time::timeout(idle_timeout, reader.recv(max_frame_bytes)).await,
+        };
+        let buf = match next {
+            Ok(frame) => match frame? {
+                Frame::Data(buf) => buf,
+                Frame::Closed => break,
+                Frame::TooLarge(len) => {
+                    // Never allocate based on an untrusted length; drop the connection instead.
+                    log::error!(
+                        "relay: frame too large peer={} conn_id={} len={} max={}",
+                        peer,
+                        conn_id,
+                        len,
+                        max_frame_bytes
+                    );
+                    break;
+                }
+            },
+            Err(_) => {
+                log::warn!(
+                    "relay: idle timeout peer={} conn_id={} ({}s)",
+                    peer,
+                    conn_id,
+                    idle_timeout.as_secs()
+                );
+                break;
+            }
+        };
+        let len = buf.len();
+        let msg = match Message::try_from_bytes(&buf) {
+            Ok(m) => m,
+            Err(e) => {
+                let prefix_len = buf.len().min(16);
+                log::warn!(
+                    "relay: dropping undecodable frame peer={} conn_id={} len={} prefix={:02x?} err={}",
+                    peer,
+                    conn_id,
+                    len,
+                    &buf[..prefix_len],
+                    e
+                );
+                // The length prefix kept us in sync: skip just this frame, keep the connection.
+                continue;
+            }
+        };
+
+        // Liveness checks are answered directly; they neither join nor reach a room.
+        match msg.kind {
+            Kind::Ping => {
+                log::debug!("relay: ping peer={} conn_id={}", peer, conn_id);
+                let _ = tx.send(Message::new_pong(&msg).to_bytes()).await;
+                continue;
+            }
+            Kind::Pong => continue,
+            _ => {}
+        }
+
+        // register sender into room when first message arrives;
+        // a Join for another room moves the registration (room switch without reconnecting).
+        let switch_room = matches!(msg.kind, Kind::Join)
+            && registered_room.as_deref().is_some_and(|r| r != msg.room);
+        if registered_room.is_none() || switch_room {
+            let r = msg.room.clone();
+            let mut map = rooms.lock().await;
+            if let Some(old) = registered_room.take() {
+                if let Some(list) = map.get_mut(&old) {
+                    list.retain(|m| m.id != conn_id && !m.tx.is_closed());
+                    if list.is_empty() {
+                        map.remove(&old);
+                    }
+                }
+                log::info!(
+                    "relay: leave peer={} conn_id={} room={}",
+                    peer,
+                    conn_id,
+                    old
+                );
+            }
+            if let Some(max) = limits
+                .max_rooms
+                .filter(|&max| !map.contains_key(&r) && map.len() >= max)
+            {
+                drop(map);
+                log::warn!(
+                    "relay: room limit reached peer={} conn_id={} room={} max={}",
+                    peer,
+                    conn_id,
+                    r,
+                    max
+                );
+                let _ = tx
+                    .send(Message::new_reject(&r, "too many rooms").to_bytes())
+                    .await;
+                break;
+            }
+            let member = Member {
+                id: conn_id,
+                tx: tx.clone(),
+                channels: msg.subscribed_channels(),
+                queued_bytes: queued_bytes.clone(),
+                hello: my_hello.clone(),
+                acks: my_acks,
+            };
+            let list = map.entry(r.clone()).or_default();
+            // Only a Join asks for the catch-up: a client that starts sending right away just
+            // gets registered.
+            let join = matches!(msg.kind, Kind::Join);
+            if join {
+                exchange_hellos(list, &member, limits.client_queue_bytes);
+            }
+            // Late joiner: catch up on what the room copied last (`--retain-last`).
+            if join && limits.retain_last.is_some() {
+                let frames = retained.lock().await.replay(&r, |c| member.wants(c));
+                for frame in frames {
+                    member.offer(frame, limits.client_queue_bytes);
+                }
+            }
+            list.push(member);
+            registered_room = Some(r);
+            log::info!(
+                "relay: register peer={} conn_id={} room={} channels={:?}",
+                peer,
+                conn_id,
+                registered_room.as_deref().unwrap_or("?"),
+                msg.subscribed_channels()
+            );
+        } else if matches!(msg.kind, Kind::Join) {
+            // Re-join of the same room: refresh the subscriptions.
+            let mut map = rooms.lock().await;
+            if let Some(m) = map
+                .get_mut(&msg.room)
+                .and_then(|list| list.iter_mut().find(|m| m.id == conn_id))
+            {
+                m.channels = msg.subscribed_channels();
+            }
+        }
+
+        if let Some(hello) = msg.hello() {
+            log::info!(
+                "relay: hello peer={} conn_id={} device_id={} version={} caps={:?}",
+                peer,
+                conn_id,
+                msg.device_id,
+                hello.version,
+                hello.caps
+            );
+            if my_hello.is_none() {
+                let answer = Message::new_relay_hello(
+                    &msg.room,
+                    &relay_hello(&limits),
+                    &relay_banner(started),
+                );
+                let _ = tx.send(answer.to_bytes()).await;
+            }
+            // Peers get it as a `Kind::Hello`, however it came in.
+            my_hello = Some(if matches!(msg.kind, Kind::Join) {
+                let mut fwd = Message::new_hello(&msg.device_id, &msg.room, &hello);
+                fwd.sender_name = msg.sender_name.clone();
+                fwd.to_bytes()
+            } else {
+                buf
+            });
+            my_acks = hello.has(CAP_ACK);
+            let mut map = rooms.lock().await;
+            if let Some(list) = registered_room.as_deref().and_then(|r| map.get_mut(r)) {
+                if let Some(me) = list.iter_mut().find(|m| m.id == conn_id) {
+                    me.hello = my_hello.clone();
+                    me.acks = my_acks;
+                }
+                if let Some(me) = list.iter().find(|m| m.id == conn_id) {
+                    exchange_hellos(list, me, limits.client_queue_bytes);
+                }
+            }
+            continue;
+        }
+
+        if matches!(msg.kind, Kind::Replay) {
+            let room = registered_room.clone().unwrap_or_default();
+            let channels = {
+                let map = rooms.lock().await;
+                map.get(&room)
+                    .and_then(|list| list.iter().find(|m| m.id == conn_id))
+                    .map(|m| m.channels.clone())
+                    .unwrap_or_default()
+            };
+            let frames = scrollback
+                .lock()
+                .await
+                .replay(&room, |c| c.is_none_or(|c| channels.iter().any(|s| s == c)));
+            log::info!(
+                "relay: replay peer={} conn_id={} room={} frames={}",
+                peer,
+                conn_id,
+                room,
+                frames.len()
+            );
+            let mut end = Message::new_replay("relay", &room);
+            end.size = frames.len();
+            // Waits for queue space: the client asked for all of it.
+            for frame in frames {
+                let _ = tx.send(frame).await;
+            }
+            let _ = tx.send(end.to_bytes()).await;
+            continue;
+        }
+
+        // broadcast to room
+        if matches!(msg.kind, Kind::Join) {
+            continue;
+        }
+        stats.received(buf.len());
+        let room = msg.room.clone();
+        let channel = msg
+            .channel
+            .as_deref()
+            .map(str::trim)
+            .filter(|c| !c.is_empty());
+        // Forward original bytes as-is (avoid re-serialization changing the wire format).
+        let out = buf;
+        // Acks only matter to a sender waiting right now; they're never kept for replay.
+        let ack = matches!(msg.kind, Kind::Ack);
+        if let Some(max) = limits.retain_last {
+            retained
+                .lock()
+                .await
+                .remember(&room, &msg.kind, channel, &out, max);
+        }
+        if let Some(n) = limits.scrollback.filter(|_| !ack) {
+            scrollback
+                .lock()
+                .await
+                .push(&room, channel, &out, n, limits.scrollback_max_bytes);
+        }
+        let mut map = rooms.lock().await;
+        if let Some(list) = map.get_mut(&room) {
+            // Drop only closed channels (a full channel should not kick the client).
+            list.retain(|m| !m.tx.is_closed());
+            let reaches = |m: &Member| m.id != conn_id && m.wants(channel) && (!ack || m.acks);
+            let targets = list.iter().filter(|m| reaches(m)).count();
+            log::debug!(
+                "relay: broadcast room={} channel={:?} from_conn={} peer={} targets={} bytes={}",
+                room,
+                channel,
+                conn_id,
+                peer,
+                targets,
+                out.len()
+            );
+            for m in list.iter() {
+                if !reaches(m) {
+                    continue;
+                }
+                if m.offer(out.clone(), limits.client_queue_bytes) {
+                    stats.forwarded(out.len());
+                } else {
+                    stats
+                        .dropped
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    log::debug!(
+                        "relay: client queue full, dropping frame room={} to_conn={} bytes={}",
+                        room,
+                        m.id,
+                        out.len()
+                    );
+                }
+            }
+        }
+    }
+
+    // remove from rooms first: the member entry holds a clone of `tx`,
+    // which would otherwise keep the writer task alive forever.
+    if let Some(room) = registered_room.clone() {
+        let mut map = rooms.lock().await;
+        if let Some(list) = map.get_mut(&room) {
+            // remove ourselves and closed channels
+            list.retain(|m| m.id != conn_id && !m.tx.is_closed());
+            // Drop the room itself once empty, so `--max-rooms` counts only live rooms.
+            if list.is_empty() {
+                map.remove(&room);
+            }
+        }
+    }
+    // cleanup writer
+    drop(tx);
+    let _ = writer.await;
+
+    log::info!(
+        "relay: disconnect peer={} conn_id={} room={:?}",
+        peer,
+        conn_id,
+        registered_room
+    );
+
+    Ok(())
+}
+
+fn rand_conn_id() -> ConnId {
+    // Good enough for a prototype: a random-ish u64 from current time.
+    // (We avoid adding an extra dependency; collisions are extremely unlikely here.)
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    #[tokio::test]
+    async fn oversized_frame_length_closes_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let relay = Relay {
+            limits: Limits {
+                max_frame_bytes: 1024,
+                ..Limits::default()
+            },
+            ..Relay::default()
+        };
+        let server = tokio::spawn(async move {
+            let (socket, peer) = listener.accept().await.unwrap();
+            handle_conn(socket, relay, peer).await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_u32(u32::MAX).await.unwrap();
+
+        // The relay must hang up instead of trying to allocate 4 GiB.
+        let mut tmp = [0u8; 1];
+        let n = tokio::time::timeout(Duration::from_secs(5), client.read(&mut tmp))
+            .await
+            .expect("relay did not close the connection")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        server.await.unwrap().unwrap();
+    }
+
+    async fn send_msg<W: AsyncWrite + Unpin>(s: &mut W, msg: &Message) {
+        let buf = msg.to_bytes();
+        s.write_u32(buf.len() as u32).await.unwrap();
+        s.write_all(&buf).await.unwrap();
+        s.flush().await.unwrap();
+    }
+
+    async fn recv_msg<R: AsyncRead + Unpin>(s: &mut R) -> Option<Message> {
+        let len = tokio::time::timeout(Duration::from_millis(500), s.read_u32())
+            .await
+            .ok()?
+            .ok()?;
+        let mut buf = vec![0u8; len as usize];
+        s.read_exact(&mut buf).await.ok()?;
+        Message::try_from_bytes(&buf).ok()
+    }
+
+    #[tokio::test]
+    async fn join_for_new_room_redirects_subsequent_sends() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let relay = Relay::default();
+        let rooms = relay.rooms.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, peer) = listener.accept().await.unwrap();
+                tokio::spawn(handle_conn(socket, relay.clone(), peer));
+            }
+        });
+
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        let mut old_peer = TcpStream::connect(addr).await.unwrap();
+        let mut new_peer = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "old")).await;
+        send_msg(&mut old_peer, &Message::new_join("o", "old")).await;
+        send_msg(&mut new_peer, &Message::new_join("n", "new")).await;
+        // Let the relay register everyone before switching.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Same connection re-joins another room, then sends.
+        send_msg(&mut a, &Message::new_join("a", "new")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send_msg(&mut a, &Message::new_text("a", "new", "hello")).await;
+
+        let got = recv_msg(&mut new_peer)
+            .await
+            .expect("new room peer got nothing");
+        assert_eq!(got.room, "new");
+        assert_eq!(got.payload.as_deref(), Some(&b"hello"[..]));
+
+        // A is no longer a member of "old": broadcasts there must not reach it.
+        send_msg(&mut old_peer, &Message::new_text("o", "old", "stale")).await;
+        assert!(recv_msg(&mut a).await.is_none());
+        assert_eq!(rooms.lock().await.get("old").map(|l| l.len()), Some(1));
+    }
+
+    #[tokio::test]
+    async fn channeled_message_reaches_only_subscribers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let relay = Relay::default();
+        tokio::spawn(async move {
+            loop {
+                let (socket, peer) = listener.accept().await.unwrap();
+                tokio::spawn(handle_conn(socket, relay.clone(), peer));
+            }
+        });
+
+        let join = |dev: &str, channels: Option<&str>| {
+            let mut m = Message::new_join(dev, "room");
+            m.channel = channels.map(str::to_string);
+            m
+        };
+        let mut sender = TcpStream::connect(addr).await.unwrap();
+        let mut photos = TcpStream::connect(addr).await.unwrap();
+        let mut plain = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut sender, &join("s", None)).await;
+        send_msg(&mut photos, &join("p", Some("notes,photos"))).await;
+        send_msg(&mut plain, &join("n", None)).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut m = Message::new_text("s", "room", "for photos");
+        m.channel = Some("photos".to_string());
+        send_msg(&mut sender, &m).await;
+        let got = recv_msg(&mut photos).await.expect("subscriber got nothing");
+        assert_eq!(got.channel.as_deref(), Some("photos"));
+        assert!(recv_msg(&mut plain).await.is_none());
+
+        // No channel: everyone in the room, as before.
+        send_msg(&mut sender, &Message::new_text("s", "room", "all")).await;
+        assert!(recv_msg(&mut photos).await.is_some());
+        assert!(recv_msg(&mut plain).await.is_some());
+
+        // A later Join in the same room replaces the subscriptions.
+        send_msg(&mut plain, &join("n", Some("photos"))).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send_msg(&mut sender, &m).await;
+        assert!(recv_msg(&mut plain).await.is_some());
+    }
+
+    async fn spawn_relay(limits: Limits) -> (std::net::SocketAddr, Relay) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let relay = Relay {
+            limits,
+            ..Relay::default()
+        };
+        tokio::spawn(serve(listener, relay.clone()));
+        (addr, relay)
+    }
+
+    /// Expect a rejection notice followed by EOF.
+    async fn expect_rejected(s: &mut TcpStream) -> String {
+        let msg = recv_msg(s).await.expect("no rejection frame");
+        let reason = msg.rejection().expect("not a rejection");
+        let mut tmp = [0u8; 1];
+        let n = tokio::time::timeout(Duration::from_secs(5), s.read(&mut tmp))
+            .await
+            .expect("relay did not close the connection")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        reason
+    }
+
+    #[tokio::test]
+    async fn corrupt_frame_is_dropped_without_closing() {
+        let (addr, _relay) = spawn_relay(Limits::default()).await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        let mut b = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut bad = Message::new_text("a", "room", "garbled").to_bytes_checked();
+        let i = bad.len() - 6;
+        bad[i] ^= 0x01;
+        a.write_u32(bad.len() as u32).await.unwrap();
+        a.write_all(&bad).await.unwrap();
+        assert!(recv_msg(&mut b).await.is_none());
+
+        send_msg(&mut a, &Message::new_text("a", "room", "fine")).await;
+        let got = recv_msg(&mut b).await.expect("connection was dropped");
+        assert_eq!(got.payload.as_deref(), Some(&b"fine"[..]));
+    }
+
+    #[tokio::test]
+    async fn clients_on_different_listeners_share_rooms() {
+        let relay = Relay::default();
+        let mut addrs = Vec::new();
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            addrs.push(listener.local_addr().unwrap());
+            tokio::spawn(serve(listener, relay.clone()));
+        }
+        assert_ne!(addrs[0], addrs[1]);
+
+        let mut a = TcpStream::connect(addrs[0]).await.unwrap();
+        let mut b = TcpStream::connect(addrs[1]).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            relay.rooms.lock().await.get("room").map(|l| l.len()),
+            Some(2)
+        );
+
+        send_msg(&mut a, &Message::new_text("a", "room", "across")).await;
+        let got = recv_msg(&mut b).await.expect("b got nothing");
+        assert_eq!(got.payload.as_deref(), Some(&b"across"[..]));
+        send_msg(&mut b, &Message::new_text("b", "room", "back")).await;
+        assert!(recv_msg(&mut a).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn connection_over_limit_is_rejected() {
+        let (addr, relay) = spawn_relay(Limits {
+            max_connections: Some(2),
+            ..Limits::default()
+        })
+        .await;
+
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        let mut b = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut c = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(expect_rejected(&mut c).await, "too many connections");
+
+        // Existing connections are unaffected.
+        send_msg(&mut a, &Message::new_text("a", "room", "still here")).await;
+        let got = recv_msg(&mut b).await.expect("b got nothing");
+        assert_eq!(got.payload.as_deref(), Some(&b"still here"[..]));
+
+        // The rejected connection gave its slot back; a disconnect frees one too.
+        drop(a);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(relay.connections.load(Ordering::SeqCst), 1);
+        let mut d = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut d, &Message::new_join("d", "room")).await;
+        send_msg(&mut d, &Message::new_text("d", "room", "hi")).await;
+        assert!(recv_msg(&mut b).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn connect_flood_is_paced_to_the_accept_rate() {
+        let (addr, _relay) = spawn_relay(Limits {
+            accept_rate: Some(20),
+            ..Limits::default()
+        })
+        .await;
+        let start = Instant::now();
+        let mut clients = Vec::new();
+        for i in 0..10 {
+            clients.push(tokio::spawn(async move {
+                let mut s = TcpStream::connect(addr).await.unwrap();
+                send_msg(&mut s, &Message::new_ping(&format!("p{i}"))).await;
+                let len = tokio::time::timeout(Duration::from_secs(5), s.read_u32())
+                    .await
+                    .expect("no pong")
+                    .unwrap();
+                assert!(len > 0);
+                start.elapsed()
+            }));
+        }
+        // Every connection is answered: they are only delayed, not turned away.
+        let mut answered = Vec::new();
+        for c in clients {
+            answered.push(c.await.unwrap());
+        }
+        answered.sort();
+        // 20 per second: one handler every 50 ms, so the tenth starts 450 ms in at the
+        // earliest (allowing for timer granularity).
+        assert!(answered[0] < Duration::from_millis(400), "{answered:?}");
+        assert!(answered[9] >= Duration::from_millis(440), "{answered:?}");
+    }
+
+    #[tokio::test]
+    async fn room_over_limit_is_rejected() {
+        let (addr, relay) = spawn_relay(Limits {
+            max_rooms: Some(1),
+            ..Limits::default()
+        })
+        .await;
+
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "one")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut spray = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut spray, &Message::new_join("x", "two")).await;
+        assert_eq!(expect_rejected(&mut spray).await, "too many rooms");
+        assert!(!relay.rooms.lock().await.contains_key("two"));
+
+        // Joining the existing room still works.
+        let mut b = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut b, &Message::new_join("b", "one")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send_msg(&mut a, &Message::new_text("a", "one", "ok")).await;
+        assert!(recv_msg(&mut b).await.is_some());
+
+        // Once the room empties it no longer counts.
+        drop(a);
+        drop(b);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut c = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut c, &Message::new_join("c", "two")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(relay.rooms.lock().await.contains_key("two"));
+    }
+
+    /// Push `frames` 32 KiB frames at a receiver that isn't reading, then count what arrives.
+    async fn burst_delivered(limits: Limits, frames: usize) -> usize {
+        let (addr, _relay) = spawn_relay(limits).await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        // Small receive window, so the kernel buffers can't absorb the burst for us.
+        let sock = tokio::net::TcpSocket::new_v4().unwrap();
+        sock.set_recv_buffer_size(64 * 1024).unwrap();
+        let mut b = sock.connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for i in 0..frames {
+            let bytes = vec![i as u8; 32 * 1024];
+            send_msg(
+                &mut a,
+                &Message::new_file("a", "room", "x.bin", "application/octet-stream", bytes),
+            )
+            .await;
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        let mut got = 0;
+        while recv_msg(&mut b).await.is_some() {
+            got += 1;
+        }
+        got
+    }
+
+    #[tokio::test]
+    async fn client_queue_size_decides_burst_drops() {
+        let frames = 300;
+        assert!(burst_delivered(Limits::default(), frames).await < frames);
+
+        let big = Limits {
+            client_queue: 1024,
+            ..Limits::default()
+        };
+        assert_eq!(burst_delivered(big, frames).await, frames);
+
+        // Same frame count, but the byte cap still bounds what one client may buffer.
+        let byte_capped = Limits {
+            client_queue: 1024,
+            client_queue_bytes: Some(256 * 1024),
+            ..Limits::default()
+        };
+        assert!(burst_delivered(byte_capped, frames).await < frames);
+    }
+
+    #[tokio::test]
+    async fn late_joiner_gets_the_retained_last_message() {
+        let (addr, _relay) = spawn_relay(Limits {
+            retain_last: Some(4096),
+            ..Limits::default()
+        })
+        .await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut a, &Message::new_text("a", "room", "old")).await;
+        send_msg(&mut a, &Message::new_text("a", "room", "current")).await;
+        send_msg(
+            &mut a,
+            &Message::new_image("a", "room", "image/png", vec![7; 64]),
+        )
+        .await;
+        // Over the retain budget: not kept, and the earlier image is stale now too.
+        send_msg(
+            &mut a,
+            &Message::new_image("a", "room", "image/png", vec![8; 8192]),
+        )
+        .await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Joins after everyone else left: still gets the latest text, and only that.
+        drop(a);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut late = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut late, &Message::new_join("late", "room")).await;
+        let got = recv_msg(&mut late).await.expect("late joiner got nothing");
+        assert_eq!(got.payload.as_deref(), Some(&b"current"[..]));
+        assert!(recv_msg(&mut late).await.is_none());
+
+        // Other rooms start empty.
+        let mut other = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut other, &Message::new_join("o", "other")).await;
+        assert!(recv_msg(&mut other).await.is_none());
+
+        // Sending without a Join registers the connection but replays nothing.
+        let mut sender = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut sender, &Message::new_text("s", "room", "new")).await;
+        assert!(recv_msg(&mut sender).await.is_none());
+
+        // Off by default.
+        let (addr, _relay) = spawn_relay(Limits::default()).await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_text("a", "room", "hi")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut late = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut late, &Message::new_join("late", "room")).await;
+        assert!(recv_msg(&mut late).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn client_requesting_replay_gets_the_scrollback_in_order() {
+        let (addr, _relay) = spawn_relay(Limits {
+            scrollback: Some(2),
+            ..Limits::default()
+        })
+        .await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        for text in ["one", "two", "three"] {
+            send_msg(&mut a, &Message::new_text("a", "room", text)).await;
+        }
+        send_msg(&mut a, &Message::new_text("a", "other", "elsewhere")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Nothing is pushed on join; only an explicit request streams it.
+        let mut b = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        assert!(recv_msg(&mut b).await.is_none());
+        send_msg(&mut b, &Message::new_replay("b", "room")).await;
+        let mut got = Vec::new();
+        let end = loop {
+            let m = recv_msg(&mut b).await.expect("replay ended early");
+            if matches!(m.kind, Kind::Replay) {
+                break m;
+            }
+            got.push(m.payload.unwrap());
+        };
+        assert_eq!(got, [b"two".to_vec(), b"three".to_vec()]);
+        assert_eq!(end.size, 2);
+        // The answer goes to the requester only.
+        assert!(recv_msg(&mut a).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn ping_gets_a_timely_pong_without_joining_a_room() {
+        let (addr, relay) = spawn_relay(Limits::default()).await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+
+        let mut p = TcpStream::connect(addr).await.unwrap();
+        let ping = Message::new_ping("p");
+        send_msg(&mut p, &ping).await;
+        let pong = recv_msg(&mut p).await.expect("no pong");
+        assert!(matches!(pong.kind, Kind::Pong));
+        assert_eq!(pong.event_id, ping.event_id);
+        // Answered directly: nothing reaches the room, and the pinger is in none.
+        assert!(recv_msg(&mut a).await.is_none());
+        assert_eq!(relay.rooms.lock().await.len(), 1);
+
+        // The blocking helper the UIs and `node ping` use.
+        let target = addr.to_string();
+        let (sock, rtt) = tokio::task::spawn_blocking(move || {
+            utils::probe::ping_relay(&target, Duration::from_secs(2))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(sock, addr);
+        assert!(rtt < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn websocket_and_tcp_clients_share_a_room() {
+        let (tcp_addr, relay) = spawn_relay(Limits::default()).await;
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_addr = ws_listener.local_addr().unwrap();
+        tokio::spawn(serve_ws(ws_listener, relay));
+
+        // Same frames as on TCP: u32 length prefix + bincode message, one per binary message.
+        let ws_frame = |msg: &Message| {
+            let buf = msg.to_bytes();
+            let mut data = (buf.len() as u32).to_be_bytes().to_vec();
+            data.extend_from_slice(&buf);
+            WsMessage::Binary(data)
+        };
+
+        let socket = TcpStream::connect(ws_addr).await.unwrap();
+        let (mut ws, _) = tokio_tungstenite::client_async(format!("ws://{ws_addr}/"), socket)
+            .await
+            .unwrap();
+        let mut tcp = TcpStream::connect(tcp_addr).await.unwrap();
+        ws.send(ws_frame(&Message::new_join("browser", "room")))
+            .await
+            .unwrap();
+        send_msg(&mut tcp, &Message::new_join("desktop", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // TCP -> WebSocket.
+        send_msg(&mut tcp, &Message::new_text("desktop", "room", "from tcp")).await;
+        let got = tokio::time::timeout(Duration::from_secs(2), ws.next())
+            .await
+            .expect("websocket client got nothing")
+            .unwrap()
+            .unwrap();
+        let WsMessage::Binary(data) = got else {
+            panic!("expected a binary message, got {got:?}");
+        };
+        let (prefix, body) = data.split_at(4);
+        assert_eq!(
+            u32::from_be_bytes(prefix.try_into().unwrap()) as usize,
+            body.len()
+        );
+        let msg = Message::try_from_bytes(body).unwrap();
+        assert_eq!(msg.payload.as_deref(), Some(&b"from tcp"[..]));
+
+        // WebSocket -> TCP.
+        ws.send(ws_frame(&Message::new_text("browser", "room", "from ws")))
+            .await
+            .unwrap();
+        let got = recv_msg(&mut tcp).await.expect("tcp client got nothing");
+        assert_eq!(got.device_id, "browser");
+        assert_eq!(got.payload.as_deref(), Some(&b"from ws"[..]));
+    }
+
+    #[tokio::test]
+    async fn census_line_summarizes_rooms_and_traffic() {
+        let (addr, relay) = spawn_relay(Limits::default()).await;
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        let mut b = TcpStream::connect(addr).await.unwrap();
+        let mut c = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        send_msg(&mut c, &Message::new_join("c", "other")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let msg = Message::new_text("a", "room", "hello");
+        send_msg(&mut a, &msg).await;
+        assert!(recv_msg(&mut b).await.is_some());
+
+        // Fake clock: the task reports whatever time the ticks carry.
+        let started = std::time::Instant::now();
+        let (tick, ticks) = mpsc::channel(4);
+        let (line_tx, mut lines) = mpsc::unbounded_channel();
+        let task = tokio::spawn(census_task(relay, started, ticks, move |l| {
+            let _ = line_tx.send(l);
+        }));
+        tick.send(started + Duration::from_secs(90)).await.unwrap();
+        let line = lines.recv().await.unwrap();
+        let size = msg.to_bytes().len();
+        for field in [
+            "uptime=90s".to_string(),
+            "rooms=2".to_string(),
+            "members=3".to_string(),
+            "connections=3".to_string(),
+            "frames_in=1".to_string(),
+            format!("bytes_in={size}"),
+            "frames_out=1".to_string(),
+            format!("bytes_out={size}"),
+            "dropped=0".to_string(),
+        ] {
+            assert!(
+                line.split(' ').any(|f| f == field),
+                "{field} missing: {line}"
+            );
+        }
+
+        // One line per tick; the task ends with its clock.
+        tick.send(started + Duration::from_secs(150)).await.unwrap();
+        assert!(lines.recv().await.unwrap().contains("uptime=150s"));
+        drop(tick);
+        task.await.unwrap();
+    }
+
+    /// Connect with the `--compress` hello; returns the codec the relay agreed to.
+    async fn hello_client(
+        addr: std::net::SocketAddr,
+    ) -> (
+        u8,
+        tokio::net::tcp::OwnedReadHalf,
+        tokio::net::tcp::OwnedWriteHalf,
+    ) {
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        let hello = conn_hello(CONN_CODEC_DEFLATE);
+        s.write_u32(hello.len() as u32).await.unwrap();
+        s.write_all(&hello).await.unwrap();
+        let len = s.read_u32().await.unwrap() as usize;
+        let mut answer = vec![0u8; len];
+        s.read_exact(&mut answer).await.unwrap();
+        let codec = parse_conn_hello(&answer).expect("no hello answer");
+        let (r, w) = s.into_split();
+        (codec, r, w)
+    }
+
+    #[tokio::test]
+    async fn deflate_clients_interoperate_with_plain_ones() {
+        use async_compression::tokio::bufread::DeflateDecoder;
+        use async_compression::tokio::write::DeflateEncoder;
+
+        let (addr, _relay) = spawn_relay(Limits::default()).await;
+        let mut deflated = Vec::new();
+        for dev in ["a", "b"] {
+            let (codec, r, w) = hello_client(addr).await;
+            assert_eq!(codec, CONN_CODEC_DEFLATE);
+            let r = DeflateDecoder::new(tokio::io::BufReader::new(r));
+            let mut w = DeflateEncoder::new(w);
+            send_msg(&mut w, &Message::new_join(dev, "room")).await;
+            deflated.push((r, w));
+        }
+        let mut plain = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut plain, &Message::new_join("p", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let text = "clipboard ".repeat(1000);
+        let (_, a_w) = &mut deflated[0];
+        send_msg(a_w, &Message::new_text("a", "room", &text)).await;
+        let (b_r, _) = &mut deflated[1];
+        let got = recv_msg(b_r).await.expect("deflate peer got nothing");
+        assert_eq!(got.payload.as_deref(), Some(text.as_bytes()));
+        let got = recv_msg(&mut plain).await.expect("plain peer got nothing");
+        assert_eq!(got.payload.as_deref(), Some(text.as_bytes()));
+
+        send_msg(&mut plain, &Message::new_text("p", "room", "plain")).await;
+        for (r, _) in deflated.iter_mut() {
+            let got = recv_msg(r)
+                .await
+                .expect("deflate peer missed the plain sender");
+            assert_eq!(got.payload.as_deref(), Some(&b"plain"[..]));
+        }
+
+        // A relay that declines still answers, and the connection stays plain.
+        let (addr, _relay) = spawn_relay(Limits {
+            compress: false,
+            ..Limits::default()
+        })
+        .await;
+        let (codec, mut r, mut w) = hello_client(addr).await;
+        assert_eq!(codec, CONN_CODEC_NONE);
+        let mut other = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut w, &Message::new_join("a", "room")).await;
+        send_msg(&mut other, &Message::new_join("o", "room")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send_msg(&mut other, &Message::new_text("o", "room", "hi")).await;
+        assert!(recv_msg(&mut r).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn peers_exchange_hello_and_agree_on_common_caps() {
+        use utils::{CAP_EXTRA_MIME, PROTOCOL_VERSION};
+
+        let (addr, _relay) = spawn_relay(Limits::default()).await;
+        // An older client: never says hello, so it must never get one.
+        let mut old = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut old, &Message::new_join("old", "room")).await;
+
+        let a_hello = Hello::new(&[CAP_DEFLATE, CAP_EXTRA_MIME]);
+        let mut a = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut a, &Message::new_hello("a", "room", &a_hello)).await;
+        let from_relay = recv_msg(&mut a).await.expect("relay answers hello");
+        assert_eq!(from_relay.device_id, "relay");
+        let relay_caps = from_relay.hello().unwrap();
+        assert!(relay_caps.has(CAP_DEFLATE) && !relay_caps.has(CAP_RETAIN));
+
+        let b_hello = Hello {
+            version: PROTOCOL_VERSION + 1,
+            caps: vec![CAP_EXTRA_MIME.to_string(), "future-thing".to_string()],
+        };
+        // Announced in the Join itself, the way nodes do it.
+        let mut b = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut b, &Message::new_join_hello("b", "room", &b_hello)).await;
+
+        // Each learns the other's capabilities, whichever said hello first.
+        let mut seen_by_b = Vec::new();
+        while let Some(m) = recv_msg(&mut b).await {
+            seen_by_b.push((m.device_id.clone(), m.hello().unwrap()));
+        }
+        let (_, a_seen) = seen_by_b
+            .iter()
+            .find(|(d, _)| d == "a")
+            .expect("b got a's hello");
+        let got = recv_msg(&mut a).await.expect("a got b's hello");
+        assert_eq!(got.device_id, "b");
+        assert!(matches!(got.kind, Kind::Hello));
+        let b_seen = got.hello().unwrap();
+
+        let common = a_seen.common(&b_hello);
+        assert_eq!(common, b_seen.common(&a_hello));
+        assert_eq!(common.version, PROTOCOL_VERSION);
+        assert_eq!(common.caps, [CAP_EXTRA_MIME]);
+
+        // Clipboard frames still flow to everyone; hellos never reach the old client.
+        send_msg(&mut a, &Message::new_text("a", "room", "hi")).await;
+        let got = recv_msg(&mut old).await.expect("old client got the text");
+        assert!(matches!(got.kind, Kind::Text));
+        assert!(recv_msg(&mut old).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn acks_reach_the_sender_but_not_older_clients() {
+        let limits = Limits {
+            scrollback: Some(8),
+            ..Limits::default()
+        };
+        let (addr, relay) = spawn_relay(limits).await;
+        let mut old = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut old, &Message::new_join("old", "room")).await;
+        let acking = Hello::new(&[CAP_ACK]);
+        let mut sender = TcpStream::connect(addr).await.unwrap();
+        let mut applier = TcpStream::connect(addr).await.unwrap();
+        for (s, id) in [(&mut sender, "s"), (&mut applier, "a")] {
+            send_msg(s, &Message::new_join_hello(id, "room", &acking)).await;
+        }
+        // The relay's and each other's hellos.
+        while recv_msg(&mut sender).await.is_some() {}
+        while recv_msg(&mut applier).await.is_some() {}
+
+        let text = Message::new_text("s", "room", "hi");
+        send_msg(&mut sender, &text).await;
+        let got = recv_msg(&mut applier).await.expect("applier got the text");
+        send_msg(&mut applier, &Message::new_ack("a", &got)).await;
+
+        let ack = recv_msg(&mut sender).await.expect("sender got the ack");
+        assert!(matches!(ack.kind, Kind::Ack));
+        assert_eq!(
+            (ack.event_id.as_str(), ack.device_id.as_str()),
+            (text.event_id.as_str(), "a")
+        );
+        // A client that can't decode acks only ever sees the text.
+        assert!(matches!(
+            recv_msg(&mut old).await.map(|m| m.kind),
+            Some(Kind::Text)
+        ));
+        assert!(recv_msg(&mut old).await.is_none());
+        // Nor are acks kept for replay.
+        assert_eq!(
+            relay.scrollback.lock().await.replay("room", |_| true).len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn hello_answer_carries_version_and_uptime() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let relay = Relay {
+            started: Started(Instant::now() - Duration::from_secs(90)),
+            ..Relay::default()
+        };
+        tokio::spawn(serve(listener, relay));
+
+        let mut s = TcpStream::connect(addr).await.unwrap();
+        send_msg(&mut s, &Message::new_hello("a", "room", &Hello::new(&[]))).await;
+        let answer = recv_msg(&mut s).await.expect("relay answers hello");
+        let banner = answer.relay_banner().expect("answer has a banner");
+        assert_eq!(banner.version, env!("CARGO_PKG_VERSION"));
+        assert!((90..120).contains(&banner.uptime_secs), "{banner:?}");
+        assert!(answer.hello().is_some());
+    }
+
+    /// A local address that was free a moment ago, for `run_relay` to bind.
+    fn free_addr() -> String {
+        let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        l.local_addr().unwrap().to_string()
+    }
+
+    async fn connect_when_up(addr: &str) -> TcpStream {
+        for _ in 0..100 {
+            if let Ok(s) = TcpStream::connect(addr).await {
+                return s;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("relay on {addr} never came up");
+    }
+
+    #[tokio::test]
+    async fn embedded_relay_broadcasts_to_the_room_until_shut_down() {
+        let addr = free_addr();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let config = RelayConfig {
+            bind: vec![addr.clone()],
+            ..RelayConfig::default()
+        };
+        let server = tokio::spawn(run_relay(config, async {
+            let _ = stopped.await;
+        }));
+
+        let mut a = connect_when_up(&addr).await;
+        let mut b = TcpStream::connect(&addr).await.unwrap();
+        let mut elsewhere = TcpStream::connect(&addr).await.unwrap();
+        send_msg(&mut a, &Message::new_join("a", "room")).await;
+        send_msg(&mut b, &Message::new_join("b", "room")).await;
+        send_msg(&mut elsewhere, &Message::new_join("c", "other")).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        send_msg(&mut a, &Message::new_text("a", "room", "hi")).await;
+        let got = recv_msg(&mut b).await.expect("room member got nothing");
+        assert_eq!(got.payload.as_deref(), Some(&b"hi"[..]));
+        // Neither the sender nor another room hears it.
+        assert!(recv_msg(&mut a).await.is_none());
+        assert!(recv_msg(&mut elsewhere).await.is_none());
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("run_relay did not return on shutdown")
+            .unwrap()
+            .unwrap();
+        // Open connections go with it, and nothing listens any more.
+        let mut tmp = [0u8; 1];
+        let n = tokio::time::timeout(Duration::from_secs(5), b.read(&mut tmp))
+            .await
+            .expect("connection outlived the relay")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        assert!(TcpStream::connect(&addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn embedded_relay_fails_to_start_on_a_bad_address() {
+        let config = RelayConfig {
+            bind: vec![free_addr(), "not-an-address".to_string()],
+            ..RelayConfig::default()
+        };
+        let err = run_relay(config, std::future::pending()).await.unwrap_err();
+        assert!(
+            format!("{err:#}").contains("bind not-an-address"),
+            "{err:#}"
+        );
+        // Nothing to listen on is an error too, not a relay that returns at once.
+        assert!(run_relay(RelayConfig::default(), std::future::pending())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn peer_addresses_are_masked_to_their_subnet() {
+        use peer_log::mask_ip;
+        use std::net::{IpAddr, SocketAddr};
+
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(mask_ip(ip("203.0.113.57")), "203.0.113.0/24");
+        assert_eq!(mask_ip(ip("2001:db8:abcd:12::1")), "2001:db8:abcd::/48");
+        // A dual-stack listener's IPv4 clients.
+        assert_eq!(mask_ip(ip("::ffff:198.51.100.7")), "198.51.100.0/24");
+        assert_eq!(mask_ip(ip("::1")), "::/48");
+
+        let peer: SocketAddr = "[2001:db8::5]:4242".parse().unwrap();
+        assert_eq!(LogPeer::Full.show(peer).to_string(), "[2001:db8::5]:4242");
+        assert_eq!(LogPeer::Masked.show(peer).to_string(), "2001:db8::/48");
+        assert_eq!(LogPeer::None.show(peer).to_string(), "-");
+        assert_eq!(LogPeer::parse("masked"), Some(LogPeer::Masked));
+        assert_eq!(LogPeer::parse("partial"), None);
+    }
+}
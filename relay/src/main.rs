@@ -1,194 +1,211 @@
 use anyhow::{bail, Context};
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{mpsc, Mutex};
 
-use utils::Kind;
-use utils::Message;
-
-type Tx = mpsc::Sender<Vec<u8>>;
-type ConnId = u64;
-type SharedRooms = Arc<Mutex<HashMap<String, Vec<(ConnId, Tx)>>>>;
+use relay::{
+    run_relay, Limits, LogPeer, RelayConfig, CLIENT_QUEUE, RETAIN_MAX_BYTES, SCROLLBACK_MAX_BYTES,
+};
+use utils::MAX_FRAME_BYTES;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"))
         .try_init();
 
-    let mut addr = std::env::var("RELAY_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+    let mut addrs: Vec<String> = Vec::new();
+    let mut ws_addrs: Vec<String> = Vec::new();
+    let env_usize = |k: &str| {
+        std::env::var(k)
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+    };
+    let mut limits = Limits {
+        max_frame_bytes: env_usize("RELAY_MAX_FRAME_BYTES").unwrap_or(MAX_FRAME_BYTES),
+        // 0 = unlimited.
+        max_rooms: env_usize("RELAY_MAX_ROOMS").filter(|&n| n > 0),
+        max_connections: env_usize("RELAY_MAX_CONNECTIONS").filter(|&n| n > 0),
+        accept_rate: env_usize("RELAY_ACCEPT_RATE")
+            .and_then(|n| u32::try_from(n).ok())
+            .filter(|&n| n > 0),
+        client_queue: env_usize("RELAY_CLIENT_QUEUE")
+            .filter(|&n| n > 0)
+            .unwrap_or(CLIENT_QUEUE),
+        client_queue_bytes: env_usize("RELAY_CLIENT_QUEUE_BYTES").filter(|&n| n > 0),
+        retain_last: None,
+        // 0 = off (the default).
+        scrollback: env_usize("RELAY_SCROLLBACK").filter(|&n| n > 0),
+        scrollback_max_bytes: env_usize("RELAY_SCROLLBACK_MAX_BYTES")
+            .unwrap_or(SCROLLBACK_MAX_BYTES),
+        compress: !matches!(
+            std::env::var("RELAY_NO_COMPRESS").ok().as_deref(),
+            Some("1") | Some("true")
+        ),
+    };
+    let mut retain_last = matches!(
+        std::env::var("RELAY_RETAIN_LAST").ok().as_deref(),
+        Some("1") | Some("true")
+    );
+    let mut retain_max_bytes = env_usize("RELAY_RETAIN_MAX_BYTES").unwrap_or(RETAIN_MAX_BYTES);
+    // 0 = off (the default).
+    let mut census_secs = env_usize("RELAY_CENSUS_SECS").unwrap_or(0) as u64;
+    let mut log_peer = match std::env::var("RELAY_LOG_PEER") {
+        Ok(v) => LogPeer::parse(&v).with_context(|| format!("invalid RELAY_LOG_PEER {v}"))?,
+        Err(_) => LogPeer::default(),
+    };
 
     // Minimal CLI parsing (avoid extra deps):
-    //   relay --bind 127.0.0.1:8080
-    // Env RELAY_ADDR still works and is the default when no args are given.
+    //   relay --bind 127.0.0.1:8080 [--bind [::1]:8080 ...]
+    // Env RELAY_ADDR still works and is the default when no --bind is given.
     let mut args = std::env::args().skip(1);
     while let Some(a) = args.next() {
         match a.as_str() {
             "--bind" | "--addr" => {
-                addr = args
+                addrs.push(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?,
+                );
+            }
+            "--ws-bind" => {
+                ws_addrs.push(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?,
+                );
+            }
+            "--max-frame-bytes" => {
+                let v = args
                     .next()
                     .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                limits.max_frame_bytes = v
+                    .parse()
+                    .with_context(|| format!("invalid --max-frame-bytes {v}"))?;
             }
-            "-h" | "--help" => {
-                println!("Usage: relay [--bind <ip:port>]\n\nEnv: RELAY_ADDR=<ip:port>");
-                return Ok(());
+            "--max-rooms" | "--max-connections" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                let n: usize = v.parse().with_context(|| format!("invalid {a} {v}"))?;
+                let n = Some(n).filter(|&n| n > 0);
+                if a == "--max-rooms" {
+                    limits.max_rooms = n;
+                } else {
+                    limits.max_connections = n;
+                }
             }
-            other => bail!("unknown arg: {other}"),
-        }
-    }
-
-    println!("Relay listening on {}", addr);
-    let listener = TcpListener::bind(&addr).await.context("bind")?;
-    let rooms: SharedRooms = Arc::new(Mutex::new(HashMap::new()));
-
-    loop {
-        let (socket, peer) = listener.accept().await.context("accept")?;
-        let rooms = rooms.clone();
-        log::info!("relay: accept peer={}", peer);
-        tokio::spawn(async move {
-            if let Err(e) = handle_conn(socket, rooms, peer).await {
-                log::warn!("relay: connection error peer={} err={:?}", peer, e);
+            "--accept-rate" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                let n: u32 = v
+                    .parse()
+                    .with_context(|| format!("invalid --accept-rate {v}"))?;
+                limits.accept_rate = Some(n).filter(|&n| n > 0);
             }
-        });
-    }
-}
-
-async fn handle_conn(
-    socket: TcpStream,
-    rooms: SharedRooms,
-    peer: std::net::SocketAddr,
-) -> anyhow::Result<()> {
-    let conn_id: ConnId = rand_conn_id();
-    let (mut reader, mut writer_half) = socket.into_split();
-    // create outbound channel
-    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(32);
-    // writer task
-    let writer = tokio::spawn(async move {
-        while let Some(buf) = rx.recv().await {
-            // write length (u32 BE) then payload
-            if writer_half.write_u32(buf.len() as u32).await.is_err() {
-                break;
+            "--client-queue" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                limits.client_queue = v
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .with_context(|| format!("invalid --client-queue {v} (must be > 0)"))?;
             }
-            if writer_half.write_all(&buf).await.is_err() {
-                break;
+            "--client-queue-bytes" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                let n: usize = v
+                    .parse()
+                    .with_context(|| format!("invalid --client-queue-bytes {v}"))?;
+                limits.client_queue_bytes = Some(n).filter(|&n| n > 0);
             }
-        }
-    });
-
-    // read loop
-    // If a peer goes away without FIN/RST (e.g. network loss), we may not notice promptly.
-    // A conservative idle timeout plus client heartbeat keeps the room membership fresh.
-    let idle_timeout = Duration::from_secs(120);
-    let mut registered_room: Option<String> = None;
-    loop {
-        // read len
-        let len = match tokio::time::timeout(idle_timeout, reader.read_u32()).await {
-            Ok(Ok(l)) => l as usize,
-            Ok(Err(_)) => break,
-            Err(_) => {
-                log::warn!(
-                    "relay: idle timeout peer={} conn_id={} ({}s)",
-                    peer,
-                    conn_id,
-                    idle_timeout.as_secs()
-                );
-                break;
+            "--retain-last" => retain_last = true,
+            "--no-compress" => limits.compress = false,
+            "--census-secs" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                census_secs = v
+                    .parse()
+                    .with_context(|| format!("invalid --census-secs {v}"))?;
             }
-        };
-        let mut buf = vec![0u8; len];
-        reader.read_exact(&mut buf).await.context("read payload")?;
-        let msg = match Message::try_from_bytes(&buf) {
-            Ok(m) => m,
-            Err(e) => {
-                let prefix_len = buf.len().min(16);
-                log::warn!(
-                    "relay: decode failed peer={} conn_id={} len={} prefix={:02x?} err={:?}",
-                    peer,
-                    conn_id,
-                    len,
-                    &buf[..prefix_len],
-                    e
-                );
-                // Protocol mismatch / corrupt frame: drop the connection to resync.
-                break;
+            "--scrollback" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                let n: usize = v
+                    .parse()
+                    .with_context(|| format!("invalid --scrollback {v}"))?;
+                limits.scrollback = Some(n).filter(|&n| n > 0);
             }
-        };
-
-        // register sender into room when first message arrives
-        if registered_room.is_none() {
-            let r = msg.room.clone();
-            let mut map = rooms.lock().await;
-            map.entry(r.clone())
-                .or_default()
-                .push((conn_id, tx.clone()));
-            registered_room = Some(r);
-            log::info!(
-                "relay: register peer={} conn_id={} room={}",
-                peer,
-                conn_id,
-                registered_room.as_deref().unwrap_or("?")
-            );
-        }
-
-        // broadcast to room
-        if matches!(msg.kind, Kind::Join) {
-            continue;
-        }
-        let room = msg.room.clone();
-        // Forward original bytes as-is (avoid re-serialization changing the wire format).
-        let out = buf;
-        let mut map = rooms.lock().await;
-        if let Some(list) = map.get_mut(&room) {
-            // Drop only closed channels (a full channel should not kick the client).
-            list.retain(|(_, s)| !s.is_closed());
-            let targets = list.len().saturating_sub(1);
-            log::debug!(
-                "relay: broadcast room={} from_conn={} peer={} targets={} bytes={}",
-                room,
-                conn_id,
-                peer,
-                targets,
-                out.len()
-            );
-            for (id, s) in list.iter() {
-                if *id == conn_id {
-                    continue;
-                }
-                let _ = s.try_send(out.clone());
+            "--scrollback-max-bytes" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                limits.scrollback_max_bytes = v
+                    .parse()
+                    .with_context(|| format!("invalid --scrollback-max-bytes {v}"))?;
             }
+            "--retain-max-bytes" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                retain_max_bytes = v
+                    .parse()
+                    .with_context(|| format!("invalid --retain-max-bytes {v}"))?;
+            }
+            "--log-peer" => {
+                let v = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("missing value for {a}"))?;
+                log_peer = LogPeer::parse(&v)
+                    .with_context(|| format!("invalid --log-peer {v} (full|masked|none)"))?;
+            }
+            "-h" | "--help" => {
+                println!(
+                    "Usage: relay [--bind <ip:port>]... [--ws-bind <ip:port>]... \
+                     [--max-frame-bytes <n>] [--max-rooms <n>] [--max-connections <n>] \
+                     [--accept-rate <per sec>] [--client-queue <frames>] [--client-queue-bytes <n>] \
+                     [--retain-last [--retain-max-bytes <n>]] \
+                     [--scrollback <n> [--scrollback-max-bytes <n>]] [--no-compress] \
+                     [--census-secs <n>] [--log-peer <full|masked|none>]\n\n\
+                     Env: RELAY_ADDR=<ip:port> RELAY_WS_ADDR=<ip:port> RELAY_MAX_FRAME_BYTES=<n> \
+                     RELAY_MAX_ROOMS=<n> RELAY_MAX_CONNECTIONS=<n> RELAY_ACCEPT_RATE=<per sec> \
+                     RELAY_CLIENT_QUEUE=<frames> RELAY_CLIENT_QUEUE_BYTES=<n> (0 = unlimited) \
+                     RELAY_RETAIN_LAST=1 RELAY_RETAIN_MAX_BYTES=<n> \
+                     RELAY_SCROLLBACK=<n> RELAY_SCROLLBACK_MAX_BYTES=<n> RELAY_NO_COMPRESS=1 \
+                     RELAY_CENSUS_SECS=<n> (0 = off) RELAY_LOG_PEER=<full|masked|none>"
+                );
+                return Ok(());
+            }
+            other => bail!("unknown arg: {other}"),
         }
     }
 
-    // cleanup writer
-    drop(tx);
-    let _ = writer.await;
-    // remove from rooms
-    if let Some(room) = registered_room.clone() {
-        let mut map = rooms.lock().await;
-        if let Some(list) = map.get_mut(&room) {
-            // remove closed channels
-            list.retain(|(_, s)| !s.is_closed());
-        }
-    }
-
-    log::info!(
-        "relay: disconnect peer={} conn_id={} room={:?}",
-        peer,
-        conn_id,
-        registered_room
-    );
+    limits.retain_last = retain_last.then_some(retain_max_bytes);
 
-    Ok(())
-}
+    if addrs.is_empty() {
+        addrs.push(std::env::var("RELAY_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string()));
+    }
+    if ws_addrs.is_empty() {
+        ws_addrs.extend(
+            std::env::var("RELAY_WS_ADDR")
+                .ok()
+                .filter(|a| !a.trim().is_empty()),
+        );
+    }
 
-fn rand_conn_id() -> ConnId {
-    // Good enough for a prototype: a random-ish u64 from current time.
-    // (We avoid adding an extra dependency; collisions are extremely unlikely here.)
-    use std::time::{SystemTime, UNIX_EPOCH};
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_nanos() as u64)
-        .unwrap_or(0)
+    let config = RelayConfig {
+        bind: addrs,
+        ws_bind: ws_addrs,
+        limits,
+        log_peer,
+        census_secs,
+    };
+    // A bad address makes run_relay fail before anything is served.
+    for addr in &config.bind {
+        println!("Relay listening on {}", addr);
+    }
+    for addr in &config.ws_bind {
+        println!("Relay listening for WebSocket clients on {}", addr);
+    }
+    run_relay(config, std::future::pending()).await
 }
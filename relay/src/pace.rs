@@ -0,0 +1,34 @@
+//! `--accept-rate`: paces new connections so a reconnect storm or a connect flood can't
+//! spawn handlers faster than the relay can take them on.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The next free accept slot, shared by every listener of one relay.
+#[derive(Default)]
+pub struct AcceptPacer {
+    next: Mutex<Option<Instant>>,
+}
+
+impl AcceptPacer {
+    /// Reserve the earliest slot at or after `now`; slots are `1 / rate` seconds apart.
+    fn reserve(&self, rate: u32, now: Instant) -> Instant {
+        let gap = Duration::from_secs(1) / rate.max(1);
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let slot = next.map_or(now, |n| n.max(now));
+        *next = Some(slot + gap);
+        slot
+    }
+
+    /// Wait for this connection's slot (no-op without a rate).
+    ///
+    /// Callers wait after `accept`, so while they do, further connections queue in the
+    /// listen backlog and the kernel refuses whatever overflows it.
+    pub async fn wait(&self, rate: Option<u32>) {
+        let Some(rate) = rate else {
+            return;
+        };
+        let slot = self.reserve(rate, Instant::now());
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
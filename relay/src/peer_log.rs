@@ -0,0 +1,64 @@
+//! `--log-peer`: how much of a client's address the relay writes to its logs.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogPeer {
+    /// `ip:port`, as before.
+    #[default]
+    Full,
+    /// The subnet only (see [`mask_ip`]).
+    Masked,
+    /// Nothing but a placeholder.
+    None,
+}
+
+impl LogPeer {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "full" => Some(Self::Full),
+            "masked" => Some(Self::Masked),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    /// `peer` the way this mode logs it.
+    pub fn show(self, peer: SocketAddr) -> ShownPeer {
+        ShownPeer { peer, mode: self }
+    }
+}
+
+/// A peer address formatted for the logs according to `--log-peer`.
+#[derive(Clone, Copy, Debug)]
+pub struct ShownPeer {
+    peer: SocketAddr,
+    mode: LogPeer,
+}
+
+impl fmt::Display for ShownPeer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            LogPeer::Full => write!(f, "{}", self.peer),
+            LogPeer::Masked => f.write_str(&mask_ip(self.peer.ip())),
+            LogPeer::None => f.write_str("-"),
+        }
+    }
+}
+
+/// The network an address is in, host bits and port dropped: a /24 for IPv4, a /48 for IPv6.
+///
+/// IPv4-mapped IPv6 addresses (a dual-stack listener's IPv4 clients) are masked as IPv4.
+pub fn mask_ip(ip: IpAddr) -> String {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{}/24", Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            format!("{}/48", Ipv6Addr::new(s[0], s[1], s[2], 0, 0, 0, 0, 0))
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! `--retain-last`: the last broadcast frame per room, kind and channel, replayed to joiners.
+//!
+//! Payloads stay in memory, so the store has a byte budget shared by all rooms; the oldest
+//! frames are evicted first. It outlives room membership, so a device joining an empty room
+//! still gets what was copied last.
+
+use std::collections::HashMap;
+
+use utils::Kind;
+
+/// Default `--retain-max-bytes`.
+pub const RETAIN_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum KindKey {
+    Text,
+    Image,
+    File,
+}
+
+type Key = (String, KindKey, Option<String>);
+
+#[derive(Default)]
+pub struct Retained {
+    /// Frame bytes with the sequence number they were stored at (for eviction and replay order).
+    frames: HashMap<Key, (u64, Vec<u8>)>,
+    bytes: usize,
+    seq: u64,
+}
+
+impl Retained {
+    /// Remember `frame` as the latest of its kind in `room`, within `max_bytes` overall.
+    pub fn remember(
+        &mut self,
+        room: &str,
+        kind: &Kind,
+        channel: Option<&str>,
+        frame: &[u8],
+        max_bytes: usize,
+    ) {
+        let kind = match kind {
+            Kind::Text => KindKey::Text,
+            Kind::Image => KindKey::Image,
+            Kind::File => KindKey::File,
+            Kind::Join | Kind::Hello | Kind::Replay | Kind::Ping | Kind::Pong | Kind::Ack => return,
+        };
+        let key = (room.to_string(), kind, channel.map(str::to_string));
+        // The previous frame is stale either way; never replay it once something newer was sent.
+        if let Some((_, old)) = self.frames.remove(&key) {
+            self.bytes -= old.len();
+        }
+        if frame.len() > max_bytes {
+            log::debug!(
+                "relay: not retaining room={} kind={:?} bytes={} (max {})",
+                room,
+                kind,
+                frame.len(),
+                max_bytes
+            );
+            return;
+        }
+        while self.bytes + frame.len() > max_bytes {
+            let Some(oldest) = self
+                .frames
+                .iter()
+                .min_by_key(|(_, (seq, _))| *seq)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            if let Some((_, old)) = self.frames.remove(&oldest) {
+                self.bytes -= old.len();
+            }
+        }
+        self.seq += 1;
+        self.bytes += frame.len();
+        self.frames.insert(key, (self.seq, frame.to_vec()));
+    }
+
+    /// Frames to send a connection that just joined `room`, oldest first.
+    pub fn replay(&self, room: &str, wants: impl Fn(Option<&str>) -> bool) -> Vec<Vec<u8>> {
+        let mut out: Vec<&(u64, Vec<u8>)> = self
+            .frames
+            .iter()
+            .filter(|((r, _, channel), _)| r == room && wants(channel.as_deref()))
+            .map(|(_, v)| v)
+            .collect();
+        out.sort_by_key(|(seq, _)| *seq);
+        out.into_iter().map(|(_, frame)| frame.clone()).collect()
+    }
+}
@@ -0,0 +1,91 @@
+//! `--scrollback <n>`: the last N broadcast frames per room, streamed to a client on request.
+//!
+//! Unlike `--retain-last` (one frame per kind, pushed on join), this keeps a short history of
+//! everything and only sends it when a client asks with a `Replay` frame. Rooms share one
+//! byte budget; the oldest frames are evicted first.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Default `--scrollback-max-bytes`.
+pub const SCROLLBACK_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+struct Entry {
+    seq: u64,
+    channel: Option<String>,
+    frame: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct Scrollback {
+    rooms: HashMap<String, VecDeque<Entry>>,
+    bytes: usize,
+    seq: u64,
+}
+
+impl Scrollback {
+    /// Append `frame` to `room`, keeping at most `max_frames` per room and `max_bytes` overall.
+    pub fn push(
+        &mut self,
+        room: &str,
+        channel: Option<&str>,
+        frame: &[u8],
+        max_frames: usize,
+        max_bytes: usize,
+    ) {
+        if frame.len() > max_bytes {
+            log::debug!(
+                "relay: not keeping room={} bytes={} in scrollback (max {})",
+                room,
+                frame.len(),
+                max_bytes
+            );
+            return;
+        }
+        while self.bytes + frame.len() > max_bytes && self.evict_oldest() {}
+        self.seq += 1;
+        self.bytes += frame.len();
+        let ring = self.rooms.entry(room.to_string()).or_default();
+        ring.push_back(Entry {
+            seq: self.seq,
+            channel: channel.map(str::to_string),
+            frame: frame.to_vec(),
+        });
+        while ring.len() > max_frames {
+            if let Some(old) = ring.pop_front() {
+                self.bytes -= old.frame.len();
+            }
+        }
+    }
+
+    /// Drop the oldest frame of any room; false when there is nothing left.
+    fn evict_oldest(&mut self) -> bool {
+        let Some(room) = self
+            .rooms
+            .iter()
+            .filter_map(|(r, ring)| ring.front().map(|e| (e.seq, r)))
+            .min()
+            .map(|(_, r)| r.clone())
+        else {
+            return false;
+        };
+        let ring = self.rooms.get_mut(&room).expect("room just found");
+        if let Some(old) = ring.pop_front() {
+            self.bytes -= old.frame.len();
+        }
+        if ring.is_empty() {
+            self.rooms.remove(&room);
+        }
+        true
+    }
+
+    /// The room's frames a client may see, oldest first.
+    pub fn replay(&self, room: &str, wants: impl Fn(Option<&str>) -> bool) -> Vec<Vec<u8>> {
+        self.rooms
+            .get(room)
+            .into_iter()
+            .flatten()
+            .filter(|e| wants(e.channel.as_deref()))
+            .map(|e| e.frame.clone())
+            .collect()
+    }
+}
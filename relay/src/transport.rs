@@ -0,0 +1,192 @@
+//! Client transports: raw TCP, and WebSocket (`--ws-bind`) for browser clients.
+//!
+//! Both carry the same frames: a u32 big-endian length followed by the bincode `Message`.
+//! Over WebSocket every binary message holds exactly one such frame, prefix included, so a
+//! client can use one codec for both. TCP clients may negotiate deflate for the whole stream
+//! (see `utils::conn_hello`); WebSocket has its own extension for that.
+
+use anyhow::Context;
+use async_compression::tokio::bufread::DeflateDecoder;
+use async_compression::tokio::write::DeflateEncoder;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::WebSocketStream;
+
+type Ws = WebSocketStream<TcpStream>;
+
+pub enum Transport {
+    Tcp(TcpStream),
+    Ws(Box<Ws>),
+}
+
+impl From<TcpStream> for Transport {
+    fn from(socket: TcpStream) -> Self {
+        Transport::Tcp(socket)
+    }
+}
+
+impl Transport {
+    pub fn split(self) -> (FrameReader, FrameWriter) {
+        match self {
+            Transport::Tcp(socket) => {
+                let (r, w) = socket.into_split();
+                (FrameReader::Tcp(r), FrameWriter::Tcp(w))
+            }
+            Transport::Ws(ws) => {
+                let (w, r) = (*ws).split();
+                (FrameReader::Ws(r), FrameWriter::Ws(w))
+            }
+        }
+    }
+}
+
+/// Finish the WebSocket handshake on an accepted socket.
+pub async fn accept_ws(socket: TcpStream, max_frame_bytes: usize) -> anyhow::Result<Transport> {
+    let config = WebSocketConfig {
+        // One frame per message, plus its length prefix.
+        max_message_size: Some(max_frame_bytes.saturating_add(4)),
+        max_frame_size: Some(max_frame_bytes.saturating_add(4)),
+        ..WebSocketConfig::default()
+    };
+    let ws = tokio_tungstenite::accept_async_with_config(socket, Some(config))
+        .await
+        .context("websocket handshake")?;
+    Ok(Transport::Ws(Box::new(ws)))
+}
+
+/// Result of reading one frame.
+pub enum Frame {
+    /// The frame body (length prefix stripped).
+    Data(Vec<u8>),
+    /// The prefix announced more than the frame limit; nothing was allocated.
+    TooLarge(usize),
+    /// The peer hung up.
+    Closed,
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max_frame_bytes: usize,
+) -> anyhow::Result<Frame> {
+    let len = match reader.read_u32().await {
+        Ok(l) => l as usize,
+        Err(_) => return Ok(Frame::Closed),
+    };
+    if len > max_frame_bytes {
+        return Ok(Frame::TooLarge(len));
+    }
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).await.context("read payload")?;
+    Ok(Frame::Data(buf))
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> anyhow::Result<()> {
+    writer.write_u32(frame.len() as u32).await?;
+    writer.write_all(frame).await?;
+    // A no-op on plain TCP; pushes a deflate block out so the peer can decode this frame now.
+    writer.flush().await?;
+    Ok(())
+}
+
+pub enum FrameReader {
+    Tcp(OwnedReadHalf),
+    Deflate(Box<DeflateDecoder<BufReader<OwnedReadHalf>>>),
+    Ws(SplitStream<Ws>),
+}
+
+impl FrameReader {
+    /// Inflate everything read from here on (after the hello exchange). TCP only.
+    pub fn deflate(self) -> Self {
+        match self {
+            FrameReader::Tcp(r) => {
+                FrameReader::Deflate(Box::new(DeflateDecoder::new(BufReader::new(r))))
+            }
+            other => other,
+        }
+    }
+
+    pub async fn recv(&mut self, max_frame_bytes: usize) -> anyhow::Result<Frame> {
+        match self {
+            FrameReader::Tcp(reader) => read_frame(reader, max_frame_bytes).await,
+            FrameReader::Deflate(reader) => read_frame(reader, max_frame_bytes).await,
+            FrameReader::Ws(stream) => loop {
+                let data = match stream.next().await {
+                    None | Some(Ok(WsMessage::Close(_))) => return Ok(Frame::Closed),
+                    Some(Err(e)) => return Err(e).context("read websocket message"),
+                    Some(Ok(WsMessage::Binary(data))) => data,
+                    // Pings are answered by tungstenite; text carries no frames.
+                    Some(Ok(_)) => continue,
+                };
+                let Some((prefix, body)) = data.split_first_chunk::<4>() else {
+                    log::warn!("relay: websocket message shorter than a length prefix");
+                    continue;
+                };
+                let len = u32::from_be_bytes(*prefix) as usize;
+                if len > max_frame_bytes {
+                    return Ok(Frame::TooLarge(len));
+                }
+                if len != body.len() {
+                    log::warn!(
+                        "relay: websocket frame length mismatch prefix={} body={}",
+                        len,
+                        body.len()
+                    );
+                    continue;
+                }
+                return Ok(Frame::Data(body.to_vec()));
+            },
+        }
+    }
+}
+
+pub enum FrameWriter {
+    Tcp(OwnedWriteHalf),
+    Deflate(Box<DeflateEncoder<OwnedWriteHalf>>),
+    Ws(SplitSink<Ws, WsMessage>),
+}
+
+impl FrameWriter {
+    /// Deflate everything written from here on (after the hello exchange). TCP only.
+    pub fn deflate(self) -> Self {
+        match self {
+            FrameWriter::Tcp(w) => FrameWriter::Deflate(Box::new(DeflateEncoder::new(w))),
+            other => other,
+        }
+    }
+
+    /// Whether [`deflate`](Self::deflate) applies to this transport.
+    pub fn can_deflate(&self) -> bool {
+        matches!(self, FrameWriter::Tcp(_))
+    }
+
+    /// Write one frame: length (u32 BE) then payload.
+    pub async fn send(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        match self {
+            FrameWriter::Tcp(writer) => write_frame(writer, frame).await?,
+            FrameWriter::Deflate(writer) => write_frame(writer, frame).await?,
+            FrameWriter::Ws(sink) => {
+                let mut data = Vec::with_capacity(4 + frame.len());
+                data.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+                data.extend_from_slice(frame);
+                sink.send(WsMessage::Binary(data)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hang up (TCP FIN, or a WebSocket close frame).
+    pub async fn close(&mut self) -> anyhow::Result<()> {
+        match self {
+            FrameWriter::Tcp(writer) => writer.shutdown().await?,
+            // Finishes the deflate stream, then shuts the socket down.
+            FrameWriter::Deflate(writer) => writer.shutdown().await?,
+            FrameWriter::Ws(sink) => sink.close().await?,
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,8 @@ use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use utils::kinds::watch_mimes_for;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct UiConfig {
     pub relay_addr: String,
@@ -15,21 +17,53 @@ pub struct UiConfig {
     #[serde(default = "default_image_mode")]
     pub image_mode: String,
 
+    /// Multi-mime image preference, most preferred first (empty = node default order).
+    #[serde(default)]
+    pub image_priority: Vec<String>,
+
     #[serde(default = "default_x11_poll_interval_ms")]
     pub x11_poll_interval_ms: u64,
 
+    /// Which kinds wl-watch publishes (see [`SyncKinds`]).
+    #[serde(default = "default_true")]
+    pub sync_text: bool,
+    #[serde(default = "default_true")]
+    pub sync_image: bool,
+    #[serde(default = "default_true")]
+    pub sync_file: bool,
+
     #[serde(default = "default_language")]
     pub language: String,
 
+    /// `auto` (follow the system), `light` or `dark`.
+    #[serde(default = "default_theme")]
+    pub theme: String,
+
     #[serde(default)]
     pub debug_mode: bool,
 
+    /// Run "Start All" when the UI opens.
+    #[serde(default)]
+    pub autostart_services: bool,
+
+    /// Name shown to peers (empty = node default: the hostname).
+    #[serde(default)]
+    pub device_name: String,
+
+    /// Destination for received files (empty = node default under the data dir).
+    #[serde(default)]
+    pub received_dir: String,
+
     /// History table column visibility map.
     /// Key = column id (e.g. "peer"), value = visible.
     /// Empty map means "use built-in defaults".
     #[serde(default)]
     pub history_columns: BTreeMap<String, bool>,
 
+    /// Most rows the history table keeps; the oldest are dropped beyond it.
+    #[serde(default = "default_history_max_rows")]
+    pub history_max_rows: usize,
+
     // Legacy field (v0): existed as `force_png = true/false`.
     // Keep it for backward-compatible loading.
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -45,6 +79,20 @@ fn default_language() -> String {
     "auto".to_string()
 }
 
+fn default_theme() -> String {
+    "auto".to_string()
+}
+
+/// The `gtk-application-prefer-dark-theme` value for a `theme` setting; anything but
+/// `light`/`dark` keeps what the system prefers.
+pub fn prefer_dark_for(theme: &str, system_prefers_dark: bool) -> bool {
+    match theme {
+        "light" => false,
+        "dark" => true,
+        _ => system_prefers_dark,
+    }
+}
+
 fn default_max_file_bytes() -> usize {
     20 * 1024 * 1024
 }
@@ -53,6 +101,14 @@ fn default_x11_poll_interval_ms() -> u64 {
     200
 }
 
+fn default_history_max_rows() -> usize {
+    5000
+}
+
+fn default_true() -> bool {
+    true
+}
+
 impl Default for UiConfig {
     fn default() -> Self {
         Self {
@@ -62,15 +118,97 @@ impl Default for UiConfig {
             max_image_bytes: 20 * 1024 * 1024,
             max_file_bytes: default_max_file_bytes(),
             image_mode: default_image_mode(),
+            image_priority: Vec::new(),
             x11_poll_interval_ms: default_x11_poll_interval_ms(),
+            sync_text: true,
+            sync_image: true,
+            sync_file: true,
             language: default_language(),
+            theme: default_theme(),
             debug_mode: false,
+            autostart_services: false,
+            device_name: String::new(),
+            received_dir: String::new(),
             history_columns: BTreeMap::new(),
+            history_max_rows: default_history_max_rows(),
             force_png: None,
         }
     }
 }
 
+/// Text / Image / File checkboxes, translated into node flags.
+///
+/// Sending is gated per kind through `wl-watch --watch-mimes`; a copy whose best type is a
+/// disabled kind is skipped as a whole. The node only gates receiving with `--text-only`, so
+/// wl-apply still applies images and files unless text is the only kind left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncKinds {
+    pub text: bool,
+    pub image: bool,
+    pub file: bool,
+}
+
+impl SyncKinds {
+    pub fn from_config(cfg: &UiConfig) -> Self {
+        Self {
+            text: cfg.sync_text,
+            image: cfg.sync_image,
+            file: cfg.sync_file,
+        }
+    }
+
+    /// Whether wl-watch has anything to do.
+    pub fn any(self) -> bool {
+        self.text || self.image || self.file
+    }
+
+    fn text_only(self) -> bool {
+        self.text && !self.image && !self.file
+    }
+
+    /// `--watch-mimes` value for a partial selection (`none` with every kind off); `None` when
+    /// everything syncs (or text alone, which `--text-only` covers).
+    pub fn watch_mimes(self) -> Option<String> {
+        if self.text_only() {
+            return None;
+        }
+        watch_mimes_for(self.text, self.image, self.file)
+    }
+
+    /// Extra `wl-watch` args.
+    pub fn watch_args(self) -> Vec<String> {
+        if self.text_only() {
+            return vec!["--text-only".to_string()];
+        }
+        match self.watch_mimes() {
+            Some(mimes) => vec!["--watch-mimes".to_string(), mimes],
+            None => Vec::new(),
+        }
+    }
+
+    /// Extra `wl-apply` args.
+    pub fn apply_args(self) -> Vec<String> {
+        if self.text_only() {
+            vec!["--text-only".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Env for the systemd units, which can't take optional args (`MCR_TEXT_ONLY`,
+    /// `MCR_WATCH_MIMES`).
+    pub fn env_lines(self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.text_only() {
+            lines.push("MCR_TEXT_ONLY=1".to_string());
+        }
+        if let Some(mimes) = self.watch_mimes() {
+            lines.push(format!("MCR_WATCH_MIMES={mimes}"));
+        }
+        lines
+    }
+}
+
 pub fn config_path() -> PathBuf {
     let base = dirs::config_dir().unwrap_or_else(|| PathBuf::from(".config"));
     base.join("multicliprelay").join("ui.toml")
@@ -96,3 +234,80 @@ pub fn save_config(path: &Path, cfg: &UiConfig) -> anyhow::Result<()> {
     fs::write(path, s).context("write config")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_kinds_are_left_out_of_the_watcher_args() {
+        let all = SyncKinds {
+            text: true,
+            image: true,
+            file: true,
+        };
+        assert!(all.watch_args().is_empty());
+        assert!(all.apply_args().is_empty());
+        assert!(all.env_lines().is_empty());
+
+        let no_images = SyncKinds {
+            image: false,
+            ..all
+        };
+        let args = no_images.watch_args();
+        assert_eq!(args[0], "--watch-mimes");
+        assert!(args[1].contains("text/plain") && args[1].contains("text/uri-list"));
+        assert!(!args[1].contains("image/"));
+        assert!(no_images.apply_args().is_empty());
+
+        let no_text = SyncKinds {
+            text: false,
+            ..all
+        };
+        let args = no_text.watch_args();
+        assert!(!args[1].split(',').any(|m| m.starts_with("text/plain")));
+        assert!(args[1].contains("image/*"));
+
+        let text_only = SyncKinds {
+            image: false,
+            file: false,
+            ..all
+        };
+        assert_eq!(text_only.watch_args(), ["--text-only"]);
+        assert_eq!(text_only.apply_args(), ["--text-only"]);
+        assert_eq!(text_only.env_lines(), ["MCR_TEXT_ONLY=1"]);
+
+        let none = SyncKinds {
+            text: false,
+            ..text_only
+        };
+        assert!(!none.any());
+        // Not an empty list, which the node reads as "everything".
+        assert_eq!(none.watch_args(), ["--watch-mimes", "none"]);
+        assert_eq!(none.env_lines(), ["MCR_WATCH_MIMES=none"]);
+
+        // Older configs without the fields sync everything.
+        let cfg: UiConfig = toml::from_str(
+            "relay_addr = \"r\"\nroom = \"x\"\nmax_text_bytes = 1\nmax_image_bytes = 1\n",
+        )
+        .unwrap();
+        assert_eq!(SyncKinds::from_config(&cfg), all);
+    }
+
+    #[test]
+    fn theme_setting_maps_to_prefer_dark() {
+        for system in [false, true] {
+            assert!(!prefer_dark_for("light", system));
+            assert!(prefer_dark_for("dark", system));
+            assert_eq!(prefer_dark_for("auto", system), system);
+            // Unknown values (e.g. from a newer UI) follow the system too.
+            assert_eq!(prefer_dark_for("sepia", system), system);
+        }
+        // Configs written before the setting existed follow the system.
+        let old: UiConfig = toml::from_str(
+            "relay_addr = \"a:1\"\nroom = \"r\"\nmax_text_bytes = 1\nmax_image_bytes = 1\n",
+        )
+        .unwrap();
+        assert_eq!(old.theme, "auto");
+    }
+}
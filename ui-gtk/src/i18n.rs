@@ -27,8 +27,14 @@ pub enum K {
     LabelX11PollIntervalMs,
     LabelImageMode,
     LabelLanguage,
+    LabelTheme,
     LabelDebugMode,
+    LabelDeviceName,
+    DeviceNamePlaceholder,
     LabelDebugEnable,
+    LabelSyncKinds,
+    LabelAutostart,
+    LabelAutostartEnable,
     BtnStartRelay,
     BtnStopRelay,
     BtnStartWatch,
@@ -43,6 +49,13 @@ pub enum K {
     BtnShowClipTypes,
     BtnClearLogs,
     BtnClearHistory,
+    BtnExportHistory,
+    HistorySearchPlaceholder,
+    HistoryKindAll,
+    HistoryKindText,
+    HistoryKindImage,
+    HistoryKindFile,
+    ExportHistoryTitle,
     ChooseImageTitle,
     ImageFilterName,
     ChooseFileTitle,
@@ -50,7 +63,11 @@ pub enum K {
     LangAuto,
     LangZhCn,
     LangEn,
+    ThemeAuto,
+    ThemeLight,
+    ThemeDark,
     ModeForcePng,
+    ModeForceWebp,
     ModeMulti,
     ModePassthrough,
     ModeSpoofPng,
@@ -66,6 +83,9 @@ pub enum K {
 
     LabelRelayTcp,
 
+    BtnShowQr,
+    BtnPauseSync,
+
     WindowWlClipboardLogs,
 
     HistoryEmptyHint,
@@ -137,10 +157,22 @@ pub fn t(lang: Lang, k: K) -> &'static str {
         (Lang::En, K::LabelImageMode) => "Image mode",
         (Lang::ZhCn, K::LabelLanguage) => "语言",
         (Lang::En, K::LabelLanguage) => "Language",
+        (Lang::ZhCn, K::LabelTheme) => "外观",
+        (Lang::En, K::LabelTheme) => "Appearance",
         (Lang::ZhCn, K::LabelDebugMode) => "调试模式",
         (Lang::En, K::LabelDebugMode) => "Debug mode",
+        (Lang::ZhCn, K::LabelDeviceName) => "设备名称",
+        (Lang::En, K::LabelDeviceName) => "Device name",
+        (Lang::ZhCn, K::DeviceNamePlaceholder) => "留空则使用主机名",
+        (Lang::En, K::DeviceNamePlaceholder) => "Empty = hostname",
         (Lang::ZhCn, K::LabelDebugEnable) => "启用详细日志",
         (Lang::En, K::LabelDebugEnable) => "Enable verbose logs",
+        (Lang::ZhCn, K::LabelSyncKinds) => "同步类型",
+        (Lang::En, K::LabelSyncKinds) => "Sync kinds",
+        (Lang::ZhCn, K::LabelAutostart) => "自动启动",
+        (Lang::En, K::LabelAutostart) => "Autostart",
+        (Lang::ZhCn, K::LabelAutostartEnable) => "打开界面时启动全部服务",
+        (Lang::En, K::LabelAutostartEnable) => "Start all services when the app opens",
 
         (Lang::ZhCn, K::BtnStartRelay) => "启动 relay",
         (Lang::En, K::BtnStartRelay) => "Start relay",
@@ -174,6 +206,20 @@ pub fn t(lang: Lang, k: K) -> &'static str {
         (Lang::En, K::BtnClearLogs) => "Clear logs",
         (Lang::ZhCn, K::BtnClearHistory) => "清空历史",
         (Lang::En, K::BtnClearHistory) => "Clear history",
+        (Lang::ZhCn, K::BtnExportHistory) => "导出…",
+        (Lang::En, K::BtnExportHistory) => "Export…",
+        (Lang::ZhCn, K::HistorySearchPlaceholder) => "搜索名称/设备/类型/详情",
+        (Lang::En, K::HistorySearchPlaceholder) => "Search name/peer/kind/details",
+        (Lang::ZhCn, K::HistoryKindAll) => "全部类型",
+        (Lang::En, K::HistoryKindAll) => "All kinds",
+        (Lang::ZhCn, K::HistoryKindText) => "文本",
+        (Lang::En, K::HistoryKindText) => "Text",
+        (Lang::ZhCn, K::HistoryKindImage) => "图片",
+        (Lang::En, K::HistoryKindImage) => "Image",
+        (Lang::ZhCn, K::HistoryKindFile) => "文件",
+        (Lang::En, K::HistoryKindFile) => "File",
+        (Lang::ZhCn, K::ExportHistoryTitle) => "导出历史（.json 或 .csv）",
+        (Lang::En, K::ExportHistoryTitle) => "Export history (.json or .csv)",
 
         (Lang::ZhCn, K::ChooseImageTitle) => "选择图片文件",
         (Lang::En, K::ChooseImageTitle) => "Choose an image file",
@@ -192,8 +238,17 @@ pub fn t(lang: Lang, k: K) -> &'static str {
         (Lang::ZhCn, K::LangEn) => "English",
         (Lang::En, K::LangEn) => "English",
 
+        (Lang::ZhCn, K::ThemeAuto) => "自动（跟随系统）",
+        (Lang::En, K::ThemeAuto) => "Auto (system)",
+        (Lang::ZhCn, K::ThemeLight) => "浅色",
+        (Lang::En, K::ThemeLight) => "Light",
+        (Lang::ZhCn, K::ThemeDark) => "深色",
+        (Lang::En, K::ThemeDark) => "Dark",
+
         (Lang::ZhCn, K::ModeForcePng) => "强制 PNG（推荐）",
         (Lang::En, K::ModeForcePng) => "Force PNG (recommended)",
+        (Lang::ZhCn, K::ModeForceWebp) => "强制 WebP（体积更小）",
+        (Lang::En, K::ModeForceWebp) => "Force WebP (smaller)",
         (Lang::ZhCn, K::ModeMulti) => "多 MIME（原格式 + PNG 兜底）",
         (Lang::En, K::ModeMulti) => "Multi-MIME (original + png)",
         (Lang::ZhCn, K::ModePassthrough) => "直通（仅原格式）",
@@ -224,6 +279,12 @@ pub fn t(lang: Lang, k: K) -> &'static str {
         (Lang::ZhCn, K::LabelRelayTcp) => "Relay 连接（TCP）",
         (Lang::En, K::LabelRelayTcp) => "Relay TCP",
 
+        (Lang::ZhCn, K::BtnShowQr) => "二维码",
+        (Lang::En, K::BtnShowQr) => "QR code",
+
+        (Lang::ZhCn, K::BtnPauseSync) => "暂停同步",
+        (Lang::En, K::BtnPauseSync) => "Pause sync",
+
         (Lang::ZhCn, K::WindowWlClipboardLogs) => "剪贴板日志（systemd）",
         (Lang::En, K::WindowWlClipboardLogs) => "Clipboard logs (systemd)",
 
@@ -242,6 +303,7 @@ pub fn populate_image_mode_combo(combo: &gtk4::ComboBoxText, lang: Lang, active_
         .or_else(|| combo.active_id().map(|s| s.to_string()));
     combo.remove_all();
     combo.append(Some("force-png"), t(lang, K::ModeForcePng));
+    combo.append(Some("force-webp"), t(lang, K::ModeForceWebp));
     combo.append(Some("multi"), t(lang, K::ModeMulti));
     combo.append(Some("passthrough"), t(lang, K::ModePassthrough));
     combo.append(Some("spoof-png"), t(lang, K::ModeSpoofPng));
@@ -250,11 +312,41 @@ pub fn populate_image_mode_combo(combo: &gtk4::ComboBoxText, lang: Lang, active_
     }
 }
 
+/// Light/dark override; ids match `UiConfig.theme`.
+pub fn populate_theme_combo(combo: &gtk4::ComboBoxText, lang: Lang, active_id: Option<&str>) {
+    let keep = active_id
+        .map(|s| s.to_string())
+        .or_else(|| combo.active_id().map(|s| s.to_string()))
+        .unwrap_or_else(|| "auto".to_string());
+    combo.remove_all();
+    combo.append(Some("auto"), t(lang, K::ThemeAuto));
+    combo.append(Some("light"), t(lang, K::ThemeLight));
+    combo.append(Some("dark"), t(lang, K::ThemeDark));
+    combo.set_active_id(Some(&keep));
+}
+
+/// Kind filter for the history tab; ids match the recorded `kind` ("" = all).
+pub fn populate_history_kind_combo(combo: &gtk4::ComboBoxText, lang: Lang, active_id: Option<&str>) {
+    let keep = active_id
+        .map(|s| s.to_string())
+        .or_else(|| combo.active_id().map(|s| s.to_string()))
+        .unwrap_or_default();
+    combo.remove_all();
+    combo.append(Some(""), t(lang, K::HistoryKindAll));
+    combo.append(Some("text"), t(lang, K::HistoryKindText));
+    combo.append(Some("image"), t(lang, K::HistoryKindImage));
+    combo.append(Some("file"), t(lang, K::HistoryKindFile));
+    combo.set_active_id(Some(&keep));
+}
+
 pub fn image_mode_hint_text(lang: Lang, mode_id: &str) -> &'static str {
     match (lang, mode_id) {
         (Lang::ZhCn, "force-png") => "最稳的兼容模式：无论收到/发送什么图片，最终都按 image/png 提供。适合 Electron/Qt 等粘贴兼容性优先的场景。",
         (Lang::En, "force-png") => "Most compatible: always provide images as image/png. Recommended for Electron/Qt and general paste reliability.",
 
+        (Lang::ZhCn, "force-webp") => "发送前把图片转换为无损 image/webp，通常比 PNG 小很多，适合大截图或慢速网络。收到的图片按原样写入剪贴板；接收端应用需要支持 WebP。",
+        (Lang::En, "force-webp") => "Convert images to lossless image/webp before sending; usually much smaller than PNG, good for large screenshots or slow links. Received images are applied as they arrive, so receiving apps need WebP support.",
+
         (Lang::ZhCn, "multi") => "提供“原始 MIME + PNG 兜底”两种表示，理论上最佳。但已知某些 Electron 应用在遇到 image/jpeg 等 offer 时可能卡死（尤其是跨设备传来的 jpeg）。如遇卡死请改用 force-png 或 spoof-png。",
         (Lang::En, "multi") => "Offer original MIME plus a PNG fallback. In theory best-of-both. Known issue: some Electron apps may freeze when image/jpeg offers exist (especially relayed JPEG). Use force-png or spoof-png if that happens.",
 
@@ -276,6 +368,7 @@ pub fn help_text(lang: Lang) -> String {
 1) 强制 PNG（force-png，推荐）\n\
    - 收到/发送图片时都转换成 image/png。\n\
    - 这是目前日用兼容性最好的模式。\n\n\
+   （强制 WebP / force-webp：发送前转换为无损 image/webp，体积更小；接收端应用需支持 WebP。）\n\n\
 2) 多 MIME（multi）\n\
    - 尝试同时提供“原始格式（如 image/jpeg）”和“PNG 兜底”。\n\
    - 理论上最优，但已知：通过本工具转发的 jpeg MIME 可能会导致部分 Electron 应用在粘贴时卡死。\n\
@@ -296,6 +389,7 @@ pub fn help_text(lang: Lang) -> String {
 1) Force PNG (force-png, recommended)\n\
    - Always convert and provide images as image/png.\n\
    - Best day-to-day compatibility today.\n\n\
+   (Force WebP / force-webp: convert to lossless image/webp before sending for smaller transfers; receiving apps need WebP support.)\n\n\
 2) Multi-MIME (multi)\n\
    - Offer both the original format (e.g. image/jpeg) and a PNG fallback.\n\
    - In theory best-of-both, but known issue: some Electron apps may freeze on paste when relayed JPEG MIME offers exist.\n\
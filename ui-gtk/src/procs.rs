@@ -16,6 +16,38 @@ pub struct Procs {
     pub x11: Option<Child>,
 }
 
+/// Same dir node uses (honors `MCR_STATE_DIR`).
+fn node_state_dir() -> PathBuf {
+    utils::paths::default_state_dir()
+}
+
+/// Ask running node services to re-join `room` (same control file as `node switch-room`),
+/// so editing the room does not require restarting wl-watch/wl-apply.
+pub fn request_room_switch(room: &str) -> std::io::Result<()> {
+    utils::paths::request_room_switch(&node_state_dir(), room)
+}
+
+/// Same flag file as `node pause --room <room>`.
+fn paused_flag_path(room: &str) -> PathBuf {
+    utils::paths::paused_path(&node_state_dir(), room.trim())
+}
+
+pub fn is_room_paused(room: &str) -> bool {
+    paused_flag_path(room).exists()
+}
+
+/// Pause/resume sync for `room`; running services keep their connections.
+pub fn set_room_paused(room: &str, paused: bool) -> anyhow::Result<()> {
+    let p = paused_flag_path(room);
+    if paused {
+        std::fs::create_dir_all(node_state_dir()).context("mkdir state dir")?;
+        std::fs::write(&p, "ui\n").context("write pause flag")?;
+    } else if p.exists() {
+        std::fs::remove_file(&p).context("remove pause flag")?;
+    }
+    Ok(())
+}
+
 pub fn terminate_child(mut child: Child, label: &'static str, log_tx: mpsc::Sender<String>) {
     // Best-effort graceful shutdown so `node wl-watch` can clean up its `wl-paste --watch` children.
     thread::spawn(move || {
@@ -3,7 +3,7 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 
-use crate::config::UiConfig;
+use crate::config::{SyncKinds, UiConfig};
 
 pub const UNIT_RELAY: &str = "multicliprelay-relay.service";
 pub const UNIT_WL_WATCH: &str = "multicliprelay-wl-watch.service";
@@ -236,6 +236,22 @@ pub fn write_env_from_ui_config(cfg: &UiConfig) -> anyhow::Result<()> {
         cfg.x11_poll_interval_ms
     ));
 
+    let received_dir = cfg.received_dir.trim();
+    if !received_dir.is_empty() {
+        lines.push(format!("MCR_RECEIVED_DIR={received_dir}"));
+    }
+
+    let device_name = cfg.device_name.trim();
+    if !device_name.is_empty() {
+        lines.push(format!("MCR_NAME={device_name}"));
+    }
+
+    if !cfg.image_priority.is_empty() {
+        lines.push(format!("MCR_IMAGE_PRIORITY={}", cfg.image_priority.join(",")));
+    }
+
+    lines.extend(SyncKinds::from_config(cfg).env_lines());
+
     if cfg.debug_mode {
         lines.push(format!("RUST_LOG={}", rust_log_for_debug()));
         lines.push("MCR_WL_WATCH_DEBUG=1".to_string());
@@ -246,6 +262,25 @@ pub fn write_env_from_ui_config(cfg: &UiConfig) -> anyhow::Result<()> {
 }
 
 pub fn apply_runtime_env_from_ui_config(cfg: &UiConfig) {
+    // Inherited by node processes spawned directly by the UI.
+    let received_dir = cfg.received_dir.trim();
+    if received_dir.is_empty() {
+        std::env::remove_var("MCR_RECEIVED_DIR");
+    } else {
+        std::env::set_var("MCR_RECEIVED_DIR", received_dir);
+    }
+    let device_name = cfg.device_name.trim();
+    if device_name.is_empty() {
+        std::env::remove_var("MCR_NAME");
+    } else {
+        std::env::set_var("MCR_NAME", device_name);
+    }
+    if cfg.image_priority.is_empty() {
+        std::env::remove_var("MCR_IMAGE_PRIORITY");
+    } else {
+        std::env::set_var("MCR_IMAGE_PRIORITY", cfg.image_priority.join(","));
+    }
+
     if cfg.debug_mode {
         std::env::set_var("RUST_LOG", rust_log_for_debug());
         std::env::set_var("MCR_WL_WATCH_DEBUG", "1");
@@ -2,7 +2,6 @@ mod wl_clipboard_logs;
 mod table;
 use glib::clone;
 use gtk4::prelude::*;
-use gtk4::gdk;
 
 use std::cell::Cell;
 use std::rc::Rc;
@@ -11,9 +10,9 @@ use std::sync::{mpsc, Arc, Mutex};
 use crate::config::{config_path, load_config, save_config};
 use crate::i18n::{
     detect_lang_from_env, help_text, image_mode_hint_text, parse_lang_id,
-    populate_image_mode_combo, t, Lang, K,
+    populate_history_kind_combo, populate_image_mode_combo, populate_theme_combo, t, Lang, K,
 };
-use crate::procs::Procs;
+use crate::procs::{self, Procs};
 use crate::systemd;
 
 mod apply_lang;
@@ -23,7 +22,9 @@ mod constants;
 mod diagnostics;
 mod helpers;
 mod history;
+mod qr;
 mod services;
+mod theme;
 mod timers;
 
 use self::apply_lang::{make_apply_lang, ApplyLangCtx};
@@ -31,12 +32,17 @@ use self::config_wiring::connect_config_wiring;
 use self::connection::install_relay_probe;
 use self::constants::{LANG_AUTO_ID, PAGE_ACTIVITY, PAGE_CONTROL, PAGE_HELP};
 use self::diagnostics::connect_diagnostics_handlers;
-use self::history::{install_history_refresh, make_history_table};
+use self::history::{
+    install_history_export, install_history_filter, install_history_refresh, make_history_table,
+};
+use self::qr::build_qr_button;
 use self::services::{
-    connect_service_handlers, make_update_services_ui, ServiceConfigInputs, ServiceWidgets,
+    autostart_services, connect_service_handlers, make_update_services_ui, ServiceConfigInputs,
+    ServiceWidgets,
 };
 use self::timers::{install_close_handler, install_log_drain, install_prune_timer};
 use self::table::{make_tabbed_table, ColumnSpec};
+use self::theme::apply_theme;
 use self::wl_clipboard_logs::build_wl_clipboard_logs_widget;
 
 pub fn build_ui(app: &gtk4::Application) {
@@ -78,52 +84,8 @@ pub fn build_ui(app: &gtk4::Application) {
 
     let (log_tx, log_rx) = mpsc::channel::<String>();
 
-        // App CSS (row zebra stripes for ColumnView-based tables).
-        if let Some(display) = gdk::Display::default() {
-                let provider = gtk4::CssProvider::new();
-                // Keep it subtle so selection highlight still stands out.
-                // Use theme colors so it works in both light and dark themes.
-                let css = r#"
-.mcr-cell {
-    padding: 4px 10px;
-}
-
-/* Make small in-cell action buttons not inflate row height. */
-.mcr-compact-btn {
-    padding: 1px 8px;
-    min-height: 0;
-    min-width: 0;
-}
-
-/* Force consistent row height across all columns. */
-columnview row,
-columnview listview row {
-    min-height: 28px;
-}
-
-/* Zebra stripes: color the whole row (not individual cells) to avoid a chopped look. */
-columnview row:nth-child(odd),
-columnview listview row:nth-child(odd) {
-    background-color: transparent;
-}
-
-columnview row:nth-child(even),
-columnview listview row:nth-child(even) {
-    background-color: alpha(@theme_fg_color, 0.028);
-}
-
-columnview row:hover:not(:selected),
-columnview listview row:hover:not(:selected) {
-    background-color: alpha(@theme_fg_color, 0.050);
-}
-"#;
-                provider.load_from_data(css);
-                gtk4::style_context_add_provider_for_display(
-                        &display,
-                        &provider,
-                        gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
-                );
-        }
+    // Light/dark preference, then the app CSS on top of it.
+    apply_theme(&cfg.theme);
 
     let window = gtk4::ApplicationWindow::builder()
         .application(app)
@@ -152,6 +114,10 @@ columnview listview row:hover:not(:selected) {
 
     let relay_entry = gtk4::Entry::builder().text(&cfg.relay_addr).build();
     let room_entry = gtk4::Entry::builder().text(&cfg.room).build();
+    let device_name_entry = gtk4::Entry::builder()
+        .text(&cfg.device_name)
+        .placeholder_text(t(initial_lang, K::DeviceNamePlaceholder))
+        .build();
 
     let max_text_adj = gtk4::Adjustment::new(
         cfg.max_text_bytes as f64,
@@ -200,6 +166,9 @@ columnview listview row:hover:not(:selected) {
     // GTK may emit `changed`, which would call back into apply_lang.
     let suppress_lang_combo = Rc::new(Cell::new(false));
 
+    let theme_combo = gtk4::ComboBoxText::new();
+    populate_theme_combo(&theme_combo, initial_lang, Some(&cfg.theme));
+
     let image_mode_combo = gtk4::ComboBoxText::new();
     let suppress_mode_combo = Rc::new(Cell::new(false));
 
@@ -237,17 +206,41 @@ columnview listview row:hover:not(:selected) {
     let lbl_x11_poll = gtk4::Label::builder().xalign(0.0).build();
     let lbl_img_mode = gtk4::Label::builder().xalign(0.0).build();
     let lbl_lang = gtk4::Label::builder().xalign(0.0).build();
+    let lbl_theme = gtk4::Label::builder().xalign(0.0).build();
     let lbl_debug = gtk4::Label::builder().xalign(0.0).build();
+    let lbl_device_name = gtk4::Label::builder().xalign(0.0).build();
+    let lbl_sync_kinds = gtk4::Label::builder().xalign(0.0).build();
+    let lbl_autostart = gtk4::Label::builder().xalign(0.0).build();
+
+    let sync_text_check = gtk4::CheckButton::builder()
+        .active(cfg.sync_text)
+        .label(t(initial_lang, K::HistoryKindText))
+        .build();
+    let sync_image_check = gtk4::CheckButton::builder()
+        .active(cfg.sync_image)
+        .label(t(initial_lang, K::HistoryKindImage))
+        .build();
+    let sync_file_check = gtk4::CheckButton::builder()
+        .active(cfg.sync_file)
+        .label(t(initial_lang, K::HistoryKindFile))
+        .build();
 
     let debug_check = gtk4::CheckButton::builder()
         .active(cfg.debug_mode)
         .label(t(initial_lang, K::LabelDebugEnable))
         .build();
 
+    let autostart_check = gtk4::CheckButton::builder()
+        .active(cfg.autostart_services)
+        .label(t(initial_lang, K::LabelAutostartEnable))
+        .build();
+
     config_grid.attach(&lbl_relay, 0, 0, 1, 1);
     config_grid.attach(&relay_entry, 1, 0, 1, 1);
     config_grid.attach(&lbl_room, 2, 0, 1, 1);
     config_grid.attach(&room_entry, 3, 0, 1, 1);
+    let qr_btn = build_qr_button(initial_lang, &relay_entry, &room_entry);
+    config_grid.attach(&qr_btn, 4, 0, 1, 1);
 
     config_grid.attach(&lbl_max_text, 0, 1, 1, 1);
     config_grid.attach(&max_text_spin, 1, 1, 1, 1);
@@ -267,8 +260,22 @@ columnview listview row:hover:not(:selected) {
     config_grid.attach(&lbl_lang, 0, 6, 1, 1);
     config_grid.attach(&language_combo, 1, 6, 3, 1);
 
-    config_grid.attach(&lbl_debug, 0, 7, 1, 1);
-    config_grid.attach(&debug_check, 1, 7, 3, 1);
+    config_grid.attach(&lbl_theme, 0, 7, 1, 1);
+    config_grid.attach(&theme_combo, 1, 7, 3, 1);
+
+    config_grid.attach(&lbl_debug, 0, 8, 1, 1);
+    config_grid.attach(&debug_check, 1, 8, 3, 1);
+
+    config_grid.attach(&lbl_device_name, 0, 9, 1, 1);
+    config_grid.attach(&device_name_entry, 1, 9, 3, 1);
+
+    config_grid.attach(&lbl_sync_kinds, 0, 10, 1, 1);
+    config_grid.attach(&sync_text_check, 1, 10, 1, 1);
+    config_grid.attach(&sync_image_check, 2, 10, 1, 1);
+    config_grid.attach(&sync_file_check, 3, 10, 1, 1);
+
+    config_grid.attach(&lbl_autostart, 0, 11, 1, 1);
+    config_grid.attach(&autostart_check, 1, 11, 3, 1);
 
     config_frame.set_child(Some(&config_grid));
 
@@ -359,6 +366,30 @@ columnview listview row:hover:not(:selected) {
     services_actions.append(&start_all);
     services_actions.append(&stop_all);
 
+    // Pause keeps services connected but stops publishing/applying for the current room.
+    let pause_toggle = gtk4::ToggleButton::with_label(t(initial_lang, K::BtnPauseSync));
+    pause_toggle.set_active(procs::is_room_paused(&room_entry.text()));
+    pause_toggle.connect_toggled(clone!(@weak room_entry, @strong log_tx => move |b| {
+        let room = room_entry.text().to_string();
+        if procs::is_room_paused(&room) == b.is_active() {
+            // Synced from the room entry below; nothing to write.
+            return;
+        }
+        match procs::set_room_paused(&room, b.is_active()) {
+            Ok(()) if b.is_active() => { let _ = log_tx.send(format!("paused sync for room '{room}'")); }
+            Ok(()) => { let _ = log_tx.send(format!("resumed sync for room '{room}'")); }
+            Err(e) => { let _ = log_tx.send(format!("pause toggle failed: {e:?}")); }
+        }
+    }));
+    // Reflect the flag of whichever room is being edited.
+    room_entry.connect_changed(clone!(@weak pause_toggle => move |e| {
+        let paused = procs::is_room_paused(&e.text());
+        if pause_toggle.is_active() != paused {
+            pause_toggle.set_active(paused);
+        }
+    }));
+    services_actions.append(&pause_toggle);
+
     let services_box = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
     services_box.append(&services_actions);
     services_box.append(&services_grid);
@@ -402,8 +433,14 @@ columnview listview row:hover:not(:selected) {
 
     // --- Activity view (sub-tabs) ---
     // 1) Sync history
-    let history_table = make_history_table(initial_lang, &cfg.history_columns);
+    let history_table = make_history_table(initial_lang, &cfg.history_columns, log_tx.clone());
     let clear_history = gtk4::Button::with_label(t(initial_lang, K::BtnClearHistory));
+    let export_history = gtk4::Button::with_label(t(initial_lang, K::BtnExportHistory));
+    let history_search = gtk4::SearchEntry::new();
+    history_search.set_placeholder_text(Some(t(initial_lang, K::HistorySearchPlaceholder)));
+    history_search.set_hexpand(true);
+    let history_kind_combo = gtk4::ComboBoxText::new();
+    populate_history_kind_combo(&history_kind_combo, initial_lang, Some(""));
 
     // Column visibility settings (persisted in ui.toml).
     let columns_btn = gtk4::MenuButton::builder()
@@ -432,6 +469,7 @@ columnview listview row:hover:not(:selected) {
             (Lang::ZhCn, "bytes") => "大小".into(),
             (Lang::ZhCn, "extra") => "详情".into(),
             (Lang::ZhCn, "preview") => "预览".into(),
+            (Lang::ZhCn, "resend") => "重发".into(),
             (_, other) => other.to_string(),
         }
     };
@@ -464,9 +502,10 @@ columnview listview row:hover:not(:selected) {
     history_actions.set_margin_start(4);
     history_actions.set_margin_end(4);
     history_actions.append(&clear_history);
-    let history_actions_spacer = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
-    history_actions_spacer.set_hexpand(true);
-    history_actions.append(&history_actions_spacer);
+    history_actions.append(&export_history);
+    // The search entry expands and takes the spacer's place.
+    history_actions.append(&history_search);
+    history_actions.append(&history_kind_combo);
     history_actions.append(&columns_btn);
 
     let history_box = gtk4::Box::new(gtk4::Orientation::Vertical, 8);
@@ -567,6 +606,7 @@ columnview listview row:hover:not(:selected) {
         window: window.clone(),
         stack: stack.clone(),
         language_combo: language_combo.clone(),
+        theme_combo: theme_combo.clone(),
         image_mode_combo: image_mode_combo.clone(),
         suppress_lang_combo: suppress_lang_combo.clone(),
         suppress_mode_combo: suppress_mode_combo.clone(),
@@ -574,6 +614,9 @@ columnview listview row:hover:not(:selected) {
         help_buf: help_buf.clone(),
         clear_logs_btn: clear_logs.clone(),
         clear_history_btn: clear_history.clone(),
+        export_history_btn: export_history.clone(),
+        history_search: history_search.clone(),
+        history_kind_combo: history_kind_combo.clone(),
         reload_btn: reload_btn.clone(),
 
         tab_history_lbl: tab_history_lbl.clone(),
@@ -587,8 +630,19 @@ columnview listview row:hover:not(:selected) {
             lbl_x11_poll: lbl_x11_poll.clone(),
         lbl_img_mode: lbl_img_mode.clone(),
         lbl_lang: lbl_lang.clone(),
+        lbl_theme: lbl_theme.clone(),
         lbl_debug: lbl_debug.clone(),
+        lbl_device_name: lbl_device_name.clone(),
+        lbl_sync_kinds: lbl_sync_kinds.clone(),
+        sync_text_check: sync_text_check.clone(),
+        sync_image_check: sync_image_check.clone(),
+        sync_file_check: sync_file_check.clone(),
+        device_name_entry: device_name_entry.clone(),
+        qr_btn: qr_btn.clone(),
+        pause_toggle: pause_toggle.clone(),
         debug_check: debug_check.clone(),
+        lbl_autostart: lbl_autostart.clone(),
+        autostart_check: autostart_check.clone(),
         lbl_relay_tcp: svc_lbl_relay_tcp.clone(),
         start_relay: start_relay_btn.clone(),
         stop_relay: stop_relay_btn.clone(),
@@ -622,6 +676,19 @@ columnview listview row:hover:not(:selected) {
         clear_history.clone(),
         log_tx.clone(),
         lang_state.clone(),
+        cfg.history_max_rows,
+    );
+    install_history_filter(
+        history_search.clone(),
+        history_kind_combo.clone(),
+        history_table.filter.clone(),
+        history_table.filter_state.clone(),
+    );
+    install_history_export(
+        export_history.clone(),
+        window.clone(),
+        log_tx.clone(),
+        lang_state.clone(),
     );
 
     // --- Button handlers (services) ---
@@ -639,6 +706,9 @@ columnview listview row:hover:not(:selected) {
                         x11_poll_spin: x11_poll_spin.clone(),
             image_mode_combo: image_mode_combo.clone(),
             debug_check: debug_check.clone(),
+            sync_text_check: sync_text_check.clone(),
+            sync_image_check: sync_image_check.clone(),
+            sync_file_check: sync_file_check.clone(),
         },
     );
 
@@ -673,14 +743,20 @@ columnview listview row:hover:not(:selected) {
                         x11_poll_spin: x11_poll_spin.clone(),
             relay_entry: relay_entry.clone(),
             room_entry: room_entry.clone(),
+            device_name_entry: device_name_entry.clone(),
             max_text_spin: max_text_spin.clone(),
             max_image_spin: max_image_spin.clone(),
             max_file_spin: max_file_spin.clone(),
             language_combo: language_combo.clone(),
+            theme_combo: theme_combo.clone(),
             image_mode_combo: image_mode_combo.clone(),
             mode_hint: mode_hint.clone(),
             reload_btn: reload_btn.clone(),
             debug_check: debug_check.clone(),
+            sync_text_check: sync_text_check.clone(),
+            sync_image_check: sync_image_check.clone(),
+            sync_file_check: sync_file_check.clone(),
+            autostart_check: autostart_check.clone(),
         },
         suppress_save_cfg: suppress_save_cfg.clone(),
         suppress_lang_combo: suppress_lang_combo.clone(),
@@ -713,6 +789,9 @@ columnview listview row:hover:not(:selected) {
     // Prune exited child processes and keep UI state correct.
     install_prune_timer(procs.clone(), use_systemd, log_tx.clone(), update_services_ui.clone());
 
+    // Autostart goes through the Start All handler, so it starts exactly what a click would.
+    autostart_services(&cfg, &procs, use_systemd, &start_all, &log_tx);
+
     root.append(&stack);
     window.set_child(Some(&root));
     window.show();
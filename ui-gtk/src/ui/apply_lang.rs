@@ -3,7 +3,10 @@ use gtk4::prelude::*;
 use std::cell::Cell;
 use std::rc::Rc;
 
-use crate::i18n::{help_text, image_mode_hint_text, populate_image_mode_combo, t, Lang, K};
+use crate::i18n::{
+    help_text, image_mode_hint_text, populate_history_kind_combo, populate_image_mode_combo,
+    populate_theme_combo, t, Lang, K,
+};
 
 use super::constants::{
     DEFAULT_IMAGE_MODE_ID, LANG_AUTO_ID, PAGE_ACTIVITY, PAGE_CONTROL, PAGE_HELP,
@@ -15,6 +18,7 @@ pub struct ApplyLangCtx {
     pub stack: gtk4::Stack,
 
     pub language_combo: gtk4::ComboBoxText,
+    pub theme_combo: gtk4::ComboBoxText,
     pub image_mode_combo: gtk4::ComboBoxText,
 
     pub suppress_lang_combo: Rc<Cell<bool>>,
@@ -25,6 +29,9 @@ pub struct ApplyLangCtx {
 
     pub clear_logs_btn: gtk4::Button,
     pub clear_history_btn: gtk4::Button,
+    pub export_history_btn: gtk4::Button,
+    pub history_search: gtk4::SearchEntry,
+    pub history_kind_combo: gtk4::ComboBoxText,
     pub reload_btn: gtk4::Button,
 
     // Activity sub-tabs
@@ -41,8 +48,19 @@ pub struct ApplyLangCtx {
     pub lbl_x11_poll: gtk4::Label,
     pub lbl_img_mode: gtk4::Label,
     pub lbl_lang: gtk4::Label,
+    pub lbl_theme: gtk4::Label,
     pub lbl_debug: gtk4::Label,
+    pub lbl_device_name: gtk4::Label,
+    pub lbl_sync_kinds: gtk4::Label,
+    pub sync_text_check: gtk4::CheckButton,
+    pub sync_image_check: gtk4::CheckButton,
+    pub sync_file_check: gtk4::CheckButton,
+    pub device_name_entry: gtk4::Entry,
+    pub qr_btn: gtk4::MenuButton,
+    pub pause_toggle: gtk4::ToggleButton,
     pub debug_check: gtk4::CheckButton,
+    pub lbl_autostart: gtk4::Label,
+    pub autostart_check: gtk4::CheckButton,
 
     // Services / status labels
     pub lbl_relay_tcp: gtk4::Label,
@@ -95,8 +113,21 @@ pub fn make_apply_lang(ctx: ApplyLangCtx) -> Rc<dyn Fn(Lang)> {
         ctx.lbl_x11_poll.set_text(t(lang, K::LabelX11PollIntervalMs));
         ctx.lbl_img_mode.set_text(t(lang, K::LabelImageMode));
         ctx.lbl_lang.set_text(t(lang, K::LabelLanguage));
+        ctx.lbl_theme.set_text(t(lang, K::LabelTheme));
         ctx.lbl_debug.set_text(t(lang, K::LabelDebugMode));
+        ctx.lbl_device_name.set_text(t(lang, K::LabelDeviceName));
+        ctx.lbl_sync_kinds.set_text(t(lang, K::LabelSyncKinds));
+        ctx.sync_text_check
+            .set_label(Some(t(lang, K::HistoryKindText)));
+        ctx.sync_image_check
+            .set_label(Some(t(lang, K::HistoryKindImage)));
+        ctx.sync_file_check
+            .set_label(Some(t(lang, K::HistoryKindFile)));
+        ctx.device_name_entry
+            .set_placeholder_text(Some(t(lang, K::DeviceNamePlaceholder)));
         ctx.lbl_relay_tcp.set_text(t(lang, K::LabelRelayTcp));
+        ctx.qr_btn.set_label(t(lang, K::BtnShowQr));
+        ctx.pause_toggle.set_label(t(lang, K::BtnPauseSync));
 
         // Buttons
         ctx.start_relay.set_label(t(lang, K::BtnStartRelay));
@@ -117,9 +148,16 @@ pub fn make_apply_lang(ctx: ApplyLangCtx) -> Rc<dyn Fn(Lang)> {
 
         ctx.debug_check
             .set_label(Some(t(lang, K::LabelDebugEnable)));
+        ctx.lbl_autostart.set_text(t(lang, K::LabelAutostart));
+        ctx.autostart_check
+            .set_label(Some(t(lang, K::LabelAutostartEnable)));
 
         ctx.clear_logs_btn.set_label(t(lang, K::BtnClearLogs));
         ctx.clear_history_btn.set_label(t(lang, K::BtnClearHistory));
+        ctx.export_history_btn.set_label(t(lang, K::BtnExportHistory));
+        ctx.history_search
+            .set_placeholder_text(Some(t(lang, K::HistorySearchPlaceholder)));
+        populate_history_kind_combo(&ctx.history_kind_combo, lang, None);
         ctx.reload_btn.set_label(t(lang, K::BtnReloadConfig));
 
         ctx.tab_history_lbl.set_text(t(lang, K::SubTabHistory));
@@ -143,6 +181,9 @@ pub fn make_apply_lang(ctx: ApplyLangCtx) -> Rc<dyn Fn(Lang)> {
         ctx.language_combo.set_active_id(Some(&active_lang));
         ctx.suppress_lang_combo.set(false);
 
+        // Theme combo labels (keep active id)
+        populate_theme_combo(&ctx.theme_combo, lang, None);
+
         // Image mode combo labels (keep active id)
         ctx.suppress_mode_combo.set(true);
         populate_image_mode_combo(&ctx.image_mode_combo, lang, None);
@@ -8,23 +8,31 @@ use std::sync::{mpsc, Arc, Mutex};
 
 use crate::config::{load_config, save_config};
 use crate::i18n::{detect_lang_from_env, image_mode_hint_text, parse_lang_id, Lang};
+use crate::procs;
 use crate::systemd;
 
 use super::constants::{DEFAULT_IMAGE_MODE_ID, LANG_AUTO_ID};
+use super::theme::apply_theme;
 
 #[derive(Clone)]
 pub struct ConfigWidgets {
     pub relay_entry: gtk4::Entry,
     pub room_entry: gtk4::Entry,
+    pub device_name_entry: gtk4::Entry,
     pub max_text_spin: gtk4::SpinButton,
     pub max_image_spin: gtk4::SpinButton,
     pub max_file_spin: gtk4::SpinButton,
     pub x11_poll_spin: gtk4::SpinButton,
     pub language_combo: gtk4::ComboBoxText,
+    pub theme_combo: gtk4::ComboBoxText,
     pub image_mode_combo: gtk4::ComboBoxText,
     pub mode_hint: gtk4::Label,
     pub reload_btn: gtk4::Button,
     pub debug_check: gtk4::CheckButton,
+    pub sync_text_check: gtk4::CheckButton,
+    pub sync_image_check: gtk4::CheckButton,
+    pub sync_file_check: gtk4::CheckButton,
+    pub autostart_check: gtk4::CheckButton,
 }
 
 pub struct ConfigWiringCtx {
@@ -59,13 +67,21 @@ pub fn make_save_cfg(cfg_path: PathBuf, ui: ConfigWidgets) -> Rc<dyn Fn()> {
 
         cfg.relay_addr = ui.relay_entry.text().to_string();
         cfg.room = ui.room_entry.text().to_string();
+        cfg.device_name = ui.device_name_entry.text().trim().to_string();
         cfg.max_text_bytes = ui.max_text_spin.value() as usize;
         cfg.max_image_bytes = ui.max_image_spin.value() as usize;
         cfg.max_file_bytes = ui.max_file_spin.value() as usize;
         cfg.image_mode = image_mode;
         cfg.x11_poll_interval_ms = ui.x11_poll_spin.value() as u64;
         cfg.language = language;
+        if let Some(theme) = ui.theme_combo.active_id() {
+            cfg.theme = theme.to_string();
+        }
         cfg.debug_mode = ui.debug_check.is_active();
+        cfg.sync_text = ui.sync_text_check.is_active();
+        cfg.sync_image = ui.sync_image_check.is_active();
+        cfg.sync_file = ui.sync_file_check.is_active();
+        cfg.autostart_services = ui.autostart_check.is_active();
         cfg.force_png = None;
         if let Err(e) = save_config(&cfg_path, &cfg) {
             eprintln!("save config failed: {:?}", e);
@@ -94,15 +110,21 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
     let ConfigWidgets {
         relay_entry,
         room_entry,
+        device_name_entry,
         max_text_spin,
         max_image_spin,
         max_file_spin,
         x11_poll_spin,
         language_combo,
+        theme_combo,
         image_mode_combo,
         mode_hint,
         reload_btn,
         debug_check,
+        sync_text_check,
+        sync_image_check,
+        sync_file_check,
+        autostart_check,
     } = ui;
 
     // Save config on change (simple + good enough)
@@ -122,6 +144,26 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
             (save_cfg)();
         }),
     );
+    // Running services follow the room live, but only once the edit is done (Enter or leaving
+    // the field): switching per keystroke would join every prefix of the name on the way.
+    // The env file covers the next start either way.
+    room_entry.connect_activate(|entry| {
+        let _ = procs::request_room_switch(&entry.text());
+    });
+    let room_focus = gtk4::EventControllerFocus::new();
+    room_focus.connect_leave(clone!(@weak room_entry => move |_| {
+        let _ = procs::request_room_switch(&room_entry.text());
+    }));
+    room_entry.add_controller(room_focus);
+    // Takes effect on the next service start (the name is read once at startup).
+    device_name_entry.connect_changed(
+        clone!(@strong save_cfg, @strong suppress_save_cfg => move |_| {
+            if suppress_save_cfg.get() {
+                return;
+            }
+            (save_cfg)();
+        }),
+    );
     max_text_spin.connect_value_changed(
         clone!(@strong save_cfg, @strong suppress_save_cfg => move |_| {
             if suppress_save_cfg.get() {
@@ -188,6 +230,18 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
         (save_cfg)();
     }));
 
+    theme_combo.connect_changed(clone!(@strong save_cfg, @strong suppress_save_cfg => move |combo| {
+        // No active id while the labels are being repopulated.
+        let Some(theme) = combo.active_id() else {
+            return;
+        };
+        if suppress_save_cfg.get() {
+            return;
+        }
+        apply_theme(&theme);
+        (save_cfg)();
+    }));
+
     debug_check.connect_toggled(clone!(@strong save_cfg, @strong suppress_save_cfg => move |_| {
         if suppress_save_cfg.get() {
             return;
@@ -195,6 +249,24 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
         (save_cfg)();
     }));
 
+    // Read on the next launch.
+    autostart_check.connect_toggled(clone!(@strong save_cfg, @strong suppress_save_cfg => move |_| {
+        if suppress_save_cfg.get() {
+            return;
+        }
+        (save_cfg)();
+    }));
+
+    // Takes effect on the next wl-watch / wl-apply start.
+    for check in [&sync_text_check, &sync_image_check, &sync_file_check] {
+        check.connect_toggled(clone!(@strong save_cfg, @strong suppress_save_cfg => move |_| {
+            if suppress_save_cfg.get() {
+                return;
+            }
+            (save_cfg)();
+        }));
+    }
+
     // Reload config from disk and apply into the UI.
     reload_btn.connect_clicked(clone!(
         @strong log_tx,
@@ -207,14 +279,20 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
         @strong suppress_mode_combo,
         @weak relay_entry,
         @weak room_entry,
+        @weak device_name_entry,
         @weak max_text_spin,
         @weak max_image_spin,
         @weak max_file_spin,
         @weak x11_poll_spin,
         @weak language_combo,
+        @weak theme_combo,
         @weak image_mode_combo,
         @weak mode_hint,
-        @weak debug_check
+        @weak debug_check,
+        @weak sync_text_check,
+        @weak sync_image_check,
+        @weak sync_file_check,
+        @weak autostart_check
         => move |_| {
             match load_config(&cfg_path) {
                 Ok(cfg) => {
@@ -222,6 +300,7 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
 
                     relay_entry.set_text(&cfg.relay_addr);
                     room_entry.set_text(&cfg.room);
+                    device_name_entry.set_text(&cfg.device_name);
                     max_text_spin.set_value(cfg.max_text_bytes as f64);
                     max_image_spin.set_value(cfg.max_image_bytes as f64);
                     max_file_spin.set_value(cfg.max_file_bytes as f64);
@@ -231,6 +310,9 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
                     language_combo.set_active_id(Some(&cfg.language));
                     suppress_lang_combo.set(false);
 
+                    theme_combo.set_active_id(Some(&cfg.theme));
+                    apply_theme(&cfg.theme);
+
                     // Mode id update; labels are refreshed by apply_lang.
                     suppress_mode_combo.set(true);
                     image_mode_combo.set_active_id(Some(&cfg.image_mode));
@@ -251,6 +333,10 @@ pub fn connect_config_wiring(ctx: ConfigWiringCtx) {
                     mode_hint.set_text(image_mode_hint_text(lang, &mode));
 
                     debug_check.set_active(cfg.debug_mode);
+                    sync_text_check.set_active(cfg.sync_text);
+                    sync_image_check.set_active(cfg.sync_image);
+                    sync_file_check.set_active(cfg.sync_file);
+                    autostart_check.set_active(cfg.autostart_services);
 
                     suppress_save_cfg.set(false);
                     let _ = systemd::write_env_from_ui_config(&cfg);
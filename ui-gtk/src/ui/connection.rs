@@ -1,58 +1,12 @@
 use glib::clone;
 use gtk4::prelude::*;
 
-use std::net::{TcpStream, ToSocketAddrs};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use crate::i18n::{t, Lang, K};
-use crate::util::normalize_relay_addr_for_connect;
-
-#[derive(Debug, Clone)]
-struct ProbeResult {
-    ok: bool,
-    detail: String,
-}
-
-fn probe_tcp(addr: &str, timeout: Duration) -> ProbeResult {
-    let addr = normalize_relay_addr_for_connect(addr);
-    let addr = addr.trim();
-    if addr.is_empty() {
-        return ProbeResult {
-            ok: false,
-            detail: "empty address".to_string(),
-        };
-    }
-
-    let mut addrs = match addr.to_socket_addrs() {
-        Ok(it) => it,
-        Err(e) => {
-            return ProbeResult {
-                ok: false,
-                detail: format!("resolve failed: {e}"),
-            };
-        }
-    };
-
-    let Some(sock) = addrs.next() else {
-        return ProbeResult {
-            ok: false,
-            detail: "no socket addresses".to_string(),
-        };
-    };
-
-    match TcpStream::connect_timeout(&sock, timeout) {
-        Ok(_) => ProbeResult {
-            ok: true,
-            detail: sock.to_string(),
-        },
-        Err(e) => ProbeResult {
-            ok: false,
-            detail: format!("{sock}: {e}"),
-        },
-    }
-}
+use utils::probe::{probe_ping, ProbeResult};
 
 pub fn install_relay_probe(
     relay_entry: gtk4::Entry,
@@ -105,7 +59,9 @@ pub fn install_relay_probe(
                 last_addr = a;
             }
 
-            let r = probe_tcp(&last_addr, Duration::from_millis(250));
+            // A ping, not a bare connect: a port held by some other service must not look
+            // connected. The detail carries the round-trip time for the tooltip.
+            let r = probe_ping(&last_addr, Duration::from_millis(800));
 
             // Avoid spamming the UI channel with identical results.
             let fingerprint = (r.ok, r.detail.clone());
@@ -13,6 +13,9 @@ use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
 
 use crate::i18n::{t, Lang, K};
+use crate::procs::spawn_node;
+use crate::util::normalize_relay_addr_for_connect;
+use utils::paths::{first_8, kept_text_path, received_dir, received_file_path};
 
 use super::table::keep_scroll_tail;
 
@@ -28,6 +31,32 @@ struct HistoryRow {
     bytes: String,
     extra: String,
     preview_path: Option<PathBuf>,
+    /// Event kind shared by a main row and its detail row, so the filter keeps them together.
+    group_kind: String,
+    /// Lowercased name/peer/kind/extra of the whole event (see `history_row_matches`).
+    haystack: String,
+    /// `node resend ...` arguments when the payload is still on disk.
+    resend_args: Option<Vec<String>>,
+    /// CSS class for sends that were skipped or failed, so they stand apart from real syncs.
+    style: Option<&'static str>,
+}
+
+/// Active history filter (search entry + kind dropdown).
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Lowercased, trimmed substring; empty matches everything.
+    query: String,
+    /// `text` / `image` / `file`; `None` matches every kind.
+    kind: Option<String>,
+}
+
+fn history_row_matches(row: &HistoryRow, f: &HistoryFilter) -> bool {
+    if let Some(k) = f.kind.as_deref() {
+        if row.group_kind != k {
+            return false;
+        }
+    }
+    f.query.is_empty() || row.haystack.contains(&f.query)
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -45,10 +74,16 @@ struct HistoryEvent {
     name: Option<String>,
     bytes: Option<usize>,
     sha256: Option<String>,
+    /// `sent` / `recv` / `skipped` / `failed` (missing on older lines).
+    outcome: Option<String>,
+    reason: Option<String>,
 }
 
 pub struct HistoryTable {
     pub store: gio::ListStore,
+    /// Filters `store` for the view; appends to `store` are filtered as they arrive.
+    pub filter: gtk4::CustomFilter,
+    pub filter_state: Rc<RefCell<HistoryFilter>>,
     #[allow(dead_code)]
     pub selection: gtk4::SingleSelection,
     #[allow(dead_code)]
@@ -57,36 +92,6 @@ pub struct HistoryTable {
     pub columns: Vec<(String, gtk4::ColumnViewColumn)>,
 }
 
-fn data_dir_base() -> PathBuf {
-    // Match node default_data_dir().
-    let base = dirs::data_dir().or_else(|| dirs::home_dir().map(|h| h.join(".local/share")));
-    match base {
-        Some(d) => d.join("multicliprelay"),
-        None => PathBuf::from("/tmp").join("multicliprelay"),
-    }
-}
-
-fn received_dir() -> PathBuf {
-    data_dir_base().join("received")
-}
-
-fn safe_for_filename(s: &str) -> String {
-    s.chars()
-        .map(|c| match c {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
-            _ => '_',
-        })
-        .collect()
-}
-
-fn first_8(s: &str) -> &str {
-    if s.len() >= 8 { &s[..8] } else { s }
-}
-
-fn is_tar_payload(name: &str, mime: Option<&str>) -> bool {
-    mime == Some("application/x-tar") || name.to_ascii_lowercase().ends_with(".tar")
-}
-
 fn image_ext_from_mime(mime: &str) -> Option<&'static str> {
     match mime {
         "image/png" => Some("png"),
@@ -94,6 +99,7 @@ fn image_ext_from_mime(mime: &str) -> Option<&'static str> {
         "image/jpg" => Some("jpg"),
         "image/webp" => Some("webp"),
         "image/gif" => Some("gif"),
+        "image/svg+xml" => Some("svg"),
         _ => None,
     }
 }
@@ -157,27 +163,8 @@ fn preview_path_for(e: &HistoryEvent) -> Option<PathBuf> {
         return None;
     }
 
-    let name = e
-        .name
-        .as_deref()
-        .unwrap_or("multicliprelay")
-        .to_string();
-    let safe = safe_for_filename(&name);
-
-    // Mirror node/wl-apply receive paths.
-    if is_tar_payload(&name, e.mime.as_deref()) {
-        let stem = safe
-            .trim_end_matches(".tar")
-            .trim_end_matches(".TAR")
-            .to_string();
-        let out_dir = received_dir().join(format!("{}_{}", sha8, stem));
-        if out_dir.exists() {
-            return Some(out_dir);
-        }
-        return None;
-    }
-
-    let out_path = received_dir().join(&sha8).join(&safe);
+    // Same layout wl-apply writes (bundle dir or `<sha8>/<name>`).
+    let out_path = received_file_path(&received_dir(), sha, e.name.as_deref(), e.mime.as_deref());
     if out_path.exists() {
         Some(out_path)
     } else {
@@ -185,6 +172,46 @@ fn preview_path_for(e: &HistoryEvent) -> Option<PathBuf> {
     }
 }
 
+/// Arguments for `node resend` if the event's payload is still stored (see `preview_path_for`).
+fn resend_args_for(e: &HistoryEvent, preview_path: Option<&PathBuf>) -> Option<Vec<String>> {
+    let kind = e.kind.as_deref()?;
+    let sha = e.sha256.as_deref()?;
+    let stored = match kind {
+        "image" | "file" => preview_path.is_some(),
+        "text" => [false, true]
+            .into_iter()
+            .any(|rtf| kept_text_path(&received_dir(), sha, rtf).exists()),
+        _ => false,
+    };
+    if !stored {
+        return None;
+    }
+
+    let relay = normalize_relay_addr_for_connect(e.relay.as_deref().unwrap_or_default());
+    let mut args = vec![
+        "resend".to_string(),
+        "--room".to_string(),
+        e.room.clone().unwrap_or_else(|| "default".into()),
+        "--kind".to_string(),
+        kind.to_string(),
+        "--sha".to_string(),
+        sha.to_string(),
+    ];
+    if !relay.is_empty() {
+        args.push("--relay".to_string());
+        args.push(relay);
+    }
+    if let Some(mime) = e.mime.as_deref().filter(|m| !m.is_empty()) {
+        args.push("--mime".to_string());
+        args.push(mime.to_string());
+    }
+    if let Some(name) = e.name.as_deref().filter(|n| !n.is_empty()) {
+        args.push("--file-name".to_string());
+        args.push(name.to_string());
+    }
+    Some(args)
+}
+
 fn row_signature(rows: &[HistoryRow]) -> String {
     // Used to avoid pointless UI churn.
     // Include a preview-exists bit so the button state updates when the file disappears.
@@ -207,15 +234,15 @@ fn row_signature(rows: &[HistoryRow]) -> String {
         out.push_str(&r.extra);
         out.push('\t');
         out.push_str(if r.preview_path.is_some() { "1" } else { "0" });
+        out.push_str(if r.resend_args.is_some() { "1" } else { "0" });
         out.push('\n');
     }
     out
 }
 
 pub fn history_path() -> PathBuf {
-    // Match node default_data_dir():
-    // - $XDG_DATA_HOME/multicliprelay (or ~/.local/share/multicliprelay)
-    data_dir_base().join("history.jsonl")
+    // $MCR_DATA_DIR, else $XDG_DATA_HOME/multicliprelay (or ~/.local/share/multicliprelay).
+    utils::paths::history_path()
 }
 
 fn read_tail_lines(path: &PathBuf, max_bytes: u64, max_lines: usize) -> Vec<String> {
@@ -269,9 +296,19 @@ fn fmt_ts(ts_ms: Option<u64>) -> String {
 
 fn format_event_rows(e: HistoryEvent) -> Vec<HistoryRow> {
     let preview_path = preview_path_for(&e);
+    let resend_args = resend_args_for(&e, preview_path.as_ref());
 
     let ts = fmt_ts(e.ts_ms);
-    let dir = e.dir.unwrap_or_else(|| "?".into());
+    let style = match e.outcome.as_deref() {
+        Some("skipped") => Some("dim-label"),
+        Some("failed") => Some("error"),
+        _ => None,
+    };
+    // A send that never went out shows how it ended instead of "send".
+    let dir = match e.outcome {
+        Some(outcome) if style.is_some() => outcome,
+        _ => e.dir.unwrap_or_else(|| "?".into()),
+    };
     let kind = e.kind.unwrap_or_else(|| "?".into());
     let bytes = fmt_bytes(e.bytes);
     let room = e.room.unwrap_or_default();
@@ -312,6 +349,9 @@ fn format_event_rows(e: HistoryEvent) -> Vec<HistoryRow> {
     };
 
     let mut extra = Vec::new();
+    if let Some(reason) = e.reason.filter(|r| !r.is_empty()) {
+        extra.push(format!("reason={}", reason));
+    }
     if let Some(name) = e.name {
         if !name.is_empty() {
             extra.push(format!("name={}", name));
@@ -348,6 +388,9 @@ fn format_event_rows(e: HistoryEvent) -> Vec<HistoryRow> {
         format!("room={room} {extra}")
     };
 
+    let haystack = format!("{name}\n{peer}\n{kind}\n{extra}").to_lowercase();
+    let group_kind = kind.clone();
+
     // Main row + optional detail row.
     // Detail row is meant to "own" the extra info so the main row stays compact.
     let mut rows = Vec::new();
@@ -361,6 +404,10 @@ fn format_event_rows(e: HistoryEvent) -> Vec<HistoryRow> {
         bytes,
         extra: String::new(),
         preview_path,
+        group_kind: group_kind.clone(),
+        haystack: haystack.clone(),
+        resend_args,
+        style,
     });
 
     if !extra.trim().is_empty() {
@@ -374,6 +421,10 @@ fn format_event_rows(e: HistoryEvent) -> Vec<HistoryRow> {
             bytes: String::new(),
             extra: format!("↳ {extra}"),
             preview_path: None,
+            group_kind,
+            haystack,
+            resend_args: None,
+            style: None,
         });
     }
     rows
@@ -383,9 +434,20 @@ fn col_visible(id: &str, cfg: &BTreeMap<String, bool>, default_visible: bool) ->
     cfg.get(id).copied().unwrap_or(default_visible)
 }
 
-pub fn make_history_table(lang: Lang, columns_cfg: &BTreeMap<String, bool>) -> HistoryTable {
+pub fn make_history_table(
+    lang: Lang,
+    columns_cfg: &BTreeMap<String, bool>,
+    log_tx: mpsc::Sender<String>,
+) -> HistoryTable {
     let store = gio::ListStore::new::<glib::BoxedAnyObject>();
-    let selection = gtk4::SingleSelection::new(Some(store.clone()));
+    let filter_state: Rc<RefCell<HistoryFilter>> = Rc::new(RefCell::new(HistoryFilter::default()));
+    let filter = gtk4::CustomFilter::new(clone!(@strong filter_state => move |item| {
+        let Some(obj) = item.downcast_ref::<glib::BoxedAnyObject>() else { return true; };
+        let row = obj.borrow::<HistoryRow>();
+        history_row_matches(&row, &filter_state.borrow())
+    }));
+    let filtered = gtk4::FilterListModel::new(Some(store.clone()), Some(filter.clone()));
+    let selection = gtk4::SingleSelection::new(Some(filtered));
     selection.set_autoselect(false);
     selection.set_can_unselect(true);
 
@@ -427,6 +489,13 @@ pub fn make_history_table(lang: Lang, columns_cfg: &BTreeMap<String, bool>) -> H
             let Some(first) = root.first_child() else { return; };
             let Ok(label) = first.downcast::<gtk4::Label>() else { return; };
             label.set_text(getter(&row));
+            // Cells are recycled: drop a style left over from the previous row.
+            for class in ["dim-label", "error"] {
+                label.remove_css_class(class);
+            }
+            if let Some(class) = row.style {
+                label.add_css_class(class);
+            }
         });
         let col = gtk4::ColumnViewColumn::new(Some(title), Some(factory));
         if let Some(w) = fixed_width {
@@ -437,7 +506,7 @@ pub fn make_history_table(lang: Lang, columns_cfg: &BTreeMap<String, bool>) -> H
         col
     };
 
-    // time(detail) | dir | name | peer(id) | kind | bytes | preview
+    // time(detail) | dir | name | peer(id) | kind | bytes | preview | resend
     let mut columns: Vec<(String, gtk4::ColumnViewColumn)> = Vec::new();
 
     // First column: shows time for main rows, shows extra for detail rows.
@@ -623,6 +692,66 @@ pub fn make_history_table(lang: Lang, columns_cfg: &BTreeMap<String, bool>) -> H
     view.append_column(&preview_col);
     columns.push(("preview".to_string(), preview_col.clone()));
 
+    // resend button column: pushes a stored payload to the room again via `node resend`.
+    let resend_factory = gtk4::SignalListItemFactory::new();
+    resend_factory.connect_setup(move |_, list_item| {
+        let root = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+        root.set_hexpand(true);
+        root.set_halign(gtk4::Align::Fill);
+        root.set_valign(gtk4::Align::Fill);
+        root.add_css_class("mcr-cell");
+
+        let btn = gtk4::Button::with_label(match lang {
+            Lang::ZhCn => "重发",
+            Lang::En => "Resend",
+        });
+        btn.add_css_class("flat");
+        btn.add_css_class("mcr-compact-btn");
+        btn.set_valign(gtk4::Align::Center);
+        btn.set_vexpand(false);
+        // Look the row up at click time: list items are recycled across rows.
+        let list_item_weak = list_item.downgrade();
+        btn.connect_clicked(clone!(@strong log_tx => move |_| {
+            let Some(list_item) = list_item_weak.upgrade() else { return; };
+            let Some(item) = list_item.item() else { return; };
+            let Ok(obj) = item.downcast::<glib::BoxedAnyObject>() else { return; };
+            let Some(args) = obj.borrow::<HistoryRow>().resend_args.clone() else { return; };
+            let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+            match spawn_node(&log_tx, &args) {
+                Ok(mut child) => {
+                    std::thread::spawn(move || {
+                        let _ = child.wait();
+                    });
+                }
+                Err(e) => {
+                    let _ = log_tx.send(format!("failed to resend: {e:?}"));
+                }
+            }
+        }));
+        root.append(&btn);
+        list_item.set_child(Some(&root));
+    });
+    resend_factory.connect_bind(move |_, list_item| {
+        let Some(item) = list_item.item() else { return; };
+        let Ok(obj) = item.downcast::<glib::BoxedAnyObject>() else { return; };
+        let row = obj.borrow::<HistoryRow>();
+        let Some(child) = list_item.child() else { return; };
+        let Ok(root) = child.downcast::<gtk4::Box>() else { return; };
+
+        let Some(first) = root.first_child() else { return; };
+        let Ok(btn) = first.downcast::<gtk4::Button>() else { return; };
+
+        btn.set_visible(!row.is_detail);
+        btn.set_sensitive(row.resend_args.is_some());
+    });
+    let resend_col = gtk4::ColumnViewColumn::new(Some(""), Some(resend_factory));
+    resend_col.set_fixed_width(80);
+    resend_col.set_expand(false);
+    resend_col.set_resizable(false);
+    resend_col.set_visible(col_visible("resend", columns_cfg, true));
+    view.append_column(&resend_col);
+    columns.push(("resend".to_string(), resend_col.clone()));
+
     let scroll = gtk4::ScrolledWindow::builder()
         .vexpand(true)
         .hexpand(true)
@@ -633,6 +762,8 @@ pub fn make_history_table(lang: Lang, columns_cfg: &BTreeMap<String, bool>) -> H
 
     HistoryTable {
         store,
+        filter,
+        filter_state,
         selection,
         view,
         scroll,
@@ -640,12 +771,93 @@ pub fn make_history_table(lang: Lang, columns_cfg: &BTreeMap<String, bool>) -> H
     }
 }
 
+/// Live-filter the history view from a search entry and a kind dropdown (ids: "", text, image, file).
+pub fn install_history_filter(
+    search: gtk4::SearchEntry,
+    kind_combo: gtk4::ComboBoxText,
+    filter: gtk4::CustomFilter,
+    filter_state: Rc<RefCell<HistoryFilter>>,
+) {
+    search.connect_search_changed(clone!(@strong filter, @strong filter_state => move |e| {
+        filter_state.borrow_mut().query = e.text().trim().to_lowercase();
+        filter.changed(gtk4::FilterChange::Different);
+    }));
+    kind_combo.connect_changed(clone!(@strong filter, @strong filter_state => move |c| {
+        filter_state.borrow_mut().kind = c.active_id().map(|s| s.to_string()).filter(|s| !s.is_empty());
+        filter.changed(gtk4::FilterChange::Different);
+    }));
+}
+
+/// Export format from the chosen file name (`node history export` supports json|csv).
+fn export_format_for(path: &std::path::Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("csv") => "csv",
+        _ => "json",
+    }
+}
+
+pub fn install_history_export(
+    export_btn: gtk4::Button,
+    window: gtk4::ApplicationWindow,
+    log_tx: mpsc::Sender<String>,
+    lang_state: Arc<Mutex<Lang>>,
+) {
+    export_btn.connect_clicked(clone!(@strong log_tx, @strong lang_state, @weak window => move |_| {
+        let lang = *lang_state.lock().unwrap();
+        let dialog = gtk4::FileChooserNative::builder()
+            .title(t(lang, K::ExportHistoryTitle))
+            .transient_for(&window)
+            .action(gtk4::FileChooserAction::Save)
+            .build();
+        dialog.set_current_name("multicliprelay-history.csv");
+
+        dialog.connect_response(clone!(@strong log_tx => move |d, resp| {
+            if resp == gtk4::ResponseType::Accept {
+                if let Some(path) = d.file().and_then(|f| f.path()) {
+                    // Reuse the node CLI so the UI and `history export` produce identical files.
+                    let out = path.to_string_lossy().into_owned();
+                    let args = ["history", "export", "--format", export_format_for(&path), "--out", out.as_str()];
+                    match spawn_node(&log_tx, &args) {
+                        Ok(mut child) => {
+                            std::thread::spawn(move || {
+                                let _ = child.wait();
+                            });
+                        }
+                        Err(e) => {
+                            let _ = log_tx.send(format!("failed to export history: {e:?}"));
+                        }
+                    }
+                }
+            }
+            d.destroy();
+        }));
+        dialog.show();
+    }));
+}
+
+/// Drop the oldest rows (`rows` is oldest first) so at most `max_rows` remain.
+///
+/// A detail row left at the front without its main row is dropped too, so an event is
+/// either shown whole or not at all. Returns how many rows were removed.
+fn cap_rows(rows: &mut Vec<HistoryRow>, max_rows: usize) -> usize {
+    let mut excess = rows.len().saturating_sub(max_rows.max(1));
+    if excess == 0 {
+        return 0;
+    }
+    while rows.get(excess).is_some_and(|r| r.is_detail) && excess + 1 < rows.len() {
+        excess += 1;
+    }
+    rows.drain(..excess);
+    excess
+}
+
 pub fn install_history_refresh(
     store: gio::ListStore,
     scroll: gtk4::ScrolledWindow,
     clear_btn: gtk4::Button,
     log_tx: mpsc::Sender<String>,
     lang_state: Arc<Mutex<Lang>>,
+    max_rows: usize,
 ) {
     // Button: clear history file.
     clear_btn.connect_clicked(clone!(@strong store, @strong log_tx => move |_| {
@@ -685,6 +897,10 @@ pub fn install_history_refresh(
                             bytes: String::new(),
                             extra: format!("↳ {l}"),
                             preview_path: None,
+                            group_kind: String::new(),
+                            haystack: l.to_lowercase(),
+                            resend_args: None,
+                            style: None,
                         });
                     }
                 }
@@ -704,9 +920,16 @@ pub fn install_history_refresh(
                     bytes: String::new(),
                     extra: hint,
                     preview_path: None,
+                    group_kind: String::new(),
+                    haystack: String::new(),
+                    resend_args: None,
+                    style: None,
                 });
             }
 
+            // Bounded, so a long busy session doesn't slow the view down.
+            cap_rows(&mut rows, max_rows);
+
             let rendered = row_signature(&rows);
             if rendered != *last_render.borrow() {
                 // Capture scroll state.
@@ -716,10 +939,9 @@ pub fn install_history_refresh(
                 let old_page = vadj.page_size();
                 let at_bottom = old_value + old_page >= (old_upper - 2.0).max(0.0);
 
-                store.remove_all();
-                for r in rows {
-                    store.append(&glib::BoxedAnyObject::new(r));
-                }
+                let objects: Vec<glib::BoxedAnyObject> =
+                    rows.into_iter().map(glib::BoxedAnyObject::new).collect();
+                store.splice(0, store.n_items(), &objects);
 
                 keep_scroll_tail(&scroll, at_bottom, old_value, old_upper);
                 *last_render.borrow_mut() = rendered;
@@ -731,3 +953,97 @@ pub fn install_history_refresh(
         }),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: &str, name: &str, remote: &str, mime: &str) -> HistoryEvent {
+        HistoryEvent {
+            ts_ms: Some(0),
+            dir: Some("recv".into()),
+            room: Some("default".into()),
+            relay: None,
+            local_device_id: Some("me".into()),
+            local_device_name: None,
+            remote_device_id: Some(remote.into()),
+            remote_device_name: None,
+            kind: Some(kind.into()),
+            mime: Some(mime.into()),
+            name: Some(name.into()),
+            bytes: Some(1),
+            sha256: None,
+            outcome: None,
+            reason: None,
+        }
+    }
+
+    fn filter(query: &str, kind: Option<&str>) -> HistoryFilter {
+        HistoryFilter {
+            query: query.trim().to_lowercase(),
+            kind: kind.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn history_filter_matches_whole_event_rows() {
+        let rows = format_event_rows(event("file", "Report.PDF", "laptop-1", "application/pdf"));
+        assert_eq!(rows.len(), 2, "main + detail row");
+        let all = |f: &HistoryFilter| rows.iter().all(|r| history_row_matches(r, f));
+        let none = |f: &HistoryFilter| rows.iter().all(|r| !history_row_matches(r, f));
+
+        assert!(all(&HistoryFilter::default()));
+        // Substring over name (in extra), peer and mime; case-insensitive.
+        assert!(all(&filter("report", None)));
+        assert!(all(&filter("LAPTOP", None)));
+        assert!(all(&filter("application/pdf", Some("file"))));
+        assert!(none(&filter("desktop", None)));
+        // Kind dropdown: detail rows follow their event's kind.
+        assert!(none(&filter("", Some("image"))));
+        assert!(all(&filter("", Some("file"))));
+    }
+
+    #[test]
+    fn skipped_and_failed_sends_are_marked() {
+        let mut e = event("image", "shot.png", "me", "image/png");
+        e.dir = Some("send".into());
+        e.outcome = Some("skipped".into());
+        e.reason = Some("too large: 64 bytes > max_image_bytes=16".into());
+        let rows = format_event_rows(e.clone());
+        assert_eq!(rows[0].dir, "skipped");
+        assert_eq!(rows[0].style, Some("dim-label"));
+        let detail = &rows[1].extra;
+        assert!(detail.contains("reason=too large"), "{detail}");
+
+        e.outcome = Some("failed".into());
+        assert_eq!(format_event_rows(e.clone())[0].style, Some("error"));
+        // Successful sends (and lines from before outcomes existed) look as they always did.
+        for outcome in [Some("sent"), None] {
+            e.outcome = outcome.map(str::to_string);
+            let row = &format_event_rows(e.clone())[0];
+            assert_eq!((row.dir.as_str(), row.style), ("send", None));
+        }
+    }
+
+    #[test]
+    fn capping_drops_the_oldest_events_and_keeps_order() {
+        let mut rows: Vec<HistoryRow> = (0..4)
+            .flat_map(|i| format_event_rows(event("file", "f", &format!("peer-{i}"), "x/y")))
+            .collect();
+        assert_eq!(rows.len(), 8);
+        fn peers(rows: &[HistoryRow]) -> Vec<String> {
+            rows.iter()
+                .filter(|r| !r.is_detail)
+                .map(|r| r.peer.clone())
+                .collect()
+        }
+
+        assert_eq!(cap_rows(&mut rows, 100), 0);
+        assert_eq!(cap_rows(&mut rows, 6), 2);
+        assert_eq!(peers(&rows), ["peer-1", "peer-2", "peer-3"]);
+        // The cut would split an event: its orphaned detail row goes too.
+        assert_eq!(cap_rows(&mut rows, 3), 4);
+        assert_eq!(peers(&rows), ["peer-3"]);
+        assert!(!rows[0].is_detail);
+    }
+}
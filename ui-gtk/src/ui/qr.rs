@@ -0,0 +1,115 @@
+use glib::clone;
+use gtk4::gdk;
+use gtk4::prelude::*;
+
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+
+use crate::i18n::{t, Lang, K};
+
+/// Pixels per QR module. GtkPicture scales with filtering, so render big enough to stay crisp.
+const QR_SCALE: usize = 8;
+/// Quiet zone (in modules) required by scanners.
+const QR_QUIET: usize = 4;
+
+/// Best-effort LAN address of this machine (no packets are sent).
+fn guess_lan_ip() -> Option<IpAddr> {
+    let sock = UdpSocket::bind("0.0.0.0:0").ok()?;
+    sock.connect("192.0.2.1:9").ok()?;
+    let ip = sock.local_addr().ok()?.ip();
+    (!ip.is_loopback() && !ip.is_unspecified()).then_some(ip)
+}
+
+/// Relay address as another device should see it.
+///
+/// The entry often holds a *bind* address (`0.0.0.0:PORT`) or loopback, which is useless
+/// on a phone; replace those with the LAN address when we can find one.
+fn relay_addr_for_peers(input: &str) -> String {
+    let s = input.trim();
+    match s.parse::<SocketAddr>() {
+        Ok(mut sa) if sa.ip().is_unspecified() || sa.ip().is_loopback() => {
+            if let Some(ip) = guess_lan_ip() {
+                sa.set_ip(ip);
+            }
+            sa.to_string()
+        }
+        _ => s.to_string(),
+    }
+}
+
+/// `mcr://relay=...&room=...` for another device to scan.
+pub fn connect_uri(relay: &str, room: &str) -> String {
+    utils::uri::build_connect_uri(&relay_addr_for_peers(relay), room.trim())
+}
+
+fn qr_texture(data: &str) -> Option<gdk::MemoryTexture> {
+    let code = qrcode::QrCode::new(data.as_bytes()).ok()?;
+    let modules = code.width();
+    let colors = code.to_colors();
+
+    let side = (modules + QR_QUIET * 2) * QR_SCALE;
+    let stride = side * 3;
+    // White background (quiet zone included), then paint dark modules.
+    let mut buf = vec![0xffu8; stride * side];
+    for (i, c) in colors.iter().enumerate() {
+        if *c != qrcode::Color::Dark {
+            continue;
+        }
+        let mx = (i % modules + QR_QUIET) * QR_SCALE;
+        let my = (i / modules + QR_QUIET) * QR_SCALE;
+        for y in my..my + QR_SCALE {
+            let row = y * stride;
+            buf[row + mx * 3..row + (mx + QR_SCALE) * 3].fill(0);
+        }
+    }
+
+    let bytes = glib::Bytes::from_owned(buf);
+    Some(gdk::MemoryTexture::new(
+        side as i32,
+        side as i32,
+        gdk::MemoryFormat::R8g8b8,
+        &bytes,
+        stride,
+    ))
+}
+
+/// "Show QR" button: the popover renders the current relay/room as an onboarding QR code.
+pub fn build_qr_button(
+    lang: Lang,
+    relay_entry: &gtk4::Entry,
+    room_entry: &gtk4::Entry,
+) -> gtk4::MenuButton {
+    let btn = gtk4::MenuButton::builder()
+        .label(t(lang, K::BtnShowQr))
+        .build();
+
+    let pop = gtk4::Popover::new();
+    let vbox = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+    vbox.set_margin_top(8);
+    vbox.set_margin_bottom(8);
+    vbox.set_margin_start(8);
+    vbox.set_margin_end(8);
+
+    let picture = gtk4::Picture::new();
+    picture.set_size_request(240, 240);
+    picture.set_can_shrink(true);
+    let uri_label = gtk4::Label::builder()
+        .selectable(true)
+        .wrap(true)
+        .max_width_chars(40)
+        .build();
+    uri_label.add_css_class("dim-label");
+
+    vbox.append(&picture);
+    vbox.append(&uri_label);
+    pop.set_child(Some(&vbox));
+    btn.set_popover(Some(&pop));
+
+    // Regenerate on every open so it follows edits to relay/room.
+    pop.connect_show(clone!(@weak relay_entry, @weak room_entry, @weak picture, @weak uri_label => move |_| {
+        let uri = connect_uri(&relay_entry.text(), &room_entry.text());
+        picture.set_paintable(qr_texture(&uri).as_ref());
+        uri_label.set_text(&uri);
+    }));
+
+    btn
+}
@@ -4,6 +4,7 @@ use gtk4::prelude::*;
 use std::rc::Rc;
 use std::sync::{mpsc, Arc, Mutex};
 
+use crate::config::{config_path, load_config, SyncKinds, UiConfig};
 use crate::i18n::{t, Lang, K};
 use crate::procs::{spawn_node, spawn_relay, terminate_child, Procs};
 use crate::systemd;
@@ -42,6 +43,68 @@ pub struct ServiceConfigInputs {
     pub x11_poll_spin: gtk4::SpinButton,
     pub image_mode_combo: gtk4::ComboBoxText,
     pub debug_check: gtk4::CheckButton,
+    pub sync_text_check: gtk4::CheckButton,
+    pub sync_image_check: gtk4::CheckButton,
+    pub sync_file_check: gtk4::CheckButton,
+}
+
+/// What is up right now: the polled systemd units, or our own child processes.
+fn running_services(
+    procs: &Procs,
+    status: systemd::ServiceStatus,
+    use_systemd: bool,
+) -> systemd::ServiceStatus {
+    if use_systemd {
+        return status;
+    }
+    systemd::ServiceStatus {
+        relay: procs.relay.is_some(),
+        watch: procs.watch.is_some(),
+        apply: procs.apply.is_some(),
+        x11: procs.x11.is_some(),
+    }
+}
+
+/// Whether launch should run "Start All": only with `autostart_services`, and not when
+/// everything it would start is already up (e.g. units that outlived the last UI).
+pub fn autostart_needed(
+    cfg: &UiConfig,
+    running: systemd::ServiceStatus,
+    use_systemd: bool,
+) -> bool {
+    if !cfg.autostart_services {
+        return false;
+    }
+    // Start All leaves wl-watch alone with no kinds selected, and x11-sync outside systemd.
+    let watch_done = running.watch || !SyncKinds::from_config(cfg).any();
+    let x11_done = running.x11 || !use_systemd;
+    !(running.relay && watch_done && running.apply && x11_done)
+}
+
+/// Launch-time autostart: the same handler as clicking "Start All".
+pub fn autostart_services(
+    cfg: &UiConfig,
+    procs: &Mutex<Procs>,
+    use_systemd: bool,
+    start_all: &gtk4::Button,
+    log_tx: &mpsc::Sender<String>,
+) {
+    if !cfg.autostart_services {
+        return;
+    }
+    // The status poller may not have run yet; ask systemd directly.
+    let status = if use_systemd {
+        systemd::status_snapshot()
+    } else {
+        systemd::ServiceStatus::default()
+    };
+    let running = running_services(&procs.lock().unwrap(), status, use_systemd);
+    if !autostart_needed(cfg, running, use_systemd) {
+        let _ = log_tx.send("autostart: services already running".into());
+        return;
+    }
+    let _ = log_tx.send("autostart: starting services".into());
+    start_all.emit_clicked();
 }
 
 pub fn make_update_services_ui(
@@ -53,13 +116,12 @@ pub fn make_update_services_ui(
 ) -> Rc<dyn Fn()> {
     Rc::new(move || {
         let lang = *lang_state.lock().unwrap();
-        let (relay_running, watch_running, apply_running, x11_running) = if use_systemd {
-            let s = *status.lock().unwrap();
-            (s.relay, s.watch, s.apply, s.x11)
-        } else {
-            let p = procs.lock().unwrap();
-            (p.relay.is_some(), p.watch.is_some(), p.apply.is_some(), p.x11.is_some())
-        };
+        let systemd::ServiceStatus {
+            relay: relay_running,
+            watch: watch_running,
+            apply: apply_running,
+            x11: x11_running,
+        } = running_services(&procs.lock().unwrap(), *status.lock().unwrap(), use_systemd);
 
         let running_txt = t(lang, K::StatusRunning);
         let stopped_txt = t(lang, K::StatusStopped);
@@ -117,6 +179,9 @@ pub fn connect_service_handlers(
         x11_poll_spin,
         image_mode_combo,
         debug_check,
+        sync_text_check,
+        sync_image_check,
+        sync_file_check,
     } = inputs;
 
     let use_systemd = systemd::enabled_from_env_or_auto();
@@ -152,8 +217,19 @@ pub fn connect_service_handlers(
     let image_mode_combo_c = image_mode_combo.clone();
     let debug_check_c = debug_check.clone();
 
-    let mk_cfg_from_ui: Rc<dyn Fn() -> crate::config::UiConfig> = Rc::new(move || {
-        crate::config::UiConfig {
+    // Read at click time: the checkboxes only change what the next start passes.
+    let sync_kinds: Rc<dyn Fn() -> SyncKinds> = Rc::new(move || SyncKinds {
+        text: sync_text_check.is_active(),
+        image: sync_image_check.is_active(),
+        file: sync_file_check.is_active(),
+    });
+    let sync_kinds_c = sync_kinds.clone();
+
+    let mk_cfg_from_ui: Rc<dyn Fn() -> UiConfig> = Rc::new(move || {
+        // Fields without a service input (device name, received dir, ...) come from disk.
+        let saved = load_config(&config_path()).unwrap_or_default();
+        let kinds = (sync_kinds_c)();
+        UiConfig {
             relay_addr: relay_entry_c.text().to_string(),
             room: room_entry_c.text().to_string(),
             max_text_bytes: spin_usize(&max_text_spin_c),
@@ -161,10 +237,11 @@ pub fn connect_service_handlers(
             max_file_bytes: spin_usize(&max_file_spin_c),
             image_mode: combo_active_id_or(&image_mode_combo_c, DEFAULT_IMAGE_MODE_ID),
             x11_poll_interval_ms: spin_usize(&x11_poll_spin_for_cfg) as u64,
-            language: "auto".to_string(),
             debug_mode: debug_check_c.is_active(),
-            history_columns: Default::default(),
-            force_png: None,
+            sync_text: kinds.text,
+            sync_image: kinds.image,
+            sync_file: kinds.file,
+            ..saved
         }
     });
 
@@ -231,7 +308,12 @@ pub fn connect_service_handlers(
     );
 
     // Watch
-    w.start_watch_btn.connect_clicked(clone!(@strong procs, @strong log_tx, @strong update_services_ui, @strong mk_cfg_from_ui_for_watch, @strong relay_entry_for_watch, @strong room_entry_for_watch, @strong max_text_spin_for_watch, @strong max_image_spin_for_watch, @strong max_file_spin_for_watch, @strong image_mode_combo_for_watch => move |_| {
+    w.start_watch_btn.connect_clicked(clone!(@strong procs, @strong log_tx, @strong update_services_ui, @strong mk_cfg_from_ui_for_watch, @strong sync_kinds, @strong relay_entry_for_watch, @strong room_entry_for_watch, @strong max_text_spin_for_watch, @strong max_image_spin_for_watch, @strong max_file_spin_for_watch, @strong image_mode_combo_for_watch => move |_| {
+        let kinds = (sync_kinds)();
+        if !kinds.any() {
+            let _ = log_tx.send("wl-watch not started: no kinds selected to sync".into());
+            return;
+        }
         if use_systemd {
             let cfg = (mk_cfg_from_ui_for_watch)();
             let _ = systemd::write_env_from_ui_config(&cfg);
@@ -258,7 +340,7 @@ pub fn connect_service_handlers(
         let max_file = spin_usize(&max_file_spin_for_watch);
         let image_mode = combo_active_id_or(&image_mode_combo_for_watch, DEFAULT_IMAGE_MODE_ID);
 
-        let args_owned: Vec<String> = vec![
+        let mut args_owned: Vec<String> = vec![
             "wl-watch".to_string(),
             "--room".to_string(),
             room,
@@ -275,6 +357,7 @@ pub fn connect_service_handlers(
             "--image-mode".to_string(),
             image_mode,
         ];
+        args_owned.extend(kinds.watch_args());
         let args: Vec<&str> = args_owned.iter().map(|s| s.as_str()).collect();
         match spawn_node(&log_tx, &args) {
             Ok(child) => {
@@ -312,7 +395,7 @@ pub fn connect_service_handlers(
     );
 
     // Apply
-    w.start_apply_btn.connect_clicked(clone!(@strong procs, @strong log_tx, @strong update_services_ui, @strong mk_cfg_from_ui_for_apply, @strong relay_entry_for_apply, @strong room_entry_for_apply, @strong max_text_spin_for_apply, @strong image_mode_combo_for_apply => move |_| {
+    w.start_apply_btn.connect_clicked(clone!(@strong procs, @strong log_tx, @strong update_services_ui, @strong mk_cfg_from_ui_for_apply, @strong sync_kinds, @strong relay_entry_for_apply, @strong room_entry_for_apply, @strong max_text_spin_for_apply, @strong image_mode_combo_for_apply => move |_| {
         if use_systemd {
             let cfg = (mk_cfg_from_ui_for_apply)();
             let _ = systemd::write_env_from_ui_config(&cfg);
@@ -336,7 +419,7 @@ pub fn connect_service_handlers(
         let room = room_entry_for_apply.text().to_string();
         let image_mode = combo_active_id_or(&image_mode_combo_for_apply, DEFAULT_IMAGE_MODE_ID);
 
-        let args_owned: Vec<String> = vec![
+        let mut args_owned: Vec<String> = vec![
             "wl-apply".to_string(),
             "--room".to_string(),
             room,
@@ -345,6 +428,7 @@ pub fn connect_service_handlers(
             "--image-mode".to_string(),
             image_mode,
         ];
+        args_owned.extend((sync_kinds)().apply_args());
         let args: Vec<&str> = args_owned.iter().map(|s| s.as_str()).collect();
         match spawn_node(&log_tx, &args) {
             Ok(child) => {
@@ -450,7 +534,7 @@ pub fn connect_service_handlers(
     }));
 
     // Start/stop all
-    w.start_all.connect_clicked(clone!(@strong procs, @strong log_tx, @strong mk_cfg_from_ui_for_all, @weak relay_entry, @weak room_entry, @weak max_text_spin, @weak max_image_spin, @weak max_file_spin, @weak image_mode_combo, @strong update_services_ui => move |_| {
+    w.start_all.connect_clicked(clone!(@strong procs, @strong log_tx, @strong mk_cfg_from_ui_for_all, @strong sync_kinds, @weak relay_entry, @weak room_entry, @weak max_text_spin, @weak max_image_spin, @weak max_file_spin, @weak image_mode_combo, @strong update_services_ui => move |_| {
         if use_systemd {
             if !systemd::node_supports_x11_sync() {
                 let _ = log_tx.send("multicliprelay-node does not support x11-sync; please upgrade/reinstall binaries (or adjust unit ExecStart)".into());
@@ -460,7 +544,9 @@ pub fn connect_service_handlers(
             let cfg = (mk_cfg_from_ui_for_all)();
             let _ = systemd::write_env_from_ui_config(&cfg);
             let _ = systemd::start(systemd::UNIT_RELAY);
-            let _ = systemd::start(systemd::UNIT_WL_WATCH);
+            if SyncKinds::from_config(&cfg).any() {
+                let _ = systemd::start(systemd::UNIT_WL_WATCH);
+            }
             let _ = systemd::start(systemd::UNIT_WL_APPLY);
             let _ = systemd::start(systemd::UNIT_X11_SYNC);
             let _ = log_tx.send("started all (systemd)".into());
@@ -475,6 +561,7 @@ pub fn connect_service_handlers(
         let max_img = spin_usize(&max_image_spin);
         let max_file = spin_usize(&max_file_spin);
         let image_mode = combo_active_id_or(&image_mode_combo, DEFAULT_IMAGE_MODE_ID);
+        let kinds = (sync_kinds)();
 
         let mut p = procs.lock().unwrap();
 
@@ -490,8 +577,8 @@ pub fn connect_service_handlers(
             }
         }
 
-        if p.watch.is_none() {
-            let args_owned: Vec<String> = vec![
+        if p.watch.is_none() && kinds.any() {
+            let mut args_owned: Vec<String> = vec![
                 "wl-watch".to_string(),
                 "--room".to_string(),
                 room.clone(),
@@ -508,6 +595,7 @@ pub fn connect_service_handlers(
                 "--image-mode".to_string(),
                 image_mode.clone(),
             ];
+            args_owned.extend(kinds.watch_args());
             let args: Vec<&str> = args_owned.iter().map(|s| s.as_str()).collect();
             match spawn_node(&log_tx, &args) {
                 Ok(child) => {
@@ -521,7 +609,7 @@ pub fn connect_service_handlers(
         }
 
         if p.apply.is_none() {
-            let args_owned: Vec<String> = vec![
+            let mut args_owned: Vec<String> = vec![
                 "wl-apply".to_string(),
                 "--room".to_string(),
                 room,
@@ -530,6 +618,7 @@ pub fn connect_service_handlers(
                 "--image-mode".to_string(),
                 image_mode,
             ];
+            args_owned.extend(kinds.apply_args());
             let args: Vec<&str> = args_owned.iter().map(|s| s.as_str()).collect();
             match spawn_node(&log_tx, &args) {
                 Ok(child) => {
@@ -579,3 +668,46 @@ pub fn connect_service_handlers(
         }),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autostart_respects_the_flag_and_what_already_runs() {
+        let off = UiConfig::default();
+        let on = UiConfig {
+            autostart_services: true,
+            ..UiConfig::default()
+        };
+        let none = systemd::ServiceStatus::default();
+        let all = systemd::ServiceStatus {
+            relay: true,
+            watch: true,
+            apply: true,
+            x11: true,
+        };
+
+        assert!(!autostart_needed(&off, none, false));
+        assert!(autostart_needed(&on, none, false));
+        assert!(autostart_needed(&on, none, true));
+        // Never started twice.
+        assert!(!autostart_needed(&on, all, true));
+
+        // x11-sync only counts under systemd, as in Start All.
+        let no_x11 = systemd::ServiceStatus { x11: false, ..all };
+        assert!(!autostart_needed(&on, no_x11, false));
+        assert!(autostart_needed(&on, no_x11, true));
+
+        // With every kind unchecked, wl-watch is not expected to run.
+        let nothing_to_watch = UiConfig {
+            sync_text: false,
+            sync_image: false,
+            sync_file: false,
+            ..on.clone()
+        };
+        let no_watch = systemd::ServiceStatus { watch: false, ..all };
+        assert!(!autostart_needed(&nothing_to_watch, no_watch, true));
+        assert!(autostart_needed(&on, no_watch, true));
+    }
+}
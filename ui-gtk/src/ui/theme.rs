@@ -0,0 +1,85 @@
+//! Light/dark preference (`UiConfig.theme`) and the app CSS that depends on it.
+
+use gtk4::gdk;
+
+use std::cell::{Cell, RefCell};
+
+use crate::config::prefer_dark_for;
+
+/// Row zebra stripes and compact cells for the ColumnView-based tables.
+///
+/// Keep it subtle so selection highlight still stands out. Theme colors are used so it works
+/// in both light and dark themes.
+const APP_CSS: &str = r#"
+.mcr-cell {
+    padding: 4px 10px;
+}
+
+/* Make small in-cell action buttons not inflate row height. */
+.mcr-compact-btn {
+    padding: 1px 8px;
+    min-height: 0;
+    min-width: 0;
+}
+
+/* Force consistent row height across all columns. */
+columnview row,
+columnview listview row {
+    min-height: 28px;
+}
+
+/* Zebra stripes: color the whole row (not individual cells) to avoid a chopped look. */
+columnview row:nth-child(odd),
+columnview listview row:nth-child(odd) {
+    background-color: transparent;
+}
+
+columnview row:nth-child(even),
+columnview listview row:nth-child(even) {
+    background-color: alpha(@theme_fg_color, 0.028);
+}
+
+columnview row:hover:not(:selected),
+columnview listview row:hover:not(:selected) {
+    background-color: alpha(@theme_fg_color, 0.050);
+}
+"#;
+
+thread_local! {
+    /// `gtk-application-prefer-dark-theme` as the system set it, before any override.
+    static SYSTEM_PREFERS_DARK: Cell<Option<bool>> = const { Cell::new(None) };
+    static APP_CSS_PROVIDER: RefCell<Option<gtk4::CssProvider>> = const { RefCell::new(None) };
+}
+
+/// Apply `theme` (`auto` / `light` / `dark`) and (re)load the app CSS so it picks up the
+/// new theme colors.
+pub fn apply_theme(theme: &str) {
+    if let Some(settings) = gtk4::Settings::default() {
+        let system = SYSTEM_PREFERS_DARK.with(|c| match c.get() {
+            Some(v) => v,
+            None => {
+                let v = settings.is_gtk_application_prefer_dark_theme();
+                c.set(Some(v));
+                v
+            }
+        });
+        settings.set_gtk_application_prefer_dark_theme(prefer_dark_for(theme, system));
+    }
+
+    let Some(display) = gdk::Display::default() else {
+        return;
+    };
+    let provider = gtk4::CssProvider::new();
+    provider.load_from_data(APP_CSS);
+    APP_CSS_PROVIDER.with(|slot| {
+        if let Some(old) = slot.borrow_mut().take() {
+            gtk4::style_context_remove_provider_for_display(&display, &old);
+        }
+        gtk4::style_context_add_provider_for_display(
+            &display,
+            &provider,
+            gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+        *slot.borrow_mut() = Some(provider);
+    });
+}
@@ -2,6 +2,8 @@ use anyhow::Context;
 
 use std::path::PathBuf;
 
+use utils::kinds::watch_mimes_for;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct UiConfig {
     pub relay_addr: String,
@@ -13,12 +15,28 @@ pub struct UiConfig {
     #[serde(default = "default_image_mode")]
     pub image_mode: String,
 
+    /// Shared with ui-gtk: multi-mime image preference (empty = node default order).
+    #[serde(default)]
+    pub image_priority: Vec<String>,
+
     #[serde(default = "default_x11_poll_interval_ms")]
     pub x11_poll_interval_ms: u64,
 
+    /// Shared with ui-gtk: which kinds wl-watch publishes.
+    #[serde(default = "default_true")]
+    pub sync_text: bool,
+    #[serde(default = "default_true")]
+    pub sync_image: bool,
+    #[serde(default = "default_true")]
+    pub sync_file: bool,
+
     #[serde(default = "default_language")]
     pub language: String,
 
+    /// Shared with ui-gtk: name shown to peers (empty = hostname).
+    #[serde(default)]
+    pub device_name: String,
+
     // Legacy field in early ui versions.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub force_png: Option<bool>,
@@ -40,6 +58,10 @@ fn default_x11_poll_interval_ms() -> u64 {
     200
 }
 
+fn default_true() -> bool {
+    true
+}
+
 impl Default for UiConfig {
     fn default() -> Self {
         Self {
@@ -49,14 +71,33 @@ impl Default for UiConfig {
             max_image_bytes: 20 * 1024 * 1024,
             max_file_bytes: default_max_file_bytes(),
             image_mode: default_image_mode(),
+            image_priority: Vec::new(),
             x11_poll_interval_ms: default_x11_poll_interval_ms(),
+            sync_text: true,
+            sync_image: true,
+            sync_file: true,
             language: default_language(),
+            device_name: String::new(),
             force_png: None,
         }
     }
 }
 
 impl UiConfig {
+    /// Node env for the per-kind sync settings (same mapping as ui-gtk's `SyncKinds`): text
+    /// alone is `MCR_TEXT_ONLY`, any other partial set (none included) a `MCR_WATCH_MIMES`
+    /// allow list.
+    pub fn sync_kind_env(&self) -> Vec<(&'static str, String)> {
+        let (text, image, file) = (self.sync_text, self.sync_image, self.sync_file);
+        if text && !image && !file {
+            return vec![("MCR_TEXT_ONLY", "1".to_string())];
+        }
+        watch_mimes_for(text, image, file)
+            .map(|mimes| ("MCR_WATCH_MIMES", mimes))
+            .into_iter()
+            .collect()
+    }
+
     pub fn relay_bind_hint(&self) -> Option<String> {
         // If relay_addr looks like a loopback address, we can bind relay to it.
         // Otherwise (e.g. a remote relay), binding locally doesn't make sense.
@@ -18,6 +18,12 @@ pub enum K {
     StopApply,
     StartX11Sync,
     StopX11Sync,
+    PauseSync,
+    ResumeSync,
+    DisableSync,
+    EnableSync,
+    RecentItems,
+    RecentEmpty,
     Quit,
     TooltipTitle,
     TooltipStatusLine,
@@ -80,6 +86,19 @@ pub fn t(lang: Lang, k: K) -> &'static str {
         (Lang::ZhCn, K::StopX11Sync) => "停止 x11-sync",
         (Lang::En, K::StopX11Sync) => "Stop x11-sync",
 
+        (Lang::ZhCn, K::PauseSync) => "暂停同步",
+        (Lang::En, K::PauseSync) => "Pause sync",
+        (Lang::ZhCn, K::ResumeSync) => "恢复同步",
+        (Lang::En, K::ResumeSync) => "Resume sync",
+        (Lang::ZhCn, K::DisableSync) => "停用全部同步",
+        (Lang::En, K::DisableSync) => "Disable all syncing",
+        (Lang::ZhCn, K::EnableSync) => "重新启用同步",
+        (Lang::En, K::EnableSync) => "Re-enable syncing",
+        (Lang::ZhCn, K::RecentItems) => "最近记录",
+        (Lang::En, K::RecentItems) => "Recent items",
+        (Lang::ZhCn, K::RecentEmpty) => "（暂无记录）",
+        (Lang::En, K::RecentEmpty) => "(nothing yet)",
+
         (Lang::ZhCn, K::Quit) => "退出",
         (Lang::En, K::Quit) => "Quit",
 
@@ -1,6 +1,7 @@
 mod config;
 mod i18n;
 mod procs;
+mod recent;
 mod systemd;
 mod tray_app;
 
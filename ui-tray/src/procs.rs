@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 use std::process::{Child, Command};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 #[derive(Default)]
 pub struct Procs {
@@ -10,6 +10,68 @@ pub struct Procs {
     pub apply: Option<Child>,
 }
 
+/// Same dir node uses (honors `MCR_STATE_DIR`).
+fn node_state_dir() -> PathBuf {
+    utils::paths::default_state_dir()
+}
+
+/// Same flag file as `node pause --room <room>`.
+fn paused_flag_path(room: &str) -> PathBuf {
+    utils::paths::paused_path(&node_state_dir(), room.trim())
+}
+
+pub fn is_room_paused(room: &str) -> bool {
+    paused_flag_path(room).exists()
+}
+
+pub fn set_room_paused(room: &str, paused: bool) -> anyhow::Result<()> {
+    let p = paused_flag_path(room);
+    if paused {
+        std::fs::create_dir_all(node_state_dir())?;
+        std::fs::write(&p, "tray\n")?;
+    } else if p.exists() {
+        std::fs::remove_file(&p)?;
+    }
+    Ok(())
+}
+
+/// Same kill switch as `node disable`: every room stops.
+fn disabled_flag_path() -> PathBuf {
+    utils::paths::disabled_path(&node_state_dir())
+}
+
+pub fn is_sync_disabled() -> bool {
+    disabled_flag_path().exists()
+}
+
+pub fn set_sync_disabled(disabled: bool) -> anyhow::Result<()> {
+    let p = disabled_flag_path();
+    if disabled {
+        std::fs::create_dir_all(node_state_dir())?;
+        std::fs::write(&p, "tray\n")?;
+    } else if p.exists() {
+        std::fs::remove_file(&p)?;
+    }
+    Ok(())
+}
+
+/// Node's history file; wl-watch/wl-apply append one line per transfer.
+pub fn node_history_path() -> PathBuf {
+    utils::paths::history_path()
+}
+
+/// When the last send/recv was recorded (history file mtime), if ever.
+pub fn history_modified() -> Option<SystemTime> {
+    std::fs::metadata(node_history_path()).ok()?.modified().ok()
+}
+
+/// Time since the last send/recv was recorded, if any.
+pub fn since_last_history_event() -> Option<Duration> {
+    let modified = history_modified()?;
+    // A clock step backwards just reads as "now".
+    Some(modified.elapsed().unwrap_or_default())
+}
+
 pub fn terminate_child(mut child: Child, label: &'static str) {
     // Best-effort graceful shutdown.
     thread::spawn(move || {
@@ -0,0 +1,207 @@
+//! "Recent items" submenu: the last few history events, newest first.
+
+use serde::Deserialize;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use utils::paths::kept_text_path;
+
+/// How many events the submenu lists.
+pub const RECENT_ITEMS: usize = 8;
+
+/// Longest preview (in chars) before it is cut.
+const LABEL_CHARS: usize = 40;
+
+/// Only the end of the history file is read; it is enough for a handful of events.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+/// The parts of a node history line the menu needs (node `history::HistoryEvent`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HistoryEvent {
+    pub dir: Option<String>,
+    pub kind: Option<String>,
+    pub mime: Option<String>,
+    pub name: Option<String>,
+    pub bytes: Option<usize>,
+    pub sha256: Option<String>,
+    /// `skipped` / `failed` for sends that never went out (node logs those too).
+    pub outcome: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentItem {
+    pub label: String,
+    /// Stored text to put back on the clipboard (text events whose payload node kept).
+    pub text_path: Option<PathBuf>,
+}
+
+/// One line, at most `max` chars (an ellipsis marks the cut).
+fn one_line(s: &str, max: usize) -> String {
+    let flat = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() <= max {
+        return flat;
+    }
+    let mut out: String = flat.chars().take(max.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// Menu label for an event; `text` is its stored text, if any.
+pub fn item_label(e: &HistoryEvent, text: Option<&str>) -> String {
+    let arrow = match e.dir.as_deref() {
+        Some("send") => "↑",
+        Some("recv") => "↓",
+        _ => "·",
+    };
+    let kind = e.kind.as_deref().unwrap_or("?");
+    let name = e.name.as_deref().filter(|n| !n.trim().is_empty());
+    let what = match (text, name) {
+        (Some(text), _) => one_line(text, LABEL_CHARS),
+        (None, Some(name)) => one_line(name, LABEL_CHARS),
+        (None, None) => match (e.mime.as_deref(), e.bytes) {
+            (Some(mime), Some(bytes)) => format!("{mime}, {bytes} B"),
+            (Some(mime), None) => mime.to_string(),
+            (None, Some(bytes)) => format!("{bytes} B"),
+            (None, None) => String::new(),
+        },
+    };
+    // The menu treats a single `_` as an access-key marker.
+    format!("{arrow} {kind}: {what}").replace('_', "__")
+}
+
+/// The newest `max` clipboard events of `events` (oldest first, as in the log), newest first.
+///
+/// Sends that were skipped or failed are left out: nothing reached the other devices.
+pub fn recent_items(
+    events: &[HistoryEvent],
+    max: usize,
+    stored_text: impl Fn(&HistoryEvent) -> Option<(PathBuf, String)>,
+) -> Vec<RecentItem> {
+    events
+        .iter()
+        .rev()
+        .filter(|e| matches!(e.kind.as_deref(), Some("text" | "image" | "file")))
+        .filter(|e| !matches!(e.outcome.as_deref(), Some("skipped" | "failed")))
+        .take(max)
+        .map(|e| {
+            let text = stored_text(e);
+            RecentItem {
+                label: item_label(e, text.as_ref().map(|(_, t)| t.as_str())),
+                text_path: text.map(|(p, _)| p),
+            }
+        })
+        .collect()
+}
+
+/// Node keeps small texts as `<received>/<sha8>/text.txt` (RTF goes to `text.rtf` and is
+/// left out here); only the start is read.
+fn stored_text(e: &HistoryEvent) -> Option<(PathBuf, String)> {
+    if e.kind.as_deref() != Some("text") {
+        return None;
+    }
+    let p = kept_text_path(&utils::paths::received_dir(), e.sha256.as_deref()?, false);
+    let mut buf = Vec::new();
+    File::open(&p).ok()?.take(4096).read_to_end(&mut buf).ok()?;
+    Some((p, String::from_utf8_lossy(&buf).into_owned()))
+}
+
+/// The last [`RECENT_ITEMS`] events of the history file at `path`.
+pub fn read_recent(path: &Path) -> Vec<RecentItem> {
+    let Ok(mut f) = File::open(path) else {
+        return Vec::new();
+    };
+    let len = f.metadata().map(|m| m.len()).unwrap_or(0);
+    let start = len.saturating_sub(TAIL_BYTES);
+    if f.seek(SeekFrom::Start(start)).is_err() {
+        return Vec::new();
+    }
+    let mut buf = Vec::new();
+    if f.read_to_end(&mut buf).is_err() {
+        return Vec::new();
+    }
+    let text = String::from_utf8_lossy(&buf);
+    // A partial first line fails to parse and is skipped like any other bad line.
+    let events: Vec<HistoryEvent> = text
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect();
+    recent_items(&events, RECENT_ITEMS, stored_text)
+}
+
+/// Put the stored text at `path` back on the local clipboard.
+pub fn copy_text(path: &Path) -> anyhow::Result<()> {
+    let status = Command::new("wl-copy")
+        .arg("--type")
+        .arg("text/plain;charset=utf-8")
+        .stdin(File::open(path)?)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    anyhow::ensure!(status.success(), "wl-copy failed: {status}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(dir: &str, kind: &str, name: Option<&str>, sha: &str) -> HistoryEvent {
+        HistoryEvent {
+            dir: Some(dir.into()),
+            kind: Some(kind.into()),
+            mime: Some("image/png".into()),
+            name: name.map(str::to_string),
+            bytes: Some(2048),
+            sha256: Some(sha.into()),
+            outcome: None,
+        }
+    }
+
+    #[test]
+    fn menu_entries_list_newest_events_with_previews() {
+        let events = vec![
+            event("send", "text", None, "old"),
+            event("recv", "join", None, "x"),
+            event("recv", "file", Some("my_report.pdf"), "f"),
+            event("send", "image", None, "i"),
+            event("recv", "text", None, "t"),
+            HistoryEvent {
+                outcome: Some("skipped".into()),
+                ..event("send", "image", None, "too-big")
+            },
+        ];
+        let text = |e: &HistoryEvent| {
+            (e.sha256.as_deref() == Some("t")).then(|| {
+                let long = format!("hello\n  world {}", "x".repeat(60));
+                (PathBuf::from("/stored/text.txt"), long)
+            })
+        };
+
+        let items = recent_items(&events, 3, text);
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels.len(), 3);
+        // Newest first; whitespace folded and cut to one short line.
+        assert!(
+            labels[0].starts_with("↓ text: hello world xxx"),
+            "{}",
+            labels[0]
+        );
+        assert!(labels[0].ends_with('…'));
+        assert_eq!(
+            labels[0].chars().count(),
+            "↓ text: ".chars().count() + LABEL_CHARS
+        );
+        assert_eq!(labels[1], "↑ image: image/png, 2048 B");
+        // Underscores are escaped so they aren't taken as access keys.
+        assert_eq!(labels[2], "↓ file: my__report.pdf");
+        // Only texts with a stored payload can be copied again.
+        assert_eq!(items[0].text_path, Some(PathBuf::from("/stored/text.txt")));
+        assert!(items[1].text_path.is_none());
+
+        assert!(recent_items(&[], 3, text).is_empty());
+    }
+}
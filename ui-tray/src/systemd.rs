@@ -217,6 +217,19 @@ pub fn write_env_from_ui_config(cfg: &UiConfig) -> anyhow::Result<()> {
         cfg.x11_poll_interval_ms
     ));
 
+    let device_name = cfg.device_name.trim();
+    if !device_name.is_empty() {
+        lines.push(format!("MCR_NAME={device_name}"));
+    }
+
+    if !cfg.image_priority.is_empty() {
+        lines.push(format!("MCR_IMAGE_PRIORITY={}", cfg.image_priority.join(",")));
+    }
+
+    for (k, v) in cfg.sync_kind_env() {
+        lines.push(format!("{k}={v}"));
+    }
+
     std::fs::write(&path, lines.join("\n") + "\n").context("write env")?;
     Ok(())
 }
@@ -1,9 +1,17 @@
 use crate::config::{load_config, UiConfig};
 use crate::i18n::{detect_lang_from_env, parse_lang_id, t, Lang, K};
-use crate::procs::{find_sibling_binary, spawn_ui_gtk, terminate_child, Procs};
+use crate::procs::{
+    find_sibling_binary, history_modified, is_room_paused, is_sync_disabled, node_history_path,
+    set_room_paused, set_sync_disabled, since_last_history_event, spawn_ui_gtk, terminate_child,
+    Procs,
+};
+use crate::recent::{copy_text, read_recent, RecentItem};
 use crate::systemd;
 
-use ksni::{menu::StandardItem, Handle, Status, ToolTip, Tray};
+use ksni::{
+    menu::{StandardItem, SubMenu},
+    Handle, Status, ToolTip, Tray,
+};
 
 use std::path::PathBuf;
 use std::process::Command;
@@ -12,6 +20,8 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+use utils::probe::probe_tcp;
+
 pub struct MultiClipRelayTray {
     state: Arc<Mutex<AppState>>,
 }
@@ -146,6 +156,14 @@ impl MultiClipRelayTray {
             .unwrap_or_else(|| PathBuf::from("multicliprelay-node"));
 
         let mut cmd = Command::new(node_bin);
+        let device_name = cfg.device_name.trim();
+        if !device_name.is_empty() {
+            cmd.arg("--device-name").arg(device_name);
+        }
+        if !cfg.image_priority.is_empty() {
+            cmd.arg("--image-priority").arg(cfg.image_priority.join(","));
+        }
+        cmd.envs(cfg.sync_kind_env());
         cmd.arg("wl-watch")
             .arg("--room")
             .arg(cfg.room)
@@ -215,6 +233,11 @@ impl MultiClipRelayTray {
             .unwrap_or_else(|| PathBuf::from("multicliprelay-node"));
 
         let mut cmd = Command::new(node_bin);
+        let device_name = cfg.device_name.trim();
+        if !device_name.is_empty() {
+            cmd.arg("--device-name").arg(device_name);
+        }
+        cmd.envs(cfg.sync_kind_env());
         cmd.arg("wl-apply")
             .arg("--room")
             .arg(cfg.room)
@@ -274,13 +297,65 @@ impl MultiClipRelayTray {
         }
     }
 
+    fn toggle_pause(&self) {
+        let mut st = self.state.lock().unwrap();
+        let next = !st.paused;
+        match set_room_paused(&st.cfg.room, next) {
+            Ok(()) => st.paused = next,
+            Err(e) => eprintln!("failed to toggle pause: {e:?}"),
+        }
+    }
+
+    fn toggle_disabled(&self) {
+        let mut st = self.state.lock().unwrap();
+        let next = !st.disabled;
+        match set_sync_disabled(next) {
+            Ok(()) => st.disabled = next,
+            Err(e) => eprintln!("failed to toggle the kill switch: {e:?}"),
+        }
+    }
+
+    fn run_action(&self, action: MenuAction) {
+        match action {
+            MenuAction::OpenControlPanel => self.open_control_panel(),
+            MenuAction::ReloadConfig => self.reload_config(),
+            MenuAction::StartAll => self.start_all(),
+            MenuAction::StopAll => self.stop_all(),
+            MenuAction::TogglePause => self.toggle_pause(),
+            MenuAction::ToggleDisabled => self.toggle_disabled(),
+            MenuAction::StartRelay => self.start_relay(),
+            MenuAction::StopRelay => self.stop_relay(),
+            MenuAction::StartWatch => self.start_watch(),
+            MenuAction::StopWatch => self.stop_watch(),
+            MenuAction::StartApply => self.start_apply(),
+            MenuAction::StopApply => self.stop_apply(),
+            MenuAction::StartX11Sync => self.start_x11_sync(),
+            MenuAction::StopX11Sync => self.stop_x11_sync(),
+            MenuAction::Quit => self.quit_and_cleanup(),
+        }
+    }
+
     fn quit_and_cleanup(&self) {
         self.stop_all();
         std::process::exit(0);
     }
 
+    fn relay_addr(&self) -> String {
+        self.state.lock().unwrap().cfg.relay_addr.clone()
+    }
+
+    /// `relay_reachable` is `None` on ticks without a fresh probe (keep the last verdict).
+    fn refresh_icon(&self, relay_reachable: Option<bool>, since_last_event: Option<Duration>) {
+        let mut st = self.state.lock().unwrap();
+        let reachable = relay_reachable.unwrap_or(st.icon != IconState::Disconnected);
+        st.icon = icon_state(reachable, since_last_event);
+    }
+
     fn prune_exited(&self) {
         let mut st = self.state.lock().unwrap();
+        // The flag may also be flipped by the GTK panel or `node pause`.
+        st.paused = is_room_paused(&st.cfg.room);
+        st.disabled = is_sync_disabled();
         if st.systemd {
             st.status = ServiceStatus {
                 relay: systemd::is_active(systemd::UNIT_RELAY),
@@ -308,6 +383,123 @@ impl MultiClipRelayTray {
     }
 }
 
+/// What the tray icon shows.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum IconState {
+    /// Relay TCP port is unreachable.
+    Disconnected,
+    Idle,
+    /// A send/recv happened within [`ACTIVE_WINDOW`].
+    Active,
+}
+
+const ACTIVE_WINDOW: Duration = Duration::from_secs(4);
+
+/// Probe the relay every N refresh ticks (the refresh tick is 600ms).
+const RELAY_PROBE_EVERY_TICKS: u32 = 3;
+
+impl IconState {
+    fn icon_name(self) -> &'static str {
+        match self {
+            IconState::Disconnected => "network-offline",
+            IconState::Idle => "edit-paste",
+            IconState::Active => "emblem-synchronizing",
+        }
+    }
+}
+
+fn icon_state(relay_reachable: bool, since_last_event: Option<Duration>) -> IconState {
+    if !relay_reachable {
+        return IconState::Disconnected;
+    }
+    match since_last_event {
+        Some(d) if d <= ACTIVE_WINDOW => IconState::Active,
+        _ => IconState::Idle,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum MenuAction {
+    OpenControlPanel,
+    ReloadConfig,
+    StartAll,
+    StopAll,
+    TogglePause,
+    ToggleDisabled,
+    StartRelay,
+    StopRelay,
+    StartWatch,
+    StopWatch,
+    StartApply,
+    StopApply,
+    StartX11Sync,
+    StopX11Sync,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MenuEntry {
+    Item {
+        action: MenuAction,
+        label: K,
+        enabled: bool,
+    },
+    /// The "recent items" submenu, filled from `AppState::recent`.
+    Recent,
+    Separator,
+}
+
+fn item(action: MenuAction, label: K, enabled: bool) -> MenuEntry {
+    MenuEntry::Item {
+        action,
+        label,
+        enabled,
+    }
+}
+
+/// Menu layout + enabled state for the given service status (kept free of ksni for testing).
+fn menu_model(
+    ss: ServiceStatus,
+    has_systemd: bool,
+    paused: bool,
+    disabled: bool,
+) -> Vec<MenuEntry> {
+    use MenuAction as A;
+
+    let any_running = ss.relay || ss.watch || ss.apply || ss.x11;
+    let all_running = ss.relay && ss.watch && ss.apply && (!has_systemd || ss.x11);
+    let pause_label = if paused { K::ResumeSync } else { K::PauseSync };
+    let disable_label = if disabled {
+        K::EnableSync
+    } else {
+        K::DisableSync
+    };
+
+    vec![
+        item(A::OpenControlPanel, K::OpenControlPanel, true),
+        item(A::ReloadConfig, K::ReloadConfig, true),
+        MenuEntry::Recent,
+        MenuEntry::Separator,
+        item(A::StartAll, K::StartAll, !all_running),
+        item(A::StopAll, K::StopAll, any_running),
+        // Pausing only matters while something could sync.
+        item(A::TogglePause, pause_label, paused || ss.watch || ss.apply),
+        // Always available: it is the emergency stop.
+        item(A::ToggleDisabled, disable_label, true),
+        MenuEntry::Separator,
+        item(A::StartRelay, K::StartRelay, !ss.relay),
+        item(A::StopRelay, K::StopRelay, ss.relay),
+        item(A::StartWatch, K::StartWatch, !ss.watch),
+        item(A::StopWatch, K::StopWatch, ss.watch),
+        item(A::StartApply, K::StartApply, !ss.apply),
+        item(A::StopApply, K::StopApply, ss.apply),
+        item(A::StartX11Sync, K::StartX11Sync, has_systemd && !ss.x11),
+        item(A::StopX11Sync, K::StopX11Sync, has_systemd && ss.x11),
+        MenuEntry::Separator,
+        item(A::Quit, K::Quit, true),
+    ]
+}
+
 pub struct AppState {
     cfg: UiConfig,
     procs: Procs,
@@ -315,6 +507,12 @@ pub struct AppState {
 
     systemd: bool,
     status: ServiceStatus,
+    paused: bool,
+    /// The node-wide kill switch (`node disable`).
+    disabled: bool,
+    icon: IconState,
+    /// Latest history events, newest first.
+    recent: Vec<RecentItem>,
 }
 
 impl AppState {
@@ -323,6 +521,7 @@ impl AppState {
         if use_systemd {
             let _ = systemd::write_env_from_ui_config(&cfg);
         }
+        let paused = is_room_paused(&cfg.room);
         Self {
             cfg,
             procs: Procs::default(),
@@ -334,6 +533,11 @@ impl AppState {
                 apply: use_systemd && systemd::is_active(systemd::UNIT_WL_APPLY),
                 x11: use_systemd && systemd::is_active(systemd::UNIT_X11_SYNC),
             },
+            paused,
+            disabled: is_sync_disabled(),
+            // Optimistic until the first probe; avoids flashing "offline" at startup.
+            icon: IconState::Idle,
+            recent: Vec::new(),
         }
     }
 
@@ -361,7 +565,7 @@ impl AppState {
 
 impl Tray for MultiClipRelayTray {
     fn icon_name(&self) -> String {
-        "edit-paste".to_string()
+        self.state.lock().unwrap().icon.icon_name().to_string()
     }
 
     fn title(&self) -> String {
@@ -434,102 +638,39 @@ impl Tray for MultiClipRelayTray {
     fn menu(&self) -> Vec<ksni::menu::MenuItem<Self>> {
         use ksni::menu::MenuItem;
 
-        let (lang, relay_running, watch_running, apply_running, x11_running, has_systemd) = {
+        let (lang, ss, has_systemd, paused, disabled, recent) = {
             let st = self.state.lock().unwrap();
-            let ss = st.service_status();
             (
                 st.lang(),
-                ss.relay,
-                ss.watch,
-                ss.apply,
-                ss.x11,
+                st.service_status(),
                 st.systemd,
+                st.paused,
+                st.disabled,
+                st.recent.clone(),
             )
         };
 
-        let any_running = relay_running || watch_running || apply_running || x11_running;
-        let all_running = relay_running && watch_running && apply_running && (!has_systemd || x11_running);
-
-        vec![
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::OpenControlPanel).into(),
-                activate: Box::new(|this: &mut Self| this.open_control_panel()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::ReloadConfig).into(),
-                activate: Box::new(|this: &mut Self| this.reload_config()),
-                ..Default::default()
-            }),
-            MenuItem::Separator,
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StartAll).into(),
-                enabled: !all_running,
-                activate: Box::new(|this: &mut Self| this.start_all()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StopAll).into(),
-                enabled: any_running,
-                activate: Box::new(|this: &mut Self| this.stop_all()),
-                ..Default::default()
-            }),
-            MenuItem::Separator,
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StartRelay).into(),
-                enabled: !relay_running,
-                activate: Box::new(|this: &mut Self| this.start_relay()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StopRelay).into(),
-                enabled: relay_running,
-                activate: Box::new(|this: &mut Self| this.stop_relay()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StartWatch).into(),
-                enabled: !watch_running,
-                activate: Box::new(|this: &mut Self| this.start_watch()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StopWatch).into(),
-                enabled: watch_running,
-                activate: Box::new(|this: &mut Self| this.stop_watch()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StartApply).into(),
-                enabled: !apply_running,
-                activate: Box::new(|this: &mut Self| this.start_apply()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StopApply).into(),
-                enabled: apply_running,
-                activate: Box::new(|this: &mut Self| this.stop_apply()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StartX11Sync).into(),
-                enabled: has_systemd && !x11_running,
-                activate: Box::new(|this: &mut Self| this.start_x11_sync()),
-                ..Default::default()
-            }),
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::StopX11Sync).into(),
-                enabled: has_systemd && x11_running,
-                activate: Box::new(|this: &mut Self| this.stop_x11_sync()),
-                ..Default::default()
-            }),
-            MenuItem::Separator,
-            MenuItem::Standard(StandardItem {
-                label: t(lang, K::Quit).into(),
-                activate: Box::new(|this: &mut Self| this.quit_and_cleanup()),
-                ..Default::default()
-            }),
-        ]
+        menu_model(ss, has_systemd, paused, disabled)
+            .into_iter()
+            .map(|e| match e {
+                MenuEntry::Separator => MenuItem::Separator,
+                MenuEntry::Item {
+                    action,
+                    label,
+                    enabled,
+                } => MenuItem::Standard(StandardItem {
+                    label: t(lang, label).into(),
+                    enabled,
+                    activate: Box::new(move |this: &mut Self| this.run_action(action)),
+                    ..Default::default()
+                }),
+                MenuEntry::Recent => MenuItem::SubMenu(SubMenu {
+                    label: t(lang, K::RecentItems).into(),
+                    submenu: recent_submenu(lang, &recent),
+                    ..Default::default()
+                }),
+            })
+            .collect()
     }
 
     fn activate(&mut self, _x: i32, _y: i32) {
@@ -537,11 +678,152 @@ impl Tray for MultiClipRelayTray {
     }
 }
 
+/// One entry per recent event; only stored texts can be copied again.
+fn recent_submenu(
+    lang: Lang,
+    recent: &[RecentItem],
+) -> Vec<ksni::menu::MenuItem<MultiClipRelayTray>> {
+    if recent.is_empty() {
+        return vec![StandardItem {
+            label: t(lang, K::RecentEmpty).into(),
+            enabled: false,
+            ..Default::default()
+        }
+        .into()];
+    }
+    recent
+        .iter()
+        .map(|item| {
+            let text_path = item.text_path.clone();
+            StandardItem {
+                label: item.label.clone(),
+                enabled: text_path.is_some(),
+                activate: Box::new(move |_: &mut MultiClipRelayTray| {
+                    if let Some(path) = &text_path {
+                        if let Err(e) = copy_text(path) {
+                            eprintln!("failed to copy recent item: {e:?}");
+                        }
+                    }
+                }),
+                ..Default::default()
+            }
+            .into()
+        })
+        .collect()
+}
+
 pub fn spawn_refresh_thread(handle: Handle<MultiClipRelayTray>) {
-    thread::spawn(move || loop {
-        thread::sleep(Duration::from_millis(600));
-        let _ = handle.update(|tray| {
-            tray.prune_exited();
-        });
+    thread::spawn(move || {
+        let mut tick: u32 = 0;
+        let mut history_seen = None;
+        loop {
+            thread::sleep(Duration::from_millis(600));
+
+            // Probe outside `update`: the connect may block for the whole timeout.
+            let reachable = (tick % RELAY_PROBE_EVERY_TICKS == 0).then(|| {
+                let addr = handle.update(|tray| tray.relay_addr());
+                probe_tcp(&addr, Duration::from_millis(250)).ok
+            });
+            tick = tick.wrapping_add(1);
+            let since_last_event = since_last_history_event();
+            // The history log only grows; re-read its tail when it changes.
+            let modified = history_modified();
+            let recent = (modified != history_seen).then(|| {
+                history_seen = modified;
+                read_recent(&node_history_path())
+            });
+
+            let _ = handle.update(|tray| {
+                tray.prune_exited();
+                tray.refresh_icon(reachable, since_last_event);
+                if let Some(recent) = recent {
+                    tray.state.lock().unwrap().recent = recent;
+                }
+            });
+        }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(model: &[MenuEntry], action: MenuAction) -> (K, bool) {
+        model
+            .iter()
+            .find_map(|e| match e {
+                MenuEntry::Item {
+                    action: a,
+                    label,
+                    enabled,
+                } if *a == action => Some((*label, *enabled)),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn icon_state_tracks_reachability_and_recent_activity() {
+        let secs = Duration::from_secs;
+        assert_eq!(icon_state(false, None), IconState::Disconnected);
+        // Unreachable wins even right after a transfer.
+        assert_eq!(icon_state(false, Some(secs(0))), IconState::Disconnected);
+        assert_eq!(icon_state(true, None), IconState::Idle);
+        assert_eq!(icon_state(true, Some(secs(1))), IconState::Active);
+        assert_eq!(icon_state(true, Some(ACTIVE_WINDOW)), IconState::Active);
+        assert_eq!(icon_state(true, Some(secs(60))), IconState::Idle);
+
+        let names = [
+            IconState::Disconnected.icon_name(),
+            IconState::Idle.icon_name(),
+            IconState::Active.icon_name(),
+        ];
+        assert!(names[0] != names[1] && names[1] != names[2] && names[0] != names[2]);
+    }
+
+    #[test]
+    fn menu_model_follows_service_status() {
+        let ss = ServiceStatus {
+            relay: true,
+            watch: true,
+            apply: false,
+            x11: false,
+        };
+        let m = menu_model(ss, true, false, false);
+        assert_eq!(enabled(&m, MenuAction::StartAll), (K::StartAll, true));
+        assert_eq!(enabled(&m, MenuAction::StopAll), (K::StopAll, true));
+        assert_eq!(enabled(&m, MenuAction::StopRelay), (K::StopRelay, true));
+        assert_eq!(enabled(&m, MenuAction::StartApply), (K::StartApply, true));
+        assert_eq!(enabled(&m, MenuAction::StopApply), (K::StopApply, false));
+        assert_eq!(enabled(&m, MenuAction::TogglePause), (K::PauseSync, true));
+
+        // Nothing running: no stop actions, pause is moot; a paused room can still be resumed.
+        let m = menu_model(ServiceStatus::default(), false, false, false);
+        assert!(!enabled(&m, MenuAction::StopAll).1);
+        assert!(!enabled(&m, MenuAction::StartX11Sync).1);
+        assert_eq!(enabled(&m, MenuAction::TogglePause), (K::PauseSync, false));
+        let m = menu_model(ServiceStatus::default(), false, true, false);
+        assert_eq!(enabled(&m, MenuAction::TogglePause), (K::ResumeSync, true));
+
+        // The kill switch is reachable whatever runs, and flips its label once engaged.
+        assert_eq!(
+            enabled(&m, MenuAction::ToggleDisabled),
+            (K::DisableSync, true)
+        );
+        let m = menu_model(ServiceStatus::default(), false, false, true);
+        assert_eq!(
+            enabled(&m, MenuAction::ToggleDisabled),
+            (K::EnableSync, true)
+        );
+
+        // Without systemd, x11-sync isn't required for "all running".
+        let all = ServiceStatus {
+            relay: true,
+            watch: true,
+            apply: true,
+            x11: false,
+        };
+        assert!(!enabled(&menu_model(all, false, false, false), MenuAction::StartAll).1);
+        assert!(enabled(&menu_model(all, true, false, false), MenuAction::StartAll).1);
+    }
+}
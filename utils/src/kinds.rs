@@ -0,0 +1,44 @@
+//! The UIs' per-kind sync switches (Text / Image / File) as a `wl-watch --watch-mimes` list,
+//! so ui-gtk and ui-tray hand the node the same allow list.
+
+/// Plain and rich text.
+pub const TEXT_WATCH_MIMES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "text/plain",
+    "text/rtf",
+    "application/rtf",
+];
+
+pub const IMAGE_WATCH_MIMES: &[&str] = &["image/*"];
+
+/// File selections, as the different file managers offer them.
+pub const FILE_WATCH_MIMES: &[&str] = &[
+    "text/uri-list",
+    "x-special/gnome-copied-files",
+    "application/x-kde4-urilist",
+];
+
+/// `--watch-mimes` value with every kind off: wl-watch has nothing to publish. (An empty list
+/// means the opposite, every type.)
+pub const WATCH_NONE: &str = "none";
+
+/// `--watch-mimes` for the kinds switched on; `None` when all of them are (no list needed).
+pub fn watch_mimes_for(text: bool, image: bool, file: bool) -> Option<String> {
+    if text && image && file {
+        return None;
+    }
+    if !(text || image || file) {
+        return Some(WATCH_NONE.to_string());
+    }
+    let mut mimes: Vec<&str> = Vec::new();
+    for (on, list) in [
+        (text, TEXT_WATCH_MIMES),
+        (image, IMAGE_WATCH_MIMES),
+        (file, FILE_WATCH_MIMES),
+    ] {
+        if on {
+            mimes.extend_from_slice(list);
+        }
+    }
+    Some(mimes.join(","))
+}
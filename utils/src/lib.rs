@@ -1,7 +1,143 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+pub mod kinds;
+pub mod paths;
+pub mod probe;
+pub mod uri;
+
 const MSG_V2_MAGIC: &[u8; 4] = b"MCR2";
+/// Same body as MCR2, followed by a big-endian crc32 of the bincode body.
+const MSG_V3_MAGIC: &[u8; 4] = b"MCR3";
+const CRC_TRAILER_LEN: usize = 4;
+
+/// Upper bound for a single frame body announced by the u32 length prefix.
+///
+/// Readers must check this *before* allocating, otherwise a corrupt or malicious peer can
+/// announce ~4 GiB and force a huge allocation. Comfortably above the UI's 200 MiB max.
+pub const MAX_FRAME_BYTES: usize = 256 * 1024 * 1024;
+
+/// MIME of the `Join`-kind frame a relay sends before closing a connection it refuses
+/// (payload: human-readable reason). Older nodes ignore it like any other `Join`.
+pub const REJECT_MIME: &str = "application/x-multicliprelay-reject";
+
+/// MIME of a `Join` that carries its sender's [`Hello`] as payload. Relays that predate the
+/// handshake register it like any other `Join` (they'd drop the connection on a `Kind::Hello`
+/// they can't decode); newer ones answer with their own `Hello`.
+pub const HELLO_JOIN_MIME: &str = "application/x-multicliprelay-hello";
+
+/// Connection hello (`node --compress`): the client's first frame is this magic plus the codec
+/// it asks for; the relay answers with the magic plus the codec it agreed to. Older relays
+/// can't decode the hello and close the connection, so clients reconnect with plain frames.
+const CONN_HELLO_MAGIC: &[u8; 4] = b"MCRZ";
+/// No stream codec: plain length-prefixed frames.
+pub const CONN_CODEC_NONE: u8 = 0;
+/// The whole byte stream (length prefixes included) deflated, flushed after every frame.
+pub const CONN_CODEC_DEFLATE: u8 = 1;
+
+pub fn conn_hello(codec: u8) -> Vec<u8> {
+    let mut out = CONN_HELLO_MAGIC.to_vec();
+    out.push(codec);
+    out
+}
+
+/// The codec a hello (or hello answer) frame names; `None` for any other frame.
+pub fn parse_conn_hello(buf: &[u8]) -> Option<u8> {
+    match buf.strip_prefix(CONN_HELLO_MAGIC.as_slice()) {
+        Some([codec]) => Some(*codec),
+        _ => None,
+    }
+}
+
+/// Protocol version a [`Hello`] announces; bump it when peers must tell old frames from new.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Capability names a [`Hello`] may list (unknown names are ignored by their receivers).
+pub const CAP_DEFLATE: &str = "deflate";
+pub const CAP_CHANNELS: &str = "channels";
+pub const CAP_RETAIN: &str = "retain";
+pub const CAP_EXTRA_MIME: &str = "extra-mime";
+pub const CAP_SCROLLBACK: &str = "scrollback";
+pub const CAP_ACK: &str = "ack";
+
+/// Payload of a `Kind::Hello`: what its sender speaks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    pub version: u32,
+    pub caps: Vec<String>,
+}
+
+impl Hello {
+    pub fn new(caps: &[&str]) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            caps: caps.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    pub fn has(&self, cap: &str) -> bool {
+        self.caps.iter().any(|c| c == cap)
+    }
+
+    /// What both sides can use: the lower version and the capabilities both list.
+    pub fn common(&self, other: &Hello) -> Hello {
+        Hello {
+            version: self.version.min(other.version),
+            caps: self.caps.iter().filter(|c| other.has(c)).cloned().collect(),
+        }
+    }
+}
+
+/// What a relay says about itself in its answer to a `Hello` (diagnostics only).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RelayBanner {
+    pub version: String,
+    pub uptime_secs: u64,
+}
+
+/// Why a frame body could not be decoded into a [`Message`].
+#[derive(Debug)]
+pub enum DecodeError {
+    /// No MCR2/MCR3 magic, and not a legacy (v0/v1) body either.
+    UnknownMagic,
+    /// Shorter than its header, trailer or body claims.
+    Truncated,
+    /// MCR3 integrity trailer does not match the body.
+    Crc { want: u32, got: u32 },
+    /// The body is complete but not a valid message.
+    Bincode(bincode::Error),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownMagic => f.write_str("unknown frame magic"),
+            Self::Truncated => f.write_str("truncated frame"),
+            Self::Crc { want, got } => write!(f, "crc mismatch: want={want:08x} got={got:08x}"),
+            Self::Bincode(e) => write!(f, "invalid message body: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Bincode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<bincode::Error> for DecodeError {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(ref io) if io.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Self::Truncated
+            }
+            _ => Self::Bincode(e),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Kind {
@@ -9,6 +145,24 @@ pub enum Kind {
     Image,
     File,
     Join,
+    /// Version/capabilities handshake (payload: a bincode [`Hello`]). Clients announce theirs
+    /// in their first `Join` (see [`HELLO_JOIN_MIME`]); relays answer and pass peers' on as this.
+    ///
+    /// Older builds can't decode this kind, so the relay only forwards it to connections that
+    /// announced a `Hello` themselves.
+    Hello,
+    /// Client -> relay: stream the room's `--scrollback`. The relay answers with the stored
+    /// frames, then a `Replay` of its own whose `size` is how many there were.
+    Replay,
+    /// Client -> relay liveness check; never broadcast and needs no room.
+    Ping,
+    /// Relay -> client answer to a `Ping`, carrying the ping's `event_id` and `ts`.
+    Pong,
+    /// Peer -> room: the message with this `event_id` was applied to the peer's clipboard.
+    ///
+    /// Older builds can't decode this kind, so the relay only forwards it to connections whose
+    /// `Hello` lists [`CAP_ACK`].
+    Ack,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -27,6 +181,147 @@ pub struct Message {
     pub payload: Option<Vec<u8>>,
     pub size: usize,
     pub sha256: Option<String>,
+    /// Optional sub-channel within the room; the relay only forwards channeled messages to
+    /// connections subscribed to it. On a `Join`, a comma-separated list of subscriptions.
+    ///
+    /// Kept last: older peers decode the body and ignore the trailing bytes.
+    pub channel: Option<String>,
+    /// Absolute paths a `File` bundle was copied from on the sender (unless it opted out with
+    /// `--no-path-metadata`). Informational only: receivers never write there.
+    ///
+    /// Appended after `channel` for the same reason.
+    pub orig_paths: Option<Vec<String>>,
+    /// The sender waits for peers to confirm they applied this (`--wait-ack`); appliers only
+    /// answer with a `Kind::Ack` then. Appended last, like `orig_paths`.
+    pub want_ack: bool,
+}
+
+/// A message frame split around its payload (see [`Message::frame_parts`]).
+#[derive(Debug, Clone)]
+pub struct FrameParts {
+    pub magic: &'static [u8],
+    pub head: Vec<u8>,
+    pub tail: Vec<u8>,
+}
+
+/// MCR2/MCR3 body before `want_ack` was added.
+///
+/// We keep it only for backward-compatible decoding.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MessageV4 {
+    pub event_id: String,
+    pub device_id: String,
+    pub sender_name: Option<String>,
+    pub ts: u64,
+    pub kind: Kind,
+    pub room: String,
+    pub mime: Option<String>,
+    pub name: Option<String>,
+    pub payload: Option<Vec<u8>>,
+    pub size: usize,
+    pub sha256: Option<String>,
+    pub channel: Option<String>,
+    pub orig_paths: Option<Vec<String>>,
+}
+
+impl From<MessageV4> for Message {
+    fn from(v4: MessageV4) -> Self {
+        Message {
+            event_id: v4.event_id,
+            device_id: v4.device_id,
+            sender_name: v4.sender_name,
+            ts: v4.ts,
+            kind: v4.kind,
+            room: v4.room,
+            mime: v4.mime,
+            name: v4.name,
+            payload: v4.payload,
+            size: v4.size,
+            sha256: v4.sha256,
+            channel: v4.channel,
+            orig_paths: v4.orig_paths,
+            want_ack: false,
+        }
+    }
+}
+
+/// MCR2/MCR3 body before `orig_paths` was added.
+///
+/// We keep it only for backward-compatible decoding.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MessageV3 {
+    pub event_id: String,
+    pub device_id: String,
+    pub sender_name: Option<String>,
+    pub ts: u64,
+    pub kind: Kind,
+    pub room: String,
+    pub mime: Option<String>,
+    pub name: Option<String>,
+    pub payload: Option<Vec<u8>>,
+    pub size: usize,
+    pub sha256: Option<String>,
+    pub channel: Option<String>,
+}
+
+impl From<MessageV3> for Message {
+    fn from(v3: MessageV3) -> Self {
+        Message {
+            event_id: v3.event_id,
+            device_id: v3.device_id,
+            sender_name: v3.sender_name,
+            ts: v3.ts,
+            kind: v3.kind,
+            room: v3.room,
+            mime: v3.mime,
+            name: v3.name,
+            payload: v3.payload,
+            size: v3.size,
+            sha256: v3.sha256,
+            channel: v3.channel,
+            orig_paths: None,
+            want_ack: false,
+        }
+    }
+}
+
+/// MCR2/MCR3 body before `channel` was added.
+///
+/// We keep it only for backward-compatible decoding.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MessageV2 {
+    pub event_id: String,
+    pub device_id: String,
+    pub sender_name: Option<String>,
+    pub ts: u64,
+    pub kind: Kind,
+    pub room: String,
+    pub mime: Option<String>,
+    pub name: Option<String>,
+    pub payload: Option<Vec<u8>>,
+    pub size: usize,
+    pub sha256: Option<String>,
+}
+
+impl From<MessageV2> for Message {
+    fn from(v2: MessageV2) -> Self {
+        Message {
+            event_id: v2.event_id,
+            device_id: v2.device_id,
+            sender_name: v2.sender_name,
+            ts: v2.ts,
+            kind: v2.kind,
+            room: v2.room,
+            mime: v2.mime,
+            name: v2.name,
+            payload: v2.payload,
+            size: v2.size,
+            sha256: v2.sha256,
+            channel: None,
+            orig_paths: None,
+            want_ack: false,
+        }
+    }
 }
 
 /// Older wire-compatible message (v0).
@@ -75,9 +370,77 @@ impl Message {
             payload: None,
             size: 0,
             sha256: None,
+            channel: None,
+            orig_paths: None,
+            want_ack: false,
         }
     }
 
+    pub fn new_hello(device_id: &str, room: &str, hello: &Hello) -> Self {
+        let payload = bincode::serialize(hello).expect("serialize hello");
+        let mut m = Self::new_join(device_id, room);
+        m.kind = Kind::Hello;
+        m.size = payload.len();
+        m.payload = Some(payload);
+        m
+    }
+
+    /// A `Join` announcing `hello` (see [`HELLO_JOIN_MIME`]).
+    pub fn new_join_hello(device_id: &str, room: &str, hello: &Hello) -> Self {
+        let mut m = Self::new_hello(device_id, room, hello);
+        m.kind = Kind::Join;
+        m.mime = Some(HELLO_JOIN_MIME.to_string());
+        m
+    }
+
+    /// The relay's `Hello` answer: the banner follows the [`Hello`] in the payload, where
+    /// clients that don't know it ignore it as trailing bytes.
+    pub fn new_relay_hello(room: &str, hello: &Hello, banner: &RelayBanner) -> Self {
+        let payload = bincode::serialize(&(hello, banner)).expect("serialize hello");
+        let mut m = Self::new_hello("relay", room, hello);
+        m.size = payload.len();
+        m.payload = Some(payload);
+        m
+    }
+
+    pub fn new_replay(device_id: &str, room: &str) -> Self {
+        let mut m = Self::new_join(device_id, room);
+        m.kind = Kind::Replay;
+        m
+    }
+
+    pub fn new_ping(device_id: &str) -> Self {
+        let mut m = Self::new_join(device_id, "");
+        m.kind = Kind::Ping;
+        m
+    }
+
+    /// The relay's answer to `ping`.
+    pub fn new_pong(ping: &Message) -> Self {
+        let mut m = Self::new_join("relay", &ping.room);
+        m.kind = Kind::Pong;
+        m.event_id = ping.event_id.clone();
+        m.ts = ping.ts;
+        m
+    }
+
+    /// `device_id`'s acknowledgement that it applied `applied`.
+    pub fn new_ack(device_id: &str, applied: &Message) -> Self {
+        let mut m = Self::new_join(device_id, &applied.room);
+        m.kind = Kind::Ack;
+        m.event_id = applied.event_id.clone();
+        m
+    }
+
+    /// Relay -> node notice that the connection is being refused (see `REJECT_MIME`).
+    pub fn new_reject(room: &str, reason: &str) -> Self {
+        let mut m = Self::new_join("relay", room);
+        m.mime = Some(REJECT_MIME.to_string());
+        m.payload = Some(reason.as_bytes().to_vec());
+        m.size = reason.len();
+        m
+    }
+
     pub fn new_text(device_id: &str, room: &str, text: &str) -> Self {
         Self {
             event_id: Uuid::new_v4().to_string(),
@@ -89,8 +452,11 @@ impl Message {
             mime: Some("text/plain;charset=utf-8".to_string()),
             name: None,
             payload: Some(text.as_bytes().to_vec()),
-            size: text.as_bytes().len(),
+            size: text.len(),
             sha256: None,
+            channel: None,
+            orig_paths: None,
+            want_ack: false,
         }
     }
 
@@ -108,6 +474,9 @@ impl Message {
             payload: Some(bytes),
             size,
             sha256: None,
+            channel: None,
+            orig_paths: None,
+            want_ack: false,
         }
     }
 
@@ -125,6 +494,9 @@ impl Message {
             payload: Some(bytes),
             size,
             sha256: None,
+            channel: None,
+            orig_paths: None,
+            want_ack: false,
         }
     }
 
@@ -136,33 +508,88 @@ impl Message {
         out
     }
 
+    /// Like `to_bytes`, but with the MCR3 integrity trailer (crc32 of the body).
+    pub fn to_bytes_checked(&self) -> Vec<u8> {
+        seal_frame(self.to_bytes())
+    }
+
+    /// The frame bytes around a payload of `payload_len` bytes that isn't held in memory, for
+    /// writers that stream it from elsewhere (`self.payload` is left out).
+    ///
+    /// `magic`, `head`, the payload and `tail` make up [`to_bytes`](Self::to_bytes); with
+    /// `checked`, the MCR3 magic, and the big-endian [`crc32`] of `head`, payload and `tail`
+    /// follows, as in [`to_bytes_checked`](Self::to_bytes_checked).
+    pub fn frame_parts(&self, payload_len: usize, checked: bool) -> FrameParts {
+        // bincode writes the fields in declaration order, with no framing of its own.
+        let before = (
+            &self.event_id,
+            &self.device_id,
+            &self.sender_name,
+            self.ts,
+            &self.kind,
+            &self.room,
+            &self.mime,
+            &self.name,
+        );
+        let mut head = bincode::serialize(&before).expect("serialize message");
+        // `Some(payload)` is the option tag and the u64 length, then the bytes themselves.
+        head.extend(bincode::serialize(&Some(payload_len as u64)).expect("serialize message"));
+        let after = (
+            self.size,
+            &self.sha256,
+            &self.channel,
+            &self.orig_paths,
+            self.want_ack,
+        );
+        FrameParts {
+            magic: if checked { MSG_V3_MAGIC } else { MSG_V2_MAGIC },
+            head,
+            tail: bincode::serialize(&after).expect("serialize message"),
+        }
+    }
+
     /// Try decoding a message from raw bytes.
     ///
     /// This function is intentionally tolerant to older on-the-wire formats.
     /// Callers should handle errors without panicking (e.g. drop the frame / reconnect).
-    pub fn try_from_bytes(b: &[u8]) -> Result<Self, bincode::Error> {
+    /// Never panics, whatever the input.
+    pub fn try_from_bytes(b: &[u8]) -> Result<Self, DecodeError> {
+        if b.len() >= MSG_V3_MAGIC.len() && &b[..MSG_V3_MAGIC.len()] == MSG_V3_MAGIC {
+            let rest = &b[MSG_V3_MAGIC.len()..];
+            if rest.len() < CRC_TRAILER_LEN {
+                return Err(DecodeError::Truncated);
+            }
+            let (body, trailer) = rest.split_at(rest.len() - CRC_TRAILER_LEN);
+            let want = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+            let got = crc32(body);
+            if want != got {
+                return Err(DecodeError::Crc { want, got });
+            }
+            return decode_body(body);
+        }
         if b.len() >= MSG_V2_MAGIC.len() && &b[..MSG_V2_MAGIC.len()] == MSG_V2_MAGIC {
-            return bincode::deserialize(&b[MSG_V2_MAGIC.len()..]);
+            return decode_body(&b[MSG_V2_MAGIC.len()..]);
         }
 
         // Backward compat: v1 had no magic prefix and no `sender_name`.
         match bincode::deserialize::<MessageV1>(b) {
-            Ok(v1) => {
-                return Ok(Message {
-                    event_id: v1.event_id,
-                    device_id: v1.device_id,
-                    sender_name: None,
-                    ts: v1.ts,
-                    kind: v1.kind,
-                    room: v1.room,
-                    mime: v1.mime,
-                    name: v1.name,
-                    payload: v1.payload,
-                    size: v1.size,
-                    sha256: v1.sha256,
-                });
-            }
-            Err(e_v1) => {
+            Ok(v1) => Ok(Message {
+                event_id: v1.event_id,
+                device_id: v1.device_id,
+                sender_name: None,
+                ts: v1.ts,
+                kind: v1.kind,
+                room: v1.room,
+                mime: v1.mime,
+                name: v1.name,
+                payload: v1.payload,
+                size: v1.size,
+                sha256: v1.sha256,
+                channel: None,
+                orig_paths: None,
+                want_ack: false,
+            }),
+            Err(_) => {
                 // Older compat: v0 may not have `size`/`sha256` fields.
                 if let Ok(v0) = bincode::deserialize::<MessageV0>(b) {
                     let size = v0.payload.as_ref().map(|p| p.len()).unwrap_or(0);
@@ -178,15 +605,149 @@ impl Message {
                         payload: v0.payload,
                         size,
                         sha256: None,
+                        channel: None,
+                        orig_paths: None,
+                        want_ack: false,
                     });
                 }
-                return Err(e_v1);
+                Err(DecodeError::UnknownMagic)
             }
         }
     }
 
-    pub fn from_bytes(b: &[u8]) -> Self {
-        Self::try_from_bytes(b).expect("deserialize message")
+    /// The relay's reason when this is a rejection notice.
+    pub fn rejection(&self) -> Option<String> {
+        if !matches!(self.kind, Kind::Join) || self.mime.as_deref() != Some(REJECT_MIME) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(self.payload.as_deref().unwrap_or_default()).into_owned())
+    }
+
+    /// The announced version and capabilities when this is a `Hello` (or a `Join` carrying one).
+    pub fn hello(&self) -> Option<Hello> {
+        let announced = match self.kind {
+            Kind::Hello => true,
+            Kind::Join => self.mime.as_deref() == Some(HELLO_JOIN_MIME),
+            _ => false,
+        };
+        if !announced {
+            return None;
+        }
+        bincode::deserialize(self.payload.as_deref()?).ok()
+    }
+
+    /// The banner of a relay's `Hello` answer (`None` from peers and older relays).
+    pub fn relay_banner(&self) -> Option<RelayBanner> {
+        if !matches!(self.kind, Kind::Hello) {
+            return None;
+        }
+        let (_, banner): (Hello, RelayBanner) =
+            bincode::deserialize(self.payload.as_deref()?).ok()?;
+        Some(banner)
+    }
+
+    /// Channels a `Join` subscribes to (empty for other kinds or when none are declared).
+    pub fn subscribed_channels(&self) -> Vec<String> {
+        if !matches!(self.kind, Kind::Join) {
+            return Vec::new();
+        }
+        self.channel
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Decode an MCR2/MCR3 body, falling back to the layouts without `want_ack`, `orig_paths` and
+/// `channel`.
+fn decode_body(body: &[u8]) -> Result<Message, DecodeError> {
+    match bincode::deserialize::<Message>(body) {
+        Ok(m) => Ok(m),
+        Err(e) => bincode::deserialize::<MessageV4>(body)
+            .map(Message::from)
+            .or_else(|_| bincode::deserialize::<MessageV3>(body).map(Message::from))
+            .or_else(|_| bincode::deserialize::<MessageV2>(body).map(Message::from))
+            .map_err(|_| DecodeError::from(e)),
+    }
+}
+
+/// Convert an MCR2 frame into MCR3 (magic bump + crc32 trailer).
+///
+/// Frames in any other format are returned unchanged, so this is safe to apply to
+/// arbitrary outgoing bytes (e.g. in `send_frame`).
+pub fn seal_frame(mut buf: Vec<u8>) -> Vec<u8> {
+    let Some((magic, trailer)) = seal_parts(&buf) else {
+        return buf;
+    };
+    buf[..magic.len()].copy_from_slice(magic);
+    buf.extend_from_slice(&trailer);
+    buf
+}
+
+/// What [`seal_frame`] changes, for writers that stream a frame instead of copying it: the
+/// magic to write in place of the MCR2 one, and the trailer to append. `None` for frames in
+/// any other format.
+pub fn seal_parts(buf: &[u8]) -> Option<(&'static [u8], [u8; CRC_TRAILER_LEN])> {
+    let body = buf.strip_prefix(MSG_V2_MAGIC.as_slice())?;
+    Some((MSG_V3_MAGIC.as_slice(), crc32(body).to_be_bytes()))
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// CRC-32 (IEEE, reflected 0xEDB88320), same as zlib/PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// [`crc32`] over data that arrives in pieces.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 = CRC32_TABLE[((self.0 ^ b as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub fn finish(self) -> u32 {
+        !self.0
     }
 }
 
@@ -217,6 +778,245 @@ mod tests {
         assert_eq!(m2.sha256.as_deref(), Some("abc"));
     }
 
+    #[test]
+    fn relay_hello_carries_a_banner_old_readers_skip() {
+        let hello = Hello::new(&[CAP_CHANNELS]);
+        let banner = RelayBanner {
+            version: "1.2.3".to_string(),
+            uptime_secs: 42,
+        };
+        let m = Message::new_relay_hello("room", &hello, &banner);
+        let m = Message::try_from_bytes(&m.to_bytes()).expect("decode");
+        assert_eq!(m.device_id, "relay");
+        assert_eq!(m.hello(), Some(hello.clone()));
+        assert_eq!(m.relay_banner(), Some(banner));
+        // A peer's hello (or an older relay's) has none.
+        assert_eq!(Message::new_hello("a", "room", &hello).relay_banner(), None);
+    }
+
+    #[test]
+    fn join_hello_is_a_join_that_announces_a_hello() {
+        let hello = Hello::new(&[CAP_ACK]);
+        let mut m = Message::new_join_hello("a", "room", &hello);
+        m.channel = Some("work".to_string());
+        let m = Message::try_from_bytes(&m.to_bytes()).expect("decode");
+        assert!(matches!(m.kind, Kind::Join));
+        assert_eq!(m.hello(), Some(hello));
+        assert_eq!(m.subscribed_channels(), vec!["work".to_string()]);
+        assert_eq!(m.rejection(), None);
+        assert_eq!(Message::new_join("a", "room").hello(), None);
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn frame_parts_surround_the_payload() {
+        let payload = b"tar bytes".to_vec();
+        let mut m = Message::new_file("dev", "room", "a.tar", "application/x-tar", payload.clone());
+        m.sha256 = Some("abc".to_string());
+        m.orig_paths = Some(vec!["/home/a".to_string()]);
+        m.want_ack = true;
+
+        let plain = m.frame_parts(payload.len(), false);
+        let body = [plain.head.as_slice(), &payload, &plain.tail].concat();
+        assert_eq!([plain.magic, body.as_slice()].concat(), m.to_bytes());
+
+        let checked = m.frame_parts(payload.len(), true);
+        let trailer = crc32(&body).to_be_bytes();
+        let frame = [checked.magic, body.as_slice(), &trailer].concat();
+        assert_eq!(frame, m.to_bytes_checked());
+    }
+
+    #[test]
+    fn checked_frame_roundtrip_and_corruption() {
+        let m = Message::new_text("dev", "room", "hello world");
+        let b = m.to_bytes_checked();
+        assert_eq!(&b[..4], b"MCR3");
+        let m2 = Message::try_from_bytes(&b).expect("clean frame decodes");
+        assert_eq!(m2.payload.as_deref(), Some(b"hello world".as_slice()));
+
+        // Flip one payload byte: must be rejected rather than decoded as garbage.
+        let mut bad = b.clone();
+        let i = bad.len() - 6;
+        bad[i] ^= 0x01;
+        assert!(Message::try_from_bytes(&bad).is_err());
+
+        // Truncated frame is rejected too.
+        assert!(Message::try_from_bytes(&b[..b.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn decode_errors_are_typed() {
+        let b = Message::new_text("dev", "room", "hello").to_bytes();
+        // Cut into `sha256`: shorter than even the oldest MCR2 layout.
+        assert!(matches!(
+            Message::try_from_bytes(&b[..b.len() - 4]),
+            Err(DecodeError::Truncated)
+        ));
+        assert!(matches!(
+            Message::try_from_bytes(b"MCR3\x01"),
+            Err(DecodeError::Truncated)
+        ));
+        let mut bad = Message::new_text("dev", "room", "hello").to_bytes_checked();
+        let i = bad.len() - 6;
+        bad[i] ^= 0x01;
+        assert!(matches!(
+            Message::try_from_bytes(&bad),
+            Err(DecodeError::Crc { .. })
+        ));
+        assert!(matches!(
+            Message::try_from_bytes(b"GET / HTTP/1.1\r\n"),
+            Err(DecodeError::UnknownMagic)
+        ));
+        // Complete, but `event_id` is not UTF-8.
+        assert!(matches!(
+            Message::try_from_bytes(b"MCR2\x02\0\0\0\0\0\0\0\xff\xff"),
+            Err(DecodeError::Bincode(_))
+        ));
+    }
+
+    #[test]
+    fn random_bytes_never_panic_the_decoder() {
+        // xorshift64*: deterministic, no extra dependency.
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = move || {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        };
+
+        for _ in 0..5000 {
+            let len = (next() % 256) as usize;
+            let mut buf: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // Half the time, make it look like a real frame so the body decoder gets exercised.
+            match next() % 4 {
+                0 if len >= 4 => buf[..4].copy_from_slice(b"MCR2"),
+                1 if len >= 4 => buf[..4].copy_from_slice(b"MCR3"),
+                _ => {}
+            }
+            let _ = Message::try_from_bytes(&buf);
+        }
+
+        // Mutations of valid frames reach deeper than pure noise.
+        let mut m = Message::new_file("dev", "room", "a.txt", "text/plain", vec![7; 64]);
+        m.channel = Some("c".to_string());
+        for frame in [m.to_bytes(), m.to_bytes_checked()] {
+            for cut in 0..frame.len() {
+                let _ = Message::try_from_bytes(&frame[..cut]);
+            }
+            for _ in 0..2000 {
+                let mut f = frame.clone();
+                for _ in 0..=(next() % 4) {
+                    let i = (next() as usize) % f.len();
+                    f[i] = next() as u8;
+                }
+                let _ = Message::try_from_bytes(&f);
+            }
+        }
+    }
+
+    #[test]
+    fn pre_channel_body_still_decodes() {
+        let mut m = Message::new_text("dev", "room", "hi");
+        m.sender_name = Some("alice".to_string());
+        let v2 = MessageV2 {
+            event_id: m.event_id.clone(),
+            device_id: m.device_id.clone(),
+            sender_name: m.sender_name.clone(),
+            ts: m.ts,
+            kind: Kind::Text,
+            room: m.room.clone(),
+            mime: m.mime.clone(),
+            name: None,
+            payload: m.payload.clone(),
+            size: m.size,
+            sha256: None,
+        };
+        let mut b = MSG_V2_MAGIC.to_vec();
+        b.extend_from_slice(&bincode::serialize(&v2).unwrap());
+        let old = Message::try_from_bytes(&seal_frame(b)).expect("decode pre-channel frame");
+        assert_eq!(old.sender_name.as_deref(), Some("alice"));
+        assert_eq!(old.channel, None);
+
+        m.channel = Some("photos".to_string());
+        let new = Message::try_from_bytes(&m.to_bytes_checked()).unwrap();
+        assert_eq!(new.channel.as_deref(), Some("photos"));
+        assert!(new.subscribed_channels().is_empty());
+
+        let mut join = Message::new_join("dev", "room");
+        join.channel = Some(" photos, ,notes".to_string());
+        assert_eq!(join.subscribed_channels(), ["photos", "notes"]);
+    }
+
+    #[test]
+    fn orig_paths_round_trip_and_older_bodies_decode_without_them() {
+        let mut m = Message::new_file("dev", "room", "a.tar", "application/x-tar", vec![1, 2]);
+        m.channel = Some("docs".to_string());
+        m.orig_paths = Some(vec!["/home/alice/a.txt".to_string()]);
+        let new = Message::try_from_bytes(&m.to_bytes_checked()).unwrap();
+        assert_eq!(new.orig_paths, m.orig_paths);
+        assert_eq!(new.channel.as_deref(), Some("docs"));
+
+        // A peer from before `orig_paths` sends the body without it.
+        let v3 = MessageV3 {
+            event_id: m.event_id.clone(),
+            device_id: m.device_id.clone(),
+            sender_name: None,
+            ts: m.ts,
+            kind: Kind::File,
+            room: m.room.clone(),
+            mime: m.mime.clone(),
+            name: m.name.clone(),
+            payload: m.payload.clone(),
+            size: m.size,
+            sha256: None,
+            channel: Some("docs".to_string()),
+        };
+        let mut b = MSG_V2_MAGIC.to_vec();
+        b.extend_from_slice(&bincode::serialize(&v3).unwrap());
+        let old = Message::try_from_bytes(&seal_frame(b)).expect("decode pre-orig_paths frame");
+        assert_eq!(old.channel.as_deref(), Some("docs"));
+        assert_eq!(old.orig_paths, None);
+    }
+
+    #[test]
+    fn want_ack_round_trips_and_older_bodies_decode_without_it() {
+        let mut m = Message::new_text("dev", "room", "hi");
+        m.orig_paths = Some(vec!["/tmp/a".to_string()]);
+        m.want_ack = true;
+        let new = Message::try_from_bytes(&m.to_bytes_checked()).unwrap();
+        assert!(new.want_ack);
+
+        let v4 = MessageV4 {
+            event_id: m.event_id.clone(),
+            device_id: m.device_id.clone(),
+            sender_name: None,
+            ts: m.ts,
+            kind: Kind::Text,
+            room: m.room.clone(),
+            mime: m.mime.clone(),
+            name: None,
+            payload: m.payload.clone(),
+            size: m.size,
+            sha256: None,
+            channel: None,
+            orig_paths: m.orig_paths.clone(),
+        };
+        let mut b = MSG_V2_MAGIC.to_vec();
+        b.extend_from_slice(&bincode::serialize(&v4).unwrap());
+        let old = Message::try_from_bytes(&b).expect("decode pre-want_ack frame");
+        assert_eq!(old.orig_paths, m.orig_paths);
+        assert!(!old.want_ack);
+    }
+
     #[test]
     fn message_v1_is_backward_compatible() {
         let v1 = MessageV1 {
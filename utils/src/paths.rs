@@ -0,0 +1,249 @@
+//! Where node keeps its state, history and received payloads.
+//!
+//! The UIs read the same locations (previews, pause flags, room control file), so all of them
+//! resolve paths through here instead of re-deriving them.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+pub const APP_DIR_NAME: &str = "multicliprelay";
+
+pub const TAR_MIME: &str = "application/x-tar";
+
+/// Overrides the runtime state dir (instance locks, suppress markers, pause flags).
+pub const STATE_DIR_ENV: &str = "MCR_STATE_DIR";
+/// Overrides the data dir (history and, unless `MCR_RECEIVED_DIR` is set, received files).
+pub const DATA_DIR_ENV: &str = "MCR_DATA_DIR";
+/// Overrides where received files and previews are stored.
+pub const RECEIVED_DIR_ENV: &str = "MCR_RECEIVED_DIR";
+
+/// Reads one env var. The resolvers below take one, so they can be checked against a fixed
+/// env instead of this process's.
+pub type EnvLookup<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+fn process_env(key: &str) -> Option<OsString> {
+    std::env::var_os(key)
+}
+
+/// Expand a leading `~` / `~/` to `$HOME` (left as-is when HOME is unset).
+pub fn expand_home(p: &Path) -> PathBuf {
+    expand_home_with(p, std::env::var_os("HOME"))
+}
+
+fn expand_home_with(p: &Path, home: Option<OsString>) -> PathBuf {
+    match (p.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => p.to_path_buf(),
+    }
+}
+
+fn env_dir(env: EnvLookup, key: &str) -> Option<PathBuf> {
+    let v = env(key).filter(|v| !v.is_empty())?;
+    Some(expand_home_with(Path::new(&v), env("HOME")))
+}
+
+pub fn default_state_dir() -> PathBuf {
+    resolve_state_dir(&process_env)
+}
+
+/// [`default_state_dir`] as `env` has it.
+pub fn resolve_state_dir(env: EnvLookup) -> PathBuf {
+    if let Some(d) = env_dir(env, STATE_DIR_ENV) {
+        return d;
+    }
+    if let Some(d) = env_dir(env, "XDG_RUNTIME_DIR") {
+        return d.join(APP_DIR_NAME);
+    }
+    let uid = unsafe { libc::geteuid() };
+    PathBuf::from(format!("/tmp/{}-{}", APP_DIR_NAME, uid))
+}
+
+pub fn default_data_dir() -> PathBuf {
+    resolve_data_dir(&process_env)
+}
+
+/// [`default_data_dir`] as `env` has it.
+pub fn resolve_data_dir(env: EnvLookup) -> PathBuf {
+    if let Some(d) = env_dir(env, DATA_DIR_ENV) {
+        return d;
+    }
+    if let Some(d) = env_dir(env, "XDG_DATA_HOME") {
+        return d.join(APP_DIR_NAME);
+    }
+    if let Some(home) = env_dir(env, "HOME") {
+        return home.join(".local/share").join(APP_DIR_NAME);
+    }
+    PathBuf::from("/tmp").join(APP_DIR_NAME)
+}
+
+pub fn default_config_dir() -> PathBuf {
+    if let Some(d) = env_dir(&process_env, "XDG_CONFIG_HOME") {
+        return d.join(APP_DIR_NAME);
+    }
+    if let Some(home) = env_dir(&process_env, "HOME") {
+        return home.join(".config").join(APP_DIR_NAME);
+    }
+    PathBuf::from("/tmp").join(APP_DIR_NAME)
+}
+
+/// `MCR_RECEIVED_DIR`, `~`-expanded (node additionally creates and validates it).
+pub fn received_dir_override() -> Option<PathBuf> {
+    env_dir(&process_env, RECEIVED_DIR_ENV)
+}
+
+/// Received dir as seen from outside node: the override, else `<data dir>/received`.
+pub fn received_dir() -> PathBuf {
+    resolve_received_dir(&process_env)
+}
+
+/// [`received_dir`] as `env` has it.
+pub fn resolve_received_dir(env: EnvLookup) -> PathBuf {
+    env_dir(env, RECEIVED_DIR_ENV).unwrap_or_else(|| resolve_data_dir(env).join("received"))
+}
+
+pub fn history_path() -> PathBuf {
+    default_data_dir().join("history.jsonl")
+}
+
+/// Per-room pause flag (`node pause --room <room>`): while it exists, nothing syncs in `room`.
+pub fn paused_path(state_dir: &Path, room: &str) -> PathBuf {
+    let safe_room = room.replace('/', "_");
+    state_dir.join(format!("paused_{}", safe_room))
+}
+
+/// Kill switch shared by every room and command (`node disable`/`enable`).
+pub const DISABLED_FILE: &str = "DISABLED";
+
+pub fn disabled_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(DISABLED_FILE)
+}
+
+/// Control file (in the state dir) that running wl-apply/wl-watch instances follow.
+///
+/// Writing a room name here makes them re-join that room on their existing connections.
+pub const ROOM_CONTROL_FILE: &str = "room";
+
+pub fn room_control_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(ROOM_CONTROL_FILE)
+}
+
+/// Ask running services sharing `state_dir` to switch to `room` (`node switch-room`, the UIs).
+pub fn request_room_switch(state_dir: &Path, room: &str) -> std::io::Result<()> {
+    let room = room.trim();
+    if room.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "room is empty",
+        ));
+    }
+    std::fs::create_dir_all(state_dir)?;
+    // Write + rename so watchers never observe a half-written name.
+    let tmp = state_dir.join(format!(".{}.{}", ROOM_CONTROL_FILE, std::process::id()));
+    std::fs::write(&tmp, format!("{room}\n"))?;
+    std::fs::rename(&tmp, room_control_path(state_dir))
+}
+
+pub fn safe_for_filename(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+pub fn is_tar_payload(name: &str, mime: Option<&str>) -> bool {
+    mime == Some(TAR_MIME) || name.to_ascii_lowercase().ends_with(".tar")
+}
+
+/// Short form of a content fingerprint for directory names (an algorithm tag like `b3:` is
+/// dropped, so these stay plain hex).
+pub fn first_8(s: &str) -> &str {
+    let s = s.split_once(':').map_or(s, |(_, hex)| hex);
+    if s.len() >= 8 {
+        &s[..8]
+    } else {
+        s
+    }
+}
+
+/// File names of the texts node keeps per `<sha8>/` dir (`--keep-text`), plain and RTF.
+pub const KEPT_TEXT_FILES: [&str; 2] = ["text.txt", "text.rtf"];
+
+/// Where node keeps a sent/received text under `base`: `<sha8>/text.rtf` for RTF,
+/// `<sha8>/text.txt` for everything else.
+pub fn kept_text_path(base: &Path, sha: &str, rtf: bool) -> PathBuf {
+    let name = if rtf {
+        KEPT_TEXT_FILES[1]
+    } else {
+        KEPT_TEXT_FILES[0]
+    };
+    base.join(first_8(sha)).join(name)
+}
+
+/// Where wl-apply puts a received file under `base`: bundles are extracted to
+/// `<sha8>_<stem>/`, single files kept as `<sha8>/<name>`.
+pub fn received_file_path(
+    base: &Path,
+    sha: &str,
+    name: Option<&str>,
+    mime: Option<&str>,
+) -> PathBuf {
+    let sha8 = first_8(sha);
+    let name = name
+        .filter(|n| !n.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}-{}", APP_DIR_NAME, sha8));
+    let safe = safe_for_filename(&name);
+    if is_tar_payload(&name, mime) {
+        let stem = safe.trim_end_matches(".tar").trim_end_matches(".TAR");
+        base.join(format!("{sha8}_{stem}"))
+    } else {
+        base.join(sha8).join(safe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn received_file_path_layout() {
+        let base = Path::new("/r");
+        let sha = "0123456789abcdef";
+        assert_eq!(
+            received_file_path(base, sha, Some("my notes.txt"), Some("text/plain")),
+            Path::new("/r/01234567/my_notes.txt")
+        );
+        assert_eq!(
+            received_file_path(base, sha, Some("photos.tar"), None),
+            Path::new("/r/01234567_photos")
+        );
+        assert_eq!(
+            received_file_path(base, sha, Some("bundle"), Some(TAR_MIME)),
+            Path::new("/r/01234567_bundle")
+        );
+        assert_eq!(
+            received_file_path(base, sha, None, None),
+            Path::new("/r/01234567/multicliprelay-01234567")
+        );
+        // Tagged fingerprints (`--hash-algo blake3`) get the same plain-hex layout.
+        assert_eq!(
+            received_file_path(base, "b3:0123456789abcdef", Some("a.txt"), None),
+            Path::new("/r/01234567/a.txt")
+        );
+    }
+
+    #[test]
+    fn kept_text_path_layout() {
+        let base = Path::new("/r");
+        assert_eq!(
+            kept_text_path(base, "b3:0123456789abcdef", false),
+            Path::new("/r/01234567/text.txt")
+        );
+        assert_eq!(
+            kept_text_path(base, "0123456789abcdef", true),
+            Path::new("/r/01234567/text.rtf")
+        );
+    }
+}
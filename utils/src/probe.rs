@@ -0,0 +1,259 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::uri::parse_connect_uri;
+use crate::{Kind, Message};
+
+/// Outcome of a single relay reachability probe (shared by the GTK panel and the tray).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Port assumed when a relay address doesn't name one.
+pub const DEFAULT_RELAY_PORT: u16 = 8080;
+
+/// Env override for [`DEFAULT_RELAY_PORT`].
+pub const DEFAULT_PORT_ENV: &str = "MCR_DEFAULT_PORT";
+
+/// Scheme prefixes users paste in front of a relay address; they carry no information.
+/// `mcr://` is not one of them: it starts a connect URI (`mcr://relay=..&room=..`), which is
+/// read as a whole.
+const ADDR_SCHEMES: [&str; 1] = ["tcp://"];
+
+/// [`DEFAULT_RELAY_PORT`], unless `MCR_DEFAULT_PORT` holds another port.
+pub fn default_relay_port() -> u16 {
+    std::env::var(DEFAULT_PORT_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&p| p != 0)
+        .unwrap_or(DEFAULT_RELAY_PORT)
+}
+
+/// Normalize a user-provided relay address for *client connections*.
+///
+/// Users sometimes type `0.0.0.0:PORT` (or `[::]:PORT`) in the UI, which is a
+/// valid *bind* address but not a meaningful *connect* target. In that case we
+/// transparently rewrite it to the local loopback address.
+///
+/// - `0.0.0.0:PORT` -> `127.0.0.1:PORT`
+/// - `[::]:PORT`    -> `[::1]:PORT`
+///
+/// A pasted connect URI gives its `relay`; otherwise a `tcp://` prefix is dropped. An address without a port gets [`default_relay_port`]
+/// (`192.168.1.5` -> `192.168.1.5:8080`).
+///
+/// Hostnames (e.g. `example.com:8080`) are preserved.
+pub fn normalize_relay_addr_for_connect(input: &str) -> String {
+    normalize_relay_addr_with_port(input, default_relay_port())
+}
+
+/// [`normalize_relay_addr_for_connect`] with an explicit default port.
+pub fn normalize_relay_addr_with_port(input: &str, default_port: u16) -> String {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    // The UI's QR code pasted into a relay field.
+    let from_uri = parse_connect_uri(input).ok();
+    let mut s = from_uri.as_ref().map_or(input, |u| u.relay.as_str()).trim();
+    for scheme in ADDR_SCHEMES {
+        if s.get(..scheme.len())
+            .is_some_and(|p| p.eq_ignore_ascii_case(scheme))
+        {
+            s = &s[scheme.len()..];
+        }
+    }
+    let s = s.trim_end_matches('/');
+    if s.is_empty() {
+        return String::new();
+    }
+
+    let parsed = s.parse::<SocketAddr>().ok().or_else(|| {
+        // A bare IP: `192.168.1.5`, `::1` or `[::1]`.
+        let ip = s.trim_start_matches('[').trim_end_matches(']');
+        ip.parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, default_port))
+    });
+    match parsed {
+        Some(mut sa) => {
+            match sa.ip() {
+                IpAddr::V4(v4) if v4.is_unspecified() => {
+                    sa.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+                }
+                IpAddr::V6(v6) if v6.is_unspecified() => {
+                    sa.set_ip(IpAddr::V6(Ipv6Addr::LOCALHOST));
+                }
+                _ => {}
+            }
+            sa.to_string()
+        }
+        // Not an IP literal (likely a hostname). Keep as-is, adding the port if missing.
+        None => match s.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => s.to_string(),
+            _ => format!("{s}:{default_port}"),
+        },
+    }
+}
+
+/// Resolve and connect, or the failed probe to report.
+fn connect(addr: &str, timeout: Duration) -> Result<(TcpStream, SocketAddr), ProbeResult> {
+    let fail = |detail: String| ProbeResult { ok: false, detail };
+    let addr = normalize_relay_addr_for_connect(addr);
+    let addr = addr.trim();
+    if addr.is_empty() {
+        return Err(fail("empty address".to_string()));
+    }
+
+    let mut addrs = addr
+        .to_socket_addrs()
+        .map_err(|e| fail(format!("resolve failed: {e}")))?;
+
+    let Some(sock) = addrs.next() else {
+        return Err(fail("no socket addresses".to_string()));
+    };
+
+    TcpStream::connect_timeout(&sock, timeout)
+        .map(|s| (s, sock))
+        .map_err(|e| fail(format!("{sock}: {e}")))
+}
+
+/// Plain TCP connect to the relay; blocking, bounded by `timeout`.
+pub fn probe_tcp(addr: &str, timeout: Duration) -> ProbeResult {
+    match connect(addr, timeout) {
+        Ok((_, sock)) => ProbeResult {
+            ok: true,
+            detail: sock.to_string(),
+        },
+        Err(r) => r,
+    }
+}
+
+/// Answers to a ping are tiny; anything bigger means we are not talking to a relay.
+const PONG_MAX_BYTES: usize = 64 * 1024;
+
+/// Send a `Ping` and wait for the relay's `Pong`; returns the round-trip time.
+///
+/// Unlike [`probe_tcp`] this fails against a port that accepts connections but isn't a
+/// relay (or is one too old to know pings). Blocking, bounded by `timeout` overall.
+pub fn ping_relay(addr: &str, timeout: Duration) -> Result<(SocketAddr, Duration), String> {
+    let deadline = Instant::now() + timeout;
+    let (mut s, sock) = connect(addr, timeout).map_err(|r| r.detail)?;
+    let ping = Message::new_ping("probe");
+    let started = Instant::now();
+    let buf = ping.to_bytes();
+    let _ = s.set_write_timeout(Some(timeout));
+    s.write_all(&(buf.len() as u32).to_be_bytes())
+        .and_then(|_| s.write_all(&buf))
+        .map_err(|e| format!("{sock}: send ping: {e}"))?;
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(format!("{sock}: no pong within {}ms", timeout.as_millis()));
+        }
+        let _ = s.set_read_timeout(Some(left));
+        let mut len = [0u8; 4];
+        let read = s.read_exact(&mut len).and_then(|_| {
+            let len = u32::from_be_bytes(len) as usize;
+            if len > PONG_MAX_BYTES {
+                return Err(std::io::Error::other(format!(
+                    "unexpected {len}-byte frame"
+                )));
+            }
+            let mut frame = vec![0u8; len];
+            s.read_exact(&mut frame).map(|_| frame)
+        });
+        let frame = match read {
+            Ok(frame) => frame,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(format!(
+                    "{sock}: no pong within {}ms (not a relay, or one too old to answer pings)",
+                    timeout.as_millis()
+                ));
+            }
+            Err(e) => return Err(format!("{sock}: no pong: {e}")),
+        };
+        let Ok(msg) = Message::try_from_bytes(&frame) else {
+            continue;
+        };
+        if let Some(reason) = msg.rejection() {
+            return Err(format!("{sock}: relay refused connection: {reason}"));
+        }
+        if matches!(msg.kind, Kind::Pong) && msg.event_id == ping.event_id {
+            return Ok((sock, started.elapsed()));
+        }
+    }
+}
+
+/// [`ping_relay`] as a probe result, with the round-trip time in the detail.
+pub fn probe_ping(addr: &str, timeout: Duration) -> ProbeResult {
+    match ping_relay(addr, timeout) {
+        Ok((sock, rtt)) => ProbeResult {
+            ok: true,
+            detail: format!("{sock} (rtt {}ms)", rtt.as_millis()),
+        },
+        Err(detail) => ProbeResult { ok: false, detail },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_reports_listening_and_unspecified_addrs() {
+        let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = l.local_addr().unwrap().port();
+
+        // A bind-style address is rewritten to loopback before connecting.
+        let r = probe_tcp(&format!("0.0.0.0:{port}"), Duration::from_millis(500));
+        assert!(r.ok, "{r:?}");
+        assert_eq!(r.detail, format!("127.0.0.1:{port}"));
+
+        drop(l);
+        assert!(!probe_tcp(&format!("127.0.0.1:{port}"), Duration::from_millis(500)).ok);
+        assert_eq!(
+            probe_tcp("  ", Duration::from_millis(10)).detail,
+            "empty address"
+        );
+    }
+
+    #[test]
+    fn addresses_get_default_port_and_lose_their_scheme() {
+        let n = |s: &str| normalize_relay_addr_with_port(s, 8080);
+        assert_eq!(n("relay.lan"), "relay.lan:8080");
+        assert_eq!(n("relay.lan:9000"), "relay.lan:9000");
+        assert_eq!(n("tcp://relay.lan"), "relay.lan:8080");
+        assert_eq!(n(" TCP://relay.lan:9000/ "), "relay.lan:9000");
+        assert_eq!(n("192.168.1.5"), "192.168.1.5:8080");
+        assert_eq!(n("tcp://192.168.1.5:9000"), "192.168.1.5:9000");
+        assert_eq!(n("0.0.0.0"), "127.0.0.1:8080");
+        assert_eq!(n("::1"), "[::1]:8080");
+        assert_eq!(n("[::]"), "[::1]:8080");
+        assert_eq!(n("tcp://"), "");
+        // A connect URI stands for its relay.
+        assert_eq!(
+            n("mcr://relay=192.168.1.5%3A9000&room=r"),
+            "192.168.1.5:9000"
+        );
+        assert_eq!(n("mcr://relay=relay.lan&room=r"), "relay.lan:8080");
+        // Not a connect URI and not an address either; the connect then fails on it.
+        assert_eq!(n("mcr://relay.lan:9000"), "mcr://relay.lan:9000");
+        assert_eq!(
+            normalize_relay_addr_with_port("relay.lan", 7000),
+            "relay.lan:7000"
+        );
+    }
+
+    #[test]
+    fn ping_fails_against_a_port_that_is_not_a_relay() {
+        let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = l.local_addr().unwrap().to_string();
+        // Accepts (so a TCP probe passes) but never answers.
+        let r = probe_ping(&addr, Duration::from_millis(200));
+        assert!(!r.ok);
+        assert!(r.detail.contains("no pong"), "{}", r.detail);
+        assert!(probe_tcp(&addr, Duration::from_millis(200)).ok);
+    }
+}
@@ -0,0 +1,121 @@
+//! Onboarding URIs (`mcr://relay=...&room=...`), rendered as a QR code by the UI and read
+//! back by `node connect-uri` or pasted into a relay field.
+
+use url::form_urlencoded;
+
+/// Scheme prefix used for onboarding URIs (rendered as a QR code by the UI).
+pub const CONNECT_URI_PREFIX: &str = "mcr://";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectUri {
+    pub relay: String,
+    pub room: String,
+}
+
+/// Why a string is not a usable connect URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectUriError {
+    /// No `mcr://` prefix.
+    NotConnectUri,
+    /// The `relay` key is missing or empty.
+    NoRelay,
+}
+
+impl std::fmt::Display for ConnectUriError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotConnectUri => write!(f, "not an {CONNECT_URI_PREFIX} uri"),
+            Self::NoRelay => f.write_str("connect uri has no relay"),
+        }
+    }
+}
+
+impl std::error::Error for ConnectUriError {}
+
+/// Build `mcr://relay=<addr>&room=<room>` (values are URL-encoded).
+pub fn build_connect_uri(relay: &str, room: &str) -> String {
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("relay", relay)
+        .append_pair("room", room)
+        .finish();
+    format!("{CONNECT_URI_PREFIX}{query}")
+}
+
+/// Parse an `mcr://relay=...&room=...` URI.
+///
+/// `room` is optional and defaults to "default"; unknown keys are ignored so newer
+/// UIs can add fields without breaking older nodes.
+pub fn parse_connect_uri(input: &str) -> Result<ConnectUri, ConnectUriError> {
+    let s = input.trim();
+    let rest = s
+        .strip_prefix(CONNECT_URI_PREFIX)
+        .ok_or(ConnectUriError::NotConnectUri)?;
+    // Be lenient with `mcr://?relay=...` and a trailing slash before the query.
+    let rest = rest.trim_start_matches('/').trim_start_matches('?');
+
+    let mut relay: Option<String> = None;
+    let mut room: Option<String> = None;
+    for (k, v) in form_urlencoded::parse(rest.as_bytes()) {
+        match k.as_ref() {
+            "relay" => relay = Some(v.into_owned()),
+            "room" => room = Some(v.into_owned()),
+            _ => {}
+        }
+    }
+
+    let relay = relay
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty())
+        .ok_or(ConnectUriError::NoRelay)?;
+    let room = room
+        .filter(|r| !r.is_empty())
+        .unwrap_or_else(|| "default".to_string());
+
+    Ok(ConnectUri { relay, room })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_uri_round_trip() {
+        let uri = build_connect_uri("192.168.1.10:8080", "default");
+        assert_eq!(uri, "mcr://relay=192.168.1.10%3A8080&room=default");
+        let parsed = parse_connect_uri(&uri).unwrap();
+        assert_eq!(parsed.relay, "192.168.1.10:8080");
+        assert_eq!(parsed.room, "default");
+    }
+
+    #[test]
+    fn connect_uri_round_trip_encoded_room() {
+        let room = "my room/家 & co=1";
+        let uri = build_connect_uri("[::1]:9000", room);
+        assert!(!uri.contains(' '));
+        assert!(!uri["mcr://".len()..].contains('/'));
+        let parsed = parse_connect_uri(&uri).unwrap();
+        assert_eq!(parsed.relay, "[::1]:9000");
+        assert_eq!(parsed.room, room);
+
+        // Hand-written percent encoding is accepted too.
+        let parsed = parse_connect_uri("mcr://?relay=h%3A1&room=a%20b").unwrap();
+        assert_eq!(parsed.relay, "h:1");
+        assert_eq!(parsed.room, "a b");
+    }
+
+    #[test]
+    fn connect_uri_rejects_invalid() {
+        assert_eq!(
+            parse_connect_uri("http://relay=a:1"),
+            Err(ConnectUriError::NotConnectUri)
+        );
+        assert_eq!(
+            parse_connect_uri("mcr://room=x"),
+            Err(ConnectUriError::NoRelay)
+        );
+        assert_eq!(
+            parse_connect_uri("mcr://relay=a:1").unwrap().room,
+            "default"
+        );
+    }
+}